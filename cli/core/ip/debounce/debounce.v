@@ -0,0 +1,57 @@
+/**
+ * Debouncer
+ *
+ * Synchronizes a mechanical switch or button input and only changes the
+ * output once the input has been stable for CYCLES clocks.
+ *
+ * Parameters:
+ *   CYCLES - Clocks the input must be stable (default: 480000, 10 ms at 48 MHz)
+ *
+ * Ports:
+ *   i_in   - Raw input (asynchronous)
+ *   o_out  - Debounced level
+ *   o_rise - Pulses high for one clock when o_out goes high
+ *   o_fall - Pulses high for one clock when o_out goes low
+ */
+module debounce #(
+    parameter CYCLES = 480_000
+) (
+    input wire i_clk,
+    input wire i_rst,
+    input wire i_in,
+    output reg o_out,
+    output reg o_rise,
+    output reg o_fall
+);
+
+    localparam COUNT_WIDTH = $clog2(CYCLES + 1);
+
+    reg [1:0] in_sync;
+    reg [COUNT_WIDTH-1:0] count;
+
+    always @(posedge i_clk or posedge i_rst) begin
+        if (i_rst) begin
+            in_sync <= 2'b00;
+            count <= {COUNT_WIDTH{1'b0}};
+            o_out <= 1'b0;
+            o_rise <= 1'b0;
+            o_fall <= 1'b0;
+        end else begin
+            in_sync <= {in_sync[0], i_in};
+            o_rise <= 1'b0;
+            o_fall <= 1'b0;
+
+            if (in_sync[1] == o_out) begin
+                count <= {COUNT_WIDTH{1'b0}};
+            end else if (count == CYCLES - 1) begin
+                count <= {COUNT_WIDTH{1'b0}};
+                o_out <= in_sync[1];
+                o_rise <= in_sync[1];
+                o_fall <= ~in_sync[1];
+            end else begin
+                count <= count + 1'b1;
+            end
+        end
+    end
+
+endmodule
@@ -0,0 +1,75 @@
+/**
+ * Debouncer test: bounces shorter than CYCLES are filtered out, stable
+ * levels pass through with a single edge pulse.
+ */
+`timescale 1ns / 1ps
+`include "affogato_tb.vh"
+
+module debounce_tb;
+    `TB_DECLARE
+
+    localparam CYCLES = 16;
+
+    wire clk;
+    reg rst = 1'b1;
+    reg in = 1'b0;
+    wire out;
+    wire rise;
+    wire fall;
+
+    integer rises = 0;
+    integer falls = 0;
+
+    affogato_clock clock (.clk(clk));
+
+    debounce #(.CYCLES(CYCLES)) dut (
+        .i_clk(clk),
+        .i_rst(rst),
+        .i_in(in),
+        .o_out(out),
+        .o_rise(rise),
+        .o_fall(fall)
+    );
+
+    always @(posedge clk) begin
+        if (rise)
+            rises = rises + 1;
+        if (fall)
+            falls = falls + 1;
+    end
+
+    task bounce(input integer times);
+        integer i;
+        begin
+            for (i = 0; i < times; i = i + 1) begin
+                in <= ~in;
+                repeat (CYCLES / 4) @(posedge clk);
+            end
+        end
+    endtask
+
+    initial begin
+        repeat (4) @(posedge clk);
+        rst <= 1'b0;
+
+        // Press: bounce, then settle high
+        bounce(6);
+        `ASSERT(!out, "bounces are ignored")
+        in <= 1'b1;
+        repeat (CYCLES + 8) @(posedge clk);
+        `ASSERT(out, "stable high passes through")
+        `ASSERT_EQ(rises, 1, "one rising edge")
+
+        // Release: bounce, then settle low
+        bounce(5);
+        `ASSERT(out, "release bounces are ignored")
+        in <= 1'b0;
+        repeat (CYCLES + 8) @(posedge clk);
+        `ASSERT(!out, "stable low passes through")
+        `ASSERT_EQ(falls, 1, "one falling edge")
+        `ASSERT_EQ(rises, 1, "no extra rising edges")
+
+        `TB_FINISH
+    end
+
+endmodule
@@ -0,0 +1,81 @@
+/**
+ * PDM Microphone Interface
+ *
+ * Drives the clock of a PDM MEMS microphone and decimates its 1-bit data
+ * stream into PCM samples by counting ones over DECIMATION bits (a boxcar
+ * filter). Add a low-pass filter downstream for audio-quality output.
+ *
+ * Parameters:
+ *   CLK_DIV    - i_clk cycles per PDM clock, even and at least 8
+ *                (default: 16, a 3 MHz PDM clock at 48 MHz)
+ *   DECIMATION - PDM bits per output sample (default: 64, 46.875 kHz)
+ *   CHANNEL    - 0 samples data at the end of the clock high phase,
+ *                1 at the end of the low phase (match the mic's L/R select)
+ *
+ * Ports:
+ *   o_pdm_clk  - Clock to the microphone
+ *   i_pdm_data - Data from the microphone (asynchronous)
+ *   o_sample   - Ones counted in the last DECIMATION bits (0..DECIMATION)
+ *   o_valid    - Pulses high for one clock when o_sample is updated
+ */
+module pdm_mic #(
+    parameter CLK_DIV = 16,
+    parameter DECIMATION = 64,
+    parameter CHANNEL = 0
+) (
+    input wire i_clk,
+    input wire i_rst,
+    output reg o_pdm_clk,
+    input wire i_pdm_data,
+    output reg [$clog2(DECIMATION+1)-1:0] o_sample,
+    output reg o_valid
+);
+
+    localparam HALF = CLK_DIV / 2;
+    localparam DIV_WIDTH = $clog2(HALF);
+    localparam SAMPLE_WIDTH = $clog2(DECIMATION + 1);
+    localparam BIT_WIDTH = $clog2(DECIMATION);
+
+    reg [1:0] data_sync;
+    reg [DIV_WIDTH-1:0] div;
+    reg [BIT_WIDTH-1:0] bit_count;
+    reg [SAMPLE_WIDTH-1:0] ones;
+
+    // Last i_clk of the half-period selected by CHANNEL
+    wire sample_now = (div == HALF - 1) && (o_pdm_clk == (CHANNEL == 0));
+
+    always @(posedge i_clk or posedge i_rst) begin
+        if (i_rst) begin
+            data_sync <= 2'b00;
+            div <= {DIV_WIDTH{1'b0}};
+            bit_count <= {BIT_WIDTH{1'b0}};
+            ones <= {SAMPLE_WIDTH{1'b0}};
+            o_pdm_clk <= 1'b0;
+            o_sample <= {SAMPLE_WIDTH{1'b0}};
+            o_valid <= 1'b0;
+        end else begin
+            data_sync <= {data_sync[0], i_pdm_data};
+            o_valid <= 1'b0;
+
+            if (div == HALF - 1) begin
+                div <= {DIV_WIDTH{1'b0}};
+                o_pdm_clk <= ~o_pdm_clk;
+            end else begin
+                div <= div + 1'b1;
+            end
+
+            if (sample_now) begin
+                if (bit_count == DECIMATION - 1) begin
+                    o_sample <= ones + data_sync[1];
+                    o_valid <= 1'b1;
+                    ones <= {SAMPLE_WIDTH{1'b0}};
+                    bit_count <= {BIT_WIDTH{1'b0}};
+                end else begin
+                    ones <= ones + data_sync[1];
+                    bit_count <= bit_count + 1'b1;
+                end
+            end
+        end
+    end
+
+endmodule
@@ -0,0 +1,66 @@
+/**
+ * PDM microphone test: a modelled mic drives data on the rising PDM clock
+ * edge and the decimated samples match the density of ones.
+ */
+`timescale 1ns / 1ps
+`include "affogato_tb.vh"
+
+module pdm_mic_tb;
+    `TB_DECLARE
+
+    localparam DECIMATION = 64;
+
+    wire clk;
+    reg rst = 1'b1;
+    wire pdm_clk;
+    reg pdm_data = 1'b0;
+    wire [6:0] sample;
+    wire valid;
+
+    // 0: all zeros, 1: all ones, 2: three ones in every four bits
+    integer pattern = 0;
+    integer phase = 0;
+
+    affogato_clock clock (.clk(clk));
+
+    pdm_mic #(.CLK_DIV(16), .DECIMATION(DECIMATION), .CHANNEL(0)) dut (
+        .i_clk(clk),
+        .i_rst(rst),
+        .o_pdm_clk(pdm_clk),
+        .i_pdm_data(pdm_data),
+        .o_sample(sample),
+        .o_valid(valid)
+    );
+
+    // Microphone model
+    always @(posedge pdm_clk) begin
+        phase = (phase + 1) % 4;
+        case (pattern)
+            0: pdm_data <= 1'b0;
+            1: pdm_data <= 1'b1;
+            default: pdm_data <= (phase != 0);
+        endcase
+    end
+
+    // The first sample after a pattern change may mix old and new data
+    task check_pattern(input integer value, input [6:0] expected);
+        begin
+            pattern = value;
+            @(posedge valid);
+            @(posedge valid);
+            `ASSERT_EQ(sample, expected, "decimated sample")
+        end
+    endtask
+
+    initial begin
+        repeat (4) @(posedge clk);
+        rst <= 1'b0;
+
+        check_pattern(0, 7'd0);
+        check_pattern(1, DECIMATION);
+        check_pattern(2, DECIMATION * 3 / 4);
+
+        `TB_FINISH
+    end
+
+endmodule
@@ -0,0 +1,40 @@
+/**
+ * PWM Generator
+ *
+ * Free-running pulse-width modulator with a period of 2^WIDTH clocks.
+ * The duty cycle is latched at the start of each period, so changing it
+ * never produces a short or long glitch pulse.
+ *
+ * Parameters:
+ *   WIDTH - Counter width in bits (default: 8, a 187.5 kHz period at 48 MHz)
+ *
+ * Ports:
+ *   i_duty - High time in clocks per period (0 = always low)
+ *   o_pwm  - High for i_duty out of every 2^WIDTH clocks
+ */
+module pwm #(
+    parameter WIDTH = 8
+) (
+    input wire i_clk,
+    input wire i_rst,
+    input wire [WIDTH-1:0] i_duty,
+    output reg o_pwm
+);
+
+    reg [WIDTH-1:0] counter;
+    reg [WIDTH-1:0] duty;
+
+    always @(posedge i_clk or posedge i_rst) begin
+        if (i_rst) begin
+            counter <= {WIDTH{1'b0}};
+            duty <= {WIDTH{1'b0}};
+            o_pwm <= 1'b0;
+        end else begin
+            counter <= counter + 1'b1;
+            if (counter == {WIDTH{1'b1}})
+                duty <= i_duty;
+            o_pwm <= (counter < duty);
+        end
+    end
+
+endmodule
@@ -0,0 +1,57 @@
+/**
+ * PWM test: high time per period matches the duty cycle, including 0% and
+ * the maximum.
+ */
+`timescale 1ns / 1ps
+`include "affogato_tb.vh"
+
+module pwm_tb;
+    `TB_DECLARE
+
+    localparam WIDTH = 4;
+    localparam PERIOD = 1 << WIDTH;
+
+    wire clk;
+    reg rst = 1'b1;
+    reg [WIDTH-1:0] duty = 0;
+    wire out;
+
+    affogato_clock clock (.clk(clk));
+
+    pwm #(.WIDTH(WIDTH)) dut (
+        .i_clk(clk),
+        .i_rst(rst),
+        .i_duty(duty),
+        .o_pwm(out)
+    );
+
+    task check_duty(input [WIDTH-1:0] value);
+        integer high;
+        begin
+            duty <= value;
+            // Let the new duty cycle be latched at a period boundary
+            repeat (2 * PERIOD) @(posedge clk);
+            high = 0;
+            repeat (PERIOD) begin
+                @(posedge clk);
+                if (out)
+                    high = high + 1;
+            end
+            `ASSERT_EQ(high, value, "high clocks per period")
+        end
+    endtask
+
+    initial begin
+        repeat (4) @(posedge clk);
+        rst <= 1'b0;
+
+        check_duty(0);
+        check_duty(1);
+        check_duty(4);
+        check_duty(PERIOD / 2);
+        check_duty(PERIOD - 1);
+
+        `TB_FINISH
+    end
+
+endmodule
@@ -0,0 +1,122 @@
+/**
+ * SPI to Register Bus Bridge
+ *
+ * SPI slave (mode 0) that turns transactions from the ESP32 into accesses
+ * on the simple register interface used by generated cores and register
+ * maps (word address, 16-bit write strobe, combinational read data).
+ * SCK, CS and MOSI are oversampled in the system clock domain, so i_clk
+ * must be at least 16x the SPI clock (48 MHz allows up to 3 MHz SCK).
+ *
+ * Protocol (16-bit words, MSB first):
+ *   [1-bit write][15-bit address][data word]...
+ *
+ *   Write: each data word is written to address, address+1, ...
+ *   Read:  MISO returns the words at address, address+1, ...
+ *
+ * Ports:
+ *   o_addr  - Register address (word addressed)
+ *   o_wdata - Write data, valid while o_we is high
+ *   o_we    - Pulses high for one clock per written word
+ *   i_rdata - Read data for o_addr (combinational from the register block)
+ */
+module spi_bridge (
+    input wire i_clk,
+    input wire i_rst,
+
+    // SPI interface (asynchronous to i_clk)
+    input wire i_cs_n,
+    input wire i_sck,
+    input wire i_mosi,
+    output reg o_miso,
+
+    // Register interface
+    output reg [14:0] o_addr,
+    output reg [15:0] o_wdata,
+    output reg o_we,
+    input wire [15:0] i_rdata
+);
+
+    reg [2:0] sck_sync;
+    reg [1:0] cs_sync;
+    reg [1:0] mosi_sync;
+
+    wire sck_rise = (sck_sync[2:1] == 2'b01);
+    wire sck_fall = (sck_sync[2:1] == 2'b10);
+    wire selected = ~cs_sync[1];
+    wire mosi = mosi_sync[1];
+
+    reg [3:0] bit_count;
+    reg [14:0] rx_shift;
+    reg [15:0] tx_shift;
+    reg header;
+    reg write;
+    reg advance;
+    reg load;
+
+    wire [15:0] rx_word = {rx_shift, mosi};
+
+    always @(posedge i_clk or posedge i_rst) begin
+        if (i_rst) begin
+            sck_sync <= 3'b000;
+            cs_sync <= 2'b11;
+            mosi_sync <= 2'b00;
+            bit_count <= 4'd0;
+            rx_shift <= 15'd0;
+            tx_shift <= 16'd0;
+            header <= 1'b1;
+            write <= 1'b0;
+            advance <= 1'b0;
+            load <= 1'b0;
+            o_miso <= 1'b0;
+            o_addr <= 15'd0;
+            o_wdata <= 16'd0;
+            o_we <= 1'b0;
+        end else begin
+            sck_sync <= {sck_sync[1:0], i_sck};
+            cs_sync <= {cs_sync[0], i_cs_n};
+            mosi_sync <= {mosi_sync[0], i_mosi};
+            o_we <= 1'b0;
+
+            // Move to the next word after a write has been seen at the old address
+            if (advance) begin
+                o_addr <= o_addr + 15'd1;
+                advance <= 1'b0;
+                load <= 1'b1;
+            end
+
+            // Read data is valid the cycle after o_addr changes
+            if (load) begin
+                tx_shift <= i_rdata;
+                load <= 1'b0;
+            end
+
+            if (!selected) begin
+                bit_count <= 4'd0;
+                header <= 1'b1;
+                o_miso <= 1'b0;
+            end else if (sck_rise) begin
+                rx_shift <= rx_word[14:0];
+                bit_count <= bit_count + 4'd1;
+                if (bit_count == 4'd15) begin
+                    if (header) begin
+                        write <= rx_word[15];
+                        o_addr <= rx_word[14:0];
+                        header <= 1'b0;
+                        load <= 1'b1;
+                    end else begin
+                        if (write) begin
+                            o_wdata <= rx_word;
+                            o_we <= 1'b1;
+                        end
+                        advance <= 1'b1;
+                    end
+                end
+            end else if (sck_fall) begin
+                // Mode 0: MISO changes on the falling edge, ready for the next rising edge
+                o_miso <= tx_shift[15];
+                tx_shift <= {tx_shift[14:0], 1'b0};
+            end
+        end
+    end
+
+endmodule
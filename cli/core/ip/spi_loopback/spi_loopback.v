@@ -0,0 +1,39 @@
+/**
+ * SPI Loopback
+ *
+ * SPI slave (mode 0) that echoes MOSI back on MISO one byte later, for
+ * `affogato bench spi` to measure the ESP32 to FPGA link. It runs entirely
+ * on SCK rather than oversampling it, so it keeps up with the fastest clock
+ * the pins and board traces allow; any bit errors come from the link.
+ *
+ * Within a transaction each byte read back is the byte written just before
+ * it. The first byte of a transaction returns the last byte of the previous
+ * one. There is no reset: the delay line powers up as zero.
+ *
+ * Ports:
+ *   i_cs_n - Chip select, active low
+ *   i_sck  - SPI clock
+ *   i_mosi - Data from the master, sampled on the rising edge
+ *   o_miso - Data to the master, changed on the falling edge
+ */
+module spi_loopback (
+    input wire i_cs_n,
+    input wire i_sck,
+    input wire i_mosi,
+    output reg o_miso = 1'b0
+);
+
+    // The last eight bits received, oldest in bit 7
+    reg [7:0] r_delay = 8'h00;
+
+    always @(posedge i_sck) begin
+        if (!i_cs_n)
+            r_delay <= {r_delay[6:0], i_mosi};
+    end
+
+    always @(negedge i_sck) begin
+        if (!i_cs_n)
+            o_miso <= r_delay[7];
+    end
+
+endmodule
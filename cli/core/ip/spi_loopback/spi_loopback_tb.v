@@ -0,0 +1,66 @@
+/**
+ * SPI loopback test: bytes echo one byte later, within and across
+ * transactions, at a fast SPI clock.
+ */
+`timescale 1ns / 1ps
+`include "affogato_tb.vh"
+
+module spi_loopback_tb;
+    `TB_DECLARE
+
+    wire sck;
+    wire mosi;
+    wire miso;
+    wire cs_n;
+
+    reg [7:0] rx;
+    reg [7:0] tx;
+    reg [7:0] last;
+    integer i;
+    integer seed = `AFFOGATO_SEED;
+
+    affogato_spi_master #(.MODE(0), .FREQ_HZ(20_000_000)) spi (
+        .sck(sck),
+        .mosi(mosi),
+        .miso(miso),
+        .cs_n(cs_n)
+    );
+
+    spi_loopback dut (
+        .i_cs_n(cs_n),
+        .i_sck(sck),
+        .i_mosi(mosi),
+        .o_miso(miso)
+    );
+
+    initial begin
+        // Each byte comes back during the next one
+        spi.select();
+        spi.transfer(8'hA5, rx);
+        spi.transfer(8'h3C, rx);
+        `ASSERT_EQ(rx, 8'hA5, "first byte echoed")
+        spi.transfer(8'h00, rx);
+        `ASSERT_EQ(rx, 8'h3C, "second byte echoed")
+        spi.transfer(8'hFF, rx);
+        `ASSERT_EQ(rx, 8'h00, "zero echoed")
+        spi.deselect();
+
+        // The last byte of a transaction opens the next one
+        spi.select();
+        spi.transfer(8'h00, rx);
+        `ASSERT_EQ(rx, 8'hFF, "previous transaction's last byte")
+
+        // A random stream
+        last = 8'h00;
+        for (i = 0; i < 64; i = i + 1) begin
+            tx = $random(seed);
+            spi.transfer(tx, rx);
+            `ASSERT_EQ(rx, last, "random byte echoed")
+            last = tx;
+        end
+        spi.deselect();
+
+        `TB_FINISH
+    end
+
+endmodule
@@ -0,0 +1,121 @@
+/**
+ * SPI Slave Register File
+ *
+ * A bank of 8-bit registers read and written by the ESP32 over SPI mode 0.
+ * SCK, CS and MOSI are oversampled in the system clock domain, so i_clk
+ * must be at least 8x the SPI clock (48 MHz allows up to 6 MHz SCK).
+ *
+ * Protocol:
+ *   [1-bit write][7-bit address][8-bit data]...
+ *
+ *   Write: data bytes are stored starting at address
+ *   Read:  MISO returns the registers starting at address
+ *   Longer transactions auto-increment the address.
+ *
+ * Parameters:
+ *   NUM_REGS - Number of registers (default: 8, at most 128)
+ *
+ * Ports:
+ *   o_regs         - All registers, register N in bits [N*8 +: 8]
+ *   o_write_strobe - Pulses high for one clock after each register write
+ *   o_write_addr   - Address of the last register written
+ */
+module spi_regfile #(
+    parameter NUM_REGS = 8
+) (
+    input wire i_clk,
+    input wire i_rst,
+
+    // SPI interface (asynchronous to i_clk)
+    input wire i_cs_n,
+    input wire i_sck,
+    input wire i_mosi,
+    output reg o_miso,
+
+    // Register outputs
+    output reg [NUM_REGS*8-1:0] o_regs,
+    output reg o_write_strobe,
+    output reg [6:0] o_write_addr
+);
+
+    reg [2:0] sck_sync;
+    reg [1:0] cs_sync;
+    reg [1:0] mosi_sync;
+
+    wire sck_rise = (sck_sync[2:1] == 2'b01);
+    wire sck_fall = (sck_sync[2:1] == 2'b10);
+    wire selected = ~cs_sync[1];
+    wire mosi = mosi_sync[1];
+
+    reg [2:0] bit_count;
+    reg [6:0] rx_shift;
+    reg [7:0] tx_shift;
+    reg first_byte;
+    reg write;
+    reg [6:0] addr;
+
+    wire [7:0] rx_byte = {rx_shift, mosi};
+
+    function [7:0] read_reg(input [6:0] a);
+        begin
+            if (a < NUM_REGS)
+                read_reg = o_regs[a*8 +: 8];
+            else
+                read_reg = 8'h00;
+        end
+    endfunction
+
+    always @(posedge i_clk or posedge i_rst) begin
+        if (i_rst) begin
+            sck_sync <= 3'b000;
+            cs_sync <= 2'b11;
+            mosi_sync <= 2'b00;
+            bit_count <= 3'd0;
+            rx_shift <= 7'd0;
+            tx_shift <= 8'd0;
+            first_byte <= 1'b1;
+            write <= 1'b0;
+            addr <= 7'd0;
+            o_miso <= 1'b0;
+            o_regs <= {NUM_REGS*8{1'b0}};
+            o_write_strobe <= 1'b0;
+            o_write_addr <= 7'd0;
+        end else begin
+            sck_sync <= {sck_sync[1:0], i_sck};
+            cs_sync <= {cs_sync[0], i_cs_n};
+            mosi_sync <= {mosi_sync[0], i_mosi};
+            o_write_strobe <= 1'b0;
+
+            if (!selected) begin
+                bit_count <= 3'd0;
+                tx_shift <= 8'd0;
+                first_byte <= 1'b1;
+                o_miso <= 1'b0;
+            end else if (sck_rise) begin
+                rx_shift <= rx_byte[6:0];
+                bit_count <= bit_count + 3'd1;
+                if (bit_count == 3'd7) begin
+                    if (first_byte) begin
+                        write <= rx_byte[7];
+                        addr <= rx_byte[6:0];
+                        tx_shift <= read_reg(rx_byte[6:0]);
+                        first_byte <= 1'b0;
+                    end else begin
+                        if (write && addr < NUM_REGS) begin
+                            o_regs[addr*8 +: 8] <= rx_byte;
+                            o_write_strobe <= 1'b1;
+                            o_write_addr <= addr;
+                        end
+                        addr <= addr + 7'd1;
+                        tx_shift <= read_reg(addr + 7'd1);
+                    end
+                end
+            end else if (sck_fall) begin
+                // Mode 0: MISO changes on the falling edge, ready for the next rising edge
+                o_miso <= tx_shift[7];
+                tx_shift <= {tx_shift[6:0], 1'b0};
+            end
+        end
+    end
+
+endmodule
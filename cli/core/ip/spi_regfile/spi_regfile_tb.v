@@ -0,0 +1,85 @@
+/**
+ * SPI register file test: burst writes, burst reads and out-of-range access.
+ */
+`timescale 1ns / 1ps
+`include "affogato_tb.vh"
+
+module spi_regfile_tb;
+    `TB_DECLARE
+
+    wire clk;
+    reg rst = 1'b1;
+
+    wire sck;
+    wire mosi;
+    wire miso;
+    wire cs_n;
+
+    wire [8*8-1:0] regs;
+    wire write_strobe;
+    wire [6:0] write_addr;
+
+    reg [7:0] rx;
+    integer writes = 0;
+
+    affogato_clock clock (.clk(clk));
+
+    affogato_spi_master #(.MODE(0), .FREQ_HZ(1_000_000)) spi (
+        .sck(sck),
+        .mosi(mosi),
+        .miso(miso),
+        .cs_n(cs_n)
+    );
+
+    spi_regfile #(.NUM_REGS(8)) dut (
+        .i_clk(clk),
+        .i_rst(rst),
+        .i_cs_n(cs_n),
+        .i_sck(sck),
+        .i_mosi(mosi),
+        .o_miso(miso),
+        .o_regs(regs),
+        .o_write_strobe(write_strobe),
+        .o_write_addr(write_addr)
+    );
+
+    always @(posedge clk) begin
+        if (write_strobe)
+            writes = writes + 1;
+    end
+
+    initial begin
+        repeat (4) @(posedge clk);
+        rst <= 1'b0;
+        repeat (4) @(posedge clk);
+
+        // Write 0xA5, 0x3C to registers 2 and 3
+        spi.write_bytes({8'h82, 8'hA5, 8'h3C}, 3);
+        repeat (8) @(posedge clk);
+        `ASSERT_EQ(regs[2*8 +: 8], 8'hA5, "register 2 written")
+        `ASSERT_EQ(regs[3*8 +: 8], 8'h3C, "register 3 written")
+        `ASSERT_EQ(writes, 2, "write strobes")
+        `ASSERT_EQ(write_addr, 7'd3, "last write address")
+
+        // Read them back in one burst
+        spi.select();
+        spi.transfer(8'h02, rx);
+        spi.transfer(8'h00, rx);
+        `ASSERT_EQ(rx, 8'hA5, "read register 2")
+        spi.transfer(8'h00, rx);
+        `ASSERT_EQ(rx, 8'h3C, "read register 3")
+        spi.deselect();
+
+        // Registers past NUM_REGS read as zero and ignore writes
+        spi.write_bytes({8'h8A, 8'hFF}, 2);
+        spi.select();
+        spi.transfer(8'h0A, rx);
+        spi.transfer(8'h00, rx);
+        spi.deselect();
+        `ASSERT_EQ(rx, 8'h00, "out-of-range register reads zero")
+        `ASSERT_EQ(writes, 2, "out-of-range write ignored")
+
+        `TB_FINISH
+    end
+
+endmodule
@@ -0,0 +1,95 @@
+/**
+ * UART Receiver
+ *
+ * 8N1 serial receiver. The input is synchronized to i_clk and each bit is
+ * sampled in the middle of its bit period.
+ *
+ * Parameters:
+ *   CLK_HZ - System clock frequency (default: 48 MHz)
+ *   BAUD   - Baud rate (default: 115200); CLK_HZ / BAUD must fit in 16 bits
+ *
+ * Ports:
+ *   i_rx          - Serial input (asynchronous)
+ *   o_data        - Last received byte
+ *   o_valid       - Pulses high for one clock when o_data is updated
+ *   o_frame_error - Pulses high instead of o_valid when the stop bit is low
+ */
+module uart_rx #(
+    parameter CLK_HZ = 48_000_000,
+    parameter BAUD = 115_200
+) (
+    input wire i_clk,
+    input wire i_rst,
+    input wire i_rx,
+    output reg [7:0] o_data,
+    output reg o_valid,
+    output reg o_frame_error
+);
+
+    localparam DIVISOR = CLK_HZ / BAUD;
+
+    localparam STATE_IDLE  = 2'd0;
+    localparam STATE_START = 2'd1;
+    localparam STATE_DATA  = 2'd2;
+    localparam STATE_STOP  = 2'd3;
+
+    reg [1:0] rx_sync;
+    reg [1:0] state;
+    reg [2:0] bit_index;
+    reg [7:0] shift;
+    reg [15:0] count;
+
+    wire rx = rx_sync[1];
+
+    always @(posedge i_clk or posedge i_rst) begin
+        if (i_rst) begin
+            rx_sync <= 2'b11;
+            state <= STATE_IDLE;
+            bit_index <= 3'd0;
+            shift <= 8'd0;
+            count <= 16'd0;
+            o_data <= 8'd0;
+            o_valid <= 1'b0;
+            o_frame_error <= 1'b0;
+        end else begin
+            rx_sync <= {rx_sync[0], i_rx};
+            o_valid <= 1'b0;
+            o_frame_error <= 1'b0;
+
+            if (state == STATE_IDLE) begin
+                if (!rx) begin
+                    // Wait half a bit to sample in the middle of the start bit
+                    state <= STATE_START;
+                    count <= DIVISOR / 2 - 1;
+                end
+            end else if (count != 16'd0) begin
+                count <= count - 16'd1;
+            end else begin
+                count <= DIVISOR - 1;
+                case (state)
+                    STATE_START: begin
+                        // A start bit that has gone high again was a glitch
+                        state <= rx ? STATE_IDLE : STATE_DATA;
+                        bit_index <= 3'd0;
+                    end
+                    STATE_DATA: begin
+                        shift <= {rx, shift[7:1]};
+                        bit_index <= bit_index + 3'd1;
+                        if (bit_index == 3'd7)
+                            state <= STATE_STOP;
+                    end
+                    default: begin
+                        if (rx) begin
+                            o_data <= shift;
+                            o_valid <= 1'b1;
+                        end else begin
+                            o_frame_error <= 1'b1;
+                        end
+                        state <= STATE_IDLE;
+                    end
+                endcase
+            end
+        end
+    end
+
+endmodule
@@ -0,0 +1,75 @@
+/**
+ * UART loopback test: bytes sent by uart_tx are received by uart_rx.
+ */
+`timescale 1ns / 1ps
+`include "affogato_tb.vh"
+
+module uart_tb;
+    `TB_DECLARE
+
+    localparam CLK_HZ = 48_000_000;
+    localparam BAUD = 3_000_000;
+
+    wire clk;
+    reg rst = 1'b1;
+
+    reg [7:0] tx_data = 8'd0;
+    reg tx_valid = 1'b0;
+    wire tx_ready;
+    wire line;
+
+    wire [7:0] rx_data;
+    wire rx_valid;
+    wire rx_frame_error;
+
+    affogato_clock #(.FREQ_HZ(CLK_HZ)) clock (.clk(clk));
+
+    uart_tx #(.CLK_HZ(CLK_HZ), .BAUD(BAUD)) tx (
+        .i_clk(clk),
+        .i_rst(rst),
+        .i_data(tx_data),
+        .i_valid(tx_valid),
+        .o_ready(tx_ready),
+        .o_tx(line)
+    );
+
+    uart_rx #(.CLK_HZ(CLK_HZ), .BAUD(BAUD)) rx (
+        .i_clk(clk),
+        .i_rst(rst),
+        .i_rx(line),
+        .o_data(rx_data),
+        .o_valid(rx_valid),
+        .o_frame_error(rx_frame_error)
+    );
+
+    always @(posedge clk) begin
+        `ASSERT(!rx_frame_error, "no framing errors")
+    end
+
+    task send_and_check(input [7:0] value);
+        begin
+            @(posedge clk);
+            while (!tx_ready) @(posedge clk);
+            tx_data <= value;
+            tx_valid <= 1'b1;
+            @(posedge clk);
+            tx_valid <= 1'b0;
+            @(posedge rx_valid);
+            `ASSERT_EQ(rx_data, value, "received byte")
+        end
+    endtask
+
+    initial begin
+        repeat (4) @(posedge clk);
+        rst <= 1'b0;
+        `ASSERT(line, "line idles high")
+
+        send_and_check(8'h55);
+        send_and_check(8'hA3);
+        send_and_check(8'h00);
+        send_and_check(8'hFF);
+
+        `TB_FINISH
+    end
+
+endmodule
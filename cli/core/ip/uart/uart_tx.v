@@ -0,0 +1,60 @@
+/**
+ * UART Transmitter
+ *
+ * 8N1 serial transmitter. Bytes are accepted with a valid/ready handshake
+ * and sent LSB first with one start and one stop bit.
+ *
+ * Parameters:
+ *   CLK_HZ - System clock frequency (default: 48 MHz)
+ *   BAUD   - Baud rate (default: 115200); CLK_HZ / BAUD must fit in 16 bits
+ *
+ * Ports:
+ *   i_data  - Byte to send, taken when i_valid and o_ready are both high
+ *   o_ready - High while idle (including after the stop bit)
+ *   o_tx    - Serial output, idles high
+ */
+module uart_tx #(
+    parameter CLK_HZ = 48_000_000,
+    parameter BAUD = 115_200
+) (
+    input wire i_clk,
+    input wire i_rst,
+    input wire [7:0] i_data,
+    input wire i_valid,
+    output wire o_ready,
+    output reg o_tx
+);
+
+    localparam DIVISOR = CLK_HZ / BAUD;
+
+    reg [8:0] shift;
+    reg [3:0] bits_left;
+    reg [15:0] count;
+
+    assign o_ready = (bits_left == 4'd0);
+
+    always @(posedge i_clk or posedge i_rst) begin
+        if (i_rst) begin
+            shift <= 9'h1FF;
+            bits_left <= 4'd0;
+            count <= 16'd0;
+            o_tx <= 1'b1;
+        end else if (o_ready) begin
+            if (i_valid) begin
+                // Start bit now; data bits and the stop bit follow from shift
+                o_tx <= 1'b0;
+                shift <= {1'b1, i_data};
+                bits_left <= 4'd10;
+                count <= DIVISOR - 1;
+            end
+        end else if (count != 16'd0) begin
+            count <= count - 16'd1;
+        end else begin
+            o_tx <= shift[0];
+            shift <= {1'b1, shift[8:1]};
+            bits_left <= bits_left - 4'd1;
+            count <= DIVISOR - 1;
+        end
+    end
+
+endmodule
@@ -0,0 +1,100 @@
+use anyhow::{bail, Result};
+
+use crate::project::{AmaranthConfig, FpgaConfig};
+
+/// Where elaborated Verilog is written, relative to the project root
+pub const OUT_DIR: &str = "fpga/build/amaranth";
+
+/// The container's system Python, which has Amaranth installed; the ESP-IDF
+/// environment puts its own venv first on the PATH
+const PYTHON: &str = "/usr/bin/python3";
+
+/// Converts each design with `amaranth.back.verilog`. Elaboratables may
+/// provide `ports` (a list or a method) for Amaranth versions that need them.
+const ELABORATE_PY: &str = r#"import importlib
+import sys
+
+from amaranth.back import verilog
+
+sys.path.insert(0, sys.argv[1])
+out_dir = sys.argv[2]
+for spec in sys.argv[3:]:
+    module_name, class_name = spec.split(":")
+    design = getattr(importlib.import_module(module_name), class_name)()
+    ports = getattr(design, "ports", None)
+    kwargs = {}
+    if ports is not None:
+        kwargs["ports"] = ports() if callable(ports) else ports
+    name = class_name.lower()
+    with open(f"{out_dir}/{name}.v", "w") as f:
+        f.write(verilog.convert(design, name=name, **kwargs))
+    print(f"Elaborated {spec} -> {out_dir}/{name}.v")
+"#;
+
+/// A design entry, "module:Class"
+struct Design<'a> {
+    class: &'a str,
+}
+
+impl<'a> Design<'a> {
+    fn parse(spec: &'a str) -> Result<Self> {
+        match spec.split_once(':') {
+            Some((module, class)) if !module.is_empty() && !class.is_empty() => {
+                Ok(Design { class })
+            }
+            _ => bail!(
+                "Invalid Amaranth design {:?} (expected \"module:Class\")",
+                spec
+            ),
+        }
+    }
+
+    /// The generated Verilog module is named after the class, in lowercase
+    fn output(&self) -> String {
+        format!("{}/{}.v", OUT_DIR, self.class.to_lowercase())
+    }
+}
+
+/// Verilog files produced by elaboration, relative to the project root
+pub fn outputs(fpga_config: &FpgaConfig) -> Result<Vec<String>> {
+    let Some(amaranth) = &fpga_config.amaranth else {
+        return Ok(Vec::new());
+    };
+    amaranth
+        .designs
+        .iter()
+        .map(|spec| Ok(Design::parse(spec)?.output()))
+        .collect()
+}
+
+/// Shell snippet that elaborates the designs (empty if there are none)
+pub fn elaborate_script(fpga_config: &FpgaConfig) -> Result<String> {
+    let Some(amaranth) = fpga_config
+        .amaranth
+        .as_ref()
+        .filter(|a| !a.designs.is_empty())
+    else {
+        return Ok(String::new());
+    };
+    for spec in &amaranth.designs {
+        Design::parse(spec)?;
+    }
+
+    Ok(format!(
+        r#"echo "Elaborating Amaranth designs..."
+mkdir -p {OUT_DIR}
+PYTHONDONTWRITEBYTECODE=1 {PYTHON} - {path} {OUT_DIR} {designs} <<'AFFOGATO_EOF'
+{ELABORATE_PY}AFFOGATO_EOF
+"#,
+        path = amaranth.path,
+        designs = amaranth.designs.join(" "),
+    ))
+}
+
+/// Shell command running one Amaranth simulation script from the design directory
+pub fn simulation_command(amaranth: &AmaranthConfig, script: &str, seed: u32) -> String {
+    format!(
+        "cd {} && PYTHONDONTWRITEBYTECODE=1 AFFOGATO_SEED={} {} {}",
+        amaranth.path, seed, PYTHON, script
+    )
+}
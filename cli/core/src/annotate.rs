@@ -0,0 +1,300 @@
+use anyhow::{bail, Result};
+use colored::Colorize;
+use std::path::Path;
+
+use crate::docker::Docker;
+use crate::output::{self, Verbosity};
+use crate::project::Project;
+use crate::tool_log;
+
+/// How build and test failures are reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum ReportFormat {
+    /// Human-readable, colored output
+    #[default]
+    Text,
+    /// GitHub Actions `::error` workflow commands that annotate the PR diff
+    Gha,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+/// A GitHub Actions workflow command annotating a file (paths relative to the project root)
+pub fn workflow_command(
+    level: Level,
+    project_root: &Path,
+    file: Option<&str>,
+    line: u32,
+    column: u32,
+    title: Option<&str>,
+    message: &str,
+) -> String {
+    let mut properties = Vec::new();
+    if let Some(file) = file.filter(|f| !f.is_empty()) {
+        properties.push(format!(
+            "file={}",
+            escape_property(&repo_path(project_root, file))
+        ));
+        if line > 0 {
+            properties.push(format!("line={}", line));
+        }
+        if column > 0 {
+            properties.push(format!("col={}", column));
+        }
+    }
+    if let Some(title) = title.filter(|t| !t.is_empty()) {
+        properties.push(format!("title={}", escape_property(title)));
+    }
+
+    let command = match level {
+        Level::Error => "error",
+        Level::Warning => "warning",
+    };
+    if properties.is_empty() {
+        format!("::{}::{}", command, escape_data(message))
+    } else {
+        format!(
+            "::{} {}::{}",
+            command,
+            properties.join(","),
+            escape_data(message)
+        )
+    }
+}
+
+/// An error or warning a tool printed, with its location if it gave one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub file: Option<String>,
+    pub line: u32,
+    pub column: u32,
+    pub message: String,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}", file)?;
+            if self.line > 0 {
+                write!(f, ":{}", self.line)?;
+            }
+            if self.column > 0 {
+                write!(f, ":{}", self.column)?;
+            }
+            write!(f, ": ")?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Errors and warnings found in tool output, each once, in the order printed
+pub fn diagnostics(output: &str) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    for diagnostic in output.lines().filter_map(parse_diagnostic) {
+        if !diagnostics.contains(&diagnostic) {
+            diagnostics.push(diagnostic);
+        }
+    }
+    diagnostics
+}
+
+/// Annotations for compiler and synthesis errors found in tool output
+pub fn tool_errors(project_root: &Path, output: &str) -> Vec<String> {
+    let mut annotations: Vec<String> = Vec::new();
+    for diagnostic in diagnostics(output) {
+        if diagnostic.level != Level::Error {
+            continue;
+        }
+        let annotation = workflow_command(
+            Level::Error,
+            project_root,
+            diagnostic.file.as_deref(),
+            diagnostic.line,
+            diagnostic.column,
+            None,
+            &diagnostic.message,
+        );
+        if !annotations.contains(&annotation) {
+            annotations.push(annotation);
+        }
+    }
+    annotations
+}
+
+/// Warnings listed in a failed build's summary; the errors are all listed
+const SUMMARY_WARNINGS: usize = 10;
+
+/// Print a failed build's errors and warnings as one line each, so the
+/// failure needn't be dug out of the tools' output above
+pub fn print_summary(output: &str) {
+    let diagnostics = diagnostics(output);
+    let (errors, warnings): (Vec<_>, Vec<_>) = diagnostics
+        .iter()
+        .partition(|diagnostic| diagnostic.level == Level::Error);
+    if errors.is_empty() && warnings.is_empty() {
+        return;
+    }
+    outln!();
+    outln!(
+        "{}",
+        format!(
+            "==> {} error(s), {} warning(s)",
+            errors.len(),
+            warnings.len()
+        )
+        .blue()
+        .bold()
+    );
+    for error in &errors {
+        outln!("  {} {}", "error:".red().bold(), error);
+    }
+    for warning in warnings.iter().take(SUMMARY_WARNINGS) {
+        outln!("  {} {}", "warning:".yellow(), warning);
+    }
+    if warnings.len() > SUMMARY_WARNINGS {
+        outln!(
+            "  ... and {} more warning(s)",
+            warnings.len() - SUMMARY_WARNINGS
+        );
+    }
+}
+
+/// Lines of output shown when a script fails in quiet mode
+const QUIET_FAILURE_LINES: usize = 20;
+
+/// Run a script in the container, saving its output to the tool log; in GHA
+/// mode, annotate errors from its output on failure
+pub fn run_script(
+    docker: &Docker,
+    project: &Project,
+    script: &str,
+    format: ReportFormat,
+) -> Result<()> {
+    let (success, output) = docker.run_in_project_tee(project, &["bash", "-c", script], false)?;
+    if success {
+        return Ok(());
+    }
+
+    match format {
+        ReportFormat::Text => {
+            if output::verbosity() == Verbosity::Quiet {
+                let lines: Vec<&str> = output.lines().collect();
+                for line in &lines[lines.len().saturating_sub(QUIET_FAILURE_LINES)..] {
+                    eprintln!("{}", line);
+                }
+            }
+            print_summary(&output);
+        }
+        ReportFormat::Gha => {
+            let project_root = project.root.as_deref().unwrap_or(Path::new("."));
+            for annotation in tool_errors(project_root, &output) {
+                outln!("{}", annotation);
+            }
+        }
+    }
+    match tool_log::path() {
+        Some(log) => bail!("Command failed (full output in {})", log.display()),
+        None => bail!("Command failed"),
+    }
+}
+
+/// Recognize error and warning lines from iverilog, yosys, verilator,
+/// nextpnr and gcc
+fn parse_diagnostic(line: &str) -> Option<Diagnostic> {
+    let line = line.trim();
+    let diagnostic = |level, file, line, column, message: &str| Diagnostic {
+        level,
+        file,
+        line,
+        column,
+        message: message.trim().to_string(),
+    };
+
+    // verilator: %Error: file:line:col: message (also %Error-CODE:, %Warning-CODE:)
+    for (prefix, level) in [("%Error", Level::Error), ("%Warning", Level::Warning)] {
+        if let Some(rest) = line.strip_prefix(prefix) {
+            let (_, rest) = rest.split_once(": ")?;
+            return Some(match split_location(rest) {
+                Some((file, line, column, message)) => {
+                    diagnostic(level, Some(file), line, column, message)
+                }
+                None => diagnostic(level, None, 0, 0, rest),
+            });
+        }
+    }
+
+    // iverilog/yosys/gcc: file:line[:col]: [error:|ERROR:|warning:] message
+    if let Some((file, line_number, column, message)) = split_location(line) {
+        let lower = message.to_lowercase();
+        let level = if lower.starts_with("error")
+            || lower.starts_with("fatal error")
+            || lower.contains("syntax error")
+        {
+            Level::Error
+        } else if lower.starts_with("warning") {
+            Level::Warning
+        } else {
+            return None;
+        };
+        let message = ["ERROR:", "error:", "fatal error:", "warning:", "Warning:"]
+            .iter()
+            .fold(message, |message, prefix| {
+                message.trim_start_matches(prefix).trim_start()
+            });
+        return Some(diagnostic(level, Some(file), line_number, column, message));
+    }
+
+    // yosys/nextpnr: ERROR: message, Warning: message
+    if let Some(message) = line.strip_prefix("ERROR:") {
+        return Some(diagnostic(Level::Error, None, 0, 0, message));
+    }
+    line.strip_prefix("Warning:")
+        .map(|message| diagnostic(Level::Warning, None, 0, 0, message))
+}
+
+/// Split "file:line[:col]: message" where file looks like a source path
+fn split_location(text: &str) -> Option<(String, u32, u32, &str)> {
+    let (file, rest) = text.split_once(':')?;
+    if file.contains(' ') || !file.contains('.') {
+        return None;
+    }
+    let (line, rest) = rest.split_once(':')?;
+    let line: u32 = line.trim().parse().ok()?;
+    let (column, message) = match rest.split_once(':') {
+        Some((column, message)) if column.trim().parse::<u32>().is_ok() => {
+            (column.trim().parse().unwrap_or(0), message)
+        }
+        _ => (0, rest),
+    };
+    let file = file.strip_prefix("/workspace/").unwrap_or(file);
+    Some((file.to_string(), line, column, message.trim()))
+}
+
+/// Annotation paths must be relative to the repository checked out by the workflow
+fn repo_path(project_root: &Path, file: &str) -> String {
+    let Ok(workspace) = std::env::var("GITHUB_WORKSPACE") else {
+        return file.to_string();
+    };
+    project_root
+        .join(file)
+        .strip_prefix(workspace)
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|_| file.to_string())
+}
+
+fn escape_data(value: &str) -> String {
+    value
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(value: &str) -> String {
+    escape_data(value).replace(':', "%3A").replace(',', "%2C")
+}
@@ -0,0 +1,95 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::dry_run;
+
+/// The last firmware build's artifacts, relative to the project root
+pub const STATE_FILE: &str = ".affogato/state/artifacts.json";
+
+/// ESP-IDF's record of a build, which names the ELF and its toolchain
+const PROJECT_DESCRIPTION: &str = "firmware/build/project_description.json";
+
+/// Where to find what the last firmware build produced, and how to read it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Artifacts {
+    /// The application ELF, relative to the project root
+    pub elf: String,
+    /// The flashed application image, relative to the project root
+    pub bin: String,
+    /// ESP-IDF target, e.g. esp32s2
+    pub target: String,
+    /// Prefix of the toolchain's binutils, e.g. xtensa-esp32s2-elf-, for
+    /// decoding backtraces against the ELF
+    pub toolchain_prefix: String,
+}
+
+impl Artifacts {
+    /// The artifacts of the firmware build in firmware/build, from ESP-IDF's
+    /// project_description.json
+    fn from_build(project_root: &Path) -> Option<Self> {
+        let text = fs::read_to_string(project_root.join(PROJECT_DESCRIPTION)).ok()?;
+        let description: Value = serde_json::from_str(&text).ok()?;
+        let field = |name: &str| Some(description.get(name)?.as_str()?.to_string());
+        Some(Self {
+            elf: format!("firmware/build/{}", field("app_elf")?),
+            bin: format!("firmware/build/{}", field("app_bin")?),
+            target: field("target")?,
+            toolchain_prefix: field("monitor_toolchain_prefix")?,
+        })
+    }
+
+    /// The artifacts recorded by the last firmware build, if any
+    pub fn load(project_root: &Path) -> Result<Option<Self>> {
+        let path = project_root.join(STATE_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(&path)?;
+        let artifacts =
+            serde_json::from_str(&text).with_context(|| format!("Invalid {}", STATE_FILE))?;
+        Ok(Some(artifacts))
+    }
+}
+
+/// Record the firmware build just finished, built or restored from the cache
+pub fn record(project_root: &Path) -> Result<()> {
+    let Some(artifacts) = Artifacts::from_build(project_root) else {
+        return Ok(());
+    };
+    if let Some(dir) = project_root.join(STATE_FILE).parent() {
+        dry_run::create_dir_all(dir)?;
+    }
+    dry_run::write(
+        project_root.join(STATE_FILE),
+        serde_json::to_string_pretty(&artifacts)? + "\n",
+    )?;
+    Ok(())
+}
+
+/// The ELF to symbolicate against and its toolchain: `elf` if given, else
+/// the last build's. The target and toolchain come from the last build, or
+/// failing that from the build directory.
+pub fn resolve_elf(project_root: &Path, elf: Option<&Path>) -> Result<(PathBuf, Artifacts)> {
+    let artifacts = match Artifacts::load(project_root)? {
+        Some(artifacts) => artifacts,
+        None => Artifacts::from_build(project_root)
+            .context("No firmware build found; run `affogato build` first")?,
+    };
+    match elf {
+        Some(elf) if !elf.is_file() => bail!("{} not found", elf.display()),
+        Some(elf) => Ok((elf.to_path_buf(), artifacts)),
+        None => {
+            let elf = project_root.join(&artifacts.elf);
+            if !elf.is_file() {
+                bail!(
+                    "{} not found; build the firmware or pass --elf",
+                    artifacts.elf
+                );
+            }
+            Ok((elf, artifacts))
+        }
+    }
+}
@@ -0,0 +1,553 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+use tracing::{info_span, warn};
+
+use crate::amaranth;
+use crate::annotate::{self, ReportFormat};
+use crate::artifacts;
+use crate::build_info::{self, BuildInfo};
+use crate::cache;
+use crate::constraints;
+use crate::debug_console;
+use crate::delivery;
+use crate::deps::{self, VENDOR_DIR};
+use crate::docker::Docker;
+use crate::dry_run;
+use crate::error::Failure;
+use crate::floorplan::{self, FLOORPLAN_SVG, PLACED_JSON};
+use crate::ip::IP_DIR;
+use crate::output;
+use crate::project::{FpgaConfig, Project, ProjectConfig};
+use crate::sdkconfig;
+use crate::shared;
+use crate::softcore;
+use crate::synth_cache;
+use crate::timing;
+
+/// nextpnr's log of the last build, relative to the project root
+pub const NEXTPNR_LOG: &str = "fpga/build/nextpnr.log";
+
+/// What an FPGA build leaves behind, restored from the build cache on a hit
+/// and brought back from remote builds
+pub const FPGA_OUTPUTS: &[&str] = &[
+    "fpga/top.json",
+    "fpga/top.asc",
+    "fpga/top.bin",
+    PLACED_JSON,
+    NEXTPNR_LOG,
+    softcore::BUILD_DIR,
+];
+
+/// What a firmware build leaves behind; idf.py may normalize sdkconfig
+pub const FIRMWARE_OUTPUTS: &[&str] = &[
+    "firmware/build",
+    "firmware/sdkconfig",
+    build_info::FIRMWARE_HEADER,
+    build_info::STATE_FILE,
+];
+
+/// Cached builds kept of each kind; firmware build directories are large
+const FPGA_CACHE_ENTRIES: usize = 32;
+const FIRMWARE_CACHE_ENTRIES: usize = 8;
+
+/// Build FPGA bitstream using config or Makefile
+pub fn build_fpga(
+    docker: &Docker,
+    project: &Project,
+    extra_args: &[String],
+    format: ReportFormat,
+) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+
+    // Check if there's a Makefile (legacy path) and no config
+    if project_root.join("fpga/Makefile").exists() && project.config.is_none() {
+        if format == ReportFormat::Gha {
+            let script = format!("make -C fpga {}", extra_args.join(" "));
+            annotate::run_script(docker, project, &script, format)
+        } else {
+            docker.run_in_project(project, &["make", "-C", "fpga"], extra_args, false)
+        }
+        .context(Failure::BuildFailed)?;
+        report_bitstream(project_root);
+        archive_timing(project_root);
+        return Ok(());
+    }
+
+    // Use affogato.toml config for building
+    let config = project
+        .config
+        .as_ref()
+        .context("No affogato.toml found and no fpga/Makefile present")?;
+
+    build_fpga_with_config(docker, project, config, format)
+}
+
+/// Build FPGA using explicit config
+pub fn build_fpga_with_config(
+    docker: &Docker,
+    project: &Project,
+    config: &ProjectConfig,
+    format: ReportFormat,
+) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+
+    let fpga_config = &config.fpga;
+    let _span = info_span!("fpga_build", top = %fpga_config.top).entered();
+    deps::ensure(project)?;
+    sdkconfig::apply_loader(
+        project_root,
+        fpga_config.boot,
+        &config.loader,
+        config.firmware.bitstream,
+    )?;
+    let info = BuildInfo::current(project_root);
+    build_info::write_fpga(project_root, &info)?;
+    shared::write(project_root, &config.shared)?;
+    let verilog_files = synthesis_sources(project_root, fpga_config)?;
+    let elaborate = amaranth::elaborate_script(fpga_config)?;
+
+    // Soft-core projects synthesize a seed memory and swap the program in after PnR
+    let (seed, pack) = match &config.softcore {
+        Some(softcore) => (
+            softcore::seed_script(softcore)?,
+            format!(
+                "{}{}",
+                softcore::program_script(softcore)?,
+                softcore::patch_script("fpga/top.asc", "fpga/top.bin")
+            ),
+        ),
+        None => (
+            String::new(),
+            "echo \"Generating bitstream...\"\nicepack fpga/top.asc fpga/top.bin\n".to_string(),
+        ),
+    };
+
+    // Determine PCF file, merging in the selected profiles' fragments
+    let pcf_file = constraints::resolve(project_root, fpga_config)?;
+
+    // Build the synthesis command
+    let verilog_list = verilog_files.join(" ");
+    let top = &fpga_config.top;
+    let device = &fpga_config.device;
+    let package = &fpga_config.package;
+    let pnr_seed = fpga_config.seed;
+    let pnr_hooks = fpga_config.pnr.hooks();
+    for (_, script) in &pnr_hooks {
+        if !project_root.join(script).is_file() {
+            bail!("nextpnr script {} from [fpga.pnr] not found", script);
+        }
+    }
+    let pnr_hook_args: String = pnr_hooks
+        .iter()
+        .map(|(option, script)| format!(" {} {}", option, script))
+        .collect();
+
+    // Full build pipeline: yosys -> nextpnr -> icepack
+    let build_script = |synth: &str| {
+        format!(
+            r#"set -e
+cd /workspace
+mkdir -p fpga/build && rm -f {NEXTPNR_LOG}
+{elaborate}{seed}{synth}echo "Place and route with nextpnr..."
+nextpnr-ice40 --{device} --package {package} --json fpga/top.json --pcf {pcf_file} --seed {pnr_seed} --asc fpga/top.asc --write {PLACED_JSON} --log {NEXTPNR_LOG}{pnr_hook_args}
+{pack}echo "FPGA build complete: fpga/top.bin"
+"#
+        )
+    };
+    let synth_script = match &fpga_config.synth.script {
+        Some(path) => Some(
+            fs::read_to_string(project_root.join(path)).with_context(|| {
+                format!(
+                    "Cannot read the synthesis script {} from [fpga.synth]",
+                    path
+                )
+            })?,
+        ),
+        None => None,
+    };
+    let build_cmd = build_script(&match &synth_script {
+        Some(script) => synth_cache::script_command(script, top, &verilog_list),
+        None => synth_cache::whole_command(top, &verilog_list),
+    });
+
+    // The build id's timestamp changes every build; a design that embeds it
+    // keeps the one it was cached with rather than never hitting the cache
+    let uses_build_id = uses_build_id(project_root, &verilog_files);
+    let image_id = cache_image_id(docker);
+    let key = image_id.as_ref().map(|image_id| {
+        let mut key = cache::Key::new("fpga", image_id);
+        key.text("script", &build_cmd);
+        // Stitched netlists can differ from a whole-design synthesis
+        if fpga_config.incremental {
+            key.text("incremental", "true");
+        }
+        key.tree(
+            project_root,
+            "fpga",
+            &[
+                "fpga/build",
+                "fpga/top.json",
+                "fpga/top.asc",
+                "fpga/top.bin",
+            ],
+        );
+        let pnr_scripts = pnr_hooks.iter().map(|(_, script)| *script);
+        for file in verilog_files
+            .iter()
+            .map(String::as_str)
+            .chain([pcf_file.as_str()])
+            .chain(pnr_scripts)
+        {
+            // The merged constraints live under fpga/build, which the tree skips
+            if !file.starts_with("fpga/") || file == constraints::MERGED_PCF {
+                key.file(project_root, file);
+            }
+        }
+        if let Some(amaranth) = &fpga_config.amaranth {
+            key.tree(project_root, &amaranth.path, &[]);
+        }
+        if let Some(softcore) = &config.softcore {
+            key.tree(project_root, &softcore.dir, &[]);
+        }
+        if uses_build_id {
+            key.text("describe", &info.describe);
+        }
+        key.finish()
+    });
+    let mut outputs = FPGA_OUTPUTS.to_vec();
+    if uses_build_id {
+        outputs.push(build_info::FPGA_MODULE);
+    }
+
+    let restored = match &key {
+        Some(key) => cache::restore("fpga", key, project_root, &outputs)?,
+        None => None,
+    };
+    let info = match restored {
+        Some(entry) => {
+            outln!("{}", "Bitstream restored from the build cache".green());
+            match cached_info(&entry) {
+                Some(cached) if uses_build_id => cached,
+                _ => info,
+            }
+        }
+        None => {
+            let plan = match &image_id {
+                // A custom script decides how the design is synthesized
+                Some(image_id) if fpga_config.incremental && synth_script.is_none() => Some(
+                    synth_cache::Plan::new(project_root, top, &verilog_files, image_id)?,
+                ),
+                _ => None,
+            };
+            let build_cmd = match &plan {
+                Some(plan) => {
+                    plan.print();
+                    build_script(&plan.script(top, &verilog_list))
+                }
+                None => build_cmd,
+            };
+            annotate::run_script(docker, project, &build_cmd, format)
+                .map_err(|e| e.context(build_failure(project_root)))?;
+            if let Some(plan) = &plan {
+                plan.store(project_root);
+            }
+            if let Some(key) = &key {
+                cache::store(
+                    "fpga",
+                    key,
+                    project_root,
+                    &outputs,
+                    &[("build.json", serde_json::to_string(&info)?)],
+                    FPGA_CACHE_ENTRIES,
+                );
+            }
+            info
+        }
+    };
+    build_info::write_firmware(project_root, info)?;
+    debug_console::write(project_root, config.firmware.debug_console)?;
+    report_bitstream(project_root);
+    archive_timing(project_root);
+    if !dry_run::is_enabled() {
+        // The heatmap is a convenience; a design it can't read still built
+        match floorplan::write(project_root, device) {
+            Ok(()) => {
+                outln!("Floorplan: {}", FLOORPLAN_SVG);
+                output::report("floorplan", json!({ "path": FLOORPLAN_SVG }));
+            }
+            Err(e) => warn!("Could not draw the floorplan: {:#}", e),
+        }
+    }
+    Ok(())
+}
+
+/// Build the ESP32 firmware with `idf.py build` and any extra arguments,
+/// restoring it from the build cache when nothing it reads has changed
+pub fn build_firmware(
+    docker: &Docker,
+    project: &Project,
+    extra_args: &[String],
+    format: ReportFormat,
+) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    if let Some(config) = &project.config {
+        delivery::write(project_root, config.firmware.bitstream)?;
+        shared::write(project_root, &config.shared)?;
+    }
+    let idf_cmd = format!("cd firmware && idf.py build {}", extra_args.join(" "));
+
+    // The header's timestamp is left out of the key, as for the bitstream;
+    // a hit brings back the header and build record it was built with
+    let key = cache_image_id(docker).map(|image_id| {
+        let mut key = cache::Key::new("firmware", &image_id);
+        key.text("command", idf_cmd.trim_end());
+        key.tree(
+            project_root,
+            "firmware",
+            &[
+                "firmware/build",
+                "firmware/managed_components",
+                "firmware/sdkconfig.old",
+                build_info::FIRMWARE_HEADER,
+            ],
+        );
+        key.file(project_root, "fpga/top.bin");
+        if let Ok(Some(info)) = BuildInfo::load(project_root) {
+            key.text("describe", &info.describe);
+        }
+        key.finish()
+    });
+
+    if let Some(key) = &key {
+        if cache::restore("firmware", key, project_root, FIRMWARE_OUTPUTS)?.is_some() {
+            outln!("{}", "Firmware restored from the build cache".green());
+            artifacts::record(project_root)?;
+            report_firmware(project_root);
+            return Ok(());
+        }
+    }
+    annotate::run_script(docker, project, idf_cmd.trim_end(), format)
+        .context(Failure::BuildFailed)?;
+    if let Some(key) = &key {
+        cache::store(
+            "firmware",
+            key,
+            project_root,
+            FIRMWARE_OUTPUTS,
+            &[],
+            FIRMWARE_CACHE_ENTRIES,
+        );
+    }
+    artifacts::record(project_root)?;
+    report_firmware(project_root);
+    Ok(())
+}
+
+/// The toolchain image's id for cache keys, or None with the cache off or the
+/// image unknown
+fn cache_image_id(docker: &Docker) -> Option<String> {
+    if !cache::is_enabled() {
+        return None;
+    }
+    docker
+        .image_id()
+        .map_err(|e| warn!("Build cache unavailable: {:#}", e))
+        .ok()
+}
+
+/// Whether the design instantiates the `affogato_build_id` module
+fn uses_build_id(project_root: &Path, verilog_files: &[String]) -> bool {
+    verilog_files
+        .iter()
+        .filter(|file| *file != build_info::FPGA_MODULE)
+        .filter_map(|file| fs::read_to_string(project_root.join(file)).ok())
+        .any(|source| source.contains("affogato_build_id"))
+}
+
+/// The build metadata a cached bitstream was built with
+fn cached_info(entry: &Path) -> Option<BuildInfo> {
+    let text = fs::read_to_string(entry.join("build.json")).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+/// Why the build failed: nextpnr reports missed clock constraints as "FAIL at <freq>"
+fn build_failure(project_root: &Path) -> Failure {
+    let log = fs::read_to_string(project_root.join(NEXTPNR_LOG)).unwrap_or_default();
+    if log
+        .lines()
+        .any(|l| l.contains("Max frequency") && l.contains("FAIL"))
+    {
+        Failure::TimingFailed
+    } else {
+        Failure::BuildFailed
+    }
+}
+
+/// Record the bitstream size in the `--json` output
+fn report_bitstream(project_root: &Path) {
+    if let Ok(meta) = fs::metadata(project_root.join("fpga/top.bin")) {
+        output::report(
+            "bitstream",
+            json!({ "path": "fpga/top.bin", "size_bytes": meta.len() }),
+        );
+    }
+}
+
+/// Keep the build's timing for `affogato timing`; the build stands without it
+fn archive_timing(project_root: &Path) {
+    if let Err(e) = timing::archive(project_root) {
+        warn!("Could not archive the timing report: {:#}", e);
+    }
+}
+
+/// Record the firmware image built by ESP-IDF and its size in the `--json` output
+pub fn report_firmware(project_root: &Path) {
+    let Some(app_bin) = firmware_image(project_root) else {
+        return;
+    };
+    if let Ok(meta) = fs::metadata(project_root.join(&app_bin)) {
+        output::report(
+            "firmware",
+            json!({ "path": app_bin, "size_bytes": meta.len() }),
+        );
+    }
+}
+
+/// The app image of the last firmware build, relative to the project root
+pub fn firmware_image(project_root: &Path) -> Option<String> {
+    let description =
+        fs::read_to_string(project_root.join("firmware/build/project_description.json")).ok()?;
+    let app_bin = serde_json::from_str::<serde_json::Value>(&description)
+        .ok()?
+        .get("app_bin")?
+        .as_str()?
+        .to_string();
+    Some(format!("firmware/build/{}", app_bin))
+}
+
+/// Quick elaboration check: parse and elaborate the design without synthesis or PnR
+pub fn check_fpga(docker: &Docker, project: &Project) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+
+    let fpga_config = project
+        .config
+        .as_ref()
+        .map(|c| c.fpga.clone())
+        .unwrap_or_default();
+    deps::ensure(project)?;
+    if let Some(config) = &project.config {
+        shared::write(project_root, &config.shared)?;
+    }
+    let verilog_list = synthesis_sources(project_root, &fpga_config)?.join(" ");
+    let elaborate = amaranth::elaborate_script(&fpga_config)?;
+    let seed = match project.config.as_ref().and_then(|c| c.softcore.as_ref()) {
+        Some(softcore) => softcore::seed_script(softcore)?,
+        None => String::new(),
+    };
+    let top = &fpga_config.top;
+
+    let check_cmd = format!(
+        r#"set -e
+cd /workspace
+{elaborate}{seed}yosys -q -p "read_verilog -lib -specify +/ice40/cells_sim.v; read_verilog {verilog_list}; hierarchy -check -top {top}; proc; check"
+"#
+    );
+
+    annotate::run_script(docker, project, &check_cmd, ReportFormat::Text)
+}
+
+/// Verilog files passed to synthesis, relative to the project root
+pub fn synthesis_sources(project_root: &Path, fpga_config: &FpgaConfig) -> Result<Vec<String>> {
+    // Find all Verilog files in fpga/rtl/
+    let rtl_dir = project_root.join("fpga/rtl");
+    let mut verilog_files = Vec::new();
+
+    if rtl_dir.exists() {
+        for entry in std::fs::read_dir(&rtl_dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "v") {
+                // Use relative path from project root
+                let rel_path = path.strip_prefix(project_root)?;
+                verilog_files.push(rel_path.display().to_string());
+            }
+        }
+    }
+
+    // Add installed IP cores
+    let ip_dir = project_root.join(IP_DIR);
+    if ip_dir.exists() {
+        collect_verilog_files(&ip_dir, project_root, &mut verilog_files)?;
+    }
+
+    // Add third_party verilog files
+    let third_party_dir = project_root.join("fpga/third_party");
+    if third_party_dir.exists() {
+        collect_verilog_files(&third_party_dir, project_root, &mut verilog_files)?;
+    }
+
+    // Add vendored dependencies
+    let vendor_dir = project_root.join(VENDOR_DIR);
+    if vendor_dir.exists() {
+        collect_verilog_files(&vendor_dir, project_root, &mut verilog_files)?;
+    }
+
+    // Add any explicitly included paths from config
+    for include in &fpga_config.include {
+        let include_path = project_root.join(include);
+        if include_path.is_dir() {
+            collect_verilog_files(&include_path, project_root, &mut verilog_files)?;
+        } else if include_path.exists() {
+            let rel_path = include_path.strip_prefix(project_root)?;
+            verilog_files.push(rel_path.display().to_string());
+        }
+    }
+
+    // Add Verilog elaborated from Amaranth designs during the build
+    verilog_files.extend(amaranth::outputs(fpga_config)?);
+
+    // Add the build id module written by the last build
+    if project_root.join(build_info::FPGA_MODULE).exists() {
+        verilog_files.push(build_info::FPGA_MODULE.to_string());
+    }
+
+    if verilog_files.is_empty() {
+        anyhow::bail!("No Verilog files found in fpga/rtl/");
+    }
+
+    Ok(verilog_files)
+}
+
+/// Recursively collect Verilog files from a directory
+pub fn collect_verilog_files(
+    dir: &Path,
+    project_root: &Path,
+    files: &mut Vec<String>,
+) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.is_dir() {
+            collect_verilog_files(&path, project_root, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "v") {
+            let rel_path = path.strip_prefix(project_root)?;
+            files.push(rel_path.display().to_string());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,239 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::dry_run;
+
+/// Verilog module holding the build id, relative to the project root
+pub const FPGA_MODULE: &str = "fpga/build/affogato_build.v";
+
+/// C header with the firmware's build metadata, relative to the project root
+pub const FIRMWARE_HEADER: &str = "firmware/main/affogato_build.h";
+
+/// The last build's metadata, relative to the project root
+pub const STATE_FILE: &str = ".affogato/build.json";
+
+/// Console command the firmware answers with [`REPLY_PREFIX`] and its build
+pub const QUERY: &str = "version";
+
+/// Start of the line `affogato_build_print()` writes
+pub const REPLY_PREFIX: &str = "affogato-build:";
+
+/// What a build was made from, embedded in both the bitstream and the firmware
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BuildInfo {
+    /// `git describe --always --dirty`, or "unknown" outside a repository
+    pub describe: String,
+    /// The first 32 bits of the commit hash, 0 outside a repository
+    pub commit: u32,
+    /// Seconds since the epoch: `SOURCE_DATE_EPOCH` if set, else the build time
+    pub timestamp: u64,
+    /// SHA-256 of fpga/top.bin, known once the bitstream is built
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitstream_sha256: Option<String>,
+}
+
+impl BuildInfo {
+    /// Metadata for a build of the project's current tree
+    pub fn current(project_root: &Path) -> Self {
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .current_dir(project_root)
+                .args(args)
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        };
+        let timestamp = std::env::var("SOURCE_DATE_EPOCH")
+            .ok()
+            .and_then(|epoch| epoch.parse().ok())
+            .unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default()
+            });
+        Self {
+            describe: git(&["describe", "--always", "--dirty"])
+                .unwrap_or_else(|| "unknown".to_string()),
+            commit: git(&["rev-parse", "HEAD"])
+                .and_then(|hash| u32::from_str_radix(hash.get(..8)?, 16).ok())
+                .unwrap_or(0),
+            timestamp,
+            bitstream_sha256: None,
+        }
+    }
+
+    /// The metadata of the project's last build, if it has been built
+    pub fn load(project_root: &Path) -> Result<Option<Self>> {
+        let path = project_root.join(STATE_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(&path)?;
+        let info =
+            serde_json::from_str(&text).with_context(|| format!("Invalid {}", STATE_FILE))?;
+        Ok(Some(info))
+    }
+
+    /// Parse the fields of a [`REPLY_PREFIX`] line as `(key, value)` pairs
+    pub fn parse_reply(line: &str) -> Vec<(String, String)> {
+        line.trim()
+            .strip_prefix(REPLY_PREFIX)
+            .unwrap_or_default()
+            .split_whitespace()
+            .filter_map(|field| field.split_once('='))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+}
+
+/// Write the `affogato_build_id` module, whose constant outputs a design can
+/// expose (e.g. as read-only registers) for firmware to read back
+pub fn write_fpga(project_root: &Path, info: &BuildInfo) -> Result<()> {
+    let module = format!(
+        "// Generated by affogato build; do not edit
+//
+// Build id of this bitstream. Instantiate it and make its outputs readable
+// from the ESP32 to have `affogato version --device` report them.
+module affogato_build_id (
+    output wire [31:0] commit,
+    output wire [31:0] timestamp
+);
+    assign commit = 32'h{:08x};
+    assign timestamp = 32'd{};
+endmodule
+",
+        info.commit, info.timestamp as u32
+    );
+    write_if_changed(&project_root.join(FPGA_MODULE), &module)
+}
+
+/// Once the bitstream is built, write the firmware header and record the build
+pub fn write_firmware(project_root: &Path, mut info: BuildInfo) -> Result<()> {
+    if let Ok(bitstream) = fs::read(project_root.join("fpga/top.bin")) {
+        info.bitstream_sha256 = Some(
+            Sha256::digest(&bitstream)
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+        );
+    }
+    if project_root.join("firmware/main").is_dir() {
+        write_if_changed(&project_root.join(FIRMWARE_HEADER), &header(&info))?;
+    }
+    if let Some(dir) = project_root.join(STATE_FILE).parent() {
+        dry_run::create_dir_all(dir)?;
+    }
+    dry_run::write(
+        project_root.join(STATE_FILE),
+        serde_json::to_string_pretty(&info)? + "\n",
+    )?;
+    Ok(())
+}
+
+fn header(info: &BuildInfo) -> String {
+    format!(
+        r#"/**
+ * Build metadata - generated by `affogato build`; do not edit
+ */
+#pragma once
+
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+#include "esp_app_desc.h"
+#include "esp_err.h"
+#include "freertos/FreeRTOS.h"
+#include "freertos/task.h"
+
+#define AFFOGATO_BUILD_DESCRIBE    "{describe}"
+#define AFFOGATO_BUILD_COMMIT      0x{commit:08x}u
+#define AFFOGATO_BUILD_TIME        {timestamp}u
+#define AFFOGATO_BITSTREAM_SHA256  "{sha}"
+
+/*
+ * Define this to read the affogato_build_id values back from the FPGA, e.g.
+ * through a register map, so `affogato version --device` can check them.
+ */
+bool affogato_fpga_build_id(uint32_t *commit, uint32_t *timestamp) __attribute__((weak));
+
+/* From the ice40 component, when it is linked in */
+esp_err_t fpga_loader_get_sha256(uint8_t sha256[32]) __attribute__((weak));
+
+/* Print the line `affogato version --device` reads */
+static inline void affogato_build_print(void)
+{{
+    printf("{prefix} firmware=%s commit=%08lx time=%lu bitstream=%s",
+           AFFOGATO_BUILD_DESCRIBE, (unsigned long)AFFOGATO_BUILD_COMMIT,
+           (unsigned long)AFFOGATO_BUILD_TIME, AFFOGATO_BITSTREAM_SHA256);
+    char elf[65];
+    esp_app_get_elf_sha256(elf, sizeof(elf));
+    printf(" elf=%s", elf);
+    uint8_t loaded[32];
+    if (fpga_loader_get_sha256 && fpga_loader_get_sha256(loaded) == ESP_OK) {{
+        printf(" loaded=");
+        for (size_t i = 0; i < sizeof(loaded); i++) {{
+            printf("%02x", loaded[i]);
+        }}
+    }}
+    uint32_t commit, timestamp;
+    if (affogato_fpga_build_id && affogato_fpga_build_id(&commit, &timestamp)) {{
+        printf(" fpga_commit=%08lx fpga_time=%lu", (unsigned long)commit, (unsigned long)timestamp);
+    }}
+    printf("\n");
+    fflush(stdout);
+}}
+
+static inline void affogato_build_task(void *arg)
+{{
+    char line[16];
+    size_t len = 0;
+    for (;;) {{
+        int c = getchar();
+        if (c == EOF) {{
+            vTaskDelay(pdMS_TO_TICKS(20));
+        }} else if (c == '\n' || c == '\r') {{
+            line[len] = '\0';
+            if (strcmp(line, "{query}") == 0) {{
+                affogato_build_print();
+            }}
+            len = 0;
+        }} else if (len < sizeof(line) - 1) {{
+            line[len++] = (char)c;
+        }}
+    }}
+}}
+
+/* Answer `{query}` on the console with affogato_build_print() */
+static inline void affogato_build_serve(void)
+{{
+    xTaskCreate(affogato_build_task, "affogato_build", 3072, NULL, 1, NULL);
+}}
+"#,
+        describe = info.describe,
+        commit = info.commit,
+        timestamp = info.timestamp as u32,
+        sha = info.bitstream_sha256.as_deref().unwrap_or("unknown"),
+        prefix = REPLY_PREFIX,
+        query = QUERY,
+    )
+}
+
+/// Leave unchanged files alone so they don't trigger rebuilds
+pub(crate) fn write_if_changed(path: &Path, contents: &str) -> Result<()> {
+    if fs::read_to_string(path).is_ok_and(|old| old == contents) {
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        dry_run::create_dir_all(dir)?;
+    }
+    dry_run::write(path, contents)?;
+    Ok(())
+}
@@ -0,0 +1,254 @@
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::SystemTime;
+use tracing::{debug, warn};
+
+use crate::dry_run;
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Stamped on every use of an entry, so the least recently used go first
+const LAST_USED: &str = ".last-used";
+
+/// Kinds of build step with entries in the cache, each in a directory of its own
+pub const KINDS: &[&str] = &["fpga", "firmware", "synth"];
+
+/// Turn the shared build cache off, e.g. for `--no-cache` or builds that must
+/// run every tool
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+/// Whether builds may restore and store outputs; never in a dry run
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst) && !dry_run::is_enabled()
+}
+
+/// The cache shared by every project: `AFFOGATO_CACHE_DIR`, else
+/// `~/.cache/affogato` (or the platform's cache directory)
+pub fn dir() -> Option<PathBuf> {
+    std::env::var_os("AFFOGATO_CACHE_DIR")
+        .map(PathBuf::from)
+        .or_else(|| dirs::cache_dir().map(|dir| dir.join("affogato")))
+}
+
+/// Content hash of everything a build step reads, naming its cache entry
+pub struct Key(Sha256);
+
+impl Key {
+    /// A key for one kind of build step ("fpga", "firmware") run in the
+    /// image with `image_id`
+    pub fn new(kind: &str, image_id: &str) -> Self {
+        let mut key = Key(Sha256::new());
+        key.text("kind", kind);
+        key.text("image", image_id);
+        key
+    }
+
+    pub fn text(&mut self, label: &str, text: &str) {
+        for part in [label, text] {
+            self.0.update((part.len() as u64).to_le_bytes());
+            self.0.update(part.as_bytes());
+        }
+    }
+
+    /// A file's path and contents, relative to the project root
+    pub fn file(&mut self, project_root: &Path, path: &str) {
+        match fs::read(project_root.join(path)) {
+            Ok(contents) => {
+                self.text("file", path);
+                self.0.update((contents.len() as u64).to_le_bytes());
+                self.0.update(&contents);
+            }
+            Err(_) => self.text("missing", path),
+        }
+    }
+
+    /// Every file under `dir` but those under `skip`, in a stable order
+    pub fn tree(&mut self, project_root: &Path, dir: &str, skip: &[&str]) {
+        let mut files = Vec::new();
+        walk(&project_root.join(dir), &mut files);
+        let mut files: Vec<String> = files
+            .iter()
+            .filter_map(|file| file.strip_prefix(project_root).ok())
+            .map(|file| file.to_string_lossy().replace('\\', "/"))
+            .filter(|file| {
+                !skip
+                    .iter()
+                    .any(|skip| file == skip || file.starts_with(&format!("{}/", skip)))
+            })
+            .collect();
+        files.sort();
+        for file in files {
+            self.file(project_root, &file);
+        }
+    }
+
+    pub fn finish(self) -> String {
+        self.0
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            walk(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+fn entry_dir(kind: &str, key: &str) -> Option<PathBuf> {
+    Some(dir()?.join(kind).join(key))
+}
+
+/// Copy a cached step's `outputs` (files or directories relative to the
+/// project root) into the project. Returns the entry, for any metadata stored
+/// with it, or None on a miss.
+pub fn restore(
+    kind: &str,
+    key: &str,
+    project_root: &Path,
+    outputs: &[&str],
+) -> Result<Option<PathBuf>> {
+    let Some(entry) = entry_dir(kind, key).filter(|entry| entry.is_dir()) else {
+        debug!(kind, key, "build cache miss");
+        return Ok(None);
+    };
+    for output in outputs {
+        let cached = entry.join(output);
+        let target = project_root.join(output);
+        if cached.is_dir() {
+            if target.exists() {
+                fs::remove_dir_all(&target)
+                    .with_context(|| format!("Failed to replace {}", output))?;
+            }
+            copy_tree(&cached, &target)?;
+        } else if cached.exists() {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(&cached, &target).with_context(|| format!("Failed to restore {}", output))?;
+        }
+    }
+    touch(&entry.join(LAST_USED));
+    debug!(kind, key, "build cache hit");
+    Ok(Some(entry))
+}
+
+/// Save a step's `outputs` and `meta` files (name, contents) under its key,
+/// then drop the least recently used entries past `keep`. The cache is a
+/// convenience, so failures are logged rather than failing the build.
+pub fn store(
+    kind: &str,
+    key: &str,
+    project_root: &Path,
+    outputs: &[&str],
+    meta: &[(&str, String)],
+    keep: usize,
+) {
+    if let Err(e) = try_store(kind, key, project_root, outputs, meta) {
+        warn!("Could not save the {} build to the cache: {:#}", kind, e);
+        return;
+    }
+    prune(kind, keep);
+}
+
+fn try_store(
+    kind: &str,
+    key: &str,
+    project_root: &Path,
+    outputs: &[&str],
+    meta: &[(&str, String)],
+) -> Result<()> {
+    let entry = entry_dir(kind, key).context("No cache directory")?;
+    if entry.is_dir() {
+        return Ok(());
+    }
+    // Fill a private directory, then move it into place in one step, so
+    // concurrent builds never see half an entry
+    let partial = entry.with_file_name(format!(".{}.{}", key, std::process::id()));
+    fs::create_dir_all(&partial)
+        .with_context(|| format!("Failed to create {}", partial.display()))?;
+    let filled = (|| -> Result<()> {
+        for output in outputs {
+            let source = project_root.join(output);
+            let cached = partial.join(output);
+            if source.is_dir() {
+                copy_tree(&source, &cached)?;
+            } else if source.exists() {
+                if let Some(parent) = cached.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::copy(&source, &cached)?;
+            }
+        }
+        for (name, contents) in meta {
+            fs::write(partial.join(name), contents)?;
+        }
+        touch(&partial.join(LAST_USED));
+        Ok(())
+    })();
+    if filled.is_err() || fs::rename(&partial, &entry).is_err() {
+        let _ = fs::remove_dir_all(&partial);
+    }
+    filled
+}
+
+fn touch(path: &Path) {
+    let _ = fs::File::create(path).and_then(|file| file.set_modified(SystemTime::now()));
+}
+
+/// Remove all but the `keep` most recently used entries of a kind
+fn prune(kind: &str, keep: usize) {
+    let Some(dir) = dir().map(|dir| dir.join(kind)) else {
+        return;
+    };
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return;
+    };
+    let mut entries: Vec<(SystemTime, PathBuf)> = entries
+        .flatten()
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .filter_map(|entry| {
+            let used = fs::metadata(entry.path().join(LAST_USED))
+                .and_then(|meta| meta.modified())
+                .ok()?;
+            Some((used, entry.path()))
+        })
+        .collect();
+    entries.sort_by_key(|(used, _)| std::cmp::Reverse(*used));
+    for (_, entry) in entries.into_iter().skip(keep) {
+        debug!(entry = %entry.display(), "pruning build cache entry");
+        let _ = fs::remove_dir_all(entry);
+    }
+}
+
+/// Copy a directory tree. Copies get the current time, so a restored build
+/// directory is newer than the sources it was built from.
+fn copy_tree(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to).with_context(|| format!("Failed to create {}", to.display()))?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.path().is_dir() {
+            copy_tree(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)
+                .with_context(|| format!("Failed to copy {}", entry.path().display()))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,162 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::docker::DEFAULT_IMAGE;
+use crate::dry_run;
+
+/// Port used when neither --port nor the user config picks one
+#[cfg(not(windows))]
+pub const DEFAULT_PORT: &str = "/dev/ttyACM0";
+#[cfg(windows)]
+pub const DEFAULT_PORT: &str = "COM3";
+
+/// Serial ports an ESP32-S2 board may show up as
+pub const PORT_PATTERNS: &[&str] = &["/dev/ttyACM*", "/dev/ttyUSB*", "/dev/cu.usbmodem*"];
+
+/// User settings from `~/.config/affogato/config.toml`, written by `affogato setup`
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Config {
+    #[serde(default)]
+    pub docker: DockerConfig,
+    #[serde(default)]
+    pub serial: SerialConfig,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DockerConfig {
+    #[serde(default = "default_image")]
+    pub image: String,
+    /// Parallel build jobs, and CPUs for each container (default: all of them)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jobs: Option<usize>,
+}
+
+impl Default for DockerConfig {
+    fn default() -> Self {
+        Self {
+            image: default_image(),
+            jobs: None,
+        }
+    }
+}
+
+fn default_image() -> String {
+    DEFAULT_IMAGE.to_string()
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct SerialConfig {
+    /// Default serial port: a device path, or "auto" to find the board when needed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<String>,
+}
+
+impl Config {
+    pub fn load() -> Result<Self> {
+        let config_path = Self::config_path()?;
+
+        if config_path.exists() {
+            let content = std::fs::read_to_string(&config_path)?;
+            toml::from_str(&content).with_context(|| format!("Invalid {}", config_path.display()))
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    /// Write the settings back to the config file
+    pub fn save(&self) -> Result<PathBuf> {
+        let config_path = Self::config_path()?;
+        if let Some(dir) = config_path.parent() {
+            dry_run::create_dir_all(dir)?;
+        }
+        dry_run::write(&config_path, toml::to_string_pretty(self)?)?;
+        Ok(config_path)
+    }
+
+    /// Whether the user config exists; first-run setup is offered until it does
+    pub fn exists() -> bool {
+        Self::config_path().is_ok_and(|path| path.exists())
+    }
+
+    pub fn config_path() -> Result<PathBuf> {
+        let config_dir = dirs::config_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("affogato");
+
+        Ok(config_dir.join("config.toml"))
+    }
+
+    /// The serial port to use: `port` if given, else the configured one
+    /// (finding the board for "auto"), else [`DEFAULT_PORT`]
+    pub fn serial_port(&self, port: Option<String>) -> String {
+        match port.or_else(|| self.serial.port.clone()).as_deref() {
+            Some("auto") => detect_port().unwrap_or_else(|| DEFAULT_PORT.to_string()),
+            Some(port) => port.to_string(),
+            // COM port numbers are handed out per device, so none is a safe guess
+            None if cfg!(windows) => detect_port().unwrap_or_else(|| DEFAULT_PORT.to_string()),
+            None => DEFAULT_PORT.to_string(),
+        }
+    }
+}
+
+/// Whether `port` names a Windows COM port (COM3, or \\.\COM12), which
+/// containers can't be given and is used from the host instead
+pub fn is_com_port(port: &str) -> bool {
+    let name = port.strip_prefix(r"\\.\").unwrap_or(port);
+    name.get(..3)
+        .is_some_and(|com| com.eq_ignore_ascii_case("COM"))
+        && name.len() > 3
+        && name[3..].bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Serial ports present on this machine that may be the board
+pub fn available_ports() -> Vec<String> {
+    if cfg!(windows) {
+        return com_ports();
+    }
+    PORT_PATTERNS
+        .iter()
+        .filter_map(|pattern| glob::glob(pattern).ok())
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|path| path.display().to_string())
+        .collect()
+}
+
+/// COM ports from the registry's list of serial devices, as `reg query`
+/// prints it: `    \Device\USBSER000    REG_SZ    COM3`
+fn com_ports() -> Vec<String> {
+    let Ok(output) = std::process::Command::new("reg")
+        .args(["query", r"HKLM\HARDWARE\DEVICEMAP\SERIALCOMM"])
+        .output()
+    else {
+        return Vec::new();
+    };
+    let mut ports: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| line.contains("REG_SZ"))
+        .filter_map(|line| line.split_whitespace().last())
+        .filter(|port| is_com_port(port))
+        .map(str::to_string)
+        .collect();
+    // USB serial adapters usually get the highest numbers
+    ports.sort_by_key(|port| std::cmp::Reverse(port[3..].parse::<u32>().unwrap_or(0)));
+    ports
+}
+
+/// Find the board's serial port: an Espressif device by id on Linux, else the
+/// first port present
+pub fn detect_port() -> Option<String> {
+    let espressif = glob::glob("/dev/serial/by-id/usb-Espressif*")
+        .ok()
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .find_map(|link| link.canonicalize().ok());
+    espressif
+        .as_deref()
+        .map(Path::display)
+        .map(|port| port.to_string())
+        .or_else(|| available_ports().into_iter().next())
+}
@@ -0,0 +1,185 @@
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::dry_run;
+use crate::project::FpgaConfig;
+
+/// The base constraints merged with the selected profiles' fragments, which
+/// nextpnr reads in place of `[fpga] pcf`
+pub const MERGED_PCF: &str = "fpga/build/constraints.pcf";
+
+/// Profiles picked with `--profile` or `AFFOGATO_PROFILE`
+static PROFILES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Select the profiles whose constraint fragments builds apply, overriding
+/// `[fpga] profiles`
+pub fn set_profiles(profiles: Vec<String>) {
+    *PROFILES.lock().unwrap_or_else(|e| e.into_inner()) = profiles;
+}
+
+/// The profiles a build applies: those selected, else `[fpga] profiles`
+pub fn selected(fpga: &FpgaConfig) -> Vec<String> {
+    let profiles = PROFILES.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    if profiles.is_empty() {
+        fpga.profiles.clone()
+    } else {
+        profiles
+    }
+}
+
+/// The pin constraints file nextpnr reads: `[fpga] pcf` as is, or merged with
+/// the fragments of the selected profiles into [`MERGED_PCF`]. Fails if the
+/// files place a signal on two pins, two signals on one pin, or give a clock
+/// two frequencies.
+pub fn resolve(project_root: &Path, fpga: &FpgaConfig) -> Result<String> {
+    let base = fpga
+        .pcf
+        .clone()
+        .unwrap_or_else(|| "fpga/project.pcf".to_string());
+    let profiles = selected(fpga);
+    if profiles.is_empty() {
+        return Ok(base);
+    }
+
+    let mut files = vec![base];
+    for profile in &profiles {
+        let Some(fragments) = fpga.constraints.get(profile) else {
+            let known: Vec<&str> = fpga.constraints.keys().map(String::as_str).collect();
+            bail!(
+                "No [fpga.constraints] for profile {} (known: {})",
+                profile,
+                if known.is_empty() {
+                    "none".to_string()
+                } else {
+                    known.join(", ")
+                }
+            );
+        };
+        files.extend(fragments.iter().cloned());
+    }
+
+    let mut merge = Merge::default();
+    for file in &files {
+        let text = fs::read_to_string(project_root.join(file))
+            .with_context(|| format!("Cannot read constraints {}", file))?;
+        merge.add(file, &text);
+    }
+    if !merge.conflicts.is_empty() {
+        bail!(
+            "Conflicting pin constraints for profile(s) {}:\n  {}",
+            profiles.join(", "),
+            merge.conflicts.join("\n  ")
+        );
+    }
+
+    let merged = format!(
+        "# Generated by affogato for profile(s) {}; edit the sources instead:\n# {}\n{}",
+        profiles.join(", "),
+        files.join(", "),
+        merge.text
+    );
+    if let Some(dir) = project_root.join(MERGED_PCF).parent() {
+        dry_run::create_dir_all(dir)?;
+    }
+    dry_run::write(project_root.join(MERGED_PCF), merged)?;
+    Ok(MERGED_PCF.to_string())
+}
+
+/// Constraint files concatenated, with what each assigned so far
+#[derive(Default)]
+struct Merge {
+    text: String,
+    /// Signal to its pin and where that was set
+    pins: BTreeMap<String, (String, String)>,
+    /// Pin to the signal placed on it
+    signals: BTreeMap<String, String>,
+    /// Clock to its frequency and where that was set
+    frequencies: BTreeMap<String, (String, String)>,
+    conflicts: Vec<String>,
+}
+
+impl Merge {
+    fn add(&mut self, file: &str, text: &str) {
+        self.text.push_str(&format!("\n# {}\n", file));
+        for (number, line) in text.lines().enumerate() {
+            let at = format!("{}:{}", file, number + 1);
+            let content = line.split('#').next().unwrap_or_default();
+            let words: Vec<&str> = content.split_whitespace().collect();
+            let duplicate = match words.first() {
+                Some(&"set_io") => self.set_io(&words[1..], &at),
+                Some(&"set_frequency") => self.set_frequency(&words[1..], &at),
+                _ => false,
+            };
+            // nextpnr rejects a signal constrained twice, even to the same pin
+            if duplicate {
+                self.text
+                    .push_str(&format!("# (also in {}) {}\n", at, line));
+            } else {
+                self.text.push_str(line);
+                self.text.push('\n');
+            }
+        }
+    }
+
+    /// Record `set_io [options] SIGNAL PIN`; true if it repeats an earlier one
+    fn set_io(&mut self, args: &[&str], at: &str) -> bool {
+        let mut positional = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match *arg {
+                "-pullup" | "-pullup_resistor" => {
+                    args.next();
+                }
+                flag if flag.starts_with('-') => {}
+                value => positional.push(value),
+            }
+        }
+        let [signal, pin] = positional[..] else {
+            return false;
+        };
+        if let Some((other_pin, other_at)) = self.pins.get(signal) {
+            if other_pin == pin {
+                return true;
+            }
+            self.conflicts.push(format!(
+                "{} is on pin {} at {} but pin {} at {}",
+                signal, other_pin, other_at, pin, at
+            ));
+            return false;
+        }
+        if let Some(other) = self.signals.get(pin) {
+            self.conflicts.push(format!(
+                "pin {} has both {} and {} ({})",
+                pin, other, signal, at
+            ));
+            return false;
+        }
+        self.pins
+            .insert(signal.to_string(), (pin.to_string(), at.to_string()));
+        self.signals.insert(pin.to_string(), signal.to_string());
+        false
+    }
+
+    /// Record `set_frequency CLOCK MHZ`; true if it repeats an earlier one
+    fn set_frequency(&mut self, args: &[&str], at: &str) -> bool {
+        let [clock, mhz] = args[..] else {
+            return false;
+        };
+        if let Some((other_mhz, other_at)) = self.frequencies.get(clock) {
+            if other_mhz == mhz {
+                return true;
+            }
+            self.conflicts.push(format!(
+                "{} is {} MHz at {} but {} MHz at {}",
+                clock, other_mhz, other_at, mhz, at
+            ));
+            return false;
+        }
+        self.frequencies
+            .insert(clock.to_string(), (mhz.to_string(), at.to_string()));
+        false
+    }
+}
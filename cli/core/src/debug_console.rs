@@ -0,0 +1,509 @@
+use anyhow::Result;
+use std::path::Path;
+
+use crate::build_info::{self, write_if_changed};
+
+/// C header with the firmware's debug console, relative to the project root
+pub const HEADER: &str = "firmware/main/affogato_console.h";
+
+/// Prompt the console REPL prints before each command
+pub const PROMPT: &str = "affogato>";
+
+/// Commands the generated console registers, besides esp_console's `help`
+pub const COMMANDS: &[&str] = &[build_info::QUERY, "reg", "bench", "hwtest"];
+
+/// Start of the line the console answers `reg` commands with
+pub const REG_PREFIX: &str = "affogato-reg:";
+
+/// Start of the lines the console answers `bench spi` with
+pub const BENCH_PREFIX: &str = "affogato-bench:";
+
+/// How long `bench spi` streams data at each clock, in milliseconds
+pub const BENCH_MS: u32 = 500;
+
+/// Start of the lines the console answers `hwtest` with
+pub const HWTEST_PREFIX: &str = "affogato-hwtest:";
+
+/// Register of the hwtest template's design holding [`HWTEST_ID`]
+pub const HWTEST_ID_ADDR: u32 = 0x0;
+
+/// Read/write register of the hwtest design, for SPI data patterns
+pub const HWTEST_SCRATCH_ADDR: u32 = 0x1;
+
+/// Register of the hwtest design whose bits 2:0 light the RGB LED
+pub const HWTEST_LED_ADDR: u32 = 0x2;
+
+/// What the hwtest design's ID register reads
+pub const HWTEST_ID: u32 = 0xAFF0;
+
+/// Largest register address of `spi_bridge`, which has a 15-bit address
+pub const MAX_ADDRESS: u32 = 0x7FFF;
+
+/// Write the debug console header when `[firmware] debug_console` is set
+pub fn write(project_root: &Path, enabled: bool) -> Result<()> {
+    if !enabled || !project_root.join("firmware/main").is_dir() {
+        return Ok(());
+    }
+    write_if_changed(&project_root.join(HEADER), &header())
+}
+
+fn header() -> String {
+    format!(
+        r#"/**
+ * Debug console - generated by `affogato build`; do not edit
+ *
+ * An esp_console REPL answering:
+ *   {query:<24}the build line `affogato version --device` reads
+ *   reg read <addr>         read a 16-bit FPGA register (`affogato reg read`)
+ *   reg write <addr> <val>  write one (`affogato reg write`)
+ *   bench spi <hz>...       stream data through spi_loopback (`affogato bench spi`)
+ *   hwtest [<out>:<in>]...  board self-test (`affogato hwtest`)
+ *
+ * Registers are reached through an spi_bridge instance (see
+ * `affogato regmap build`): SPI mode 0 at up to 3 MHz, added without
+ * SPI_DEVICE_HALFDUPLEX. The bench needs an spi_loopback core on its own
+ * chip select; see affogato_console_enable_bench(). The self-test expects the
+ * hwtest template's design; see affogato_console_enable_hwtest().
+ */
+#pragma once
+
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include "driver/gpio.h"
+#include "driver/spi_master.h"
+#include "esp_console.h"
+#include "esp_heap_caps.h"
+#include "esp_timer.h"
+#include "freertos/FreeRTOS.h"
+#include "freertos/semphr.h"
+#include "freertos/task.h"
+#include "affogato_build.h"
+
+/* From the ice40 component, when it is linked in, to share the bus */
+extern SemaphoreHandle_t master_spi_semaphore __attribute__((weak));
+
+static spi_device_handle_t affogato_console_regs;
+static spi_host_device_t affogato_console_bench_host;
+static int affogato_console_bench_cs = -1;
+
+/* Board self-test hooks, see affogato_console_enable_hwtest() */
+typedef struct {{
+    int creset_gpio;
+    int cdone_gpio;
+    /* Configure the FPGA from reset; CDONE must be high when it returns ESP_OK */
+    esp_err_t (*configure)(void);
+}} affogato_hwtest_config_t;
+
+static affogato_hwtest_config_t affogato_console_hwtest_config;
+static bool affogato_console_hwtest_enabled;
+
+/* Bytes per bench transaction, within the default DMA transfer limit */
+#define AFFOGATO_BENCH_CHUNK 2048
+
+/* One spi_bridge transaction: [write][address:15] then a 16-bit data word */
+static inline esp_err_t affogato_console_transfer(bool write, uint16_t addr, uint16_t *value)
+{{
+    uint8_t tx[4] = {{(write ? 0x80 : 0) | ((addr >> 8) & 0x7F), addr & 0xFF,
+                     write ? *value >> 8 : 0, write ? *value & 0xFF : 0}};
+    uint8_t rx[4] = {{0}};
+    spi_transaction_t trans = {{
+        .length = 32,
+        .tx_buffer = tx,
+        .rx_buffer = rx,
+    }};
+    bool shared = &master_spi_semaphore != NULL && master_spi_semaphore != NULL;
+    if (shared) {{
+        xSemaphoreTake(master_spi_semaphore, portMAX_DELAY);
+    }}
+    esp_err_t ret = spi_device_transmit(affogato_console_regs, &trans);
+    if (shared) {{
+        xSemaphoreGive(master_spi_semaphore);
+    }}
+    if (ret == ESP_OK && !write) {{
+        *value = ((uint16_t)rx[2] << 8) | rx[3];
+    }}
+    return ret;
+}}
+
+static int affogato_console_version(int argc, char **argv)
+{{
+    affogato_build_print();
+    return 0;
+}}
+
+static int affogato_console_reg(int argc, char **argv)
+{{
+    bool write = argc == 4 && strcmp(argv[1], "write") == 0;
+    if (!write && !(argc == 3 && strcmp(argv[1], "read") == 0)) {{
+        printf("usage: reg read <addr> | reg write <addr> <value>\n");
+        return 1;
+    }}
+    unsigned long addr = strtoul(argv[2], NULL, 0);
+    uint16_t value = write ? (uint16_t)strtoul(argv[3], NULL, 0) : 0;
+    esp_err_t ret = ESP_ERR_INVALID_ARG;
+    if (!affogato_console_regs) {{
+        ret = ESP_ERR_INVALID_STATE;
+    }} else if (addr <= 0x{max:X}) {{
+        ret = affogato_console_transfer(write, (uint16_t)addr, &value);
+    }}
+    if (ret == ESP_OK) {{
+        printf("{prefix} addr=0x%04lx value=0x%04x\n", addr, value);
+    }} else {{
+        printf("{prefix} addr=0x%04lx error=%s\n", addr, esp_err_to_name(ret));
+    }}
+    fflush(stdout);
+    return ret == ESP_OK ? 0 : 1;
+}}
+
+/*
+ * Stream xorshift data through the loopback for {bench_ms} ms at each clock and
+ * count the bits that do not come back. Each byte is echoed during the next
+ * one; the first byte at each clock is not checked.
+ */
+static int affogato_console_bench_spi(int argc, char **argv)
+{{
+    if (argc < 3 || strcmp(argv[1], "spi") != 0) {{
+        printf("usage: bench spi <hz> [<hz>...]\n");
+        return 1;
+    }}
+    uint8_t *tx = heap_caps_malloc(AFFOGATO_BENCH_CHUNK, MALLOC_CAP_DMA);
+    uint8_t *rx = heap_caps_malloc(AFFOGATO_BENCH_CHUNK, MALLOC_CAP_DMA);
+    esp_err_t ret = ESP_OK;
+    if (affogato_console_bench_cs < 0) {{
+        ret = ESP_ERR_INVALID_STATE;
+    }} else if (!tx || !rx) {{
+        ret = ESP_ERR_NO_MEM;
+    }}
+    uint32_t state = 0x2545F491;
+    for (int i = 2; ret == ESP_OK && i < argc; i++) {{
+        unsigned long hz = strtoul(argv[i], NULL, 0);
+        spi_device_interface_config_t config = {{
+            .mode = 0,
+            .clock_speed_hz = (int)hz,
+            .spics_io_num = affogato_console_bench_cs,
+            .queue_size = 1,
+        }};
+        spi_device_handle_t device;
+        esp_err_t added = spi_bus_add_device(affogato_console_bench_host, &config, &device);
+        if (added != ESP_OK) {{
+            printf("{bench} hz=%lu error=%s\n", hz, esp_err_to_name(added));
+            fflush(stdout);
+            continue;
+        }}
+        int khz = 0;
+        spi_device_get_actual_freq(device, &khz);
+
+        bool shared = &master_spi_semaphore != NULL && master_spi_semaphore != NULL;
+        if (shared) {{
+            xSemaphoreTake(master_spi_semaphore, portMAX_DELAY);
+        }}
+        uint64_t bytes = 0, bits = 0, errors = 0;
+        int64_t busy = 0;
+        uint8_t last = 0;
+        bool first = true;
+        esp_err_t sent = ESP_OK;
+        int64_t end = esp_timer_get_time() + {bench_ms} * 1000LL;
+        for (size_t j = 0; j < AFFOGATO_BENCH_CHUNK; j++) {{
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            tx[j] = (uint8_t)state;
+        }}
+        while (sent == ESP_OK && esp_timer_get_time() < end) {{
+            spi_transaction_t trans = {{
+                .length = AFFOGATO_BENCH_CHUNK * 8,
+                .tx_buffer = tx,
+                .rx_buffer = rx,
+            }};
+            int64_t start = esp_timer_get_time();
+            sent = spi_device_polling_transmit(device, &trans);
+            busy += esp_timer_get_time() - start;
+            for (size_t j = first ? 1 : 0; j < AFFOGATO_BENCH_CHUNK; j++) {{
+                errors += __builtin_popcount(rx[j] ^ (j ? tx[j - 1] : last));
+                bits += 8;
+            }}
+            bytes += AFFOGATO_BENCH_CHUNK;
+            last = tx[AFFOGATO_BENCH_CHUNK - 1];
+            first = false;
+            for (size_t j = 0; j < AFFOGATO_BENCH_CHUNK; j++) {{
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                tx[j] = (uint8_t)state;
+            }}
+        }}
+        if (shared) {{
+            xSemaphoreGive(master_spi_semaphore);
+        }}
+        spi_bus_remove_device(device);
+
+        if (sent == ESP_OK) {{
+            printf("{bench} hz=%lu actual=%d bytes=%llu bits=%llu errors=%llu us=%lld\n", hz,
+                   khz * 1000, (unsigned long long)bytes, (unsigned long long)bits,
+                   (unsigned long long)errors, (long long)busy);
+        }} else {{
+            printf("{bench} hz=%lu error=%s\n", hz, esp_err_to_name(sent));
+        }}
+        fflush(stdout);
+    }}
+    if (ret != ESP_OK) {{
+        printf("{bench} error=%s\n", esp_err_to_name(ret));
+    }}
+    printf("{bench} done\n");
+    fflush(stdout);
+    free(tx);
+    free(rx);
+    return ret == ESP_OK ? 0 : 1;
+}}
+
+static void affogato_console_hwtest_report(const char *check, const char *result,
+                                          const char *detail)
+{{
+    printf("{hwtest} check=%s result=%s detail=%s\n", check, result, detail);
+    fflush(stdout);
+}}
+
+/* Holding CRESET low must clear CDONE, and configuring must set it again */
+static void affogato_console_hwtest_cdone(void)
+{{
+    const affogato_hwtest_config_t *config = &affogato_console_hwtest_config;
+    if (!affogato_console_hwtest_enabled) {{
+        affogato_console_hwtest_report("cdone", "skip",
+                                       "affogato_console_enable_hwtest() was not called");
+        return;
+    }}
+    if (!gpio_get_level(config->cdone_gpio)) {{
+        affogato_console_hwtest_report("cdone", "fail",
+                                       "CDONE was low before the test; the FPGA is not configured");
+        return;
+    }}
+    gpio_set_level(config->creset_gpio, 0);
+    vTaskDelay(pdMS_TO_TICKS(20));
+    bool cleared = !gpio_get_level(config->cdone_gpio);
+    esp_err_t ret = config->configure();
+    char detail[96];
+    if (!cleared) {{
+        affogato_console_hwtest_report("cdone", "fail", "CDONE stayed high with CRESET low");
+    }} else if (ret != ESP_OK) {{
+        snprintf(detail, sizeof(detail), "configuring after the reset failed: %s",
+                 esp_err_to_name(ret));
+        affogato_console_hwtest_report("cdone", "fail", detail);
+    }} else if (!gpio_get_level(config->cdone_gpio)) {{
+        affogato_console_hwtest_report("cdone", "fail", "CDONE stayed low after configuring");
+    }} else {{
+        affogato_console_hwtest_report("cdone", "pass",
+                                       "CRESET cleared CDONE and configuring set it again");
+    }}
+}}
+
+/* Read the design's ID, then walk ones and zeros through the scratch register */
+static void affogato_console_hwtest_spi(void)
+{{
+    if (!affogato_console_regs) {{
+        affogato_console_hwtest_report("spi", "skip",
+                                       "no register bus was passed to affogato_console_start()");
+        return;
+    }}
+    char detail[96];
+    uint16_t id = 0;
+    esp_err_t ret = affogato_console_transfer(false, 0x{id_addr:X}, &id);
+    if (ret != ESP_OK || id != 0x{id:04X}) {{
+        if (ret != ESP_OK) {{
+            snprintf(detail, sizeof(detail), "reading the ID failed: %s", esp_err_to_name(ret));
+        }} else {{
+            snprintf(detail, sizeof(detail), "ID read 0x%04x, expected 0x{id:04x}", id);
+        }}
+        affogato_console_hwtest_report("spi", "fail", detail);
+        return;
+    }}
+    for (int i = 0; i < 34; i++) {{
+        uint16_t pattern = i < 16   ? (uint16_t)(1u << i)
+                           : i < 32 ? (uint16_t)~(1u << (i - 16))
+                           : i == 32 ? 0xA5A5
+                                     : 0x5A5A;
+        uint16_t written = pattern;
+        uint16_t readback = 0;
+        ret = affogato_console_transfer(true, 0x{scratch_addr:X}, &written);
+        if (ret == ESP_OK) {{
+            ret = affogato_console_transfer(false, 0x{scratch_addr:X}, &readback);
+        }}
+        if (ret != ESP_OK || readback != pattern) {{
+            if (ret != ESP_OK) {{
+                snprintf(detail, sizeof(detail), "scratch access failed: %s", esp_err_to_name(ret));
+            }} else {{
+                snprintf(detail, sizeof(detail), "wrote 0x%04x to scratch, read 0x%04x", pattern,
+                         readback);
+            }}
+            affogato_console_hwtest_report("spi", "fail", detail);
+            return;
+        }}
+    }}
+    affogato_console_hwtest_report("spi", "pass", "ID read and 34 scratch patterns read back");
+}}
+
+/* Show red, green then blue; only the operator can say whether they lit */
+static void affogato_console_hwtest_rgb(void)
+{{
+    if (!affogato_console_regs) {{
+        affogato_console_hwtest_report("rgb", "skip",
+                                       "no register bus was passed to affogato_console_start()");
+        return;
+    }}
+    esp_err_t ret = ESP_OK;
+    for (int i = 0; i < 3 && ret == ESP_OK; i++) {{
+        uint16_t color = 1u << i;
+        ret = affogato_console_transfer(true, 0x{led_addr:X}, &color);
+        vTaskDelay(pdMS_TO_TICKS(700));
+    }}
+    uint16_t off = 0;
+    affogato_console_transfer(true, 0x{led_addr:X}, &off);
+    if (ret != ESP_OK) {{
+        char detail[96];
+        snprintf(detail, sizeof(detail), "writing the LED register failed: %s",
+                 esp_err_to_name(ret));
+        affogato_console_hwtest_report("rgb", "fail", detail);
+    }} else {{
+        affogato_console_hwtest_report("rgb", "manual", "showed red, green, then blue");
+    }}
+}}
+
+/* Drive <out> high and low against <in>, pulled the opposite way each time */
+static void affogato_console_hwtest_gpio(const char *pair)
+{{
+    char check[32];
+    snprintf(check, sizeof(check), "gpio:%s", pair);
+    char *end;
+    long out = strtol(pair, &end, 10);
+    long in = *end == ':' ? strtol(end + 1, &end, 10) : -1;
+    if (in < 0 || *end || !GPIO_IS_VALID_OUTPUT_GPIO(out) || !GPIO_IS_VALID_GPIO(in)) {{
+        affogato_console_hwtest_report(check, "fail", "not a valid <out>:<in> GPIO pair");
+        return;
+    }}
+    gpio_reset_pin(out);
+    gpio_reset_pin(in);
+    gpio_set_direction(out, GPIO_MODE_OUTPUT);
+    gpio_set_direction(in, GPIO_MODE_INPUT);
+    gpio_set_pull_mode(in, GPIO_PULLDOWN_ONLY);
+    gpio_set_level(out, 1);
+    vTaskDelay(1);
+    int high = gpio_get_level(in);
+    gpio_set_pull_mode(in, GPIO_PULLUP_ONLY);
+    gpio_set_level(out, 0);
+    vTaskDelay(1);
+    int low = gpio_get_level(in);
+    gpio_reset_pin(out);
+    gpio_reset_pin(in);
+    if (high && !low) {{
+        affogato_console_hwtest_report(check, "pass", "followed high and low");
+    }} else if (!high && !low) {{
+        affogato_console_hwtest_report(check, "fail", "read low while driven high");
+    }} else if (high && low) {{
+        affogato_console_hwtest_report(check, "fail", "read high while driven low");
+    }} else {{
+        affogato_console_hwtest_report(check, "fail", "read the opposite of what was driven");
+    }}
+}}
+
+static int affogato_console_hwtest(int argc, char **argv)
+{{
+    affogato_console_hwtest_cdone();
+    affogato_console_hwtest_spi();
+    affogato_console_hwtest_rgb();
+    for (int i = 1; i < argc; i++) {{
+        affogato_console_hwtest_gpio(argv[i]);
+    }}
+    printf("{hwtest} done\n");
+    fflush(stdout);
+    return 0;
+}}
+
+/*
+ * Let `hwtest` reset and reconfigure the FPGA through the given pins and
+ * loader. Call before or after affogato_console_start().
+ */
+static inline void affogato_console_enable_hwtest(const affogato_hwtest_config_t *config)
+{{
+    affogato_console_hwtest_config = *config;
+    affogato_console_hwtest_enabled = true;
+}}
+
+/*
+ * Let `bench spi` use an spi_loopback core on an initialized SPI bus, selected
+ * by cs_gpio. Call before or after affogato_console_start().
+ */
+static inline void affogato_console_enable_bench(spi_host_device_t host, int cs_gpio)
+{{
+    affogato_console_bench_host = host;
+    affogato_console_bench_cs = cs_gpio;
+}}
+
+/*
+ * Start the console REPL. Pass the SPI device of the FPGA's register bus, or
+ * NULL until there is one (`reg` then answers ESP_ERR_INVALID_STATE).
+ */
+static inline esp_err_t affogato_console_start(spi_device_handle_t regs)
+{{
+    affogato_console_regs = regs;
+    esp_console_repl_t *repl = NULL;
+    esp_console_repl_config_t config = ESP_CONSOLE_REPL_CONFIG_DEFAULT();
+    config.prompt = "{prompt}";
+#if CONFIG_ESP_CONSOLE_USB_CDC
+    esp_console_dev_usb_cdc_config_t device = ESP_CONSOLE_DEV_CDC_CONFIG_DEFAULT();
+    esp_err_t ret = esp_console_new_repl_usb_cdc(&device, &config, &repl);
+#else
+    esp_console_dev_uart_config_t device = ESP_CONSOLE_DEV_UART_CONFIG_DEFAULT();
+    esp_err_t ret = esp_console_new_repl_uart(&device, &config, &repl);
+#endif
+    if (ret != ESP_OK) {{
+        return ret;
+    }}
+    const esp_console_cmd_t commands[] = {{
+        {{
+            .command = "{query}",
+            .help = "Print the firmware and bitstream build",
+            .func = &affogato_console_version,
+        }},
+        {{
+            .command = "reg",
+            .help = "Read or write an FPGA register",
+            .hint = "read <addr> | write <addr> <value>",
+            .func = &affogato_console_reg,
+        }},
+        {{
+            .command = "bench",
+            .help = "Measure the SPI link through an spi_loopback core",
+            .hint = "spi <hz> [<hz>...]",
+            .func = &affogato_console_bench_spi,
+        }},
+        {{
+            .command = "hwtest",
+            .help = "Run the board self-test",
+            .hint = "[<out>:<in>...]",
+            .func = &affogato_console_hwtest,
+        }},
+    }};
+    for (size_t i = 0; i < sizeof(commands) / sizeof(commands[0]); i++) {{
+        ret = esp_console_cmd_register(&commands[i]);
+        if (ret != ESP_OK) {{
+            return ret;
+        }}
+    }}
+    return esp_console_start_repl(repl);
+}}
+"#,
+        query = build_info::QUERY,
+        prefix = REG_PREFIX,
+        prompt = PROMPT,
+        max = MAX_ADDRESS,
+        bench = BENCH_PREFIX,
+        bench_ms = BENCH_MS,
+        hwtest = HWTEST_PREFIX,
+        id_addr = HWTEST_ID_ADDR,
+        id = HWTEST_ID,
+        scratch_addr = HWTEST_SCRATCH_ADDR,
+        led_addr = HWTEST_LED_ADDR,
+    )
+}
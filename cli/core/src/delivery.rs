@@ -0,0 +1,464 @@
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::build_info::write_if_changed;
+use crate::dry_run;
+use crate::project::BitstreamDelivery;
+
+/// CMake the firmware's top-level CMakeLists.txt includes after `project()`,
+/// relative to the project root
+pub const CMAKE_FILE: &str = "firmware/affogato_bitstream.cmake";
+
+/// C header giving the firmware the bitstream, relative to the project root
+pub const HEADER: &str = "firmware/main/affogato_bitstream.h";
+
+/// Partition table written when the bitstream needs a partition
+pub const PARTITION_TABLE: &str = "firmware/partitions.csv";
+
+/// Label of the partition holding the bitstream or its filesystem
+pub const PARTITION_LABEL: &str = "fpga";
+
+/// Where the bitstream is staged for the firmware build to flash
+const STAGING_DIR: &str = "firmware/build/bitstream";
+
+/// Starts a raw partition's image: "AFBS", then the bitstream's length, both
+/// little-endian 32-bit words
+const PARTITION_MAGIC: u32 = 0x5342_4641;
+const PARTITION_HEADER_LEN: usize = 8;
+
+/// Size of the generated table's `fpga` partition; the largest iCE40
+/// bitstreams are about 130 KiB
+const RAW_PARTITION_SIZE: usize = 0x40000;
+const FS_PARTITION_SIZE: usize = 0x80000;
+
+/// First line of a partition table affogato wrote and may rewrite
+const GENERATED_MARK: &str = "# Generated by `affogato build` from [firmware] bitstream";
+
+/// Component manager dependency providing littlefs_create_partition_image()
+const LITTLEFS_COMPONENT: &str = "joltwallet/littlefs";
+const COMPONENT_MANIFEST: &str = "firmware/main/idf_component.yml";
+
+/// Write the CMake plumbing and header for `[firmware] bitstream`, and stage
+/// the bitstream where the firmware build flashes it from
+pub fn write(project_root: &Path, delivery: BitstreamDelivery) -> Result<()> {
+    if !project_root.join("firmware/main").is_dir() {
+        return Ok(());
+    }
+    let cmake_lists =
+        fs::read_to_string(project_root.join("firmware/CMakeLists.txt")).unwrap_or_default();
+    if !cmake_lists.contains("affogato_bitstream.cmake") {
+        // Projects from before the setting embed the bitstream themselves
+        if delivery != BitstreamDelivery::Embed {
+            bail!(
+                "firmware/CMakeLists.txt embeds the bitstream itself; to use [firmware] \
+                 bitstream = \"{}\", replace its target_add_binary_data line with \
+                 include(affogato_bitstream.cmake)",
+                name(delivery)
+            );
+        }
+    } else {
+        write_if_changed(&project_root.join(CMAKE_FILE), &cmake(delivery))?;
+    }
+    write_if_changed(&project_root.join(HEADER), &header(delivery))?;
+
+    if delivery == BitstreamDelivery::Embed {
+        return Ok(());
+    }
+    write_partition_table(project_root, delivery)?;
+    if delivery == BitstreamDelivery::Littlefs {
+        require_littlefs(project_root)?;
+    }
+
+    let Ok(bitstream) = fs::read(project_root.join("fpga/top.bin")) else {
+        return Ok(());
+    };
+    let (path, image) = match delivery {
+        BitstreamDelivery::Partition => {
+            if PARTITION_HEADER_LEN + bitstream.len() > RAW_PARTITION_SIZE
+                && is_generated(project_root)
+            {
+                bail!(
+                    "The bitstream ({} bytes) does not fit the {} KiB `{}` partition",
+                    bitstream.len(),
+                    RAW_PARTITION_SIZE / 1024,
+                    PARTITION_LABEL
+                );
+            }
+            let mut image = PARTITION_MAGIC.to_le_bytes().to_vec();
+            image.extend((bitstream.len() as u32).to_le_bytes());
+            image.extend(bitstream);
+            (format!("{}/partition.bin", STAGING_DIR), image)
+        }
+        _ => (format!("{}/fs/top.bin", STAGING_DIR), bitstream),
+    };
+    let path = project_root.join(path);
+    if fs::read(&path).is_ok_and(|old| old == image) {
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        dry_run::create_dir_all(dir)?;
+    }
+    dry_run::write(&path, image).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// idf.py targets that flash the application without the bootloader and
+/// partition table, plus the `fpga` partition when the bitstream lives there
+pub fn app_flash_targets(delivery: BitstreamDelivery) -> String {
+    match delivery {
+        BitstreamDelivery::Embed => "app-flash".to_string(),
+        _ => format!("app-flash {}-flash", PARTITION_LABEL),
+    }
+}
+
+/// The setting's value in affogato.toml
+fn name(delivery: BitstreamDelivery) -> &'static str {
+    match delivery {
+        BitstreamDelivery::Embed => "embed",
+        BitstreamDelivery::Spiffs => "spiffs",
+        BitstreamDelivery::Littlefs => "littlefs",
+        BitstreamDelivery::Partition => "partition",
+    }
+}
+
+fn cmake(delivery: BitstreamDelivery) -> String {
+    let step = match delivery {
+        BitstreamDelivery::Embed => {
+            "target_add_binary_data(${CMAKE_PROJECT_NAME}.elf \"../fpga/top.bin\" BINARY)".to_string()
+        }
+        BitstreamDelivery::Spiffs => format!(
+            "spiffs_create_partition_image({} \"${{CMAKE_BINARY_DIR}}/bitstream/fs\" FLASH_IN_PROJECT)",
+            PARTITION_LABEL
+        ),
+        BitstreamDelivery::Littlefs => format!(
+            "littlefs_create_partition_image({} \"${{CMAKE_BINARY_DIR}}/bitstream/fs\" FLASH_IN_PROJECT)",
+            PARTITION_LABEL
+        ),
+        // spiffs and littlefs images get their own `fpga-flash` target; give
+        // the raw partition one too
+        BitstreamDelivery::Partition => format!(
+            r#"idf_component_get_property(main_args esptool_py FLASH_ARGS)
+idf_component_get_property(sub_args esptool_py FLASH_SUB_ARGS)
+esptool_py_flash_target({label}-flash "${{main_args}}" "${{sub_args}}")
+esptool_py_flash_to_partition({label}-flash {label} "${{CMAKE_BINARY_DIR}}/bitstream/partition.bin")
+esptool_py_flash_to_partition(flash {label} "${{CMAKE_BINARY_DIR}}/bitstream/partition.bin")"#,
+            label = PARTITION_LABEL
+        ),
+    };
+    format!(
+        "# Generated by `affogato build` from [firmware] bitstream = \"{}\"; do not edit\n{}\n",
+        name(delivery),
+        step
+    )
+}
+
+/// Write the partition table with an `fpga` partition, or check that a
+/// project's own table has one
+fn write_partition_table(project_root: &Path, delivery: BitstreamDelivery) -> Result<()> {
+    let path = project_root.join(PARTITION_TABLE);
+    if path.exists() && !is_generated(project_root) {
+        let table = fs::read_to_string(&path)?;
+        let has_partition = table
+            .lines()
+            .any(|line| line.split(',').next().map(str::trim) == Some(PARTITION_LABEL));
+        if !has_partition {
+            bail!(
+                "{} has no `{}` partition for [firmware] bitstream = \"{}\"; add one, e.g.\n  {}",
+                PARTITION_TABLE,
+                PARTITION_LABEL,
+                name(delivery),
+                partition_line(delivery)
+            );
+        }
+        return Ok(());
+    }
+    let table = format!(
+        "{} = \"{}\"\n\
+         # Name,   Type, SubType, Offset,  Size, Flags\n\
+         nvs,      data, nvs,     0x9000,  0x6000,\n\
+         phy_init, data, phy,     0xf000,  0x1000,\n\
+         factory,  app,  factory, 0x10000, 1M,\n\
+         {}\n",
+        GENERATED_MARK,
+        name(delivery),
+        partition_line(delivery)
+    );
+    write_if_changed(&path, &table)
+}
+
+/// Whether the partition table is one affogato wrote
+fn is_generated(project_root: &Path) -> bool {
+    fs::read_to_string(project_root.join(PARTITION_TABLE))
+        .map_or(true, |table| table.starts_with(GENERATED_MARK))
+}
+
+/// The table line for the `fpga` partition; filesystems use the spiffs subtype,
+/// as the littlefs component expects, and raw bitstreams a custom one
+fn partition_line(delivery: BitstreamDelivery) -> String {
+    let (subtype, size) = match delivery {
+        BitstreamDelivery::Partition => ("0x40", RAW_PARTITION_SIZE),
+        _ => ("spiffs", FS_PARTITION_SIZE),
+    };
+    format!(
+        "{:<10}data, {:<9}0x110000, {:#x},",
+        format!("{},", PARTITION_LABEL),
+        format!("{},", subtype),
+        size
+    )
+}
+
+/// Add the littlefs component to the main component's manifest, or check that
+/// an existing manifest has it
+fn require_littlefs(project_root: &Path) -> Result<()> {
+    let path = project_root.join(COMPONENT_MANIFEST);
+    match fs::read_to_string(&path) {
+        Ok(manifest) if manifest.contains(LITTLEFS_COMPONENT) => Ok(()),
+        Ok(_) => bail!(
+            "[firmware] bitstream = \"littlefs\" needs the {} component; add it to the \
+             dependencies in {}:\n  {}: \"^1.14\"",
+            LITTLEFS_COMPONENT,
+            COMPONENT_MANIFEST,
+            LITTLEFS_COMPONENT
+        ),
+        Err(_) => write_if_changed(
+            &path,
+            &format!(
+                "## Added by `affogato build` for [firmware] bitstream = \"littlefs\"\n\
+                 dependencies:\n  {}: \"^1.14\"\n",
+                LITTLEFS_COMPONENT
+            ),
+        ),
+    }
+}
+
+fn header(delivery: BitstreamDelivery) -> String {
+    let (includes, source) = match delivery {
+        BitstreamDelivery::Embed => (
+            "",
+            r#"/* From target_add_binary_data in affogato_bitstream.cmake */
+extern const uint8_t _binary_top_bin_start[];
+extern const uint8_t _binary_top_bin_end[];
+
+typedef struct {
+    size_t size;
+} affogato_bitstream_t;
+
+static inline esp_err_t affogato_bitstream_open(affogato_bitstream_t *bitstream)
+{
+    bitstream->size = _binary_top_bin_end - _binary_top_bin_start;
+    return ESP_OK;
+}
+
+static inline esp_err_t affogato_bitstream_read(affogato_bitstream_t *bitstream, size_t offset,
+                                                void *buffer, size_t len)
+{
+    if (offset + len > bitstream->size) {
+        return ESP_ERR_INVALID_SIZE;
+    }
+    memcpy(buffer, _binary_top_bin_start + offset, len);
+    return ESP_OK;
+}
+
+static inline void affogato_bitstream_close(affogato_bitstream_t *bitstream)
+{
+    (void)bitstream;
+}
+"#
+            .to_string(),
+        ),
+        BitstreamDelivery::Spiffs | BitstreamDelivery::Littlefs => {
+            let (include, mount) = if delivery == BitstreamDelivery::Spiffs {
+                (
+                    "#include \"esp_spiffs.h\"\n",
+                    format!(
+                        r#"    if (!esp_spiffs_mounted("{label}")) {{
+        esp_vfs_spiffs_conf_t conf = {{
+            .base_path = "/{label}",
+            .partition_label = "{label}",
+            .max_files = 1,
+        }};
+        esp_err_t ret = esp_vfs_spiffs_register(&conf);"#,
+                        label = PARTITION_LABEL
+                    ),
+                )
+            } else {
+                (
+                    "#include \"esp_littlefs.h\"\n",
+                    format!(
+                        r#"    if (!esp_littlefs_mounted("{label}")) {{
+        esp_vfs_littlefs_conf_t conf = {{
+            .base_path = "/{label}",
+            .partition_label = "{label}",
+        }};
+        esp_err_t ret = esp_vfs_littlefs_register(&conf);"#,
+                        label = PARTITION_LABEL
+                    ),
+                )
+            };
+            (
+                include,
+                format!(
+                    r#"#define AFFOGATO_BITSTREAM_PATH "/{label}/top.bin"
+
+typedef struct {{
+    size_t size;
+    FILE *file;
+}} affogato_bitstream_t;
+
+/* Mounts the `{label}` partition the first time */
+static inline esp_err_t affogato_bitstream_open(affogato_bitstream_t *bitstream)
+{{
+{mount}
+        if (ret != ESP_OK) {{
+            return ret;
+        }}
+    }}
+    struct stat st;
+    if (stat(AFFOGATO_BITSTREAM_PATH, &st) != 0) {{
+        return ESP_ERR_NOT_FOUND;
+    }}
+    bitstream->file = fopen(AFFOGATO_BITSTREAM_PATH, "rb");
+    if (bitstream->file == NULL) {{
+        return ESP_FAIL;
+    }}
+    bitstream->size = st.st_size;
+    return ESP_OK;
+}}
+
+static inline esp_err_t affogato_bitstream_read(affogato_bitstream_t *bitstream, size_t offset,
+                                                void *buffer, size_t len)
+{{
+    if (offset + len > bitstream->size) {{
+        return ESP_ERR_INVALID_SIZE;
+    }}
+    if (fseek(bitstream->file, offset, SEEK_SET) != 0 ||
+        fread(buffer, 1, len, bitstream->file) != len) {{
+        return ESP_FAIL;
+    }}
+    return ESP_OK;
+}}
+
+static inline void affogato_bitstream_close(affogato_bitstream_t *bitstream)
+{{
+    fclose(bitstream->file);
+    bitstream->file = NULL;
+}}
+"#,
+                    label = PARTITION_LABEL,
+                ),
+            )
+        }
+        BitstreamDelivery::Partition => (
+            "#include \"esp_partition.h\"\n",
+            format!(
+                r#"/* The partition starts with a header: the magic "AFBS", then the
+ * bitstream's length, both little-endian 32-bit words */
+#define AFFOGATO_BITSTREAM_MAGIC      0x{magic:08X}u
+#define AFFOGATO_BITSTREAM_HEADER_LEN {header_len}
+
+typedef struct {{
+    size_t size;
+    const esp_partition_t *partition;
+}} affogato_bitstream_t;
+
+static inline esp_err_t affogato_bitstream_open(affogato_bitstream_t *bitstream)
+{{
+    bitstream->partition = esp_partition_find_first(ESP_PARTITION_TYPE_DATA,
+                                                    ESP_PARTITION_SUBTYPE_ANY, "{label}");
+    if (bitstream->partition == NULL) {{
+        return ESP_ERR_NOT_FOUND;
+    }}
+    uint32_t header[2];
+    esp_err_t ret = esp_partition_read(bitstream->partition, 0, header, sizeof(header));
+    if (ret != ESP_OK) {{
+        return ret;
+    }}
+    if (header[0] != AFFOGATO_BITSTREAM_MAGIC ||
+        header[1] > bitstream->partition->size - AFFOGATO_BITSTREAM_HEADER_LEN) {{
+        return ESP_ERR_INVALID_STATE;
+    }}
+    bitstream->size = header[1];
+    return ESP_OK;
+}}
+
+static inline esp_err_t affogato_bitstream_read(affogato_bitstream_t *bitstream, size_t offset,
+                                                void *buffer, size_t len)
+{{
+    if (offset + len > bitstream->size) {{
+        return ESP_ERR_INVALID_SIZE;
+    }}
+    return esp_partition_read(bitstream->partition, AFFOGATO_BITSTREAM_HEADER_LEN + offset,
+                              buffer, len);
+}}
+
+static inline void affogato_bitstream_close(affogato_bitstream_t *bitstream)
+{{
+    bitstream->partition = NULL;
+}}
+"#,
+                magic = PARTITION_MAGIC,
+                header_len = PARTITION_HEADER_LEN,
+                label = PARTITION_LABEL,
+            ),
+        ),
+    };
+    let includes = match delivery {
+        BitstreamDelivery::Spiffs | BitstreamDelivery::Littlefs => {
+            format!("#include <stdio.h>\n#include <sys/stat.h>\n{}", includes)
+        }
+        _ => includes.to_string(),
+    };
+    format!(
+        r#"/**
+ * FPGA bitstream - generated by `affogato build` from
+ * [firmware] bitstream = "{name}"; do not edit
+ *
+ * The same calls read the bitstream wherever the build put it:
+ *   affogato_bitstream_t bitstream;
+ *   affogato_bitstream_open(&bitstream);     sets bitstream.size
+ *   affogato_bitstream_read(&bitstream, offset, buffer, len);
+ *   affogato_bitstream_close(&bitstream);
+ */
+#pragma once
+
+#include <stddef.h>
+#include <stdint.h>
+#include <string.h>
+{includes}#include "esp_err.h"
+
+{source}"#,
+        name = name(delivery),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn app_flash_targets_include_the_bitstream_partition() {
+        assert_eq!(app_flash_targets(BitstreamDelivery::Embed), "app-flash");
+        for delivery in [
+            BitstreamDelivery::Spiffs,
+            BitstreamDelivery::Littlefs,
+            BitstreamDelivery::Partition,
+        ] {
+            assert_eq!(app_flash_targets(delivery), "app-flash fpga-flash");
+        }
+    }
+
+    #[test]
+    fn every_partition_delivery_defines_the_flash_target() {
+        for delivery in [
+            BitstreamDelivery::Spiffs,
+            BitstreamDelivery::Littlefs,
+            BitstreamDelivery::Partition,
+        ] {
+            let cmake = cmake(delivery);
+            // *_create_partition_image() defines fpga-flash itself
+            let defines = cmake.contains("_create_partition_image(fpga ")
+                || cmake.contains("esptool_py_flash_target(fpga-flash ");
+            assert!(defines, "{:?}: {}", delivery, cmake);
+        }
+    }
+}
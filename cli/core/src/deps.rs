@@ -0,0 +1,289 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use crate::dry_run;
+use crate::project::{HdlDep, Project};
+
+/// Where dependencies are vendored, relative to the project root
+pub const VENDOR_DIR: &str = "fpga/vendor";
+
+/// Lockfile pinning each dependency to a commit
+pub const LOCKFILE: &str = "affogato.lock";
+
+/// Git clones that vendored files are copied from
+pub const CACHE_DIR: &str = ".affogato/deps";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Lockfile {
+    #[serde(default)]
+    dep: Vec<LockedDep>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LockedDep {
+    name: String,
+    git: String,
+    #[serde(default)]
+    rev: Option<String>,
+    commit: String,
+}
+
+impl Lockfile {
+    fn load(project_root: &Path) -> Result<Self> {
+        let path = project_root.join(LOCKFILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&path)?;
+        toml::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    fn save(&self, project_root: &Path) -> Result<()> {
+        let content = format!(
+            "# Generated by `affogato deps`. Commit this file.\n\n{}",
+            toml::to_string(self)?
+        );
+        dry_run::write(project_root.join(LOCKFILE), content)?;
+        Ok(())
+    }
+
+    /// The locked commit, if the lock entry still matches the declaration
+    fn commit_for(&self, name: &str, dep: &HdlDep) -> Option<&str> {
+        self.dep
+            .iter()
+            .find(|l| l.name == name && l.git == dep.git && l.rev == dep.rev)
+            .map(|l| l.commit.as_str())
+    }
+}
+
+/// Vendor every `[fpga.deps]` entry into fpga/vendor at its locked commit.
+/// Dependencies named in `update` (or all, if it is empty and `update_all`)
+/// are re-resolved from their `rev` instead.
+pub fn fetch(project: &Project, update: &[String], update_all: bool) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    let deps = project
+        .config
+        .as_ref()
+        .map(|c| c.fpga.deps.clone())
+        .unwrap_or_default();
+
+    for name in update {
+        if !deps.contains_key(name) {
+            bail!("Unknown dependency: {} (not in [fpga.deps])", name);
+        }
+    }
+
+    let old_lock = Lockfile::load(project_root)?;
+    let mut lock = Lockfile::default();
+
+    if deps.is_empty() {
+        outln!("{}", "No dependencies in [fpga.deps]".yellow());
+    }
+
+    for (name, dep) in &deps {
+        let locked = if update_all || update.contains(name) {
+            None
+        } else {
+            old_lock.commit_for(name, dep)
+        };
+
+        outln!("{}", format!("==> Fetching {}", name).blue().bold());
+        if dry_run::is_enabled() {
+            dry_run::note(&format!("fetch {} into {}/{}", dep.git, VENDOR_DIR, name));
+            continue;
+        }
+        let cache = project_root.join(CACHE_DIR).join(name);
+        let commit = checkout(&cache, dep, locked)?;
+
+        let vendor = project_root.join(VENDOR_DIR).join(name);
+        if vendor.exists() {
+            dry_run::remove_dir_all(&vendor)?;
+        }
+        let source = match &dep.path {
+            Some(path) => cache.join(path),
+            None => cache.clone(),
+        };
+        if !source.is_dir() {
+            bail!(
+                "Path {} not found in {}",
+                dep.path.as_deref().unwrap_or("."),
+                dep.git
+            );
+        }
+        if dep.files.is_empty() {
+            copy_tree(&source, &vendor)?;
+        } else {
+            copy_files(&source, &vendor, &dep.files)
+                .with_context(|| format!("Failed to vendor {}", name))?;
+        }
+
+        let pinned = if locked.is_some() {
+            "locked"
+        } else {
+            "resolved"
+        };
+        let short = &commit[..commit.len().min(12)];
+        outln!(
+            "  {} {}/{} {}",
+            "Vendored".green(),
+            VENDOR_DIR,
+            name,
+            format!("({} {})", pinned, short).dimmed()
+        );
+
+        lock.dep.push(LockedDep {
+            name: name.clone(),
+            git: dep.git.clone(),
+            rev: dep.rev.clone(),
+            commit,
+        });
+    }
+
+    // Remove vendored copies of dependencies that are no longer declared
+    for old in &old_lock.dep {
+        if !deps.contains_key(&old.name) {
+            let vendor = project_root.join(VENDOR_DIR).join(&old.name);
+            if vendor.exists() {
+                dry_run::remove_dir_all(&vendor)?;
+                outln!("  {} {}/{}", "Removed".yellow(), VENDOR_DIR, old.name);
+            }
+        }
+    }
+
+    lock.save(project_root)?;
+    Ok(())
+}
+
+/// Fetch dependencies if any declared one hasn't been vendored yet
+pub fn ensure(project: &Project) -> Result<()> {
+    let (Some(project_root), Some(config)) = (&project.root, &project.config) else {
+        return Ok(());
+    };
+    let missing = config
+        .fpga
+        .deps
+        .keys()
+        .any(|name| !project_root.join(VENDOR_DIR).join(name).exists());
+    if missing {
+        fetch(project, &[], false)?;
+    }
+    Ok(())
+}
+
+/// Check out the locked commit, or resolve `rev`, in the cached clone
+fn checkout(cache: &Path, dep: &HdlDep, locked: Option<&str>) -> Result<String> {
+    if !cache.join(".git").exists() {
+        if let Some(parent) = cache.parent() {
+            dry_run::create_dir_all(parent)?;
+        }
+        git(
+            None,
+            &["clone", "--quiet", &dep.git, &cache.display().to_string()],
+        )?;
+    }
+
+    let commit = match locked {
+        Some(commit) => {
+            if git(
+                Some(cache),
+                &["cat-file", "-e", &format!("{}^{{commit}}", commit)],
+            )
+            .is_err()
+            {
+                git(Some(cache), &["fetch", "--quiet", "--tags", "origin"])?;
+            }
+            commit.to_string()
+        }
+        None => {
+            git(Some(cache), &["fetch", "--quiet", "--tags", "origin"])?;
+            resolve(cache, dep.rev.as_deref())?
+        }
+    };
+
+    git(Some(cache), &["checkout", "--quiet", "--detach", &commit])?;
+    Ok(commit)
+}
+
+/// Resolve a branch, tag or commit to a commit hash (default branch if unset)
+fn resolve(cache: &Path, rev: Option<&str>) -> Result<String> {
+    let candidates = match rev {
+        // Branches only exist as remote-tracking refs in the clone
+        Some(rev) => vec![format!("origin/{}", rev), rev.to_string()],
+        None => vec!["origin/HEAD".to_string()],
+    };
+    for candidate in &candidates {
+        let spec = format!("{}^{{commit}}", candidate);
+        if let Ok(commit) = git(Some(cache), &["rev-parse", "--verify", "--quiet", &spec]) {
+            return Ok(commit);
+        }
+    }
+    bail!("Revision {} not found", rev.unwrap_or("HEAD"))
+}
+
+fn git(dir: Option<&Path>, args: &[&str]) -> Result<String> {
+    let mut command = Command::new("git");
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    let output = command.args(args).output().context("Failed to run git")?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Copy the files matching `patterns` (relative to `from`), keeping their layout
+fn copy_files(from: &Path, to: &Path, patterns: &[String]) -> Result<()> {
+    for pattern in patterns {
+        let full = from.join(pattern);
+        let matches = glob::glob(&full.to_string_lossy())
+            .with_context(|| format!("Invalid file pattern: {}", pattern))?;
+        let mut found = false;
+        for path in matches {
+            let path = path?;
+            if !path.is_file() {
+                continue;
+            }
+            let target = to.join(path.strip_prefix(from)?);
+            if let Some(parent) = target.parent() {
+                dry_run::create_dir_all(parent)?;
+            }
+            dry_run::copy(&path, &target)?;
+            found = true;
+        }
+        if !found {
+            bail!("No files match {}", pattern);
+        }
+    }
+    Ok(())
+}
+
+/// Copy a checkout without its .git directory
+fn copy_tree(from: &Path, to: &Path) -> Result<()> {
+    dry_run::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        let path = entry.path();
+        let target = to.join(entry.file_name());
+        if path.is_dir() {
+            copy_tree(&path, &target)?;
+        } else {
+            dry_run::copy(&path, &target)?;
+        }
+    }
+    Ok(())
+}
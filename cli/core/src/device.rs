@@ -0,0 +1,116 @@
+use anyhow::{bail, Result};
+use std::time::Duration;
+
+use crate::docker::Docker;
+use crate::dry_run;
+use crate::error::Failure;
+use crate::project::Project;
+
+/// How long the firmware has to answer a query
+pub const REPLY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Sends a command line to the board and prints the first reply line with the
+/// given prefix, or with a final argument every such line until one starting
+/// with it. The timeout restarts with each reply. pyserial comes with esptool
+/// in the image.
+const QUERY_SCRIPT: &str = r#"
+import serial, sys, time
+port, command, prefix, timeout = sys.argv[1], sys.argv[2], sys.argv[3], float(sys.argv[4])
+done = sys.argv[5] if len(sys.argv) > 5 else None
+s = serial.Serial(port, 115200, timeout=0.2)
+s.reset_input_buffer()
+s.write((command + "\n").encode())
+end = time.time() + timeout
+while time.time() < end:
+    line = s.readline().decode(errors="replace").strip()
+    if line.startswith(prefix):
+        print(line, flush=True)
+        if done is None or line.startswith(done):
+            sys.exit(0)
+        end = time.time() + timeout
+sys.exit(1)
+"#;
+
+/// Send `command` to the firmware's console on `port` and return the first
+/// reply line starting with `prefix`. A dry run returns an empty line.
+pub fn query(
+    docker: &Docker,
+    project: &Project,
+    port: &str,
+    command: &str,
+    prefix: &str,
+    timeout: Duration,
+) -> Result<String> {
+    let output = run(docker, project, port, command, &[prefix], timeout)?;
+    if dry_run::is_enabled() {
+        return Ok(String::new());
+    }
+    match output.lines().find(|line| line.starts_with(prefix)) {
+        Some(line) => Ok(line.to_string()),
+        None => Err(no_reply(command, port)),
+    }
+}
+
+/// Send `command` and collect every reply line starting with `prefix`, up to
+/// and including one starting with `done`, allowing `timeout` between lines.
+/// A dry run returns no lines.
+pub fn query_lines(
+    docker: &Docker,
+    project: &Project,
+    port: &str,
+    command: &str,
+    prefix: &str,
+    done: &str,
+    timeout: Duration,
+) -> Result<Vec<String>> {
+    let output = run(docker, project, port, command, &[prefix, done], timeout)?;
+    if dry_run::is_enabled() {
+        return Ok(Vec::new());
+    }
+    let lines: Vec<String> = output
+        .lines()
+        .filter(|line| line.starts_with(prefix))
+        .map(str::to_string)
+        .collect();
+    if lines.is_empty() {
+        return Err(no_reply(command, port));
+    }
+    if !lines.iter().any(|line| line.starts_with(done)) {
+        bail!(
+            "The board stopped answering `{}` on {} partway through",
+            command,
+            port
+        );
+    }
+    Ok(lines)
+}
+
+fn run(
+    docker: &Docker,
+    project: &Project,
+    port: &str,
+    command: &str,
+    prefixes: &[&str],
+    timeout: Duration,
+) -> Result<String> {
+    crate::docker::require_device(port)?;
+    let timeout = timeout.as_secs_f64().to_string();
+    let mut args = vec![
+        "python3",
+        "-c",
+        QUERY_SCRIPT,
+        port,
+        command,
+        prefixes[0],
+        &timeout,
+    ];
+    args.extend(&prefixes[1..]);
+    docker.run_in_project_capture(project, &args, true)
+}
+
+fn no_reply(command: &str, port: &str) -> anyhow::Error {
+    Failure::DeviceNotFound.error(format!(
+        "No reply to `{}` from the board on {}; is its firmware answering on the console?",
+        command, port
+    ))
+}
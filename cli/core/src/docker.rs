@@ -0,0 +1,1023 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use serde_json::json;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, info_span, trace, Span};
+
+use crate::config::{available_ports, is_com_port, DEFAULT_PORT};
+use crate::dry_run;
+use crate::error::Failure;
+use crate::interrupt;
+use crate::output;
+use crate::progress::{self, Progress};
+use crate::project::Project;
+use crate::tool_log;
+
+/// Image used when neither --image nor AFFOGATO_IMAGE is given
+pub const DEFAULT_IMAGE: &str = "ghcr.io/meawoppl/affogato:latest";
+
+/// Docker volume keeping ESP-IDF's ccache between containers, so firmware
+/// rebuilds only recompile what changed
+pub const CCACHE_VOLUME: &str = "affogato-ccache";
+
+/// Where containers mount the ccache volume
+const CCACHE_DIR: &str = "/ccache";
+
+pub struct Docker {
+    image: String,
+    jobs: Option<usize>,
+}
+
+impl Docker {
+    pub fn new(image: Option<String>) -> Result<Self> {
+        // Check Docker is available (a dry run only prints commands)
+        if !dry_run::is_enabled() {
+            which::which("docker")
+                .context(
+                    "Docker not found. Please install Docker: https://docs.docker.com/get-docker/",
+                )
+                .context(Failure::DockerUnavailable)?;
+        }
+
+        Ok(Self {
+            image: image.unwrap_or_else(|| DEFAULT_IMAGE.to_string()),
+            jobs: None,
+        })
+    }
+
+    /// Limit containers to `jobs` CPUs and run make, ninja and cmake with as
+    /// many parallel jobs; None leaves both to the tools' defaults
+    pub fn with_jobs(mut self, jobs: Option<usize>) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    /// `docker run` arguments every container gets: the ccache volume and
+    /// the job limit
+    fn run_args(&self) -> Vec<String> {
+        let mut args = vec![
+            "-v".to_string(),
+            format!("{}:{}", CCACHE_VOLUME, CCACHE_DIR),
+            "-e".to_string(),
+            format!("CCACHE_DIR={}", CCACHE_DIR),
+        ];
+        let Some(jobs) = self.jobs else {
+            return args;
+        };
+        args.extend([
+            "-e".to_string(),
+            format!("MAKEFLAGS=-j{}", jobs),
+            "-e".to_string(),
+            format!("CMAKE_BUILD_PARALLEL_LEVEL={}", jobs),
+        ]);
+        // Pinning to CPUs both caps the container and makes ninja and nproc,
+        // which count the CPUs they may run on, pick the same number of jobs
+        let cpus = thread::available_parallelism().map_or(1, |cpus| cpus.get());
+        if jobs < cpus {
+            args.push(format!("--cpuset-cpus=0-{}", jobs - 1));
+        }
+        args
+    }
+
+    /// In a dry run, print the docker command, and any script it runs, instead of running it
+    fn print_dry_run(&self, args: &[String]) -> bool {
+        if !dry_run::is_enabled() {
+            return false;
+        }
+        let mut scripts = Vec::new();
+        let command: Vec<String> = args
+            .iter()
+            .map(|arg| {
+                if arg.contains('\n') {
+                    scripts.push(arg.trim());
+                    format!("<script {}>", scripts.len())
+                } else if arg.is_empty() || arg.contains(char::is_whitespace) {
+                    format!("'{}'", arg)
+                } else {
+                    arg.clone()
+                }
+            })
+            .collect();
+        dry_run::note(&format!("docker {}", command.join(" ")));
+        for (i, script) in scripts.iter().enumerate() {
+            outln!("{}", format!("    <script {}>:", i + 1).dimmed());
+            for line in script.lines() {
+                outln!("{}", format!("    {}", line).trim_end());
+            }
+        }
+        true
+    }
+
+    /// Span that a container's logs and output are attributed to
+    fn container_span(&self, args: &[String]) -> Span {
+        let span = info_span!("container", image = %self.image);
+        span.in_scope(|| debug!("docker {}", args.join(" ")));
+        span
+    }
+
+    /// Check if image exists locally
+    pub fn image_exists(&self) -> Result<bool> {
+        let output = Command::new("docker")
+            .args(["image", "inspect", &self.image])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()?;
+
+        Ok(output.success())
+    }
+
+    /// The local image's content-addressed id, identifying the exact toolchain
+    pub fn image_id(&self) -> Result<String> {
+        let output = Command::new("docker")
+            .args(["image", "inspect", "--format", "{{.Id}}", &self.image])
+            .stderr(Stdio::null())
+            .output()
+            .context("Failed to run docker")?;
+        if !output.status.success() {
+            bail!("Image {} is not available locally", self.image);
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    /// The digest the image was pulled by (`repo@sha256:...`), which pins
+    /// exactly this image; None for images built locally
+    pub fn repo_digest(&self) -> Result<Option<String>> {
+        let output = Command::new("docker")
+            .args([
+                "image",
+                "inspect",
+                "--format",
+                "{{range .RepoDigests}}{{println .}}{{end}}",
+                &self.image,
+            ])
+            .stderr(Stdio::null())
+            .output()
+            .context("Failed to run docker")?;
+        if !output.status.success() {
+            bail!("Image {} is not available locally", self.image);
+        }
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .next()
+            .map(str::to_string))
+    }
+
+    /// Write the image to a tar archive with `docker save`
+    pub fn save_image(&self, file: &Path) -> Result<()> {
+        let file = file.display().to_string();
+        self.run_docker(&["save", "-o", &file, &self.image])
+    }
+
+    /// Load an image saved with [`Docker::save_image`]
+    pub fn load_image(&self, file: &Path) -> Result<()> {
+        let file = file.display().to_string();
+        self.run_docker(&["load", "-q", "-i", &file])
+    }
+
+    /// Whether a docker volume exists
+    pub fn volume_exists(&self, volume: &str) -> Result<bool> {
+        let status = Command::new("docker")
+            .args(["volume", "inspect", volume])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .context("Failed to run docker")?;
+        Ok(status.success())
+    }
+
+    /// Write a docker volume's contents to a tar archive
+    pub fn export_volume(&self, volume: &str, file: &Path) -> Result<()> {
+        let args = self.volume_tar_args(volume, &["cf", "-"], false);
+        if self.print_dry_run(&args) {
+            return Ok(());
+        }
+        let archive =
+            File::create(file).with_context(|| format!("Failed to create {}", file.display()))?;
+        let status = Command::new("docker")
+            .args(&args)
+            .stdout(archive)
+            .status()
+            .context("Failed to run docker")?;
+        if !status.success() {
+            bail!("Failed to export the {} volume", volume);
+        }
+        Ok(())
+    }
+
+    /// Unpack a tar archive written by [`Docker::export_volume`] into a
+    /// docker volume, creating it if needed
+    pub fn import_volume(&self, volume: &str, file: &Path) -> Result<()> {
+        let args = self.volume_tar_args(volume, &["xf", "-"], true);
+        if self.print_dry_run(&args) {
+            return Ok(());
+        }
+        let archive =
+            File::open(file).with_context(|| format!("Failed to open {}", file.display()))?;
+        let status = Command::new("docker")
+            .args(&args)
+            .stdin(archive)
+            .status()
+            .context("Failed to run docker")?;
+        if !status.success() {
+            bail!("Failed to import the {} volume", volume);
+        }
+        Ok(())
+    }
+
+    /// `docker run` arguments running tar over a volume mounted at /volume,
+    /// streaming the archive through stdin or stdout
+    fn volume_tar_args(&self, volume: &str, tar: &[&str], stdin: bool) -> Vec<String> {
+        let mut args = vec!["run".to_string(), "--rm".to_string()];
+        if stdin {
+            args.push("-i".to_string());
+        }
+        args.extend([
+            "-v".to_string(),
+            format!("{}:/volume", volume),
+            self.image.clone(),
+            "tar".to_string(),
+        ]);
+        args.extend(tar.iter().map(|arg| arg.to_string()));
+        args.extend(["-C".to_string(), "/volume".to_string(), ".".to_string()]);
+        args
+    }
+
+    /// Run a docker command that needs no container, failing if it fails
+    fn run_docker(&self, args: &[&str]) -> Result<()> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        if self.print_dry_run(&args) {
+            return Ok(());
+        }
+        let status = Command::new("docker")
+            .args(&args)
+            .stdout(output::child_stdout())
+            .status()
+            .context("Failed to run docker")?;
+        if !status.success() {
+            bail!("docker {} failed", args[0]);
+        }
+        Ok(())
+    }
+
+    /// Ensure image is available, pulling if needed
+    pub fn ensure_image(&self) -> Result<()> {
+        if dry_run::is_enabled() {
+            return Ok(());
+        }
+        if !self.image_exists()? {
+            self.require_daemon()?;
+            outln!(
+                "{}",
+                format!("Image {} not found, pulling...", self.image).yellow()
+            );
+            self.pull()?;
+        }
+        Ok(())
+    }
+
+    /// Fail if the docker daemon can't be reached, e.g. it isn't running
+    fn require_daemon(&self) -> Result<()> {
+        let status = Command::new("docker")
+            .args(["version", "--format", "{{.Server.Version}}"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()?;
+        if !status.success() {
+            return Err(Failure::DockerUnavailable.error(
+                "Cannot connect to the Docker daemon. Start Docker, and check your user may use it (e.g. is in the docker group)",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Pull the container image
+    pub fn pull(&self) -> Result<()> {
+        outln!("{}", format!("==> Pulling {}", self.image).blue().bold());
+        if self.print_dry_run(&["pull".to_string(), self.image.clone()]) {
+            return Ok(());
+        }
+
+        let status = if progress::enabled() {
+            self.pull_with_progress()?
+        } else {
+            Command::new("docker")
+                .args(["pull", &self.image])
+                .stdout(output::child_stdout())
+                .status()
+                .context("Failed to run docker pull")?
+        };
+
+        if !status.success() {
+            bail!("Failed to pull image: {}", self.image);
+        }
+
+        outln!("{}", "Pull complete".green());
+        Ok(())
+    }
+
+    /// Pull with a bar counting the image's downloaded layers
+    fn pull_with_progress(&self) -> Result<ExitStatus> {
+        let mut child = Command::new("docker")
+            .args(["pull", &self.image])
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("Failed to run docker pull")?;
+
+        let progress = Progress::new(&format!("Pulling {}", self.image));
+        let stdout = child.stdout.take().unwrap();
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            progress.line(&line, false);
+        }
+        Ok(child.wait()?)
+    }
+
+    /// Build container locally from Dockerfile
+    pub fn build_local(&self) -> Result<()> {
+        // Find affogato root (where docker/Dockerfile lives)
+        let affogato_root = self.find_affogato_root()?;
+        let dockerfile_dir = affogato_root.join("docker");
+
+        if !dockerfile_dir.join("Dockerfile").exists() {
+            bail!(
+                "Dockerfile not found at {:?}. Are you in the affogato repository?",
+                dockerfile_dir
+            );
+        }
+
+        outln!(
+            "{}",
+            format!("==> Building {} from {:?}", self.image, dockerfile_dir)
+                .blue()
+                .bold()
+        );
+
+        if self.print_dry_run(&["build".into(), "-t".into(), self.image.clone(), ".".into()]) {
+            return Ok(());
+        }
+        let status = Command::new("docker")
+            .args(["build", "-t", &self.image, "."])
+            .current_dir(&dockerfile_dir)
+            .stdout(output::child_stdout())
+            .status()
+            .context("Failed to run docker build")?;
+
+        if !status.success() {
+            bail!("Docker build failed");
+        }
+
+        outln!("{}", "Build complete".green());
+        Ok(())
+    }
+
+    /// Show container info
+    pub fn info(&self) -> Result<()> {
+        outln!("{}", "Affogato Container Info".blue().bold());
+        outln!("  Image: {}", self.image);
+
+        let available = self.image_exists()?;
+        let mut id = None;
+        let mut size = None;
+        let mut created = None;
+        if available {
+            outln!("  Status: {}", "Available locally".green());
+
+            // Get image details
+            let inspect = Command::new("docker")
+                .args([
+                    "image",
+                    "inspect",
+                    &self.image,
+                    "--format",
+                    "{{.Id}} {{.Size}} {{.Created}}",
+                ])
+                .output()?;
+
+            if inspect.status.success() {
+                let info = String::from_utf8_lossy(&inspect.stdout);
+                let parts: Vec<&str> = info.split_whitespace().collect();
+                if parts.len() >= 3 {
+                    outln!("  ID: {}", &parts[0][7..19]); // Short ID
+                    let bytes: u64 = parts[1].parse().unwrap_or(0);
+                    outln!("  Size: {:.1} MB", bytes as f64 / 1_000_000.0);
+                    id = Some(parts[0].to_string());
+                    size = Some(bytes);
+                    created = Some(parts[2].to_string());
+                }
+            }
+        } else {
+            outln!("  Status: {}", "Not pulled yet".yellow());
+            outln!("  Run: affogato docker pull");
+        }
+
+        output::report(
+            "image",
+            json!({
+                "name": self.image,
+                "available": available,
+                "id": id,
+                "size_bytes": size,
+                "created": created,
+            }),
+        );
+        Ok(())
+    }
+
+    /// Run command in container with project mounted
+    pub fn run_in_project(
+        &self,
+        project: &Project,
+        cmd: &[&str],
+        extra_args: &[String],
+        usb: bool,
+    ) -> Result<()> {
+        let project_root = project
+            .root
+            .as_ref()
+            .context("Not in an Affogato project")?;
+
+        let name = container_name();
+        let mut args = vec![
+            "run".to_string(),
+            "--rm".to_string(),
+            "--name".to_string(),
+            name.clone(),
+            "-v".to_string(),
+            format!("{}:/workspace", project_root.display()),
+            "-w".to_string(),
+            "/workspace".to_string(),
+        ];
+
+        // Add USB device if requested
+        if usb {
+            args.extend(usb_args());
+        }
+
+        args.extend(self.run_args());
+
+        // Add image
+        args.push(self.image.clone());
+
+        // Add command
+        args.extend(cmd.iter().map(|s| s.to_string()));
+        args.extend(extra_args.iter().cloned());
+
+        let _span = self.container_span(&args).entered();
+        if self.print_dry_run(&args) {
+            return Ok(());
+        }
+        let _tracked = interrupt::Tracked::new(&name);
+
+        let status = Command::new("docker")
+            .args(&args)
+            .stdout(output::child_stdout())
+            .status()
+            .context("Failed to run docker")?;
+
+        debug!("docker exited: {}", status);
+        if !status.success() {
+            bail!("Command failed with exit code: {:?}", status.code());
+        }
+
+        Ok(())
+    }
+
+    /// Start a command in a container without waiting for it, e.g. a serial monitor
+    pub fn spawn_in_project(
+        &self,
+        project: &Project,
+        cmd: &[&str],
+        usb: bool,
+    ) -> Result<BackgroundContainer> {
+        let project_root = project
+            .root
+            .as_ref()
+            .context("Not in an Affogato project")?;
+
+        let name = container_name();
+
+        let mut args = vec![
+            "run".to_string(),
+            "--rm".to_string(),
+            "--name".to_string(),
+            name.clone(),
+            "-v".to_string(),
+            format!("{}:/workspace", project_root.display()),
+            "-w".to_string(),
+            "/workspace".to_string(),
+        ];
+
+        if usb {
+            args.extend(usb_args());
+        }
+
+        args.extend(self.run_args());
+        args.push(self.image.clone());
+        args.extend(cmd.iter().map(|s| s.to_string()));
+
+        let _span = self.container_span(&args).entered();
+
+        if self.print_dry_run(&args) {
+            return Ok(BackgroundContainer {
+                name,
+                child: None,
+                _tracked: None,
+            });
+        }
+        let tracked = interrupt::Tracked::new(&name);
+        let child = Command::new("docker")
+            .args(&args)
+            .stdin(Stdio::null())
+            .stdout(output::child_stdout())
+            .spawn()
+            .context("Failed to run docker")?;
+
+        Ok(BackgroundContainer {
+            name,
+            child: Some(child),
+            _tracked: Some(tracked),
+        })
+    }
+
+    /// Run command in container, streaming its output (as much as the verbosity
+    /// shows) while also collecting it and saving it to the tool log, with a
+    /// spinner showing the stage it has reached.
+    /// Returns whether the command succeeded and everything it printed.
+    pub fn run_in_project_tee(
+        &self,
+        project: &Project,
+        cmd: &[&str],
+        usb: bool,
+    ) -> Result<(bool, String)> {
+        let project_root = project
+            .root
+            .as_ref()
+            .context("Not in an Affogato project")?;
+
+        let name = container_name();
+        let mut args = vec![
+            "run".to_string(),
+            "--rm".to_string(),
+            "--name".to_string(),
+            name.clone(),
+            "-v".to_string(),
+            format!("{}:/workspace", project_root.display()),
+            "-w".to_string(),
+            "/workspace".to_string(),
+        ];
+
+        if usb {
+            args.extend(usb_args());
+        }
+
+        args.extend(self.run_args());
+        args.push(self.image.clone());
+        args.extend(cmd.iter().map(|s| s.to_string()));
+
+        let _span = self.container_span(&args).entered();
+        if self.print_dry_run(&args) {
+            return Ok((true, String::new()));
+        }
+        let _tracked = interrupt::Tracked::new(&name);
+        tool_log::open(project_root);
+
+        let mut child = Command::new("docker")
+            .args(&args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to run docker")?;
+
+        let collected = Arc::new(Mutex::new(String::new()));
+        let progress = Arc::new(Progress::new("Starting container"));
+        let tee = |pipe: Box<dyn Read + Send>, stderr: bool| {
+            let collected = Arc::clone(&collected);
+            let progress = Arc::clone(&progress);
+            let span = Span::current();
+            thread::spawn(move || {
+                let _span = span.enter();
+                for line in BufReader::new(pipe).lines().map_while(Result::ok) {
+                    trace!(target: "affogato::container", stderr, "{}", line);
+                    tool_log::write_line(&line);
+                    progress.line(&line, stderr);
+                    let mut collected = collected.lock().unwrap();
+                    collected.push_str(&line);
+                    collected.push('\n');
+                }
+            })
+        };
+        let stdout = tee(Box::new(child.stdout.take().unwrap()), false);
+        let stderr = tee(Box::new(child.stderr.take().unwrap()), true);
+
+        let status = child.wait()?;
+        let _ = stdout.join();
+        let _ = stderr.join();
+        debug!("docker exited: {}", status);
+
+        let output = collected.lock().unwrap().clone();
+        Ok((status.success(), output))
+    }
+
+    /// Run command in container and capture output
+    pub fn run_in_project_capture(
+        &self,
+        project: &Project,
+        cmd: &[&str],
+        usb: bool,
+    ) -> Result<String> {
+        let project_root = project
+            .root
+            .as_ref()
+            .context("Not in an Affogato project")?;
+
+        let name = container_name();
+        let mut args = vec![
+            "run".to_string(),
+            "--rm".to_string(),
+            "--name".to_string(),
+            name.clone(),
+            "-v".to_string(),
+            format!("{}:/workspace", project_root.display()),
+            "-w".to_string(),
+            "/workspace".to_string(),
+        ];
+
+        if usb {
+            args.extend(usb_args());
+        }
+
+        args.extend(self.run_args());
+        args.push(self.image.clone());
+        args.extend(cmd.iter().map(|s| s.to_string()));
+
+        let _span = self.container_span(&args).entered();
+        if self.print_dry_run(&args) {
+            return Ok(String::new());
+        }
+        let _tracked = interrupt::Tracked::new(&name);
+        tool_log::open(project_root);
+        let output = Command::new("docker")
+            .args(&args)
+            .output()
+            .context("Failed to run docker")?;
+        debug!("docker exited: {}", output.status);
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        trace_output(&stdout, false);
+        trace_output(&stderr, true);
+
+        Ok(format!("{}{}", stdout, stderr))
+    }
+
+    /// Run command in container and capture output, killing the container if it
+    /// is still running after `limit`. Returns the output and whether it was killed.
+    pub fn run_in_project_capture_with_limit(
+        &self,
+        project: &Project,
+        cmd: &[&str],
+        limit: Duration,
+    ) -> Result<(String, bool)> {
+        let project_root = project
+            .root
+            .as_ref()
+            .context("Not in an Affogato project")?;
+
+        let name = container_name();
+
+        let mut args = vec![
+            "run".to_string(),
+            "--rm".to_string(),
+            "--name".to_string(),
+            name.clone(),
+            "-v".to_string(),
+            format!("{}:/workspace", project_root.display()),
+            "-w".to_string(),
+            "/workspace".to_string(),
+        ];
+
+        args.extend(self.run_args());
+        args.push(self.image.clone());
+        args.extend(cmd.iter().map(|s| s.to_string()));
+
+        let _span = self.container_span(&args).entered();
+        if self.print_dry_run(&args) {
+            return Ok((String::new(), false));
+        }
+        let _tracked = interrupt::Tracked::new(&name);
+        tool_log::open(project_root);
+        let mut child = Command::new("docker")
+            .args(&args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to run docker")?;
+
+        let read = |mut pipe: Box<dyn Read + Send>| {
+            thread::spawn(move || {
+                let mut buf = Vec::new();
+                let _ = pipe.read_to_end(&mut buf);
+                String::from_utf8_lossy(&buf).to_string()
+            })
+        };
+        let stdout = read(Box::new(child.stdout.take().unwrap()));
+        let stderr = read(Box::new(child.stderr.take().unwrap()));
+
+        let deadline = Instant::now() + limit;
+        let mut killed = false;
+        while child.try_wait()?.is_none() {
+            if Instant::now() >= deadline {
+                // --rm removes the container, and its temp files, once it is killed
+                let _ = Command::new("docker")
+                    .args(["kill", &name])
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status();
+                killed = true;
+                child.wait()?;
+                debug!("docker killed after {:?}", limit);
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+        trace_output(&stdout, false);
+        trace_output(&stderr, true);
+
+        Ok((format!("{}{}", stdout, stderr), killed))
+    }
+
+    /// Start a command in a container with its stdin and stdout piped, for a
+    /// session affogato drives itself, e.g. a console
+    pub fn spawn_interactive_in_project(
+        &self,
+        project: &Project,
+        cmd: &[&str],
+        usb: bool,
+    ) -> Result<BackgroundContainer> {
+        let project_root = project
+            .root
+            .as_ref()
+            .context("Not in an Affogato project")?;
+
+        let name = container_name();
+
+        let mut args = vec![
+            "run".to_string(),
+            "--rm".to_string(),
+            "-i".to_string(),
+            "--name".to_string(),
+            name.clone(),
+            "-v".to_string(),
+            format!("{}:/workspace", project_root.display()),
+            "-w".to_string(),
+            "/workspace".to_string(),
+        ];
+
+        if usb {
+            args.extend(usb_args());
+        }
+
+        args.extend(self.run_args());
+        args.push(self.image.clone());
+        args.extend(cmd.iter().map(|s| s.to_string()));
+
+        let _span = self.container_span(&args).entered();
+
+        if self.print_dry_run(&args) {
+            return Ok(BackgroundContainer {
+                name,
+                child: None,
+                _tracked: None,
+            });
+        }
+        let tracked = interrupt::Tracked::new(&name);
+        let child = Command::new("docker")
+            .args(&args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("Failed to run docker")?;
+
+        Ok(BackgroundContainer {
+            name,
+            child: Some(child),
+            _tracked: Some(tracked),
+        })
+    }
+
+    /// Run command in container with project and extra mount options
+    pub fn run_in_project_with_extra_mounts(
+        &self,
+        project: &Project,
+        cmd: &[&str],
+        extra_mounts: &[&str],
+        usb: bool,
+    ) -> Result<()> {
+        let project_root = project
+            .root
+            .as_ref()
+            .context("Not in an Affogato project")?;
+
+        let name = container_name();
+        let mut args = vec![
+            "run".to_string(),
+            "--rm".to_string(),
+            "--name".to_string(),
+            name.clone(),
+            "-v".to_string(),
+            format!("{}:/workspace", project_root.display()),
+            "-w".to_string(),
+            "/workspace".to_string(),
+        ];
+
+        // Add extra mounts
+        for mount in extra_mounts {
+            args.push(mount.to_string());
+        }
+
+        if usb {
+            args.extend(usb_args());
+        }
+
+        args.extend(self.run_args());
+        args.push(self.image.clone());
+        args.extend(cmd.iter().map(|s| s.to_string()));
+
+        let _span = self.container_span(&args).entered();
+        if self.print_dry_run(&args) {
+            return Ok(());
+        }
+        let _tracked = interrupt::Tracked::new(&name);
+
+        let status = Command::new("docker")
+            .args(&args)
+            .stdout(output::child_stdout())
+            .status()
+            .context("Failed to run docker")?;
+
+        debug!("docker exited: {}", status);
+        if !status.success() {
+            bail!("Command failed with exit code: {:?}", status.code());
+        }
+
+        Ok(())
+    }
+
+    /// Run command in container without project
+    pub fn run_standalone(&self, cmd: &[&str], usb: bool) -> Result<()> {
+        let cwd = std::env::current_dir()?;
+
+        let name = container_name();
+        let mut args = vec![
+            "run".to_string(),
+            "--rm".to_string(),
+            "--name".to_string(),
+            name.clone(),
+            "-it".to_string(),
+            "-v".to_string(),
+            format!("{}:/workspace", cwd.display()),
+            "-w".to_string(),
+            "/workspace".to_string(),
+        ];
+
+        if usb {
+            args.extend(usb_args());
+        }
+
+        args.extend(self.run_args());
+        args.push(self.image.clone());
+        args.extend(cmd.iter().map(|s| s.to_string()));
+
+        if self.print_dry_run(&args) {
+            return Ok(());
+        }
+        let _tracked = interrupt::Tracked::new(&name);
+        let status = Command::new("docker")
+            .args(&args)
+            .stdout(output::child_stdout())
+            .status()
+            .context("Failed to run docker")?;
+
+        if !status.success() {
+            bail!("Command failed");
+        }
+
+        Ok(())
+    }
+
+    fn find_affogato_root(&self) -> Result<std::path::PathBuf> {
+        // Try to find affogato root by looking for docker/Dockerfile
+        let mut dir = std::env::current_dir()?;
+        loop {
+            if dir.join("docker/Dockerfile").exists() && dir.join("components/ice40").exists() {
+                return Ok(dir);
+            }
+            if !dir.pop() {
+                break;
+            }
+        }
+
+        // Try common locations
+        if let Some(home) = dirs::home_dir() {
+            let candidates = [
+                home.join("repos/affogato"),
+                home.join("src/affogato"),
+                home.join(".affogato"),
+            ];
+            for candidate in candidates {
+                if candidate.join("docker/Dockerfile").exists() {
+                    return Ok(candidate);
+                }
+            }
+        }
+
+        bail!("Could not find Affogato installation. Set AFFOGATO_PATH or run from the affogato directory.");
+    }
+}
+
+/// Fail unless the serial port to flash or monitor exists
+pub fn require_device(port: &str) -> Result<()> {
+    let present = if is_com_port(port) {
+        available_ports()
+            .iter()
+            .any(|p| p.eq_ignore_ascii_case(port.trim_start_matches(r"\\.\")))
+    } else {
+        Path::new(port).exists()
+    };
+    if !present {
+        return Err(Failure::DeviceNotFound.error(format!(
+            "No device at {}. Is the board plugged in? Use --port to pick another port",
+            port
+        )));
+    }
+    Ok(())
+}
+
+/// Docker flags giving a container the board. Privileged mode exposes the
+/// whole USB bus, which a board in DFU mode (with no serial port) needs.
+fn usb_args() -> Vec<String> {
+    let mut args = vec!["--privileged".to_string()];
+    if Path::new(DEFAULT_PORT).exists() {
+        args.push(format!("--device={}", DEFAULT_PORT));
+    }
+    args
+}
+
+/// A unique name for a new container. Killing the docker client alone leaves
+/// the container running, so every container is named to be killed by name.
+fn container_name() -> String {
+    static CONTAINERS: AtomicUsize = AtomicUsize::new(0);
+    format!(
+        "affogato-{}-{}",
+        std::process::id(),
+        CONTAINERS.fetch_add(1, Ordering::SeqCst)
+    )
+}
+
+/// Log captured container output line by line, in the current container span,
+/// and save it to the tool log
+fn trace_output(output: &str, stderr: bool) {
+    tool_log::write(output);
+    for line in output.lines() {
+        trace!(target: "affogato::container", stderr, "{}", line);
+    }
+}
+
+/// A container started with `spawn_in_project`, killed when stopped or dropped
+pub struct BackgroundContainer {
+    name: String,
+    /// `None` in a dry run
+    child: Option<Child>,
+    _tracked: Option<interrupt::Tracked>,
+}
+
+impl BackgroundContainer {
+    /// The docker client process, `None` in a dry run
+    pub fn child(&mut self) -> Option<&mut Child> {
+        self.child.as_mut()
+    }
+}
+
+impl Drop for BackgroundContainer {
+    fn drop(&mut self) {
+        let Some(child) = &mut self.child else {
+            return;
+        };
+        // Killing the docker client alone would leave the container holding the port
+        let _ = Command::new("docker")
+            .args(["kill", &self.name])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        let _ = child.wait();
+    }
+}
@@ -0,0 +1,82 @@
+use colored::Colorize;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Switch to dry-run mode: docker commands and file changes are printed, not performed.
+/// The file functions below stand in for their `std::fs` namesakes.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+/// Print an action skipped by the dry run
+pub fn note(action: &str) {
+    outln!("{} {}", "[dry-run]".cyan(), action);
+}
+
+pub fn write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    if is_enabled() {
+        note(&format!(
+            "write {} ({} bytes)",
+            display(path.as_ref()),
+            contents.as_ref().len()
+        ));
+        return Ok(());
+    }
+    fs::write(path, contents)
+}
+
+pub fn create_dir_all(path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+    if is_enabled() {
+        if !path.is_dir() {
+            note(&format!("create directory {}", display(path)));
+        }
+        return Ok(());
+    }
+    fs::create_dir_all(path)
+}
+
+pub fn copy(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<u64> {
+    if is_enabled() {
+        note(&format!(
+            "copy {} to {}",
+            display(from.as_ref()),
+            display(to.as_ref())
+        ));
+        return Ok(0);
+    }
+    fs::copy(from, to)
+}
+
+pub fn remove_file(path: impl AsRef<Path>) -> io::Result<()> {
+    if is_enabled() {
+        note(&format!("remove {}", display(path.as_ref())));
+        return Ok(());
+    }
+    fs::remove_file(path)
+}
+
+pub fn remove_dir_all(path: impl AsRef<Path>) -> io::Result<()> {
+    if is_enabled() {
+        note(&format!("remove directory {}", display(path.as_ref())));
+        return Ok(());
+    }
+    fs::remove_dir_all(path)
+}
+
+/// A path relative to the current directory where possible
+fn display(path: &Path) -> String {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    path.strip_prefix(&cwd)
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
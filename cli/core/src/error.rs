@@ -0,0 +1,77 @@
+use std::fmt;
+
+/// Classes of failure that get their own exit code, so scripts and CI can
+/// branch on why a command failed. Attach one to an error with
+/// `.context(Failure::BuildFailed)`; other errors exit with 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// Run outside an Affogato project
+    NotAProject,
+    /// docker is missing, or its daemon can't be reached
+    DockerUnavailable,
+    /// Synthesis, place-and-route or the firmware build failed
+    BuildFailed,
+    /// A testbench, the coverage gate or a board self-test check failed
+    TestsFailed,
+    /// nextpnr couldn't meet a clock constraint
+    TimingFailed,
+    /// The serial port to flash or monitor doesn't exist
+    DeviceNotFound,
+}
+
+impl Failure {
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Failure::NotAProject => 3,
+            Failure::DockerUnavailable => 4,
+            Failure::BuildFailed => 5,
+            Failure::TestsFailed => 6,
+            Failure::TimingFailed => 7,
+            Failure::DeviceNotFound => 8,
+        }
+    }
+
+    /// A new error of this class
+    pub fn error(
+        self,
+        message: impl fmt::Display + fmt::Debug + Send + Sync + 'static,
+    ) -> anyhow::Error {
+        anyhow::Error::msg(message).context(self)
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Failure::NotAProject => "not an Affogato project",
+            Failure::DockerUnavailable => "docker unavailable",
+            Failure::BuildFailed => "build failed",
+            Failure::TestsFailed => "tests failed",
+            Failure::TimingFailed => "timing failed",
+            Failure::DeviceNotFound => "device not found",
+        })
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/// The failure class attached to an error, if any
+pub fn failure(error: &anyhow::Error) -> Option<Failure> {
+    error.downcast_ref::<Failure>().copied()
+}
+
+/// Exit code for a command that failed with `error`
+pub fn exit_code(error: &anyhow::Error) -> u8 {
+    failure(error).map_or(1, Failure::exit_code)
+}
+
+/// The error and its causes on one line, without the failure class tags
+pub fn message(error: &anyhow::Error) -> String {
+    let tag = failure(error).map(|f| f.to_string());
+    error
+        .chain()
+        .map(|e| e.to_string())
+        .filter(|m| Some(m) != tag.as_ref())
+        .collect::<Vec<_>>()
+        .join(": ")
+}
@@ -0,0 +1,221 @@
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use crate::dry_run;
+
+/// nextpnr's placed and routed design, relative to the project root
+pub const PLACED_JSON: &str = "fpga/build/placed.json";
+
+/// The floorplan heatmap, relative to the project root
+pub const FLOORPLAN_SVG: &str = "fpga/build/floorplan.svg";
+
+/// Logic cells per logic tile
+const CELLS_PER_TILE: usize = 8;
+
+/// Side of one tile in the SVG, in pixels
+const TILE: usize = 14;
+
+/// Space around and between the panels, in pixels
+const MARGIN: usize = 30;
+
+/// What nextpnr put in one tile
+#[derive(Default)]
+struct Tile {
+    logic_cells: usize,
+    rams: usize,
+    /// IO, DSP, oscillator and other hard blocks
+    other: usize,
+    /// Routing wires used in the tile
+    wires: usize,
+}
+
+/// Tile grid of a device as (columns, rows), for devices whose size is known
+fn grid(device: &str) -> Option<(usize, usize)> {
+    match device {
+        "up5k" | "up3k" => Some((26, 32)),
+        "hx1k" | "lp1k" => Some((14, 18)),
+        "hx4k" | "lp4k" | "hx8k" | "lp8k" => Some((34, 34)),
+        _ => None,
+    }
+}
+
+/// Write an SVG heatmap of logic cell, BRAM and routing use per tile from
+/// nextpnr's placed design
+pub fn write(project_root: &Path, device: &str) -> Result<()> {
+    let text = fs::read_to_string(project_root.join(PLACED_JSON))
+        .with_context(|| format!("Failed to read {}", PLACED_JSON))?;
+    let design: Value =
+        serde_json::from_str(&text).with_context(|| format!("Invalid {}", PLACED_JSON))?;
+    let tiles = tiles(&design);
+    let (columns, rows) = grid(device).unwrap_or_else(|| {
+        let x = tiles.keys().map(|(x, _)| x + 1).max().unwrap_or(1);
+        let y = tiles.keys().map(|(_, y)| y + 1).max().unwrap_or(1);
+        (x, y)
+    });
+    dry_run::write(
+        project_root.join(FLOORPLAN_SVG),
+        svg(&tiles, device, columns, rows),
+    )?;
+    Ok(())
+}
+
+/// Tally cells by their `NEXTPNR_BEL` (e.g. "X12/Y5/lc3") and routing wires by
+/// the tiles named in each net's `ROUTING` ("wire;pip;strength;...")
+fn tiles(design: &Value) -> BTreeMap<(usize, usize), Tile> {
+    let mut tiles: BTreeMap<(usize, usize), Tile> = BTreeMap::new();
+    let modules = design["modules"].as_object().into_iter().flatten();
+    for (_, module) in modules {
+        let cells = module["cells"].as_object().into_iter().flatten();
+        for (_, cell) in cells {
+            let Some(tile) = cell["attributes"]["NEXTPNR_BEL"]
+                .as_str()
+                .and_then(location)
+            else {
+                continue;
+            };
+            let entry = tiles.entry(tile).or_default();
+            match cell["type"].as_str() {
+                Some("ICESTORM_LC") => entry.logic_cells += 1,
+                Some("ICESTORM_RAM") => entry.rams += 1,
+                _ => entry.other += 1,
+            }
+        }
+        let nets = module["netnames"].as_object().into_iter().flatten();
+        for (_, net) in nets {
+            let Some(routing) = net["attributes"]["ROUTING"].as_str() else {
+                continue;
+            };
+            for wire in routing.split(';').step_by(3) {
+                if let Some(tile) = location(wire) {
+                    tiles.entry(tile).or_default().wires += 1;
+                }
+            }
+        }
+    }
+    tiles
+}
+
+/// The tile of a bel or wire name such as "X12/Y5/lc3"
+fn location(name: &str) -> Option<(usize, usize)> {
+    let mut parts = name.split('/');
+    let x = parts.next()?.strip_prefix('X')?.parse().ok()?;
+    let y = parts.next()?.strip_prefix('Y')?.parse().ok()?;
+    Some((x, y))
+}
+
+/// Yellow for light use through red for full, as an SVG color
+fn heat(fraction: f64) -> String {
+    let fraction = fraction.clamp(0.0, 1.0);
+    format!(
+        "hsl({:.0},90%,{:.0}%)",
+        60.0 - 60.0 * fraction,
+        85.0 - 40.0 * fraction
+    )
+}
+
+fn svg(
+    tiles: &BTreeMap<(usize, usize), Tile>,
+    device: &str,
+    columns: usize,
+    rows: usize,
+) -> String {
+    let panel_width = columns * TILE;
+    let width = MARGIN * 3 + panel_width * 2;
+    let height = MARGIN * 2 + rows * TILE + 40;
+    let max_wires = tiles
+        .values()
+        .map(|tile| tile.wires)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let logic_cells: usize = tiles.values().map(|tile| tile.logic_cells).sum();
+    let rams: usize = tiles.values().map(|tile| tile.rams).sum();
+    let wires: usize = tiles.values().map(|tile| tile.wires).sum();
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" font-family="sans-serif" font-size="12">"#
+    );
+    let _ = writeln!(
+        svg,
+        r#"<rect width="{width}" height="{height}" fill="white"/>"#
+    );
+    let panels = [
+        (
+            MARGIN,
+            format!("{device} placement: {logic_cells} logic cells, {rams} BRAM"),
+        ),
+        (
+            MARGIN * 2 + panel_width,
+            format!("Routing: {wires} wires, busiest tile {max_wires}"),
+        ),
+    ];
+    for (panel, (left, title)) in panels.iter().enumerate() {
+        let _ = writeln!(
+            svg,
+            r#"<text x="{left}" y="{}" font-weight="bold">{title}</text>"#,
+            MARGIN - 10
+        );
+        for x in 0..columns {
+            for y in 0..rows {
+                let tile = tiles.get(&(x, y));
+                let (fill, detail) = match tile {
+                    None => ("#eeeeee".to_string(), "empty".to_string()),
+                    Some(tile) if panel == 1 => (
+                        if tile.wires == 0 {
+                            "#eeeeee".to_string()
+                        } else {
+                            heat(tile.wires as f64 / max_wires as f64)
+                        },
+                        format!("{} wires", tile.wires),
+                    ),
+                    Some(tile) if tile.rams > 0 => ("#3182bd".to_string(), "BRAM".to_string()),
+                    Some(tile) if tile.logic_cells > 0 => (
+                        heat(tile.logic_cells as f64 / CELLS_PER_TILE as f64),
+                        format!("{}/{} logic cells", tile.logic_cells, CELLS_PER_TILE),
+                    ),
+                    Some(tile) if tile.other > 0 => (
+                        "#74c476".to_string(),
+                        format!("{} IO or hard blocks", tile.other),
+                    ),
+                    Some(_) => ("#eeeeee".to_string(), "routing only".to_string()),
+                };
+                // Row 0 is at the bottom of the die
+                let _ = writeln!(
+                    svg,
+                    r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{fill}" stroke="white"><title>X{x} Y{y}: {detail}</title></rect>"#,
+                    left + x * TILE,
+                    MARGIN + (rows - 1 - y) * TILE,
+                    TILE,
+                    TILE
+                );
+            }
+        }
+    }
+
+    let legend = MARGIN + rows * TILE + 25;
+    let entries = [
+        (heat(0.125), "light"),
+        (heat(0.5), "half"),
+        (heat(1.0), "full"),
+        ("#3182bd".to_string(), "BRAM"),
+        ("#74c476".to_string(), "IO / hard block"),
+        ("#eeeeee".to_string(), "unused"),
+    ];
+    for (i, (fill, label)) in entries.iter().enumerate() {
+        let x = MARGIN + i * 110;
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{x}" y="{}" width="{TILE}" height="{TILE}" fill="{fill}"/><text x="{}" y="{legend}">{label}</text>"#,
+            legend - 11,
+            x + TILE + 5
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
@@ -0,0 +1,69 @@
+use anyhow::{Context, Result};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Exit code after Ctrl+C, as for a shell command killed by SIGINT
+pub const EXIT_CODE: i32 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether Ctrl+C exits the process; cleared by long-running loops that stop themselves
+static EXIT: AtomicBool = AtomicBool::new(true);
+
+/// Names of the containers currently running in the foreground
+static CONTAINERS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Install the Ctrl+C handler: it kills the containers affogato started (their
+/// `--rm` removes them and their temp files) and exits with [`EXIT_CODE`].
+/// Call once, early; interrupts before this kill affogato but not its containers.
+pub fn install() -> Result<()> {
+    ctrlc::set_handler(|| {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        let containers = std::mem::take(&mut *CONTAINERS.lock().unwrap());
+        if !containers.is_empty() {
+            eprintln!("\nInterrupted, stopping {} container(s)", containers.len());
+            let _ = Command::new("docker")
+                .arg("kill")
+                .args(&containers)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+        }
+        if EXIT.load(Ordering::SeqCst) {
+            std::process::exit(EXIT_CODE);
+        }
+    })
+    .context("Failed to install Ctrl+C handler")
+}
+
+/// Keep running after Ctrl+C, for a loop that polls [`requested`] and stops
+/// itself. Running containers are still killed.
+pub fn handle_in_loop() {
+    EXIT.store(false, Ordering::SeqCst);
+}
+
+/// Whether Ctrl+C has been pressed
+pub fn requested() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// A running container, killed on Ctrl+C until this is dropped
+pub(crate) struct Tracked {
+    name: String,
+}
+
+impl Tracked {
+    pub(crate) fn new(name: &str) -> Self {
+        CONTAINERS.lock().unwrap().push(name.to_string());
+        Tracked {
+            name: name.to_string(),
+        }
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        CONTAINERS.lock().unwrap().retain(|n| *n != self.name);
+    }
+}
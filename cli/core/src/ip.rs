@@ -0,0 +1,180 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use serde_json::json;
+use std::path::Path;
+
+use crate::dry_run;
+use crate::output;
+use crate::project::Project;
+use crate::test::find_test_dir;
+
+/// Where cores are installed, relative to the project root
+pub const IP_DIR: &str = "fpga/rtl/ip";
+
+/// A reusable core shipped with affogato
+struct IpCore {
+    name: &'static str,
+    description: &'static str,
+    /// RTL sources, installed into fpga/rtl/ip
+    rtl: &'static [(&'static str, &'static str)],
+    /// Testbench, installed into the project's test directory
+    testbench: (&'static str, &'static str),
+}
+
+const CATALOG: &[IpCore] = &[
+    IpCore {
+        name: "uart",
+        description: "8N1 UART transmitter and receiver",
+        rtl: &[
+            ("uart_tx.v", include_str!("../ip/uart/uart_tx.v")),
+            ("uart_rx.v", include_str!("../ip/uart/uart_rx.v")),
+        ],
+        testbench: ("uart_tb.v", include_str!("../ip/uart/uart_tb.v")),
+    },
+    IpCore {
+        name: "spi_regfile",
+        description: "SPI slave register file (mode 0, auto-incrementing)",
+        rtl: &[(
+            "spi_regfile.v",
+            include_str!("../ip/spi_regfile/spi_regfile.v"),
+        )],
+        testbench: (
+            "spi_regfile_tb.v",
+            include_str!("../ip/spi_regfile/spi_regfile_tb.v"),
+        ),
+    },
+    IpCore {
+        name: "spi_bridge",
+        description: "SPI slave to 16-bit register bus bridge",
+        rtl: &[(
+            "spi_bridge.v",
+            include_str!("../ip/spi_bridge/spi_bridge.v"),
+        )],
+        testbench: (
+            "spi_bridge_tb.v",
+            include_str!("../ip/spi_bridge/spi_bridge_tb.v"),
+        ),
+    },
+    IpCore {
+        name: "spi_loopback",
+        description: "SPI slave echoing each byte back, for `affogato bench spi`",
+        rtl: &[(
+            "spi_loopback.v",
+            include_str!("../ip/spi_loopback/spi_loopback.v"),
+        )],
+        testbench: (
+            "spi_loopback_tb.v",
+            include_str!("../ip/spi_loopback/spi_loopback_tb.v"),
+        ),
+    },
+    IpCore {
+        name: "pwm",
+        description: "Glitch-free PWM generator",
+        rtl: &[("pwm.v", include_str!("../ip/pwm/pwm.v"))],
+        testbench: ("pwm_tb.v", include_str!("../ip/pwm/pwm_tb.v")),
+    },
+    IpCore {
+        name: "debounce",
+        description: "Switch debouncer with edge pulses",
+        rtl: &[("debounce.v", include_str!("../ip/debounce/debounce.v"))],
+        testbench: (
+            "debounce_tb.v",
+            include_str!("../ip/debounce/debounce_tb.v"),
+        ),
+    },
+    IpCore {
+        name: "pdm_mic",
+        description: "PDM MEMS microphone clock and decimator",
+        rtl: &[("pdm_mic.v", include_str!("../ip/pdm_mic/pdm_mic.v"))],
+        testbench: ("pdm_mic_tb.v", include_str!("../ip/pdm_mic/pdm_mic_tb.v")),
+    },
+];
+
+/// Print the available cores
+pub fn list() {
+    output::report(
+        "cores",
+        CATALOG
+            .iter()
+            .map(|c| json!({ "name": c.name, "description": c.description }))
+            .collect::<Vec<_>>(),
+    );
+    outln!("{}", "Available IP cores:".bold());
+    for core in CATALOG {
+        outln!("  {:14} {}", core.name, core.description);
+    }
+}
+
+/// Install a core and its testbench into the project
+pub fn add(project: &Project, name: &str, force: bool) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+
+    let Some(core) = CATALOG.iter().find(|c| c.name == name) else {
+        list();
+        bail!("Unknown IP core: {}", name);
+    };
+
+    let files = core_files(project_root, core);
+    if !force {
+        for (path, _) in &files {
+            if project_root.join(path).exists() {
+                bail!("{} already exists (use --force to overwrite)", path);
+            }
+        }
+    }
+
+    outln!("{}", format!("==> Adding {}", core.name).blue().bold());
+    for (path, content) in &files {
+        let full = project_root.join(path);
+        if let Some(parent) = full.parent() {
+            dry_run::create_dir_all(parent)?;
+        }
+        dry_run::write(&full, content)?;
+        outln!("  {} {}", "Created".green(), path);
+    }
+    outln!();
+    outln!(
+        "Run its testbench with: affogato test {}",
+        core.testbench.0.trim_end_matches("_tb.v")
+    );
+
+    Ok(())
+}
+
+/// Install the files of a core that are missing, leaving existing ones alone.
+/// Used by generators whose output instantiates a catalog core.
+pub fn ensure(project_root: &Path, name: &str) -> Result<()> {
+    let core = CATALOG
+        .iter()
+        .find(|c| c.name == name)
+        .with_context(|| format!("Unknown IP core: {}", name))?;
+
+    for (path, content) in core_files(project_root, core) {
+        let full = project_root.join(&path);
+        if full.exists() {
+            continue;
+        }
+        if let Some(parent) = full.parent() {
+            dry_run::create_dir_all(parent)?;
+        }
+        dry_run::write(&full, content)?;
+        outln!("  {} {}", "Created".green(), path);
+    }
+    Ok(())
+}
+
+/// Project-relative destinations and contents of a core's RTL and testbench
+fn core_files(project_root: &Path, core: &IpCore) -> Vec<(String, &'static str)> {
+    let test_dir = find_test_dir(project_root, "fpga").unwrap_or_else(|| "fpga/rtl_test".into());
+    let (tb_name, tb_content) = core.testbench;
+    let mut files: Vec<(String, &'static str)> = core
+        .rtl
+        .iter()
+        .map(|(file, content)| (format!("{}/{}", IP_DIR, file), *content))
+        .collect();
+    files.push((format!("{}/{}", test_dir, tb_name), tb_content));
+    files
+}
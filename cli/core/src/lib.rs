@@ -0,0 +1,104 @@
+//! Library behind the `affogato` CLI for ESP32-S2 + ICE40 FPGA projects.
+//!
+//! Everything the command-line tool does to a project is available here, so
+//! IDE plugins, CI helpers and other tools can drive it directly instead of
+//! running `affogato` and parsing its output:
+//!
+//! - [`project`]: finding a project and loading `affogato.toml`
+//! - [`docker`]: running the toolchain container against a project
+//! - [`build`]: the FPGA synthesis, place-and-route and packing pipeline
+//! - [`test`](mod@test): discovering and running testbenches
+//!
+//! Errors are returned as [`anyhow::Error`]. Progress is still printed to the
+//! terminal as the CLI does, and container output is streamed through.
+//!
+//! ```no_run
+//! use affogato_core::{build, docker::Docker, project::Project, test};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let project = Project::detect_from("path/to/project")?;
+//! project.require_project()?;
+//!
+//! let docker = Docker::new(None)?;
+//! docker.ensure_image()?;
+//!
+//! build::build_fpga(&docker, &project, &[], Default::default())?;
+//! let opts = test::TestOptions {
+//!     fpga_dir: "fpga".to_string(),
+//!     ..Default::default()
+//! };
+//! test::run_tests(&docker, &project, &opts)?;
+//! # Ok(())
+//! # }
+//! ```
+
+/// Human output and the `--json` result document
+#[macro_use]
+pub mod output;
+
+/// Amaranth HDL elaboration
+pub mod amaranth;
+/// Reporting build and test failures as text or CI annotations
+pub mod annotate;
+/// The last build's artifacts, for commands that read the firmware's ELF
+pub mod artifacts;
+/// The FPGA build pipeline
+pub mod build;
+/// Build metadata embedded in the bitstream and firmware
+pub mod build_info;
+/// The build cache shared across projects and checkouts
+pub mod cache;
+/// User settings
+pub mod config;
+/// Pin constraints merged from per-profile fragments
+pub mod constraints;
+/// The firmware's debug console, for reaching the FPGA from the host
+pub mod debug_console;
+/// How the bitstream reaches the device with the firmware
+pub mod delivery;
+/// Vendored HDL dependencies
+pub mod deps;
+/// Talking to the firmware over its serial console
+pub mod device;
+/// The toolchain container runtime
+pub mod docker;
+/// Printing commands and file changes instead of performing them
+pub mod dry_run;
+/// Failure classes and their exit codes
+pub mod error;
+/// Heatmaps of placement and routing use across the tile grid
+pub mod floorplan;
+/// Ctrl+C handling that stops running containers
+pub mod interrupt;
+/// Bundled IP cores
+pub mod ip;
+/// Utilization and timing from nextpnr's log
+pub mod nextpnr;
+/// Spinners and progress bars for long-running tools
+pub mod progress;
+/// Project detection, `affogato.toml` and project templates
+pub mod project;
+/// Keeping the firmware's sdkconfig in step with affogato.toml
+pub mod sdkconfig;
+/// Constants shared between the Verilog and the firmware
+pub mod shared;
+/// RISC-V soft-core program builds
+pub mod softcore;
+/// Per-module synthesis for incremental FPGA builds
+pub mod synth_cache;
+/// The bundled testbench library
+pub mod tblib;
+/// The testbench runner
+pub mod test;
+/// Each FPGA build's timing, archived for comparison
+pub mod timing;
+/// The saved output of the tools a command runs
+pub mod tool_log;
+/// Value Change Dump parsing
+pub mod vcd;
+/// Lightweight Verilog source scanning
+pub mod verilog;
+/// Host-side waveform viewers
+pub mod viewer;
+/// Waveform assertions declared next to testbenches
+pub mod wavecheck;
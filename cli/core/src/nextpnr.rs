@@ -0,0 +1,165 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::build::NEXTPNR_LOG;
+
+/// Cells of one kind the design uses, out of those on the device
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    pub used: u64,
+    pub total: u64,
+}
+
+impl Usage {
+    pub fn percent(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.used as f64 * 100.0 / self.total as f64
+        }
+    }
+}
+
+/// What nextpnr reported about the last place-and-route
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Report {
+    /// Device utilisation by cell type, e.g. ICESTORM_LC
+    pub utilization: BTreeMap<String, Usage>,
+    /// Achieved maximum frequency of each clock, in MHz
+    pub fmax_mhz: BTreeMap<String, f64>,
+    /// Frequency each clock is constrained to, in MHz
+    #[serde(default)]
+    pub target_mhz: BTreeMap<String, f64>,
+    /// The slowest path clocked by each clock
+    #[serde(default)]
+    pub critical_paths: BTreeMap<String, CriticalPath>,
+}
+
+/// The longest register-to-register path of one clock domain
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CriticalPath {
+    /// The cell port the path starts from
+    pub from: String,
+    /// The cell port the path ends at
+    pub to: String,
+    /// Delay through cells, in ns
+    pub logic_ns: f64,
+    /// Delay through routing, in ns
+    pub routing_ns: f64,
+}
+
+impl CriticalPath {
+    pub fn delay_ns(&self) -> f64 {
+        self.logic_ns + self.routing_ns
+    }
+}
+
+impl Report {
+    /// Read the report from the last build's log, if there is one
+    pub fn load(project_root: &Path) -> Option<Self> {
+        fs::read_to_string(project_root.join(NEXTPNR_LOG))
+            .ok()
+            .map(|log| Self::parse(&log))
+    }
+
+    /// Parse a nextpnr log. Utilisation and timing are printed after placement
+    /// and again after routing; the last figures win.
+    pub fn parse(log: &str) -> Self {
+        let mut report = Report::default();
+        let mut in_utilization = false;
+        let mut path: Option<(String, CriticalPath)> = None;
+        for line in log.lines() {
+            let text = line.trim_start_matches("Info:").trim();
+            // Critical path report for clock 'clk' (posedge -> posedge):
+            if let Some(rest) = text.strip_prefix("Critical path report for clock '") {
+                path = rest
+                    .split_once('\'')
+                    .map(|(clock, _)| (clock.to_string(), CriticalPath::default()));
+                continue;
+            }
+            if let Some((clock, critical)) = &mut path {
+                // Each step names a cell port after Source, Sink or Setup;
+                // the path ends with "3.4 ns logic, 5.5 ns routing"
+                if let Some((logic, routing)) = text.split_once(" ns logic, ") {
+                    critical.logic_ns = logic.trim().parse().unwrap_or_default();
+                    critical.routing_ns = routing
+                        .split_whitespace()
+                        .next()
+                        .and_then(|ns| ns.parse().ok())
+                        .unwrap_or_default();
+                    report
+                        .critical_paths
+                        .insert(std::mem::take(clock), std::mem::take(critical));
+                    path = None;
+                    continue;
+                }
+                let mut words = text.split_whitespace();
+                while let Some(word) = words.next() {
+                    match word {
+                        "Source" if critical.from.is_empty() => {
+                            critical.from = words.next().unwrap_or_default().to_string();
+                        }
+                        "Sink" | "Setup" => {
+                            critical.to = words.next().unwrap_or_default().to_string();
+                        }
+                        _ => continue,
+                    }
+                    break;
+                }
+                continue;
+            }
+            if text.starts_with("Device utilisation") {
+                in_utilization = true;
+                continue;
+            }
+            if in_utilization {
+                match parse_usage(text) {
+                    Some((cell, usage)) => {
+                        report.utilization.insert(cell, usage);
+                        continue;
+                    }
+                    None => in_utilization = false,
+                }
+            }
+            // Max frequency for clock 'clk$SB_IO_IN_$glb_clk': 52.34 MHz (PASS at 12.00 MHz)
+            if let Some(rest) = text.strip_prefix("Max frequency for clock") {
+                let Some((clock, rest)) = rest.trim().rsplit_once("':") else {
+                    continue;
+                };
+                let clock = clock.trim_start_matches('\'').to_string();
+                if let Some(mhz) = rest
+                    .split_whitespace()
+                    .next()
+                    .and_then(|mhz| mhz.parse().ok())
+                {
+                    report.fmax_mhz.insert(clock.clone(), mhz);
+                }
+                // "(PASS at 12.00 MHz)"
+                if let Some(target) = rest
+                    .split_once(" at ")
+                    .and_then(|(_, target)| target.split_whitespace().next())
+                    .and_then(|mhz| mhz.parse().ok())
+                {
+                    report.target_mhz.insert(clock, target);
+                }
+            }
+        }
+        report
+    }
+}
+
+/// "ICESTORM_LC:   184/ 5280     3%"
+fn parse_usage(text: &str) -> Option<(String, Usage)> {
+    let (cell, counts) = text.split_once(':')?;
+    let (used, rest) = counts.split_once('/')?;
+    let total = rest.split_whitespace().next()?;
+    Some((
+        cell.trim().to_string(),
+        Usage {
+            used: used.trim().parse().ok()?,
+            total: total.parse().ok()?,
+        },
+    ))
+}
@@ -0,0 +1,188 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fmt;
+use std::io::{self, IsTerminal, Write};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Mutex;
+
+static JSON: AtomicBool = AtomicBool::new(false);
+static COLOR: AtomicBool = AtomicBool::new(true);
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+/// Structured results collected while a command runs, emitted by [`finish`]
+static REPORT: Mutex<Option<Map<String, Value>>> = Mutex::new(None);
+
+/// Print a human-readable line: to stdout normally, to stderr in JSON mode
+#[macro_export]
+macro_rules! outln {
+    () => {
+        $crate::output::write_line(format_args!(""))
+    };
+    ($($arg:tt)*) => {
+        $crate::output::write_line(format_args!($($arg)*))
+    };
+}
+
+/// Like [`outln!`] without the newline, e.g. for a status finished later on the same line
+#[macro_export]
+macro_rules! out {
+    ($($arg:tt)*) => {
+        $crate::output::write(format_args!($($arg)*))
+    };
+}
+
+/// How much of the tools' output (yosys, nextpnr, idf.py) is shown. The full
+/// output is saved to the tool log whatever the level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// `-q`: no tool output, only the final status (and the end of the output on failure)
+    Quiet,
+    /// Tool output without nextpnr's step-by-step info
+    Normal,
+    /// `-v`: all tool output, and debug logs
+    Verbose,
+    /// `-vv`: all tool output, and trace logs including captured container output
+    Trace,
+}
+
+impl Verbosity {
+    /// The level for `-q` and a count of `-v` flags
+    pub fn from_flags(quiet: bool, verbose: u8) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, 0) => Verbosity::Normal,
+            (false, 1) => Verbosity::Verbose,
+            (false, _) => Verbosity::Trace,
+        }
+    }
+}
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::SeqCst);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::SeqCst) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        2 => Verbosity::Verbose,
+        _ => Verbosity::Trace,
+    }
+}
+
+/// When to color output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum ColorChoice {
+    /// Color when writing to a terminal, unless NO_COLOR is set
+    #[default]
+    Auto,
+    /// Always color, even when piped
+    Always,
+    /// Never color
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether to color a stream, given whether it is a terminal
+    pub fn enabled(self, terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                terminal && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+            }
+        }
+    }
+}
+
+/// Decide whether to color human output, for the `colored` crate and progress bars.
+/// Call after [`set_json`], which moves human output to stderr.
+pub fn set_color(choice: ColorChoice) {
+    let terminal = if is_json() {
+        io::stderr().is_terminal()
+    } else {
+        io::stdout().is_terminal()
+    };
+    let enabled = choice.enabled(terminal);
+    COLOR.store(enabled, Ordering::SeqCst);
+    colored::control::set_override(enabled);
+}
+
+pub fn color_enabled() -> bool {
+    COLOR.load(Ordering::SeqCst)
+}
+
+/// Switch to JSON mode: human output goes to stderr and stdout carries only
+/// the JSON document written by [`finish`]
+pub fn set_json(enabled: bool) {
+    JSON.store(enabled, Ordering::SeqCst);
+}
+
+pub fn is_json() -> bool {
+    JSON.load(Ordering::SeqCst)
+}
+
+#[doc(hidden)]
+pub fn write_line(args: fmt::Arguments) {
+    if is_json() {
+        eprintln!("{}", args);
+    } else {
+        println!("{}", args);
+    }
+}
+
+#[doc(hidden)]
+pub fn write(args: fmt::Arguments) {
+    if is_json() {
+        eprint!("{}", args);
+    } else {
+        print!("{}", args);
+        let _ = io::stdout().flush();
+    }
+}
+
+/// Where child processes should send their standard output
+pub fn child_stdout() -> Stdio {
+    if is_json() {
+        Stdio::from(io::stderr())
+    } else {
+        Stdio::inherit()
+    }
+}
+
+/// Add a result to the command's JSON output under `key`. Does nothing outside JSON mode.
+pub fn report(key: &str, value: impl Serialize) {
+    if !is_json() {
+        return;
+    }
+    let value = serde_json::to_value(value).unwrap_or(Value::Null);
+    REPORT
+        .lock()
+        .unwrap()
+        .get_or_insert_with(Map::new)
+        .insert(key.to_string(), value);
+}
+
+/// Write the command's JSON document to stdout: the command name, whether it
+/// succeeded, the error if it didn't, and everything passed to [`report`]
+pub fn finish(command: &str, error: Option<&anyhow::Error>) {
+    if !is_json() {
+        return;
+    }
+    let mut document = Map::new();
+    document.insert("command".to_string(), command.into());
+    document.insert("success".to_string(), error.is_none().into());
+    if let Some(error) = error {
+        document.insert("error".to_string(), crate::error::message(error).into());
+        document.insert(
+            "exit_code".to_string(),
+            crate::error::exit_code(error).into(),
+        );
+    }
+    if let Some(report) = REPORT.lock().unwrap().take() {
+        document.extend(report);
+    }
+    println!("{}", Value::Object(document));
+}
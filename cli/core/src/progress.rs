@@ -0,0 +1,203 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use std::collections::BTreeSet;
+use std::io::{self, IsTerminal};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::dry_run;
+use crate::output::{self, Verbosity};
+
+/// Whether to draw progress indicators: only on a terminal, and never in a dry run
+pub fn enabled() -> bool {
+    io::stderr().is_terminal() && !dry_run::is_enabled()
+}
+
+/// A spinner under a tool's streamed output, showing the stage the tool has
+/// reached (recognized from its output) and how long it has been running.
+/// Hidden when progress is disabled; output lines are printed either way.
+pub struct Progress {
+    bar: ProgressBar,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    stage: String,
+    /// Image layers seen and finished by `docker pull`
+    layers: BTreeSet<String>,
+    pulled: BTreeSet<String>,
+}
+
+/// What a line of tool output says about progress
+enum Update<'a> {
+    /// A script's `echo "Doing something..."`
+    Stage(&'a str),
+    /// A step within the current stage, e.g. nextpnr moving from placing to routing
+    Detail(&'static str),
+    /// ninja's `[done/total]`, as printed by `idf.py build`, or esptool's percentage
+    Step(u64, u64),
+    /// `docker pull` reporting on a layer
+    Layer { id: &'a str, complete: bool },
+}
+
+impl Progress {
+    pub fn new(message: &str) -> Self {
+        let bar = if enabled() {
+            ProgressBar::new_spinner().with_style(spinner_style())
+        } else {
+            ProgressBar::hidden()
+        };
+        bar.set_message(message.to_string());
+        bar.enable_steady_tick(Duration::from_millis(100));
+        Progress {
+            bar,
+            state: Mutex::new(State {
+                stage: message.to_string(),
+                ..State::default()
+            }),
+        }
+    }
+
+    /// Track a line of the tool's output and print it above the spinner, if
+    /// the verbosity shows it. `docker pull`'s per-layer lines are summarized
+    /// by the bar instead.
+    pub fn line(&self, line: &str, stderr: bool) {
+        let update = parse(line);
+        if let Some(update) = &update {
+            self.update(update);
+        }
+        let shown = match output::verbosity() {
+            Verbosity::Quiet => false,
+            Verbosity::Normal => match update {
+                Some(Update::Layer { .. }) => false,
+                // The bar counts the steps
+                Some(Update::Step(..)) => self.bar.is_hidden(),
+                _ => !is_detail(line),
+            },
+            Verbosity::Verbose | Verbosity::Trace => !matches!(update, Some(Update::Layer { .. })),
+        };
+        if !shown {
+            return;
+        }
+        self.bar.suspend(|| {
+            if stderr {
+                eprintln!("{}", line);
+            } else {
+                outln!("{}", line);
+            }
+        });
+    }
+
+    fn update(&self, update: &Update) {
+        let mut state = self.state.lock().unwrap();
+        match *update {
+            Update::Stage(stage) => {
+                state.stage = stage.to_string();
+                self.bar.set_style(spinner_style());
+                self.bar.set_message(stage.to_string());
+            }
+            Update::Detail(detail) => {
+                self.bar
+                    .set_message(format!("{} ({})", state.stage, detail));
+            }
+            Update::Step(done, total) => self.steps(done, total),
+            Update::Layer { id, complete } => {
+                state.layers.insert(id.to_string());
+                if complete {
+                    state.pulled.insert(id.to_string());
+                }
+                self.steps(state.pulled.len() as u64, state.layers.len() as u64);
+            }
+        }
+    }
+
+    fn steps(&self, done: u64, total: u64) {
+        if self.bar.length() != Some(total) {
+            self.bar.set_style(bar_style());
+            self.bar.set_length(total);
+        }
+        self.bar.set_position(done);
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+fn spinner_style() -> ProgressStyle {
+    let template = if output::color_enabled() {
+        "{spinner:.blue} {msg} {elapsed:.dim}"
+    } else {
+        "{spinner} {msg} {elapsed}"
+    };
+    ProgressStyle::with_template(template).unwrap()
+}
+
+fn bar_style() -> ProgressStyle {
+    let template = if output::color_enabled() {
+        "{spinner:.blue} {msg} [{bar:30.cyan/blue}] {pos}/{len} {elapsed:.dim}"
+    } else {
+        "{spinner} {msg} [{bar:30}] {pos}/{len} {elapsed}"
+    };
+    ProgressStyle::with_template(template)
+        .unwrap()
+        .progress_chars("=> ")
+}
+
+/// nextpnr's step-by-step info, hidden at normal verbosity; its timing and
+/// utilisation summaries are kept
+fn is_detail(line: &str) -> bool {
+    let line = line.trim();
+    line.starts_with("Info: ") && !line.contains("Max frequency") && !line.ends_with('%')
+}
+
+fn parse(line: &str) -> Option<Update<'_>> {
+    let trimmed = line.trim();
+
+    // Stage announcements echoed by the build scripts
+    if let Some(stage) = trimmed.strip_suffix("...") {
+        if !stage.is_empty() && !stage.contains("...") && !stage.starts_with("Info:") {
+            return Some(Update::Stage(stage));
+        }
+    }
+
+    // nextpnr
+    if let Some(info) = trimmed.strip_prefix("Info: ") {
+        const STEPS: &[(&str, &str)] = &[
+            ("Packing", "packing"),
+            ("Running main analytical placer", "placing"),
+            ("Running simulated annealing placer", "refining placement"),
+            ("Routing", "routing"),
+            ("Critical path report", "timing analysis"),
+        ];
+        return STEPS
+            .iter()
+            .find(|(prefix, _)| info.starts_with(prefix))
+            .map(|(_, detail)| Update::Detail(detail));
+    }
+
+    // ninja: [12/845] Building C object ...
+    if let Some((counts, _)) = trimmed.strip_prefix('[').and_then(|r| r.split_once(']')) {
+        if let Some((done, total)) = counts.split_once('/') {
+            if let (Ok(done), Ok(total)) = (done.parse(), total.parse()) {
+                return Some(Update::Step(done, total));
+            }
+        }
+    }
+
+    // esptool: Writing at 0x00010000... (12 %)
+    if trimmed.starts_with("Writing at 0x") {
+        let percent = trimmed.rsplit_once('(')?.1.strip_suffix("%)")?.trim();
+        return Some(Update::Step(percent.parse().ok()?, 100));
+    }
+
+    // docker pull: 4f4fb700ef54: Pull complete
+    let (id, status) = trimmed.split_once(": ")?;
+    if id.len() == 12 && id.chars().all(|c| c.is_ascii_hexdigit()) {
+        let complete = status == "Pull complete" || status == "Already exists";
+        return Some(Update::Layer { id, complete });
+    }
+    None
+}
@@ -0,0 +1,1620 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::error::Failure;
+use crate::{debug_console, dry_run};
+
+/// Project configuration from affogato.toml
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ProjectConfig {
+    #[serde(default)]
+    pub project: ProjectSection,
+    #[serde(default)]
+    pub fpga: FpgaConfig,
+    #[allow(dead_code)]
+    #[serde(default)]
+    pub firmware: FirmwareConfig,
+    #[serde(default)]
+    pub test: TestConfig,
+    /// Formal verification tasks from `[formal.<name>]` tables
+    #[serde(default)]
+    pub formal: BTreeMap<String, FormalTask>,
+    #[serde(default)]
+    pub lint: LintConfig,
+    #[serde(default)]
+    pub watch: WatchConfig,
+    /// Git hook commands from `[hooks]`, installed by `affogato hooks install`
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    /// RISC-V soft-core program from `[softcore]`, built into the bitstream's BRAM
+    #[serde(default)]
+    pub softcore: Option<SoftcoreConfig>,
+    #[serde(default)]
+    pub hwtest: HwtestConfig,
+    #[serde(default)]
+    pub la: LaConfig,
+    /// Firmware/FPGA co-simulation settings from `[cosim]`
+    #[serde(default)]
+    pub cosim: CosimConfig,
+    /// Pins and clocks of the `ice40` component's loader from `[loader]`
+    #[serde(default)]
+    pub loader: LoaderConfig,
+    /// Constants from `[shared]`, written to both a Verilog and a C header
+    #[serde(default)]
+    pub shared: BTreeMap<String, SharedConstant>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ProjectSection {
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FpgaConfig {
+    #[serde(default = "default_device")]
+    pub device: String,
+    #[serde(default = "default_package")]
+    pub package: String,
+    #[serde(default = "default_top")]
+    pub top: String,
+    #[serde(default)]
+    pub pcf: Option<String>,
+    /// Profiles whose constraint fragments apply when `--profile` picks none
+    #[serde(default)]
+    pub profiles: Vec<String>,
+    /// Pin constraint fragments by profile, from `[fpga.constraints]`, merged
+    /// over `pcf` when their profile is selected
+    #[serde(default)]
+    pub constraints: BTreeMap<String, Vec<String>>,
+    /// Additional Verilog files/directories to include
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// HDL dependencies from `[fpga.deps]`, vendored by `affogato deps fetch`
+    #[serde(default)]
+    pub deps: BTreeMap<String, HdlDep>,
+    /// Bus fabric from `[fpga.bus]`, generated by `affogato gen bus`
+    #[serde(default)]
+    pub bus: Option<BusConfig>,
+    /// Amaranth designs from `[fpga.amaranth]`, elaborated to Verilog before synthesis
+    #[serde(default)]
+    pub amaranth: Option<AmaranthConfig>,
+    /// nextpnr placement seed, pinned so the same netlist always places the same way
+    #[serde(default = "default_seed")]
+    pub seed: u64,
+    /// Where the FPGA gets its configuration at power-up
+    #[serde(default)]
+    pub boot: FpgaBoot,
+    /// Synthesize the top's submodules separately, reusing cached netlists of
+    /// unchanged ones
+    #[serde(default)]
+    pub incremental: bool,
+    /// Python scripts nextpnr runs during place and route, from `[fpga.pnr]`
+    #[serde(default)]
+    pub pnr: PnrConfig,
+    /// Synthesis options from `[fpga.synth]`
+    #[serde(default)]
+    pub synth: SynthConfig,
+}
+
+/// How the design is synthesized
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SynthConfig {
+    /// Yosys script replacing the built-in synthesis, relative to the project
+    /// root. `${TOP}`, `${SOURCES}` and `${JSON}` in it are replaced by the top
+    /// module, the Verilog files and the netlist nextpnr reads.
+    #[serde(default)]
+    pub script: Option<String>,
+}
+
+/// Python scripts run inside nextpnr at points in its flow, with its `ctx`
+/// in scope, e.g. to constrain specific nets or lock cells to locations.
+/// Paths are relative to the project root.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PnrConfig {
+    /// Before packing, with the synthesized netlist loaded
+    #[serde(default)]
+    pub pre_pack: Option<String>,
+    /// After packing, before placement
+    #[serde(default)]
+    pub pre_place: Option<String>,
+    /// After placement, before routing
+    #[serde(default)]
+    pub pre_route: Option<String>,
+    /// After routing, before the design is written
+    #[serde(default)]
+    pub post_route: Option<String>,
+}
+
+impl PnrConfig {
+    /// Each configured script with the nextpnr option that runs it
+    pub fn hooks(&self) -> Vec<(&'static str, &str)> {
+        [
+            ("--pre-pack", &self.pre_pack),
+            ("--pre-place", &self.pre_place),
+            ("--pre-route", &self.pre_route),
+            ("--post-route", &self.post_route),
+        ]
+        .into_iter()
+        .filter_map(|(option, script)| Some((option, script.as_deref()?)))
+        .collect()
+    }
+}
+
+/// How the board configures the FPGA
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FpgaBoot {
+    /// The ESP32 loads the configuration RAM over SPI on every boot
+    #[default]
+    Cram,
+    /// The FPGA boots from an SPI configuration flash, which the ESP32 (or
+    /// `affogato fpga --iceprog`) writes
+    Flash,
+}
+
+/// Python HDL designs written with Amaranth
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AmaranthConfig {
+    /// Directory added to the Python path, holding the designs and their simulations
+    #[serde(default = "default_amaranth_path")]
+    pub path: String,
+    /// Elaboratables to convert, as "module:Class"
+    #[serde(default)]
+    pub designs: Vec<String>,
+}
+
+fn default_amaranth_path() -> String {
+    "fpga/amaranth".to_string()
+}
+
+/// A register bus connecting the SPI bridge to the project's peripherals
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BusConfig {
+    /// Module and header name
+    #[serde(default = "default_bus_name")]
+    pub name: String,
+    #[serde(default, rename = "peripheral")]
+    pub peripherals: Vec<BusPeripheral>,
+}
+
+/// A peripheral on the bus, from `[[fpga.bus.peripheral]]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BusPeripheral {
+    pub name: String,
+    /// First word address (default: the next free address aligned to `size`)
+    #[serde(default)]
+    pub base: Option<u32>,
+    /// Address space in 16-bit words, a power of two
+    #[serde(default = "default_bus_size")]
+    pub size: u32,
+}
+
+fn default_bus_name() -> String {
+    "bus_fabric".to_string()
+}
+
+fn default_bus_size() -> u32 {
+    16
+}
+
+/// A Verilog dependency fetched from git
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HdlDep {
+    pub git: String,
+    /// Branch, tag or commit (default branch if unset)
+    #[serde(default)]
+    pub rev: Option<String>,
+    /// Subdirectory of the repository to vendor (e.g. "rtl")
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Files or globs within `path` to vendor (default: everything)
+    #[serde(default)]
+    pub files: Vec<String>,
+}
+
+fn default_device() -> String {
+    "up5k".to_string()
+}
+
+fn default_package() -> String {
+    "sg48".to_string()
+}
+
+fn default_top() -> String {
+    "top".to_string()
+}
+
+impl Default for FpgaConfig {
+    fn default() -> Self {
+        Self {
+            device: default_device(),
+            package: default_package(),
+            top: default_top(),
+            pcf: None,
+            profiles: Vec::new(),
+            constraints: BTreeMap::new(),
+            include: Vec::new(),
+            deps: BTreeMap::new(),
+            bus: None,
+            amaranth: None,
+            seed: default_seed(),
+            boot: FpgaBoot::default(),
+            incremental: false,
+            pnr: PnrConfig::default(),
+            synth: SynthConfig::default(),
+        }
+    }
+}
+
+fn default_seed() -> u64 {
+    1
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct FirmwareConfig {
+    #[allow(dead_code)]
+    #[serde(default)]
+    pub project_name: Option<String>,
+    /// Generate the `affogato_console.h` debug console with every build
+    #[serde(default)]
+    pub debug_console: bool,
+    /// How the bitstream reaches the device with the firmware
+    #[serde(default)]
+    pub bitstream: BitstreamDelivery,
+}
+
+/// Where the firmware finds the bitstream it loads into the FPGA
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BitstreamDelivery {
+    /// Linked into the application image with target_add_binary_data
+    #[default]
+    Embed,
+    /// A file in a SPIFFS partition, flashed with the application
+    Spiffs,
+    /// A file in a LittleFS partition, using the joltwallet/littlefs component
+    Littlefs,
+    /// A raw data partition of its own, outside the application image
+    Partition,
+}
+
+/// Board self-test settings from the `[hwtest]` section
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct HwtestConfig {
+    /// ESP32 GPIO pairs wired together on the test fixture, as [output, input]
+    #[serde(default)]
+    pub gpio_loopbacks: Vec<[u32; 2]>,
+}
+
+/// Logic analyzer settings from the `[la]` section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LaConfig {
+    /// sigrok driver for the analyzer, with its connection if needed
+    /// (e.g. "fx2lafw" or "saleae-logic16"); sigrok-cli's default if unset
+    #[serde(default)]
+    pub driver: Option<String>,
+    /// Sample rate, in Hz or with a k/M suffix
+    #[serde(default = "default_la_samplerate")]
+    pub samplerate: String,
+    /// Analyzer channel each board signal is clipped to, e.g. FSPI_CLK = "D0"
+    #[serde(default = "default_la_probes")]
+    pub probes: BTreeMap<String, String>,
+}
+
+fn default_la_samplerate() -> String {
+    "24M".to_string()
+}
+
+/// The SPI lines between the ESP32 and the FPGA on D0-D3
+fn default_la_probes() -> BTreeMap<String, String> {
+    [
+        ("FSPI_CLK", "D0"),
+        ("FSPI_MOSI", "D1"),
+        ("FSPI_MISO", "D2"),
+        ("FSPI_CS", "D3"),
+    ]
+    .into_iter()
+    .map(|(signal, channel)| (signal.to_string(), channel.to_string()))
+    .collect()
+}
+
+impl Default for LaConfig {
+    fn default() -> Self {
+        Self {
+            driver: None,
+            samplerate: default_la_samplerate(),
+            probes: default_la_probes(),
+        }
+    }
+}
+
+/// Co-simulation settings from the `[cosim]` section: which Verilator model
+/// the firmware's SPI driver talks to, and through which ports
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CosimConfig {
+    /// Top module to simulate; `[fpga] top` if unset
+    #[serde(default)]
+    pub top: Option<String>,
+    /// Directory of C test files, relative to the project root
+    #[serde(default = "default_cosim_tests")]
+    pub tests: String,
+    /// Top-level ports wired to the SPI master; chip select is active low
+    #[serde(default = "default_cosim_sck")]
+    pub sck: String,
+    #[serde(default = "default_cosim_mosi")]
+    pub mosi: String,
+    #[serde(default = "default_cosim_miso")]
+    pub miso: String,
+    #[serde(default = "default_cosim_cs")]
+    pub cs: String,
+    /// Clock input port to drive, for designs not clocked by SB_HFOSC
+    #[serde(default)]
+    pub clock: Option<String>,
+    /// Frequency of `clock`, in MHz
+    #[serde(default = "default_cosim_clock_mhz")]
+    pub clock_mhz: f64,
+    /// SPI clock, in Hz
+    #[serde(default = "default_cosim_spi_hz")]
+    pub spi_hz: u32,
+}
+
+fn default_cosim_tests() -> String {
+    "cosim".to_string()
+}
+
+fn default_cosim_sck() -> String {
+    "FSPI_CLK".to_string()
+}
+
+fn default_cosim_mosi() -> String {
+    "FSPI_MOSI".to_string()
+}
+
+fn default_cosim_miso() -> String {
+    "FSPI_MISO".to_string()
+}
+
+fn default_cosim_cs() -> String {
+    "FSPI_CS".to_string()
+}
+
+fn default_cosim_clock_mhz() -> f64 {
+    12.0
+}
+
+fn default_cosim_spi_hz() -> u32 {
+    1_000_000
+}
+
+impl Default for CosimConfig {
+    fn default() -> Self {
+        Self {
+            top: None,
+            tests: default_cosim_tests(),
+            sck: default_cosim_sck(),
+            mosi: default_cosim_mosi(),
+            miso: default_cosim_miso(),
+            cs: default_cosim_cs(),
+            clock: None,
+            clock_mhz: default_cosim_clock_mhz(),
+            spi_hz: default_cosim_spi_hz(),
+        }
+    }
+}
+
+/// Settings for the `ice40` ESP-IDF component from the `[loader]` section,
+/// written to the firmware's sdkconfig at build time. Unset ones keep their
+/// menuconfig values.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct LoaderConfig {
+    /// SPI peripheral the FPGA is on: 2 (SPI2/FSPI) or 3 (SPI3)
+    #[serde(default)]
+    pub spi_host: Option<i64>,
+    /// ESP32 GPIOs wired to the FPGA
+    #[serde(default)]
+    pub cs: Option<i64>,
+    #[serde(default)]
+    pub clk: Option<i64>,
+    #[serde(default)]
+    pub mosi: Option<i64>,
+    #[serde(default)]
+    pub miso: Option<i64>,
+    /// QSPI write protect and hold, or -1 for none
+    #[serde(default)]
+    pub wp: Option<i64>,
+    #[serde(default)]
+    pub hd: Option<i64>,
+    #[serde(default)]
+    pub creset: Option<i64>,
+    #[serde(default)]
+    pub cdone: Option<i64>,
+    /// SPI clock while loading the bitstream, in MHz
+    #[serde(default)]
+    pub programming_mhz: Option<i64>,
+    /// SPI clock for talking to the running design, in MHz
+    #[serde(default)]
+    pub comms_mhz: Option<i64>,
+}
+
+/// A `[shared]` constant: a plain integer, or an unsigned value of a given
+/// bit width
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SharedConstant {
+    Value(i64),
+    Sized { value: i64, width: u32 },
+}
+
+/// Testbench runner settings from the `[test]` section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestConfig {
+    /// Simulation timeout applied to every testbench (e.g. "60s", "2m")
+    #[serde(default = "default_test_timeout")]
+    pub timeout: String,
+    /// Wall-clock cap on each test's container, including compilation
+    #[serde(default = "default_wall_clock")]
+    pub wall_clock: String,
+    /// Minimum merged coverage percentage for `affogato test --coverage`
+    #[serde(default)]
+    pub min_coverage: Option<f64>,
+    /// Waveform format written by `affogato test --waves`
+    #[serde(default)]
+    pub wave_format: WaveFormat,
+    /// Waveform viewer for `--view` ("gtkwave" or "surfer"; auto-detected if unset)
+    #[serde(default)]
+    pub viewer: Option<String>,
+    /// Per-testbench overrides from `[test.<name>]` tables
+    #[serde(flatten)]
+    pub tests: BTreeMap<String, TestbenchConfig>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WaveFormat {
+    #[default]
+    Vcd,
+    /// Compressed format, much smaller for long simulations
+    Fst,
+}
+
+impl WaveFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            WaveFormat::Vcd => "vcd",
+            WaveFormat::Fst => "fst",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct TestbenchConfig {
+    #[serde(default)]
+    pub timeout: Option<String>,
+    /// Tags used by `--tag` / `--skip-tag`, merged with `// affogato-tags:` headers
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Signal globs compared against the golden waveform (all signals if empty)
+    #[serde(default)]
+    pub compare: Vec<String>,
+    /// Extra simulator flags passed to iverilog/verilator
+    #[serde(default)]
+    pub flags: Vec<String>,
+    /// Preprocessor defines ("NAME" or "NAME=VALUE")
+    #[serde(default)]
+    pub defines: Vec<String>,
+    /// Runtime plusargs ("verbose" or "seed=3"), without the leading `+`
+    #[serde(default)]
+    pub plusargs: Vec<String>,
+    /// RTL files, directories or globs to compile instead of all of fpga/rtl
+    #[serde(default)]
+    pub sources: Vec<String>,
+}
+
+fn default_test_timeout() -> String {
+    "60s".to_string()
+}
+
+fn default_wall_clock() -> String {
+    "10m".to_string()
+}
+
+impl Default for TestConfig {
+    fn default() -> Self {
+        Self {
+            timeout: default_test_timeout(),
+            wall_clock: default_wall_clock(),
+            min_coverage: None,
+            wave_format: WaveFormat::default(),
+            viewer: None,
+            tests: BTreeMap::new(),
+        }
+    }
+}
+
+impl TestConfig {
+    /// Resolve the simulation timeout for a testbench
+    pub fn timeout_for(&self, test_name: &str) -> Result<Duration> {
+        let timeout = self
+            .tests
+            .get(test_name)
+            .and_then(|t| t.timeout.as_deref())
+            .unwrap_or(&self.timeout);
+        humantime::parse_duration(timeout)
+            .with_context(|| format!("Invalid test timeout for {}: {:?}", test_name, timeout))
+    }
+
+    /// Wall-clock limit for a test's container, never shorter than its simulation timeout
+    pub fn wall_clock_for(&self, test_name: &str) -> Result<Duration> {
+        let wall_clock = humantime::parse_duration(&self.wall_clock)
+            .with_context(|| format!("Invalid test wall_clock: {:?}", self.wall_clock))?;
+        // Leave room for compilation and the simulator's own --kill-after
+        let minimum = self.timeout_for(test_name)? + Duration::from_secs(30);
+        Ok(wall_clock.max(minimum))
+    }
+}
+
+/// Soft-core program settings from the `[softcore]` section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SoftcoreConfig {
+    /// Program sources (`*.c`, `*.S`) and linker script (`sections.ld`)
+    #[serde(default = "default_softcore_dir")]
+    pub dir: String,
+    /// Size of the program memory in 32-bit words, as instantiated in the RTL
+    #[serde(default = "default_softcore_words")]
+    pub words: u32,
+    /// Instruction set passed to `-march`
+    #[serde(default = "default_softcore_arch")]
+    pub arch: String,
+}
+
+fn default_softcore_dir() -> String {
+    "softcore".to_string()
+}
+
+fn default_softcore_words() -> u32 {
+    1024
+}
+
+fn default_softcore_arch() -> String {
+    "rv32i".to_string()
+}
+
+/// Watch mode settings from the `[watch]` section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchConfig {
+    /// Files, directories or globs whose changes never trigger a rebuild
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    /// Files or directories to watch in addition to fpga/ and firmware/
+    #[serde(default)]
+    pub extra_paths: Vec<String>,
+    /// Show a desktop notification when a build or test run finishes
+    #[serde(default = "default_true")]
+    pub notify: bool,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            ignore: Vec::new(),
+            extra_paths: Vec::new(),
+            notify: true,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Commands the git hooks run, from the `[hooks]` section; an empty list
+/// leaves that hook uninstalled
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HooksConfig {
+    #[serde(default = "default_pre_commit")]
+    pub pre_commit: Vec<String>,
+    #[serde(default = "default_pre_push")]
+    pub pre_push: Vec<String>,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            pre_commit: default_pre_commit(),
+            pre_push: default_pre_push(),
+        }
+    }
+}
+
+fn default_pre_commit() -> Vec<String> {
+    vec![
+        "affogato fmt --check".to_string(),
+        "affogato lint --diff".to_string(),
+    ]
+}
+
+fn default_pre_push() -> Vec<String> {
+    vec!["affogato check".to_string()]
+}
+
+/// Lint settings from the `[lint]` section
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct LintConfig {
+    #[serde(default)]
+    pub tool: LintTool,
+    /// Per-rule settings, keyed by verilator warning code or verible rule name
+    #[serde(default)]
+    pub rules: BTreeMap<String, RuleSetting>,
+    /// Extra arguments passed to the lint tool (e.g. "-Wno-DECLFILENAME")
+    #[serde(default)]
+    pub flags: Vec<String>,
+    /// Include directories for `include files
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Files, directories or globs not to lint (generated code, third_party)
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Rules to ignore in particular files, keyed by path glob
+    #[serde(default)]
+    pub disable: BTreeMap<String, Vec<String>>,
+}
+
+impl LintConfig {
+    pub fn is_excluded(&self, file: &str) -> bool {
+        self.exclude.iter().any(|pattern| {
+            let dir = pattern.trim_end_matches('/');
+            file == dir
+                || file.starts_with(&format!("{}/", dir))
+                || glob::Pattern::new(pattern).is_ok_and(|p| p.matches(file))
+        })
+    }
+
+    /// Whether a rule is disabled for a file by `[lint.disable]`
+    pub fn is_disabled(&self, file: &str, rule: &str) -> bool {
+        self.disable.iter().any(|(pattern, rules)| {
+            rules.iter().any(|r| r == rule)
+                && glob::Pattern::new(pattern).is_ok_and(|p| p.matches(file))
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum LintTool {
+    #[default]
+    Verilator,
+    /// Style and naming rules from verible-verilog-lint
+    Verible,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Off,
+    Warning,
+    Error,
+}
+
+/// A rule's severity, optionally with verible rule parameters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RuleSetting {
+    Severity(Severity),
+    /// e.g. `line-length = { severity = "warning", config = "length:120" }`
+    Detailed {
+        severity: Severity,
+        #[serde(default)]
+        config: Option<String>,
+    },
+}
+
+impl RuleSetting {
+    pub fn severity(&self) -> Severity {
+        match self {
+            RuleSetting::Severity(severity) => *severity,
+            RuleSetting::Detailed { severity, .. } => *severity,
+        }
+    }
+
+    pub fn config(&self) -> Option<&str> {
+        match self {
+            RuleSetting::Severity(_) => None,
+            RuleSetting::Detailed { config, .. } => config.as_deref(),
+        }
+    }
+}
+
+/// A SymbiYosys task generated from affogato.toml
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FormalTask {
+    /// "prove" (k-induction), "bmc", "cover" or "live"
+    #[serde(default = "default_formal_mode")]
+    pub mode: String,
+    #[serde(default = "default_formal_depth")]
+    pub depth: u32,
+    #[serde(default = "default_formal_engine")]
+    pub engine: String,
+    /// Top module (defaults to the task name)
+    #[serde(default)]
+    pub top: Option<String>,
+    /// RTL files or directories to read (defaults to all of fpga/rtl)
+    #[serde(default)]
+    pub sources: Vec<String>,
+    #[serde(default)]
+    pub defines: Vec<String>,
+}
+
+fn default_formal_mode() -> String {
+    "prove".to_string()
+}
+
+fn default_formal_depth() -> u32 {
+    20
+}
+
+fn default_formal_engine() -> String {
+    "smtbmc".to_string()
+}
+
+impl ProjectConfig {
+    /// Load project config from affogato.toml
+    pub fn load(project_root: &Path) -> Result<Self> {
+        let config_path = project_root.join("affogato.toml");
+        if config_path.exists() {
+            let content = fs::read_to_string(&config_path)?;
+            Ok(toml::from_str(&content)?)
+        } else {
+            Ok(Self::default())
+        }
+    }
+}
+
+/// The project found around a directory. All fields are `None` outside a project.
+pub struct Project {
+    /// Directory containing affogato.toml (or the legacy firmware/ and fpga/ layout)
+    pub root: Option<PathBuf>,
+    pub name: Option<String>,
+    /// Parsed affogato.toml; `None` for legacy projects without one
+    pub config: Option<ProjectConfig>,
+}
+
+impl Project {
+    /// Detect if we're in an Affogato project: the one `AFFOGATO_PROJECT_ROOT`
+    /// names, else the one containing the current directory
+    pub fn detect() -> Result<Self> {
+        let cwd = std::env::current_dir()?;
+        match std::env::var_os("AFFOGATO_PROJECT_ROOT").filter(|root| !root.is_empty()) {
+            Some(root) => Self::detect_at(cwd.join(root)),
+            None => Self::detect_from(cwd),
+        }
+    }
+
+    /// The project rooted at exactly `root`, without searching its parents;
+    /// for callers that name the project explicitly
+    pub fn detect_at(root: impl AsRef<Path>) -> Result<Self> {
+        let root = root.as_ref();
+        let dir = root
+            .canonicalize()
+            .with_context(|| format!("Project root {} not found", root.display()))
+            .context(Failure::NotAProject)?;
+        match Self::at(dir)? {
+            Some(project) => Ok(project),
+            None => Err(Failure::NotAProject.error(format!(
+                "{} is not an Affogato project (no affogato.toml)",
+                root.display()
+            ))),
+        }
+    }
+
+    /// Find the project containing `start`, searching its parent directories
+    pub fn detect_from(start: impl AsRef<Path>) -> Result<Self> {
+        let mut dir = start.as_ref().to_path_buf();
+        loop {
+            if let Some(project) = Self::at(dir.clone())? {
+                return Ok(project);
+            }
+            if !dir.pop() {
+                break;
+            }
+        }
+
+        Ok(Self {
+            root: None,
+            name: None,
+            config: None,
+        })
+    }
+
+    /// The project rooted at `dir`, if it is one
+    fn at(dir: PathBuf) -> Result<Option<Self>> {
+        // Check for affogato.toml (new style)
+        if dir.join("affogato.toml").exists() {
+            let config = ProjectConfig::load(&dir)?;
+            let name = config
+                .project
+                .name
+                .clone()
+                .or_else(|| dir.file_name().map(|n| n.to_string_lossy().to_string()));
+            return Ok(Some(Self {
+                root: Some(dir),
+                name,
+                config: Some(config),
+            }));
+        }
+
+        // Check for legacy markers (firmware/CMakeLists.txt + fpga/ directory)
+        // No longer requires fpga/Makefile
+        let has_firmware = dir.join("firmware/CMakeLists.txt").exists();
+        let has_fpga = dir.join("fpga").is_dir();
+        if has_firmware && has_fpga {
+            let name = dir.file_name().map(|n| n.to_string_lossy().to_string());
+            return Ok(Some(Self {
+                root: Some(dir),
+                name,
+                config: None,
+            }));
+        }
+        Ok(None)
+    }
+
+    /// Fail with a hint to run `affogato new` or `init` outside a project
+    pub fn require_project(&self) -> Result<()> {
+        if self.root.is_none() {
+            return Err(Failure::NotAProject.error(
+                "Not in an Affogato project. Run 'affogato new <name>' to create one, or 'affogato init' to initialize the current directory.",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Directory for persisted tool state (`.affogato/`), created on demand
+    pub fn state_dir(&self) -> Result<PathBuf> {
+        let root = self.root.as_ref().context("Not in an Affogato project")?;
+        let dir = root.join(".affogato");
+        dry_run::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+}
+
+/// Project templates for `new` and `init`
+const TEMPLATES: &[&str] = &["basic", "softcore", "hwtest"];
+
+fn check_template(template: &str) -> Result<()> {
+    if !TEMPLATES.contains(&template) {
+        bail!(
+            "Unknown template: {} (available: {})",
+            template,
+            TEMPLATES.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Create a new project
+pub fn create_new(name: &str, template: &str) -> Result<()> {
+    check_template(template)?;
+    let project_dir = PathBuf::from(name);
+
+    if project_dir.exists() {
+        bail!("Directory '{}' already exists", name);
+    }
+
+    outln!(
+        "{}",
+        format!("==> Creating new project: {}", name).blue().bold()
+    );
+
+    write_project(&project_dir, name, template)?;
+
+    outln!("{}", "Project created successfully!".green());
+    outln!();
+    outln!("Next steps:");
+    outln!("  cd {}", name);
+    outln!("  affogato build    # Build FPGA + firmware");
+    outln!("  affogato flash    # Flash to device");
+    outln!("  affogato monitor  # Serial console");
+
+    Ok(())
+}
+
+/// Initialize current directory as a project
+pub fn init_current(template: &str) -> Result<()> {
+    check_template(template)?;
+    let cwd = std::env::current_dir()?;
+    let name = cwd
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "project".to_string());
+
+    if cwd.join("firmware").exists() || cwd.join("fpga").exists() {
+        bail!("Directory already contains firmware/ or fpga/ - already initialized?");
+    }
+
+    outln!(
+        "{}",
+        format!("==> Initializing project: {}", name).blue().bold()
+    );
+
+    write_project(&cwd, &name, template)?;
+
+    outln!("{}", "Project initialized!".green());
+
+    Ok(())
+}
+
+/// Write the files of a `template` project into `project_dir`
+fn write_project(project_dir: &Path, name: &str, template: &str) -> Result<()> {
+    // Create directory structure
+    dry_run::create_dir_all(project_dir.join("firmware/main"))?;
+    dry_run::create_dir_all(project_dir.join("fpga/rtl"))?;
+
+    // The templates add their sections to affogato.toml before it is written
+    let mut toml_content = affogato_toml(name);
+
+    // Write firmware files
+    write_firmware_files(project_dir, name)?;
+
+    // Write FPGA files
+    write_fpga_files(project_dir, name)?;
+
+    if template == "softcore" {
+        write_softcore_files(project_dir, name, &mut toml_content)?;
+    }
+    if template == "hwtest" {
+        write_hwtest_files(project_dir, name, &mut toml_content)?;
+    }
+
+    // Write affogato.toml
+    dry_run::write(project_dir.join("affogato.toml"), toml_content)?;
+    Ok(())
+}
+
+fn write_firmware_files(project_dir: &Path, name: &str) -> Result<()> {
+    // CMakeLists.txt
+    let cmake = format!(
+        r#"cmake_minimum_required(VERSION 3.16)
+
+include($ENV{{IDF_PATH}}/tools/cmake/project.cmake)
+project({name})
+
+# Embeds or flashes the bitstream as [firmware] bitstream says
+include(affogato_bitstream.cmake)
+"#
+    );
+    dry_run::write(project_dir.join("firmware/CMakeLists.txt"), cmake)?;
+
+    // main/CMakeLists.txt
+    let main_cmake = r#"idf_component_register(
+    SRCS "main.c"
+    INCLUDE_DIRS "."
+    REQUIRES driver console esp_timer spiffs esp_partition
+)
+"#;
+    dry_run::write(project_dir.join("firmware/main/CMakeLists.txt"), main_cmake)?;
+
+    // main/main.c
+    let main_c = format!(
+        r#"#include <stdio.h>
+#include "freertos/FreeRTOS.h"
+#include "freertos/task.h"
+#include "esp_log.h"
+#include "driver/spi_master.h"
+#include "driver/gpio.h"
+#include "affogato_bitstream.h"
+#include "affogato_build.h"
+#include "affogato_console.h"
+
+static const char *TAG = "{name}";
+
+void app_main(void)
+{{
+    ESP_LOGI(TAG, "{name} starting");
+    affogato_build_print();
+
+    // Serves `version` and `reg read`/`reg write` on the console; pass the
+    // FPGA register bus's spi_device_handle_t once SPI is set up
+    ESP_ERROR_CHECK(affogato_console_start(NULL));
+
+    affogato_bitstream_t bitstream;
+    ESP_ERROR_CHECK(affogato_bitstream_open(&bitstream));
+    ESP_LOGI(TAG, "FPGA bitstream size: %d bytes", bitstream.size);
+    affogato_bitstream_close(&bitstream);
+
+    // TODO: Initialize SPI and load FPGA
+    // See affogato/components/ice40 for reusable loader
+
+    while (1) {{
+        ESP_LOGI(TAG, "Heartbeat");
+        vTaskDelay(pdMS_TO_TICKS(1000));
+    }}
+}}
+"#
+    );
+    dry_run::write(project_dir.join("firmware/main/main.c"), main_c)?;
+
+    // sdkconfig.defaults
+    let sdkconfig = r#"CONFIG_IDF_TARGET="esp32s2"
+CONFIG_ESP_CONSOLE_USB_CDC=y
+CONFIG_ESP_MAIN_TASK_STACK_SIZE=4096
+CONFIG_LOG_COLORS=y
+"#;
+    dry_run::write(project_dir.join("firmware/sdkconfig.defaults"), sdkconfig)?;
+
+    Ok(())
+}
+
+fn affogato_toml(name: &str) -> String {
+    format!(
+        r#"[project]
+name = "{name}"
+
+[fpga]
+device = "up5k"
+package = "sg48"
+top = "top"
+pcf = "fpga/project.pcf"
+
+[firmware]
+debug_console = true
+"#
+    )
+}
+
+fn write_fpga_files(project_dir: &Path, name: &str) -> Result<()> {
+    // project.pcf
+    let pcf = r#"# SPI Interface to ESP32-S2
+set_io FSPI_CLK     15
+set_io FSPI_MOSI    17
+set_io FSPI_MISO    14
+set_io FSPI_CS      16
+
+# Note: RGB LED pins (39, 40, 41) are directly driven by the SB_RGBA_DRV
+# primitive and do not require PCF assignments.
+"#;
+    dry_run::write(project_dir.join("fpga/project.pcf"), pcf)?;
+
+    // top.v
+    let top_v = format!(
+        r#"// {name} - FPGA Top Module
+module top (
+    input wire FSPI_CLK,
+    input wire FSPI_MOSI,
+    output wire FSPI_MISO,
+    input wire FSPI_CS
+);
+    // 48MHz internal oscillator
+    wire clk;
+    SB_HFOSC #(.CLKHF_DIV("0b00")) osc (.CLKHFPU(1'b1), .CLKHFEN(1'b1), .CLKHF(clk));
+
+    // Heartbeat counter
+    reg [25:0] counter;
+    always @(posedge clk) counter <= counter + 1;
+
+    // RGB LED (directly driven by SB_RGBA_DRV primitive, no external pins needed)
+    wire rgb0, rgb1, rgb2;
+    SB_RGBA_DRV #(
+        .CURRENT_MODE("0b0"),
+        .RGB0_CURRENT("0b000001"),
+        .RGB1_CURRENT("0b000001"),
+        .RGB2_CURRENT("0b000001")
+    ) rgb (
+        .CURREN(1'b1),
+        .RGBLEDEN(1'b1),
+        .RGB0PWM(counter[24]),
+        .RGB1PWM(counter[25]),
+        .RGB2PWM(counter[23]),
+        .RGB0(rgb0),
+        .RGB1(rgb1),
+        .RGB2(rgb2)
+    );
+
+    // SPI stub (directly drives MISO low)
+    assign FSPI_MISO = 1'b0;
+endmodule
+"#
+    );
+    dry_run::write(project_dir.join("fpga/rtl/top.v"), top_v)?;
+
+    Ok(())
+}
+
+/// Replace the basic design and firmware with the board self-test answering
+/// `affogato hwtest`: an spi_bridge with ID, scratch and LED registers, and
+/// firmware that configures the FPGA itself so the test can reset it
+fn write_hwtest_files(project_dir: &Path, name: &str, toml_content: &mut String) -> Result<()> {
+    toml_content.push_str(
+        r#"
+[hwtest]
+# ESP32 GPIO pairs wired together on the test fixture, as [output, input]
+gpio_loopbacks = []
+"#,
+    );
+
+    let top_v = format!(
+        r#"// {name} - board self-test design
+//
+// Answers `affogato hwtest` through an spi_bridge register bus:
+//   0x{id_addr:X}  ID       reads 0x{id:04X}
+//   0x{scratch_addr:X}  SCRATCH  read/write, for SPI data patterns
+//   0x{led_addr:X}  LED      bits 2:0 light the RGB LED red, green and blue
+module top (
+    input wire FSPI_CLK,
+    input wire FSPI_MOSI,
+    output wire FSPI_MISO,
+    input wire FSPI_CS
+);
+    // 48MHz internal oscillator; spi_bridge needs 16x the SPI clock
+    wire clk;
+    SB_HFOSC #(.CLKHF_DIV("0b00")) osc (.CLKHFPU(1'b1), .CLKHFEN(1'b1), .CLKHF(clk));
+
+    // Hold the registers in reset for a few cycles after configuration
+    reg [3:0] reset_count = 4'd0;
+    wire rst = !(&reset_count);
+    always @(posedge clk) begin
+        if (rst)
+            reset_count <= reset_count + 1'b1;
+    end
+
+    wire [14:0] addr;
+    wire [15:0] wdata;
+    wire we;
+    reg [15:0] rdata;
+
+    spi_bridge bridge (
+        .i_clk(clk),
+        .i_rst(rst),
+        .i_cs_n(FSPI_CS),
+        .i_sck(FSPI_CLK),
+        .i_mosi(FSPI_MOSI),
+        .o_miso(FSPI_MISO),
+        .o_addr(addr),
+        .o_wdata(wdata),
+        .o_we(we),
+        .i_rdata(rdata)
+    );
+
+    reg [15:0] scratch;
+    reg [2:0] leds;
+
+    always @(posedge clk) begin
+        if (rst) begin
+            scratch <= 16'h0000;
+            leds <= 3'd0;
+        end else if (we) begin
+            if (addr == 15'h{scratch_addr:X})
+                scratch <= wdata;
+            if (addr == 15'h{led_addr:X})
+                leds <= wdata[2:0];
+        end
+    end
+
+    always @(*) begin
+        case (addr)
+            15'h{id_addr:X}: rdata = 16'h{id:04X};
+            15'h{scratch_addr:X}: rdata = scratch;
+            15'h{led_addr:X}: rdata = {{13'd0, leds}};
+            default: rdata = 16'h0000;
+        endcase
+    end
+
+    // RGB LED (directly driven by SB_RGBA_DRV primitive, no external pins needed)
+    wire rgb0, rgb1, rgb2;
+    SB_RGBA_DRV #(
+        .CURRENT_MODE("0b0"),
+        .RGB0_CURRENT("0b000001"),
+        .RGB1_CURRENT("0b000001"),
+        .RGB2_CURRENT("0b000001")
+    ) rgb (
+        .CURREN(1'b1),
+        .RGBLEDEN(1'b1),
+        .RGB0PWM(leds[0]),
+        .RGB1PWM(leds[1]),
+        .RGB2PWM(leds[2]),
+        .RGB0(rgb0),
+        .RGB1(rgb1),
+        .RGB2(rgb2)
+    );
+endmodule
+"#,
+        id_addr = debug_console::HWTEST_ID_ADDR,
+        id = debug_console::HWTEST_ID,
+        scratch_addr = debug_console::HWTEST_SCRATCH_ADDR,
+        led_addr = debug_console::HWTEST_LED_ADDR,
+    );
+    dry_run::write(project_dir.join("fpga/rtl/top.v"), top_v)?;
+    crate::ip::ensure(project_dir, "spi_bridge")?;
+
+    let main_c = format!(
+        r#"#include <stdio.h>
+#include <string.h>
+#include "freertos/FreeRTOS.h"
+#include "freertos/task.h"
+#include "esp_log.h"
+#include "esp_rom_gpio.h"
+#include "driver/spi_master.h"
+#include "driver/gpio.h"
+#include "soc/gpio_sig_map.h"
+#include "affogato_bitstream.h"
+#include "affogato_build.h"
+#include "affogato_console.h"
+
+static const char *TAG = "{name}";
+
+// IcedEspresso wiring, as in the ice40 component's Kconfig defaults
+#define FPGA_CS_GPIO     10
+#define FPGA_MOSI_GPIO   11
+#define FPGA_SCLK_GPIO   12
+#define FPGA_MISO_GPIO   13
+#define FPGA_CRESET_GPIO 36
+#define FPGA_CDONE_GPIO  37
+
+static spi_device_handle_t loader;
+
+// Send bytes with chip select under manual control; NULL sends idle clocks
+static esp_err_t send(const uint8_t *data, size_t len)
+{{
+    static uint8_t zeros[16];
+    spi_transaction_t trans = {{
+        .length = len * 8,
+        .tx_buffer = data ? data : zeros,
+    }};
+    return spi_device_transmit(loader, &trans);
+}}
+
+// Configure the FPGA as an SPI slave (Lattice TN1248): reset it with chip
+// select low, stream the bitstream, then clock until CDONE rises
+static esp_err_t configure_fpga(void)
+{{
+    static uint8_t chunk[4096];
+    affogato_bitstream_t bitstream;
+    esp_err_t ret = affogato_bitstream_open(&bitstream);
+    if (ret != ESP_OK) {{
+        return ret;
+    }}
+
+    esp_rom_gpio_connect_out_signal(FPGA_CS_GPIO, SIG_GPIO_OUT_IDX, false, false);
+    gpio_set_level(FPGA_CS_GPIO, 0);
+    gpio_set_level(FPGA_CRESET_GPIO, 0);
+    vTaskDelay(1);
+    gpio_set_level(FPGA_CRESET_GPIO, 1);
+    vTaskDelay(pdMS_TO_TICKS(20));
+
+    gpio_set_level(FPGA_CS_GPIO, 1);
+    ret = send(NULL, 1);
+    gpio_set_level(FPGA_CS_GPIO, 0);
+    for (size_t offset = 0; ret == ESP_OK && offset < bitstream.size; offset += sizeof(chunk)) {{
+        size_t len = bitstream.size - offset < sizeof(chunk) ? bitstream.size - offset : sizeof(chunk);
+        ret = affogato_bitstream_read(&bitstream, offset, chunk, len);
+        if (ret == ESP_OK) {{
+            ret = send(chunk, len);
+        }}
+    }}
+    gpio_set_level(FPGA_CS_GPIO, 1);
+    affogato_bitstream_close(&bitstream);
+    if (ret == ESP_OK) {{
+        ret = send(NULL, 13);
+    }}
+    if (ret == ESP_OK && !gpio_get_level(FPGA_CDONE_GPIO)) {{
+        ret = ESP_ERR_TIMEOUT;
+    }}
+    if (ret == ESP_OK) {{
+        ret = send(NULL, 7);
+    }}
+    esp_rom_gpio_connect_out_signal(FPGA_CS_GPIO, FSPICS0_OUT_IDX, false, false);
+    return ret;
+}}
+
+void app_main(void)
+{{
+    ESP_LOGI(TAG, "{name} starting");
+    affogato_build_print();
+
+    gpio_reset_pin(FPGA_CRESET_GPIO);
+    gpio_set_direction(FPGA_CRESET_GPIO, GPIO_MODE_OUTPUT);
+    gpio_reset_pin(FPGA_CDONE_GPIO);
+    gpio_set_direction(FPGA_CDONE_GPIO, GPIO_MODE_INPUT);
+
+    spi_bus_config_t bus = {{
+        .mosi_io_num = FPGA_MOSI_GPIO,
+        .miso_io_num = FPGA_MISO_GPIO,
+        .sclk_io_num = FPGA_SCLK_GPIO,
+        .quadwp_io_num = -1,
+        .quadhd_io_num = -1,
+        .max_transfer_sz = 4096,
+    }};
+    ESP_ERROR_CHECK(spi_bus_initialize(SPI2_HOST, &bus, SPI_DMA_CH_AUTO));
+
+    // The register bus is added first so it owns hardware chip select 0;
+    // spi_bridge oversamples SCK at 48 MHz, so it runs at 1 MHz
+    spi_device_interface_config_t regs_config = {{
+        .mode = 0,
+        .clock_speed_hz = 1000000,
+        .spics_io_num = FPGA_CS_GPIO,
+        .queue_size = 1,
+    }};
+    spi_device_handle_t regs;
+    ESP_ERROR_CHECK(spi_bus_add_device(SPI2_HOST, &regs_config, &regs));
+
+    spi_device_interface_config_t loader_config = {{
+        .mode = 3,
+        .clock_speed_hz = 20000000,
+        .spics_io_num = -1,
+        .queue_size = 1,
+        .flags = SPI_DEVICE_HALFDUPLEX,
+    }};
+    ESP_ERROR_CHECK(spi_bus_add_device(SPI2_HOST, &loader_config, &loader));
+
+    esp_err_t ret = configure_fpga();
+    ESP_LOGI(TAG, "FPGA configuration: %s", esp_err_to_name(ret));
+
+    // `affogato hwtest` runs the self-test through the console
+    const affogato_hwtest_config_t hwtest = {{
+        .creset_gpio = FPGA_CRESET_GPIO,
+        .cdone_gpio = FPGA_CDONE_GPIO,
+        .configure = configure_fpga,
+    }};
+    affogato_console_enable_hwtest(&hwtest);
+    ESP_ERROR_CHECK(affogato_console_start(regs));
+}}
+"#
+    );
+    dry_run::write(project_dir.join("firmware/main/main.c"), main_c)?;
+    Ok(())
+}
+
+/// Replace the basic FPGA design with a picorv32 SoC running a C program from BRAM
+fn write_softcore_files(project_dir: &Path, name: &str, toml_content: &mut String) -> Result<()> {
+    toml_content.push_str(
+        r#"
+[fpga.deps]
+picorv32 = { git = "https://github.com/YosysHQ/picorv32", files = ["picorv32.v"] }
+
+[softcore]
+dir = "softcore"
+words = 1024
+arch = "rv32i"
+"#,
+    );
+
+    let top_v = format!(
+        r#"// {name} - picorv32 soft-core SoC
+//
+// The CPU runs the program in softcore/ from a block RAM. `affogato build`
+// compiles it and loads it into the bitstream with icebram, so the design is
+// only resynthesized when the RTL changes.
+//
+// Memory map:
+//   0x0000_0000  Program memory (MEM_WORDS 32-bit words)
+//   0x1000_0000  LED register (bits 2:0 drive the RGB LED)
+module top (
+    input wire FSPI_CLK,
+    input wire FSPI_MOSI,
+    output wire FSPI_MISO,
+    input wire FSPI_CS
+);
+    // Must match `words` in the [softcore] section of affogato.toml
+    localparam MEM_WORDS = 1024;
+    localparam ADDR_BITS = $clog2(MEM_WORDS);
+
+    // 12MHz from the internal oscillator (48MHz / 4)
+    wire clk;
+    SB_HFOSC #(.CLKHF_DIV("0b10")) osc (.CLKHFPU(1'b1), .CLKHFEN(1'b1), .CLKHF(clk));
+
+    // Hold the CPU in reset for a few cycles after configuration
+    reg [3:0] reset_count = 4'd0;
+    wire resetn = &reset_count;
+    always @(posedge clk) begin
+        if (!resetn)
+            reset_count <= reset_count + 1'b1;
+    end
+
+    wire mem_valid;
+    wire mem_instr;
+    reg mem_ready;
+    wire [31:0] mem_addr;
+    wire [31:0] mem_wdata;
+    wire [3:0] mem_wstrb;
+    reg [31:0] mem_rdata;
+
+    picorv32 #(
+        .PROGADDR_RESET(32'h0000_0000),
+        .STACKADDR(MEM_WORDS * 4),
+        .ENABLE_COUNTERS(0),
+        .CATCH_MISALIGN(0),
+        .CATCH_ILLINSN(0)
+    ) cpu (
+        .clk(clk),
+        .resetn(resetn),
+        .mem_valid(mem_valid),
+        .mem_instr(mem_instr),
+        .mem_ready(mem_ready),
+        .mem_addr(mem_addr),
+        .mem_wdata(mem_wdata),
+        .mem_wstrb(mem_wstrb),
+        .mem_rdata(mem_rdata),
+        .pcpi_wr(1'b0),
+        .pcpi_rd(32'd0),
+        .pcpi_wait(1'b0),
+        .pcpi_ready(1'b0),
+        .irq(32'd0)
+    );
+
+    // Program memory, initialized with a seed pattern that the build replaces
+    reg [31:0] memory [0:MEM_WORDS-1];
+    initial $readmemh("fpga/build/softcore/seed.hex", memory);
+
+    wire [ADDR_BITS-1:0] word = mem_addr[ADDR_BITS+1:2];
+    reg [2:0] leds = 3'd0;
+
+    always @(posedge clk) begin
+        mem_ready <= 1'b0;
+        if (mem_valid && !mem_ready) begin
+            mem_ready <= 1'b1;
+            if (mem_addr < MEM_WORDS * 4) begin
+                mem_rdata <= memory[word];
+                if (mem_wstrb[0]) memory[word][7:0] <= mem_wdata[7:0];
+                if (mem_wstrb[1]) memory[word][15:8] <= mem_wdata[15:8];
+                if (mem_wstrb[2]) memory[word][23:16] <= mem_wdata[23:16];
+                if (mem_wstrb[3]) memory[word][31:24] <= mem_wdata[31:24];
+            end else if (mem_addr == 32'h1000_0000) begin
+                mem_rdata <= {{29'd0, leds}};
+                if (|mem_wstrb)
+                    leds <= mem_wdata[2:0];
+            end else begin
+                mem_rdata <= 32'd0;
+            end
+        end
+    end
+
+    // RGB LED (directly driven by SB_RGBA_DRV primitive, no external pins needed)
+    wire rgb0, rgb1, rgb2;
+    SB_RGBA_DRV #(
+        .CURRENT_MODE("0b0"),
+        .RGB0_CURRENT("0b000001"),
+        .RGB1_CURRENT("0b000001"),
+        .RGB2_CURRENT("0b000001")
+    ) rgb (
+        .CURREN(1'b1),
+        .RGBLEDEN(1'b1),
+        .RGB0PWM(leds[0]),
+        .RGB1PWM(leds[1]),
+        .RGB2PWM(leds[2]),
+        .RGB0(rgb0),
+        .RGB1(rgb1),
+        .RGB2(rgb2)
+    );
+
+    // SPI stub (directly drives MISO low)
+    assign FSPI_MISO = 1'b0;
+endmodule
+"#
+    );
+    dry_run::write(project_dir.join("fpga/rtl/top.v"), top_v)?;
+
+    let softcore_dir = project_dir.join("softcore");
+    dry_run::create_dir_all(&softcore_dir)?;
+
+    let start_s = r#"/* Reset entry point: clear .bss and call main() */
+    .section .text.start
+    .global _start
+_start:
+    la sp, _stack_top
+    la a0, _bss_start
+    la a1, _bss_end
+1:
+    bgeu a0, a1, 2f
+    sw zero, 0(a0)
+    addi a0, a0, 4
+    j 1b
+2:
+    call main
+3:
+    j 3b
+"#;
+    dry_run::write(softcore_dir.join("start.S"), start_s)?;
+
+    let sections_ld = r#"/* The whole program runs from the soft-core's block RAM.
+ * LENGTH must match `words` in [softcore] (4 bytes per word). */
+MEMORY
+{
+    ram (rwx) : ORIGIN = 0x00000000, LENGTH = 4K
+}
+
+SECTIONS
+{
+    .text : {
+        KEEP(*(.text.start))
+        *(.text*)
+        *(.rodata*)
+        *(.srodata*)
+    } > ram
+
+    .data : {
+        *(.data*)
+        *(.sdata*)
+    } > ram
+
+    .bss (NOLOAD) : {
+        . = ALIGN(4);
+        _bss_start = .;
+        *(.bss*)
+        *(.sbss*)
+        *(COMMON)
+        . = ALIGN(4);
+        _bss_end = .;
+    } > ram
+
+    _stack_top = ORIGIN(ram) + LENGTH(ram);
+}
+"#;
+    dry_run::write(softcore_dir.join("sections.ld"), sections_ld)?;
+
+    let main_c = format!(
+        r#"// {name} - soft-core program
+#include <stdint.h>
+
+#define LEDS (*(volatile uint32_t *)0x10000000)
+
+static void delay(uint32_t loops)
+{{
+    for (volatile uint32_t i = 0; i < loops; i++) {{
+    }}
+}}
+
+int main(void)
+{{
+    uint32_t count = 0;
+
+    while (1) {{
+        LEDS = count++;
+        delay(100000);
+    }}
+}}
+"#
+    );
+    dry_run::write(softcore_dir.join("main.c"), main_c)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Dry-run mode is process-wide, so one test at a time may switch it
+    static DRY_RUN: Mutex<()> = Mutex::new(());
+
+    /// Writes a `template` project under --dry-run, which must touch nothing
+    fn dry_run_new(template: &str) {
+        let project_dir = std::env::temp_dir().join(format!("affogato-dry-run-{}", template));
+        let _guard = DRY_RUN.lock().unwrap();
+        dry_run::set_enabled(true);
+        let result = write_project(&project_dir, "demo", template);
+        dry_run::set_enabled(false);
+        result.unwrap();
+        assert!(!project_dir.exists());
+    }
+
+    #[test]
+    fn dry_run_basic() {
+        dry_run_new("basic");
+    }
+
+    #[test]
+    fn dry_run_hwtest() {
+        dry_run_new("hwtest");
+    }
+
+    #[test]
+    fn dry_run_softcore() {
+        dry_run_new("softcore");
+    }
+}
@@ -0,0 +1,205 @@
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::delivery;
+use crate::dry_run;
+use crate::project::{BitstreamDelivery, FpgaBoot, LoaderConfig};
+
+/// ESP-IDF's settings, which the firmware build reads and updates
+const SDKCONFIG: &str = "firmware/sdkconfig";
+
+/// The settings a fresh firmware build starts from
+const SDKCONFIG_DEFAULTS: &str = "firmware/sdkconfig.defaults";
+
+/// The ice40 component's Kconfig choice for `[fpga] boot`
+const BOOT_FLASH: &str = "CONFIG_FPGA_BOOT_FLASH";
+const BOOT_CRAM: &str = "CONFIG_FPGA_BOOT_CRAM";
+
+/// The ice40 component's Kconfig options set by `[loader]` keys, with the
+/// ranges Kconfig accepts
+const LOADER_OPTIONS: &[(&str, &str, i64, i64)] = &[
+    ("spi_host", "CONFIG_FPGA_SPI_HOST", 2, 3),
+    ("cs", "CONFIG_FPGA_CS_GPIO", 0, 48),
+    ("clk", "CONFIG_FPGA_SCLK_GPIO", 0, 48),
+    ("mosi", "CONFIG_FPGA_MOSI_GPIO", 0, 48),
+    ("miso", "CONFIG_FPGA_MISO_GPIO", 0, 48),
+    ("wp", "CONFIG_FPGA_WP_GPIO", -1, 48),
+    ("hd", "CONFIG_FPGA_HD_GPIO", -1, 48),
+    ("creset", "CONFIG_FPGA_CRESET_GPIO", 0, 48),
+    ("cdone", "CONFIG_FPGA_CDONE_GPIO", 0, 48),
+    ("programming_mhz", "CONFIG_FPGA_SPI_FREQ_PROGRAMMING", 1, 25),
+    ("comms_mhz", "CONFIG_FPGA_SPI_FREQ_COMMS", 1, 80),
+];
+
+/// ESP-IDF's partition table choice, switched to the generated table when the
+/// bitstream needs a partition
+const PARTITION_TABLE_CUSTOM: &str = "CONFIG_PARTITION_TABLE_CUSTOM";
+const PARTITION_TABLE_OTHERS: &[&str] = &[
+    "CONFIG_PARTITION_TABLE_SINGLE_APP",
+    "CONFIG_PARTITION_TABLE_SINGLE_APP_ENCRYPTED_NVS",
+    "CONFIG_PARTITION_TABLE_SINGLE_APP_LARGE",
+    "CONFIG_PARTITION_TABLE_SINGLE_APP_LARGE_ENC_NVS",
+    "CONFIG_PARTITION_TABLE_TWO_OTA",
+    "CONFIG_PARTITION_TABLE_TWO_OTA_ENCRYPTED_NVS",
+];
+const PARTITION_TABLE_FILENAME: &str = "CONFIG_PARTITION_TABLE_CUSTOM_FILENAME";
+
+/// Heads the settings in sdkconfig.defaults
+const DEFAULTS_COMMENT: &str =
+    "# Set from affogato.toml ([fpga] boot, [firmware] bitstream and [loader])";
+
+/// Headings written by earlier versions
+const OLD_COMMENTS: &[&str] = &[
+    "# Set from affogato.toml ([fpga] boot and [loader])",
+    "# FPGA boots from its configuration flash ([fpga] boot)",
+];
+
+/// Set the ice40 loader's configuration source, from `[fpga] boot`, and its
+/// pins and clocks, from `[loader]`, in the firmware's sdkconfig and
+/// sdkconfig.defaults, with the partition table `[firmware] bitstream` needs
+pub fn apply_loader(
+    project_root: &Path,
+    boot: FpgaBoot,
+    loader: &LoaderConfig,
+    bitstream: BitstreamDelivery,
+) -> Result<()> {
+    let flash = boot == FpgaBoot::Flash;
+    let setting = |option: &str, on: bool| {
+        if on {
+            format!("{}=y", option)
+        } else {
+            format!("# {} is not set", option)
+        }
+    };
+    // sdkconfig records the whole choice; the defaults only the non-default one
+    let mut settings = vec![
+        (BOOT_FLASH, setting(BOOT_FLASH, flash)),
+        (BOOT_CRAM, setting(BOOT_CRAM, !flash)),
+    ];
+    let mut defaults = Vec::new();
+    if flash {
+        defaults.push(setting(BOOT_FLASH, true));
+    }
+
+    let values = serde_json::to_value(loader)?;
+    for &(key, option, min, max) in LOADER_OPTIONS {
+        let Some(value) = values[key].as_i64() else {
+            continue;
+        };
+        if !(min..=max).contains(&value) {
+            bail!(
+                "[loader] {} = {} is out of range; it must be from {} to {}",
+                key,
+                value,
+                min,
+                max
+            );
+        }
+        let line = format!("{}={}", option, value);
+        settings.push((option, line.clone()));
+        defaults.push(line);
+    }
+
+    // Embedded bitstreams leave the partition table to the project
+    if bitstream != BitstreamDelivery::Embed {
+        settings.push((
+            PARTITION_TABLE_CUSTOM,
+            setting(PARTITION_TABLE_CUSTOM, true),
+        ));
+        for &option in PARTITION_TABLE_OTHERS {
+            settings.push((option, setting(option, false)));
+        }
+        let filename = delivery::PARTITION_TABLE.trim_start_matches("firmware/");
+        let line = format!("{}=\"{}\"", PARTITION_TABLE_FILENAME, filename);
+        settings.push((PARTITION_TABLE_FILENAME, line.clone()));
+        defaults.push(setting(PARTITION_TABLE_CUSTOM, true));
+        defaults.push(line);
+    }
+
+    update_sdkconfig(&project_root.join(SDKCONFIG), &settings)?;
+    let managed: Vec<&str> = settings.iter().map(|(option, _)| *option).collect();
+    update_defaults(&project_root.join(SDKCONFIG_DEFAULTS), &managed, &defaults)
+}
+
+/// The Kconfig option a line sets, whether to a value or "is not set"
+fn option_of(line: &str) -> Option<&str> {
+    if let Some(option) = line
+        .strip_prefix("# ")
+        .and_then(|line| line.strip_suffix(" is not set"))
+    {
+        return Some(option);
+    }
+    line.starts_with("CONFIG_")
+        .then(|| line.split('=').next())
+        .flatten()
+}
+
+/// Replace each option's line in sdkconfig, adding those it lacks
+fn update_sdkconfig(path: &Path, settings: &[(&str, String)]) -> Result<()> {
+    let Ok(text) = fs::read_to_string(path) else {
+        return Ok(());
+    };
+    let mut written = Vec::new();
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        match option_of(line).and_then(|option| settings.iter().find(|(o, _)| *o == option)) {
+            Some((option, setting)) => {
+                if !written.contains(option) {
+                    lines.push(setting.clone());
+                    written.push(*option);
+                }
+            }
+            None => lines.push(line.to_string()),
+        }
+    }
+    for (option, setting) in settings {
+        if !written.contains(option) {
+            lines.push(setting.clone());
+        }
+    }
+    write_if_changed(path, &text, lines)
+}
+
+/// Rewrite affogato's block at the end of sdkconfig.defaults as `lines`,
+/// dropping any other lines for the `managed` options
+fn update_defaults(path: &Path, managed: &[&str], lines: &[String]) -> Result<()> {
+    let Ok(text) = fs::read_to_string(path) else {
+        return Ok(());
+    };
+    let mut kept: Vec<String> = Vec::new();
+    let mut in_block = false;
+    for line in text.lines() {
+        if line == DEFAULTS_COMMENT || OLD_COMMENTS.contains(&line) {
+            in_block = true;
+            continue;
+        }
+        if in_block && !line.trim().is_empty() {
+            continue;
+        }
+        in_block = false;
+        if option_of(line).is_some_and(|option| managed.contains(&option)) {
+            continue;
+        }
+        kept.push(line.to_string());
+    }
+    while kept.last().is_some_and(|line| line.trim().is_empty()) {
+        kept.pop();
+    }
+    if !lines.is_empty() {
+        kept.push(String::new());
+        kept.push(DEFAULTS_COMMENT.to_string());
+        kept.extend(lines.iter().cloned());
+    }
+    write_if_changed(path, &text, kept)
+}
+
+/// Write the lines back unless nothing changed, so the firmware isn't rebuilt
+/// for nothing
+fn write_if_changed(path: &Path, text: &str, lines: Vec<String>) -> Result<()> {
+    let updated = lines.join("\n") + "\n";
+    if updated == text {
+        return Ok(());
+    }
+    dry_run::write(path, updated).with_context(|| format!("Failed to update {}", path.display()))
+}
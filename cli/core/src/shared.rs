@@ -0,0 +1,110 @@
+use anyhow::{bail, Result};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::build_info::write_if_changed;
+use crate::project::SharedConstant;
+
+/// Verilog header of localparams, included inside modules, relative to the
+/// project root; kept beside the RTL so `` `include "affogato_shared.vh" ``
+/// finds it
+pub const VERILOG_HEADER: &str = "fpga/rtl/affogato_shared.vh";
+
+/// C header of the same constants as #defines
+pub const C_HEADER: &str = "firmware/main/affogato_shared.h";
+
+/// Write both headers from the `[shared]` table, after checking every
+/// constant means the same thing on both sides
+pub fn write(project_root: &Path, constants: &BTreeMap<String, SharedConstant>) -> Result<()> {
+    if constants.is_empty() {
+        return Ok(());
+    }
+    let mut verilog = String::from(
+        "// Shared constants - generated by `affogato build` from [shared] in\n\
+         // affogato.toml; do not edit. Include inside a module:\n\
+         //   `include \"affogato_shared.vh\"\n",
+    );
+    let mut c = String::from(
+        "/* Shared constants - generated by `affogato build` from [shared] in\n \
+         * affogato.toml; do not edit. The FPGA sees the same values through\n \
+         * fpga/rtl/affogato_shared.vh. */\n#pragma once\n",
+    );
+    for (name, constant) in constants {
+        check_name(name)?;
+        let (value, width) = match *constant {
+            SharedConstant::Value(value) => (value, None),
+            SharedConstant::Sized { value, width } => (value, Some(width)),
+        };
+        match width {
+            None => {
+                // Unsized Verilog parameters are 32-bit integers
+                if i32::try_from(value).is_err() {
+                    bail!(
+                        "[shared] {} = {} does not fit a 32-bit integer; give it a width, \
+                         e.g. {} = {{ value = {}, width = 64 }}",
+                        name,
+                        value,
+                        name,
+                        value
+                    );
+                }
+                verilog.push_str(&format!("localparam {} = {};\n", name, value));
+                if value < 0 {
+                    c.push_str(&format!("#define {} ({})\n", name, value));
+                } else {
+                    c.push_str(&format!("#define {} {}\n", name, value));
+                }
+            }
+            Some(width) => {
+                if !(1..=64).contains(&width) {
+                    bail!("[shared] {} has width {}; it must be 1 to 64", name, width);
+                }
+                if value < 0 || (width < 64 && value >> width != 0) {
+                    bail!(
+                        "[shared] {} = {} does not fit in {} unsigned bits",
+                        name,
+                        value,
+                        width
+                    );
+                }
+                let digits = (width as usize).div_ceil(4);
+                verilog.push_str(&format!(
+                    "localparam [{}:0] {} = {}'h{:0digits$X};\n",
+                    width - 1,
+                    name,
+                    width,
+                    value
+                ));
+                let suffix = if width > 32 { "ull" } else { "u" };
+                c.push_str(&format!(
+                    "#define {} 0x{:0digits$X}{}\n",
+                    name, value, suffix
+                ));
+            }
+        }
+    }
+
+    if project_root.join("fpga/rtl").is_dir() {
+        write_if_changed(&project_root.join(VERILOG_HEADER), &verilog)?;
+    }
+    if project_root.join("firmware/main").is_dir() {
+        write_if_changed(&project_root.join(C_HEADER), &c)?;
+    }
+    Ok(())
+}
+
+/// A name must be an identifier in both languages
+fn check_name(name: &str) -> Result<()> {
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        bail!(
+            "[shared] {:?} is not a valid name; use letters, digits and underscores",
+            name
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+
+use crate::delivery;
+use crate::docker::{require_device, Docker};
+use crate::error::Failure;
+use crate::project::{Project, SoftcoreConfig};
+
+/// Where the program image and patched bitstream are built, relative to the project root
+pub const BUILD_DIR: &str = "fpga/build/softcore";
+
+/// Random BRAM contents synthesized into the bitstream, replaced by the program
+/// with icebram. It must not change between synthesis and patching.
+pub const SEED_HEX: &str = "fpga/build/softcore/seed.hex";
+
+const PROGRAM_HEX: &str = "fpga/build/softcore/program.hex";
+
+/// Shell snippet creating the seed memory image, if it doesn't exist yet
+pub fn seed_script(softcore: &SoftcoreConfig) -> Result<String> {
+    check(softcore)?;
+    Ok(format!(
+        r#"mkdir -p {BUILD_DIR}
+[ -f {SEED_HEX} ] || icebram -g 32 {words} > {SEED_HEX}
+"#,
+        words = softcore.words,
+    ))
+}
+
+/// Shell snippet compiling the program and writing it as one hex word per BRAM line
+pub fn program_script(softcore: &SoftcoreConfig) -> Result<String> {
+    check(softcore)?;
+    let dir = &softcore.dir;
+    let bytes = softcore.words * 4;
+    Ok(format!(
+        r#"echo "Compiling soft-core program..."
+mkdir -p {BUILD_DIR}
+SOURCES=$(find {dir} -name '*.c' -o -name '*.S' | sort)
+riscv64-unknown-elf-gcc -march={arch} -mabi=ilp32 -Os -ffreestanding -nostdlib \
+    -ffunction-sections -fdata-sections -Wl,--gc-sections \
+    -T {dir}/sections.ld -Wl,-Map,{BUILD_DIR}/program.map \
+    -I{dir} -o {BUILD_DIR}/program.elf $SOURCES
+riscv64-unknown-elf-objcopy -O binary {BUILD_DIR}/program.elf {BUILD_DIR}/program.bin
+SIZE=$(stat -c %s {BUILD_DIR}/program.bin)
+if [ $SIZE -gt {bytes} ]; then
+    echo "error: program is $SIZE bytes but the soft-core memory holds {bytes}"
+    exit 1
+fi
+echo "Program size: $SIZE of {bytes} bytes"
+truncate -s {bytes} {BUILD_DIR}/program.bin
+od -An -v -tx4 -w4 {BUILD_DIR}/program.bin | tr -d ' ' > {PROGRAM_HEX}
+"#,
+        arch = softcore.arch,
+    ))
+}
+
+/// Shell snippet replacing the seed in `asc` with the program and packing `bin`
+pub fn patch_script(asc: &str, bin: &str) -> String {
+    format!(
+        r#"echo "Loading program into BRAM..."
+icebram {SEED_HEX} {PROGRAM_HEX} < {asc} > {BUILD_DIR}/top.asc
+icepack {BUILD_DIR}/top.asc {bin}
+"#
+    )
+}
+
+fn check(softcore: &SoftcoreConfig) -> Result<()> {
+    if softcore.words == 0 || !softcore.words.is_multiple_of(256) {
+        bail!("[softcore] words must be a non-zero multiple of 256 (whole iCE40 BRAMs)");
+    }
+    Ok(())
+}
+
+/// Recompile the program and patch it into the last bitstream, skipping synthesis.
+/// With `port`, the firmware and the new bitstream are flashed as well.
+pub fn rebuild(docker: &Docker, project: &Project, port: Option<&str>) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    let softcore = project
+        .config
+        .as_ref()
+        .and_then(|c| c.softcore.as_ref())
+        .context("No [softcore] section in affogato.toml")?;
+
+    if !project_root.join(SEED_HEX).exists() || !project_root.join("fpga/top.asc").exists() {
+        bail!("No soft-core bitstream to patch yet; run `affogato fpga` first");
+    }
+
+    outln!("{}", "==> Rebuilding soft-core program".blue().bold());
+    let script = format!(
+        "set -e\ncd /workspace\n{}{}echo \"FPGA build complete: fpga/top.bin\"\n",
+        program_script(softcore)?,
+        patch_script("fpga/top.asc", "fpga/top.bin")
+    );
+    docker
+        .run_in_project(project, &["bash", "-c", &script], &[], false)
+        .context(Failure::BuildFailed)?;
+
+    if let Some(port) = port {
+        require_device(port)?;
+        // The bitstream is in the app, or in the `fpga` partition beside it
+        let delivery = project
+            .config
+            .as_ref()
+            .map(|c| c.firmware.bitstream)
+            .unwrap_or_default();
+        outln!("{}", format!("==> Flashing to {}", port).blue().bold());
+        let cmd = format!(
+            "cd firmware && idf.py -p {} {}",
+            port,
+            delivery::app_flash_targets(delivery)
+        );
+        docker.run_in_project(project, &["bash", "-c", &cmd], &[], true)?;
+    }
+    Ok(())
+}
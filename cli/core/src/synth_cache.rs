@@ -0,0 +1,298 @@
+use anyhow::Result;
+use colored::Colorize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use crate::amaranth;
+use crate::cache;
+use crate::verilog;
+
+/// Netlists of separately synthesized modules, relative to the project root
+pub const MODULES_DIR: &str = "fpga/build/modules";
+
+/// Module netlists kept in the shared cache; they are small
+const CACHE_ENTRIES: usize = 256;
+
+/// Yosys synthesis flags, shared by module and whole-design synthesis
+const SYNTH_FLAGS: &str = "-abc2 -relut";
+
+/// A submodule of the top synthesized on its own, so an unchanged one can be
+/// restored from the cache instead of synthesized again
+pub struct ModuleSynth {
+    pub name: String,
+    key: String,
+    pub cached: bool,
+}
+
+impl ModuleSynth {
+    fn netlist(&self) -> String {
+        format!("{}/{}.json", MODULES_DIR, self.name)
+    }
+}
+
+/// How an incremental build synthesizes the design: the top's submodules one
+/// by one, then the top with their netlists stitched in
+pub struct Plan {
+    pub modules: Vec<ModuleSynth>,
+}
+
+/// Sources of one Verilog file, as far as the cache keys are concerned
+struct Source {
+    modules: Vec<String>,
+    identifiers: BTreeSet<String>,
+    defines: bool,
+    reads_files: bool,
+    includes: Vec<String>,
+}
+
+impl Plan {
+    /// Key the top's submodules and restore the unchanged ones from the cache.
+    /// Submodules that can't be synthesized alone safely are left to the top:
+    /// those given parameters, generated by the build or loading memory files.
+    pub fn new(
+        project_root: &Path,
+        top: &str,
+        verilog_files: &[String],
+        image_id: &str,
+    ) -> Result<Self> {
+        let mut sources = BTreeMap::new();
+        for file in verilog_files {
+            let Ok(text) = fs::read_to_string(project_root.join(file)) else {
+                continue;
+            };
+            let text = verilog::strip_comments(&text);
+            sources.insert(
+                file.clone(),
+                Source {
+                    modules: verilog::defined_modules(&text),
+                    identifiers: verilog::identifiers(&text),
+                    defines: text.contains("`define"),
+                    reads_files: text.contains("$readmem"),
+                    includes: includes(&text),
+                },
+            );
+        }
+        let defined_in: BTreeMap<&str, &str> = sources
+            .iter()
+            .flat_map(|(file, source)| {
+                source
+                    .modules
+                    .iter()
+                    .map(move |module| (module.as_str(), file.as_str()))
+            })
+            .collect();
+        let Some(top_file) = defined_in.get(top).copied() else {
+            return Ok(Plan {
+                modules: Vec::new(),
+            });
+        };
+        let top_source = &sources[top_file];
+        let top_text = fs::read_to_string(project_root.join(top_file))
+            .map(|text| verilog::strip_comments(&text))
+            .unwrap_or_default();
+        if top_text.contains("defparam") {
+            return Ok(Plan {
+                modules: Vec::new(),
+            });
+        }
+        // Macros defined in any file reach every file read after it
+        let define_files: Vec<&str> = sources
+            .iter()
+            .filter(|(_, source)| source.defines)
+            .map(|(file, _)| file.as_str())
+            .collect();
+
+        let mut modules = Vec::new();
+        for (&name, &file) in &defined_in {
+            if file == top_file
+                || !top_source.identifiers.contains(name)
+                || has_overrides(&top_text, name)
+            {
+                continue;
+            }
+            // Every file the module's hierarchy is defined in
+            let mut files = BTreeSet::from([file]);
+            loop {
+                let before = files.len();
+                let used: Vec<&str> = files
+                    .iter()
+                    .flat_map(|file| sources[*file].identifiers.iter())
+                    .filter_map(|ident| defined_in.get(ident.as_str()).copied())
+                    .collect();
+                files.extend(used);
+                if files.len() == before {
+                    break;
+                }
+            }
+            if files.contains(top_file)
+                || files
+                    .iter()
+                    .any(|file| file.starts_with(amaranth::OUT_DIR) || sources[*file].reads_files)
+            {
+                continue;
+            }
+
+            let mut key = cache::Key::new("synth", image_id);
+            key.text("module", name);
+            key.text("script", &module_command(name, "<sources>"));
+            for file in files.iter().chain(&define_files) {
+                key.file(project_root, file);
+                for include in &sources[*file].includes {
+                    key.file(project_root, &resolve_include(project_root, file, include));
+                }
+            }
+            modules.push(ModuleSynth {
+                name: name.to_string(),
+                key: key.finish(),
+                cached: false,
+            });
+        }
+
+        for module in &mut modules {
+            module.cached =
+                cache::restore("synth", &module.key, project_root, &[&module.netlist()])?.is_some();
+        }
+        Ok(Plan { modules })
+    }
+
+    /// Report which submodules were restored from the cache
+    pub fn print(&self) {
+        if self.modules.is_empty() {
+            return;
+        }
+        let cached: Vec<&str> = self
+            .modules
+            .iter()
+            .filter(|module| module.cached)
+            .map(|module| module.name.as_str())
+            .collect();
+        outln!(
+            "{}",
+            format!(
+                "Incremental synthesis: {} of {} submodules unchanged{}",
+                cached.len(),
+                self.modules.len(),
+                if cached.is_empty() {
+                    String::new()
+                } else {
+                    format!(" ({})", cached.join(", "))
+                }
+            )
+            .dimmed()
+        );
+    }
+
+    /// The build script's synthesis step: changed submodules on their own,
+    /// then the top with every submodule's netlist in place of its source
+    pub fn script(&self, top: &str, verilog_list: &str) -> String {
+        if self.modules.is_empty() {
+            return whole_command(top, verilog_list);
+        }
+        let mut script = format!("mkdir -p {}\n", MODULES_DIR);
+        for module in self.modules.iter().filter(|module| !module.cached) {
+            script.push_str(&format!(
+                "echo \"Synthesizing {}...\"\n{}\n",
+                module.name,
+                module_command(&module.name, verilog_list)
+            ));
+        }
+        let names: Vec<&str> = self
+            .modules
+            .iter()
+            .map(|module| module.name.as_str())
+            .collect();
+        let netlists: Vec<String> = self
+            .modules
+            .iter()
+            .map(|module| format!("read_json {}; ", module.netlist()))
+            .collect();
+        script.push_str(&format!(
+            "echo \"Synthesizing {top} with {count} submodule netlists...\"\n\
+             yosys -q -p \"delete {names}; {netlists}synth_ice40 {SYNTH_FLAGS} -top {top} -json fpga/top.json\" {verilog_list}\n",
+            count = names.len(),
+            names = names.join(" "),
+            netlists = netlists.concat(),
+        ));
+        script
+    }
+
+    /// Save the submodules synthesized by a successful build
+    pub fn store(&self, project_root: &Path) {
+        for module in self.modules.iter().filter(|module| !module.cached) {
+            cache::store(
+                "synth",
+                &module.key,
+                project_root,
+                &[&module.netlist()],
+                &[],
+                CACHE_ENTRIES,
+            );
+        }
+    }
+}
+
+/// Synthesize the whole design in one go
+pub fn whole_command(top: &str, verilog_list: &str) -> String {
+    format!(
+        "echo \"Synthesizing with Yosys...\"\nyosys -q -p \"synth_ice40 {SYNTH_FLAGS} -top {top} -json fpga/top.json\" {verilog_list}\n"
+    )
+}
+
+/// Synthesize the whole design with the project's own yosys script, its
+/// variables filled in
+pub fn script_command(script: &str, top: &str, verilog_list: &str) -> String {
+    let script = script
+        .replace("${TOP}", top)
+        .replace("${SOURCES}", verilog_list)
+        .replace("${JSON}", "fpga/top.json");
+    format!(
+        "echo \"Synthesizing with Yosys (custom script)...\"\nrm -f fpga/top.json\ncat > fpga/build/synth.ys <<'AFFOGATO_SYNTH'\n{}\nAFFOGATO_SYNTH\nyosys -q -s fpga/build/synth.ys\n",
+        script.trim_end()
+    )
+}
+
+/// Synthesize one module, flattened, into its netlist
+fn module_command(module: &str, verilog_list: &str) -> String {
+    format!(
+        "yosys -q -p \"synth_ice40 {SYNTH_FLAGS} -top {module}; select {module}; write_json -selected {MODULES_DIR}/{module}.json\" {verilog_list}"
+    )
+}
+
+/// Whether the top instantiates `module` with parameters, `module #(...)`
+fn has_overrides(top_text: &str, module: &str) -> bool {
+    let compact: String = top_text.split_whitespace().collect();
+    let pattern = format!("{}#(", module);
+    compact.match_indices(&pattern).any(|(at, _)| {
+        !compact[..at]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+    })
+}
+
+/// Files named by `` `include "..." ``
+fn includes(text: &str) -> Vec<String> {
+    text.match_indices("`include")
+        .filter_map(|(at, directive)| {
+            let rest = &text[at + directive.len()..];
+            let start = rest.find('"')? + 1;
+            let end = rest[start..].find('"')? + start;
+            Some(rest[start..end].to_string())
+        })
+        .collect()
+}
+
+/// Where yosys finds an include: next to the including file, else relative to
+/// the project root
+fn resolve_include(project_root: &Path, file: &str, include: &str) -> String {
+    let beside = Path::new(file)
+        .parent()
+        .map(|dir| dir.join(include).to_string_lossy().replace('\\', "/"))
+        .unwrap_or_else(|| include.to_string());
+    if project_root.join(&beside).exists() {
+        beside
+    } else {
+        include.to_string()
+    }
+}
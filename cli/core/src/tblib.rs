@@ -0,0 +1,45 @@
+use anyhow::Result;
+use std::path::Path;
+
+use crate::dry_run;
+
+/// Version of the bundled testbench library, bumped on incompatible changes
+pub const VERSION: u32 = 1;
+
+/// Where the library is installed inside a project (relative to the root)
+pub const DIR: &str = ".affogato/tblib";
+
+/// Library sources compiled into the binary
+const FILES: &[(&str, &str)] = &[
+    ("affogato_tb.vh", include_str!("../tblib/affogato_tb.vh")),
+    (
+        "affogato_clock.v",
+        include_str!("../tblib/affogato_clock.v"),
+    ),
+    (
+        "affogato_spi_master.v",
+        include_str!("../tblib/affogato_spi_master.v"),
+    ),
+];
+
+/// Write the testbench library into the project so simulations can use it
+pub fn install(project_root: &Path) -> Result<()> {
+    let dir = project_root.join(DIR);
+    dry_run::create_dir_all(&dir)?;
+
+    for (name, content) in FILES {
+        dry_run::write(dir.join(name), content)?;
+    }
+    dry_run::write(dir.join("VERSION"), format!("{}\n", VERSION))?;
+
+    Ok(())
+}
+
+/// Verilog modules to add to every simulation compile
+pub fn sources() -> Vec<String> {
+    FILES
+        .iter()
+        .filter(|(name, _)| name.ends_with(".v"))
+        .map(|(name, _)| format!("{}/{}", DIR, name))
+        .collect()
+}
@@ -0,0 +1,1825 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::{info_span, warn};
+
+use crate::amaranth;
+use crate::annotate::{self, Level, ReportFormat};
+use crate::build::collect_verilog_files;
+use crate::deps::VENDOR_DIR;
+use crate::docker::Docker;
+use crate::dry_run;
+use crate::error::Failure;
+use crate::output;
+use crate::project::{AmaranthConfig, FpgaConfig, Project, TestConfig, WaveFormat};
+use crate::shared;
+use crate::tblib;
+use crate::vcd::{self, Vcd};
+use crate::verilog;
+use crate::viewer;
+use crate::wavecheck::WaveChecks;
+
+/// Test result with timing information
+struct TestResult {
+    name: String,
+    passed: bool,
+    timed_out: bool,
+    duration: Duration,
+    seed: u32,
+    output: String,
+}
+
+/// Options for a test run, as given on the command line
+#[derive(Default)]
+pub struct TestOptions {
+    pub name: Option<String>,
+    pub view: bool,
+    pub fpga_dir: String,
+    pub verbose: bool,
+    pub parallel: bool,
+    /// Only run tests carrying one of these tags
+    pub tags: Vec<String>,
+    /// Skip tests carrying any of these tags
+    pub skip_tags: Vec<String>,
+    /// Print discovered tests without running them
+    pub list: bool,
+    /// Only rerun tests that failed in the previous run
+    pub failed: bool,
+    /// Collect coverage with Verilator and generate an HTML report
+    pub coverage: bool,
+    /// Fail if merged coverage is below this percentage
+    pub min_coverage: Option<f64>,
+    /// Record each test's waveform as its new golden reference
+    pub bless: bool,
+    /// Overwrite `.expected` snapshots with the current output
+    pub update_snapshots: bool,
+    /// Random seed passed to simulations (chosen per run if not given)
+    pub seed: Option<u32>,
+    /// Only run this shard's share of the tests
+    pub shard: Option<Shard>,
+    /// Balance shards using recorded test durations
+    pub balance: bool,
+    /// Dump waveforms for every test, regardless of the testbench's own dumping
+    pub waves: bool,
+    pub format: ReportFormat,
+    /// Write the results as a JUnit XML report to this path
+    pub report: Option<PathBuf>,
+}
+
+/// One of `count` deterministic partitions of the test suite (`index` is 1-based)
+#[derive(Debug, Clone, Copy)]
+pub struct Shard {
+    pub index: usize,
+    pub count: usize,
+}
+
+impl std::str::FromStr for Shard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (index, count) = s
+            .split_once('/')
+            .ok_or_else(|| format!("expected K/N, got {:?}", s))?;
+        let index: usize = index
+            .parse()
+            .map_err(|_| format!("invalid shard index: {}", index))?;
+        let count: usize = count
+            .parse()
+            .map_err(|_| format!("invalid shard count: {}", count))?;
+        if count == 0 || index == 0 || index > count {
+            return Err(format!(
+                "shard must satisfy 1 <= K <= N, got {}/{}",
+                index, count
+            ));
+        }
+        Ok(Self { index, count })
+    }
+}
+
+impl Shard {
+    /// Keep this shard's tests. Without durations, tests are dealt out
+    /// round-robin in name order; with them, each test goes to the shard with
+    /// the least total recorded time so far, longest tests first.
+    fn select(&self, tests: Vec<Testbench>, durations: Option<&TestHistory>) -> Vec<Testbench> {
+        let mut assignment = vec![0; tests.len()];
+
+        match durations {
+            None => {
+                for (i, shard) in assignment.iter_mut().enumerate() {
+                    *shard = i % self.count;
+                }
+            }
+            Some(history) => {
+                let known: Vec<f64> = tests
+                    .iter()
+                    .filter_map(|tb| history.results.get(&tb.name).map(|r| r.duration_secs))
+                    .collect();
+                let fallback = if known.is_empty() {
+                    1.0
+                } else {
+                    known.iter().sum::<f64>() / known.len() as f64
+                };
+                let duration = |tb: &Testbench| {
+                    history
+                        .results
+                        .get(&tb.name)
+                        .map(|r| r.duration_secs)
+                        .unwrap_or(fallback)
+                };
+
+                let mut order: Vec<usize> = (0..tests.len()).collect();
+                order.sort_by(|&a, &b| {
+                    duration(&tests[b])
+                        .total_cmp(&duration(&tests[a]))
+                        .then(tests[a].name.cmp(&tests[b].name))
+                });
+
+                let mut totals = vec![0.0f64; self.count];
+                for i in order {
+                    let shard = (0..self.count)
+                        .min_by(|&a, &b| totals[a].total_cmp(&totals[b]))
+                        .unwrap();
+                    totals[shard] += duration(&tests[i]);
+                    assignment[i] = shard;
+                }
+            }
+        }
+
+        tests
+            .into_iter()
+            .zip(assignment)
+            .filter(|(_, shard)| *shard == self.index - 1)
+            .map(|(tb, _)| tb)
+            .collect()
+    }
+}
+
+/// Results of previous runs, persisted in `.affogato/test-results.toml`
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct TestHistory {
+    #[serde(default)]
+    results: BTreeMap<String, TestRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TestRecord {
+    passed: bool,
+    #[serde(default)]
+    timed_out: bool,
+    duration_secs: f64,
+    #[serde(default)]
+    seed: Option<u32>,
+}
+
+impl TestHistory {
+    fn path(project: &Project) -> Result<PathBuf> {
+        Ok(project.state_dir()?.join("test-results.toml"))
+    }
+
+    fn load(project: &Project) -> Result<Self> {
+        let path = Self::path(project)?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&path)?;
+        toml::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    fn save(&self, project: &Project) -> Result<()> {
+        dry_run::write(Self::path(project)?, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    fn record(&mut self, results: &[TestResult]) {
+        for result in results {
+            self.results.insert(
+                result.name.clone(),
+                TestRecord {
+                    passed: result.passed,
+                    timed_out: result.timed_out,
+                    duration_secs: result.duration.as_secs_f64(),
+                    seed: Some(result.seed),
+                },
+            );
+        }
+    }
+
+    fn failed(&self, name: &str) -> bool {
+        self.results.get(name).is_some_and(|r| !r.passed)
+    }
+}
+
+/// Shared state for running the testbenches of one invocation
+struct TestRunner<'a> {
+    docker: &'a Docker,
+    project: &'a Project,
+    config: TestConfig,
+    fpga: FpgaConfig,
+    rtl_dir: String,
+    test_dir: String,
+    view: bool,
+    verbose: bool,
+    coverage: bool,
+    bless: bool,
+    update_snapshots: bool,
+    seed: u32,
+    waves: bool,
+}
+
+/// Marker printed by the test script when the simulation is killed
+const TIMEOUT_MARKER: &str = "TIMEOUT: simulation did not finish";
+
+/// Marker printed by the test script when an Amaranth simulation exits successfully
+const AMARANTH_PASS_MARKER: &str = "AFFOGATO: simulation passed";
+
+/// Where per-test coverage data and the merged report are written
+const COVERAGE_DIR: &str = ".affogato/coverage";
+
+/// Where simulation output is copied for snapshot comparison
+const SIM_LOG_DIR: &str = ".affogato/sim";
+
+/// Where simulation waveforms are copied for golden comparison
+const WAVES_DIR: &str = ".affogato/waves";
+
+/// Test directory locations, in order of preference
+fn test_dir_candidates(fpga_dir: &str) -> [String; 4] {
+    [
+        format!("{}/rtl_test", fpga_dir),
+        format!("{}/test", fpga_dir),
+        format!("{}/testbench", fpga_dir),
+        format!("{}_test", fpga_dir),
+    ]
+}
+
+/// Find the project's test directory (relative to the project root)
+pub fn find_test_dir(project_root: &Path, fpga_dir: &str) -> Option<String> {
+    test_dir_candidates(fpga_dir)
+        .into_iter()
+        .find(|d| project_root.join(d).exists())
+}
+
+/// Run Verilog testbenches using iverilog
+pub fn run_tests(docker: &Docker, project: &Project, opts: &TestOptions) -> Result<()> {
+    run_selection(docker, project, opts, None)
+}
+
+/// Latest recorded result for each selected test (`None` if it has never run)
+pub fn rolling_status(
+    project: &Project,
+    opts: &TestOptions,
+) -> Result<Vec<(String, Option<bool>)>> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    let fpga = project
+        .config
+        .as_ref()
+        .map(|c| c.fpga.clone())
+        .unwrap_or_default();
+    let test_dir = match find_test_dir(project_root, &opts.fpga_dir) {
+        Some(d) => d,
+        None if fpga.amaranth.is_some() => format!("{}/rtl_test", opts.fpga_dir),
+        None => return Ok(Vec::new()),
+    };
+    let config = project
+        .config
+        .as_ref()
+        .map(|c| c.test.clone())
+        .unwrap_or_default();
+
+    let tests = discover_tests(
+        project_root,
+        &test_dir,
+        fpga.amaranth.as_ref(),
+        opts.name.as_deref(),
+        &config,
+    )?;
+    let tests = filter_by_tags(tests, &opts.tags, &opts.skip_tags);
+    let history = TestHistory::load(project)?;
+    Ok(tests
+        .into_iter()
+        .map(|tb| {
+            let passed = history.results.get(&tb.name).map(|r| r.passed);
+            (tb.name, passed)
+        })
+        .collect())
+}
+
+/// Run only the testbenches affected by a set of changed files
+pub fn run_affected(
+    docker: &Docker,
+    project: &Project,
+    opts: &TestOptions,
+    changed: &[PathBuf],
+) -> Result<()> {
+    run_selection(docker, project, opts, Some(changed))
+}
+
+fn run_selection(
+    docker: &Docker,
+    project: &Project,
+    opts: &TestOptions,
+    changed: Option<&[PathBuf]>,
+) -> Result<()> {
+    let project_root = project.root.as_ref().unwrap();
+    let fpga_dir = &opts.fpga_dir;
+    let test_name = opts.name.as_deref();
+    let fpga = project
+        .config
+        .as_ref()
+        .map(|c| c.fpga.clone())
+        .unwrap_or_default();
+
+    let test_dir = match find_test_dir(project_root, fpga_dir) {
+        Some(d) => d,
+        // Amaranth simulations live with the designs, so a project may have no testbenches
+        None if fpga.amaranth.is_some() => format!("{}/rtl_test", fpga_dir),
+        None => {
+            outln!("{}", "No test directory found. Expected one of:".yellow());
+            for d in &test_dir_candidates(fpga_dir) {
+                outln!("  - {}", d);
+            }
+            return Ok(());
+        }
+    };
+
+    // Find RTL source directory
+    let rtl_dir = format!("{}/rtl", fpga_dir);
+    if !project_root.join(&rtl_dir).exists() {
+        bail!("RTL directory not found: {}", rtl_dir);
+    }
+    if let Some(config) = &project.config {
+        shared::write(project_root, &config.shared)?;
+    }
+
+    let config = project
+        .config
+        .as_ref()
+        .map(|c| c.test.clone())
+        .unwrap_or_default();
+
+    // Discover tests
+    let tests = discover_tests(
+        project_root,
+        &test_dir,
+        fpga.amaranth.as_ref(),
+        test_name,
+        &config,
+    )?;
+    let mut tests = filter_by_tags(tests, &opts.tags, &opts.skip_tags);
+    let mut history = TestHistory::load(project)?;
+
+    if opts.failed {
+        tests.retain(|tb| history.failed(&tb.name));
+        if tests.is_empty() {
+            outln!("{}", "No failed tests from the previous run".green());
+            return Ok(());
+        }
+    }
+
+    if tests.is_empty() {
+        outln!("{}", "No tests found".yellow());
+        return Ok(());
+    }
+
+    if let Some(shard) = opts.shard {
+        let total = tests.len();
+        tests = shard.select(tests, opts.balance.then_some(&history));
+        outln!(
+            "{}",
+            format!(
+                "Shard {}/{}: {} of {} test(s)",
+                shard.index,
+                shard.count,
+                tests.len(),
+                total
+            )
+            .dimmed()
+        );
+        if tests.is_empty() {
+            return Ok(());
+        }
+    }
+
+    if let Some(changed) = changed {
+        let affected = affected_tests(project_root, &rtl_dir, &tests, changed)?;
+        tests.retain(|tb| affected.contains(&tb.name));
+        if tests.is_empty() {
+            outln!("{}", "No tests affected by this change".dimmed());
+            return Ok(());
+        }
+    }
+
+    if opts.list {
+        list_tests(&tests, &history);
+        return Ok(());
+    }
+
+    let runner = TestRunner {
+        docker,
+        project,
+        config,
+        fpga,
+        rtl_dir,
+        test_dir,
+        view: opts.view,
+        verbose: opts.verbose,
+        coverage: opts.coverage,
+        bless: opts.bless,
+        update_snapshots: opts.update_snapshots,
+        seed: opts.seed.unwrap_or_else(random_seed),
+        waves: opts.waves,
+    };
+
+    tblib::install(project_root)?;
+
+    if opts.coverage {
+        // Start from a clean slate so stale data from removed tests isn't merged
+        let coverage_dir = project_root.join(COVERAGE_DIR);
+        if coverage_dir.exists() {
+            dry_run::remove_dir_all(&coverage_dir)?;
+        }
+    }
+
+    let test_count = tests.len();
+    outln!(
+        "{}",
+        format!("==> Running {} test(s) (seed {})", test_count, runner.seed)
+            .blue()
+            .bold()
+    );
+
+    let start_time = Instant::now();
+    let results = if opts.parallel && test_count > 1 {
+        runner.run_parallel(&tests)?
+    } else {
+        runner.run_sequential(&tests)?
+    };
+
+    let total_duration = start_time.elapsed();
+    if dry_run::is_enabled() {
+        return Ok(());
+    }
+
+    history.record(&results);
+    history.save(project)?;
+    report_results(&results, runner.seed, total_duration);
+    if let Some(path) = &opts.report {
+        write_junit(
+            path,
+            &runner.test_dir,
+            &results,
+            runner.seed,
+            total_duration,
+        )?;
+    }
+
+    // Print summary
+    outln!();
+    outln!("{}", "Test Results:".bold());
+    let mut all_passed = true;
+    let mut pass_count = 0;
+
+    for result in &results {
+        let status = if result.passed {
+            pass_count += 1;
+            "PASS".green()
+        } else if result.timed_out {
+            all_passed = false;
+            "TIMEOUT".red()
+        } else {
+            all_passed = false;
+            "FAIL".red()
+        };
+        outln!(
+            "  {:40} {} ({:.2}s, seed {})",
+            result.name,
+            status,
+            result.duration.as_secs_f64(),
+            result.seed
+        );
+    }
+
+    let failures: Vec<_> = results.iter().filter(|r| !r.passed).collect();
+    if !failures.is_empty() {
+        outln!();
+        outln!("{}", "Reproduce failures with:".bold());
+        for result in &failures {
+            outln!("  affogato test {} --seed {}", result.name, result.seed);
+        }
+
+        if opts.format == ReportFormat::Gha {
+            outln!();
+            for result in &failures {
+                for annotation in annotate::tool_errors(project_root, &result.output) {
+                    outln!("{}", annotation);
+                }
+                let reason = result
+                    .output
+                    .lines()
+                    .map(str::trim)
+                    .find(|l| {
+                        let upper = l.to_uppercase();
+                        upper.contains("FAIL") || upper.contains("TIMEOUT")
+                    })
+                    .unwrap_or("Test failed");
+                outln!(
+                    "{}",
+                    annotate::workflow_command(
+                        Level::Error,
+                        project_root,
+                        Some(&format!("{}/{}_tb.v", runner.test_dir, result.name)),
+                        0,
+                        0,
+                        Some(&format!(
+                            "Test {} failed (seed {})",
+                            result.name, result.seed
+                        )),
+                        reason,
+                    )
+                );
+            }
+        }
+    }
+
+    // Print timing summary
+    outln!();
+    outln!(
+        "{} {} passed, {} failed in {:.2}s",
+        "Summary:".bold(),
+        pass_count.to_string().green(),
+        (test_count - pass_count).to_string().red(),
+        total_duration.as_secs_f64()
+    );
+
+    if opts.view {
+        if let Err(e) = runner.view_waveforms(&results) {
+            warn!("Could not open waveform viewer: {:#}", e);
+        }
+    }
+
+    if opts.coverage {
+        let min_coverage = opts.min_coverage.or(runner.config.min_coverage);
+        report_coverage(docker, project, min_coverage)?;
+    }
+
+    if !all_passed {
+        return Err(Failure::TestsFailed.error("Some tests failed"));
+    }
+
+    Ok(())
+}
+
+/// Record each test's outcome and the totals in the `--json` output
+fn report_results(results: &[TestResult], seed: u32, duration: Duration) {
+    let passed = results.iter().filter(|r| r.passed).count();
+    output::report(
+        "tests",
+        results
+            .iter()
+            .map(|r| {
+                json!({
+                    "name": r.name,
+                    "passed": r.passed,
+                    "timed_out": r.timed_out,
+                    "duration_secs": r.duration.as_secs_f64(),
+                    "seed": r.seed,
+                })
+            })
+            .collect::<Vec<_>>(),
+    );
+    output::report(
+        "summary",
+        json!({
+            "passed": passed,
+            "failed": results.len() - passed,
+            "duration_secs": duration.as_secs_f64(),
+            "seed": seed,
+        }),
+    );
+}
+
+/// Write the results as JUnit XML, which CI systems show as a test report
+fn write_junit(
+    path: &Path,
+    test_dir: &str,
+    results: &[TestResult],
+    seed: u32,
+    duration: Duration,
+) -> Result<()> {
+    let tests = results.len();
+    let failures = results.iter().filter(|r| !r.passed).count();
+    let time = duration.as_secs_f64();
+    let mut lines = vec![
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#.to_string(),
+        format!(r#"<testsuites tests="{tests}" failures="{failures}" time="{time:.3}">"#),
+        format!(
+            r#"  <testsuite name="affogato" tests="{tests}" failures="{failures}" time="{time:.3}">"#
+        ),
+        "    <properties>".to_string(),
+        format!(r#"      <property name="seed" value="{seed}"/>"#),
+        "    </properties>".to_string(),
+    ];
+    for result in results {
+        let name = xml_escape(&result.name);
+        let dir = xml_escape(test_dir);
+        lines.push(format!(
+            r#"    <testcase name="{name}" classname="{dir}" file="{dir}/{name}_tb.v" time="{:.3}">"#,
+            result.duration.as_secs_f64()
+        ));
+        if !result.passed {
+            let (kind, message) = if result.timed_out {
+                ("timeout", "Test timed out")
+            } else {
+                ("failure", "Test failed")
+            };
+            lines.push(format!(
+                r#"      <failure type="{kind}" message="{message}">Reproduce with: affogato test {name} --seed {}</failure>"#,
+                result.seed
+            ));
+        }
+        lines.push(format!(
+            "      <system-out>{}</system-out>",
+            xml_escape(&result.output)
+        ));
+        lines.push("    </testcase>".to_string());
+    }
+    lines.push("  </testsuite>".to_string());
+    lines.push("</testsuites>".to_string());
+    dry_run::write(path, lines.join("\n") + "\n")
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Escape text for an XML attribute or element, dropping control characters
+/// XML can't carry
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\n' | '\t' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Merge per-test coverage data, write the HTML report, and apply the gate
+fn report_coverage(docker: &Docker, project: &Project, min_coverage: Option<f64>) -> Result<()> {
+    outln!();
+    outln!("{}", "==> Merging coverage".blue().bold());
+
+    let script = format!(
+        r#"
+cd /workspace/{COVERAGE_DIR} 2>/dev/null || {{ echo "No coverage data collected"; exit 0; }}
+ls *.dat >/dev/null 2>&1 || {{ echo "No coverage data collected"; exit 0; }}
+verilator_coverage --annotate annotated --write-info coverage.info *.dat 2>&1
+if command -v genhtml >/dev/null 2>&1; then
+    genhtml -q coverage.info -o html 2>&1 && echo "HTML report: {COVERAGE_DIR}/html/index.html"
+else
+    echo "genhtml not found in container; skipping HTML report"
+fi
+"#
+    );
+
+    let output = docker.run_in_project_capture(project, &["bash", "-c", &script], false)?;
+    for line in output.lines() {
+        outln!("  {}", line);
+    }
+
+    let total = parse_total_coverage(&output);
+    if let (Some(min), Some(total)) = (min_coverage, total) {
+        if total < min {
+            return Err(Failure::TestsFailed.error(format!(
+                "Coverage {:.2}% is below the minimum of {:.2}%",
+                total, min
+            )));
+        }
+        outln!(
+            "{}",
+            format!("Coverage {:.2}% meets the minimum of {:.2}%", total, min).green()
+        );
+    } else if min_coverage.is_some() {
+        bail!("Could not determine total coverage for the minimum-coverage gate");
+    }
+
+    Ok(())
+}
+
+/// Extract the percentage from verilator_coverage's "Total coverage (a/b) p%" line
+fn parse_total_coverage(output: &str) -> Option<f64> {
+    output
+        .lines()
+        .find(|l| l.contains("Total coverage"))
+        .and_then(|l| l.split_whitespace().last())
+        .and_then(|p| p.trim_end_matches('%').parse().ok())
+}
+
+impl TestRunner<'_> {
+    /// Open the waveform of a single test, or list them when several ran
+    fn view_waveforms(&self, results: &[TestResult]) -> Result<()> {
+        let project_root = self.project.root.as_ref().unwrap();
+        let ext = self.config.wave_format.extension();
+        let waves: Vec<_> = results
+            .iter()
+            .map(|r| {
+                (
+                    r.name.as_str(),
+                    project_root
+                        .join(WAVES_DIR)
+                        .join(format!("{}.{}", r.name, ext)),
+                )
+            })
+            .filter(|(_, wave)| wave.exists())
+            .collect();
+
+        outln!();
+        match waves.as_slice() {
+            [] => outln!(
+                "{}",
+                "No waveforms were produced (does the testbench call $dumpfile? Try --waves)"
+                    .yellow()
+            ),
+            [(name, wave)] => {
+                let layout_base = project_root
+                    .join(&self.test_dir)
+                    .join(format!("{}_tb", name));
+                viewer::open(wave, &layout_base, self.config.viewer.as_deref())?;
+            }
+            _ => {
+                outln!("{}", "Waveforms:".bold());
+                for (_, wave) in &waves {
+                    let relative = wave.strip_prefix(project_root).unwrap_or(wave);
+                    outln!("  {}", relative.display());
+                }
+                outln!("Open one with: affogato test <name> --view");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn run_sequential(&self, tests: &[Testbench]) -> Result<Vec<TestResult>> {
+        let mut results = Vec::new();
+
+        for test in tests {
+            let result = match test.kind {
+                TestKind::Verilog => self.run_single(&test.name)?,
+                TestKind::Amaranth => self.run_amaranth(test)?,
+            };
+            results.push(result);
+        }
+
+        Ok(results)
+    }
+
+    fn run_parallel(&self, tests: &[Testbench]) -> Result<Vec<TestResult>> {
+        // Parallel execution would require Docker struct to impl Clone/Send
+        // For now, fall back to sequential execution
+        outln!(
+            "{}",
+            "Note: Parallel execution not yet implemented, running sequentially".dimmed()
+        );
+        self.run_sequential(tests)
+    }
+
+    /// Shell snippet compiling the RTL sources and testbench into `$TMPDIR/test`
+    fn compile_script(&self, test_name: &str) -> String {
+        let extra = self.compile_args(test_name);
+        let format = self.config.wave_format;
+        if self.coverage {
+            // Verilator needs tracing enabled for the testbench's own $dumpvars
+            let trace = match (self.waves, format) {
+                (false, _) => "",
+                (true, WaveFormat::Vcd) => "--trace",
+                (true, WaveFormat::Fst) => "--trace-fst",
+            };
+            format!(
+                r#"# Compile with verilator, instrumented for coverage; pipefail keeps
+# verilator's status through the filter, so set -e stops on a failed compile
+set -o pipefail
+verilator --binary --timing --coverage {trace} -Wno-fatal -Wno-lint -Wno-style \
+    -DNO_ICE40_DEFAULT_ASSIGNMENTS \
+    --top-module {test_name}_tb \
+    -I{tblib_dir} {extra} \
+    -Mdir $TMPDIR/obj -o test \
+    $RTL_FILES \
+    {test_dir}/{test_name}_tb.v \
+    2>&1 | {{ grep -v '^- V' || true; }}
+set +o pipefail
+cp $TMPDIR/obj/test $TMPDIR/test"#,
+                test_name = test_name,
+                test_dir = self.test_dir,
+                tblib_dir = tblib::DIR,
+                extra = extra,
+            )
+        } else {
+            // An extra top-level module dumps the whole testbench hierarchy
+            let (dump, dump_top) = if self.waves {
+                let dump = format!(
+                    r#"cat > $TMPDIR/affogato_waves.v <<'AFFOGATO_EOF'
+module affogato_waves;
+    initial begin
+        $dumpfile("{test_name}_tb.{ext}");
+        $dumpvars(0, {test_name}_tb);
+    end
+endmodule
+AFFOGATO_EOF
+"#,
+                    ext = format.extension(),
+                );
+                (dump, "-s affogato_waves $TMPDIR/affogato_waves.v")
+            } else {
+                (String::new(), "")
+            };
+            format!(
+                r#"# Compile with iverilog
+{dump}iverilog -g2012 -Wall \
+    -DNO_ICE40_DEFAULT_ASSIGNMENTS \
+    -s {test_name}_tb {dump_top} \
+    -I{tblib_dir} {extra} \
+    -o $TMPDIR/test \
+    $RTL_FILES \
+    {test_dir}/{test_name}_tb.v \
+    2>&1"#,
+                test_name = test_name,
+                test_dir = self.test_dir,
+                tblib_dir = tblib::DIR,
+                extra = extra,
+            )
+        }
+    }
+
+    /// Command that runs the compiled simulation in `$TMPDIR`
+    fn run_command(&self) -> &'static str {
+        match (self.coverage, self.config.wave_format) {
+            (true, _) => "./test",
+            (false, WaveFormat::Vcd) => "vvp ./test",
+            // vvp writes $dumpfile output as FST with this extended argument
+            (false, WaveFormat::Fst) => "vvp ./test -fst",
+        }
+    }
+
+    /// The seed define, the `[shared]` header's include path, per-test `-D`
+    /// defines and extra simulator flags, shell-quoted
+    fn compile_args(&self, test_name: &str) -> String {
+        let mut args = vec![format!("-DAFFOGATO_SEED={}", self.seed)];
+        // Testbenches include the [shared] constants as the RTL does
+        let project_root = self.project.root.as_ref().unwrap();
+        if project_root.join(shared::VERILOG_HEADER).exists() {
+            if let Some(dir) = Path::new(shared::VERILOG_HEADER).parent() {
+                args.push(format!("-I{}", dir.display()));
+            }
+        }
+        let Some(tb) = self.config.tests.get(test_name) else {
+            return args.join(" ");
+        };
+        args.into_iter()
+            .chain(tb.defines.iter().map(|d| format!("-D{}", d)))
+            .chain(tb.flags.iter().cloned())
+            .map(|a| shell_quote(&a))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Shell expression listing the RTL sources to compile for a test
+    fn rtl_sources(&self, test_name: &str) -> Result<String> {
+        let sources = self
+            .config
+            .tests
+            .get(test_name)
+            .map(|t| t.sources.as_slice())
+            .unwrap_or_default();
+
+        let project_root = self.project.root.as_ref().unwrap();
+        if sources.is_empty() {
+            let mut dirs = self.rtl_dir.clone();
+            if project_root.join(VENDOR_DIR).exists() {
+                dirs = format!("{} {}", dirs, VENDOR_DIR);
+            }
+            let mut sources = format!("$(find {} -name '*.v' | tr '\\n' ' ')", dirs);
+            for output in amaranth::outputs(&self.fpga)? {
+                sources = format!("{} {}", sources, output);
+            }
+            return Ok(sources);
+        }
+
+        let mut files = Vec::new();
+        for pattern in sources {
+            let full = project_root.join(pattern);
+            if full.is_dir() {
+                collect_verilog_files(&full, project_root, &mut files)?;
+                continue;
+            }
+            let matches = glob::glob(&full.to_string_lossy())
+                .with_context(|| format!("Invalid source pattern: {}", pattern))?;
+            let before = files.len();
+            for path in matches {
+                let path = path?;
+                files.push(path.strip_prefix(project_root)?.display().to_string());
+            }
+            if files.len() == before {
+                bail!("Source for test {} not found: {}", test_name, pattern);
+            }
+        }
+
+        Ok(files
+            .iter()
+            .map(|f| shell_quote(f))
+            .collect::<Vec<_>>()
+            .join(" "))
+    }
+
+    /// Runtime plusargs (the run's seed plus per-test ones), shell-quoted
+    fn plusargs(&self, test_name: &str) -> String {
+        let configured = self
+            .config
+            .tests
+            .get(test_name)
+            .map(|t| t.plusargs.as_slice())
+            .unwrap_or_default();
+
+        std::iter::once(format!("+seed={}", self.seed))
+            .chain(
+                configured
+                    .iter()
+                    .map(|p| shell_quote(&format!("+{}", p.trim_start_matches('+')))),
+            )
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Shell snippet saving the simulation's coverage data for merging
+    fn collect_coverage_script(&self, test_name: &str) -> String {
+        if !self.coverage {
+            return String::new();
+        }
+        format!(
+            r#"
+# Save coverage data for merging
+if [ -f coverage.dat ]; then
+    mkdir -p /workspace/{COVERAGE_DIR}
+    cp coverage.dat /workspace/{COVERAGE_DIR}/{test_name}.dat
+fi"#
+        )
+    }
+
+    fn golden_path(&self, test_name: &str) -> PathBuf {
+        self.project
+            .root
+            .as_ref()
+            .unwrap()
+            .join(&self.test_dir)
+            .join("golden")
+            .join(format!("{}.vcd", test_name))
+    }
+
+    fn checks_path(&self, test_name: &str) -> PathBuf {
+        self.project
+            .root
+            .as_ref()
+            .unwrap()
+            .join(&self.test_dir)
+            .join(format!("{}_tb.toml", test_name))
+    }
+
+    fn snapshot_path(&self, test_name: &str) -> PathBuf {
+        self.project
+            .root
+            .as_ref()
+            .unwrap()
+            .join(&self.test_dir)
+            .join(format!("{}_tb.expected", test_name))
+    }
+
+    /// Update or compare against the `.expected` output snapshot, returning a mismatch message
+    fn check_snapshot(&self, test_name: &str, sim_log: &Path) -> Result<Option<String>> {
+        let snapshot = self.snapshot_path(test_name);
+
+        if !self.update_snapshots && !snapshot.exists() {
+            return Ok(None);
+        }
+        if !sim_log.exists() {
+            return Ok(Some(
+                "FAIL: no simulation output captured for snapshot".to_string(),
+            ));
+        }
+
+        let actual = normalize_sim_output(&fs::read_to_string(sim_log)?);
+
+        if self.update_snapshots {
+            dry_run::write(&snapshot, &actual)?;
+            return Ok(None);
+        }
+
+        let expected = normalize_sim_output(&fs::read_to_string(&snapshot)?);
+        Ok(snapshot_diff(&expected, &actual).map(|diff| {
+            format!(
+                "FAIL: output differs from {}_tb.expected (run with --update-snapshots to accept)\n{}",
+                test_name, diff
+            )
+        }))
+    }
+
+    /// Bless or compare against the golden waveform, returning a mismatch message
+    fn check_golden(&self, test_name: &str, waves: &Path) -> Result<Option<String>> {
+        let golden = self.golden_path(test_name);
+
+        if self.config.wave_format != WaveFormat::Vcd && (self.bless || golden.exists()) {
+            return Ok(Some(
+                "FAIL: golden waveforms require wave_format = \"vcd\"".to_string(),
+            ));
+        }
+
+        if self.bless {
+            if !waves.exists() {
+                return Ok(Some(
+                    "FAIL: no VCD produced to bless (does the testbench call $dumpfile?)"
+                        .to_string(),
+                ));
+            }
+            dry_run::create_dir_all(golden.parent().unwrap())?;
+            dry_run::copy(waves, &golden)?;
+            return Ok(None);
+        }
+
+        if !golden.exists() {
+            return Ok(None);
+        }
+        if !waves.exists() {
+            return Ok(Some(
+                "FAIL: golden waveform exists but no VCD was produced".to_string(),
+            ));
+        }
+
+        let expected = Vcd::load(&golden)?;
+        let actual = Vcd::load(waves)?;
+        let patterns = self
+            .config
+            .tests
+            .get(test_name)
+            .map(|t| t.compare.clone())
+            .unwrap_or_default();
+        let signals = expected.select(&patterns)?;
+
+        Ok(
+            vcd::first_divergence(&expected, &actual, &signals).map(|d| {
+                format!(
+                    "FAIL: waveform diverges from golden at time {} ({}): {} expected {} got {}",
+                    d.time, expected.timescale, d.signal, d.expected, d.actual
+                )
+            }),
+        )
+    }
+
+    /// Evaluate the `<name>_tb.toml` waveform checks, returning a failure message
+    fn check_waves(&self, test_name: &str, waves: &Path) -> Result<Option<String>> {
+        let path = self.checks_path(test_name);
+        if !path.exists() {
+            return Ok(None);
+        }
+        if self.config.wave_format != WaveFormat::Vcd {
+            return Ok(Some(
+                "FAIL: waveform checks require wave_format = \"vcd\"".to_string(),
+            ));
+        }
+        if !waves.exists() {
+            return Ok(Some(
+                "FAIL: waveform checks declared but no VCD was produced".to_string(),
+            ));
+        }
+
+        let checks = WaveChecks::load(&path)?;
+        let failures = checks.evaluate(&Vcd::load(waves)?)?;
+        if failures.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(
+            failures
+                .iter()
+                .map(|f| format!("FAIL: {}", f))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        ))
+    }
+
+    fn run_single(&self, test_name: &str) -> Result<TestResult> {
+        let _span = info_span!("test", name = test_name, seed = self.seed).entered();
+        let verbose = self.verbose;
+        let timeout = self.config.timeout_for(test_name)?.as_secs().max(1);
+        let wall_clock = self.config.wall_clock_for(test_name)?;
+
+        if !verbose {
+            out!("  Testing {:40} ", test_name);
+        } else {
+            outln!("  {} {}", "Testing".blue(), test_name.bold());
+        }
+
+        let start = Instant::now();
+
+        let project_root = self.project.root.as_ref().unwrap();
+        let waves = project_root.join(WAVES_DIR).join(format!(
+            "{}.{}",
+            test_name,
+            self.config.wave_format.extension()
+        ));
+        if waves.exists() {
+            dry_run::remove_file(&waves)?;
+        }
+        let save_waves = self.waves
+            || self.view
+            || self.bless
+            || self.golden_path(test_name).exists()
+            || self.checks_path(test_name).exists();
+
+        let sim_log = project_root
+            .join(SIM_LOG_DIR)
+            .join(format!("{}.log", test_name));
+        if sim_log.exists() {
+            dry_run::remove_file(&sim_log)?;
+        }
+        let has_snapshot = self.update_snapshots || self.snapshot_path(test_name).exists();
+
+        // Build the iverilog command that:
+        // 1. Compiles all RTL sources + the testbench
+        // 2. Runs the simulation
+        // 3. Checks for errors in output
+        let script = format!(
+            r#"
+set -e
+cd /workspace
+{elaborate}
+# Create temp directory for test
+TMPDIR=$(mktemp -d)
+trap "rm -rf $TMPDIR" EXIT
+
+# RTL sources, plus the bundled testbench library
+RTL_FILES="{rtl_sources} {tblib_sources}"
+
+{compile}
+
+# Run simulation, killing it if it never reaches $finish
+cd $TMPDIR
+set +e
+timeout --kill-after=5 {timeout} {run} {plusargs} > $TMPDIR/sim.log 2>&1
+STATUS=$?
+set -e
+cat $TMPDIR/sim.log
+
+# Save simulation output for snapshot comparison
+if [ "{save_sim_log}" = "true" ]; then
+    mkdir -p /workspace/{SIM_LOG_DIR}
+    cp $TMPDIR/sim.log /workspace/{SIM_LOG_DIR}/{test_name}.log
+fi
+
+if [ $STATUS -eq 124 ] || [ $STATUS -eq 137 ]; then
+    echo '{timeout_marker} within {timeout}s (missing $finish?)'
+    exit 1
+fi
+
+WAVE=$(ls *.{ext} 2>/dev/null | head -1 || true)
+
+# Save waveform for --waves, --view and golden comparison
+if [ "{save_waves}" = "true" ] && [ -n "$WAVE" ]; then
+    mkdir -p /workspace/{WAVES_DIR}
+    cp $WAVE /workspace/{WAVES_DIR}/{test_name}.{ext}
+    if [ "{waves}" = "true" ]; then
+        echo "Waveform saved to {WAVES_DIR}/{test_name}.{ext}"
+    fi
+fi
+{collect_coverage}
+"#,
+            save_waves = save_waves,
+            waves = self.waves,
+            ext = self.config.wave_format.extension(),
+            run = self.run_command(),
+            save_sim_log = has_snapshot,
+            compile = self.compile_script(test_name),
+            collect_coverage = self.collect_coverage_script(test_name),
+            rtl_sources = self.rtl_sources(test_name)?,
+            elaborate = amaranth::elaborate_script(&self.fpga)?,
+            plusargs = self.plusargs(test_name),
+            tblib_sources = tblib::sources().join(" "),
+            timeout = timeout,
+            timeout_marker = TIMEOUT_MARKER,
+        );
+
+        // Run in docker and capture output, killing the container if it hangs
+        let (mut output, killed) = self.docker.run_in_project_capture_with_limit(
+            self.project,
+            &["bash", "-c", &script],
+            wall_clock,
+        )?;
+        if dry_run::is_enabled() {
+            return Ok(TestResult {
+                name: test_name.to_string(),
+                passed: true,
+                timed_out: false,
+                duration: Duration::ZERO,
+                seed: self.seed,
+                output,
+            });
+        }
+        if killed {
+            output.push_str(&format!(
+                "{}: container killed after wall-clock limit of {}s\n",
+                TIMEOUT_MARKER,
+                wall_clock.as_secs()
+            ));
+        }
+
+        let duration = start.elapsed();
+
+        let timed_out = output.contains(TIMEOUT_MARKER);
+        // Snapshot tests are judged by their output rather than a PASS line
+        let mut passed = !timed_out
+            && !output.to_lowercase().contains("error")
+            && !output.to_lowercase().contains("fail")
+            && (has_snapshot || output.to_lowercase().contains("pass"));
+
+        if passed {
+            let mismatch = match self.check_snapshot(test_name, &sim_log)? {
+                Some(mismatch) => Some(mismatch),
+                None => match self.check_golden(test_name, &waves)? {
+                    Some(mismatch) => Some(mismatch),
+                    None => self.check_waves(test_name, &waves)?,
+                },
+            };
+            if let Some(mismatch) = mismatch {
+                passed = false;
+                output.push_str(&mismatch);
+                output.push('\n');
+            }
+        }
+
+        self.print_result(passed, &output, duration);
+
+        Ok(TestResult {
+            name: test_name.to_string(),
+            passed,
+            timed_out,
+            duration,
+            seed: self.seed,
+            output,
+        })
+    }
+
+    /// Run an Amaranth simulation script; it passes if the script exits successfully
+    fn run_amaranth(&self, test: &Testbench) -> Result<TestResult> {
+        let _span = info_span!("test", name = %test.name, seed = self.seed).entered();
+        let amaranth = self
+            .fpga
+            .amaranth
+            .as_ref()
+            .context("No [fpga.amaranth] section in affogato.toml")?;
+        let timeout = self.config.timeout_for(&test.name)?.as_secs().max(1);
+        let wall_clock = self.config.wall_clock_for(&test.name)?;
+
+        if !self.verbose {
+            out!("  Testing {:40} ", test.name);
+        } else {
+            outln!("  {} {}", "Testing".blue(), test.name.bold());
+        }
+
+        let start = Instant::now();
+        let script_name = Path::new(&test.path)
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let script = format!(
+            r#"
+cd /workspace
+set +e
+timeout --kill-after=5 {timeout} bash -c {command} 2>&1
+STATUS=$?
+if [ $STATUS -eq 124 ] || [ $STATUS -eq 137 ]; then
+    echo '{TIMEOUT_MARKER} within {timeout}s'
+elif [ $STATUS -eq 0 ]; then
+    echo '{AMARANTH_PASS_MARKER}'
+fi
+"#,
+            command = shell_quote(&amaranth::simulation_command(
+                amaranth,
+                &script_name,
+                self.seed
+            )),
+        );
+
+        let (mut output, killed) = self.docker.run_in_project_capture_with_limit(
+            self.project,
+            &["bash", "-c", &script],
+            wall_clock,
+        )?;
+        if dry_run::is_enabled() {
+            return Ok(TestResult {
+                name: test.name.clone(),
+                passed: true,
+                timed_out: false,
+                duration: Duration::ZERO,
+                seed: self.seed,
+                output,
+            });
+        }
+        if killed {
+            output.push_str(&format!(
+                "{}: container killed after wall-clock limit of {}s\n",
+                TIMEOUT_MARKER,
+                wall_clock.as_secs()
+            ));
+        }
+
+        let duration = start.elapsed();
+        let timed_out = output.contains(TIMEOUT_MARKER);
+        let passed = !timed_out && output.lines().last() == Some(AMARANTH_PASS_MARKER);
+        self.print_result(passed, &output, duration);
+
+        Ok(TestResult {
+            name: test.name.clone(),
+            passed,
+            timed_out,
+            duration,
+            seed: self.seed,
+            output,
+        })
+    }
+
+    /// Print a test's status, with its output if it failed or in verbose mode
+    fn print_result(&self, passed: bool, output: &str, duration: Duration) {
+        if self.verbose {
+            // Always show output in verbose mode
+            outln!("{}", "--- Output ---".dimmed());
+            for line in output.lines() {
+                outln!("    {}", highlight_output(line));
+            }
+            outln!("{}", "--------------".dimmed());
+            let status = if passed { "PASS".green() } else { "FAIL".red() };
+            outln!("  Result: {} ({:.2}s)", status, duration.as_secs_f64());
+            outln!();
+        } else if passed {
+            outln!("{}", "PASS".green());
+        } else {
+            outln!("{}", "FAIL".red());
+            // Print output on failure
+            outln!("{}", "--- Output ---".dimmed());
+            for line in output.lines() {
+                outln!("    {}", highlight_output(line));
+            }
+            outln!("{}", "--------------".dimmed());
+        }
+    }
+}
+
+/// Map changed files to the names of the testbenches that depend on them.
+///
+/// A changed testbench selects itself. A changed RTL file selects every
+/// testbench that instantiates one of its modules, directly or through other
+/// RTL modules. Changes that can't be mapped (e.g. include files) select all
+/// tests.
+fn affected_tests(
+    project_root: &Path,
+    rtl_dir: &str,
+    tests: &[Testbench],
+    changed: &[PathBuf],
+) -> Result<BTreeSet<String>> {
+    let all = || tests.iter().map(|tb| tb.name.clone()).collect();
+
+    let mut rtl_files = Vec::new();
+    collect_verilog_files(&project_root.join(rtl_dir), project_root, &mut rtl_files)?;
+
+    // (defined modules, referenced identifiers) per RTL file
+    let mut sources = BTreeMap::new();
+    for file in &rtl_files {
+        let source = fs::read_to_string(project_root.join(file))?;
+        sources.insert(
+            project_root.join(file),
+            (
+                verilog::defined_modules(&source),
+                verilog::identifiers(&source),
+            ),
+        );
+    }
+
+    let mut affected_tests = BTreeSet::new();
+    let mut affected_modules = BTreeSet::new();
+
+    for path in changed {
+        if let Some(tb) = tests.iter().find(|tb| project_root.join(&tb.path) == *path) {
+            affected_tests.insert(tb.name.clone());
+        } else if let Some((modules, _)) = sources.get(path) {
+            affected_modules.extend(modules.iter().cloned());
+        } else {
+            return Ok(all());
+        }
+    }
+
+    // Propagate to modules that instantiate an affected module
+    loop {
+        let before = affected_modules.len();
+        for (modules, idents) in sources.values() {
+            if idents.iter().any(|i| affected_modules.contains(i)) {
+                affected_modules.extend(modules.iter().cloned());
+            }
+        }
+        if affected_modules.len() == before {
+            break;
+        }
+    }
+
+    for tb in tests {
+        let source = fs::read_to_string(project_root.join(&tb.path))?;
+        if verilog::identifiers(&source)
+            .iter()
+            .any(|i| affected_modules.contains(i))
+        {
+            affected_tests.insert(tb.name.clone());
+        }
+    }
+
+    Ok(affected_tests)
+}
+
+/// Print discovered testbenches without running them
+fn list_tests(tests: &[Testbench], history: &TestHistory) {
+    output::report(
+        "tests",
+        tests
+            .iter()
+            .map(|tb| {
+                json!({
+                    "name": tb.name,
+                    "path": tb.path,
+                    "tags": tb.tags,
+                    "last_passed": history.results.get(&tb.name).map(|r| r.passed),
+                })
+            })
+            .collect::<Vec<_>>(),
+    );
+    outln!("{}", format!("{} test(s):", tests.len()).blue().bold());
+    for tb in tests {
+        let last = match history.results.get(&tb.name) {
+            Some(r) if r.passed => "PASS".green(),
+            Some(r) if r.timed_out => "TIMEOUT".red(),
+            Some(_) => "FAIL".red(),
+            None => "-".dimmed(),
+        };
+        let tags = if tb.tags.is_empty() {
+            String::new()
+        } else {
+            format!("[{}]", tb.tags.join(", "))
+        };
+        outln!(
+            "  {:30} {:7} {:40} {}",
+            tb.name.green(),
+            last,
+            tb.path.dimmed(),
+            tags.cyan()
+        );
+    }
+}
+
+/// A discovered testbench and its metadata
+struct Testbench {
+    name: String,
+    /// Testbench source, relative to the project root
+    path: String,
+    tags: Vec<String>,
+    kind: TestKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TestKind {
+    /// `<name>_tb.v`, simulated with iverilog or Verilator
+    Verilog,
+    /// `test_*.py` next to the Amaranth designs, run with Python
+    Amaranth,
+}
+
+fn discover_tests(
+    project_root: &Path,
+    test_dir: &str,
+    amaranth: Option<&AmaranthConfig>,
+    pattern: Option<&str>,
+    config: &TestConfig,
+) -> Result<Vec<Testbench>> {
+    let test_path = project_root.join(test_dir);
+
+    let pattern = match pattern {
+        Some(name) if !is_glob(name) => {
+            // Run specific test
+            let tb_file = test_path.join(format!("{}_tb.v", name));
+            if tb_file.exists() {
+                return Ok(vec![load_testbench(test_dir, &test_path, name, config)?]);
+            }
+            if let Some(amaranth) = amaranth {
+                let script = format!("{}/{}.py", amaranth.path, name);
+                if name.starts_with("test_") && project_root.join(&script).exists() {
+                    return Ok(vec![amaranth_test(name, script, config)]);
+                }
+            }
+            bail!("Test not found: {}_tb.v", name);
+        }
+        Some(pattern) => Some(
+            glob::Pattern::new(pattern)
+                .with_context(|| format!("Invalid test pattern: {}", pattern))?,
+        ),
+        None => None,
+    };
+
+    // Discover all tests
+    let mut tests = Vec::new();
+
+    if test_path.exists() {
+        for entry in fs::read_dir(&test_path)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+
+            if let Some(test_name) = name.strip_suffix("_tb.v") {
+                if pattern.as_ref().is_some_and(|p| !p.matches(test_name)) {
+                    continue;
+                }
+                tests.push(load_testbench(test_dir, &test_path, test_name, config)?);
+            }
+        }
+    }
+
+    // Amaranth simulations: test_*.py alongside the designs
+    if let Some(amaranth) = amaranth {
+        let sim_path = project_root.join(&amaranth.path);
+        if sim_path.exists() {
+            for entry in fs::read_dir(&sim_path)? {
+                let name = entry?.file_name().to_string_lossy().to_string();
+                let Some(test_name) = name.strip_suffix(".py") else {
+                    continue;
+                };
+                if !test_name.starts_with("test_")
+                    || pattern.as_ref().is_some_and(|p| !p.matches(test_name))
+                {
+                    continue;
+                }
+                let script = format!("{}/{}", amaranth.path, name);
+                tests.push(amaranth_test(test_name, script, config));
+            }
+        }
+    }
+
+    tests.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(tests)
+}
+
+fn is_glob(name: &str) -> bool {
+    name.contains(['*', '?', '['])
+}
+
+fn load_testbench(
+    test_dir: &str,
+    test_path: &Path,
+    name: &str,
+    config: &TestConfig,
+) -> Result<Testbench> {
+    let source = fs::read_to_string(test_path.join(format!("{}_tb.v", name)))?;
+
+    let mut tags = header_tags(&source);
+    if let Some(tb_config) = config.tests.get(name) {
+        for tag in &tb_config.tags {
+            if !tags.contains(tag) {
+                tags.push(tag.clone());
+            }
+        }
+    }
+
+    Ok(Testbench {
+        name: name.to_string(),
+        path: format!("{}/{}_tb.v", test_dir, name),
+        tags,
+        kind: TestKind::Verilog,
+    })
+}
+
+/// An Amaranth simulation script, tagged only from `[test.<name>]`
+fn amaranth_test(name: &str, path: String, config: &TestConfig) -> Testbench {
+    Testbench {
+        name: name.to_string(),
+        path,
+        tags: config
+            .tests
+            .get(name)
+            .map(|t| t.tags.clone())
+            .unwrap_or_default(),
+        kind: TestKind::Amaranth,
+    }
+}
+
+/// Parse `// affogato-tags: a, b` lines from the leading comment block
+fn header_tags(source: &str) -> Vec<String> {
+    let mut tags = Vec::new();
+
+    for line in source.lines().map(str::trim) {
+        if line.is_empty() {
+            continue;
+        }
+        let Some(comment) = line.strip_prefix("//") else {
+            break;
+        };
+        if let Some(list) = comment.trim().strip_prefix("affogato-tags:") {
+            tags.extend(
+                list.split(',')
+                    .map(|t| t.trim().to_string())
+                    .filter(|t| !t.is_empty()),
+            );
+        }
+    }
+
+    tags
+}
+
+/// Apply `--tag` / `--skip-tag` filters
+fn filter_by_tags(tests: Vec<Testbench>, tags: &[String], skip_tags: &[String]) -> Vec<Testbench> {
+    tests
+        .into_iter()
+        .filter(|tb| tags.is_empty() || tb.tags.iter().any(|t| tags.contains(t)))
+        .filter(|tb| !tb.tags.iter().any(|t| skip_tags.contains(t)))
+        .collect()
+}
+
+/// Normalize simulator output so snapshots don't churn on incidental details
+fn normalize_sim_output(output: &str) -> String {
+    let mut lines: Vec<&str> = output
+        .lines()
+        .map(str::trim_end)
+        .filter(|l| !l.starts_with("VCD info:") && !l.starts_with("FST info:"))
+        .filter(|l| !l.contains("$finish called at"))
+        .collect();
+
+    while lines.last().is_some_and(|l| l.is_empty()) {
+        lines.pop();
+    }
+
+    let mut normalized = lines.join("\n");
+    normalized.push('\n');
+    normalized
+}
+
+/// Describe the first differences between two snapshots, if any
+fn snapshot_diff(expected: &str, actual: &str) -> Option<String> {
+    if expected == actual {
+        return None;
+    }
+
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let first = expected
+        .iter()
+        .zip(&actual)
+        .position(|(e, a)| e != a)
+        .unwrap_or(expected.len().min(actual.len()));
+
+    let mut diff = format!("first difference at line {}:", first + 1);
+    for line in expected.iter().skip(first).take(5) {
+        diff.push_str(&format!("\n  - {}", line));
+    }
+    for line in actual.iter().skip(first).take(5) {
+        diff.push_str(&format!("\n  + {}", line));
+    }
+    Some(diff)
+}
+
+/// Pick a seed for a run when none was given
+fn random_seed() -> u32 {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    // Fold the clock and pid together, keeping the seed positive for $random
+    ((nanos ^ (nanos >> 32) ^ u128::from(std::process::id())) as u32) & 0x7fff_ffff
+}
+
+/// Quote a string for safe use as a single shell word
+fn shell_quote(s: &str) -> String {
+    if !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_=+./:,@".contains(c))
+    {
+        s.to_string()
+    } else {
+        format!("'{}'", s.replace('\'', "'\\''"))
+    }
+}
+
+fn highlight_output(line: &str) -> String {
+    let lower = line.to_lowercase();
+
+    if lower.contains("error") || lower.contains("fail") {
+        line.red().to_string()
+    } else if lower.contains("warn") {
+        line.yellow().to_string()
+    } else if lower.contains("pass") {
+        line.green().to_string()
+    } else {
+        line.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    fn testbenches(count: usize) -> Vec<Testbench> {
+        (0..count)
+            .map(|i| Testbench {
+                name: format!("t{:02}", i),
+                path: format!("fpga/rtl_test/t{:02}_tb.v", i),
+                tags: Vec::new(),
+                kind: TestKind::Verilog,
+            })
+            .collect()
+    }
+
+    fn history(durations: &[(&str, f64)]) -> TestHistory {
+        TestHistory {
+            results: durations
+                .iter()
+                .map(|(name, secs)| {
+                    let record = TestRecord {
+                        passed: true,
+                        timed_out: false,
+                        duration_secs: *secs,
+                        seed: None,
+                    };
+                    (name.to_string(), record)
+                })
+                .collect(),
+        }
+    }
+
+    /// Each shard's test names, for `count` shards of `tests` testbenches
+    fn shards(tests: usize, count: usize, durations: Option<&TestHistory>) -> Vec<Vec<String>> {
+        (1..=count)
+            .map(|index| {
+                Shard { index, count }
+                    .select(testbenches(tests), durations)
+                    .into_iter()
+                    .map(|tb| tb.name)
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Every test lands in exactly one shard
+    fn assert_partition(tests: usize, shards: &[Vec<String>]) {
+        let mut seen = BTreeSet::new();
+        for name in shards.iter().flatten() {
+            assert!(seen.insert(name.clone()), "{} is in two shards", name);
+        }
+        let all: BTreeSet<String> = testbenches(tests).into_iter().map(|tb| tb.name).collect();
+        assert_eq!(seen, all);
+    }
+
+    #[test]
+    fn shards_round_robin_without_durations() {
+        let shards = shards(10, 3, None);
+        assert_partition(10, &shards);
+        assert_eq!(shards[0], ["t00", "t03", "t06", "t09"]);
+        assert_eq!(shards[1], ["t01", "t04", "t07"]);
+        assert_eq!(shards[2], ["t02", "t05", "t08"]);
+    }
+
+    #[test]
+    fn shards_balance_recorded_durations() {
+        // t04 and t05 have no record and count as the mean, 4.75s
+        let durations = history(&[("t00", 10.0), ("t01", 2.0), ("t02", 3.0), ("t03", 4.0)]);
+        let shards = shards(6, 2, Some(&durations));
+        assert_partition(6, &shards);
+        // Longest first, each to the lighter shard: 10 + 3 + 2 against 4.75 + 4.75 + 4
+        assert_eq!(shards[0], ["t00", "t01", "t02"]);
+        assert_eq!(shards[1], ["t03", "t04", "t05"]);
+    }
+
+    #[test]
+    fn more_shards_than_tests_leaves_some_empty() {
+        for durations in [None, Some(&history(&[("t00", 1.0)]))] {
+            let shards = shards(2, 4, durations);
+            assert_partition(2, &shards);
+            assert_eq!(shards.iter().filter(|shard| shard.is_empty()).count(), 2);
+        }
+    }
+
+    #[test]
+    fn parses_shard() {
+        let shard: Shard = "2/3".parse().unwrap();
+        assert_eq!((shard.index, shard.count), (2, 3));
+        for bad in ["0/3", "4/3", "1/0", "3", "a/b"] {
+            assert!(bad.parse::<Shard>().is_err(), "{} should not parse", bad);
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::build_info::BuildInfo;
+use crate::dry_run;
+use crate::nextpnr::{CriticalPath, Report};
+
+/// One line per FPGA build, relative to the project root
+pub const ARCHIVE: &str = ".affogato/metrics/timing.jsonl";
+
+/// Records kept in the archive; the oldest are dropped first
+const KEPT: usize = 1000;
+
+/// The timing of one FPGA build
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    /// `git describe --always --dirty` of the tree that was built
+    pub describe: String,
+    /// Full hash of the commit checked out, if in a repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// Whether the tree had uncommitted changes
+    #[serde(default)]
+    pub dirty: bool,
+    /// Built by `affogato timing --compare` in a temporary worktree rather
+    /// than in this checkout
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub worktree: bool,
+    /// When the build ran, in seconds since the epoch
+    pub recorded: u64,
+    #[serde(default)]
+    pub fmax_mhz: BTreeMap<String, f64>,
+    #[serde(default)]
+    pub target_mhz: BTreeMap<String, f64>,
+    #[serde(default)]
+    pub critical_paths: BTreeMap<String, CriticalPath>,
+}
+
+impl Record {
+    /// The timing in the last build's nextpnr log, against the current tree
+    pub fn current(project_root: &Path) -> Option<Self> {
+        let report = Report::load(project_root)?;
+        if report.fmax_mhz.is_empty() {
+            return None;
+        }
+        let describe = BuildInfo::current(project_root).describe;
+        Some(Self {
+            dirty: describe.ends_with("-dirty"),
+            describe,
+            commit: rev_parse(project_root, "HEAD"),
+            worktree: false,
+            recorded: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            fmax_mhz: report.fmax_mhz,
+            target_mhz: report.target_mhz,
+            critical_paths: report.critical_paths,
+        })
+    }
+}
+
+/// Add the timing of the build just finished, built or restored from the
+/// cache, to the archive
+pub fn archive(project_root: &Path) -> Result<()> {
+    if dry_run::is_enabled() {
+        return Ok(());
+    }
+    let Some(record) = Record::current(project_root) else {
+        return Ok(());
+    };
+    append(project_root, &record)
+}
+
+/// Add a record to the archive, dropping the oldest past the limit
+pub fn append(project_root: &Path, record: &Record) -> Result<()> {
+    let path = project_root.join(ARCHIVE);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut records = load(project_root).unwrap_or_default();
+    if records.len() >= KEPT {
+        records.drain(..=records.len() - KEPT);
+        records.push(record.clone());
+        let text: Vec<String> = records
+            .iter()
+            .map(serde_json::to_string)
+            .collect::<Result<_, _>>()?;
+        fs::write(&path, text.join("\n") + "\n")?;
+        return Ok(());
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(record)?)?;
+    Ok(())
+}
+
+/// Every archived record, oldest first
+pub fn load(project_root: &Path) -> Result<Vec<Record>> {
+    let path = project_root.join(ARCHIVE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let text =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("Invalid record on line {} of {}", i + 1, ARCHIVE))
+        })
+        .collect()
+}
+
+/// The full hash of a git revision, if it names one
+pub fn rev_parse(project_root: &Path, rev: &str) -> Option<String> {
+    Command::new("git")
+        .current_dir(project_root)
+        .args(["rev-parse", "--verify", "--quiet"])
+        .arg(format!("{}^{{commit}}", rev))
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
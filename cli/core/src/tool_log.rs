@@ -0,0 +1,115 @@
+use anyhow::Result;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::dry_run;
+
+/// Logs of past commands' tool output, relative to the project root. Each is
+/// named `<UTC timestamp>-<command>.log`, so they sort oldest first.
+pub const LOG_DIR: &str = ".affogato/logs";
+
+/// Older logs are deleted when a new one is started
+const KEPT_LOGS: usize = 50;
+
+/// The command being run, e.g. "gen bus", named in the log file
+static COMMAND: Mutex<Option<String>> = Mutex::new(None);
+
+/// This command's log, opened when it first runs a tool
+static LOG: Mutex<Option<(PathBuf, File)>> = Mutex::new(None);
+
+/// Name the command whose tool output is about to be logged
+pub fn set_command(command: &str) {
+    *COMMAND.lock().unwrap() = Some(command.to_string());
+}
+
+/// Start this command's log under `project_root`, if it hasn't been started
+pub fn open(project_root: &Path) {
+    if dry_run::is_enabled() {
+        return;
+    }
+    let mut log = LOG.lock().unwrap();
+    if log.is_some() {
+        return;
+    }
+    let dir = project_root.join(LOG_DIR);
+    if fs::create_dir_all(&dir).is_err() {
+        return;
+    }
+    prune(project_root);
+
+    let command = COMMAND.lock().unwrap().clone().unwrap_or_default();
+    let now = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+    // 2026-10-16T12:34:56Z -> 20261016-123456
+    let stamp: String = now
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == 'T')
+        .map(|c| if c == 'T' { '-' } else { c })
+        .collect();
+    let name = format!("{}-{}.log", stamp, command.replace(' ', "-"));
+    let path = dir.join(name);
+    if let Ok(mut file) = File::create(&path) {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        let _ = writeln!(file, "# affogato {} ({})", args.join(" "), now);
+        *log = Some((path, file));
+    }
+}
+
+/// Save a line of tool output
+pub fn write_line(line: &str) {
+    if let Some((_, file)) = LOG.lock().unwrap().as_mut() {
+        let _ = writeln!(file, "{}", line);
+    }
+}
+
+/// Save a block of captured tool output, kept together
+pub fn write(text: &str) {
+    if let Some((_, file)) = LOG.lock().unwrap().as_mut() {
+        let _ = file.write_all(text.as_bytes());
+        if !text.is_empty() && !text.ends_with('\n') {
+            let _ = writeln!(file);
+        }
+    }
+}
+
+/// Record how the command ended, at the bottom of its log
+pub fn finish(error: Option<&anyhow::Error>) {
+    if let Some((_, file)) = LOG.lock().unwrap().as_mut() {
+        let _ = match error {
+            Some(error) => writeln!(file, "# failed: {}", crate::error::message(error)),
+            None => writeln!(file, "# succeeded"),
+        };
+    }
+}
+
+/// Where this command's tool output is saved, once a tool has run
+pub fn path() -> Option<PathBuf> {
+    LOG.lock().unwrap().as_ref().map(|(path, _)| path.clone())
+}
+
+/// The project's logs, oldest first
+pub fn list(project_root: &Path) -> Result<Vec<PathBuf>> {
+    let dir = project_root.join(LOG_DIR);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut logs: Vec<PathBuf> = fs::read_dir(&dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "log"))
+        .collect();
+    logs.sort();
+    Ok(logs)
+}
+
+/// Delete all but the newest logs, leaving room for one more
+fn prune(project_root: &Path) {
+    let Ok(logs) = list(project_root) else {
+        return;
+    };
+    let excess = (logs.len() + 1).saturating_sub(KEPT_LOGS);
+    for old in &logs[..excess] {
+        let _ = fs::remove_file(old);
+    }
+}
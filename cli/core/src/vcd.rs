@@ -0,0 +1,322 @@
+use anyhow::{bail, Context, Result};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+
+/// A parsed Value Change Dump
+pub struct Vcd {
+    /// Timescale declared in the header (e.g. "1ns")
+    pub timescale: String,
+    /// Hierarchical signal name -> identifier code
+    pub signals: BTreeMap<String, String>,
+    /// Identifier code -> (time, value) changes in time order
+    changes: HashMap<String, Vec<(u64, String)>>,
+}
+
+/// The first point where two waveforms disagree
+pub struct Divergence {
+    pub time: u64,
+    pub signal: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl Vcd {
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&source).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut vcd = Vcd {
+            timescale: String::new(),
+            signals: BTreeMap::new(),
+            changes: HashMap::new(),
+        };
+
+        let mut tokens = source.split_whitespace();
+        let mut scope: Vec<String> = Vec::new();
+        let mut time = 0u64;
+
+        while let Some(token) = tokens.next() {
+            match token {
+                "$scope" => {
+                    let _kind = tokens.next();
+                    scope.push(tokens.next().context("Truncated $scope")?.to_string());
+                    skip_to_end(&mut tokens);
+                }
+                "$upscope" => {
+                    scope.pop();
+                    skip_to_end(&mut tokens);
+                }
+                "$var" => {
+                    let _kind = tokens.next();
+                    let _width = tokens.next();
+                    let id = tokens.next().context("Truncated $var")?.to_string();
+                    let name = tokens.next().context("Truncated $var")?;
+                    skip_to_end(&mut tokens);
+
+                    let mut full = scope.clone();
+                    full.push(name.to_string());
+                    vcd.signals.insert(full.join("."), id.clone());
+                    vcd.changes.entry(id).or_default();
+                }
+                "$timescale" => {
+                    let mut parts = Vec::new();
+                    for t in tokens.by_ref() {
+                        if t == "$end" {
+                            break;
+                        }
+                        parts.push(t);
+                    }
+                    vcd.timescale = parts.join("");
+                }
+                // Keywords whose bodies contain value changes
+                "$dumpvars" | "$dumpall" | "$dumpon" | "$dumpoff" | "$end" => {}
+                t if t.starts_with('$') => skip_to_end(&mut tokens),
+                t if t.starts_with('#') => {
+                    time = t[1..]
+                        .parse()
+                        .with_context(|| format!("Invalid timestamp: {}", t))?;
+                }
+                t if t.starts_with(['b', 'B']) => {
+                    let id = tokens.next().context("Truncated vector value change")?;
+                    vcd.push_change(id, time, normalize(&t[1..]));
+                }
+                t if t.starts_with(['r', 'R']) => {
+                    let id = tokens.next().context("Truncated real value change")?;
+                    vcd.push_change(id, time, normalize_real(&t[1..]));
+                }
+                t => {
+                    let (value, id) = t.split_at(1);
+                    if id.is_empty() {
+                        bail!("Unexpected token in value changes: {}", t);
+                    }
+                    vcd.push_change(id, time, value.to_lowercase());
+                }
+            }
+        }
+
+        Ok(vcd)
+    }
+
+    fn push_change(&mut self, id: &str, time: u64, value: String) {
+        let changes = self.changes.entry(id.to_string()).or_default();
+        match changes.last_mut() {
+            // Multiple changes in one timestep: the last one wins
+            Some((t, v)) if *t == time => *v = value,
+            _ => changes.push((time, value)),
+        }
+    }
+
+    fn signal_changes(&self, signal: &str) -> &[(u64, String)] {
+        self.signals
+            .get(signal)
+            .and_then(|id| self.changes.get(id))
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    /// Value of a signal at a given time, if it has been assigned yet
+    pub fn value_at(&self, signal: &str, time: u64) -> Option<&str> {
+        let changes = self.signal_changes(signal);
+        let idx = changes.partition_point(|(t, _)| *t <= time);
+        idx.checked_sub(1).map(|i| changes[i].1.as_str())
+    }
+
+    /// Times at which a signal changes value
+    pub fn change_times(&self, signal: &str) -> impl Iterator<Item = u64> + '_ {
+        self.signal_changes(signal).iter().map(|(t, _)| *t)
+    }
+
+    /// Signal names matching any of the glob patterns (all signals if empty)
+    pub fn select(&self, patterns: &[String]) -> Result<Vec<String>> {
+        if patterns.is_empty() {
+            return Ok(self.signals.keys().cloned().collect());
+        }
+        let patterns = patterns
+            .iter()
+            .map(|p| glob::Pattern::new(p).with_context(|| format!("Invalid pattern: {}", p)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(self
+            .signals
+            .keys()
+            .filter(|name| patterns.iter().any(|p| p.matches(name)))
+            .cloned()
+            .collect())
+    }
+}
+
+/// Find the earliest point where `actual` differs from `golden` on the given signals
+pub fn first_divergence(golden: &Vcd, actual: &Vcd, signals: &[String]) -> Option<Divergence> {
+    let mut first: Option<Divergence> = None;
+
+    for signal in signals {
+        if !actual.signals.contains_key(signal) {
+            let divergence = Divergence {
+                time: 0,
+                signal: signal.clone(),
+                expected: "present".to_string(),
+                actual: "missing".to_string(),
+            };
+            return Some(divergence);
+        }
+
+        let times: BTreeSet<u64> = golden
+            .change_times(signal)
+            .chain(actual.change_times(signal))
+            .collect();
+
+        for time in times {
+            if first.as_ref().is_some_and(|d| d.time <= time) {
+                break;
+            }
+            let expected = golden.value_at(signal, time).unwrap_or("x");
+            let got = actual.value_at(signal, time).unwrap_or("x");
+            if expected != got {
+                first = Some(Divergence {
+                    time,
+                    signal: signal.clone(),
+                    expected: expected.to_string(),
+                    actual: got.to_string(),
+                });
+                break;
+            }
+        }
+    }
+
+    first
+}
+
+fn skip_to_end<'a>(tokens: &mut impl Iterator<Item = &'a str>) {
+    for t in tokens.by_ref() {
+        if t == "$end" {
+            break;
+        }
+    }
+}
+
+/// Normalize vector values so "b0011" and "b11" compare equal
+fn normalize(value: &str) -> String {
+    let value = value.to_lowercase();
+    let trimmed = value.trim_start_matches('0');
+    if trimmed.is_empty() {
+        "0".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+/// Normalize real values so "r0.50" and "r.5" compare equal
+fn normalize_real(value: &str) -> String {
+    value
+        .parse::<f64>()
+        .map_or_else(|_| value.to_lowercase(), |real| real.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A dump of `tb.clk`, `tb.bus` (4 bits) and `tb.level` (real) with
+    /// the given value changes after the header
+    fn dump(changes: &str) -> Vcd {
+        let source = format!(
+            "$timescale 1 ns $end
+$scope module tb $end
+$var wire 1 ! clk $end
+$var wire 4 \" bus $end
+$var real 64 # level $end
+$upscope $end
+$enddefinitions $end
+{}",
+            changes
+        );
+        Vcd::parse(&source).unwrap()
+    }
+
+    #[test]
+    fn parses_header_and_changes() {
+        let vcd = dump("#0\n$dumpvars\n0!\nb0000 \"\nr0 #\n$end\n#5\n1!\n#10\n0!\n");
+        assert_eq!(vcd.timescale, "1ns");
+        assert_eq!(vcd.signals.get("tb.clk").map(String::as_str), Some("!"));
+        assert_eq!(vcd.value_at("tb.clk", 0), Some("0"));
+        assert_eq!(vcd.value_at("tb.clk", 7), Some("1"));
+        assert_eq!(vcd.value_at("tb.clk", 10), Some("0"));
+        assert_eq!(vcd.change_times("tb.clk").collect::<Vec<_>>(), [0, 5, 10]);
+    }
+
+    #[test]
+    fn unassigned_signal_has_no_value() {
+        let vcd = dump("#5\n1!\n");
+        assert_eq!(vcd.value_at("tb.clk", 4), None);
+        assert_eq!(vcd.value_at("tb.bus", 5), None);
+    }
+
+    #[test]
+    fn last_change_in_a_timestep_wins() {
+        let vcd = dump("#0\n0!\n#5\n1!\n0!\nx!\n");
+        assert_eq!(vcd.value_at("tb.clk", 5), Some("x"));
+        assert_eq!(vcd.change_times("tb.clk").collect::<Vec<_>>(), [0, 5]);
+    }
+
+    #[test]
+    fn vectors_compare_without_leading_zeros() {
+        let golden = dump("#0\nb0011 \"\n#5\nb0000 \"\n");
+        let actual = dump("#0\nb11 \"\n#5\nb0 \"\n");
+        assert_eq!(golden.value_at("tb.bus", 0), Some("11"));
+        assert_eq!(golden.value_at("tb.bus", 5), Some("0"));
+        let signals = vec!["tb.bus".to_string()];
+        assert!(first_divergence(&golden, &actual, &signals).is_none());
+    }
+
+    #[test]
+    fn reals_compare_by_value() {
+        let vcd = dump("#0\nr0.5 #\n#5\nr10 #\n#10\nr0.50 #\n");
+        assert_eq!(vcd.value_at("tb.level", 0), Some("0.5"));
+        assert_eq!(vcd.value_at("tb.level", 5), Some("10"));
+        assert_eq!(vcd.value_at("tb.level", 10), Some("0.5"));
+
+        let golden = dump("#0\nr0.5 #\n");
+        let same = dump("#0\nr.5 #\n");
+        let different = dump("#0\nr5 #\n");
+        let signals = vec!["tb.level".to_string()];
+        assert!(first_divergence(&golden, &same, &signals).is_none());
+        let divergence = first_divergence(&golden, &different, &signals).unwrap();
+        assert_eq!(
+            (divergence.expected.as_str(), divergence.actual.as_str()),
+            ("0.5", "5")
+        );
+    }
+
+    #[test]
+    fn reports_the_earliest_divergence_across_signals() {
+        let golden = dump("#0\n0!\nb0 \"\n#10\n1!\n#20\nb1 \"\n");
+        let actual = dump("#0\n0!\nb0 \"\n#15\n1!\n#20\nb10 \"\n");
+        let signals = vec!["tb.bus".to_string(), "tb.clk".to_string()];
+        let divergence = first_divergence(&golden, &actual, &signals).unwrap();
+        assert_eq!(divergence.time, 10);
+        assert_eq!(divergence.signal, "tb.clk");
+        assert_eq!(divergence.expected, "1");
+        assert_eq!(divergence.actual, "0");
+    }
+
+    #[test]
+    fn signal_missing_from_the_actual_dump_diverges() {
+        let golden = dump("#0\n0!\n");
+        let actual = Vcd::parse(
+            "$scope module tb $end $var wire 1 ! clk $end $upscope $end $enddefinitions $end #0 0!",
+        )
+        .unwrap();
+        let signals = vec!["tb.clk".to_string(), "tb.bus".to_string()];
+        let divergence = first_divergence(&golden, &actual, &signals).unwrap();
+        assert_eq!(divergence.signal, "tb.bus");
+        assert_eq!(divergence.actual, "missing");
+    }
+
+    #[test]
+    fn rejects_a_bad_timestamp() {
+        assert!(Vcd::parse("$enddefinitions $end #abc").is_err());
+    }
+}
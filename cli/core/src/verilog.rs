@@ -0,0 +1,254 @@
+use std::collections::BTreeSet;
+
+/// Collect every identifier-like token in a Verilog source
+pub fn identifiers(source: &str) -> BTreeSet<String> {
+    source
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '$'))
+        .filter(|t| !t.is_empty() && !t.starts_with(|c: char| c.is_ascii_digit()))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Names of the modules defined in a Verilog source
+pub fn defined_modules(source: &str) -> Vec<String> {
+    let mut modules = Vec::new();
+    let mut tokens = source.split_whitespace();
+
+    while let Some(token) = tokens.next() {
+        if token == "module" {
+            if let Some(name) = tokens.next() {
+                let name: String = name
+                    .chars()
+                    .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+                    .collect();
+                if !name.is_empty() {
+                    modules.push(name);
+                }
+            }
+        }
+    }
+
+    modules
+}
+
+/// A port in a module header
+pub struct Port {
+    pub name: String,
+    /// "input", "output" or "inout"
+    pub direction: String,
+    /// Packed range such as `[7:0]`, if any
+    pub range: Option<String>,
+}
+
+/// Remove `//` and `/* */` comments
+pub fn strip_comments(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('/', Some('/')) => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        out.push('\n');
+                        break;
+                    }
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+                out.push(' ');
+            }
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// Parse the ports of `module`, supporting ANSI and non-ANSI headers
+pub fn module_ports(source: &str, module: &str) -> Option<Vec<Port>> {
+    let source = strip_comments(source);
+
+    // Locate "module <name>" followed by a non-identifier character
+    let mut start = None;
+    let mut search = 0;
+    while let Some(pos) = source[search..].find("module") {
+        let after = search + pos + "module".len();
+        let rest = source[after..].trim_start();
+        if rest.starts_with(module)
+            && !rest[module.len()..].starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+        {
+            start = Some(source.len() - rest.len() + module.len());
+            break;
+        }
+        search = after;
+    }
+    let mut rest = source[start?..].trim_start();
+
+    // Skip a parameter list
+    if let Some(params) = rest.strip_prefix('#') {
+        let params = params.trim_start();
+        let end = matching_paren(params)?;
+        rest = params[end + 1..].trim_start();
+    }
+
+    let header_end = if rest.starts_with('(') {
+        matching_paren(rest)?
+    } else {
+        0
+    };
+    let header = if header_end > 0 {
+        &rest[1..header_end]
+    } else {
+        ""
+    };
+    let body_end = rest.find("endmodule").unwrap_or(rest.len());
+    let body = &rest[header_end..body_end];
+
+    let mut ports = Vec::new();
+    let mut direction = None;
+    let mut range = None;
+    let mut names_only = Vec::new();
+
+    for entry in split_top_level(header) {
+        match parse_declaration(&entry) {
+            Some((dir, r, names)) => {
+                direction = Some(dir);
+                range = r;
+                for name in names {
+                    ports.push(port(&name, direction.as_deref(), &range));
+                }
+            }
+            None => {
+                let name = entry.trim().to_string();
+                if name.is_empty() {
+                    continue;
+                }
+                if direction.is_some() {
+                    // ANSI continuation: "input a, b"
+                    ports.push(port(&name, direction.as_deref(), &range));
+                } else {
+                    names_only.push(name);
+                }
+            }
+        }
+    }
+
+    // Non-ANSI style: directions are declared in the module body
+    if !names_only.is_empty() {
+        let mut declared = Vec::new();
+        for statement in body.split(';') {
+            if let Some((dir, r, names)) = parse_declaration(statement) {
+                for name in names {
+                    declared.push(port(&name, Some(&dir), &r));
+                }
+            }
+        }
+        for name in names_only {
+            let declared = declared.iter().find(|p| p.name == name);
+            ports.push(Port {
+                direction: declared
+                    .map(|p| p.direction.clone())
+                    .unwrap_or_else(|| "inout".to_string()),
+                range: declared.and_then(|p| p.range.clone()),
+                name,
+            });
+        }
+    }
+
+    Some(ports)
+}
+
+fn port(name: &str, direction: Option<&str>, range: &Option<String>) -> Port {
+    Port {
+        name: name.to_string(),
+        direction: direction.unwrap_or("inout").to_string(),
+        range: range.clone(),
+    }
+}
+
+/// Parse "input wire [7:0] a, b" into (direction, range, names)
+fn parse_declaration(text: &str) -> Option<(String, Option<String>, Vec<String>)> {
+    let text = text.trim();
+    let direction = ["input", "output", "inout"]
+        .into_iter()
+        .find(|d| text.starts_with(d) && text[d.len()..].starts_with(char::is_whitespace))?;
+    let mut rest = text[direction.len()..].trim_start();
+
+    let mut range = None;
+    loop {
+        if let Some(r) = rest.strip_prefix('[') {
+            let end = r.find(']')?;
+            range = Some(format!("[{}]", r[..end].replace(' ', "")));
+            rest = r[end + 1..].trim_start();
+        } else if let Some(r) = ["wire", "reg", "logic", "signed", "unsigned"]
+            .into_iter()
+            .find_map(|kw| {
+                rest.strip_prefix(kw)
+                    .filter(|r| r.starts_with(|c: char| c.is_whitespace() || c == '['))
+            })
+        {
+            rest = r.trim_start();
+        } else {
+            break;
+        }
+    }
+
+    let names = rest
+        .split(',')
+        .map(|n| n.split('=').next().unwrap_or("").trim().to_string())
+        .filter(|n| !n.is_empty())
+        .collect();
+    Some((direction.to_string(), range, names))
+}
+
+/// Index of the parenthesis closing the one at the start of `text`
+fn matching_paren(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Split on commas that aren't nested in brackets or parentheses
+fn split_top_level(text: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut current = String::new();
+
+    for c in text.chars() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    if !current.trim().is_empty() {
+        parts.push(current);
+    }
+
+    parts
+}
@@ -0,0 +1,77 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::dry_run;
+
+/// Waveform viewers we know how to launch, in order of preference
+const VIEWERS: &[&str] = &["gtkwave", "surfer"];
+
+/// Open a waveform in a host-side viewer, keeping its layout in `layout_base`
+/// (`<layout_base>.gtkw` for GTKWave, `<layout_base>.surf.ron` for Surfer).
+pub fn open(wave: &Path, layout_base: &Path, preferred: Option<&str>) -> Result<()> {
+    let viewer = match preferred {
+        Some(viewer) => {
+            which::which(viewer)
+                .with_context(|| format!("Waveform viewer '{}' not found on PATH", viewer))?;
+            viewer.to_string()
+        }
+        None => match VIEWERS.iter().find(|v| which::which(v).is_ok()) {
+            Some(viewer) => viewer.to_string(),
+            None => bail!(
+                "No waveform viewer found. Install GTKWave or Surfer, or open {} manually.",
+                wave.display()
+            ),
+        },
+    };
+
+    let mut cmd = Command::new(&viewer);
+    let layout = match viewer.as_str() {
+        "surfer" => {
+            let layout = layout_base.with_extension("surf.ron");
+            cmd.arg(wave);
+            // Surfer writes its state file on save; only load one that exists
+            if layout.exists() {
+                cmd.arg("--state-file").arg(&layout);
+            }
+            layout
+        }
+        _ => {
+            let layout = layout_base.with_extension("gtkw");
+            if !layout.exists() {
+                // Seed a save file so "Write Save File" in GTKWave lands here
+                dry_run::write(
+                    &layout,
+                    format!(
+                        "[dumpfile] \"{}\"\n[savefile] \"{}\"\n",
+                        wave.display(),
+                        layout.display()
+                    ),
+                )?;
+            }
+            cmd.arg(wave).arg(&layout);
+            layout
+        }
+    };
+
+    outln!(
+        "{}",
+        format!(
+            "Opening {} in {} (layout: {})",
+            wave.display(),
+            viewer,
+            layout.display()
+        )
+        .dimmed()
+    );
+
+    // Detach so the test command returns while the viewer stays open
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to launch {}", viewer))?;
+
+    Ok(())
+}
@@ -0,0 +1,460 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use crate::vcd::Vcd;
+
+/// Checks declared in a testbench's `<name>_tb.toml`
+#[derive(Debug, Deserialize)]
+pub struct WaveChecks {
+    #[serde(default)]
+    pub check: Vec<WaveCheck>,
+}
+
+/// A black-box assertion evaluated against the simulation VCD
+#[derive(Debug, Deserialize)]
+pub struct WaveCheck {
+    /// Hierarchical signal name, or a unique suffix of one (e.g. "dut.led_r")
+    pub signal: String,
+    /// Only check from this simulation time on (e.g. "10us")
+    #[serde(default)]
+    pub after: Option<String>,
+    /// Only check up to this simulation time
+    #[serde(default)]
+    pub before: Option<String>,
+    /// Only check while this condition holds (e.g. "cs_n == 0")
+    #[serde(default, rename = "while")]
+    pub condition: Option<String>,
+    /// Expected toggle frequency (e.g. "1kHz")
+    #[serde(default)]
+    pub toggles: Option<String>,
+    /// Allowed relative frequency error for `toggles`
+    #[serde(default = "default_tolerance")]
+    pub tolerance: f64,
+    /// Value the signal must never have ("x", "z" or a number)
+    #[serde(default)]
+    pub never: Option<String>,
+    /// Value the signal must always have
+    #[serde(default)]
+    pub always: Option<String>,
+}
+
+fn default_tolerance() -> f64 {
+    0.1
+}
+
+impl WaveChecks {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Evaluate every check, returning one message per failure
+    pub fn evaluate(&self, vcd: &Vcd) -> Result<Vec<String>> {
+        let seconds_per_tick = parse_timescale(&vcd.timescale)?;
+        let mut failures = Vec::new();
+        for check in &self.check {
+            if let Some(failure) = check.evaluate(vcd, seconds_per_tick)? {
+                failures.push(format!("{}: {}", check.signal, failure));
+            }
+        }
+        Ok(failures)
+    }
+}
+
+/// A signal value a check compares against
+enum Expected {
+    Unknown,
+    HighZ,
+    Number(u128),
+}
+
+impl Expected {
+    fn parse(value: &str) -> Result<Self> {
+        let value = value.trim().to_lowercase();
+        let number = if value == "x" {
+            return Ok(Expected::Unknown);
+        } else if value == "z" {
+            return Ok(Expected::HighZ);
+        } else if let Some(hex) = value.strip_prefix("0x") {
+            u128::from_str_radix(hex, 16)
+        } else if let Some(bin) = value.strip_prefix("0b") {
+            u128::from_str_radix(bin, 2)
+        } else {
+            value.parse()
+        };
+        number
+            .map(Expected::Number)
+            .with_context(|| format!("Invalid value: {}", value))
+    }
+
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            Expected::Unknown => value.contains('x'),
+            Expected::HighZ => value.contains('z'),
+            Expected::Number(n) => u128::from_str_radix(value, 2).is_ok_and(|v| v == *n),
+        }
+    }
+}
+
+impl WaveCheck {
+    fn evaluate(&self, vcd: &Vcd, seconds_per_tick: f64) -> Result<Option<String>> {
+        let signal = resolve_signal(vcd, &self.signal)?;
+        let to_ticks = |time: &Option<String>, default: u64| -> Result<u64> {
+            match time {
+                Some(t) => {
+                    let duration = humantime::parse_duration(t)
+                        .with_context(|| format!("Invalid time: {}", t))?;
+                    Ok((duration.as_secs_f64() / seconds_per_tick).round() as u64)
+                }
+                None => Ok(default),
+            }
+        };
+        let start = to_ticks(&self.after, 0)?;
+        let end = to_ticks(&self.before, u64::MAX)?;
+        let condition = self
+            .condition
+            .as_deref()
+            .map(|c| Condition::parse(vcd, c))
+            .transpose()?;
+        let format_time = |ticks: u64| format_seconds(ticks as f64 * seconds_per_tick);
+
+        if let Some(frequency) = &self.toggles {
+            let expected = parse_frequency(frequency)?;
+            // Edges between known levels: the initial value and x/z settling
+            // out of reset are not toggles
+            let level = |time: u64| matches!(vcd.value_at(&signal, time), Some("0" | "1"));
+            let times: Vec<u64> = vcd
+                .change_times(&signal)
+                .filter(|t| (start..=end).contains(t))
+                .filter(|&t| t > 0 && level(t) && level(t - 1))
+                .collect();
+            if times.len() < 3 {
+                return Ok(Some(format!(
+                    "expected to toggle at {} but changed {} time(s)",
+                    frequency,
+                    times.len()
+                )));
+            }
+            let half_period = (times[times.len() - 1] - times[0]) as f64 / (times.len() - 1) as f64;
+            let measured = 1.0 / (2.0 * half_period * seconds_per_tick);
+            if ((measured - expected) / expected).abs() > self.tolerance {
+                return Ok(Some(format!(
+                    "expected to toggle at {} but measured {}",
+                    frequency,
+                    format_frequency(measured)
+                )));
+            }
+        }
+
+        let rules = [(&self.never, true), (&self.always, false)];
+        for (value, forbidden) in rules {
+            let Some(value) = value else {
+                continue;
+            };
+            let expected = Expected::parse(value)?;
+
+            // Values are piecewise constant, so only change points need checking
+            let mut times: BTreeSet<u64> = vcd.change_times(&signal).collect();
+            if let Some(condition) = &condition {
+                times.extend(vcd.change_times(&condition.signal));
+            }
+            times.insert(start);
+
+            for time in times.range(start..=end) {
+                if condition.as_ref().is_some_and(|c| !c.holds(vcd, *time)) {
+                    continue;
+                }
+                let actual = vcd.value_at(&signal, *time).unwrap_or("x");
+                if expected.matches(actual) == forbidden {
+                    let rule = if forbidden { "never" } else { "always" };
+                    return Ok(Some(format!(
+                        "expected {} {} but was {} at {}",
+                        rule,
+                        value,
+                        actual,
+                        format_time(*time)
+                    )));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// A `while` condition such as "cs_n == 0"
+struct Condition {
+    signal: String,
+    value: Expected,
+    equal: bool,
+}
+
+impl Condition {
+    fn parse(vcd: &Vcd, condition: &str) -> Result<Self> {
+        let (signal, value, equal) = if let Some((s, v)) = condition.split_once("==") {
+            (s, v, true)
+        } else if let Some((s, v)) = condition.split_once("!=") {
+            (s, v, false)
+        } else {
+            (condition, "1", true)
+        };
+        Ok(Condition {
+            signal: resolve_signal(vcd, signal.trim())?,
+            value: Expected::parse(value)?,
+            equal,
+        })
+    }
+
+    fn holds(&self, vcd: &Vcd, time: u64) -> bool {
+        let value = vcd.value_at(&self.signal, time).unwrap_or("x");
+        self.value.matches(value) == self.equal
+    }
+}
+
+/// Find a signal by full name or unique hierarchical suffix
+fn resolve_signal(vcd: &Vcd, name: &str) -> Result<String> {
+    if vcd.signals.contains_key(name) {
+        return Ok(name.to_string());
+    }
+    let suffix = format!(".{}", name);
+    let matches: Vec<&String> = vcd
+        .signals
+        .keys()
+        .filter(|s| s.ends_with(&suffix))
+        .collect();
+    match matches.as_slice() {
+        [signal] => Ok((*signal).clone()),
+        [] => bail!("Signal {} not found in waveform", name),
+        _ => bail!(
+            "Signal {} is ambiguous ({} matches), use a longer name",
+            name,
+            matches.len()
+        ),
+    }
+}
+
+/// Seconds per tick for a VCD timescale such as "1ns" or "10ps"
+fn parse_timescale(timescale: &str) -> Result<f64> {
+    let split = timescale
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(timescale.len());
+    let (count, unit) = timescale.split_at(split);
+    let count: f64 = count.parse().unwrap_or(1.0);
+    let unit = match unit {
+        "s" => 1.0,
+        "ms" => 1e-3,
+        "us" => 1e-6,
+        "ns" => 1e-9,
+        "ps" => 1e-12,
+        "fs" => 1e-15,
+        _ => bail!("Unsupported VCD timescale: {}", timescale),
+    };
+    Ok(count * unit)
+}
+
+/// Frequency in Hz from strings such as "1Hz" or "12 MHz"
+fn parse_frequency(frequency: &str) -> Result<f64> {
+    let lower = frequency.trim().to_lowercase();
+    let (number, scale) = if let Some(n) = lower.strip_suffix("ghz") {
+        (n, 1e9)
+    } else if let Some(n) = lower.strip_suffix("mhz") {
+        (n, 1e6)
+    } else if let Some(n) = lower.strip_suffix("khz") {
+        (n, 1e3)
+    } else if let Some(n) = lower.strip_suffix("hz") {
+        (n, 1.0)
+    } else {
+        bail!("Invalid frequency: {} (expected e.g. \"1kHz\")", frequency);
+    };
+    let number: f64 = number
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid frequency: {}", frequency))?;
+    Ok(number * scale)
+}
+
+fn format_frequency(hz: f64) -> String {
+    // Avoid printing 999.999kHz for a measured 1MHz
+    let hz = (hz * 1e3).round() / 1e3;
+    if hz >= 1e6 {
+        format!("{:.3}MHz", hz / 1e6)
+    } else if hz >= 1e3 {
+        format!("{:.3}kHz", hz / 1e3)
+    } else {
+        format!("{:.3}Hz", hz)
+    }
+}
+
+fn format_seconds(seconds: f64) -> String {
+    if seconds >= 1.0 {
+        format!("{:.3}s", seconds)
+    } else if seconds >= 1e-3 {
+        format!("{:.3}ms", seconds * 1e3)
+    } else if seconds >= 1e-6 {
+        format!("{:.3}us", seconds * 1e6)
+    } else {
+        format!("{:.1}ns", seconds * 1e9)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 1ns ticks. `dut.led` is x until 100ns, then 0, toggling every 500ns
+    /// (1MHz) from 600ns to 5.1us. `miso` is x except while `cs_n` is low
+    /// from 200ns to 400ns. `a.clk` and `b.clk` share a suffix.
+    fn waveform() -> Vcd {
+        let mut source = String::from(
+            "$timescale 1ns $end
+$scope module tb $end
+$var wire 1 ! cs_n $end
+$var wire 1 \" miso $end
+$scope module dut $end $var wire 1 # led $end $upscope $end
+$scope module a $end $var wire 1 $ clk $end $upscope $end
+$scope module b $end $var wire 1 % clk $end $upscope $end
+$upscope $end
+$enddefinitions $end
+#0 1! x\" x# 0$ 0%
+#100 0#
+#200 0! 1\"
+#300 0\"
+#400 1! x\"
+",
+        );
+        for k in 0..10 {
+            source.push_str(&format!("#{} {}#\n", 600 + 500 * k, (k + 1) % 2));
+        }
+        Vcd::parse(&source).unwrap()
+    }
+
+    /// Failures of the checks in a `<name>_tb.toml` against [`waveform`]
+    fn failures(checks: &str) -> Vec<String> {
+        let checks: WaveChecks = toml::from_str(checks).unwrap();
+        checks.evaluate(&waveform()).unwrap()
+    }
+
+    #[test]
+    fn parses_timescales() {
+        let close = |timescale: &str, seconds: f64| {
+            let parsed = parse_timescale(timescale).unwrap();
+            assert!(
+                (parsed - seconds).abs() < seconds * 1e-9,
+                "{}: {}",
+                timescale,
+                parsed
+            );
+        };
+        close("1ns", 1e-9);
+        close("10ps", 10e-12);
+        close("100us", 100e-6);
+        close("s", 1.0);
+        assert!(parse_timescale("1min").is_err());
+    }
+
+    #[test]
+    fn parses_frequencies() {
+        assert_eq!(parse_frequency("1Hz").unwrap(), 1.0);
+        assert_eq!(parse_frequency("1kHz").unwrap(), 1e3);
+        assert_eq!(parse_frequency("12 MHz").unwrap(), 12e6);
+        assert_eq!(parse_frequency("2.5GHz").unwrap(), 2.5e9);
+        assert!(parse_frequency("1k").is_err());
+        assert!(parse_frequency("fastHz").is_err());
+    }
+
+    #[test]
+    fn measures_toggle_frequency_within_tolerance() {
+        let check = |toggles: &str, tolerance: f64| {
+            failures(&format!(
+                "[[check]]\nsignal = \"dut.led\"\ntoggles = \"{}\"\ntolerance = {}",
+                toggles, tolerance
+            ))
+        };
+        assert!(check("1MHz", 0.1).is_empty());
+        assert!(check("1.05MHz", 0.1).is_empty());
+        assert_eq!(
+            check("1.05MHz", 0.01),
+            ["dut.led: expected to toggle at 1.05MHz but measured 1.000MHz"]
+        );
+        assert_eq!(
+            check("2MHz", 0.1),
+            ["dut.led: expected to toggle at 2MHz but measured 1.000MHz"]
+        );
+    }
+
+    #[test]
+    fn too_few_toggles_in_the_window_fail() {
+        // Settling from x to 0 at 100ns is not a toggle, leaving 600ns and 1.1us
+        assert_eq!(
+            failures("[[check]]\nsignal = \"dut.led\"\ntoggles = \"1MHz\"\nbefore = \"1200ns\""),
+            ["dut.led: expected to toggle at 1MHz but changed 2 time(s)"]
+        );
+    }
+
+    #[test]
+    fn never_x_only_while_the_condition_holds() {
+        assert!(
+            failures("[[check]]\nsignal = \"miso\"\nnever = \"x\"\nwhile = \"cs_n == 0\"")
+                .is_empty()
+        );
+        assert_eq!(
+            failures("[[check]]\nsignal = \"miso\"\nnever = \"x\""),
+            ["miso: expected never x but was x at 0.0ns"]
+        );
+        assert_eq!(
+            failures("[[check]]\nsignal = \"miso\"\nnever = \"x\"\nwhile = \"cs_n != 0\""),
+            ["miso: expected never x but was x at 0.0ns"]
+        );
+        // A bare signal means "== 1"
+        assert_eq!(
+            failures("[[check]]\nsignal = \"miso\"\nnever = \"x\"\nwhile = \"cs_n\""),
+            ["miso: expected never x but was x at 0.0ns"]
+        );
+    }
+
+    #[test]
+    fn checks_only_between_after_and_before() {
+        assert_eq!(
+            failures("[[check]]\nsignal = \"dut.led\"\nnever = \"x\""),
+            ["dut.led: expected never x but was x at 0.0ns"]
+        );
+        assert!(
+            failures("[[check]]\nsignal = \"dut.led\"\nnever = \"x\"\nafter = \"100ns\"")
+                .is_empty()
+        );
+        assert!(failures(
+            "[[check]]\nsignal = \"dut.led\"\nalways = \"0\"\nafter = \"100ns\"\nbefore = \"599ns\""
+        )
+        .is_empty());
+        assert_eq!(
+            failures(
+                "[[check]]\nsignal = \"dut.led\"\nalways = \"0b0\"\nafter = \"100ns\"\nbefore = \"1us\""
+            ),
+            ["dut.led: expected always 0b0 but was 1 at 600.0ns"]
+        );
+    }
+
+    #[test]
+    fn ambiguous_suffix_is_an_error() {
+        let checks: WaveChecks =
+            toml::from_str("[[check]]\nsignal = \"clk\"\nnever = \"x\"").unwrap();
+        let error = checks.evaluate(&waveform()).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "Signal clk is ambiguous (2 matches), use a longer name"
+        );
+        assert!(failures("[[check]]\nsignal = \"a.clk\"\nnever = \"x\"").is_empty());
+        assert!(failures("[[check]]\nsignal = \"tb.b.clk\"\nalways = \"0\"").is_empty());
+    }
+
+    #[test]
+    fn missing_signal_is_an_error() {
+        let checks: WaveChecks =
+            toml::from_str("[[check]]\nsignal = \"mosi\"\nnever = \"x\"").unwrap();
+        assert!(checks.evaluate(&waveform()).is_err());
+    }
+}
@@ -0,0 +1,29 @@
+/**
+ * Affogato Testbench Clock
+ *
+ * Free-running clock for simulation. Defaults to the 48 MHz rate of the
+ * UP5K SB_HFOSC used by most designs on this board.
+ *
+ * Parameters:
+ *   FREQ_HZ - Clock frequency (default: 48 MHz)
+ *
+ * Tasks:
+ *   wait_cycles(n) - Wait for n rising edges
+ */
+`timescale 1ns / 1ps
+module affogato_clock #(
+    parameter FREQ_HZ = 48_000_000
+) (
+    output reg clk
+);
+
+    localparam real HALF_PERIOD_NS = 500_000_000.0 / FREQ_HZ;
+
+    initial clk = 1'b0;
+    always #(HALF_PERIOD_NS) clk = ~clk;
+
+    task wait_cycles(input integer n);
+        repeat (n) @(posedge clk);
+    endtask
+
+endmodule
@@ -0,0 +1,106 @@
+/**
+ * Affogato SPI Master Bus Functional Model
+ *
+ * Drives SPI transactions the way the ESP32-S2 FSPI peripheral does:
+ * active-low chip select asserted before the first clock edge, MSB-first
+ * bytes with no gaps inside a transaction, and the clock idling at CPOL.
+ *
+ * Parameters:
+ *   MODE       - SPI mode 0-3 (default: 0, as used by the examples)
+ *   FREQ_HZ    - SCK frequency (default: 10 MHz)
+ *   CS_SETUP_NS - CS assertion to first edge, and last edge to CS release
+ *
+ * Tasks:
+ *   select()              - Assert CS
+ *   deselect()            - Release CS
+ *   transfer(tx, rx)      - Exchange one byte
+ *   write_bytes(data, n)  - Select, send the low n bytes of data (most
+ *                           significant first), deselect
+ *
+ * Example:
+ *   affogato_spi_master #(.MODE(0)) spi (
+ *       .sck(sck), .mosi(mosi), .miso(miso), .cs_n(cs)
+ *   );
+ *   reg [7:0] rx;
+ *   initial begin
+ *       spi.select();
+ *       spi.transfer(8'h03, rx);
+ *       spi.deselect();
+ *   end
+ */
+`timescale 1ns / 1ps
+module affogato_spi_master #(
+    parameter MODE = 0,
+    parameter FREQ_HZ = 10_000_000,
+    parameter CS_SETUP_NS = 50
+) (
+    output reg sck,
+    output reg mosi,
+    input wire miso,
+    output reg cs_n
+);
+
+    localparam CPOL = (MODE >> 1) & 1;
+    localparam CPHA = MODE & 1;
+    localparam real HALF_PERIOD_NS = 500_000_000.0 / FREQ_HZ;
+
+    initial begin
+        sck = CPOL;
+        mosi = 1'b0;
+        cs_n = 1'b1;
+    end
+
+    task select;
+        begin
+            sck = CPOL;
+            cs_n = 1'b0;
+            #(CS_SETUP_NS);
+        end
+    endtask
+
+    task deselect;
+        begin
+            #(CS_SETUP_NS);
+            cs_n = 1'b1;
+            sck = CPOL;
+            #(CS_SETUP_NS);
+        end
+    endtask
+
+    task transfer(input [7:0] tx, output [7:0] rx);
+        integer i;
+        begin
+            for (i = 7; i >= 0; i = i - 1) begin
+                if (CPHA == 0) begin
+                    // Data valid before the leading edge, sampled on it
+                    mosi = tx[i];
+                    #(HALF_PERIOD_NS);
+                    sck = ~CPOL;
+                    rx[i] = miso;
+                    #(HALF_PERIOD_NS);
+                    sck = CPOL;
+                end else begin
+                    // Data changes on the leading edge, sampled on the trailing one
+                    sck = ~CPOL;
+                    mosi = tx[i];
+                    #(HALF_PERIOD_NS);
+                    sck = CPOL;
+                    rx[i] = miso;
+                    #(HALF_PERIOD_NS);
+                end
+            end
+        end
+    endtask
+
+    task write_bytes(input [255:0] data, input integer n);
+        integer b;
+        reg [7:0] discard;
+        begin
+            select();
+            for (b = n - 1; b >= 0; b = b - 1)
+                transfer(data[b*8 +: 8], discard);
+            deselect();
+        end
+    endtask
+
+endmodule
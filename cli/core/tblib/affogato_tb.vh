@@ -0,0 +1,61 @@
+/**
+ * Affogato Testbench Helpers
+ *
+ * Assertion macros that report in the format the `affogato test` runner
+ * expects: any "FAIL" line fails the test, a final "PASS" line passes it.
+ *
+ * Usage:
+ *   `include "affogato_tb.vh"
+ *
+ *   module my_tb;
+ *       `TB_DECLARE
+ *       initial begin
+ *           `ASSERT(ready, "ready after reset")
+ *           `ASSERT_EQ(data, 8'hA5, "read back data")
+ *           `TB_FINISH
+ *       end
+ *   endmodule
+ *
+ * The runner passes each run's random seed as `AFFOGATO_SEED` and as the
+ * `+seed=N` plusarg; draw random stimulus from it so failures can be
+ * reproduced with `affogato test <name> --seed N`:
+ *
+ *   integer seed = `AFFOGATO_SEED;
+ *   ...
+ *   data = $random(seed);
+ */
+`ifndef AFFOGATO_TB_VH
+`define AFFOGATO_TB_VH
+
+`define AFFOGATO_TB_VERSION 1
+
+`ifndef AFFOGATO_SEED
+`define AFFOGATO_SEED 0
+`endif
+
+// Declare the error counter used by the assertion macros
+`define TB_DECLARE integer affogato_tb_errors = 0;
+
+`define ASSERT(cond, msg) \
+    if (!(cond)) begin \
+        $display("FAIL: %s (t=%0t)", msg, $time); \
+        affogato_tb_errors = affogato_tb_errors + 1; \
+    end
+
+`define ASSERT_EQ(actual, expected, msg) \
+    if ((actual) !== (expected)) begin \
+        $display("FAIL: %s: got 0x%0h, expected 0x%0h (t=%0t)", msg, actual, expected, $time); \
+        affogato_tb_errors = affogato_tb_errors + 1; \
+    end
+
+// Print the overall result and end the simulation
+`define TB_FINISH \
+    begin \
+        if (affogato_tb_errors == 0) \
+            $display("PASS"); \
+        else \
+            $display("FAIL: %0d assertion(s) failed", affogato_tb_errors); \
+        $finish; \
+    end
+
+`endif
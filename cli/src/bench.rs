@@ -0,0 +1,218 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use serde_json::json;
+
+use affogato_core::debug_console::{BENCH_MS, BENCH_PREFIX};
+use affogato_core::docker::Docker;
+use affogato_core::project::Project;
+use affogato_core::{device, dry_run, outln, output};
+
+use crate::reg;
+
+/// SPI clocks tried when none are given, up to the ESP32's 80 MHz
+const DEFAULT_FREQS: &[u32] = &[
+    1_000_000, 2_000_000, 5_000_000, 10_000_000, 20_000_000, 26_666_667, 40_000_000, 80_000_000,
+];
+
+/// Fastest clock the ESP32's SPI peripherals generate
+const MAX_FREQ: u32 = 80_000_000;
+
+/// One clock's result from the firmware
+struct Run {
+    hz: u32,
+    actual_hz: u32,
+    bytes: u64,
+    bits: u64,
+    errors: u64,
+    us: u64,
+}
+
+/// Stream pseudorandom data through an `spi_loopback` core at each clock and
+/// report the throughput and bit error rate
+pub fn spi(docker: &Docker, project: &Project, freqs: &[String], port: &str) -> Result<()> {
+    let freqs = if freqs.is_empty() {
+        DEFAULT_FREQS.to_vec()
+    } else {
+        freqs
+            .iter()
+            .map(|freq| parse_freq(freq))
+            .collect::<Result<_>>()?
+    };
+    reg::warn_if_console_off(project);
+    docker.ensure_image()?;
+
+    outln!(
+        "{}",
+        format!("==> Benchmarking SPI on {}", port).blue().bold()
+    );
+    let command = std::iter::once("bench spi".to_string())
+        .chain(freqs.iter().map(u32::to_string))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let done = format!("{} done", BENCH_PREFIX);
+    let lines = device::query_lines(
+        docker,
+        project,
+        port,
+        &command,
+        BENCH_PREFIX,
+        &done,
+        device::REPLY_TIMEOUT + std::time::Duration::from_millis(BENCH_MS.into()),
+    )?;
+    if dry_run::is_enabled() {
+        return Ok(());
+    }
+
+    let field = |line: &'_ str, key: &str| -> Option<String> {
+        line.split_whitespace()
+            .find_map(|field| field.strip_prefix(key)?.strip_prefix('='))
+            .map(str::to_string)
+    };
+    // An error without a clock means the bench could not start at all
+    if let Some(error) = lines
+        .iter()
+        .find(|line| field(line, "hz").is_none())
+        .and_then(|line| field(line, "error"))
+    {
+        match error.as_str() {
+            "ESP_ERR_INVALID_STATE" => bail!(
+                "The firmware has no loopback to bench; add the spi_loopback core and pass its SPI host and chip select to affogato_console_enable_bench()"
+            ),
+            _ => bail!("The firmware failed to run the bench: {}", error),
+        }
+    }
+
+    let mut runs = Vec::new();
+    let mut failures = Vec::new();
+    outln!(
+        "  {:>10}  {:>10}  {:>12}  {:>10}  {:>10}",
+        "Clock",
+        "Actual",
+        "Throughput",
+        "Bit errors",
+        "Error rate"
+    );
+    for line in &lines {
+        let number = |key: &str| field(line, key).and_then(|value| value.parse::<u64>().ok());
+        match (field(line, "error"), number("hz")) {
+            (Some(error), Some(hz)) => {
+                outln!(
+                    "  {:>10}  {}",
+                    mhz(hz as u32),
+                    format!("not run: {}", error).yellow()
+                );
+                failures.push(json!({ "hz": hz, "error": error }));
+            }
+            (None, Some(hz)) => {
+                let run = (|| {
+                    Some(Run {
+                        hz: hz as u32,
+                        actual_hz: number("actual")? as u32,
+                        bytes: number("bytes")?,
+                        bits: number("bits")?,
+                        errors: number("errors")?,
+                        us: number("us")?,
+                    })
+                })()
+                .with_context(|| format!("Unexpected reply from the board: {}", line))?;
+                let row = format!(
+                    "  {:>10}  {:>10}  {:>12}  {:>10}  {:>10}",
+                    mhz(run.hz),
+                    mhz(run.actual_hz),
+                    format!("{:.2} MB/s", throughput(&run) / 1e6),
+                    run.errors,
+                    error_rate(&run)
+                );
+                if run.errors == 0 {
+                    outln!("{}", row.green());
+                } else {
+                    outln!("{}", row.red());
+                }
+                runs.push(run);
+            }
+            _ => {}
+        }
+    }
+
+    match runs
+        .iter()
+        .filter(|run| run.errors == 0 && run.bits > 0)
+        .max_by_key(|run| run.actual_hz)
+    {
+        Some(run) => outln!(
+            "{}",
+            format!(
+                "Highest clean clock: {} ({} bits without error)",
+                mhz(run.actual_hz),
+                run.bits
+            )
+            .green()
+            .bold()
+        ),
+        None => outln!("{}", "No clock ran without bit errors".red().bold()),
+    }
+
+    output::report(
+        "bench_spi",
+        runs.iter()
+            .map(|run| {
+                json!({
+                    "hz": run.hz,
+                    "actual_hz": run.actual_hz,
+                    "bytes": run.bytes,
+                    "bits": run.bits,
+                    "errors": run.errors,
+                    "us": run.us,
+                    "bytes_per_second": throughput(run),
+                })
+            })
+            .chain(failures)
+            .collect::<Vec<_>>(),
+    );
+    Ok(())
+}
+
+/// A clock in Hz, or with a k or M suffix (e.g. 500k, 26.67M)
+fn parse_freq(text: &str) -> Result<u32> {
+    let trimmed = text.trim_end_matches("Hz").trim_end_matches("hz");
+    let (number, scale) = match trimmed.chars().last() {
+        Some('M' | 'm') => (&trimmed[..trimmed.len() - 1], 1e6),
+        Some('k' | 'K') => (&trimmed[..trimmed.len() - 1], 1e3),
+        _ => (trimmed, 1.0),
+    };
+    let hz = number
+        .parse::<f64>()
+        .ok()
+        .map(|value| (value * scale).round())
+        .filter(|hz| *hz >= 1.0 && *hz <= MAX_FREQ as f64)
+        .with_context(|| {
+            format!(
+                "Invalid SPI clock: {} (expected e.g. 10M, up to {})",
+                text,
+                mhz(MAX_FREQ)
+            )
+        })?;
+    Ok(hz as u32)
+}
+
+fn mhz(hz: u32) -> String {
+    let text = format!("{:.2}", hz as f64 / 1e6);
+    format!("{} MHz", text.trim_end_matches('0').trim_end_matches('.'))
+}
+
+/// Bytes per second while transactions were on the bus
+fn throughput(run: &Run) -> f64 {
+    if run.us == 0 {
+        0.0
+    } else {
+        run.bytes as f64 * 1e6 / run.us as f64
+    }
+}
+
+fn error_rate(run: &Run) -> String {
+    if run.errors == 0 {
+        "0".to_string()
+    } else {
+        format!("{:.1e}", run.errors as f64 / run.bits.max(1) as f64)
+    }
+}
@@ -0,0 +1,795 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use affogato_core::docker::Docker;
+use affogato_core::project::Project;
+use affogato_core::{dry_run, outln, output};
+
+/// Synchronizes the FPGA to the start of a configuration image
+const PREAMBLE: [u8; 4] = [0x7e, 0xaa, 0x99, 0x7e];
+
+/// Scratch files for iceunpack, relative to the project root
+const SCRATCH_DIR: &str = ".affogato/bitstream";
+
+/// Size of each boot applet in an icemulti warm boot header
+const APPLET_SIZE: usize = 32;
+
+/// Logic tile columns holding the eight logic cells' LUT and flip-flop bits
+const LOGIC_CELL_COLUMNS: std::ops::RangeInclusive<usize> = 36..=45;
+
+/// Tiles listed per kind before the rest are only counted
+const LISTED_TILES: usize = 12;
+
+/// One configuration image: what the FPGA loads on a cold or warm boot
+#[derive(Debug, Serialize)]
+pub struct Image {
+    /// Byte offset of the image in the file
+    pub offset: usize,
+    pub size: usize,
+    /// Comment written by the tool that packed it (e.g. nextpnr's version)
+    pub comment: Option<String>,
+    /// Internal oscillator range used while configuring: low, medium or high
+    pub frequency: &'static str,
+    /// Whether the design may trigger a warm boot through SB_WARMBOOT
+    pub warmboot: bool,
+    pub nosleep: bool,
+    pub cram: Vec<Bank>,
+    pub bram: Vec<Bank>,
+}
+
+/// A CRAM or BRAM bank written by the image
+#[derive(Debug, Serialize)]
+pub struct Bank {
+    pub bank: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Whether any bit of the bank is set
+    pub used: bool,
+}
+
+/// Where an icemulti header boots from: `None` for the cold (power-on) boot,
+/// else the warm boot selected by SB_WARMBOOT's S1:S0
+#[derive(Debug, Serialize)]
+pub struct BootEntry {
+    pub warm: Option<u32>,
+    pub address: usize,
+}
+
+/// An iCE40 bitstream, decoded the way icestorm's iceunpack reads it
+#[derive(Debug, Serialize)]
+pub struct Bitstream {
+    pub size: usize,
+    /// The warm boot header of a multi-image file; empty for a single image
+    pub boot: Vec<BootEntry>,
+    pub images: Vec<Image>,
+}
+
+/// How a command sequence ended
+enum End {
+    Wakeup,
+    Reboot { address: usize },
+}
+
+impl Bitstream {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let (first, end) = parse_image(data, 0)?;
+        let End::Reboot { .. } = end else {
+            return Ok(Self {
+                size: data.len(),
+                boot: Vec::new(),
+                images: vec![first],
+            });
+        };
+
+        // An icemulti header: a cold boot applet, then one per warm boot image
+        let mut boot = Vec::new();
+        for (index, start) in (0..5).map(|i| (i, i * APPLET_SIZE)) {
+            let (_, end) = parse_image(data, start)?;
+            let End::Reboot { address } = end else {
+                bail!("Boot applet {} at byte {} does not reboot", index, start);
+            };
+            boot.push(BootEntry {
+                warm: (index > 0).then(|| index as u32 - 1),
+                address,
+            });
+        }
+        let mut addresses: Vec<usize> = boot.iter().map(|entry| entry.address).collect();
+        addresses.sort_unstable();
+        addresses.dedup();
+        let images = addresses
+            .into_iter()
+            .map(|address| parse_image(data, address).map(|(image, _)| image))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            size: data.len(),
+            boot,
+            images,
+        })
+    }
+}
+
+/// Decode the commands of the image at `start` up to its wakeup or reboot
+fn parse_image(data: &[u8], start: usize) -> Result<(Image, End)> {
+    let mut pos = start;
+    let mut comment = None;
+    while data.get(pos..pos + PREAMBLE.len()) != Some(&PREAMBLE[..]) {
+        if pos + PREAMBLE.len() > data.len() {
+            bail!("No iCE40 bitstream preamble after byte {}", start);
+        }
+        // icepack writes comments as 0xff 0x00 <text> 0x00 0xff
+        if data[pos] == 0xff && data.get(pos + 1) == Some(&0x00) {
+            let text = &data[pos + 2..];
+            let len = text
+                .windows(2)
+                .position(|pair| pair == [0x00, 0xff])
+                .context("Unterminated bitstream comment")?;
+            let lines = String::from_utf8_lossy(&text[..len]).replace('\0', "\n");
+            comment = Some(lines.trim().to_string());
+            pos += 2 + len + 2;
+            continue;
+        }
+        pos += 1;
+    }
+    pos += PREAMBLE.len();
+
+    let mut image = Image {
+        offset: start,
+        size: 0,
+        comment,
+        frequency: "low",
+        warmboot: false,
+        nosleep: false,
+        cram: Vec::new(),
+        bram: Vec::new(),
+    };
+    let (mut bank, mut width, mut height, mut address) = (0, 0, 0, 0);
+    let end = loop {
+        let command = *data
+            .get(pos)
+            .with_context(|| format!("Bitstream ends at byte {} before its wakeup", pos))?;
+        let len = (command & 0xf) as usize;
+        let payload = data
+            .get(pos + 1..pos + 1 + len)
+            .context("Bitstream ends inside a command")?
+            .iter()
+            .fold(0u32, |value, byte| value << 8 | *byte as u32);
+        pos += 1 + len;
+        match (command >> 4, payload) {
+            // CRAM or BRAM data: width x height bits, then two zero bytes
+            (0, 1 | 3) => {
+                let bytes = (width * height / 8) as usize;
+                let section = data
+                    .get(pos..pos + bytes)
+                    .context("Bitstream ends inside a bank")?;
+                let entry = Bank {
+                    bank,
+                    width,
+                    height,
+                    used: section.iter().any(|byte| *byte != 0),
+                };
+                if payload == 1 {
+                    image.cram.push(entry);
+                } else {
+                    image.bram.push(entry);
+                }
+                pos += bytes + 2;
+            }
+            (0, 5) | (2, _) => {} // CRC reset and check
+            (0, 6) => break End::Wakeup,
+            (0, 8) => break End::Reboot { address },
+            (1, value) => bank = value,
+            // An SPI read command (0x03) and the 24-bit flash address
+            (4, value) => address = (value & 0xff_ffff) as usize,
+            (5, value) => {
+                image.frequency = match value {
+                    0 => "low",
+                    1 => "medium",
+                    _ => "high",
+                }
+            }
+            (6, value) => width = value + 1,
+            (7, value) => height = value,
+            (8, _) => {} // offset within the bank
+            (9, value) => {
+                image.nosleep = value & 0x01 != 0;
+                image.warmboot = value & 0x20 != 0;
+            }
+            _ => bail!(
+                "Unknown bitstream command 0x{:02x} at byte {}",
+                command,
+                pos - 1 - len
+            ),
+        }
+    };
+    image.size = pos - start;
+    Ok((image, end))
+}
+
+/// Unpack an image with iceunpack into its ASCII form, `None` on a dry run
+pub fn unpack(
+    docker: &Docker,
+    project: &Project,
+    data: &[u8],
+    name: &str,
+) -> Result<Option<String>> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    let bin = format!("{}/{}.bin", SCRATCH_DIR, name);
+    let asc = format!("{}/{}.asc", SCRATCH_DIR, name);
+    dry_run::create_dir_all(project_root.join(SCRATCH_DIR))?;
+    dry_run::write(project_root.join(&bin), data)?;
+    let output = docker.run_in_project_capture(project, &["iceunpack", &bin, &asc], false)?;
+    if dry_run::is_enabled() {
+        return Ok(None);
+    }
+    let text = fs::read_to_string(project_root.join(&asc))
+        .with_context(|| format!("iceunpack failed: {}", output.trim()))?;
+    let _ = fs::remove_file(project_root.join(&bin));
+    let _ = fs::remove_file(project_root.join(&asc));
+    Ok(Some(text))
+}
+
+/// An image unpacked by iceunpack: each tile's bits and each BRAM's contents
+struct Asc {
+    device: Option<String>,
+    /// Section lines by (kind, x, y), e.g. ("logic_tile", 1, 1) or ("ram_data", 6, 1)
+    sections: BTreeMap<(String, u32, u32), Vec<String>>,
+    /// Bits outside any tile, such as global settings
+    extra_bits: BTreeSet<String>,
+}
+
+impl Asc {
+    fn parse(text: &str) -> Self {
+        let mut asc = Asc {
+            device: None,
+            sections: BTreeMap::new(),
+            extra_bits: BTreeSet::new(),
+        };
+        let mut current = None;
+        for line in text.lines() {
+            let Some(directive) = line.strip_prefix('.') else {
+                if let Some(key) = &current {
+                    if let Some(lines) = asc.sections.get_mut(key) {
+                        lines.push(line.trim().to_string());
+                    }
+                }
+                continue;
+            };
+            current = None;
+            let mut fields = directive.split_whitespace();
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some("device"), Some(device), _) => asc.device = Some(device.to_string()),
+                (Some("extra_bit"), ..) => {
+                    asc.extra_bits.insert(directive.to_string());
+                }
+                (Some(kind), Some(x), Some(y)) => {
+                    if let (Ok(x), Ok(y)) = (x.parse(), y.parse()) {
+                        let key = (kind.to_string(), x, y);
+                        asc.sections.insert(key.clone(), Vec::new());
+                        current = Some(key);
+                    }
+                }
+                _ => {}
+            }
+        }
+        asc
+    }
+
+    /// BRAM blocks in total and those holding any non-zero data
+    fn bram_blocks(&self) -> (usize, usize) {
+        let blocks: Vec<_> = self
+            .sections
+            .iter()
+            .filter(|((kind, _, _), _)| kind == "ram_data")
+            .collect();
+        let used = blocks
+            .iter()
+            .filter(|(_, lines)| lines.iter().any(|line| line.chars().any(|c| c != '0')))
+            .count();
+        (blocks.len(), used)
+    }
+}
+
+/// Positions (row, column) of the bits that differ between two sections,
+/// whose lines are tile bits or, with `hex`, BRAM contents in hex
+fn differing_bits(a: &[String], b: &[String], hex: bool) -> Vec<(usize, usize)> {
+    let empty = String::new();
+    let mut bits = Vec::new();
+    for row in 0..a.len().max(b.len()) {
+        let (a, b) = (a.get(row).unwrap_or(&empty), b.get(row).unwrap_or(&empty));
+        let (a, b) = (a.as_bytes(), b.as_bytes());
+        for column in 0..a.len().max(b.len()) {
+            let digit = |line: &[u8]| {
+                line.get(column)
+                    .and_then(|c| (*c as char).to_digit(16))
+                    .unwrap_or(0)
+            };
+            let changed = digit(a) ^ digit(b);
+            if !hex {
+                if changed != 0 {
+                    bits.push((row, column));
+                }
+                continue;
+            }
+            for bit in 0..4 {
+                if changed & (1 << bit) != 0 {
+                    bits.push((row, column * 4 + 3 - bit));
+                }
+            }
+        }
+    }
+    bits
+}
+
+/// Show what a bitstream holds: device, images, boot header and BRAM use
+pub fn info(docker: &Docker, project: &Project, file: &Path) -> Result<()> {
+    let data = fs::read(file).with_context(|| format!("Failed to read {}", file.display()))?;
+    let bitstream = Bitstream::parse(&data)
+        .with_context(|| format!("{} is not an iCE40 bitstream", file.display()))?;
+
+    outln!("{}", format!("==> {}", file.display()).blue().bold());
+    outln!("{:<13}{} bytes", "Size", bitstream.size);
+    if bitstream.boot.is_empty() {
+        outln!("{:<13}{}", "Boot", "single image");
+    }
+    for entry in &bitstream.boot {
+        let image = bitstream
+            .images
+            .iter()
+            .position(|image| image.offset == entry.address)
+            .unwrap_or_default();
+        let label = match entry.warm {
+            None => "Cold boot".to_string(),
+            Some(n) => format!("Warm boot {}", n),
+        };
+        outln!("{:<13}image {} at 0x{:06x}", label, image, entry.address);
+    }
+
+    let configured = project
+        .config
+        .as_ref()
+        .map(|config| config.fpga.device.as_str());
+    let mut images = Vec::new();
+    for (index, image) in bitstream.images.iter().enumerate() {
+        outln!(
+            "{}",
+            format!(
+                "Image {} at 0x{:06x}, {} bytes",
+                index, image.offset, image.size
+            )
+            .bold()
+        );
+        if let Some(comment) = &image.comment {
+            for (i, line) in comment.lines().enumerate() {
+                outln!("  {:<11}{}", if i == 0 { "Comment" } else { "" }, line);
+            }
+        }
+
+        // iceunpack names the device from the CRAM layout: 1k, 5k, 8k, ...
+        let mut device = None;
+        let mut blocks = None;
+        if project.root.is_some() {
+            docker.ensure_image()?;
+            let unpacked = &data[image.offset..image.offset + image.size];
+            if let Some(asc) = unpack(docker, project, unpacked, &format!("image{}", index))? {
+                device = asc
+                    .lines()
+                    .find_map(|line| line.strip_prefix(".device "))
+                    .map(|name| name.trim().to_string());
+                blocks = Some(Asc::parse(&asc).bram_blocks());
+            }
+        }
+        match (&device, configured) {
+            (Some(device), Some(configured)) if configured.ends_with(device.as_str()) => {
+                outln!("  {:<11}{}", "Device", configured)
+            }
+            (Some(device), Some(configured)) => outln!(
+                "  {:<11}{}  {}",
+                "Device",
+                device,
+                format!("✗ the project targets {}", configured).red()
+            ),
+            (Some(device), None) => outln!("  {:<11}{}", "Device", device),
+            (None, _) if project.root.is_none() => outln!(
+                "  {:<11}{}",
+                "Device",
+                "unknown; run inside a project to unpack it".dimmed()
+            ),
+            (None, _) => outln!("  {:<11}{}", "Device", "unknown".dimmed()),
+        }
+
+        if let Some(bank) = image.cram.first() {
+            outln!(
+                "  {:<11}{} banks of {}x{} bits",
+                "CRAM",
+                image.cram.len(),
+                bank.width,
+                bank.height
+            );
+        }
+        let used: Vec<String> = image
+            .bram
+            .iter()
+            .filter(|bank| bank.used)
+            .map(|bank| bank.bank.to_string())
+            .collect();
+        let banks = if used.is_empty() {
+            format!("none of {} banks initialized", image.bram.len())
+        } else {
+            format!(
+                "{} {} of {} initialized",
+                if used.len() == 1 { "bank" } else { "banks" },
+                used.join(", "),
+                image.bram.len()
+            )
+        };
+        match blocks {
+            Some((total, with_data)) => outln!(
+                "  {:<11}{} ({} of {} blocks hold data)",
+                "BRAM",
+                banks,
+                with_data,
+                total
+            ),
+            None => outln!("  {:<11}{}", "BRAM", banks),
+        }
+        outln!(
+            "  {:<11}{} frequency, warm boot {}{}",
+            "Config",
+            image.frequency,
+            if image.warmboot {
+                "enabled"
+            } else {
+                "disabled"
+            },
+            if image.nosleep { ", no sleep" } else { "" }
+        );
+
+        let mut entry = serde_json::to_value(image)?;
+        entry["device"] = json!(device);
+        entry["bram_blocks"] =
+            json!(blocks.map(|(total, used)| json!({ "total": total, "used": used })));
+        images.push(entry);
+    }
+    output::report(
+        "bitstream",
+        json!({
+            "file": file,
+            "size": bitstream.size,
+            "boot": bitstream.boot,
+            "images": images,
+        }),
+    );
+    Ok(())
+}
+
+/// How `bitstream diff` groups tiles, in the order it lists them
+const GROUPS: [&str; 5] = [
+    "Logic tiles",
+    "BRAM contents",
+    "BRAM tiles",
+    "IO tiles",
+    "Other tiles",
+];
+
+fn group(kind: &str) -> &'static str {
+    match kind {
+        "logic_tile" => "Logic tiles",
+        "ram_data" => "BRAM contents",
+        "ramb_tile" | "ramt_tile" => "BRAM tiles",
+        "io_tile" => "IO tiles",
+        _ => "Other tiles",
+    }
+}
+
+/// A tile or BRAM block that differs between two images
+#[derive(Serialize)]
+struct TileDiff {
+    kind: String,
+    x: u32,
+    y: u32,
+    bits: usize,
+    /// Whether only logic cell (LUT and flip-flop) bits changed
+    logic_cells_only: bool,
+}
+
+/// Compare two bitstreams tile by tile, telling new LUT or BRAM contents
+/// apart from changes to routing, IO or block configuration
+pub fn diff(docker: &Docker, project: &Project, a: &Path, b: &Path) -> Result<()> {
+    let read = |file: &Path| -> Result<(Vec<u8>, Bitstream)> {
+        let data = fs::read(file).with_context(|| format!("Failed to read {}", file.display()))?;
+        let bitstream = Bitstream::parse(&data)
+            .with_context(|| format!("{} is not an iCE40 bitstream", file.display()))?;
+        Ok((data, bitstream))
+    };
+    let (data_a, bitstream_a) = read(a)?;
+    let (data_b, bitstream_b) = read(b)?;
+
+    outln!(
+        "{}",
+        format!("==> Comparing {} and {}", a.display(), b.display())
+            .blue()
+            .bold()
+    );
+    if data_a == data_b {
+        outln!("{}", "The bitstreams are identical".green().bold());
+        output::report("bitstream_diff", json!({ "identical": true, "images": [] }));
+        return Ok(());
+    }
+    let count = bitstream_a.images.len().min(bitstream_b.images.len());
+    if bitstream_a.images.len() != bitstream_b.images.len() {
+        outln!(
+            "{} {} has {} images and {} has {}; comparing the first {}",
+            "!".yellow(),
+            a.display(),
+            bitstream_a.images.len(),
+            b.display(),
+            bitstream_b.images.len(),
+            count
+        );
+    }
+
+    docker.ensure_image()?;
+    let mut structural = false;
+    let mut changed = false;
+    let mut images = Vec::new();
+    for index in 0..count {
+        let unpacked = |data: &[u8], image: &Image, side: &str| {
+            let bytes = &data[image.offset..image.offset + image.size];
+            unpack(docker, project, bytes, &format!("{}{}", side, index))
+        };
+        let image_a = &bitstream_a.images[index];
+        let image_b = &bitstream_b.images[index];
+        let (Some(asc_a), Some(asc_b)) = (
+            unpacked(&data_a, image_a, "a")?,
+            unpacked(&data_b, image_b, "b")?,
+        ) else {
+            continue;
+        };
+        let (asc_a, asc_b) = (Asc::parse(&asc_a), Asc::parse(&asc_b));
+        if count > 1 {
+            outln!("{}", format!("Image {}", index).bold());
+        }
+        if asc_a.device != asc_b.device {
+            structural = true;
+            outln!(
+                "  {:<15}{} vs {}",
+                "Device",
+                asc_a.device.as_deref().unwrap_or("unknown"),
+                asc_b.device.as_deref().unwrap_or("unknown")
+            );
+        }
+
+        let keys: BTreeSet<_> = asc_a.sections.keys().chain(asc_b.sections.keys()).collect();
+        let mut tiles = Vec::new();
+        for key in keys {
+            let (kind, x, y) = key;
+            let empty = Vec::new();
+            let bits = differing_bits(
+                asc_a.sections.get(key).unwrap_or(&empty),
+                asc_b.sections.get(key).unwrap_or(&empty),
+                kind == "ram_data",
+            );
+            if bits.is_empty() {
+                continue;
+            }
+            let logic_cells_only = kind == "logic_tile"
+                && bits
+                    .iter()
+                    .all(|(_, column)| LOGIC_CELL_COLUMNS.contains(column));
+            tiles.push(TileDiff {
+                kind: kind.clone(),
+                x: *x,
+                y: *y,
+                bits: bits.len(),
+                logic_cells_only,
+            });
+        }
+        let extra_bits = asc_a
+            .extra_bits
+            .symmetric_difference(&asc_b.extra_bits)
+            .count();
+
+        for label in GROUPS {
+            let group: Vec<&TileDiff> = tiles
+                .iter()
+                .filter(|tile| group(&tile.kind) == label)
+                .collect();
+            if group.is_empty() {
+                continue;
+            }
+            let cells_only = group.iter().filter(|tile| tile.logic_cells_only).count();
+            let verb = if group.len() == 1 {
+                "differs"
+            } else {
+                "differ"
+            };
+            let summary = if label == "Logic tiles" {
+                format!(
+                    "{} {} ({} only in LUT/FF bits)",
+                    group.len(),
+                    verb,
+                    cells_only
+                )
+            } else {
+                format!("{} {}", group.len(), verb)
+            };
+            outln!("  {:<15}{}", label, summary);
+            for tile in group.iter().take(LISTED_TILES) {
+                outln!(
+                    "    {:<11}{} bit{}{}",
+                    format!("({},{})", tile.x, tile.y),
+                    tile.bits,
+                    if tile.bits == 1 { "" } else { "s" },
+                    if tile.logic_cells_only {
+                        ", LUT/FF only"
+                    } else {
+                        ""
+                    }
+                );
+            }
+            if group.len() > LISTED_TILES {
+                outln!("    ... and {} more", group.len() - LISTED_TILES);
+            }
+        }
+        if extra_bits > 0 {
+            outln!("  {:<15}{} differ", "Global bits", extra_bits);
+        }
+        changed |= !tiles.is_empty() || extra_bits > 0;
+
+        let routing_changed = extra_bits > 0
+            || tiles
+                .iter()
+                .any(|tile| tile.kind != "ram_data" && !tile.logic_cells_only);
+        structural |= routing_changed;
+        images.push(json!({
+            "image": index,
+            "tiles": tiles,
+            "extra_bits": extra_bits,
+            "routing_changed": routing_changed,
+        }));
+    }
+    output::report(
+        "bitstream_diff",
+        json!({ "identical": false, "routing_changed": structural, "images": images }),
+    );
+    if dry_run::is_enabled() {
+        return Ok(());
+    }
+
+    if structural {
+        outln!(
+            "{}",
+            "Routing, IO or block configuration changed".yellow().bold()
+        );
+    } else if !changed {
+        outln!(
+            "{}",
+            "The configurations are identical; only comments or framing differ"
+                .green()
+                .bold()
+        );
+    } else {
+        outln!(
+            "{}",
+            "Only LUT/FF settings and BRAM contents changed; routing is unchanged"
+                .green()
+                .bold()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tiny image: high-frequency configuration with warm boot enabled,
+    /// one 16x2 CRAM bank with a bit set and one empty BRAM bank
+    fn image() -> Vec<u8> {
+        let mut data = vec![0xff, 0x00];
+        data.extend_from_slice(b"Lattice\0nextpnr");
+        data.extend_from_slice(&[0x00, 0xff]);
+        data.extend_from_slice(&PREAMBLE);
+        data.extend_from_slice(&[
+            0x51, 0x02, // oscillator range: high
+            0x01, 0x05, // CRC reset
+            0x92, 0x00, 0x21, // nosleep and warm boot
+            0x62, 0x00, 0x0f, // width 16
+            0x72, 0x00, 0x02, // height 2
+            0x11, 0x00, // bank 0
+            0x82, 0x00, 0x00, // offset 0
+            0x01, 0x01, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, // CRAM data
+            0x11, 0x01, // bank 1
+            0x01, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // BRAM data
+            0x22, 0x12, 0x34, // CRC check
+            0x01, 0x06, // wakeup
+        ]);
+        data
+    }
+
+    /// A boot applet that reboots into the image at `address`
+    fn applet(address: usize) -> Vec<u8> {
+        let mut data = PREAMBLE.to_vec();
+        data.extend_from_slice(&[0x44, 0x03]);
+        data.extend_from_slice(&address.to_be_bytes()[5..]);
+        data.extend_from_slice(&[0x01, 0x08]);
+        data.resize(APPLET_SIZE, 0x00);
+        data
+    }
+
+    #[test]
+    fn decodes_a_single_image() {
+        let data = image();
+        let bitstream = Bitstream::parse(&data).unwrap();
+        assert_eq!(bitstream.size, data.len());
+        assert!(bitstream.boot.is_empty());
+        let [image] = &bitstream.images[..] else {
+            panic!("expected one image, got {}", bitstream.images.len());
+        };
+        assert_eq!(image.offset, 0);
+        assert_eq!(image.size, data.len());
+        assert_eq!(image.comment.as_deref(), Some("Lattice\nnextpnr"));
+        assert_eq!(image.frequency, "high");
+        assert!(image.warmboot);
+        assert!(image.nosleep);
+        let cram: Vec<_> = image
+            .cram
+            .iter()
+            .map(|b| (b.bank, b.width, b.height, b.used))
+            .collect();
+        assert_eq!(cram, [(0, 16, 2, true)]);
+        let bram: Vec<_> = image.bram.iter().map(|b| (b.bank, b.used)).collect();
+        assert_eq!(bram, [(1, false)]);
+    }
+
+    #[test]
+    fn decodes_a_warm_boot_header() {
+        let first = 5 * APPLET_SIZE;
+        let second = first + image().len();
+        let mut data = Vec::new();
+        for address in [first, first, second, second, second] {
+            data.extend(applet(address));
+        }
+        data.extend(image());
+        data.extend(image());
+
+        let bitstream = Bitstream::parse(&data).unwrap();
+        let boot: Vec<_> = bitstream
+            .boot
+            .iter()
+            .map(|entry| (entry.warm, entry.address))
+            .collect();
+        assert_eq!(
+            boot,
+            [
+                (None, first),
+                (Some(0), first),
+                (Some(1), second),
+                (Some(2), second),
+                (Some(3), second),
+            ]
+        );
+        let offsets: Vec<usize> = bitstream.images.iter().map(|image| image.offset).collect();
+        assert_eq!(offsets, [first, second]);
+    }
+
+    #[test]
+    fn rejects_truncated_or_foreign_data() {
+        let data = image();
+        let error = Bitstream::parse(&data[..data.len() - 2]).unwrap_err();
+        assert!(error.to_string().contains("before its wakeup"), "{}", error);
+        assert!(Bitstream::parse(&[0u8; 64]).is_err());
+        let mut unknown = image();
+        let wakeup = unknown.len() - 2;
+        unknown[wakeup] = 0xf1;
+        assert!(Bitstream::parse(&unknown).is_err());
+    }
+}
@@ -0,0 +1,389 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+
+use affogato_core::amaranth;
+use affogato_core::build::synthesis_sources;
+use affogato_core::docker::Docker;
+use affogato_core::dry_run;
+use affogato_core::outln;
+use affogato_core::project::Project;
+
+/// Netlist written by yosys for analysis, relative to the project root
+const NETLIST: &str = ".affogato/cdc/netlist.json";
+
+/// Flip-flop data ports traced for crossings (resets are not checked)
+const SYNC_PORTS: &[&str] = &["D", "EN"];
+
+#[derive(Deserialize)]
+struct Netlist {
+    modules: HashMap<String, Module>,
+}
+
+#[derive(Deserialize)]
+struct Module {
+    #[serde(default)]
+    ports: BTreeMap<String, Port>,
+    #[serde(default)]
+    cells: BTreeMap<String, Cell>,
+    #[serde(default)]
+    netnames: BTreeMap<String, NetName>,
+}
+
+#[derive(Deserialize)]
+struct Port {
+    direction: String,
+    bits: Vec<Bit>,
+}
+
+#[derive(Deserialize)]
+struct Cell {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    port_directions: HashMap<String, String>,
+    connections: HashMap<String, Vec<Bit>>,
+}
+
+#[derive(Deserialize)]
+struct NetName {
+    #[serde(default)]
+    hide_name: u8,
+    bits: Vec<Bit>,
+}
+
+/// A net bit, or a constant ("0", "1", "x", "z")
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(untagged)]
+enum Bit {
+    Net(u64),
+    #[allow(dead_code)]
+    Const(char),
+}
+
+/// What drives a net bit
+#[derive(Clone, Copy)]
+enum Driver<'a> {
+    Flop(&'a str),
+    Input(&'a str),
+    Logic(&'a str),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Source<'a> {
+    Flop(&'a str),
+    Input(&'a str),
+}
+
+/// A signal launched in one clock domain and captured in another
+struct Crossing {
+    from: String,
+    from_domain: String,
+    to: String,
+    to_domain: String,
+    reason: &'static str,
+}
+
+/// Synthesize the design and report unsynchronized clock-domain crossings
+pub fn run_cdc(docker: &Docker, project: &Project, top: Option<&str>) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+
+    let fpga_config = project
+        .config
+        .as_ref()
+        .map(|c| c.fpga.clone())
+        .unwrap_or_default();
+    let top = top.unwrap_or(&fpga_config.top);
+    let sources = synthesis_sources(project_root, &fpga_config)?;
+    let elaborate = amaranth::elaborate_script(&fpga_config)?;
+
+    outln!("{}", "==> Extracting netlist".blue().bold());
+    dry_run::create_dir_all(project_root.join(".affogato/cdc"))?;
+    // Keep generic flip-flop cells (all with a CLK port), folding enables and sync resets
+    let script = format!(
+        r#"set -e
+cd /workspace
+{elaborate}yosys -q -p "read_verilog -lib -specify +/ice40/cells_sim.v; read_verilog {sources}; hierarchy -top {top}; proc; flatten; opt_dff; opt_clean; write_json {netlist}"
+"#,
+        sources = sources.join(" "),
+        top = top,
+        netlist = NETLIST,
+    );
+    docker.run_in_project(project, &["bash", "-c", &script], &[], false)?;
+
+    let path = project_root.join(NETLIST);
+    let json =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let netlist: Netlist = serde_json::from_str(&json).context("Failed to parse yosys netlist")?;
+    let module = netlist
+        .modules
+        .get(top)
+        .with_context(|| format!("Top module '{}' not found in netlist", top))?;
+
+    let (domains, crossings) = analyze(module);
+
+    outln!("{}", "==> Clock domains".blue().bold());
+    for (domain, count) in &domains {
+        outln!("  {:40} {} flip-flop(s)", domain, count);
+    }
+    outln!();
+
+    if domains.len() < 2 {
+        outln!("{}", "Single clock domain, nothing to check.".green());
+        return Ok(());
+    }
+
+    if crossings.is_empty() {
+        outln!("{}", "All clock-domain crossings are synchronized.".green());
+        return Ok(());
+    }
+
+    outln!("{}", "Unsynchronized crossings:".bold());
+    for crossing in &crossings {
+        outln!(
+            "  {} ({}) -> {} ({})",
+            crossing.from.yellow(),
+            crossing.from_domain,
+            crossing.to.yellow(),
+            crossing.to_domain
+        );
+        outln!("    {}", crossing.reason.red());
+    }
+    outln!();
+    outln!(
+        "Pass signals through a two flip-flop synchronizer (or a handshake/async FIFO for buses)."
+    );
+
+    bail!(
+        "{} unsynchronized clock-domain crossing(s)",
+        crossings.len()
+    );
+}
+
+/// Returns flip-flop counts per clock domain and the unsynchronized crossings
+fn analyze(module: &Module) -> (BTreeMap<String, usize>, Vec<Crossing>) {
+    let names = net_names(module);
+    let name_of = |bit: Bit| -> String {
+        match bit {
+            Bit::Net(n) => names.get(&n).cloned().unwrap_or_else(|| format!("${}", n)),
+            Bit::Const(c) => format!("1'b{}", c),
+        }
+    };
+
+    let flops: BTreeMap<&str, &Cell> = module
+        .cells
+        .iter()
+        .filter(|(_, cell)| is_flop(cell))
+        .map(|(name, cell)| (name.as_str(), cell))
+        .collect();
+
+    // Map every net bit to its driver and its loads
+    let mut drivers: HashMap<Bit, Driver> = HashMap::new();
+    let mut loads: HashMap<Bit, Vec<(&str, &str)>> = HashMap::new();
+    for (name, port) in &module.ports {
+        if port.direction == "input" {
+            for bit in &port.bits {
+                drivers.insert(*bit, Driver::Input(name));
+            }
+        }
+    }
+    for (name, cell) in &module.cells {
+        let flop = flops.contains_key(name.as_str());
+        for (port, bits) in &cell.connections {
+            let output = cell
+                .port_directions
+                .get(port)
+                .is_some_and(|d| d == "output");
+            for bit in bits {
+                if output {
+                    let driver = if flop {
+                        Driver::Flop(name)
+                    } else {
+                        Driver::Logic(name)
+                    };
+                    drivers.insert(*bit, driver);
+                } else {
+                    loads.entry(*bit).or_default().push((name, port));
+                }
+            }
+        }
+    }
+
+    let clock_of = |cell: &Cell| cell.connections["CLK"][0];
+    let mut domains: BTreeMap<String, usize> = BTreeMap::new();
+    for cell in flops.values() {
+        *domains.entry(name_of(clock_of(cell))).or_default() += 1;
+    }
+
+    // Every (source, capturing flop) pair, and whether the path is a direct wire
+    let mut edges: BTreeMap<(Source, &str), bool> = BTreeMap::new();
+    for (&name, cell) in &flops {
+        for port in SYNC_PORTS {
+            let Some(bits) = cell.connections.get(*port) else {
+                continue;
+            };
+            for bit in bits {
+                for (source, direct) in trace_sources(module, &drivers, *bit) {
+                    let entry = edges.entry((source, name)).or_insert(true);
+                    *entry &= direct;
+                }
+            }
+        }
+    }
+
+    // An input sampled by a single domain is assumed to be synchronous to it
+    let mut input_domains: HashMap<&str, BTreeSet<Bit>> = HashMap::new();
+    for (source, to) in edges.keys() {
+        if let Source::Input(port) = source {
+            input_domains
+                .entry(port)
+                .or_default()
+                .insert(clock_of(flops[to]));
+        }
+    }
+
+    let mut crossings = Vec::new();
+    for ((source, to), direct) in &edges {
+        let to_cell = flops[to];
+        let to_clock = clock_of(to_cell);
+        let (from, from_domain) = match source {
+            Source::Flop(from) => {
+                let from_clock = clock_of(flops[from]);
+                if from_clock == to_clock {
+                    continue;
+                }
+                (
+                    name_of(flops[from].connections["Q"][0]),
+                    name_of(from_clock),
+                )
+            }
+            Source::Input(port) => {
+                let sampled_by = &input_domains[port];
+                if sampled_by.len() < 2 {
+                    continue;
+                }
+                // Clock inputs feeding logic are not data crossings
+                if flops
+                    .values()
+                    .any(|f| module.ports[*port].bits.contains(&clock_of(f)))
+                {
+                    continue;
+                }
+                (port.to_string(), "async input".to_string())
+            }
+        };
+
+        let reason = if !direct {
+            "combinational logic before the first synchronizer stage"
+        } else if !feeds_second_stage(to_cell, &flops, &loads) {
+            "no second synchronizer stage"
+        } else {
+            continue;
+        };
+
+        crossings.push(Crossing {
+            from,
+            from_domain,
+            to: name_of(to_cell.connections["Q"][0]),
+            to_domain: name_of(to_clock),
+            reason,
+        });
+    }
+
+    (domains, crossings)
+}
+
+/// Flip-flops and inputs reaching `bit` through combinational logic
+fn trace_sources<'a>(
+    module: &'a Module,
+    drivers: &HashMap<Bit, Driver<'a>>,
+    bit: Bit,
+) -> Vec<(Source<'a>, bool)> {
+    let mut sources = Vec::new();
+    let mut visited = BTreeSet::new();
+    let mut stack = vec![(bit, true)];
+
+    while let Some((bit, direct)) = stack.pop() {
+        if !visited.insert(bit) {
+            continue;
+        }
+        match drivers.get(&bit) {
+            Some(Driver::Flop(name)) => sources.push((Source::Flop(name), direct)),
+            Some(Driver::Input(port)) => sources.push((Source::Input(port), direct)),
+            Some(Driver::Logic(name)) => {
+                let cell = &module.cells[*name];
+                for (port, bits) in &cell.connections {
+                    if cell.port_directions.get(port).is_some_and(|d| d == "input") {
+                        stack.extend(bits.iter().map(|b| (*b, false)));
+                    }
+                }
+            }
+            None => {}
+        }
+    }
+
+    sources
+}
+
+/// Whether every output bit of a capturing flop feeds only a flop in the same domain
+fn feeds_second_stage(
+    cell: &Cell,
+    flops: &BTreeMap<&str, &Cell>,
+    loads: &HashMap<Bit, Vec<(&str, &str)>>,
+) -> bool {
+    let clock = cell.connections["CLK"][0];
+    cell.connections["Q"].iter().all(|bit| {
+        let Some(loads) = loads.get(bit) else {
+            return false;
+        };
+        loads.len() == 1
+            && loads.iter().all(|(name, port)| {
+                *port == "D"
+                    && flops
+                        .get(name)
+                        .is_some_and(|f| f.connections["CLK"][0] == clock)
+            })
+    })
+}
+
+fn is_flop(cell: &Cell) -> bool {
+    cell.kind.starts_with('$')
+        && cell.connections.contains_key("CLK")
+        && cell.connections.contains_key("Q")
+}
+
+/// Most readable name for each net bit, preferring user names close to the top
+fn net_names(module: &Module) -> HashMap<u64, String> {
+    let mut names: HashMap<u64, (u8, usize, String)> = HashMap::new();
+    for (name, net) in &module.netnames {
+        let width = net.bits.len();
+        for (i, bit) in net.bits.iter().enumerate() {
+            let Bit::Net(n) = bit else {
+                continue;
+            };
+            let display = if width == 1 {
+                name.clone()
+            } else {
+                format!("{}[{}]", name, i)
+            };
+            let rank = (net.hide_name, name.matches('.').count(), display);
+            names
+                .entry(*n)
+                .and_modify(|best| {
+                    if rank < *best {
+                        *best = rank.clone();
+                    }
+                })
+                .or_insert(rank);
+        }
+    }
+    names
+        .into_iter()
+        .map(|(n, (_, _, name))| (n, name))
+        .collect()
+}
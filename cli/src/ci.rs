@@ -0,0 +1,275 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use std::path::Path;
+
+use affogato_core::docker::DEFAULT_IMAGE;
+use affogato_core::dry_run;
+use affogato_core::outln;
+use affogato_core::project::Project;
+use affogato_core::test::find_test_dir;
+
+use crate::update;
+
+/// CI systems `affogato ci init` writes a pipeline for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Provider {
+    /// GitHub Actions, in .github/workflows/affogato.yml
+    #[default]
+    Github,
+    /// GitLab CI, in .gitlab-ci.yml
+    Gitlab,
+}
+
+impl Provider {
+    fn path(self) -> &'static str {
+        match self {
+            Provider::Github => ".github/workflows/affogato.yml",
+            Provider::Gitlab => ".gitlab-ci.yml",
+        }
+    }
+}
+
+/// Report of the test results, which both CI systems display
+const JUNIT_REPORT: &str = "junit.xml";
+
+/// What the project's pipeline runs and keeps, from affogato.toml and the
+/// project's layout
+struct Pipeline {
+    name: String,
+    /// Toolchain image, when not the default
+    image: Option<String>,
+    /// Build command: `build`, or `fpga` for projects without firmware
+    build: &'static str,
+    has_tests: bool,
+    artifacts: Vec<&'static str>,
+}
+
+impl Pipeline {
+    fn new(project_root: &Path, project: &Project, image: &str) -> Self {
+        let config = project.config.as_ref();
+        let name = project
+            .name
+            .clone()
+            .or_else(|| config.and_then(|c| c.project.name.clone()))
+            .unwrap_or_else(|| "affogato".to_string());
+        let has_firmware = project_root.join("firmware").is_dir();
+        let has_tests = find_test_dir(project_root, "fpga").is_some()
+            || config.is_some_and(|c| c.fpga.amaranth.is_some());
+
+        let mut artifacts = vec!["fpga/top.bin", "fpga/build/nextpnr.log"];
+        if has_firmware {
+            artifacts.extend(["firmware/build/*.bin", "firmware/build/flasher_args.json"]);
+        }
+        Self {
+            name,
+            image: (image != DEFAULT_IMAGE).then(|| image.to_string()),
+            build: if has_firmware { "build" } else { "fpga" },
+            has_tests,
+            artifacts,
+        }
+    }
+}
+
+/// Write a CI pipeline that pulls the toolchain image, builds the bitstream
+/// and firmware, runs the testbenches into a JUnit report and keeps the
+/// artifacts
+pub fn init(project: &Project, image: &str, provider: Provider, force: bool) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    let path = project_root.join(provider.path());
+    if path.exists() && !force {
+        bail!(
+            "{} already exists (use --force to overwrite)",
+            provider.path()
+        );
+    }
+
+    let pipeline = Pipeline::new(project_root, project, image);
+    let content = match provider {
+        Provider::Github => github(&pipeline),
+        Provider::Gitlab => gitlab(&pipeline),
+    };
+    if let Some(parent) = path.parent() {
+        dry_run::create_dir_all(parent)?;
+    }
+    dry_run::write(&path, content)
+        .with_context(|| format!("Failed to write {}", provider.path()))?;
+
+    outln!("{}", format!("Generated {}", provider.path()).green());
+    outln!(
+        "  affogato {}{}, then upload {}",
+        pipeline.build,
+        if pipeline.has_tests {
+            " and affogato test"
+        } else {
+            ""
+        },
+        pipeline.artifacts.join(", ")
+    );
+    if provider == Provider::Gitlab {
+        outln!(
+            "{}",
+            "The job needs a shell-executor runner with docker; see the comment at the top"
+                .yellow()
+        );
+    }
+    Ok(())
+}
+
+/// URL of the newest release build of affogato for CI's x86_64 Linux runners
+pub fn install_url() -> String {
+    format!(
+        "https://github.com/{}/releases/latest/download/affogato-x86_64-unknown-linux-gnu.tar.gz",
+        update::repository()
+    )
+}
+
+fn github(pipeline: &Pipeline) -> String {
+    let mut yaml = format!(
+        r#"# Generated by `affogato ci init` from affogato.toml. Edit freely, or rerun
+# with --force to regenerate.
+name: {name}
+
+on:
+  push:
+    branches: [main]
+  pull_request:
+
+jobs:
+  build:
+    name: Build and test
+    runs-on: ubuntu-latest
+"#,
+        name = pipeline.name,
+    );
+    if let Some(image) = &pipeline.image {
+        yaml.push_str(&format!("    env:\n      AFFOGATO_IMAGE: {}\n", image));
+    }
+    yaml.push_str(&format!(
+        r#"    steps:
+      - uses: actions/checkout@v4
+
+      - name: Install affogato
+        run: curl -fsSL {url} | sudo tar xz -C /usr/local/bin
+
+      - name: Pull the toolchain image
+        run: docker pull {image}
+
+      - name: Build
+        run: affogato {build} --format gha
+"#,
+        url = install_url(),
+        image = pipeline.image.as_deref().unwrap_or(DEFAULT_IMAGE),
+        build = pipeline.build,
+    ));
+    if pipeline.has_tests {
+        yaml.push_str(&format!(
+            r#"
+      - name: Test
+        run: affogato test --format gha --report {report}
+"#,
+            report = JUNIT_REPORT,
+        ));
+    }
+    yaml.push_str(&format!(
+        r#"
+      - name: Upload build artifacts
+        uses: actions/upload-artifact@v4
+        with:
+          name: {name}-build
+          path: |
+{paths}
+"#,
+        name = pipeline.name,
+        paths = pipeline
+            .artifacts
+            .iter()
+            .map(|path| format!("            {}", path))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    ));
+    if pipeline.has_tests {
+        yaml.push_str(&format!(
+            r#"
+      - name: Upload test report
+        if: always()
+        uses: actions/upload-artifact@v4
+        with:
+          name: {name}-tests
+          path: {report}
+"#,
+            name = pipeline.name,
+            report = JUNIT_REPORT,
+        ));
+    }
+    yaml
+}
+
+fn gitlab(pipeline: &Pipeline) -> String {
+    let mut yaml = String::from(
+        r#"# Generated by `affogato ci init --provider gitlab` from affogato.toml. Edit
+# freely, or rerun with --force to regenerate.
+#
+# affogato bind-mounts the checkout into its toolchain containers, so the job
+# needs a runner whose docker can see the checkout: a shell executor on a
+# machine with docker installed. Add `tags:` to pick one.
+"#,
+    );
+    if let Some(image) = &pipeline.image {
+        yaml.push_str(&format!("\nvariables:\n  AFFOGATO_IMAGE: {}\n", image));
+    }
+    let mut script = vec![
+        "mkdir -p \"$HOME/.local/bin\"".to_string(),
+        format!(
+            "curl -fsSL {} | tar xz -C \"$HOME/.local/bin\"",
+            install_url()
+        ),
+        "export PATH=\"$HOME/.local/bin:$PATH\"".to_string(),
+        format!(
+            "docker pull {}",
+            pipeline.image.as_deref().unwrap_or(DEFAULT_IMAGE)
+        ),
+        format!("affogato {}", pipeline.build),
+    ];
+    if pipeline.has_tests {
+        script.push(format!("affogato test --report {}", JUNIT_REPORT));
+    }
+    yaml.push_str(&format!(
+        r#"
+build:
+  stage: build
+  script:
+{script}
+  artifacts:
+    when: always
+    paths:
+{paths}
+"#,
+        script = script
+            .iter()
+            .map(|line| format!("    - {}", yaml_quote(line)))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        paths = pipeline
+            .artifacts
+            .iter()
+            .map(|path| format!("      - {}", path))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    ));
+    if pipeline.has_tests {
+        yaml.push_str(&format!("    reports:\n      junit: {}\n", JUNIT_REPORT));
+    }
+    yaml
+}
+
+/// Quote a script line that YAML would otherwise misread
+fn yaml_quote(line: &str) -> String {
+    if line.contains(": ") || line.starts_with(['"', '\'', '*', '&', '!', '|', '>', '%', '@']) {
+        format!("'{}'", line.replace('\'', "''"))
+    } else {
+        line.to_string()
+    }
+}
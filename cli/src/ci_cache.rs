@@ -0,0 +1,235 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use affogato_core::build::{FIRMWARE_OUTPUTS, FPGA_OUTPUTS};
+use affogato_core::cache;
+use affogato_core::deps::{self, VENDOR_DIR};
+use affogato_core::docker::{Docker, CCACHE_VOLUME};
+use affogato_core::dry_run;
+use affogato_core::outln;
+use affogato_core::output;
+use affogato_core::project::Project;
+
+/// Archives `affogato cache export` writes into its directory, one per part
+const BUILD_CACHE_ARCHIVE: &str = "build-cache.tar";
+const DEPS_ARCHIVE: &str = "deps.tar";
+const CCACHE_ARCHIVE: &str = "ccache.tar";
+const IMAGE_ARCHIVE: &str = "image.tar";
+
+/// Print what a CI system should cache between runs: the key to cache under,
+/// the directories and the docker volume, and the image they were built with
+pub fn manifest(docker: &Docker, project: &Project, image: &str) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    let image_id = docker
+        .image_id()
+        .context("Pull the image first (affogato docker pull); cache keys depend on it")?;
+    let digest = docker.repo_digest()?;
+    let restore_key = key_prefix(&image_id);
+    let key = format!(
+        "{}{}",
+        restore_key,
+        &source_hash(project_root, &image_id)[..16]
+    );
+    let paths = cache_paths(project);
+
+    output::report(
+        "cache_manifest",
+        json!({
+            "key": key,
+            "restore_keys": [restore_key],
+            "image": image,
+            "image_id": image_id,
+            "digest": digest,
+            "paths": paths.iter().map(|(path, _)| path.display().to_string()).collect::<Vec<_>>(),
+            "volumes": [CCACHE_VOLUME],
+        }),
+    );
+
+    outln!("{}", "==> CI cache manifest".blue().bold());
+    outln!("{:<14}{}", "Key:", key);
+    outln!("{:<14}{}", "Restore key:", restore_key);
+    outln!("{:<14}{}", "Image:", image);
+    outln!("{:<14}{}", "  id:", image_id);
+    if let Some(digest) = &digest {
+        outln!("{:<14}{}", "  digest:", digest);
+    }
+    outln!("Paths:");
+    for (path, what) in &paths {
+        outln!("  {:<40} {}", path.display(), what.dimmed());
+    }
+    outln!("Docker volume:");
+    outln!("  {:<40} {}", CCACHE_VOLUME, "ESP-IDF ccache".dimmed());
+    outln!();
+    outln!("Cache the paths directly, or everything including the volume with:");
+    outln!("  affogato cache export <dir>    after the build");
+    outln!("  affogato cache import <dir>    before it");
+    Ok(())
+}
+
+/// Write the build cache, HDL dependency clones, the ccache volume and, with
+/// `include_image`, the toolchain image as tar archives into `dir`
+pub fn export(docker: &Docker, project: &Project, dir: &Path, include_image: bool) -> Result<()> {
+    outln!(
+        "{}",
+        format!("==> Exporting caches to {}", dir.display())
+            .blue()
+            .bold()
+    );
+    dry_run::create_dir_all(dir)?;
+
+    let mut exported = Vec::new();
+    if let Some(build_cache) = cache::dir().filter(|dir| dir.is_dir()) {
+        tar_create(&dir.join(BUILD_CACHE_ARCHIVE), &build_cache)?;
+        exported.push(BUILD_CACHE_ARCHIVE);
+    }
+    if let Some(clones) = deps_dir(project).filter(|dir| dir.is_dir()) {
+        tar_create(&dir.join(DEPS_ARCHIVE), &clones)?;
+        exported.push(DEPS_ARCHIVE);
+    }
+    if docker.volume_exists(CCACHE_VOLUME)? {
+        docker.export_volume(CCACHE_VOLUME, &dir.join(CCACHE_ARCHIVE))?;
+        exported.push(CCACHE_ARCHIVE);
+    }
+    if include_image {
+        docker.ensure_image()?;
+        docker.save_image(&dir.join(IMAGE_ARCHIVE))?;
+        exported.push(IMAGE_ARCHIVE);
+    }
+
+    output::report("cache_export", json!({ "dir": dir, "archives": exported }));
+    if exported.is_empty() {
+        outln!("{}", "Nothing to export yet; run a build first".yellow());
+    } else {
+        outln!("{}", format!("Exported {}", exported.join(", ")).green());
+    }
+    Ok(())
+}
+
+/// Restore whatever `affogato cache export` wrote into `dir`
+pub fn import(docker: &Docker, project: &Project, dir: &Path) -> Result<()> {
+    if !dir.is_dir() {
+        bail!("{} not found; nothing to import", dir.display());
+    }
+    outln!(
+        "{}",
+        format!("==> Importing caches from {}", dir.display())
+            .blue()
+            .bold()
+    );
+
+    let mut imported = Vec::new();
+    let archive = dir.join(BUILD_CACHE_ARCHIVE);
+    if archive.exists() {
+        let build_cache = cache::dir().context("No cache directory; set AFFOGATO_CACHE_DIR")?;
+        tar_extract(&archive, &build_cache)?;
+        imported.push(BUILD_CACHE_ARCHIVE);
+    }
+    let archive = dir.join(DEPS_ARCHIVE);
+    if archive.exists() {
+        if let Some(clones) = deps_dir(project) {
+            tar_extract(&archive, &clones)?;
+            imported.push(DEPS_ARCHIVE);
+        }
+    }
+    let archive = dir.join(CCACHE_ARCHIVE);
+    if archive.exists() {
+        docker.import_volume(CCACHE_VOLUME, &archive)?;
+        imported.push(CCACHE_ARCHIVE);
+    }
+    let archive = dir.join(IMAGE_ARCHIVE);
+    if archive.exists() {
+        docker.load_image(&archive)?;
+        imported.push(IMAGE_ARCHIVE);
+    }
+
+    output::report("cache_import", json!({ "dir": dir, "archives": imported }));
+    if imported.is_empty() {
+        outln!("{}", "No cache archives found".yellow());
+    } else {
+        outln!("{}", format!("Imported {}", imported.join(", ")).green());
+    }
+    Ok(())
+}
+
+/// Directories worth caching, with what each holds
+fn cache_paths(project: &Project) -> Vec<(PathBuf, &'static str)> {
+    let mut paths = Vec::new();
+    if let Some(dir) = cache::dir() {
+        paths.push((dir, "shared build cache"));
+    }
+    let has_deps = project
+        .config
+        .as_ref()
+        .is_some_and(|config| !config.fpga.deps.is_empty());
+    if has_deps {
+        paths.push((PathBuf::from(deps::CACHE_DIR), "HDL dependency clones"));
+    }
+    paths
+}
+
+/// The project's HDL dependency clones, if it has a project
+fn deps_dir(project: &Project) -> Option<PathBuf> {
+    Some(project.root.as_ref()?.join(deps::CACHE_DIR))
+}
+
+/// Cache key prefix for builds with this image; any entry under it is a
+/// useful starting point
+fn key_prefix(image_id: &str) -> String {
+    let id = image_id.trim_start_matches("sha256:");
+    format!("affogato-{}-", &id[..id.len().min(12)])
+}
+
+/// Hash of everything the build reads, so the key changes with the sources
+fn source_hash(project_root: &Path, image_id: &str) -> String {
+    let mut key = cache::Key::new("ci", image_id);
+    key.file(project_root, "affogato.toml");
+    key.file(project_root, deps::LOCKFILE);
+    let mut skip: Vec<&str> = FPGA_OUTPUTS.to_vec();
+    skip.extend(["fpga/build", VENDOR_DIR]);
+    key.tree(project_root, "fpga", &skip);
+    let mut skip: Vec<&str> = FIRMWARE_OUTPUTS.to_vec();
+    skip.push("firmware/managed_components");
+    key.tree(project_root, "firmware", &skip);
+    key.finish()
+}
+
+fn tar_create(archive: &Path, dir: &Path) -> Result<()> {
+    run_tar(&[
+        "cf",
+        &archive.display().to_string(),
+        "-C",
+        &dir.display().to_string(),
+        ".",
+    ])
+}
+
+fn tar_extract(archive: &Path, dir: &Path) -> Result<()> {
+    dry_run::create_dir_all(dir)?;
+    run_tar(&[
+        "xf",
+        &archive.display().to_string(),
+        "-C",
+        &dir.display().to_string(),
+    ])
+}
+
+fn run_tar(args: &[&str]) -> Result<()> {
+    if dry_run::is_enabled() {
+        dry_run::note(&format!("tar {}", args.join(" ")));
+        return Ok(());
+    }
+    let status = Command::new("tar")
+        .args(args)
+        .status()
+        .context("Failed to run tar")?;
+    if !status.success() {
+        bail!("tar {} failed", args.join(" "));
+    }
+    Ok(())
+}
@@ -0,0 +1,169 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use std::fs;
+use std::path::Path;
+
+use affogato_core::build::FPGA_OUTPUTS;
+use affogato_core::build_info;
+use affogato_core::cache;
+use affogato_core::docker::Docker;
+use affogato_core::dry_run;
+use affogato_core::outln;
+use affogato_core::project::Project;
+
+/// What `affogato clean` removes
+pub struct CleanOptions {
+    pub fpga: bool,
+    pub firmware: bool,
+    /// The shared build cache's entries
+    pub cache: bool,
+    /// Remove the whole firmware build directory, CMake cache included,
+    /// rather than running `idf.py clean`
+    pub full: bool,
+}
+
+/// Clean each selected part, carrying on past a part that fails so one
+/// broken build directory doesn't keep the rest from being cleaned
+pub fn run(docker: &Docker, project: &Project, opts: &CleanOptions) -> Result<()> {
+    let results = [
+        opts.fpga
+            .then(|| clean_part("FPGA", || clean_fpga(docker, project))),
+        opts.firmware
+            .then(|| clean_part("firmware", || clean_firmware(docker, project, opts.full))),
+        opts.cache.then(|| clean_part("build cache", clean_cache)),
+    ];
+    let ran = results.iter().flatten().count();
+    let failed = results.iter().flatten().filter(|ok| !**ok).count();
+    if failed > 0 {
+        bail!("{} of {} cleans failed", failed, ran);
+    }
+    Ok(())
+}
+
+/// Run one part's clean under its heading; false if it failed
+fn clean_part(part: &str, clean: impl FnOnce() -> Result<()>) -> bool {
+    outln!("{}", format!("==> Cleaning {}", part).blue().bold());
+    match clean() {
+        Ok(()) => true,
+        Err(e) => {
+            outln!("{}", format!("Cleaning {} failed: {:#}", part, e).red());
+            false
+        }
+    }
+}
+
+fn clean_fpga(docker: &Docker, project: &Project) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    // Projects built by their own Makefile know their own outputs
+    if project_root.join("fpga/Makefile").exists() && project.config.is_none() {
+        docker.ensure_image()?;
+        return docker.run_in_project(project, &["make", "-C", "fpga", "clean"], &[], false);
+    }
+    let mut paths = vec!["fpga/build"];
+    paths.extend(
+        FPGA_OUTPUTS
+            .iter()
+            .filter(|output| !output.starts_with("fpga/build/")),
+    );
+    remove(docker, project, project_root, &paths)
+}
+
+fn clean_firmware(docker: &Docker, project: &Project, full: bool) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    if !project_root.join("firmware").is_dir() {
+        outln!("No firmware in this project");
+        return Ok(());
+    }
+    if full {
+        return remove(
+            docker,
+            project,
+            project_root,
+            &["firmware/build", build_info::FIRMWARE_HEADER],
+        );
+    }
+    if !project_root.join("firmware/build").is_dir() {
+        outln!("Nothing to clean");
+        return Ok(());
+    }
+    docker.ensure_image()?;
+    docker.run_in_project(
+        project,
+        &["bash", "-c", "cd firmware && idf.py clean"],
+        &[],
+        false,
+    )
+}
+
+/// Remove the cached builds of every project; fetched demos are kept
+fn clean_cache() -> Result<()> {
+    let dir = cache::dir().context("No cache directory; set AFFOGATO_CACHE_DIR")?;
+    let entries: Vec<_> = cache::KINDS
+        .iter()
+        .map(|kind| dir.join(kind))
+        .filter(|entry| entry.exists())
+        .collect();
+    if entries.is_empty() {
+        outln!("Nothing to clean");
+        return Ok(());
+    }
+    for entry in entries {
+        outln!("  {} ({})", entry.display(), human_size(size(&entry)));
+        dry_run::remove_dir_all(&entry)
+            .with_context(|| format!("Failed to remove {}", entry.display()))?;
+    }
+    Ok(())
+}
+
+/// Remove project paths, listing those that exist. Containers write them as
+/// root on Linux, so they go from inside one.
+fn remove(docker: &Docker, project: &Project, project_root: &Path, paths: &[&str]) -> Result<()> {
+    let existing: Vec<&str> = paths
+        .iter()
+        .copied()
+        .filter(|path| project_root.join(path).exists())
+        .collect();
+    if existing.is_empty() {
+        outln!("Nothing to clean");
+        return Ok(());
+    }
+    for path in &existing {
+        outln!(
+            "  {} ({})",
+            path,
+            human_size(size(&project_root.join(path)))
+        );
+    }
+    docker.ensure_image()?;
+    let mut cmd = vec!["rm", "-rf"];
+    cmd.extend(existing);
+    docker.run_in_project(project, &cmd, &[], false)
+}
+
+/// Total size of a file or everything under a directory
+fn size(path: &Path) -> u64 {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|entry| size(&entry.path())).sum())
+        .unwrap_or(0)
+}
+
+fn human_size(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1 << 30 => format!("{:.1} GB", b as f64 / (1u64 << 30) as f64),
+        b if b >= 1 << 20 => format!("{:.1} MB", b as f64 / (1u64 << 20) as f64),
+        b if b >= 1 << 10 => format!("{:.1} KB", b as f64 / (1u64 << 10) as f64),
+        b => format!("{} B", b),
+    }
+}
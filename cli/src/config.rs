@@ -1,51 +0,0 @@
-// Config module for future use (user settings, project config, etc.)
-#![allow(dead_code)]
-
-use anyhow::Result;
-use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-
-#[derive(Debug, Serialize, Deserialize, Default)]
-pub struct Config {
-    #[serde(default)]
-    pub docker: DockerConfig,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct DockerConfig {
-    #[serde(default = "default_image")]
-    pub image: String,
-}
-
-impl Default for DockerConfig {
-    fn default() -> Self {
-        Self {
-            image: default_image(),
-        }
-    }
-}
-
-fn default_image() -> String {
-    "ghcr.io/meawoppl/affogato:latest".to_string()
-}
-
-impl Config {
-    pub fn load() -> Result<Self> {
-        let config_path = Self::config_path()?;
-
-        if config_path.exists() {
-            let content = std::fs::read_to_string(&config_path)?;
-            Ok(toml::from_str(&content)?)
-        } else {
-            Ok(Self::default())
-        }
-    }
-
-    pub fn config_path() -> Result<PathBuf> {
-        let config_dir = dirs::config_dir()
-            .unwrap_or_else(|| PathBuf::from("."))
-            .join("affogato");
-
-        Ok(config_dir.join("config.toml"))
-    }
-}
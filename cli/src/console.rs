@@ -0,0 +1,261 @@
+use anyhow::{Context, Result};
+use colored::Colorize;
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Editor, ExternalPrinter, Helper};
+use std::io::{BufRead, BufReader, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use affogato_core::debug_console::{COMMANDS, PROMPT};
+use affogato_core::docker::{self, Docker};
+use affogato_core::outln;
+use affogato_core::project::Project;
+
+use crate::{reg, regmap};
+
+/// Copies lines from the container's stdin to the serial port and everything
+/// the board prints back to its stdout, exiting if the port goes away.
+/// pyserial comes with esptool in the image.
+const BRIDGE_SCRIPT: &str = r#"
+import os, serial, sys, threading
+s = serial.Serial(sys.argv[1], 115200, timeout=0.1)
+def forward():
+    try:
+        while True:
+            data = s.read(256)
+            if data:
+                sys.stdout.buffer.write(data)
+                sys.stdout.flush()
+    except Exception:
+        os._exit(1)
+threading.Thread(target=forward, daemon=True).start()
+for line in sys.stdin.buffer:
+    s.write(line)
+"#;
+
+/// Command history, in the project's state directory
+const HISTORY_FILE: &str = "console_history";
+
+/// How long `help` has to list the firmware's commands when connecting
+const DISCOVERY_TIME: Duration = Duration::from_millis(1500);
+
+/// Attach to the firmware's console, editing lines, keeping history and
+/// completing commands on the host
+pub fn run(docker: &Docker, project: &Project, port: &str) -> Result<()> {
+    docker::require_device(port)?;
+    reg::warn_if_console_off(project);
+    docker.ensure_image()?;
+
+    let mut container = docker.spawn_interactive_in_project(
+        project,
+        &["python3", "-u", "-c", BRIDGE_SCRIPT, port],
+        true,
+    )?;
+    let Some(child) = container.child() else {
+        return Ok(());
+    };
+    let mut stdin = child.stdin.take().context("No stdin for the console")?;
+    let stdout = child.stdout.take().context("No stdout from the console")?;
+
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    let commands = Arc::new(Mutex::new(
+        COMMANDS
+            .iter()
+            .chain(&["help"])
+            .map(|command| command.to_string())
+            .collect::<Vec<_>>(),
+    ));
+    let helper = ConsoleHelper {
+        commands: commands.clone(),
+        registers: regmap::names(project_root).unwrap_or_default(),
+    };
+    let mut editor: Editor<ConsoleHelper, DefaultHistory> = Editor::new()?;
+    editor.set_helper(Some(helper));
+    let history = project.state_dir()?.join(HISTORY_FILE);
+    let _ = editor.load_history(&history);
+
+    let mut print: Box<dyn FnMut(String) + Send> = match editor.create_external_printer() {
+        Ok(mut printer) => Box::new(move |line| {
+            let _ = printer.print(format!("{}\n", line));
+        }),
+        Err(_) => Box::new(|line| println!("{}", line)),
+    };
+
+    // Lines from the board: command names while `help` answers, then
+    // everything but the prompt and the echo of what was just sent
+    let sent = Arc::new(Mutex::new(String::new()));
+    let quitting = Arc::new(AtomicBool::new(false));
+    let discovery_end = Instant::now() + DISCOVERY_TIME;
+    let reader = {
+        let sent = sent.clone();
+        let quitting = quitting.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).split(b'\n') {
+                let Ok(line) = line else {
+                    break;
+                };
+                let line = strip_escapes(&String::from_utf8_lossy(&line));
+                if Instant::now() < discovery_end {
+                    if let Some(name) = help_command(&line) {
+                        let mut commands = commands.lock().unwrap();
+                        if !commands.contains(&name) {
+                            commands.push(name);
+                        }
+                    }
+                    continue;
+                }
+                let line = strip_prompts(&line);
+                let mut sent = sent.lock().unwrap();
+                if line.is_empty() || (!sent.is_empty() && line == *sent) {
+                    sent.clear();
+                    continue;
+                }
+                print(line);
+            }
+            if !quitting.load(Ordering::SeqCst) {
+                print("Disconnected from the board".red().to_string());
+            }
+        })
+    };
+
+    outln!(
+        "{}",
+        format!("==> Connected to the console on {}", port)
+            .blue()
+            .bold()
+    );
+    outln!(
+        "{}",
+        "Tab completes commands; Ctrl+D or exit to quit".yellow()
+    );
+    writeln!(stdin, "help").context("Failed to write to the console")?;
+    thread::sleep(DISCOVERY_TIME);
+
+    let prompt = format!("{} ", PROMPT);
+    loop {
+        if reader.is_finished() {
+            break;
+        }
+        let line = match editor.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into()),
+        };
+        let line = line.trim();
+        if line == "exit" || line == "quit" {
+            break;
+        }
+        if !line.is_empty() {
+            let _ = editor.add_history_entry(line);
+        }
+        *sent.lock().unwrap() = line.to_string();
+        if writeln!(stdin, "{}", line).is_err() {
+            break;
+        }
+    }
+    quitting.store(true, Ordering::SeqCst);
+    let _ = editor.save_history(&history);
+    Ok(())
+}
+
+/// A board line without its carriage return or terminal escape sequences
+fn strip_escapes(line: &str) -> String {
+    let mut text = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            // CSI sequences end with a letter
+            '\x1b' => {
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            }
+            '\r' => {}
+            _ => text.push(c),
+        }
+    }
+    text
+}
+
+/// A board line without the prompts printed before it
+fn strip_prompts(line: &str) -> String {
+    let mut text = line;
+    while let Some(rest) = text.trim_start().strip_prefix(PROMPT) {
+        text = rest;
+    }
+    text.trim().to_string()
+}
+
+/// The command a line of `help` output describes: its unindented lines start
+/// with the command name, followed by its arguments
+fn help_command(line: &str) -> Option<String> {
+    if line.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let name = line.split_whitespace().next()?;
+    name.chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        .then(|| name.to_string())
+}
+
+/// Completes command names, then the arguments of the generated console's
+/// commands, with register names from regmap.toml
+struct ConsoleHelper {
+    commands: Arc<Mutex<Vec<String>>>,
+    registers: Vec<String>,
+}
+
+impl Completer for ConsoleHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        let start = before.rfind(' ').map_or(0, |space| space + 1);
+        let partial = &before[start..];
+        let words: Vec<&str> = before[..start].split_whitespace().collect();
+        let options: Vec<String> = match words.as_slice() {
+            [] => self.commands.lock().unwrap().clone(),
+            ["reg"] => vec!["read".into(), "write".into()],
+            ["reg", "read" | "write"] => self.registers.clone(),
+            ["bench"] => vec!["spi".into()],
+            _ => Vec::new(),
+        };
+        let mut candidates: Vec<Pair> = options
+            .into_iter()
+            .filter(|option| option.to_lowercase().starts_with(&partial.to_lowercase()))
+            .map(|option| Pair {
+                display: option.clone(),
+                replacement: format!("{} ", option),
+            })
+            .collect();
+        candidates.sort_by(|a, b| a.display.cmp(&b.display));
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ConsoleHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ConsoleHelper {}
+
+impl Validator for ConsoleHelper {}
+
+impl Helper for ConsoleHelper {}
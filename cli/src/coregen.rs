@@ -0,0 +1,323 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+
+use affogato_core::dry_run;
+use affogato_core::outln;
+use affogato_core::project::Project;
+
+/// Ports of the register interface shared by generated cores: a word address,
+/// a 16-bit write strobe and a combinational read of the addressed register
+/// (the same data width as `spi_slave_reg`).
+pub const BUS_PORTS: &str = "    // Register interface
+    input wire [{addr_msb}:0] i_addr,
+    input wire [15:0] i_wdata,
+    input wire i_we,
+    output reg [15:0] o_rdata,";
+
+/// A generated core: Verilog for the FPGA and a C header for the firmware
+struct GeneratedCore {
+    name: String,
+    verilog: String,
+    header: String,
+}
+
+/// Generate a PWM block with `channels` outputs of `resolution` bits each
+pub fn generate_pwm(
+    project: &Project,
+    channels: u32,
+    resolution: u32,
+    name: Option<&str>,
+    force: bool,
+) -> Result<()> {
+    if !(1..=16).contains(&channels) {
+        bail!("--channels must be between 1 and 16");
+    }
+    if !(2..=16).contains(&resolution) {
+        bail!("--resolution must be between 2 and 16 bits");
+    }
+    let name = name
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("pwm_{}ch", channels));
+    check_name(&name)?;
+
+    let command = format!(
+        "affogato gen core pwm --channels {} --resolution {}",
+        channels, resolution
+    );
+    let msb = resolution - 1;
+    let addr_msb = addr_bits(channels + 1) - 1;
+
+    let mut decls = String::new();
+    let mut resets = String::new();
+    let mut writes = String::new();
+    let mut latches = String::new();
+    let mut outputs = String::new();
+    let mut reads = String::new();
+    for ch in 0..channels {
+        decls.push_str(&format!(
+            "    reg [{msb}:0] duty{ch};\n    reg [{msb}:0] duty{ch}_latched;\n"
+        ));
+        resets.push_str(&format!(
+            "            duty{ch} <= {resolution}'d0;\n            duty{ch}_latched <= {resolution}'d0;\n"
+        ));
+        writes.push_str(&format!(
+            "                    {}: duty{ch} <= i_wdata[{msb}:0];\n",
+            ch + 1
+        ));
+        latches.push_str(&format!("                duty{ch}_latched <= duty{ch};\n"));
+        outputs.push_str(&format!(
+            "            o_pwm[{ch}] <= enable[{ch}] && (counter < duty{ch}_latched);\n"
+        ));
+        reads.push_str(&format!(
+            "            {}: o_rdata = {};\n",
+            ch + 1,
+            pad16(&format!("duty{ch}"), resolution)
+        ));
+    }
+
+    let verilog = format!(
+        r#"/**
+ * {name} - {channels}-channel, {resolution}-bit PWM
+ *
+ * Generated by `{command}`.
+ * Register offsets for the firmware are in {name}.h.
+ *
+ * Registers (16-bit, word addressed):
+ *   0x00      CTRL  - Channel enable mask (bit N enables channel N)
+ *   0x01 + N  DUTY  - Channel N high time in clocks per 2^{resolution}-clock period
+ *
+ * Duty cycles are latched at the start of each period, so updates never
+ * produce glitch pulses.
+ */
+module {name} (
+    input wire i_clk,
+    input wire i_rst,
+
+{bus}
+
+    output reg [{ch_msb}:0] o_pwm
+);
+
+    reg [{msb}:0] counter;
+    reg [{ch_msb}:0] enable;
+{decls}
+    always @(posedge i_clk or posedge i_rst) begin
+        if (i_rst) begin
+            counter <= {resolution}'d0;
+            enable <= {channels}'d0;
+{resets}            o_pwm <= {channels}'d0;
+        end else begin
+            if (i_we) begin
+                case (i_addr)
+                    0: enable <= i_wdata[{ch_msb}:0];
+{writes}                    default: ;
+                endcase
+            end
+
+            counter <= counter + 1'b1;
+            if (counter == {{{resolution}{{1'b1}}}}) begin
+{latches}            end
+
+{outputs}        end
+    end
+
+    always @(*) begin
+        case (i_addr)
+            0: o_rdata = {ctrl_read};
+{reads}            default: o_rdata = 16'd0;
+        endcase
+    end
+
+endmodule
+"#,
+        bus = BUS_PORTS.replace("{addr_msb}", &addr_msb.to_string()),
+        ch_msb = channels - 1,
+        ctrl_read = pad16("enable", channels),
+    );
+
+    let upper = name.to_uppercase();
+    let header = format!(
+        r#"/**
+ * {name} register map - generated by `{command}`
+ */
+#pragma once
+
+#define {upper}_CHANNELS    {channels}
+#define {upper}_RESOLUTION  {resolution}
+#define {upper}_DUTY_MAX    0x{duty_max:X}
+
+/* Register offsets (16-bit words) */
+#define {upper}_CTRL        0x00
+#define {upper}_DUTY(n)     (0x01 + (n))
+"#,
+        duty_max = (1u32 << resolution) - 1,
+    );
+
+    write_core(
+        project,
+        &GeneratedCore {
+            name,
+            verilog,
+            header,
+        },
+        force,
+    )
+}
+
+/// Generate a GPIO block with `width` pins, each with output, direction and input registers
+pub fn generate_gpio(project: &Project, width: u32, name: Option<&str>, force: bool) -> Result<()> {
+    if !(1..=16).contains(&width) {
+        bail!("--width must be between 1 and 16");
+    }
+    let name = name
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("gpio_{}", width));
+    check_name(&name)?;
+
+    let command = format!("affogato gen core gpio --width {}", width);
+    let msb = width - 1;
+
+    let verilog = format!(
+        r#"/**
+ * {name} - {width}-pin GPIO
+ *
+ * Generated by `{command}`.
+ * Register offsets for the firmware are in {name}.h.
+ *
+ * Registers (16-bit, word addressed):
+ *   0x00  OUT - Output levels
+ *   0x01  DIR - Output enables (1 = drive the pin)
+ *   0x02  IN  - Pin levels, synchronized to i_clk (read-only)
+ *
+ * Connect o_gpio_out/o_gpio_oe/i_gpio_in to SB_IO tristate pins, or use
+ * the signals internally.
+ */
+module {name} (
+    input wire i_clk,
+    input wire i_rst,
+
+{bus}
+
+    output reg [{msb}:0] o_gpio_out,
+    output reg [{msb}:0] o_gpio_oe,
+    input wire [{msb}:0] i_gpio_in
+);
+
+    reg [{msb}:0] in_sync1;
+    reg [{msb}:0] in_sync2;
+
+    always @(posedge i_clk or posedge i_rst) begin
+        if (i_rst) begin
+            o_gpio_out <= {width}'d0;
+            o_gpio_oe <= {width}'d0;
+            in_sync1 <= {width}'d0;
+            in_sync2 <= {width}'d0;
+        end else begin
+            in_sync1 <= i_gpio_in;
+            in_sync2 <= in_sync1;
+            if (i_we) begin
+                case (i_addr)
+                    0: o_gpio_out <= i_wdata[{msb}:0];
+                    1: o_gpio_oe <= i_wdata[{msb}:0];
+                    default: ;
+                endcase
+            end
+        end
+    end
+
+    always @(*) begin
+        case (i_addr)
+            0: o_rdata = {out_read};
+            1: o_rdata = {oe_read};
+            2: o_rdata = {in_read};
+            default: o_rdata = 16'd0;
+        endcase
+    end
+
+endmodule
+"#,
+        bus = BUS_PORTS.replace("{addr_msb}", "1"),
+        out_read = pad16("o_gpio_out", width),
+        oe_read = pad16("o_gpio_oe", width),
+        in_read = pad16("in_sync2", width),
+    );
+
+    let upper = name.to_uppercase();
+    let header = format!(
+        r#"/**
+ * {name} register map - generated by `{command}`
+ */
+#pragma once
+
+#define {upper}_WIDTH  {width}
+
+/* Register offsets (16-bit words) */
+#define {upper}_OUT    0x00
+#define {upper}_DIR    0x01
+#define {upper}_IN     0x02
+"#
+    );
+
+    write_core(
+        project,
+        &GeneratedCore {
+            name,
+            verilog,
+            header,
+        },
+        force,
+    )
+}
+
+/// Zero-extend a signal of `width` bits to the 16-bit data bus
+fn pad16(signal: &str, width: u32) -> String {
+    if width == 16 {
+        signal.to_string()
+    } else {
+        format!("{{{}'d0, {}}}", 16 - width, signal)
+    }
+}
+
+/// Address bits needed for `registers` registers (at least 1)
+pub fn addr_bits(registers: u32) -> u32 {
+    (32 - (registers - 1).leading_zeros()).max(1)
+}
+
+pub fn check_name(name: &str) -> Result<()> {
+    let valid = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        bail!("Invalid module name: {}", name);
+    }
+    Ok(())
+}
+
+/// Write the Verilog into fpga/rtl and the header into firmware/main
+fn write_core(project: &Project, core: &GeneratedCore, force: bool) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+
+    let files = [
+        (format!("fpga/rtl/{}.v", core.name), &core.verilog),
+        (format!("firmware/main/{}.h", core.name), &core.header),
+    ];
+    if !force {
+        for (path, _) in &files {
+            if project_root.join(path).exists() {
+                bail!("{} already exists (use --force to overwrite)", path);
+            }
+        }
+    }
+
+    for (path, content) in &files {
+        let full = project_root.join(path);
+        if let Some(parent) = full.parent() {
+            dry_run::create_dir_all(parent)?;
+        }
+        dry_run::write(&full, content)?;
+        outln!("  {} {}", "Generated".green(), path);
+    }
+    Ok(())
+}
@@ -0,0 +1,720 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+
+use affogato_core::build::synthesis_sources;
+use affogato_core::build_info::{self, BuildInfo};
+use affogato_core::docker::Docker;
+use affogato_core::error::Failure;
+use affogato_core::project::{CosimConfig, Project};
+use affogato_core::{amaranth, annotate, deps, dry_run, outln, output, shared};
+
+/// Generated harness, mocks and model, relative to the project root
+const COSIM_DIR: &str = ".affogato/cosim";
+
+/// Waveform written with `--trace`, relative to the project root
+const TRACE_FILE: &str = ".affogato/cosim/cosim.vcd";
+
+/// Prefix of the lines the harness reports each test's result on
+const RESULT_PREFIX: &str = "cosim:";
+
+/// Longest a whole run may take before the simulation is killed
+const TIMEOUT_SECS: u32 = 600;
+
+/// Stand-ins for the ESP-IDF headers the SPI driver and regmap helpers
+/// include, as (path under include/, contents)
+const MOCK_HEADERS: &[(&str, &str)] = &[
+    (
+        "esp_err.h",
+        r#"#pragma once
+#include <stdio.h>
+#include <stdlib.h>
+
+typedef int esp_err_t;
+
+#define ESP_OK 0
+#define ESP_FAIL -1
+#define ESP_ERR_NO_MEM 0x101
+#define ESP_ERR_INVALID_ARG 0x102
+#define ESP_ERR_INVALID_STATE 0x103
+#define ESP_ERR_INVALID_SIZE 0x104
+#define ESP_ERR_NOT_FOUND 0x105
+#define ESP_ERR_NOT_SUPPORTED 0x106
+#define ESP_ERR_TIMEOUT 0x107
+
+const char *esp_err_to_name(esp_err_t code);
+
+#define ESP_ERROR_CHECK(x) do { \
+        esp_err_t err_ = (x); \
+        if (err_ != ESP_OK) { \
+            fprintf(stderr, "%s:%d: %s failed: %s\n", __FILE__, __LINE__, #x, esp_err_to_name(err_)); \
+            abort(); \
+        } \
+    } while (0)
+"#,
+    ),
+    (
+        "esp_log.h",
+        r#"#pragma once
+#include <stdio.h>
+
+#define ESP_LOG_(level, tag, fmt, ...) printf(level " (%s) " fmt "\n", tag, ##__VA_ARGS__)
+#define ESP_LOGE(tag, fmt, ...) ESP_LOG_("E", tag, fmt, ##__VA_ARGS__)
+#define ESP_LOGW(tag, fmt, ...) ESP_LOG_("W", tag, fmt, ##__VA_ARGS__)
+#define ESP_LOGI(tag, fmt, ...) ESP_LOG_("I", tag, fmt, ##__VA_ARGS__)
+#define ESP_LOGD(tag, fmt, ...) do { (void)(tag); } while (0)
+#define ESP_LOGV(tag, fmt, ...) do { (void)(tag); } while (0)
+"#,
+    ),
+    (
+        "driver/spi_common.h",
+        r#"#pragma once
+#include <stdbool.h>
+#include <stdint.h>
+#include <esp_err.h>
+
+typedef enum {
+    SPI1_HOST = 0,
+    SPI2_HOST = 1,
+    SPI3_HOST = 2,
+} spi_host_device_t;
+
+#define SPI_DMA_DISABLED 0
+#define SPI_DMA_CH_AUTO 3
+
+typedef struct {
+    int mosi_io_num;
+    int miso_io_num;
+    int sclk_io_num;
+    int quadwp_io_num;
+    int quadhd_io_num;
+    int max_transfer_sz;
+    uint32_t flags;
+    int intr_flags;
+} spi_bus_config_t;
+
+esp_err_t spi_bus_initialize(spi_host_device_t host, const spi_bus_config_t *config, int dma_chan);
+esp_err_t spi_bus_free(spi_host_device_t host);
+"#,
+    ),
+    (
+        "driver/spi_master.h",
+        r#"#pragma once
+#include <stddef.h>
+#include <driver/spi_common.h>
+
+#define SPI_DEVICE_HALFDUPLEX (1 << 4)
+#define SPI_TRANS_USE_RXDATA (1 << 2)
+#define SPI_TRANS_USE_TXDATA (1 << 3)
+
+typedef struct spi_device_t *spi_device_handle_t;
+
+typedef struct {
+    uint8_t command_bits;
+    uint8_t address_bits;
+    uint8_t dummy_bits;
+    uint8_t mode;
+    int clock_speed_hz;
+    int spics_io_num;
+    uint32_t flags;
+    int queue_size;
+} spi_device_interface_config_t;
+
+typedef struct {
+    uint32_t flags;
+    uint16_t cmd;
+    uint64_t addr;
+    size_t length;
+    size_t rxlength;
+    void *user;
+    union {
+        const void *tx_buffer;
+        uint8_t tx_data[4];
+    };
+    union {
+        void *rx_buffer;
+        uint8_t rx_data[4];
+    };
+} spi_transaction_t;
+
+esp_err_t spi_bus_add_device(spi_host_device_t host, const spi_device_interface_config_t *config,
+                             spi_device_handle_t *handle);
+esp_err_t spi_bus_remove_device(spi_device_handle_t handle);
+esp_err_t spi_device_transmit(spi_device_handle_t handle, spi_transaction_t *trans);
+esp_err_t spi_device_polling_transmit(spi_device_handle_t handle, spi_transaction_t *trans);
+"#,
+    ),
+    (
+        "freertos/FreeRTOS.h",
+        r#"#pragma once
+#include <stdint.h>
+
+typedef uint32_t TickType_t;
+typedef int BaseType_t;
+
+#define pdTRUE 1
+#define pdFALSE 0
+#define pdPASS 1
+#define portMAX_DELAY ((TickType_t)0xffffffff)
+#define portTICK_PERIOD_MS 1
+#define pdMS_TO_TICKS(ms) ((TickType_t)(ms))
+
+/* Simulated time passes in the FPGA model instead */
+void cosim_wait_us(uint32_t us);
+#define vTaskDelay(ticks) cosim_wait_us((uint32_t)(ticks) * 1000)
+"#,
+    ),
+    (
+        "freertos/task.h",
+        r#"#pragma once
+#include <freertos/FreeRTOS.h>
+"#,
+    ),
+    (
+        "freertos/semphr.h",
+        r#"#pragma once
+#include <freertos/FreeRTOS.h>
+
+/* Tests run on one thread, so every take succeeds */
+typedef void *SemaphoreHandle_t;
+SemaphoreHandle_t xSemaphoreCreateMutex(void);
+#define xSemaphoreCreateBinary() xSemaphoreCreateMutex()
+BaseType_t xSemaphoreTake(SemaphoreHandle_t sem, TickType_t ticks);
+BaseType_t xSemaphoreGive(SemaphoreHandle_t sem);
+"#,
+    ),
+    (
+        "ice40/master_spi.h",
+        r#"#pragma once
+#include <driver/spi_master.h>
+#include <esp_err.h>
+#include <freertos/FreeRTOS.h>
+#include <freertos/semphr.h>
+
+#define FPGA_SPI_HOST SPI2_HOST
+
+extern SemaphoreHandle_t master_spi_semaphore;
+esp_err_t master_spi_init(void);
+"#,
+    ),
+    (
+        "affogato_cosim.h",
+        r#"#pragma once
+/*
+ * Firmware/FPGA co-simulation tests. Each COSIM_TEST runs against the
+ * Verilator model of the design, talking to it through the mocked SPI
+ * driver:
+ *
+ *     COSIM_TEST(reads_id) {
+ *         uint16_t id;
+ *         COSIM_ASSERT_OK(regs_read(cosim_device, REGS_ID, &id));
+ *         COSIM_ASSERT_EQ(id, 0xCAFE);
+ *     }
+ */
+#include <stdint.h>
+#include <stdio.h>
+#include <driver/spi_master.h>
+#include <esp_err.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef void (*cosim_test_fn)(void);
+
+/* The FPGA on the SPI bus, already added */
+extern spi_device_handle_t cosim_device;
+
+void cosim_register(const char *name, cosim_test_fn fn);
+void cosim_fail(const char *file, int line, const char *message);
+/* Let the design run for a while */
+void cosim_wait_us(uint32_t us);
+uint64_t cosim_time_ns(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#define COSIM_TEST(name) \
+    static void name(void); \
+    __attribute__((constructor)) static void cosim_register_##name(void) { cosim_register(#name, name); } \
+    static void name(void)
+
+#define COSIM_ASSERT(cond) do { \
+        if (!(cond)) { \
+            cosim_fail(__FILE__, __LINE__, #cond); \
+            return; \
+        } \
+    } while (0)
+
+#define COSIM_ASSERT_EQ(a, b) do { \
+        long long a_ = (long long)(a), b_ = (long long)(b); \
+        if (a_ != b_) { \
+            char message_[256]; \
+            snprintf(message_, sizeof message_, "%s == %s (0x%llx != 0x%llx)", #a, #b, a_, b_); \
+            cosim_fail(__FILE__, __LINE__, message_); \
+            return; \
+        } \
+    } while (0)
+
+#define COSIM_ASSERT_OK(expr) COSIM_ASSERT_EQ((expr), ESP_OK)
+"#,
+    ),
+];
+
+/// The SPI master and FreeRTOS calls, routed to the harness
+const MOCK_SOURCE: &str = r#"#include <affogato_cosim.h>
+#include <freertos/semphr.h>
+#include <ice40/master_spi.h>
+
+void cosim_spi_transfer(const uint8_t *tx, uint8_t *rx, size_t bits);
+
+struct spi_device_t {
+    int unused;
+};
+
+static struct spi_device_t device;
+static int mutex;
+
+spi_device_handle_t cosim_device = &device;
+SemaphoreHandle_t master_spi_semaphore = &mutex;
+
+SemaphoreHandle_t xSemaphoreCreateMutex(void) { return &mutex; }
+
+BaseType_t xSemaphoreTake(SemaphoreHandle_t sem, TickType_t ticks)
+{
+    (void)sem, (void)ticks;
+    return pdTRUE;
+}
+
+BaseType_t xSemaphoreGive(SemaphoreHandle_t sem)
+{
+    (void)sem;
+    return pdTRUE;
+}
+
+esp_err_t master_spi_init(void) { return ESP_OK; }
+
+esp_err_t spi_bus_initialize(spi_host_device_t host, const spi_bus_config_t *config, int dma_chan)
+{
+    (void)host, (void)config, (void)dma_chan;
+    return ESP_OK;
+}
+
+esp_err_t spi_bus_free(spi_host_device_t host)
+{
+    (void)host;
+    return ESP_OK;
+}
+
+esp_err_t spi_bus_add_device(spi_host_device_t host, const spi_device_interface_config_t *config,
+                             spi_device_handle_t *handle)
+{
+    (void)host, (void)config;
+    *handle = &device;
+    return ESP_OK;
+}
+
+esp_err_t spi_bus_remove_device(spi_device_handle_t handle)
+{
+    (void)handle;
+    return ESP_OK;
+}
+
+esp_err_t spi_device_transmit(spi_device_handle_t handle, spi_transaction_t *trans)
+{
+    if (!handle || !trans) {
+        return ESP_ERR_INVALID_ARG;
+    }
+    const uint8_t *tx = trans->flags & SPI_TRANS_USE_TXDATA ? trans->tx_data : trans->tx_buffer;
+    uint8_t *rx = trans->flags & SPI_TRANS_USE_RXDATA ? trans->rx_data : trans->rx_buffer;
+    cosim_spi_transfer(tx, rx, trans->length);
+    return ESP_OK;
+}
+
+esp_err_t spi_device_polling_transmit(spi_device_handle_t handle, spi_transaction_t *trans)
+{
+    return spi_device_transmit(handle, trans);
+}
+
+const char *esp_err_to_name(esp_err_t code)
+{
+    switch (code) {
+    case ESP_OK: return "ESP_OK";
+    case ESP_FAIL: return "ESP_FAIL";
+    case ESP_ERR_NO_MEM: return "ESP_ERR_NO_MEM";
+    case ESP_ERR_INVALID_ARG: return "ESP_ERR_INVALID_ARG";
+    case ESP_ERR_INVALID_STATE: return "ESP_ERR_INVALID_STATE";
+    case ESP_ERR_TIMEOUT: return "ESP_ERR_TIMEOUT";
+    default: return "ESP_ERR";
+    }
+}
+"#;
+
+/// Timed models of the oscillators, which yosys' cells_sim.v leaves unclocked;
+/// other primitives come from cells_sim.v
+const CELLS: &str = r#"`timescale 1ns / 1ps
+
+// 48 MHz divided by 2^CLKHF_DIV
+module SB_HFOSC #(
+    parameter CLKHF_DIV = "0b00",
+    parameter TRIM_EN = "0b0"
+) (
+    input CLKHFPU, input CLKHFEN,
+    input TRIM0, input TRIM1, input TRIM2, input TRIM3, input TRIM4,
+    input TRIM5, input TRIM6, input TRIM7, input TRIM8, input TRIM9,
+    output reg CLKHF = 1'b0
+);
+    localparam real HALF_NS = CLKHF_DIV == "0b01" ? 20.833
+                            : CLKHF_DIV == "0b10" ? 41.667
+                            : CLKHF_DIV == "0b11" ? 83.333
+                            : 10.417;
+    always #(HALF_NS) CLKHF = ~CLKHF;
+endmodule
+
+// 10 kHz
+module SB_LFOSC (input CLKLFPU, input CLKLFEN, output reg CLKLF = 1'b0);
+    always #(50000) CLKLF = ~CLKLF;
+endmodule
+"#;
+
+/// Build and run the co-simulation, running the tests named in `filter` (all
+/// if empty); with `trace`, also write a waveform
+pub fn run(docker: &Docker, project: &Project, filter: &[String], trace: bool) -> Result<()> {
+    project.require_project()?;
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    let config = project.config.as_ref().context("No affogato.toml found")?;
+    let cosim = &config.cosim;
+    let tests = test_sources(project_root, cosim)?;
+    let top = cosim.top.as_deref().unwrap_or(&config.fpga.top);
+
+    deps::ensure(project)?;
+    build_info::write_fpga(project_root, &BuildInfo::current(project_root))?;
+    shared::write(project_root, &config.shared)?;
+    let verilog = synthesis_sources(project_root, &config.fpga)?;
+    let elaborate = amaranth::elaborate_script(&config.fpga)?;
+
+    let dir = project_root.join(COSIM_DIR);
+    for (path, contents) in MOCK_HEADERS {
+        let path = dir.join("include").join(path);
+        if let Some(parent) = path.parent() {
+            dry_run::create_dir_all(parent)?;
+        }
+        dry_run::write(path, contents)?;
+    }
+    dry_run::write(dir.join("affogato_cosim_mock.c"), MOCK_SOURCE)?;
+    dry_run::write(dir.join("cells.v"), CELLS)?;
+    dry_run::write(dir.join("harness.cpp"), harness(cosim, top, trace))?;
+
+    let c_sources: Vec<String> = std::iter::once(format!("{}/affogato_cosim_mock.c", COSIM_DIR))
+        .chain(tests)
+        .collect();
+    let script = format!(
+        r#"set -e
+cd /workspace
+{elaborate}D={dir}
+rm -rf $D/obj && mkdir -p $D/obj
+INCLUDES="-I$D/include -Ifirmware/main -I{tests_dir}"
+for f in {c_sources}; do
+    gcc -c -O1 -g -Wall $INCLUDES "$f" -o "$D/obj/$(basename "${{f%.c}}").c.o"
+done
+echo "Compiling Verilator model of {top}..."
+verilator --cc --exe --build -j 0 --timing {trace_flag}\
+    -Wno-fatal -Wno-lint -Wno-style -Wno-MODDUP \
+    --timescale 1ns/1ps --top-module {top} -Mdir $D/obj -o cosim \
+    -CFLAGS "-I/workspace/$D/include" \
+    -LDFLAGS "$(ls /workspace/$D/obj/*.c.o | tr '\n' ' ')" \
+    $D/cells.v {verilog} -v $(yosys-config --datdir)/ice40/cells_sim.v \
+    $D/harness.cpp > $D/verilator.log 2>&1 || {{ cat $D/verilator.log; exit 1; }}
+timeout --kill-after=5 {TIMEOUT_SECS} $D/obj/cosim {filter}
+"#,
+        dir = COSIM_DIR,
+        tests_dir = cosim.tests,
+        c_sources = c_sources.join(" "),
+        trace_flag = if trace { "--trace " } else { "" },
+        verilog = verilog.join(" "),
+        filter = filter.join(" "),
+    );
+
+    outln!(
+        "{}",
+        format!(
+            "==> Co-simulating {} against the firmware's SPI driver",
+            top
+        )
+        .blue()
+        .bold()
+    );
+    docker.ensure_image()?;
+    let (success, log) = docker.run_in_project_tee(project, &["bash", "-c", &script], false)?;
+    if dry_run::is_enabled() {
+        return Ok(());
+    }
+
+    let results: Vec<(&str, &str, &str)> = log
+        .lines()
+        .filter_map(|line| line.strip_prefix(RESULT_PREFIX))
+        .filter_map(|line| {
+            let (result, rest) = line.trim().split_once(' ')?;
+            let (name, message) = rest.split_once(' ').unwrap_or((rest, ""));
+            Some((result, name, message))
+        })
+        .collect();
+    if results.is_empty() {
+        if !success {
+            annotate::print_summary(&log);
+            return Err(Failure::BuildFailed.error("The co-simulation failed to build or run"));
+        }
+        bail!(
+            "No co-simulation tests ran{}",
+            if filter.is_empty() {
+                format!("; add COSIM_TEST()s to {}/", cosim.tests)
+            } else {
+                format!(" matching {}", filter.join(", "))
+            }
+        );
+    }
+
+    let failed: Vec<_> = results
+        .iter()
+        .filter(|(result, _, _)| *result == "FAIL")
+        .collect();
+    outln!();
+    outln!(
+        "{} passed, {} failed",
+        results.len() - failed.len(),
+        failed.len()
+    );
+    if trace {
+        outln!("Waveform: {}", TRACE_FILE);
+    }
+    output::report(
+        "cosim",
+        json!({
+            "top": top,
+            "tests": results
+                .iter()
+                .map(|(result, name, message)| json!({
+                    "test": name,
+                    "passed": *result == "PASS",
+                    "message": message,
+                }))
+                .collect::<Vec<_>>(),
+            "passed": failed.is_empty() && success,
+        }),
+    );
+    if !failed.is_empty() {
+        return Err(Failure::TestsFailed.error(format!(
+            "{} of {} co-simulation tests failed",
+            failed.len(),
+            results.len()
+        )));
+    }
+    if !success {
+        return Err(Failure::TestsFailed.error("The co-simulation exited abnormally"));
+    }
+    outln!("{}", "All co-simulation tests passed".green().bold());
+    Ok(())
+}
+
+/// C files under the `[cosim] tests` directory, relative to the project root
+fn test_sources(project_root: &Path, cosim: &CosimConfig) -> Result<Vec<String>> {
+    let dir = project_root.join(&cosim.tests);
+    let mut sources = Vec::new();
+    if dir.is_dir() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "c") {
+                sources.push(path.strip_prefix(project_root)?.display().to_string());
+            }
+        }
+    }
+    if sources.is_empty() {
+        bail!(
+            "No C test files in {}/; add COSIM_TEST()s there (see Co-Simulation in the README)",
+            cosim.tests
+        );
+    }
+    sources.sort();
+    Ok(sources)
+}
+
+/// The C++ main that drives the model: steps its timed events and the clock
+/// pin, and bit-bangs SPI mode 0 on the configured ports
+fn harness(cosim: &CosimConfig, top: &str, trace: bool) -> String {
+    let spi_half_ps = 500_000_000_000u64 / u64::from(cosim.spi_hz.max(1));
+    let (clock_half_ps, clock_toggle, clock_init) = match &cosim.clock {
+        Some(clock) => (
+            (500_000.0 / cosim.clock_mhz).round() as u64,
+            format!("top->{clock} = !top->{clock};"),
+            format!("top->{} = 0;", clock),
+        ),
+        None => (0, String::new(), String::new()),
+    };
+    let (sck, mosi, miso, cs) = (&cosim.sck, &cosim.mosi, &cosim.miso, &cosim.cs);
+    format!(
+        r#"// Generated by affogato cosim
+#include <verilated.h>
+#include "V{top}.h"
+#include <cstdio>
+#include <cstring>
+#include <string>
+#include <vector>
+{trace_include}
+#include "affogato_cosim.h"
+
+static const uint64_t SPI_HALF_PS = {spi_half_ps}ULL;
+static const uint64_t CLOCK_HALF_PS = {clock_half_ps}ULL;
+
+static VerilatedContext *ctx;
+static V{top} *top;
+static uint64_t next_clock_edge = CLOCK_HALF_PS;
+static std::string failure;
+{trace_decl}
+struct Test {{
+    const char *name;
+    cosim_test_fn fn;
+}};
+
+static std::vector<Test> &tests()
+{{
+    static std::vector<Test> registered;
+    return registered;
+}}
+
+static void settle()
+{{
+    top->eval();
+    {trace_dump}
+}}
+
+// Run the model up to `until` ps: its own timed events and the clock pin
+static void advance_to(uint64_t until)
+{{
+    for (;;) {{
+        uint64_t next = until;
+        if (top->eventsPending() && top->nextTimeSlot() < next) next = top->nextTimeSlot();
+        if (CLOCK_HALF_PS && next_clock_edge < next) next = next_clock_edge;
+        if (next > (uint64_t)ctx->time()) ctx->time(next);
+        if (CLOCK_HALF_PS && next_clock_edge == next) {{
+            {clock_toggle}
+            next_clock_edge += CLOCK_HALF_PS;
+        }}
+        settle();
+        if (next == until) break;
+    }}
+}}
+
+extern "C" void cosim_spi_transfer(const uint8_t *tx, uint8_t *rx, size_t bits)
+{{
+    uint64_t t = ctx->time();
+    top->{cs} = 0;
+    advance_to(t += SPI_HALF_PS);
+    for (size_t i = 0; i < bits; i++) {{
+        uint8_t mask = 0x80 >> (i % 8);
+        top->{mosi} = tx && (tx[i / 8] & mask) ? 1 : 0;
+        advance_to(t += SPI_HALF_PS);
+        bool miso = top->{miso} & 1;
+        top->{sck} = 1;
+        settle();
+        if (rx) rx[i / 8] = miso ? rx[i / 8] | mask : rx[i / 8] & ~mask;
+        advance_to(t += SPI_HALF_PS);
+        top->{sck} = 0;
+        settle();
+    }}
+    advance_to(t += SPI_HALF_PS);
+    top->{cs} = 1;
+    settle();
+    advance_to(t += 4 * SPI_HALF_PS);
+}}
+
+extern "C" void cosim_register(const char *name, cosim_test_fn fn)
+{{
+    tests().push_back({{name, fn}});
+}}
+
+extern "C" void cosim_fail(const char *file, int line, const char *message)
+{{
+    if (failure.empty()) failure = std::string(file) + ":" + std::to_string(line) + ": " + message;
+}}
+
+extern "C" void cosim_wait_us(uint32_t us)
+{{
+    advance_to(ctx->time() + (uint64_t)us * 1000000ULL);
+}}
+
+extern "C" uint64_t cosim_time_ns(void)
+{{
+    return ctx->time() / 1000;
+}}
+
+static bool selected(int argc, char **argv, const char *name)
+{{
+    if (argc < 2) return true;
+    for (int i = 1; i < argc; i++) {{
+        if (!strcmp(argv[i], name)) return true;
+    }}
+    return false;
+}}
+
+int main(int argc, char **argv)
+{{
+    ctx = new VerilatedContext;
+    top = new V{top}{{ctx}};
+    {trace_open}
+    top->{cs} = 1;
+    top->{sck} = 0;
+    top->{mosi} = 0;
+    {clock_init}
+    settle();
+    // Let oscillators start and power-on resets release
+    cosim_wait_us(10);
+
+    int failed = 0;
+    for (const Test &test : tests()) {{
+        if (!selected(argc, argv, test.name)) continue;
+        failure.clear();
+        test.fn();
+        if (failure.empty()) {{
+            printf("{RESULT_PREFIX} PASS %s\n", test.name);
+        }} else {{
+            failed++;
+            printf("{RESULT_PREFIX} FAIL %s %s\n", test.name, failure.c_str());
+        }}
+        fflush(stdout);
+    }}
+
+    top->final();
+    {trace_close}
+    delete top;
+    delete ctx;
+    return failed ? 1 : 0;
+}}
+"#,
+        trace_include = if trace {
+            "#include <verilated_vcd_c.h>"
+        } else {
+            ""
+        },
+        trace_decl = if trace {
+            "static VerilatedVcdC *tfp;\n"
+        } else {
+            ""
+        },
+        trace_dump = if trace { "tfp->dump(ctx->time());" } else { "" },
+        trace_open = if trace {
+            format!(
+                "ctx->traceEverOn(true);\n    tfp = new VerilatedVcdC;\n    top->trace(tfp, 99);\n    tfp->open(\"/workspace/{}\");",
+                TRACE_FILE
+            )
+        } else {
+            String::new()
+        },
+        trace_close = if trace { "tfp->close();" } else { "" },
+    )
+}
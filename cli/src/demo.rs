@@ -1,146 +1,432 @@
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 
-use crate::build::build_fpga_with_config;
-use crate::docker::Docker;
-use crate::project::{Project, ProjectConfig};
+use affogato_core::annotate::ReportFormat;
+use affogato_core::build;
+use affogato_core::cache;
+use affogato_core::docker::{require_device, Docker};
+use affogato_core::dry_run;
+use affogato_core::outln;
+use affogato_core::output;
+use affogato_core::project::Project;
+use affogato_core::test::{self, find_test_dir, TestOptions};
 
-/// Available demos
-const DEMOS: &[(&str, &str)] = &[
-    ("colorwheel", "RGB LED cycles through colors autonomously"),
-    ("web-led", "WiFi color picker controls RGB LED via SPI"),
-];
+use crate::update;
+
+/// Manifest marking a directory of `examples/` as a demo
+const MANIFEST: &str = "demo.toml";
+
+/// Where fetched demos are unpacked, one directory per release tag, under the
+/// cache directory
+const FETCHED_DIR: &str = "demos";
+
+/// A demo's demo.toml
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    name: String,
+    description: String,
+    /// Boards and anything else the demo needs, e.g. a phone for WiFi
+    #[serde(default)]
+    hardware: Vec<String>,
+    #[serde(default)]
+    build: DemoBuild,
+}
+
+/// How a demo is built
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DemoBuild {
+    /// Build the ESP32 firmware as well as the bitstream
+    #[serde(default = "default_true")]
+    firmware: bool,
+    /// Affogato components the firmware uses, copied into firmware/components
+    #[serde(default)]
+    components: Vec<String>,
+}
+
+impl Default for DemoBuild {
+    fn default() -> Self {
+        Self {
+            firmware: true,
+            components: Vec::new(),
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// A demo found in an examples directory
+struct Demo {
+    manifest: Manifest,
+    dir: PathBuf,
+    /// The checkout or fetched release it came from, holding `components/`
+    root: PathBuf,
+}
+
+/// Every demo available: from an affogato checkout or installation first,
+/// then from fetched releases, newest first. The first demo of each name wins.
+fn discover_all() -> Result<Vec<Demo>> {
+    let mut roots: Vec<PathBuf> = find_affogato_path().into_iter().collect();
+    roots.extend(fetched_releases());
+    if roots.is_empty() {
+        bail!(
+            "No demos found. Download one with `affogato demo fetch <name>`, or set \
+             AFFOGATO_PATH to an affogato checkout."
+        );
+    }
+    let mut demos: Vec<Demo> = Vec::new();
+    for root in roots {
+        for demo in discover(&root)? {
+            if !demos.iter().any(|d| d.manifest.name == demo.manifest.name) {
+                demos.push(demo);
+            }
+        }
+    }
+    demos.sort_by(|a, b| a.manifest.name.cmp(&b.manifest.name));
+    Ok(demos)
+}
+
+/// Release directories under the fetched demos, newest release first
+fn fetched_releases() -> Vec<PathBuf> {
+    let Some(dir) = cache::dir().map(|dir| dir.join(FETCHED_DIR)) else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut releases: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.join("examples").is_dir())
+        .collect();
+    // Tags are r<commit count>
+    let count = |path: &PathBuf| {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix('r'))
+            .and_then(|count| count.parse::<u64>().ok())
+            .unwrap_or_default()
+    };
+    releases.sort_by_key(|path| std::cmp::Reverse(count(path)));
+    releases
+}
+
+/// Every directory of `examples/` with a demo.toml, by name
+fn discover(root: &Path) -> Result<Vec<Demo>> {
+    let examples = root.join("examples");
+    let mut demos = Vec::new();
+    for entry in
+        fs::read_dir(&examples).with_context(|| format!("Failed to read {}", examples.display()))?
+    {
+        let dir = entry?.path();
+        let path = dir.join(MANIFEST);
+        if !path.exists() {
+            continue;
+        }
+        let text = fs::read_to_string(&path)?;
+        let manifest: Manifest =
+            toml::from_str(&text).with_context(|| format!("Invalid {}", path.display()))?;
+        demos.push(Demo {
+            manifest,
+            dir,
+            root: root.to_path_buf(),
+        });
+    }
+    demos.sort_by(|a, b| a.manifest.name.cmp(&b.manifest.name));
+    Ok(demos)
+}
 
 /// List available demos
-pub fn list_demos() {
-    println!("{}", "Available demos:".blue().bold());
-    println!();
-    for (name, description) in DEMOS {
-        println!("  {:<12} - {}", name.green(), description);
-    }
-    println!();
-    println!("Run a demo with: affogato demo <name>");
+pub fn list_demos() -> Result<()> {
+    let demos = discover_all()?;
+    print_demos(&demos);
+    Ok(())
+}
+
+fn print_demos(demos: &[Demo]) {
+    output::report(
+        "demos",
+        demos.iter().map(|demo| &demo.manifest).collect::<Vec<_>>(),
+    );
+    outln!("{}", "Available demos:".blue().bold());
+    outln!();
+    for demo in demos {
+        outln!(
+            "  {:<12} - {}",
+            demo.manifest.name.green(),
+            demo.manifest.description
+        );
+        if !demo.manifest.hardware.is_empty() {
+            outln!(
+                "  {:<12}   {}",
+                "",
+                format!("Needs: {}", demo.manifest.hardware.join(", ")).dimmed()
+            );
+        }
+    }
+    outln!();
+    outln!("Run a demo with: affogato demo <name>");
 }
 
-/// Copy a demo to the current directory and optionally build/run it
+/// Copy a demo to the current directory and optionally build/run it, or
+/// with `sim`, simulate it
 pub fn run_demo(
     docker: &Docker,
     name: &str,
     port: &str,
     build_only: bool,
+    sim: bool,
     list: bool,
 ) -> Result<()> {
     if list {
-        list_demos();
-        return Ok(());
+        return list_demos();
     }
 
-    // Find the affogato installation to locate examples
-    let affogato_path = find_affogato_path()?;
-    let demo_src = affogato_path.join("examples").join(name);
-
-    if !demo_src.exists() {
-        println!("{}", format!("Demo '{}' not found.", name).red());
-        println!();
-        list_demos();
+    let demos = discover_all()?;
+    let Some(demo) = demos.iter().find(|demo| demo.manifest.name == name) else {
+        outln!("{}", format!("Demo '{}' not found.", name).red());
+        outln!();
+        print_demos(&demos);
         bail!("Unknown demo: {}", name);
-    }
+    };
 
     let dest = PathBuf::from(name);
 
     if dest.exists() {
-        println!(
+        outln!(
             "{}",
             format!("Directory '{}' already exists. Using existing copy.", name).yellow()
         );
     } else {
-        println!(
+        outln!(
             "{}",
             format!("==> Copying demo '{}' to ./{}", name, name)
                 .blue()
                 .bold()
         );
-        copy_dir_recursive(&demo_src, &dest)?;
+        copy_dir_recursive(&demo.dir, &dest)?;
     }
 
-    // Load project config from the demo's affogato.toml
-    let dest_canonical = dest.canonicalize()?;
-    let config = ProjectConfig::load(&dest_canonical)?;
+    // The firmware finds components in its own components/ directory
+    for component in &demo.manifest.build.components {
+        let src = demo.root.join("components").join(component);
+        let component_dest = dest.join("firmware/components").join(component);
+        if !src.is_dir() {
+            bail!(
+                "Demo '{}' needs the {} component, which is not in {}",
+                name,
+                component,
+                demo.root.join("components").display()
+            );
+        }
+        if !component_dest.exists() {
+            copy_dir_recursive(&src, &component_dest)?;
+        }
+    }
 
-    // Create a project context for the demo directory
-    let project = Project {
-        root: Some(dest_canonical),
-        name: Some(name.to_string()),
-        config: Some(config.clone()),
-    };
+    let project = Project::detect_from(dest.canonicalize()?)?;
+    project.require_project()?;
+
+    if sim {
+        return simulate(docker, &project, name);
+    }
+    if !demo.manifest.hardware.is_empty() {
+        outln!("Needs: {}", demo.manifest.hardware.join(", "));
+    }
 
     docker.ensure_image()?;
 
-    // Build the demo
-    println!("{}", "==> Building FPGA bitstream".blue().bold());
-    build_fpga_with_config(docker, &project, &config)?;
+    // Build the demo as `affogato build` would
+    outln!("{}", "==> Building FPGA bitstream".blue().bold());
+    build::build_fpga(docker, &project, &[], ReportFormat::Text)?;
 
-    println!("{}", "==> Building ESP32 firmware".blue().bold());
-    // Mount components from the affogato repo
-    let components_mount = format!(
-        "-v {}:/workspace/components",
-        affogato_path.join("components").display()
-    );
-    docker.run_in_project_with_extra_mounts(
-        &project,
-        &["bash", "-c", "cd firmware && idf.py build"],
-        &[&components_mount],
-        false,
-    )?;
-
-    if build_only {
-        println!("{}", "Build complete!".green());
-        println!();
-        println!("To flash and run:");
-        println!("  cd {}", name);
-        println!("  affogato run");
+    if demo.manifest.build.firmware {
+        outln!("{}", "==> Building ESP32 firmware".blue().bold());
+        build::build_firmware(docker, &project, &[], ReportFormat::Text)?;
+    }
+
+    if build_only || !demo.manifest.build.firmware {
+        outln!("{}", "Build complete!".green());
+        outln!();
+        outln!("To flash and run:");
+        outln!("  cd {}", name);
+        outln!("  affogato run");
         return Ok(());
     }
 
     // Flash and monitor
-    println!(
+    require_device(port)?;
+    outln!(
         "{}",
         format!("==> Flashing and monitoring on {}", port)
             .blue()
             .bold()
     );
-    println!("{}", "Ctrl+] to exit".yellow());
+    outln!("{}", "Ctrl+] to exit".yellow());
 
     let flash_cmd = format!("cd firmware && idf.py -p {} flash monitor", port);
-    docker.run_in_project_with_extra_mounts(
-        &project,
-        &["bash", "-c", &flash_cmd],
-        &[&components_mount],
-        true,
-    )?;
+    docker.run_in_project(&project, &["bash", "-c", &flash_cmd], &[], true)?;
+
+    Ok(())
+}
+
+/// Run a copied demo's testbenches and open the waveform, so it can be tried
+/// without a board
+fn simulate(docker: &Docker, project: &Project, name: &str) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    if find_test_dir(project_root, "fpga").is_none() {
+        bail!("Demo '{}' has no testbenches to simulate", name);
+    }
+
+    docker.ensure_image()?;
+
+    outln!("{}", "==> Simulating the FPGA design".blue().bold());
+    let opts = TestOptions {
+        view: true,
+        fpga_dir: "fpga".to_string(),
+        ..Default::default()
+    };
+    test::run_tests(docker, project, &opts)?;
+
+    outln!();
+    outln!("To simulate again, or build and run once a board is connected:");
+    outln!("  cd {}", name);
+    outln!("  affogato test --view");
+    outln!("  affogato run");
+    Ok(())
+}
+
+/// Download a demo from the GitHub release matching this affogato (or `tag`),
+/// verify it against the release's checksums and unpack it where `affogato
+/// demo` finds it. Without a name, list the release's demos.
+pub fn fetch(name: Option<&str>, tag: Option<&str>) -> Result<()> {
+    let tag = match tag {
+        Some("latest") => update::latest_tag()?,
+        Some(tag) => tag.to_string(),
+        None => update::release_tag(),
+    };
+    let base = format!(
+        "https://github.com/{}/releases/download/{}",
+        update::repository(),
+        tag
+    );
+    let checksums =
+        update::download(&format!("{}/{}", base, update::CHECKSUMS)).with_context(|| {
+            format!(
+                "No release {} found; pass --tag with another release, or --tag latest",
+                tag
+            )
+        })?;
+    let checksums = String::from_utf8(checksums).context("Invalid checksum file")?;
+    let available: Vec<&str> = checksums
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(|file| file.trim_start_matches('*'))
+        .filter_map(|file| file.strip_prefix("demo-")?.strip_suffix(".tar.gz"))
+        .collect();
+
+    let Some(name) = name else {
+        output::report("demo_fetch", json!({ "release": tag, "demos": available }));
+        if available.is_empty() {
+            outln!("Release {} has no demos to fetch", tag);
+            return Ok(());
+        }
+        outln!("{}", format!("Demos in release {}:", tag).blue().bold());
+        for demo in &available {
+            outln!("  {}", demo.green());
+        }
+        outln!();
+        outln!("Fetch one with: affogato demo fetch <name>");
+        return Ok(());
+    };
+    if !available.contains(&name) {
+        bail!(
+            "Release {} has no demo named '{}' (it has: {})",
+            tag,
+            name,
+            available.join(", ")
+        );
+    }
+
+    outln!(
+        "{}",
+        format!("==> Fetching demo '{}' from release {}", name, tag)
+            .blue()
+            .bold()
+    );
+    let archive_name = format!("demo-{}.tar.gz", name);
+    let archive = update::download(&format!("{}/{}", base, archive_name))?;
+    update::verify(&archive, &archive_name, &checksums)?;
+    outln!("Checksum verified");
 
+    let dir = cache::dir()
+        .context("No cache directory; set AFFOGATO_CACHE_DIR")?
+        .join(FETCHED_DIR)
+        .join(&tag);
+    if dry_run::is_enabled() {
+        dry_run::note(&format!("unpack {} into {}", archive_name, dir.display()));
+        return Ok(());
+    }
+    fs::create_dir_all(&dir)?;
+    // A fresh copy replaces any earlier one of the same demo
+    let _ = fs::remove_dir_all(dir.join("examples").join(name));
+    let archive_path = dir.join(&archive_name);
+    fs::write(&archive_path, &archive)?;
+    let status = Command::new("tar")
+        .arg("xzf")
+        .arg(&archive_path)
+        .arg("-C")
+        .arg(&dir)
+        .status()
+        .context("Failed to run tar")?;
+    fs::remove_file(&archive_path)?;
+    if !status.success() {
+        bail!("Failed to unpack {}", archive_name);
+    }
+
+    output::report(
+        "demo_fetch",
+        json!({ "release": tag, "demo": name, "path": dir }),
+    );
+    outln!(
+        "{}",
+        format!("Fetched '{}' into {}", name, dir.display()).green()
+    );
+    outln!("Run it with: affogato demo {}", name);
     Ok(())
 }
 
 /// Find the affogato installation directory
-fn find_affogato_path() -> Result<PathBuf> {
+fn find_affogato_path() -> Option<PathBuf> {
     // Check environment variable first
     if let Ok(path) = std::env::var("AFFOGATO_PATH") {
         let p = PathBuf::from(path);
         if p.join("examples").exists() {
-            return Ok(p);
+            return Some(p);
         }
     }
 
     // Check if we're running from within the affogato repo
-    let exe_path = std::env::current_exe()?;
-    if let Some(parent) = exe_path.parent() {
+    if let Some(parent) = std::env::current_exe()
+        .ok()
+        .as_deref()
+        .and_then(Path::parent)
+    {
         // cargo run puts binary in target/debug or target/release
         for ancestor in parent.ancestors() {
             if ancestor.join("examples").exists() && ancestor.join("components").exists() {
-                return Ok(ancestor.to_path_buf());
+                return Some(ancestor.to_path_buf());
             }
         }
     }
@@ -154,20 +440,14 @@ fn find_affogato_path() -> Result<PathBuf> {
         PathBuf::from("/usr/local/share/affogato"),
     ];
 
-    for candidate in candidates {
-        if candidate.join("examples").exists() {
-            return Ok(candidate);
-        }
-    }
-
-    bail!(
-        "Could not find Affogato installation with examples. Set AFFOGATO_PATH environment variable."
-    );
+    candidates
+        .into_iter()
+        .find(|candidate| candidate.join("examples").exists())
 }
 
 /// Recursively copy a directory
-fn copy_dir_recursive(src: &PathBuf, dest: &PathBuf) -> Result<()> {
-    fs::create_dir_all(dest)?;
+fn copy_dir_recursive(src: &Path, dest: &Path) -> Result<()> {
+    dry_run::create_dir_all(dest)?;
 
     for entry in fs::read_dir(src)? {
         let entry = entry?;
@@ -177,7 +457,7 @@ fn copy_dir_recursive(src: &PathBuf, dest: &PathBuf) -> Result<()> {
         if src_path.is_dir() {
             copy_dir_recursive(&src_path, &dest_path)?;
         } else {
-            fs::copy(&src_path, &dest_path)?;
+            dry_run::copy(&src_path, &dest_path)?;
         }
     }
 
@@ -0,0 +1,418 @@
+use anyhow::{bail, Result};
+use colored::Colorize;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+use affogato_core::build::synthesis_sources;
+use affogato_core::config;
+use affogato_core::outln;
+use affogato_core::output;
+use affogato_core::project::{Project, ProjectConfig};
+use affogato_core::verilog;
+
+/// Images older than this get a warning, as fixes and new tools land in newer ones
+const STALE_IMAGE_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+/// The outcome of one check, with how to fix it if it didn't pass
+#[derive(Serialize)]
+struct Check {
+    name: &'static str,
+    status: Status,
+    detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fix: Option<String>,
+}
+
+#[derive(Default)]
+struct Checks(Vec<Check>);
+
+impl Checks {
+    fn ok(&mut self, name: &'static str, detail: impl Into<String>) {
+        self.add(name, Status::Ok, detail.into(), None);
+    }
+
+    fn warn(&mut self, name: &'static str, detail: impl Into<String>, fix: impl Into<String>) {
+        self.add(name, Status::Warn, detail.into(), Some(fix.into()));
+    }
+
+    fn fail(&mut self, name: &'static str, detail: impl Into<String>, fix: impl Into<String>) {
+        self.add(name, Status::Fail, detail.into(), Some(fix.into()));
+    }
+
+    fn add(&mut self, name: &'static str, status: Status, detail: String, fix: Option<String>) {
+        let mark = match status {
+            Status::Ok => "✓".green(),
+            Status::Warn => "!".yellow(),
+            Status::Fail => "✗".red(),
+        };
+        let mut lines = detail.lines();
+        outln!("  {} {}", mark, lines.next().unwrap_or_default());
+        for line in lines {
+            outln!("      {}", line);
+        }
+        if let Some(fix) = &fix {
+            outln!("    {} {}", "fix:".yellow(), fix);
+        }
+        self.0.push(Check {
+            name,
+            status,
+            detail,
+            fix,
+        });
+    }
+
+    fn count(&self, status: Status) -> usize {
+        self.0.iter().filter(|c| c.status == status).count()
+    }
+}
+
+/// Check the environment affogato needs, printing a fix for each problem.
+/// `project` is the result of detecting the project, so a broken
+/// affogato.toml is reported rather than stopping the checks.
+pub fn run(image: &str, port: &str, project: Result<Project>) -> Result<()> {
+    let mut checks = Checks::default();
+
+    outln!("{}", "==> Docker".blue().bold());
+    if check_docker(&mut checks) {
+        outln!("{}", "==> Container image".blue().bold());
+        check_image(&mut checks, image);
+    }
+
+    outln!("{}", "==> USB device".blue().bold());
+    check_device(&mut checks, port);
+
+    outln!("{}", "==> Project".blue().bold());
+    check_project(&mut checks, project);
+
+    output::report("checks", &checks.0);
+    let failed = checks.count(Status::Fail);
+    let warnings = checks.count(Status::Warn);
+    outln!();
+    if failed > 0 {
+        bail!("{} check(s) failed, {} warning(s)", failed, warnings);
+    }
+    if warnings > 0 {
+        outln!(
+            "{}",
+            format!("All checks passed, {} warning(s)", warnings).yellow()
+        );
+    } else {
+        outln!("{}", "All checks passed".green());
+    }
+    Ok(())
+}
+
+/// Check docker alone, as `affogato setup` does before pulling the image
+pub fn docker_ready() -> bool {
+    check_docker(&mut Checks::default())
+}
+
+/// Returns whether the daemon is usable, so the image can be checked
+fn check_docker(checks: &mut Checks) -> bool {
+    let Ok(path) = which::which("docker") else {
+        checks.fail(
+            "docker",
+            "docker not found on PATH",
+            "Install Docker: https://docs.docker.com/get-docker/",
+        );
+        return false;
+    };
+    checks.ok("docker", format!("docker found at {}", path.display()));
+
+    let output = match Command::new("docker")
+        .args(["version", "--format", "{{.Server.Version}}"])
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) => {
+            checks.fail(
+                "docker-daemon",
+                format!("Failed to run docker: {}", e),
+                "Reinstall Docker: https://docs.docker.com/get-docker/",
+            );
+            return false;
+        }
+    };
+    if output.status.success() {
+        let version = String::from_utf8_lossy(&output.stdout);
+        checks.ok(
+            "docker-daemon",
+            format!("Docker daemon reachable (version {})", version.trim()),
+        );
+        return true;
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr).to_lowercase();
+    if stderr.contains("permission denied") {
+        checks.fail(
+            "docker-daemon",
+            "No permission to use the Docker daemon",
+            "sudo usermod -aG docker $USER, then log out and back in",
+        );
+    } else {
+        checks.fail(
+            "docker-daemon",
+            "Cannot connect to the Docker daemon",
+            "Start Docker (sudo systemctl start docker, or open Docker Desktop)",
+        );
+    }
+    false
+}
+
+fn check_image(checks: &mut Checks, image: &str) {
+    let output = Command::new("docker")
+        .args(["image", "inspect", image, "--format", "{{.Created}}"])
+        .output();
+    let created = match output {
+        Ok(output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).trim().to_string()
+        }
+        _ => {
+            checks.warn(
+                "image",
+                format!("Image {} not pulled yet (it is pulled on first use)", image),
+                "affogato docker pull",
+            );
+            return;
+        }
+    };
+
+    let age = humantime::parse_rfc3339_weak(&created)
+        .ok()
+        .and_then(|built| SystemTime::now().duration_since(built).ok());
+    match age {
+        Some(age) if age > STALE_IMAGE_AGE => checks.warn(
+            "image",
+            format!("Image {} was built {} days ago", image, days(age)),
+            "affogato docker pull",
+        ),
+        Some(age) => checks.ok(
+            "image",
+            format!("Image {} present, built {} days ago", image, days(age)),
+        ),
+        None => checks.ok("image", format!("Image {} present", image)),
+    }
+}
+
+fn days(age: Duration) -> u64 {
+    age.as_secs() / (24 * 60 * 60)
+}
+
+fn check_device(checks: &mut Checks, port: &str) {
+    if !Path::new(port).exists() {
+        let found = config::available_ports();
+        match found.first() {
+            Some(other) => checks.warn(
+                "device",
+                format!("No device at {}, but found {}", port, found.join(", ")),
+                format!("Pass --port {} to flash, monitor and run", other),
+            ),
+            None => checks.warn(
+                "device",
+                format!("No device at {} (only needed to flash and monitor)", port),
+                "Connect the board over USB with a data cable; check dmesg for it",
+            ),
+        }
+        return;
+    }
+    checks.ok("device", format!("Device found at {}", port));
+    check_device_access(checks, port);
+}
+
+#[cfg(unix)]
+fn check_device_access(checks: &mut Checks, port: &str) {
+    use std::os::unix::fs::MetadataExt;
+
+    let Ok(meta) = fs::metadata(port) else {
+        return;
+    };
+    let groups = user_groups();
+    let mode = meta.mode();
+    let access = (mode & 0o006 == 0o006)
+        || (groups.contains(&meta.gid()) && mode & 0o060 == 0o060)
+        || (user_id() == Some(meta.uid()) && mode & 0o600 == 0o600);
+    if access {
+        checks.ok(
+            "device-access",
+            format!("{} is readable and writable", port),
+        );
+        return;
+    }
+    let group = group_name(meta.gid()).unwrap_or_else(|| meta.gid().to_string());
+    checks.fail(
+        "device-access",
+        format!("No read/write permission on {} (owned by group {})", port, group),
+        format!(
+            "affogato setup-udev, then reconnect the board (or sudo usermod -aG {} $USER and log in again)",
+            group
+        ),
+    );
+}
+
+#[cfg(not(unix))]
+fn check_device_access(_checks: &mut Checks, _port: &str) {}
+
+#[cfg(unix)]
+fn user_groups() -> Vec<u32> {
+    Command::new("id")
+        .arg("-G")
+        .output()
+        .map(|o| {
+            String::from_utf8_lossy(&o.stdout)
+                .split_whitespace()
+                .filter_map(|g| g.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(unix)]
+fn user_id() -> Option<u32> {
+    let output = Command::new("id").arg("-u").output().ok()?;
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+#[cfg(unix)]
+fn group_name(gid: u32) -> Option<String> {
+    fs::read_to_string("/etc/group")
+        .ok()?
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.get(2) == Some(&gid.to_string().as_str()))
+        .map(|fields| fields[0].to_string())
+}
+
+fn check_project(checks: &mut Checks, project: Result<Project>) {
+    let project = match project {
+        Ok(project) => project,
+        Err(e) => {
+            checks.fail(
+                "config",
+                format!("affogato.toml is invalid:\n{:#}", e),
+                "Correct affogato.toml at the location above",
+            );
+            return;
+        }
+    };
+    let Some(root) = &project.root else {
+        outln!("  {}", "Not in an Affogato project, skipped".dimmed());
+        return;
+    };
+    checks.ok("project", format!("Project root {}", root.display()));
+
+    let Some(config) = &project.config else {
+        checks.warn(
+            "config",
+            "No affogato.toml (legacy Makefile project)",
+            "affogato init, to build from affogato.toml",
+        );
+        return;
+    };
+    check_config_sections(checks, root);
+
+    if !root.join("fpga").is_dir() {
+        checks.fail(
+            "layout",
+            "fpga/ directory missing",
+            "Create fpga/rtl/ with the design's Verilog",
+        );
+        return;
+    }
+    if !root.join("firmware/CMakeLists.txt").exists() {
+        checks.warn(
+            "layout",
+            "firmware/CMakeLists.txt missing, so `affogato build` can't build firmware",
+            "Copy firmware/ from a project made with affogato new",
+        );
+    }
+
+    let pcf = config
+        .fpga
+        .pcf
+        .clone()
+        .unwrap_or_else(|| "fpga/project.pcf".to_string());
+    if root.join(&pcf).exists() {
+        checks.ok("pcf", format!("Pin constraints {}", pcf));
+    } else {
+        checks.fail(
+            "pcf",
+            format!("Pin constraints {} not found", pcf),
+            "Add the file, or point [fpga] pcf at it",
+        );
+    }
+
+    let sources = match synthesis_sources(root, &config.fpga) {
+        Ok(sources) => sources,
+        Err(e) => {
+            checks.fail(
+                "sources",
+                e.to_string(),
+                "Add the design's Verilog to fpga/rtl/",
+            );
+            return;
+        }
+    };
+    let top = &config.fpga.top;
+    let defined = sources.iter().any(|source| {
+        fs::read_to_string(root.join(source))
+            .is_ok_and(|text| verilog::defined_modules(&text).contains(top))
+    });
+    if defined || config.fpga.amaranth.is_some() {
+        checks.ok(
+            "sources",
+            format!("{} Verilog file(s), top module {}", sources.len(), top),
+        );
+    } else {
+        checks.fail(
+            "sources",
+            format!(
+                "Top module {} not defined in any of {} Verilog file(s)",
+                top,
+                sources.len()
+            ),
+            "Set [fpga] top to the design's top module",
+        );
+    }
+}
+
+/// Warn about sections affogato doesn't know, which are otherwise silently ignored
+fn check_config_sections(checks: &mut Checks, root: &Path) {
+    let Ok(text) = fs::read_to_string(root.join("affogato.toml")) else {
+        return;
+    };
+    let Ok(table) = text.parse::<toml::Table>() else {
+        return;
+    };
+    let known = serde_json::to_value(ProjectConfig::default()).unwrap_or_default();
+    let unknown: Vec<&String> = table
+        .keys()
+        .filter(|key| known.get(key.as_str()).is_none())
+        .collect();
+    if unknown.is_empty() {
+        checks.ok("config", "affogato.toml is valid");
+    } else {
+        checks.warn(
+            "config",
+            format!(
+                "affogato.toml has unknown section(s), which are ignored: {}",
+                unknown
+                    .iter()
+                    .map(|k| format!("[{}]", k))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            "Check the section names against the Configuration section of the README",
+        );
+    }
+}
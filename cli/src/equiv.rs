@@ -0,0 +1,130 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use std::fs;
+
+use affogato_core::amaranth;
+use affogato_core::build::synthesis_sources;
+use affogato_core::docker::Docker;
+use affogato_core::dry_run;
+use affogato_core::outln;
+use affogato_core::project::Project;
+
+/// Netlist and logs for the equivalence check, relative to the project root
+const EQUIV_DIR: &str = ".affogato/equiv";
+
+/// iCE40 hard IP kept as opaque cells on both sides of the comparison
+const HARD_IP: &[&str] = &[
+    "SB_HFOSC",
+    "SB_LFOSC",
+    "SB_PLL40_CORE",
+    "SB_PLL40_PAD",
+    "SB_IO",
+    "SB_GB_IO",
+    "SB_RAM40_4K",
+    "SB_SPRAM256KA",
+    "SB_MAC16",
+    "SB_RGBA_DRV",
+    "SB_LED_DRV_CUR",
+    "SB_I2C",
+    "SB_SPI",
+    "SB_WARMBOOT",
+];
+
+/// Check that the synth_ice40 netlist is equivalent to the source RTL
+pub fn run_equiv(docker: &Docker, project: &Project, top: Option<&str>, seq: u32) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+
+    let fpga_config = project
+        .config
+        .as_ref()
+        .map(|c| c.fpga.clone())
+        .unwrap_or_default();
+    let top = top.unwrap_or(&fpga_config.top);
+    let sources = synthesis_sources(project_root, &fpga_config)?.join(" ");
+    let elaborate = amaranth::elaborate_script(&fpga_config)?;
+    let cells = "+/ice40/cells_sim.v";
+
+    outln!("{}", "==> Synthesizing gate-level netlist".blue().bold());
+    dry_run::create_dir_all(project_root.join(EQUIV_DIR))?;
+    let synth = format!(
+        r#"set -e
+cd /workspace
+{elaborate}yosys -q -l {dir}/synth.log -p "read_verilog -lib -specify {cells}; read_verilog {sources}; synth_ice40 -top {top}; write_verilog -noattr {dir}/gate.v"
+"#,
+        dir = EQUIV_DIR,
+    );
+    docker.run_in_project(project, &["bash", "-c", &synth], &[], false)?;
+
+    // Latches are almost never intended on an FPGA and are a common cause of mismatch
+    let synth_log =
+        fs::read_to_string(project_root.join(EQUIV_DIR).join("synth.log")).unwrap_or_default();
+    let latches: Vec<&str> = synth_log
+        .lines()
+        .filter(|l| l.contains("Latch inferred"))
+        .collect();
+
+    outln!("{}", "==> Checking equivalence".blue().bold());
+    let hard_ip = HARD_IP.join(" ");
+    let equiv = format!(
+        r#"cd /workspace
+yosys -l {dir}/equiv.log -p "
+read_verilog -lib -specify {cells}; read_verilog {sources}; prep -top {top}; async2sync; design -stash gold;
+read_verilog -specify {cells}; blackbox {hard_ip}; read_verilog {dir}/gate.v; hierarchy -top {top}; flatten; prep -top {top}; async2sync; design -stash gate;
+read_verilog -lib -specify {cells};
+design -copy-from gold -as gold {top}; design -copy-from gate -as gate {top};
+equiv_make gold gate equiv; hierarchy -top equiv;
+equiv_simple -seq {seq}; equiv_induct -seq {seq}; equiv_status" 2>&1
+"#,
+        dir = EQUIV_DIR,
+    );
+    let output = docker.run_in_project_capture(project, &["bash", "-c", &equiv], false)?;
+
+    let Some(summary) = output
+        .lines()
+        .find(|l| l.contains("are proven and") && l.contains("unproven"))
+    else {
+        for line in output.lines() {
+            outln!("    {}", line);
+        }
+        bail!(
+            "Equivalence check did not complete (see {}/equiv.log)",
+            EQUIV_DIR
+        );
+    };
+
+    // equiv_status lists each unproven cell with its gold and gate signals
+    let unproven: Vec<&str> = output
+        .lines()
+        .skip_while(|l| !l.contains("Unproven $equiv cells:"))
+        .skip(1)
+        .take_while(|l| l.starts_with("    "))
+        .map(str::trim)
+        .collect();
+
+    outln!();
+    for latch in &latches {
+        outln!("  {} {}", "Latch:".yellow(), latch.trim());
+    }
+    outln!("  {}", summary.trim());
+
+    if !unproven.is_empty() {
+        outln!();
+        outln!("{}", "Unproven signals (gold vs gate):".bold());
+        for line in &unproven {
+            outln!("    {}", line.red());
+        }
+        bail!(
+            "Synthesized netlist is not equivalent to the RTL ({} unproven)",
+            unproven.len()
+        );
+    }
+
+    outln!(
+        "{}",
+        "Synthesized netlist is equivalent to the RTL.".green()
+    );
+    Ok(())
+}
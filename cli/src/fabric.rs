@@ -0,0 +1,278 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+
+use crate::coregen::check_name;
+use crate::regmap::{align_defines, spi_helpers, SPI_INCLUDES};
+use affogato_core::dry_run;
+use affogato_core::ip;
+use affogato_core::outln;
+use affogato_core::project::{BusConfig, Project};
+
+/// Address space of `spi_bridge` in 16-bit words
+const ADDRESS_SPACE: u32 = 0x8000;
+
+/// A peripheral with its base address resolved
+struct Slot {
+    name: String,
+    base: u32,
+    size: u32,
+}
+
+impl Slot {
+    /// Address bits passed through to the peripheral
+    fn addr_bits(&self) -> u32 {
+        self.size.trailing_zeros().max(1)
+    }
+}
+
+/// Generate the `[fpga.bus]` fabric: Verilog in fpga/rtl, base addresses in firmware/main
+pub fn generate(project: &Project) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    let bus = project
+        .config
+        .as_ref()
+        .and_then(|c| c.fpga.bus.clone())
+        .context("No [fpga.bus] section in affogato.toml")?;
+
+    check_name(&bus.name)?;
+    let slots = assign(&bus)?;
+
+    outln!(
+        "{}",
+        format!("==> Generating bus fabric {}", bus.name)
+            .blue()
+            .bold()
+    );
+    for slot in &slots {
+        outln!(
+            "  0x{:04X}-0x{:04X}  {}",
+            slot.base,
+            slot.base + slot.size - 1,
+            slot.name
+        );
+    }
+
+    let files = [
+        (
+            format!("fpga/rtl/{}.v", bus.name),
+            verilog(&bus.name, &slots),
+        ),
+        (
+            format!("firmware/main/{}.h", bus.name),
+            header(&bus.name, &slots),
+        ),
+    ];
+    for (path, content) in &files {
+        let full = project_root.join(path);
+        if let Some(parent) = full.parent() {
+            dry_run::create_dir_all(parent)?;
+        }
+        dry_run::write(&full, content)?;
+        outln!("  {} {}", "Generated".green(), path);
+    }
+    ip::ensure(project_root, "spi_bridge")?;
+
+    Ok(())
+}
+
+/// Check the peripherals and place those without a base address
+fn assign(bus: &BusConfig) -> Result<Vec<Slot>> {
+    if bus.peripherals.is_empty() {
+        bail!("[fpga.bus] has no [[fpga.bus.peripheral]] entries");
+    }
+
+    let mut slots: Vec<Slot> = Vec::new();
+    let mut next: u32 = 0;
+    for peripheral in &bus.peripherals {
+        check_name(&peripheral.name)?;
+        if slots.iter().any(|s| s.name == peripheral.name) {
+            bail!("Duplicate bus peripheral: {}", peripheral.name);
+        }
+        let size = peripheral.size;
+        if !size.is_power_of_two() || size > ADDRESS_SPACE / 2 {
+            bail!(
+                "{}: size must be a power of two no larger than 0x{:X} words",
+                peripheral.name,
+                ADDRESS_SPACE / 2
+            );
+        }
+
+        let base = match peripheral.base {
+            Some(base) => {
+                if base % size != 0 {
+                    bail!(
+                        "{}: base 0x{:X} is not aligned to its size 0x{:X}",
+                        peripheral.name,
+                        base,
+                        size
+                    );
+                }
+                base
+            }
+            None => {
+                // First aligned address at or after `next` that doesn't overlap
+                let mut base = next.div_ceil(size) * size;
+                while let Some(other) = slots
+                    .iter()
+                    .find(|s| base < s.base + s.size && s.base < base + size)
+                {
+                    base = (other.base + other.size).div_ceil(size) * size;
+                }
+                base
+            }
+        };
+        if base + size > ADDRESS_SPACE {
+            bail!(
+                "{}: 0x{:X}+0x{:X} is outside the 15-bit address space",
+                peripheral.name,
+                base,
+                size
+            );
+        }
+        if let Some(other) = slots
+            .iter()
+            .find(|s| base < s.base + s.size && s.base < base + size)
+        {
+            bail!("{} overlaps {}", peripheral.name, other.name);
+        }
+
+        next = base + size;
+        slots.push(Slot {
+            name: peripheral.name.clone(),
+            base,
+            size,
+        });
+    }
+    Ok(slots)
+}
+
+fn verilog(name: &str, slots: &[Slot]) -> String {
+    let mut table = String::new();
+    let mut ports = String::new();
+    let mut decode = String::new();
+    let mut reads = String::new();
+
+    for (i, slot) in slots.iter().enumerate() {
+        let p = &slot.name;
+        let bits = slot.addr_bits();
+        let select_bits = 15 - slot.size.trailing_zeros();
+        table.push_str(&format!(
+            " *   0x{:04X}-0x{:04X}  {}\n",
+            slot.base,
+            slot.base + slot.size - 1,
+            p
+        ));
+
+        ports.push_str(&format!(
+            "
+    // {p}
+    output wire [{}:0] o_{p}_addr,
+    output wire [15:0] o_{p}_wdata,
+    output wire o_{p}_we,
+    input wire [15:0] i_{p}_rdata{}
+",
+            bits - 1,
+            if i + 1 == slots.len() { "" } else { "," }
+        ));
+
+        decode.push_str(&format!(
+            "    wire {p}_sel = (addr[14:{}] == {}'h{:X});\n",
+            15 - select_bits,
+            select_bits,
+            slot.base >> (15 - select_bits)
+        ));
+        decode.push_str(&format!("    assign o_{p}_addr = addr[{}:0];\n", bits - 1));
+        decode.push_str(&format!("    assign o_{p}_wdata = wdata;\n"));
+        decode.push_str(&format!("    assign o_{p}_we = we && {p}_sel;\n\n"));
+
+        let keyword = if i == 0 { "if" } else { "else if" };
+        reads.push_str(&format!(
+            "        {keyword} ({p}_sel) rdata = i_{p}_rdata;\n"
+        ));
+    }
+
+    let example = &slots[0].name;
+    format!(
+        r#"/**
+ * {name} - register bus fabric
+ *
+ * Generated by `affogato gen bus` from [fpga.bus] in affogato.toml; edit the
+ * config, not this file. Base addresses for the firmware are in {name}.h.
+ *
+ * Decodes the address of spi_bridge transactions and routes them to the
+ * register interface of each peripheral (16-bit words):
+{table} *
+ * Unmapped addresses read as zero. Connect each peripheral's register
+ * interface to its ports, for example:
+ *
+ *   .i_addr({example}_addr), .i_wdata({example}_wdata), .i_we({example}_we), .o_rdata({example}_rdata)
+ */
+module {name} (
+    input wire i_clk,
+    input wire i_rst,
+
+    // SPI interface (asynchronous to i_clk)
+    input wire i_cs_n,
+    input wire i_sck,
+    input wire i_mosi,
+    output wire o_miso,
+{ports});
+
+    wire [14:0] addr;
+    wire [15:0] wdata;
+    wire we;
+    reg [15:0] rdata;
+
+    spi_bridge bridge (
+        .i_clk(i_clk),
+        .i_rst(i_rst),
+        .i_cs_n(i_cs_n),
+        .i_sck(i_sck),
+        .i_mosi(i_mosi),
+        .o_miso(o_miso),
+        .o_addr(addr),
+        .o_wdata(wdata),
+        .o_we(we),
+        .i_rdata(rdata)
+    );
+
+{decode}    always @(*) begin
+{reads}        else rdata = 16'd0;
+    end
+
+endmodule
+"#
+    )
+}
+
+fn header(name: &str, slots: &[Slot]) -> String {
+    let upper = name.to_uppercase();
+    let mut defines = vec!["/* Peripheral base addresses (16-bit words) */".to_string()];
+    for slot in slots {
+        defines.push(format!(
+            "#define {}_{}_BASE 0x{:04X}",
+            upper,
+            slot.name.to_uppercase(),
+            slot.base
+        ));
+    }
+
+    format!(
+        "/**
+ * {name} address map - generated by `affogato gen bus` from affogato.toml
+ *
+ * Add a peripheral's register offsets to its base, e.g.
+ * {name}_write(device, {upper}_{first}_BASE + offset, value).
+ */
+#pragma once
+{SPI_INCLUDES}
+{}
+{}",
+        align_defines(defines).join("\n"),
+        spi_helpers(name),
+        first = slots[0].name.to_uppercase(),
+    )
+}
@@ -0,0 +1,144 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use serde_json::json;
+
+use affogato_core::build::collect_verilog_files;
+use affogato_core::docker::Docker;
+use affogato_core::outln;
+use affogato_core::output;
+use affogato_core::project::Project;
+use affogato_core::test::find_test_dir;
+
+const FORMATTER: &str = "verible-verilog-format";
+
+/// Printed by the script when the formatter is missing from the image
+const MISSING_TOOL_MARKER: &str = "AFFOGATO: formatter not found";
+
+/// Printed by the check script before each file the formatter would change
+const UNFORMATTED_MARKER: &str = "AFFOGATO-UNFORMATTED ";
+
+/// Printed by the check script before each file the formatter couldn't parse
+const FAILED_MARKER: &str = "AFFOGATO-FAILED ";
+
+/// Format the design and testbench Verilog with verible-verilog-format, or
+/// with `check`, fail listing the files it would change
+pub fn run(docker: &Docker, project: &Project, check: bool) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    let exclude = project
+        .config
+        .as_ref()
+        .map(|c| c.lint.clone())
+        .unwrap_or_default();
+
+    let mut files = Vec::new();
+    let mut dirs = vec!["fpga/rtl".to_string()];
+    dirs.extend(find_test_dir(project_root, "fpga"));
+    for dir in dirs {
+        let dir = project_root.join(dir);
+        if dir.is_dir() {
+            collect_verilog_files(&dir, project_root, &mut files)?;
+        }
+    }
+    files.retain(|f| !exclude.is_excluded(f));
+    if files.is_empty() {
+        bail!("No Verilog files found in fpga/rtl/");
+    }
+    files.sort();
+
+    outln!(
+        "{}",
+        format!(
+            "==> {} {} Verilog file(s)",
+            if check {
+                "Checking formatting of"
+            } else {
+                "Formatting"
+            },
+            files.len()
+        )
+        .blue()
+        .bold()
+    );
+    let run = if check {
+        format!(
+            r#"for f in {files}; do
+  if ! {binary} "$f" > /tmp/formatted.v; then echo "{failed}$f"; continue; fi
+  cmp -s /tmp/formatted.v "$f" || echo "{unformatted}$f"
+done"#,
+            files = files.join(" "),
+            binary = FORMATTER,
+            failed = FAILED_MARKER,
+            unformatted = UNFORMATTED_MARKER,
+        )
+    } else {
+        format!(
+            r#"for f in {files}; do
+  {binary} --inplace "$f" || echo "{failed}$f"
+done"#,
+            files = files.join(" "),
+            binary = FORMATTER,
+            failed = FAILED_MARKER,
+        )
+    };
+    let script = format!(
+        r#"cd /workspace
+command -v {binary} >/dev/null || {{ echo "{marker}"; exit 127; }}
+{run}
+"#,
+        binary = FORMATTER,
+        marker = MISSING_TOOL_MARKER,
+    );
+    let output = docker.run_in_project_capture(project, &["bash", "-c", &script], false)?;
+    if output.contains(MISSING_TOOL_MARKER) {
+        bail!(
+            "{} is not installed in the container image; run `affogato docker pull`",
+            FORMATTER
+        );
+    }
+
+    let marked = |marker: &str| -> Vec<String> {
+        output
+            .lines()
+            .filter_map(|line| line.strip_prefix(marker))
+            .map(str::to_string)
+            .collect()
+    };
+    let unformatted = marked(UNFORMATTED_MARKER);
+    let failed = marked(FAILED_MARKER);
+    output::report(
+        "fmt",
+        json!({
+            "files": files.len(),
+            "unformatted": unformatted,
+            "failed": failed,
+        }),
+    );
+
+    for line in output.lines().filter(|line| !line.starts_with("AFFOGATO-")) {
+        outln!("    {}", line);
+    }
+    for file in &failed {
+        outln!("  {} {}", "could not parse".red(), file);
+    }
+    if !check {
+        if !failed.is_empty() {
+            bail!("{} file(s) could not be formatted", failed.len());
+        }
+        outln!("{}", "Formatted".green());
+        return Ok(());
+    }
+    for file in &unformatted {
+        outln!("  {} {}", "needs formatting".yellow(), file);
+    }
+    if !failed.is_empty() || !unformatted.is_empty() {
+        bail!(
+            "{} file(s) need formatting; run `affogato fmt`",
+            unformatted.len() + failed.len()
+        );
+    }
+    outln!("{}", "All files formatted".green());
+    Ok(())
+}
@@ -0,0 +1,291 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use affogato_core::build::collect_verilog_files;
+use affogato_core::docker::Docker;
+use affogato_core::dry_run;
+use affogato_core::project::{FormalTask, Project};
+use affogato_core::{out, outln};
+
+/// Where SymbiYosys work directories and generated .sby files live
+const FORMAL_DIR: &str = ".affogato/formal";
+
+/// A formal verification job: an existing .sby file or one generated from config
+struct FormalJob {
+    name: String,
+    /// .sby file relative to the project root
+    sby: String,
+}
+
+/// Outcome of one SymbiYosys run
+struct FormalResult {
+    name: String,
+    status: String,
+    failed_asserts: Vec<String>,
+    traces: Vec<PathBuf>,
+}
+
+/// Run SymbiYosys on the project's .sby files and `[formal.<name>]` tasks
+pub fn run_formal(
+    docker: &Docker,
+    project: &Project,
+    module: Option<&str>,
+    fpga_dir: &str,
+    verbose: bool,
+) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+
+    let mut jobs = discover_sby_files(project_root, fpga_dir)?;
+    if let Some(config) = &project.config {
+        for (name, task) in &config.formal {
+            if jobs.iter().any(|j| &j.name == name) {
+                continue;
+            }
+            let sby = generate_sby(project_root, fpga_dir, name, task)?;
+            jobs.push(FormalJob {
+                name: name.clone(),
+                sby,
+            });
+        }
+    }
+
+    if let Some(module) = module {
+        jobs.retain(|j| j.name == module);
+        if jobs.is_empty() {
+            bail!(
+                "No formal task named '{}' (expected {}/formal/{}.sby or [formal.{}] in affogato.toml)",
+                module,
+                fpga_dir,
+                module,
+                module
+            );
+        }
+    }
+
+    if jobs.is_empty() {
+        outln!(
+            "{}",
+            format!(
+                "No formal tasks found. Add .sby files under {}/formal/ or [formal.<module>] sections to affogato.toml.",
+                fpga_dir
+            )
+            .yellow()
+        );
+        return Ok(());
+    }
+
+    outln!(
+        "{}",
+        format!("==> Running {} formal task(s)", jobs.len())
+            .blue()
+            .bold()
+    );
+
+    let mut results = Vec::new();
+    for job in &jobs {
+        out!("  Proving {:40} ", job.name);
+        let result = run_job(docker, project, project_root, job, verbose)?;
+        let status = match result.status.as_str() {
+            "PASS" => "PASS".green(),
+            other => other.red(),
+        };
+        outln!("{}", status);
+        results.push(result);
+    }
+
+    outln!();
+    outln!("{}", "Formal Results:".bold());
+    let mut all_passed = true;
+    for result in &results {
+        if result.status == "PASS" {
+            outln!("  {:40} {}", result.name, "PROVED".green());
+            continue;
+        }
+        all_passed = false;
+        outln!("  {:40} {}", result.name, result.status.red());
+        for assert in &result.failed_asserts {
+            outln!("    {}", assert.red());
+        }
+        for trace in &result.traces {
+            let relative = trace.strip_prefix(project_root).unwrap_or(trace);
+            outln!("    Counterexample: {}", relative.display());
+        }
+    }
+
+    if !all_passed {
+        bail!("Formal verification failed");
+    }
+
+    Ok(())
+}
+
+/// Find .sby files under the FPGA directory
+fn discover_sby_files(project_root: &Path, fpga_dir: &str) -> Result<Vec<FormalJob>> {
+    let mut jobs = Vec::new();
+    let mut stack = vec![project_root.join(fpga_dir)];
+
+    while let Some(dir) = stack.pop() {
+        if !dir.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                stack.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "sby") {
+                jobs.push(FormalJob {
+                    name: path.file_stem().unwrap().to_string_lossy().to_string(),
+                    sby: path.strip_prefix(project_root)?.display().to_string(),
+                });
+            }
+        }
+    }
+
+    jobs.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(jobs)
+}
+
+/// Write a .sby file for a `[formal.<name>]` task, returning its relative path
+fn generate_sby(
+    project_root: &Path,
+    fpga_dir: &str,
+    name: &str,
+    task: &FormalTask,
+) -> Result<String> {
+    let mut files = Vec::new();
+    if task.sources.is_empty() {
+        collect_verilog_files(
+            &project_root.join(format!("{}/rtl", fpga_dir)),
+            project_root,
+            &mut files,
+        )?;
+    } else {
+        for source in &task.sources {
+            let path = project_root.join(source);
+            if path.is_dir() {
+                collect_verilog_files(&path, project_root, &mut files)?;
+            } else {
+                files.push(source.clone());
+            }
+        }
+    }
+    if files.is_empty() {
+        bail!("No sources for formal task {}", name);
+    }
+
+    let defines: String = task.defines.iter().map(|d| format!(" -D{}", d)).collect();
+    let basenames: Vec<String> = files
+        .iter()
+        .map(|f| {
+            Path::new(f)
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .to_string()
+        })
+        .collect();
+
+    let sby = format!(
+        r#"# Generated by affogato from [formal.{name}] - edits will be overwritten
+[options]
+mode {mode}
+depth {depth}
+
+[engines]
+{engine}
+
+[script]
+read -formal -DFORMAL{defines} {basenames}
+prep -top {top}
+
+[files]
+{files}
+"#,
+        mode = task.mode,
+        depth = task.depth,
+        engine = task.engine,
+        basenames = basenames.join(" "),
+        top = task.top.as_deref().unwrap_or(name),
+        files = files
+            .iter()
+            .map(|f| format!("/workspace/{}", f))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    );
+
+    let dir = project_root.join(FORMAL_DIR);
+    dry_run::create_dir_all(&dir)?;
+    dry_run::write(dir.join(format!("{}.sby", name)), sby)?;
+
+    Ok(format!("{}/{}.sby", FORMAL_DIR, name))
+}
+
+fn run_job(
+    docker: &Docker,
+    project: &Project,
+    project_root: &Path,
+    job: &FormalJob,
+    verbose: bool,
+) -> Result<FormalResult> {
+    let workdir = format!("{}/{}", FORMAL_DIR, job.name);
+    let host_workdir = project_root.join(&workdir);
+    if host_workdir.exists() {
+        dry_run::remove_dir_all(&host_workdir)?;
+    }
+
+    let script = format!(
+        "cd /workspace && sby -f -d {workdir} {sby} 2>&1",
+        workdir = workdir,
+        sby = job.sby
+    );
+    let output = docker.run_in_project_capture(project, &["bash", "-c", &script], false)?;
+
+    let status = fs::read_to_string(host_workdir.join("status"))
+        .ok()
+        .and_then(|s| s.split_whitespace().next().map(str::to_string))
+        .unwrap_or_else(|| "ERROR".to_string());
+
+    if verbose || status == "ERROR" {
+        outln!();
+        outln!("{}", "--- Output ---".dimmed());
+        for line in output.lines() {
+            outln!("    {}", line);
+        }
+        outln!("{}", "--------------".dimmed());
+    }
+
+    let failed_asserts = output
+        .lines()
+        .filter(|l| l.contains("Assert failed in") || l.contains("Unreached cover statement"))
+        .map(|l| l.trim().to_string())
+        .collect();
+
+    let mut traces = Vec::new();
+    if status != "PASS" && host_workdir.exists() {
+        for entry in fs::read_dir(&host_workdir)? {
+            let engine_dir = entry?.path();
+            if !engine_dir.is_dir() {
+                continue;
+            }
+            for file in fs::read_dir(&engine_dir)? {
+                let path = file?.path();
+                if path.extension().is_some_and(|ext| ext == "vcd") {
+                    traces.push(path);
+                }
+            }
+        }
+        traces.sort();
+    }
+
+    Ok(FormalResult {
+        name: job.name.clone(),
+        status,
+        failed_asserts,
+        traces,
+    })
+}
@@ -0,0 +1,204 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use std::fs;
+use std::path::Path;
+
+use affogato_core::build::collect_verilog_files;
+use affogato_core::dry_run;
+use affogato_core::outln;
+use affogato_core::project::Project;
+use affogato_core::test::find_test_dir;
+use affogato_core::verilog::{self, Port};
+
+/// Generate a testbench skeleton for an RTL module
+pub fn generate_testbench(
+    project: &Project,
+    module: &str,
+    fpga_dir: &str,
+    force: bool,
+) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+
+    let (file, ports) = find_module(project_root, fpga_dir, module)?;
+
+    let test_dir =
+        find_test_dir(project_root, fpga_dir).unwrap_or_else(|| format!("{}/rtl_test", fpga_dir));
+    let tb_path = project_root
+        .join(&test_dir)
+        .join(format!("{}_tb.v", module));
+
+    if tb_path.exists() && !force {
+        bail!(
+            "{}/{}_tb.v already exists (use --force to overwrite)",
+            test_dir,
+            module
+        );
+    }
+
+    dry_run::create_dir_all(project_root.join(&test_dir))?;
+    dry_run::write(&tb_path, testbench_source(module, &ports))?;
+
+    outln!(
+        "{}",
+        format!(
+            "Generated {}/{}_tb.v for {} ({} ports, from {})",
+            test_dir,
+            module,
+            module,
+            ports.len(),
+            file
+        )
+        .green()
+    );
+    outln!("Run it with: affogato test {}", module);
+
+    Ok(())
+}
+
+/// Find the RTL file defining `module` and parse its ports
+fn find_module(project_root: &Path, fpga_dir: &str, module: &str) -> Result<(String, Vec<Port>)> {
+    let mut files = Vec::new();
+    collect_verilog_files(
+        &project_root.join(format!("{}/rtl", fpga_dir)),
+        project_root,
+        &mut files,
+    )?;
+
+    for file in files {
+        let source = fs::read_to_string(project_root.join(&file))?;
+        if !verilog::defined_modules(&source)
+            .iter()
+            .any(|m| m == module)
+        {
+            continue;
+        }
+        let ports = verilog::module_ports(&source, module)
+            .with_context(|| format!("Could not parse the port list of {} in {}", module, file))?;
+        return Ok((file, ports));
+    }
+
+    bail!("Module not found in {}/rtl: {}", fpga_dir, module);
+}
+
+/// Port name without a conventional `i_`/`o_` direction prefix
+fn base_name(port: &Port) -> String {
+    let name = port.name.to_lowercase();
+    ["i_", "o_", "io_"]
+        .iter()
+        .find_map(|p| name.strip_prefix(p))
+        .map(str::to_string)
+        .unwrap_or(name)
+}
+
+fn is_clock(port: &Port) -> bool {
+    let name = base_name(port);
+    port.direction == "input"
+        && port.range.is_none()
+        && (name == "clk"
+            || name.contains("clock")
+            || name.ends_with("_clk")
+            || name.starts_with("clk_"))
+}
+
+fn is_reset(port: &Port) -> bool {
+    let name = base_name(port);
+    port.direction == "input"
+        && port.range.is_none()
+        && (name.starts_with("rst") || name.starts_with("reset"))
+}
+
+fn is_active_low(port: &Port) -> bool {
+    let name = base_name(port);
+    name.ends_with("_n") || name.ends_with("_b") || name == "rstn" || name == "resetn"
+}
+
+fn testbench_source(module: &str, ports: &[Port]) -> String {
+    let clock = ports.iter().find(|p| is_clock(p));
+    let reset = ports.iter().find(|p| is_reset(p));
+
+    let mut decls = String::new();
+    for port in ports {
+        let range = port
+            .range
+            .as_deref()
+            .map(|r| format!("{} ", r))
+            .unwrap_or_default();
+        let line = match port.direction.as_str() {
+            "input" => {
+                let init = match reset {
+                    Some(r) if r.name == port.name && is_active_low(r) => "1'b0",
+                    Some(r) if r.name == port.name => "1'b1",
+                    _ => "0",
+                };
+                format!("    reg {}{} = {};\n", range, port.name, init)
+            }
+            _ => format!("    wire {}{};\n", range, port.name),
+        };
+        decls.push_str(&line);
+    }
+
+    let connections = ports
+        .iter()
+        .map(|p| format!("        .{}({})", p.name, p.name))
+        .collect::<Vec<_>>()
+        .join(",\n");
+
+    let clock_gen = match clock {
+        Some(clk) => format!(
+            "    // 48 MHz clock (matches the UP5K HFOSC)\n    always #10.417 {clk} = ~{clk};\n",
+            clk = clk.name
+        ),
+        None => "    // No clock input detected\n".to_string(),
+    };
+
+    let wait = |cycles: u32| match clock {
+        Some(clk) => format!("repeat ({}) @(posedge {});", cycles, clk.name),
+        None => format!("#{};", cycles * 20),
+    };
+
+    let reset_seq = match reset {
+        Some(rst) => {
+            let release = if is_active_low(rst) { "1'b1" } else { "1'b0" };
+            format!(
+                "        // Hold reset for a few cycles\n        {}\n        {} = {};\n\n",
+                wait(4),
+                rst.name,
+                release
+            )
+        }
+        None => String::new(),
+    };
+
+    format!(
+        r#"`timescale 1ns / 1ps
+`include "affogato_tb.vh"
+// Testbench for {module}, generated by `affogato gen tb {module}`
+
+module {module}_tb;
+    // Assertion bookkeeping from the bundled testbench library
+    `TB_DECLARE
+
+    // DUT ports
+{decls}
+{clock_gen}
+    {module} dut (
+{connections}
+    );
+
+    initial begin
+        $dumpfile("{module}_tb.vcd");
+        $dumpvars(0, {module}_tb);
+
+{reset_seq}        // TODO: drive stimulus and add `ASSERT(...) checks
+        {run}
+
+        `TB_FINISH
+    end
+endmodule
+"#,
+        run = wait(100),
+    )
+}
@@ -0,0 +1,91 @@
+use anyhow::{Context, Result};
+use colored::Colorize;
+use std::fs;
+use std::path::PathBuf;
+
+use affogato_core::dry_run;
+use affogato_core::outln;
+use affogato_core::project::{HooksConfig, Project};
+
+use crate::git::git;
+
+/// First comment line of every hook affogato writes, marking it as ours to
+/// overwrite or remove
+const MARKER: &str = "# Generated by `affogato hooks install`";
+
+/// Write git hooks running the `[hooks]` commands, replacing hooks affogato
+/// wrote before; other hooks are kept unless `force` is given
+pub fn install(project: &Project, force: bool) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    let config = project
+        .config
+        .as_ref()
+        .map(|c| c.hooks.clone())
+        .unwrap_or_default();
+    let top_level = PathBuf::from(git(project_root, &["rev-parse", "--show-toplevel"])?);
+    let hooks_dir = project_root.join(git(project_root, &["rev-parse", "--git-path", "hooks"])?);
+    // Hooks run from the top of the work tree; the project may be below it
+    let subdir = project_root
+        .canonicalize()?
+        .strip_prefix(top_level.canonicalize()?)
+        .map(|dir| dir.to_string_lossy().replace('\\', "/"))
+        .unwrap_or_default();
+
+    outln!("{}", "==> Installing git hooks".blue().bold());
+    let HooksConfig {
+        pre_commit,
+        pre_push,
+    } = config;
+    for (hook, commands) in [("pre-commit", pre_commit), ("pre-push", pre_push)] {
+        let path = hooks_dir.join(hook);
+        let ours = fs::read_to_string(&path).map_or(true, |hook| hook.contains(MARKER));
+        if !ours && !force {
+            outln!(
+                "  {} {} (not written by affogato; use --force to replace it)",
+                "kept".yellow(),
+                hook
+            );
+            continue;
+        }
+        if commands.is_empty() {
+            if path.exists() {
+                dry_run::remove_file(&path)?;
+                outln!("  {} {}", "removed".dimmed(), hook);
+            }
+            continue;
+        }
+        dry_run::create_dir_all(&hooks_dir)?;
+        dry_run::write(&path, script(&subdir, &commands))
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        #[cfg(unix)]
+        if !dry_run::is_enabled() {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+        }
+        outln!("  {} {}: {}", "wrote".green(), hook, commands.join(", "));
+    }
+    outln!("Skip them for one commit or push with --no-verify");
+    Ok(())
+}
+
+fn script(subdir: &str, commands: &[String]) -> String {
+    let mut script = format!(
+        "#!/bin/sh\n{} from [hooks] in affogato.toml;\n\
+         # rerun it after editing that section. Skip with --no-verify.\nset -e\n",
+        MARKER
+    );
+    if !subdir.is_empty() {
+        script.push_str(&format!(
+            "cd \"$(git rev-parse --show-toplevel)/{}\"\n",
+            subdir
+        ));
+    }
+    for command in commands {
+        script.push_str(command);
+        script.push('\n');
+    }
+    script
+}
@@ -0,0 +1,145 @@
+use anyhow::Result;
+use colored::Colorize;
+use serde_json::json;
+
+use affogato_core::debug_console::HWTEST_PREFIX;
+use affogato_core::docker::Docker;
+use affogato_core::error::Failure;
+use affogato_core::project::Project;
+use affogato_core::{device, dry_run, outln, output};
+
+use crate::{reg, setup};
+
+/// How long the board may take over one check; the LED check alone takes ~2s
+const CHECK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(6);
+
+/// One check's outcome as the firmware reports it
+struct Check {
+    name: String,
+    result: String,
+    detail: String,
+}
+
+/// Run the firmware's self-test and print a pass/fail report per check
+pub fn run(docker: &Docker, project: &Project, port: &str) -> Result<()> {
+    let loopbacks = project
+        .config
+        .as_ref()
+        .map(|config| config.hwtest.gpio_loopbacks.clone())
+        .unwrap_or_default();
+    reg::warn_if_console_off(project);
+    docker.ensure_image()?;
+
+    outln!(
+        "{}",
+        format!("==> Testing the board on {}", port).blue().bold()
+    );
+    outln!("Watch the RGB LED: it should light red, green, then blue");
+    let command = std::iter::once("hwtest".to_string())
+        .chain(
+            loopbacks
+                .iter()
+                .map(|[out, input]| format!("{}:{}", out, input)),
+        )
+        .collect::<Vec<_>>()
+        .join(" ");
+    let lines = device::query_lines(
+        docker,
+        project,
+        port,
+        &command,
+        HWTEST_PREFIX,
+        &format!("{} done", HWTEST_PREFIX),
+        CHECK_TIMEOUT,
+    )?;
+    if dry_run::is_enabled() {
+        return Ok(());
+    }
+
+    let mut checks: Vec<Check> = lines.iter().filter_map(|line| parse(line)).collect();
+    for check in checks.iter_mut().filter(|check| check.result == "manual") {
+        if !setup::interactive() {
+            check.result = "skip".to_string();
+            check.detail = "needs an operator; run interactively to check it by eye".to_string();
+            continue;
+        }
+        let answer = setup::prompt("Did the RGB LED light red, green, then blue? [y/N]")?;
+        if answer.to_lowercase().starts_with('y') {
+            check.result = "pass".to_string();
+            check.detail = "red, green and blue confirmed by the operator".to_string();
+        } else {
+            check.result = "fail".to_string();
+            check.detail = "the operator did not see red, green and blue".to_string();
+        }
+    }
+
+    outln!();
+    for check in &checks {
+        let result = match check.result.as_str() {
+            "pass" => "PASS".green().bold(),
+            "fail" => "FAIL".red().bold(),
+            _ => "SKIP".yellow().bold(),
+        };
+        outln!("  {}  {:14} {}", result, label(&check.name), check.detail);
+    }
+    let count = |result: &str| checks.iter().filter(|check| check.result == result).count();
+    let (passed, failed) = (count("pass"), count("fail"));
+    let skipped = checks.len() - passed - failed;
+    outln!();
+    outln!("{} passed, {} failed, {} skipped", passed, failed, skipped);
+    output::report(
+        "hwtest",
+        json!({
+            "checks": checks
+                .iter()
+                .map(|check| json!({
+                    "check": check.name,
+                    "result": check.result,
+                    "detail": check.detail,
+                }))
+                .collect::<Vec<_>>(),
+            "passed": failed == 0,
+        }),
+    );
+    if failed > 0 {
+        return Err(Failure::TestsFailed.error(format!(
+            "{} of {} board checks failed",
+            failed,
+            checks.len()
+        )));
+    }
+    outln!("{}", "Board passed".green().bold());
+    Ok(())
+}
+
+/// A `check=<name> result=<result> detail=<text>` line; the detail runs to
+/// the end of the line
+fn parse(line: &str) -> Option<Check> {
+    let (fields, detail) = line.split_once(" detail=")?;
+    let field = |key: &str| {
+        fields
+            .split_whitespace()
+            .find_map(|field| field.strip_prefix(key)?.strip_prefix('='))
+            .map(str::to_string)
+    };
+    Some(Check {
+        name: field("check")?,
+        result: field("result")?,
+        detail: detail.to_string(),
+    })
+}
+
+fn label(check: &str) -> String {
+    match check {
+        "cdone" => "CDONE/CRESET".to_string(),
+        "spi" => "SPI".to_string(),
+        "rgb" => "RGB LED".to_string(),
+        _ => match check
+            .strip_prefix("gpio:")
+            .and_then(|pair| pair.split_once(':'))
+        {
+            Some((out, input)) => format!("GPIO {} -> {}", out, input),
+            None => check.to_string(),
+        },
+    }
+}
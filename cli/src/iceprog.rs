@@ -0,0 +1,102 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use std::process::Command;
+
+use affogato_core::docker::Docker;
+use affogato_core::error::Failure;
+use affogato_core::project::{FpgaBoot, Project};
+use affogato_core::{dry_run, outln};
+
+/// The bitstream `affogato fpga` builds, relative to the project root
+const BITSTREAM: &str = "fpga/top.bin";
+
+/// What iceprog prints when no FTDI programmer is attached
+const NOT_FOUND: &str = "Can't find iCE FTDI USB device";
+
+/// Load the bitstream into the iCE40 through an FTDI programmer with iceprog,
+/// without going through the ESP32: into its configuration RAM, or its
+/// configuration flash with `[fpga] boot = "flash"`. Uses iceprog from PATH
+/// when installed, else the one in the image with USB passed through.
+pub fn program(docker: &Docker, project: &Project, ftdi: Option<&str>) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    if !dry_run::is_enabled() && !project_root.join(BITSTREAM).exists() {
+        bail!(
+            "No bitstream at {}; the FPGA build did not produce one",
+            BITSTREAM
+        );
+    }
+
+    let boot = project
+        .config
+        .as_ref()
+        .map(|config| config.fpga.boot)
+        .unwrap_or_default();
+    // -S writes the FPGA's configuration RAM; without it iceprog writes the
+    // flash and resets the FPGA to boot from it
+    let mut args = Vec::new();
+    if boot == FpgaBoot::Cram {
+        args.push("-S".to_string());
+    }
+    if let Some(ftdi) = ftdi {
+        args.extend(["-d".to_string(), ftdi.to_string()]);
+    }
+    args.push(BITSTREAM.to_string());
+
+    let (success, output) = match which::which("iceprog") {
+        Ok(iceprog) => {
+            outln!(
+                "{}",
+                format!("==> Programming the FPGA with {}", iceprog.display())
+                    .blue()
+                    .bold()
+            );
+            let mut command = Command::new(&iceprog);
+            command.args(&args).current_dir(project_root);
+            if dry_run::is_enabled() {
+                dry_run::note(&format!("{:?}", command));
+                return Ok(());
+            }
+            let result = command.output().context("Failed to run iceprog")?;
+            // iceprog reports progress on stderr
+            let output = String::from_utf8_lossy(&result.stderr).to_string();
+            outln!("{}", output.trim_end());
+            (result.status.success(), output)
+        }
+        Err(_) => {
+            outln!(
+                "{}",
+                "==> Programming the FPGA with iceprog in the container"
+                    .blue()
+                    .bold()
+            );
+            docker.ensure_image()?;
+            let cmd: Vec<&str> = std::iter::once("iceprog")
+                .chain(args.iter().map(String::as_str))
+                .collect();
+            docker.run_in_project_tee(project, &cmd, true)?
+        }
+    };
+    if dry_run::is_enabled() {
+        return Ok(());
+    }
+    if !success {
+        if output.contains(NOT_FOUND) {
+            return Err(Failure::DeviceNotFound.error(
+                "No FTDI programmer found (vendor 0403, product 6010 or 6014); check it is plugged in and, on Linux, run affogato setup-udev",
+            ));
+        }
+        bail!("iceprog failed to program the FPGA");
+    }
+    outln!(
+        "{}",
+        match boot {
+            FpgaBoot::Cram => "FPGA configured over the FTDI programmer",
+            FpgaBoot::Flash => "Configuration flash written over the FTDI programmer",
+        }
+        .green()
+    );
+    Ok(())
+}
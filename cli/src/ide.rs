@@ -0,0 +1,179 @@
+use anyhow::{Context, Result};
+use colored::Colorize;
+use serde_json::{json, Value};
+use std::path::Path;
+
+use affogato_core::docker::DEFAULT_IMAGE;
+use affogato_core::dry_run;
+use affogato_core::outln;
+use affogato_core::project::Project;
+use affogato_core::test::find_test_dir;
+
+use crate::ci;
+
+/// Editors `affogato ide init` writes configuration for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Editor {
+    /// VS Code: a devcontainer, tasks and recommended extensions
+    #[default]
+    Vscode,
+}
+
+const DEVCONTAINER: &str = ".devcontainer/devcontainer.json";
+const TASKS: &str = ".vscode/tasks.json";
+const EXTENSIONS: &str = ".vscode/extensions.json";
+
+/// Extensions for the project's Verilog, the ESP-IDF C sources and the
+/// devcontainer
+const RECOMMENDED_EXTENSIONS: &[&str] = &[
+    "mshr-h.veriloghdl",
+    "ms-vscode.cpptools",
+    "ms-vscode-remote.remote-containers",
+];
+
+/// Write editor configuration for the project, keeping existing files
+/// unless `force` is given
+pub fn init(project: &Project, image: &str, editor: Editor, force: bool) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    let files = match editor {
+        Editor::Vscode => vec![
+            (DEVCONTAINER, devcontainer(project, image)),
+            (TASKS, tasks(project_root)),
+            (
+                EXTENSIONS,
+                json!({ "recommendations": RECOMMENDED_EXTENSIONS }),
+            ),
+        ],
+    };
+
+    for (file, content) in files {
+        let path = project_root.join(file);
+        if path.exists() && !force {
+            outln!(
+                "{}",
+                format!("Kept existing {} (use --force to overwrite)", file).yellow()
+            );
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            dry_run::create_dir_all(parent)?;
+        }
+        let content = serde_json::to_string_pretty(&content)? + "\n";
+        dry_run::write(&path, content).with_context(|| format!("Failed to write {}", file))?;
+        outln!("{}", format!("Generated {}", file).green());
+    }
+    outln!(
+        "Run tasks with Ctrl+Shift+B (build) or Tasks: Run Test Task; errors appear in Problems"
+    );
+    Ok(())
+}
+
+/// A devcontainer on the toolchain image, with the host's docker for the
+/// containers affogato starts. The checkout is mounted at its host path so
+/// those containers' bind mounts of it resolve on the host.
+fn devcontainer(project: &Project, image: &str) -> Value {
+    let name = project
+        .name
+        .clone()
+        .or_else(|| project.config.as_ref()?.project.name.clone())
+        .unwrap_or_else(|| "affogato".to_string());
+    let mut devcontainer = json!({
+        "name": name,
+        "image": image,
+        "workspaceMount": "source=${localWorkspaceFolder},target=${localWorkspaceFolder},type=bind",
+        "workspaceFolder": "${localWorkspaceFolder}",
+        "features": {
+            "ghcr.io/devcontainers/features/docker-outside-of-docker:1": {}
+        },
+        "postCreateCommand": format!(
+            "curl -fsSL {} | tar xz -C /usr/local/bin",
+            ci::install_url()
+        ),
+        "customizations": {
+            "vscode": { "extensions": RECOMMENDED_EXTENSIONS }
+        },
+    });
+    if image != DEFAULT_IMAGE {
+        devcontainer["containerEnv"] = json!({ "AFFOGATO_IMAGE": image });
+    }
+    devcontainer
+}
+
+/// Tasks running the CLI with `--format gha`, whose `::error` annotations the
+/// problem matchers turn into entries in the Problems panel
+fn tasks(project_root: &Path) -> Value {
+    let has_firmware = project_root.join("firmware").is_dir();
+    let mut tasks = vec![task(
+        if has_firmware { "Build" } else { "Build FPGA" },
+        if has_firmware {
+            "affogato build --format gha"
+        } else {
+            "affogato fpga --format gha"
+        },
+        Some("build"),
+    )];
+    if has_firmware {
+        tasks.push(task("Build FPGA", "affogato fpga --format gha", None));
+    }
+    if find_test_dir(project_root, "fpga").is_some() {
+        tasks.push(task("Test", "affogato test --format gha", Some("test")));
+    }
+    tasks.push(task("Lint", "affogato lint --format gha", None));
+    if has_firmware {
+        tasks.push(task("Flash", "affogato flash", None));
+        tasks.push(json!({
+            "label": "Flash and monitor",
+            "type": "shell",
+            "command": "affogato run",
+            "problemMatcher": [],
+        }));
+    }
+    json!({ "version": "2.0.0", "tasks": tasks })
+}
+
+fn task(label: &str, command: &str, group: Option<&str>) -> Value {
+    let mut task = json!({
+        "label": label,
+        "type": "shell",
+        "command": command,
+        "problemMatcher": problem_matchers(),
+    });
+    if let Some(group) = group {
+        task["group"] = json!({ "kind": group, "isDefault": true });
+    }
+    task
+}
+
+/// Matchers for `::error file=...,line=...::message` annotations, and for
+/// those naming only a file, such as a failing testbench
+fn problem_matchers() -> Value {
+    json!([
+        {
+            "owner": "affogato",
+            "fileLocation": ["relative", "${workspaceFolder}"],
+            "pattern": {
+                "regexp": "^::(error|warning) file=([^,]+),line=(\\d+)(?:,col=(\\d+))?(?:,title=[^:]*)?::(.*)$",
+                "severity": 1,
+                "file": 2,
+                "line": 3,
+                "column": 4,
+                "message": 5,
+            },
+        },
+        {
+            "owner": "affogato",
+            "fileLocation": ["relative", "${workspaceFolder}"],
+            "pattern": {
+                "regexp": "^::(error|warning) file=([^,:]+)(?:,title=([^:]*))?::(.*)$",
+                "kind": "file",
+                "severity": 1,
+                "file": 2,
+                "code": 3,
+                "message": 4,
+            },
+        },
+    ])
+}
@@ -0,0 +1,291 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::SystemTime;
+
+use affogato_core::error::Failure;
+use affogato_core::project::{LaConfig, Project};
+use affogato_core::{dry_run, outln, output};
+
+/// The SPI decoder's channels and the board signals wired to them
+const SPI_SIGNALS: &[(&str, &str)] = &[
+    ("clk", "FSPI_CLK"),
+    ("mosi", "FSPI_MOSI"),
+    ("miso", "FSPI_MISO"),
+    ("cs", "FSPI_CS"),
+];
+
+/// Decoded bytes shown in the terminal; the rest go to the decode file
+const SHOWN_BYTES: usize = 16;
+
+/// Capture board signals with a sigrok logic analyzer, decoding SPI traffic
+/// when the `spi` group is captured
+pub fn capture(
+    project: &Project,
+    channels: &[String],
+    duration: &str,
+    trigger: Option<&str>,
+    output: Option<PathBuf>,
+    open: bool,
+) -> Result<()> {
+    let config = project
+        .config
+        .as_ref()
+        .map(|config| config.la.clone())
+        .unwrap_or_default();
+    let (signals, spi) = select(&config, channels)?;
+    let time = humantime::parse_duration(duration)
+        .with_context(|| format!("Invalid capture duration: {}", duration))?;
+    let sigrok = which::which("sigrok-cli").context(
+        "sigrok-cli not found on PATH. Install it (e.g. apt install sigrok-cli) to use a logic analyzer",
+    )?;
+
+    let capture = match output {
+        Some(path) => path,
+        None => {
+            let stamp = humantime::format_rfc3339_seconds(SystemTime::now())
+                .to_string()
+                .replace(':', "-");
+            let dir = project.state_dir()?.join("captures");
+            dry_run::create_dir_all(&dir)?;
+            dir.join(format!("capture-{}.sr", stamp.trim_end_matches('Z')))
+        }
+    };
+
+    outln!(
+        "{}",
+        format!(
+            "==> Capturing {} for {}",
+            signals
+                .iter()
+                .map(|(signal, _)| signal.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            humantime::format_duration(time)
+        )
+        .blue()
+        .bold()
+    );
+    let mut command = Command::new(&sigrok);
+    if let Some(driver) = &config.driver {
+        command.args(["--driver", driver]);
+    }
+    command
+        .args(["--config", &format!("samplerate={}", config.samplerate)])
+        .args([
+            "--channels",
+            &signals
+                .iter()
+                .map(|(signal, channel)| format!("{}={}", channel, signal))
+                .collect::<Vec<_>>()
+                .join(","),
+        ])
+        .args(["--time", &time.as_millis().to_string()]);
+    if let Some(trigger) = trigger {
+        command.args(["--triggers", trigger]);
+    }
+    command.arg("--output-file").arg(&capture);
+    if dry_run::is_enabled() {
+        dry_run::note(&format!("{:?}", command));
+        return Ok(());
+    }
+    let result = command
+        .stdin(Stdio::null())
+        .output()
+        .context("Failed to run sigrok-cli")?;
+    if !result.status.success() {
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        let error = anyhow::anyhow!(
+            "sigrok-cli failed to capture: {}",
+            stderr.trim().lines().last().unwrap_or("no output")
+        );
+        if stderr.contains("No devices found") {
+            return Err(error.context(Failure::DeviceNotFound).context(
+                "No logic analyzer found; set its sigrok driver under [la] in affogato.toml",
+            ));
+        }
+        return Err(error);
+    }
+    outln!("Saved {}", capture.display().to_string().bold());
+
+    let mut report = json!({ "capture": capture, "signals": signals });
+    if spi {
+        let (decode, bytes) = decode_spi(&sigrok, &capture)?;
+        report["spi_bytes"] = json!(bytes);
+        report["decode"] = json!(decode);
+    }
+    output::report("la_capture", report);
+
+    if open {
+        open_pulseview(&capture);
+    }
+    Ok(())
+}
+
+/// The (signal, analyzer channel) pairs to capture, and whether to decode SPI.
+/// `channels` holds signal names from `[la.probes]`, or `spi` for the SPI bus.
+fn select(config: &LaConfig, channels: &[String]) -> Result<(Vec<(String, String)>, bool)> {
+    let channel = |signal: &str| {
+        config
+            .probes
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(signal))
+            .map(|(name, channel)| (name.clone(), channel.clone()))
+    };
+    let mut signals: Vec<(String, String)> = Vec::new();
+    let mut spi = false;
+    for name in channels {
+        let selected = if name.eq_ignore_ascii_case("spi") {
+            spi = true;
+            SPI_SIGNALS
+                .iter()
+                .map(|(_, signal)| {
+                    channel(signal).with_context(|| {
+                        format!("No analyzer channel for {} under [la.probes]", signal)
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            vec![channel(name).with_context(|| {
+                format!(
+                    "Unknown signal: {} (spi, or one of {} from [la.probes])",
+                    name,
+                    config.probes.keys().cloned().collect::<Vec<_>>().join(", ")
+                )
+            })?]
+        };
+        for pair in selected {
+            if !signals.contains(&pair) {
+                signals.push(pair);
+            }
+        }
+    }
+    if signals.is_empty() {
+        bail!("No channels to capture; pass --channels spi or signal names");
+    }
+    Ok((signals, spi))
+}
+
+/// Run sigrok's SPI decoder over a capture, print the first bytes each way and
+/// write them all next to the capture. Returns the decode file and byte count.
+fn decode_spi(sigrok: &Path, capture: &Path) -> Result<(PathBuf, usize)> {
+    let decoder = std::iter::once("spi".to_string())
+        .chain(
+            SPI_SIGNALS
+                .iter()
+                .map(|(pin, signal)| format!("{}={}", pin, signal)),
+        )
+        .collect::<Vec<_>>()
+        .join(":");
+    let annotations = |class: &str| -> Result<Vec<String>> {
+        let result = Command::new(sigrok)
+            .arg("--input-file")
+            .arg(capture)
+            .args(["--protocol-decoders", &decoder])
+            .args(["--protocol-decoder-annotations", &format!("spi={}", class)])
+            .stdin(Stdio::null())
+            .output()
+            .context("Failed to run sigrok-cli")?;
+        if !result.status.success() {
+            bail!(
+                "sigrok-cli failed to decode {}: {}",
+                capture.display(),
+                String::from_utf8_lossy(&result.stderr).trim()
+            );
+        }
+        // One "spi-1: 9F" line per byte
+        Ok(String::from_utf8_lossy(&result.stdout)
+            .lines()
+            .filter_map(|line| line.rsplit(": ").next())
+            .map(|byte| byte.trim().to_string())
+            .filter(|byte| !byte.is_empty())
+            .collect())
+    };
+    let mosi = annotations("mosi-data")?;
+    let miso = annotations("miso-data")?;
+    let bytes = mosi.len().max(miso.len());
+
+    let rows: Vec<String> = (0..bytes)
+        .map(|i| {
+            format!(
+                "{:>6}  {:>4}  {:>4}",
+                i,
+                mosi.get(i).map_or("", String::as_str),
+                miso.get(i).map_or("", String::as_str)
+            )
+        })
+        .collect();
+    let decode = capture.with_extension("spi.txt");
+    dry_run::write(
+        &decode,
+        format!(
+            "{:>6}  {:>4}  {:>4}\n{}\n",
+            "Byte",
+            "MOSI",
+            "MISO",
+            rows.join("\n")
+        ),
+    )?;
+
+    outln!();
+    if bytes == 0 {
+        outln!(
+            "{}",
+            "No SPI traffic decoded; check the probes and the capture length".yellow()
+        );
+        return Ok((decode, 0));
+    }
+    outln!(
+        "{}",
+        format!("{:>6}  {:>4}  {:>4}", "Byte", "MOSI", "MISO").bold()
+    );
+    for row in rows.iter().take(SHOWN_BYTES) {
+        outln!("{}", row);
+    }
+    if bytes > SHOWN_BYTES {
+        outln!(
+            "{}",
+            format!("   ... {} more", bytes - SHOWN_BYTES).dimmed()
+        );
+    }
+    outln!(
+        "Decoded {} SPI bytes into {}",
+        bytes,
+        decode.display().to_string().bold()
+    );
+    Ok((decode, bytes))
+}
+
+/// Open a capture in PulseView, detached like the waveform viewers
+fn open_pulseview(capture: &Path) {
+    if which::which("pulseview").is_err() {
+        outln!(
+            "{}",
+            format!(
+                "PulseView not found on PATH; open {} in it manually",
+                capture.display()
+            )
+            .yellow()
+        );
+        return;
+    }
+    outln!(
+        "{}",
+        format!("Opening {} in PulseView", capture.display()).dimmed()
+    );
+    let launched = Command::new("pulseview")
+        .arg("--input-file")
+        .arg(capture)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    if let Err(err) = launched {
+        outln!(
+            "{}",
+            format!("Failed to launch PulseView: {}", err).yellow()
+        );
+    }
+}
@@ -0,0 +1,725 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use affogato_core::annotate::{self, Level};
+use affogato_core::build::collect_verilog_files;
+use affogato_core::docker::Docker;
+use affogato_core::dry_run;
+use affogato_core::outln;
+use affogato_core::output;
+use affogato_core::project::{LintConfig, LintTool, Project, RuleSetting, Severity};
+
+/// Printed by the lint script when the selected tool is not installed
+const MISSING_TOOL_MARKER: &str = "AFFOGATO: lint tool not found";
+
+/// Hand-written waivers, committed at the project root
+const WAIVERS_FILE: &str = "lint-waivers.toml";
+
+/// Findings frozen by `affogato lint --update-baseline`
+const BASELINE_FILE: &str = "lint-baseline.toml";
+
+/// How findings are reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum LintFormat {
+    /// Human-readable, colored output
+    #[default]
+    Text,
+    /// SARIF 2.1.0 JSON on stdout, for code scanning and editors
+    Sarif,
+    /// GitHub Actions workflow commands that annotate the PR diff
+    Gha,
+}
+
+pub struct LintOptions {
+    pub fpga_dir: String,
+    pub tool: Option<LintTool>,
+    pub verbose: bool,
+    /// Freeze the current findings into the baseline file
+    pub update_baseline: bool,
+    pub format: LintFormat,
+    /// Only lint Verilog changed relative to this git revision
+    pub diff: Option<String>,
+    /// Fail on warnings as well as errors
+    pub strict: bool,
+    /// Report findings but always exit successfully
+    pub no_fail: bool,
+}
+
+/// A single diagnostic reported by a lint tool
+#[derive(Serialize)]
+pub struct Finding {
+    /// Path relative to the project root (empty if the tool gave none)
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+    pub severity: Severity,
+    /// Verilator warning code or verible rule name
+    pub rule: String,
+    pub message: String,
+}
+
+#[derive(Deserialize, Default)]
+struct Waivers {
+    #[serde(default)]
+    waiver: Vec<Waiver>,
+}
+
+/// A finding suppressed on purpose, e.g. `{ file = "fpga/rtl/legacy/*.v", rule = "WIDTH" }`
+#[derive(Deserialize)]
+struct Waiver {
+    /// Glob matched against the finding's path
+    file: String,
+    #[serde(default)]
+    rule: Option<String>,
+    #[serde(default)]
+    line: Option<u32>,
+    #[allow(dead_code)]
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+impl Waiver {
+    fn matches(&self, finding: &Finding) -> bool {
+        glob::Pattern::new(&self.file).is_ok_and(|p| p.matches(&finding.file))
+            && self.rule.as_ref().is_none_or(|r| *r == finding.rule)
+            && self.line.is_none_or(|l| l == finding.line)
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Baseline {
+    #[serde(default)]
+    finding: Vec<BaselineEntry>,
+}
+
+/// A frozen finding; line numbers are left out so edits elsewhere in a file don't unfreeze it
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct BaselineEntry {
+    file: String,
+    rule: String,
+    message: String,
+}
+
+impl From<&Finding> for BaselineEntry {
+    fn from(finding: &Finding) -> Self {
+        BaselineEntry {
+            file: finding.file.clone(),
+            rule: finding.rule.clone(),
+            message: finding.message.clone(),
+        }
+    }
+}
+
+/// Lint the FPGA sources with the configured tool and print the findings
+pub fn run_lint(docker: &Docker, project: &Project, opts: &LintOptions) -> Result<()> {
+    let fpga_dir = opts.fpga_dir.as_str();
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+
+    let config = project
+        .config
+        .as_ref()
+        .map(|c| c.lint.clone())
+        .unwrap_or_default();
+    let tool = opts.tool.unwrap_or(config.tool);
+
+    let mut files = Vec::new();
+    let rtl_dir = project_root.join(fpga_dir).join("rtl");
+    if rtl_dir.exists() {
+        collect_verilog_files(&rtl_dir, project_root, &mut files)?;
+    }
+    files.retain(|f| !config.is_excluded(f));
+    if files.is_empty() {
+        bail!("No Verilog files found in {}/rtl/", fpga_dir);
+    }
+    files.sort();
+
+    let mut extra_args = String::new();
+    if let Some(base) = &opts.diff {
+        files = files_to_lint(project_root, &files, &changed_files(project_root, base)?)?;
+        if files.is_empty() {
+            if opts.format != LintFormat::Sarif {
+                outln!("{}", format!("No Verilog changes since {}", base).green());
+            }
+            return Ok(());
+        }
+        // Unchanged modules are still needed to elaborate the changed ones
+        if tool == LintTool::Verilator {
+            extra_args = format!("-y {dir}/rtl -I{dir}/rtl", dir = fpga_dir);
+        }
+    }
+
+    // Keep stdout machine-readable for SARIF
+    if opts.format != LintFormat::Sarif {
+        outln!(
+            "{}",
+            format!("==> Linting Verilog with {}", tool_binary(tool))
+                .blue()
+                .bold()
+        );
+    }
+
+    let script = format!(
+        r#"cd /workspace
+command -v {binary} >/dev/null || {{ echo "{marker}"; exit 127; }}
+{binary} {args} {extra_args} {files} 2>&1
+"#,
+        binary = tool_binary(tool),
+        marker = MISSING_TOOL_MARKER,
+        args = tool_args(tool, &config),
+        extra_args = extra_args,
+        files = files.join(" "),
+    );
+    let output = docker.run_in_project_capture(project, &["bash", "-c", &script], false)?;
+
+    if output.contains(MISSING_TOOL_MARKER) {
+        bail!(
+            "{} is not installed in the container image",
+            tool_binary(tool)
+        );
+    }
+
+    if opts.verbose && opts.format != LintFormat::Sarif {
+        outln!("{}", "--- Output ---".dimmed());
+        for line in output.lines() {
+            outln!("    {}", line);
+        }
+        outln!("{}", "--------------".dimmed());
+    }
+
+    let mut findings = parse_output(tool, &output, &config);
+    if opts.diff.is_some() {
+        // Modules pulled in through -y are reported too; keep only the linted files
+        findings.retain(|f| f.file.is_empty() || files.contains(&f.file));
+    }
+
+    let waivers_path = project_root.join(WAIVERS_FILE);
+    let waivers: Waivers = if waivers_path.exists() {
+        let content = fs::read_to_string(&waivers_path)?;
+        toml::from_str(&content).with_context(|| format!("Failed to parse {}", WAIVERS_FILE))?
+    } else {
+        Waivers::default()
+    };
+    let (waived, findings): (Vec<Finding>, Vec<Finding>) = findings
+        .into_iter()
+        .partition(|f| waivers.waiver.iter().any(|w| w.matches(f)));
+
+    let baseline_path = project_root.join(BASELINE_FILE);
+    if opts.update_baseline {
+        let mut baseline = Baseline {
+            finding: findings.iter().map(BaselineEntry::from).collect(),
+        };
+        baseline.finding.sort();
+        dry_run::write(
+            &baseline_path,
+            format!(
+                "# Generated by `affogato lint --update-baseline`; new findings fail lint\n\n{}",
+                toml::to_string(&baseline)?
+            ),
+        )?;
+        outln!(
+            "{}",
+            format!(
+                "Baseline updated: {} finding(s) frozen in {}",
+                baseline.finding.len(),
+                BASELINE_FILE
+            )
+            .green()
+        );
+        return Ok(());
+    }
+
+    let baseline: Option<Baseline> = if baseline_path.exists() {
+        let content = fs::read_to_string(&baseline_path)?;
+        Some(
+            toml::from_str(&content)
+                .with_context(|| format!("Failed to parse {}", BASELINE_FILE))?,
+        )
+    } else {
+        None
+    };
+
+    // Each baseline entry absorbs one matching finding, so duplicates still count
+    let mut frozen = 0;
+    let findings = match &baseline {
+        Some(baseline) => {
+            let mut remaining = baseline.finding.clone();
+            findings
+                .into_iter()
+                .filter(|f| {
+                    let entry = BaselineEntry::from(f);
+                    match remaining.iter().position(|e| *e == entry) {
+                        Some(idx) => {
+                            remaining.swap_remove(idx);
+                            frozen += 1;
+                            false
+                        }
+                        None => true,
+                    }
+                })
+                .collect()
+        }
+        None => findings,
+    };
+
+    output::report("findings", &findings);
+    match opts.format {
+        LintFormat::Sarif => outln!(
+            "{}",
+            serde_json::to_string_pretty(&sarif(tool, project_root, &findings))?
+        ),
+        LintFormat::Gha => {
+            for finding in &findings {
+                let level = match finding.severity {
+                    Severity::Error => Level::Error,
+                    _ => Level::Warning,
+                };
+                outln!(
+                    "{}",
+                    annotate::workflow_command(
+                        level,
+                        project_root,
+                        Some(&finding.file),
+                        finding.line,
+                        finding.column,
+                        Some(&finding.rule),
+                        &finding.message,
+                    )
+                );
+            }
+            print_summary(&findings);
+        }
+        LintFormat::Text => print_findings(&findings),
+    }
+    if opts.format != LintFormat::Sarif && (!waived.is_empty() || frozen > 0) {
+        outln!(
+            "{}",
+            format!("({} waived, {} in baseline)", waived.len(), frozen).dimmed()
+        );
+    }
+
+    if opts.no_fail {
+        return Ok(());
+    }
+
+    let errors = findings
+        .iter()
+        .filter(|f| f.severity == Severity::Error)
+        .count();
+    let warnings = findings.len() - errors;
+
+    if baseline.is_some() && !findings.is_empty() {
+        bail!(
+            "{} new lint finding(s) not in {}",
+            findings.len(),
+            BASELINE_FILE
+        );
+    }
+    if errors > 0 {
+        bail!("Lint failed with {} error(s)", errors);
+    }
+    if opts.strict && warnings > 0 {
+        bail!("Lint failed with {} warning(s) (--strict)", warnings);
+    }
+
+    Ok(())
+}
+
+/// Files changed since `base` (committed, staged, unstaged or untracked),
+/// relative to the project root
+fn changed_files(project_root: &Path, base: &str) -> Result<Vec<String>> {
+    let git = |args: &[&str]| -> Result<Vec<String>> {
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(project_root)
+            .output()
+            .context("Failed to run git")?;
+        if !output.status.success() {
+            bail!(
+                "git {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::to_string)
+            .collect())
+    };
+
+    let mut changed = git(&[
+        "diff",
+        "--name-only",
+        "--relative",
+        "--diff-filter=ACMR",
+        base,
+    ])?;
+    changed.extend(git(&["ls-files", "--others", "--exclude-standard"])?);
+    Ok(changed)
+}
+
+/// Changed source files, plus every source that includes a changed header
+fn files_to_lint(project_root: &Path, files: &[String], changed: &[String]) -> Result<Vec<String>> {
+    let headers: Vec<&str> = changed
+        .iter()
+        .filter(|f| f.ends_with(".vh") || f.ends_with(".svh"))
+        .filter_map(|f| Path::new(f).file_name()?.to_str())
+        .collect();
+
+    let mut selected = Vec::new();
+    for file in files {
+        if changed.contains(file) {
+            selected.push(file.clone());
+            continue;
+        }
+        if headers.is_empty() {
+            continue;
+        }
+        let source = fs::read_to_string(project_root.join(file))?;
+        let includes_changed = source.lines().any(|line| {
+            line.trim_start()
+                .strip_prefix("`include")
+                .and_then(|rest| rest.trim().strip_prefix('"'))
+                .and_then(|rest| rest.split('"').next())
+                .and_then(|inc| Path::new(inc).file_name()?.to_str())
+                .is_some_and(|inc| headers.contains(&inc))
+        });
+        if includes_changed {
+            selected.push(file.clone());
+        }
+    }
+    Ok(selected)
+}
+
+fn tool_binary(tool: LintTool) -> &'static str {
+    match tool {
+        LintTool::Verilator => "verilator",
+        LintTool::Verible => "verible-verilog-lint",
+    }
+}
+
+/// Tool arguments enabling, disabling and configuring rules from `[lint.rules]`
+fn tool_args(tool: LintTool, config: &LintConfig) -> String {
+    match tool {
+        LintTool::Verilator => {
+            let mut args = vec!["--lint-only".to_string(), "-Wall".to_string()];
+            for (rule, setting) in rules_for(tool, config) {
+                if setting.severity() == Severity::Off {
+                    args.push(format!("-Wno-{}", rule));
+                }
+            }
+            args.extend(config.include.iter().map(|dir| format!("-I{}", dir)));
+            args.extend(config.flags.iter().cloned());
+            args.join(" ")
+        }
+        LintTool::Verible => {
+            let rules: Vec<String> = rules_for(tool, config)
+                .map(
+                    |(rule, setting)| match (setting.severity(), setting.config()) {
+                        (Severity::Off, _) => format!("-{}", rule),
+                        (_, Some(params)) => format!("+{}={}", rule, params),
+                        (_, None) => format!("+{}", rule),
+                    },
+                )
+                .collect();
+            let mut args = config.flags.clone();
+            if !rules.is_empty() {
+                args.insert(0, format!("--rules={}", rules.join(",")));
+            }
+            args.join(" ")
+        }
+    }
+}
+
+/// Rules configured for a tool: verilator codes are upper case (WIDTH), verible
+/// rule names are lower case (line-length)
+fn rules_for(tool: LintTool, config: &LintConfig) -> impl Iterator<Item = (&String, &RuleSetting)> {
+    config.rules.iter().filter(move |(rule, _)| {
+        let verilator = rule
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+        verilator == (tool == LintTool::Verilator)
+    })
+}
+
+/// Parse tool output into findings, applying configured rule severities
+pub fn parse_output(tool: LintTool, output: &str, config: &LintConfig) -> Vec<Finding> {
+    let parse = match tool {
+        LintTool::Verilator => parse_verilator_line,
+        LintTool::Verible => parse_verible_line,
+    };
+
+    output
+        .lines()
+        .filter_map(parse)
+        .filter_map(|mut finding| {
+            if let Some(setting) = config.rules.get(&finding.rule) {
+                finding.severity = setting.severity();
+            }
+            if config.is_disabled(&finding.file, &finding.rule) {
+                finding.severity = Severity::Off;
+            }
+            (finding.severity != Severity::Off).then_some(finding)
+        })
+        .collect()
+}
+
+/// `%Warning-UNUSEDSIGNAL: fpga/rtl/top.v:12:10: Signal is not used: 'x'`
+fn parse_verilator_line(line: &str) -> Option<Finding> {
+    let (header, rest) = line.split_once(": ")?;
+    let (severity, rule) = match header.split_once('-') {
+        Some((level, code)) => (level, code),
+        None => (header, ""),
+    };
+    let severity = match severity {
+        "%Warning" => Severity::Warning,
+        "%Error" => Severity::Error,
+        _ => return None,
+    };
+    // The final "Exiting due to N error(s)" line is not a finding
+    if rest.starts_with("Exiting due to") {
+        return None;
+    }
+
+    let (file, line, column, message) = split_location(rest).unwrap_or((String::new(), 0, 0, rest));
+    Some(Finding {
+        file,
+        line,
+        column,
+        severity,
+        rule: rule.to_string(),
+        message: message.to_string(),
+    })
+}
+
+/// `fpga/rtl/top.v:12:5: Line length exceeds max: 100; is: 120 [Style: line-length] [line-length]`
+fn parse_verible_line(line: &str) -> Option<Finding> {
+    let (file, line_number, column, message) = split_location(line)?;
+
+    // Style findings end with the rule name; anything else is a syntax error
+    let (message, rule, severity) = match message.rfind(" [") {
+        Some(idx) if message.ends_with(']') => {
+            let rule = &message[idx + 2..message.len() - 1];
+            let message = message[..idx].trim_end();
+            let message = match message.rfind(" [Style:") {
+                Some(style) => message[..style].trim_end(),
+                None => message,
+            };
+            (message, rule, Severity::Warning)
+        }
+        _ => (message, "syntax", Severity::Error),
+    };
+
+    Some(Finding {
+        file,
+        line: line_number,
+        column,
+        severity,
+        rule: rule.to_string(),
+        message: message.to_string(),
+    })
+}
+
+/// Split "file:line:col: message", where col may be a range like "5-12"
+fn split_location(text: &str) -> Option<(String, u32, u32, &str)> {
+    let mut parts = text.splitn(4, ':');
+    let file = parts.next()?;
+    let line = parts.next()?.trim().parse().ok()?;
+    let column = parts.next()?.trim();
+    let column = column
+        .split('-')
+        .next()
+        .and_then(|c| c.parse().ok())
+        .unwrap_or(0);
+    let message = parts.next()?.trim();
+    // Tools run from /workspace; report paths relative to the project root
+    let file = file.trim();
+    let file = file.strip_prefix("/workspace/").unwrap_or(file);
+    Some((file.to_string(), line, column, message))
+}
+
+/// SARIF 2.1.0 log with paths relative to the host project root
+fn sarif(tool: LintTool, project_root: &Path, findings: &[Finding]) -> serde_json::Value {
+    let rules: BTreeSet<&str> = findings
+        .iter()
+        .map(|f| f.rule.as_str())
+        .filter(|r| !r.is_empty())
+        .collect();
+
+    let results: Vec<serde_json::Value> = findings
+        .iter()
+        .map(|finding| {
+            let mut result = json!({
+                "level": match finding.severity {
+                    Severity::Error => "error",
+                    _ => "warning",
+                },
+                "message": { "text": finding.message },
+            });
+            if !finding.rule.is_empty() {
+                result["ruleId"] = json!(finding.rule);
+            }
+            if !finding.file.is_empty() {
+                let mut region = json!({ "startLine": finding.line.max(1) });
+                if finding.column > 0 {
+                    region["startColumn"] = json!(finding.column);
+                }
+                result["locations"] = json!([{
+                    "physicalLocation": {
+                        "artifactLocation": {
+                            "uri": finding.file,
+                            "uriBaseId": "%SRCROOT%",
+                        },
+                        "region": region,
+                    }
+                }]);
+            }
+            result
+        })
+        .collect();
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": tool_binary(tool),
+                    "rules": rules.iter().map(|r| json!({ "id": r })).collect::<Vec<_>>(),
+                }
+            },
+            "originalUriBaseIds": {
+                "%SRCROOT%": { "uri": format!("file://{}/", project_root.display()) }
+            },
+            "results": results,
+        }]
+    })
+}
+
+fn print_findings(findings: &[Finding]) {
+    for finding in findings {
+        let severity = match finding.severity {
+            Severity::Error => "error".red().bold(),
+            _ => "warning".yellow().bold(),
+        };
+        let location = if finding.file.is_empty() {
+            String::new()
+        } else {
+            format!("{}:{}:{}: ", finding.file, finding.line, finding.column)
+        };
+        let rule = if finding.rule.is_empty() {
+            String::new()
+        } else {
+            format!(" [{}]", finding.rule)
+        };
+        outln!("  {}{}{}: {}", location, severity, rule, finding.message);
+    }
+
+    print_summary(findings);
+}
+
+fn print_summary(findings: &[Finding]) {
+    let errors = findings
+        .iter()
+        .filter(|f| f.severity == Severity::Error)
+        .count();
+    let warnings = findings.len() - errors;
+
+    outln!();
+    let summary = format!("Lint: {} error(s), {} warning(s)", errors, warnings);
+    if errors > 0 {
+        outln!("{}", summary.red().bold());
+    } else if warnings > 0 {
+        outln!("{}", summary.yellow().bold());
+    } else {
+        outln!("{}", summary.green().bold());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finding(file: &str, line: u32, column: u32, severity: Severity, rule: &str) -> Finding {
+        Finding {
+            file: file.to_string(),
+            line,
+            column,
+            severity,
+            rule: rule.to_string(),
+            message: format!("{} message", rule),
+        }
+    }
+
+    #[test]
+    fn sarif_lists_rules_and_results() {
+        let findings = [
+            finding("fpga/rtl/top.v", 12, 5, Severity::Error, "WIDTH"),
+            finding("fpga/rtl/top.v", 30, 0, Severity::Warning, "UNUSED"),
+            finding("fpga/rtl/pwm.v", 3, 1, Severity::Warning, "WIDTH"),
+        ];
+        let log = sarif(LintTool::Verilator, Path::new("/home/me/proj"), &findings);
+
+        assert_eq!(log["version"], "2.1.0");
+        let run = &log["runs"][0];
+        assert_eq!(run["tool"]["driver"]["name"], "verilator");
+        assert_eq!(
+            run["tool"]["driver"]["rules"],
+            json!([{ "id": "UNUSED" }, { "id": "WIDTH" }])
+        );
+        assert_eq!(
+            run["originalUriBaseIds"]["%SRCROOT%"]["uri"],
+            "file:///home/me/proj/"
+        );
+
+        let results = run["results"].as_array().unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0]["level"], "error");
+        assert_eq!(results[0]["ruleId"], "WIDTH");
+        assert_eq!(results[0]["message"]["text"], "WIDTH message");
+        let location = &results[0]["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "fpga/rtl/top.v");
+        assert_eq!(location["artifactLocation"]["uriBaseId"], "%SRCROOT%");
+        assert_eq!(
+            location["region"],
+            json!({ "startLine": 12, "startColumn": 5 })
+        );
+
+        // No column: the region has only the line
+        assert_eq!(results[1]["level"], "warning");
+        assert_eq!(
+            results[1]["locations"][0]["physicalLocation"]["region"],
+            json!({ "startLine": 30 })
+        );
+    }
+
+    #[test]
+    fn sarif_omits_what_the_tool_did_not_give() {
+        let findings = [finding("", 0, 0, Severity::Warning, "")];
+        let log = sarif(LintTool::Verible, Path::new("/proj"), &findings);
+        let run = &log["runs"][0];
+        assert_eq!(run["tool"]["driver"]["name"], "verible-verilog-lint");
+        assert_eq!(run["tool"]["driver"]["rules"], json!([]));
+        let result = &run["results"][0];
+        assert!(result.get("ruleId").is_none());
+        assert!(result.get("locations").is_none());
+    }
+
+    #[test]
+    fn sarif_clamps_line_zero_to_one() {
+        let findings = [finding("fpga/rtl/top.v", 0, 0, Severity::Error, "SYNTAX")];
+        let log = sarif(LintTool::Verilator, Path::new("/proj"), &findings);
+        assert_eq!(
+            log["runs"][0]["results"][0]["locations"][0]["physicalLocation"]["region"]["startLine"],
+            1
+        );
+    }
+}
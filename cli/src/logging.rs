@@ -0,0 +1,52 @@
+use anyhow::{Context, Result};
+use std::io::{self, IsTerminal};
+use tracing_subscriber::EnvFilter;
+
+use affogato_core::output::{ColorChoice, Verbosity};
+
+/// How log events are written to stderr
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum LogFormat {
+    /// One line per event, with the spans it happened in
+    #[default]
+    Text,
+    /// One JSON object per event, for log collectors
+    Json,
+}
+
+/// Set up logging to stderr. `level` (a level or `RUST_LOG`-style filter such
+/// as `affogato_core=trace`) wins over `RUST_LOG`; otherwise the verbosity
+/// picks it: errors with `-q`, warnings by default, debug with `-v` and trace with `-vv`.
+pub fn init(
+    level: Option<&str>,
+    format: LogFormat,
+    verbosity: Verbosity,
+    color: ColorChoice,
+) -> Result<()> {
+    let filter = match level {
+        Some(level) => {
+            EnvFilter::try_new(level).with_context(|| format!("Invalid log level: {}", level))?
+        }
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+            EnvFilter::new(match verbosity {
+                Verbosity::Quiet => "error",
+                Verbosity::Normal => "warn",
+                Verbosity::Verbose => "debug",
+                Verbosity::Trace => "trace",
+            })
+        }),
+    };
+
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr);
+    match format {
+        LogFormat::Text => subscriber
+            .without_time()
+            .with_target(false)
+            .with_ansi(color.enabled(io::stderr().is_terminal()))
+            .init(),
+        LogFormat::Json => subscriber.json().with_span_list(true).init(),
+    }
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use std::fs;
+use std::path::Path;
+
+use affogato_core::outln;
+use affogato_core::output;
+use affogato_core::project::Project;
+use affogato_core::tool_log;
+
+/// Print a saved log of a past command's tool output. `which` is "last" (the
+/// default) or n, counting back from the newest log as 1.
+pub fn run(project: &Project, which: Option<&str>, list: bool, path_only: bool) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    let logs = tool_log::list(project_root)?;
+    if logs.is_empty() {
+        bail!(
+            "No logs yet; they are saved in {} by builds, tests and flashes",
+            tool_log::LOG_DIR
+        );
+    }
+
+    if list {
+        return list_logs(&logs);
+    }
+
+    let n = match which.unwrap_or("last") {
+        "last" => 1,
+        n => n
+            .parse::<usize>()
+            .ok()
+            .filter(|n| *n > 0)
+            .with_context(|| format!("Expected last or a number, got {}", n))?,
+    };
+    let Some(log) = logs.iter().rev().nth(n - 1) else {
+        bail!("Only {} log(s) saved", logs.len());
+    };
+
+    if path_only {
+        outln!("{}", log.display());
+        return Ok(());
+    }
+    let text =
+        fs::read_to_string(log).with_context(|| format!("Failed to read {}", log.display()))?;
+    for line in text.lines() {
+        outln!("{}", line);
+    }
+    Ok(())
+}
+
+fn list_logs(logs: &[std::path::PathBuf]) -> Result<()> {
+    let mut entries = Vec::new();
+    for (i, log) in logs.iter().rev().enumerate() {
+        let text = fs::read_to_string(log).unwrap_or_default();
+        let command = text
+            .lines()
+            .next()
+            .and_then(|header| header.strip_prefix("# "))
+            .map(|header| header.rsplit_once(" (").map_or(header, |(args, _)| args))
+            .unwrap_or_default()
+            .to_string();
+        let status = match text.lines().last() {
+            Some("# succeeded") => "ok",
+            Some(line) if line.starts_with("# failed") => "failed",
+            _ => "incomplete",
+        };
+        let name = file_stem(log);
+        let padded = format!("{:<10}", status);
+        let shown = match status {
+            "ok" => padded.green(),
+            "failed" => padded.red(),
+            _ => padded.yellow(),
+        };
+        outln!("{:>3}  {}  {}  {}", i + 1, shown, name, command.dimmed());
+        entries.push(serde_json::json!({
+            "n": i + 1,
+            "status": status,
+            "command": command,
+            "path": log,
+        }));
+    }
+    output::report("logs", entries);
+    Ok(())
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
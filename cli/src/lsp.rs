@@ -0,0 +1,151 @@
+use anyhow::{bail, Context, Result};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::thread;
+
+use affogato_core::docker::Docker;
+use affogato_core::project::Project;
+
+/// Language servers `affogato lsp` can run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Server {
+    /// verible-verilog-ls, from the Verible suite `affogato lint --tool verible` uses
+    #[default]
+    Verible,
+    /// svls, built on the svlint rules
+    Svls,
+}
+
+impl Server {
+    fn binary(self) -> &'static str {
+        match self {
+            Server::Verible => "verible-verilog-ls",
+            Server::Svls => "svls",
+        }
+    }
+}
+
+/// Where the project is mounted in the container, as a file URI
+const CONTAINER_URI: &str = "file:///workspace";
+
+/// Run a Verilog language server in the container, speaking LSP on stdin
+/// and stdout. File URIs under the project root are rewritten to the
+/// container's /workspace on the way in and back on the way out, so the
+/// server's diagnostics land on the editor's files.
+///
+/// Nothing else may be written to stdout, which carries the protocol; the
+/// server's own logs go to stderr.
+pub fn run(docker: &Docker, project: &Project, server: Server, args: &[String]) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    // Pulling would print progress onto the protocol stream
+    if !docker.image_exists()? {
+        bail!("Image not available locally; run `affogato docker pull` first");
+    }
+
+    let host_uris = host_uris(project_root);
+    let mut cmd = vec![server.binary()];
+    cmd.extend(args.iter().map(String::as_str));
+    let mut container = docker.spawn_interactive_in_project(project, &cmd, false)?;
+    let Some(child) = container.child() else {
+        return Ok(());
+    };
+    let mut server_in = child.stdin.take().context("No stdin for the server")?;
+    let server_out = child.stdout.take().context("No stdout for the server")?;
+
+    // Editor to server; ends when the editor closes stdin, which closes the
+    // server's stdin and stops it
+    let to_container = host_uris.clone();
+    thread::spawn(move || {
+        let mut editor = BufReader::new(io::stdin().lock());
+        while let Ok(Some(message)) = read_message(&mut editor) {
+            let message = translate(&message, &to_container, CONTAINER_URI);
+            if write_message(&mut server_in, &message).is_err() {
+                break;
+            }
+        }
+    });
+
+    // Server to editor, until the server exits
+    let mut server_out = BufReader::new(server_out);
+    let mut editor = io::stdout().lock();
+    while let Some(message) = read_message(&mut server_out)? {
+        let message = translate(&message, &[CONTAINER_URI.to_string()], &host_uris[0]);
+        write_message(&mut editor, &message)?;
+    }
+    Ok(())
+}
+
+/// The project root as the file URIs editors send: percent-encoded, with a
+/// drive letter's colon either encoded (VS Code) or not
+fn host_uris(project_root: &Path) -> Vec<String> {
+    let path = project_root.to_string_lossy().replace('\\', "/");
+    let path = path.strip_prefix("//?/").unwrap_or(&path);
+    let mut encoded = String::new();
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    let slash = if encoded.starts_with('/') { "" } else { "/" };
+    let uri = format!("file://{}{}", slash, encoded);
+    let mut uris = vec![uri.clone()];
+    if uri.contains("%3A") {
+        uris.push(uri.replacen("%3A", ":", 1));
+    }
+    uris
+}
+
+/// Rewrite URIs starting with any of `from` to start with `to`, where the
+/// URI is the directory itself or a file under it
+fn translate(message: &str, from: &[String], to: &str) -> String {
+    let mut message = message.to_string();
+    for from in from {
+        for end in ['/', '"'] {
+            message = message.replace(&format!("{}{}", from, end), &format!("{}{}", to, end));
+        }
+    }
+    message
+}
+
+/// Read one LSP message: `Content-Length` and other headers, a blank line,
+/// then the JSON body. None at end of input.
+fn read_message(reader: &mut impl BufRead) -> Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = Some(
+                    value
+                        .trim()
+                        .parse::<usize>()
+                        .context("Bad Content-Length")?,
+                );
+            }
+        }
+    }
+    let length = length.context("LSP message without a Content-Length")?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(
+        String::from_utf8(body).context("LSP message is not UTF-8")?,
+    ))
+}
+
+fn write_message(writer: &mut impl Write, body: &str) -> io::Result<()> {
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
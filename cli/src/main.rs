@@ -1,18 +1,62 @@
-use anyhow::Result;
-use clap::{Parser, Subcommand};
+use anyhow::{bail, Context, Result};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 use colored::Colorize;
+use std::path::PathBuf;
+use std::process::ExitCode;
 
-mod build;
-mod config;
+mod bench;
+mod bitstream;
+mod cdc;
+mod ci;
+mod ci_cache;
+mod clean;
+mod console;
+mod coregen;
+mod cosim;
 mod demo;
-mod docker;
-mod project;
-mod test;
+mod doctor;
+mod equiv;
+mod fabric;
+mod fmt;
+mod formal;
+mod gen;
+mod git;
+mod hooks;
+mod hwtest;
+mod iceprog;
+mod ide;
+mod la;
+mod lint;
+mod logging;
+mod logs;
+mod lsp;
+mod metrics;
+mod monitor;
+mod native;
+mod package;
+mod plugin;
+mod reg;
+mod regmap;
+mod remote;
+mod repro;
+mod setup;
+mod signing;
+mod timing;
+mod udev;
+mod update;
+mod verify;
+mod version;
 mod watch;
 
-use build::build_fpga;
-use docker::Docker;
-use project::Project;
+use affogato_core::build::{self, build_fpga};
+use affogato_core::config::{is_com_port, Config};
+use affogato_core::docker::{require_device, Docker};
+use affogato_core::error;
+use affogato_core::outln;
+use affogato_core::project::{self, Project};
+use affogato_core::{
+    annotate, cache, constraints, deps, dry_run, interrupt, ip, output, softcore, test, tool_log,
+};
 
 /// Affogato - ESP32-S2 + ICE40 FPGA Development Tool
 #[derive(Parser)]
@@ -23,13 +67,78 @@ struct Cli {
     #[command(subcommand)]
     command: Commands,
 
+    /// Run as if affogato was started in DIR, like `git -C` and `cargo -C`
+    #[arg(short = 'C', long = "project-dir", global = true, value_name = "DIR")]
+    project_dir: Option<PathBuf>,
+
     /// Docker image to use
     #[arg(long, global = true, env = "AFFOGATO_IMAGE")]
     image: Option<String>,
 
-    /// Verbose output
-    #[arg(short, long, global = true)]
-    verbose: bool,
+    /// Show all tool output and debug logs; -vv adds trace logs
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Hide tool output, showing only the final status (the full output is saved to .affogato/logs)
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Log level or filter, e.g. debug or affogato_core=trace (default: RUST_LOG, or warn)
+    #[arg(long, global = true, value_name = "LEVEL")]
+    log_level: Option<String>,
+
+    /// Log format: text, or json for log collectors
+    #[arg(long, global = true, value_enum, default_value = "text")]
+    log_format: logging::LogFormat,
+
+    /// Print results as a JSON document on stdout, with human output on stderr
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Print the docker commands, scripts and file changes instead of running them
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// Run every build step instead of restoring unchanged ones from the
+    /// shared build cache
+    #[arg(long, global = true)]
+    no_cache: bool,
+
+    /// Parallel build jobs for make and idf.py, also capping each container's
+    /// CPUs (default: [docker] jobs in the user config, else all CPUs)
+    #[arg(
+        short,
+        long,
+        global = true,
+        env = "AFFOGATO_JOBS",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    jobs: Option<u32>,
+
+    /// Build profile whose [fpga.constraints] fragments apply, e.g. rev-b or
+    /// debug; repeat or comma-separate for several (default: [fpga] profiles)
+    #[arg(
+        long = "profile",
+        global = true,
+        env = "AFFOGATO_PROFILE",
+        value_name = "NAME",
+        value_delimiter = ','
+    )]
+    profiles: Vec<String>,
+
+    /// When to color output: auto (on a terminal, unless NO_COLOR is set), always or never
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_name = "WHEN",
+        default_value = "auto"
+    )]
+    color: output::ColorChoice,
+
+    /// Don't color output; same as --color never
+    #[arg(long, global = true)]
+    no_color: bool,
 }
 
 #[derive(Subcommand)]
@@ -39,14 +148,15 @@ enum Commands {
         /// Project name
         name: String,
 
-        /// Template to use (default: basic)
+        /// Template to use: basic, softcore for a picorv32 running C from BRAM, or
+        /// hwtest for the board self-test
         #[arg(short, long, default_value = "basic")]
         template: String,
     },
 
     /// Initialize Affogato in an existing directory
     Init {
-        /// Template to use
+        /// Template to use (basic, softcore, hwtest)
         #[arg(short, long, default_value = "basic")]
         template: String,
     },
@@ -54,6 +164,19 @@ enum Commands {
     /// Build FPGA bitstream
     #[command(alias = "build-fpga")]
     Fpga {
+        /// Report errors as text or GitHub Actions annotations
+        #[arg(long, value_enum, default_value = "text")]
+        format: annotate::ReportFormat,
+
+        /// Then load the bitstream through an FTDI programmer with iceprog,
+        /// bypassing the ESP32
+        #[arg(long)]
+        iceprog: bool,
+
+        /// FTDI device for iceprog (e.g. "i:0x0403:0x6014" or "d:002/005")
+        #[arg(long, requires = "iceprog")]
+        ftdi: Option<String>,
+
         /// Additional arguments passed to make
         #[arg(trailing_var_arg = true)]
         args: Vec<String>,
@@ -61,6 +184,18 @@ enum Commands {
 
     /// Build ESP32 firmware (includes FPGA)
     Build {
+        /// Report errors as text or GitHub Actions annotations
+        #[arg(long, value_enum, default_value = "text")]
+        format: annotate::ReportFormat,
+
+        /// Build twice from clean outputs and check the artifacts are identical
+        #[arg(long, conflicts_with = "args")]
+        verify_repro: bool,
+
+        /// Build on this ssh host (e.g. user@buildbox) and copy the artifacts back
+        #[arg(long, value_name = "HOST", conflicts_with = "verify_repro")]
+        remote: Option<String>,
+
         /// Additional arguments passed to idf.py
         #[arg(trailing_var_arg = true)]
         args: Vec<String>,
@@ -68,31 +203,103 @@ enum Commands {
 
     /// Flash firmware to device
     Flash {
-        /// Serial port
-        #[arg(short, long, default_value = "/dev/ttyACM0")]
-        port: String,
+        /// Serial port (default: the configured port, or /dev/ttyACM0)
+        #[arg(short, long, conflicts_with = "dfu")]
+        port: Option<String>,
+
+        /// Flash over the S2's native USB DFU instead of serial (hold BOOT while plugging in)
+        #[arg(long)]
+        dfu: bool,
     },
 
     /// Monitor serial output
     Monitor {
-        /// Serial port
-        #[arg(short, long, default_value = "/dev/ttyACM0")]
-        port: String,
+        /// Serial port (default: the configured port, or /dev/ttyACM0)
+        #[arg(short, long)]
+        port: Option<String>,
+
+        /// Decode backtraces against this ELF instead of the last build's
+        #[arg(long)]
+        elf: Option<PathBuf>,
+    },
+
+    /// Attach to the firmware's debug console with line editing, history and
+    /// tab completion
+    Console {
+        /// Serial port (default: the configured port, or /dev/ttyACM0)
+        #[arg(short, long)]
+        port: Option<String>,
     },
 
     /// Flash and immediately monitor
     Run {
-        /// Serial port
-        #[arg(short, long, default_value = "/dev/ttyACM0")]
-        port: String,
+        /// Serial port (default: the configured port, or /dev/ttyACM0)
+        #[arg(short, long)]
+        port: Option<String>,
+
+        /// Decode backtraces against this ELF instead of the last build's
+        #[arg(long)]
+        elf: Option<PathBuf>,
+    },
+
+    /// Show the last build's version, or check what the board is running
+    Version {
+        /// Ask the board for its firmware and FPGA build over serial
+        #[arg(long)]
+        device: bool,
+
+        /// Serial port (default: the configured port, or /dev/ttyACM0)
+        #[arg(short, long, requires = "device")]
+        port: Option<String>,
+    },
+
+    /// Check the board runs exactly the release in a package bundle
+    Verify {
+        /// The bundle: a package .tar.gz, its unpacked directory or its manifest.json
+        bundle: PathBuf,
+
+        /// Serial port (default: the configured port, or /dev/ttyACM0)
+        #[arg(short, long, conflicts_with = "offline")]
+        port: Option<String>,
+
+        /// Check the bundle's signatures against this public key (PEM)
+        #[arg(long, value_name = "PUBLIC_KEY")]
+        key: Option<PathBuf>,
+
+        /// Only check the signatures, without a board
+        #[arg(long, requires = "key")]
+        offline: bool,
+    },
+
+    /// Bundle the built bitstream and firmware with a manifest into a release archive
+    Package {
+        /// Directory for the archive, relative to the project root
+        #[arg(short, long, default_value = "dist")]
+        output: PathBuf,
+
+        /// Also bundle a single image of every flashed partition, written at offset 0
+        #[arg(long)]
+        merged: bool,
+
+        /// Also bundle the firmware as UF2, for boards with a UF2 bootloader
+        #[arg(long)]
+        uf2: bool,
+
+        /// Also bundle the firmware as DFU, for flashing over native USB
+        #[arg(long)]
+        dfu: bool,
+
+        /// Sign the manifest and app image with this ed25519 or RSA private key (PEM)
+        #[arg(long, value_name = "KEY")]
+        sign: Option<PathBuf>,
     },
 
     /// Run Verilog testbenches
     Test {
-        /// Specific test to run (without _tb.v suffix)
+        /// Test name or glob pattern (without _tb.v suffix), e.g. 'spi_*'
         name: Option<String>,
 
-        /// Launch GTKWave to view waveforms
+        /// Open the waveform in GTKWave or Surfer on the host
         #[arg(long)]
         view: bool,
 
@@ -100,13 +307,197 @@ enum Commands {
         #[arg(long, default_value = "fpga")]
         dir: String,
 
-        /// Show verbose output for all tests
-        #[arg(short, long)]
-        verbose: bool,
-
         /// Run tests in parallel (experimental)
         #[arg(long)]
         parallel: bool,
+
+        /// Only run tests with this tag (repeatable)
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+
+        /// Skip tests with this tag (repeatable)
+        #[arg(long = "skip-tag", value_name = "TAG")]
+        skip_tags: Vec<String>,
+
+        /// List discovered tests without running them
+        #[arg(long)]
+        list: bool,
+
+        /// Rerun only the tests that failed in the previous run
+        #[arg(long)]
+        failed: bool,
+
+        /// Rerun affected tests whenever RTL or testbench files change
+        #[arg(long)]
+        watch: bool,
+
+        /// Collect Verilator coverage and write an HTML report to .affogato/coverage
+        #[arg(long)]
+        coverage: bool,
+
+        /// Fail if total coverage is below this percentage
+        #[arg(long, value_name = "PCT", requires = "coverage")]
+        min_coverage: Option<f64>,
+
+        /// Record each passing test's waveform as its golden reference
+        #[arg(long)]
+        bless: bool,
+
+        /// Accept current simulation output into the `.expected` snapshots
+        #[arg(long)]
+        update_snapshots: bool,
+
+        /// Random seed for simulations (random per run by default)
+        #[arg(long)]
+        seed: Option<u32>,
+
+        /// Run only shard K of N, e.g. 2/4 (for splitting across CI runners)
+        #[arg(long, value_name = "K/N")]
+        shard: Option<test::Shard>,
+
+        /// Balance shards by recorded test durations
+        #[arg(long, requires = "shard")]
+        balance: bool,
+
+        /// Dump waveforms for every test to .affogato/waves (format from [test] wave_format)
+        #[arg(long)]
+        waves: bool,
+
+        /// Report failures as text or GitHub Actions annotations
+        #[arg(long, value_enum, default_value = "text")]
+        format: annotate::ReportFormat,
+
+        /// Also write the results as a JUnit XML report to this file
+        #[arg(long, value_name = "FILE")]
+        report: Option<PathBuf>,
+    },
+
+    /// Check the synthesized netlist for unsynchronized clock-domain crossings
+    Cdc {
+        /// Top module (default: [fpga] top)
+        #[arg(long)]
+        top: Option<String>,
+    },
+
+    /// Check the synthesized netlist is logically equivalent to the RTL
+    Equiv {
+        /// Top module (default: [fpga] top)
+        #[arg(long)]
+        top: Option<String>,
+
+        /// Sequential depth for equiv_simple/equiv_induct
+        #[arg(long, default_value = "5")]
+        seq: u32,
+    },
+
+    /// Run C tests against a Verilator model of the FPGA design through a
+    /// host build of the firmware's SPI driver
+    Cosim {
+        /// Tests to run (default: all)
+        tests: Vec<String>,
+
+        /// Write a waveform to .affogato/cosim/cosim.vcd
+        #[arg(long)]
+        trace: bool,
+    },
+
+    /// Run formal verification with SymbiYosys
+    Formal {
+        /// Task to run (.sby file stem or [formal.<name>]); omit to run all
+        module: Option<String>,
+
+        /// FPGA directory (default: fpga)
+        #[arg(long, default_value = "fpga")]
+        dir: String,
+    },
+
+    /// Generate code from project sources
+    Gen {
+        #[command(subcommand)]
+        command: GenCommands,
+    },
+
+    /// Add a bundled component to the project
+    Add {
+        #[command(subcommand)]
+        command: AddCommands,
+    },
+
+    /// Manage HDL dependencies declared in [fpga.deps]
+    Deps {
+        #[command(subcommand)]
+        command: DepsCommands,
+    },
+
+    /// Recompile the soft-core program and load it into the bitstream without resynthesizing
+    Softcore {
+        /// Flash the firmware with the updated bitstream
+        #[arg(long)]
+        flash: bool,
+
+        /// Serial port for --flash (default: the configured port, or /dev/ttyACM0)
+        #[arg(short, long)]
+        port: Option<String>,
+    },
+
+    /// Inspect built bitstreams
+    Bitstream {
+        #[command(subcommand)]
+        command: BitstreamCommands,
+    },
+
+    /// Read and write FPGA registers through the firmware's debug console
+    Reg {
+        #[command(subcommand)]
+        command: RegCommands,
+    },
+
+    /// Show the last FPGA build's Fmax and critical paths, or compare them
+    /// with another build's
+    Timing {
+        /// Compare with this git revision's build (built in a temporary
+        /// worktree if not archived), or "previous" for the build before
+        #[arg(long, value_name = "REF")]
+        compare: Option<String>,
+
+        /// With --compare, fail if any clock's Fmax dropped
+        #[arg(long, requires = "compare")]
+        fail_on_regression: bool,
+    },
+
+    /// Measure the hardware through the firmware's debug console
+    Bench {
+        #[command(subcommand)]
+        command: BenchCommands,
+    },
+
+    /// Run the board self-test: CDONE/CRESET, SPI, RGB LED and GPIO loopbacks
+    Hwtest {
+        /// Serial port (default: the configured port, or /dev/ttyACM0)
+        #[arg(short, long)]
+        port: Option<String>,
+    },
+
+    /// Capture the board's wires with a sigrok logic analyzer
+    La {
+        #[command(subcommand)]
+        command: LaCommands,
+    },
+
+    /// Generate register blocks from a register map description
+    Regmap {
+        #[command(subcommand)]
+        command: RegmapCommands,
+    },
+
+    /// Parse and elaborate the FPGA design without synthesis, in seconds
+    Check,
+
+    /// Format Verilog with verible-verilog-format
+    Fmt {
+        /// Only report files that need formatting, failing if there are any
+        #[arg(long)]
+        check: bool,
     },
 
     /// Lint Verilog files
@@ -114,6 +505,47 @@ enum Commands {
         /// FPGA directory (default: fpga)
         #[arg(long, default_value = "fpga")]
         dir: String,
+
+        /// Lint tool (default: [lint] tool, or verilator)
+        #[arg(long, value_enum)]
+        tool: Option<project::LintTool>,
+
+        /// Freeze current findings in lint-baseline.toml so only new ones fail
+        #[arg(long)]
+        update_baseline: bool,
+
+        /// Output format
+        #[arg(long, value_enum, default_value = "text")]
+        format: lint::LintFormat,
+
+        /// Only lint files changed since a git revision (default: HEAD)
+        #[arg(
+            long,
+            value_name = "BASE",
+            num_args = 0..=1,
+            default_missing_value = "HEAD",
+            conflicts_with = "update_baseline"
+        )]
+        diff: Option<String>,
+
+        /// Fail on warnings, not just errors
+        #[arg(long)]
+        strict: bool,
+
+        /// Always exit successfully, even when lint finds errors
+        #[arg(long, conflicts_with = "strict")]
+        no_fail: bool,
+    },
+
+    /// Run a Verilog language server in the container for the editor, over stdio
+    Lsp {
+        /// Language server to run
+        #[arg(long, value_enum, default_value = "verible")]
+        server: lsp::Server,
+
+        /// Additional arguments passed to the server
+        #[arg(trailing_var_arg = true)]
+        args: Vec<String>,
     },
 
     /// Open ESP-IDF menuconfig
@@ -121,6 +553,22 @@ enum Commands {
 
     /// Clean build artifacts
     Clean {
+        /// Clean the FPGA build (with no selector, FPGA and firmware are cleaned)
+        #[arg(long)]
+        fpga: bool,
+
+        /// Clean the firmware build
+        #[arg(long)]
+        firmware: bool,
+
+        /// Clear the shared build cache, used by every project
+        #[arg(long)]
+        cache: bool,
+
+        /// Clean the FPGA and firmware builds fully and clear the build cache
+        #[arg(long, conflicts_with_all = ["fpga", "firmware", "cache"])]
+        all: bool,
+
         /// Full clean including CMake cache
         #[arg(long)]
         full: bool,
@@ -139,30 +587,403 @@ enum Commands {
         command: DockerCommands,
     },
 
+    /// Check docker, the image, USB device access and the project, suggesting fixes
+    Doctor {
+        /// Serial port the board should appear as (default: the configured port, or /dev/ttyACM0)
+        #[arg(short, long)]
+        port: Option<String>,
+    },
+
+    /// Install the udev rule giving non-root access to the board (Linux)
+    SetupUdev {
+        /// Print the rule instead of installing it
+        #[arg(long)]
+        print: bool,
+    },
+
+    /// Show the saved tool output of a past build, test or flash
+    Logs {
+        /// Which log: last, or n counting back from the newest (1)
+        which: Option<String>,
+
+        /// List the saved logs instead
+        #[arg(long)]
+        list: bool,
+
+        /// Print the log's path instead of its contents
+        #[arg(long)]
+        path: bool,
+    },
+
+    /// Set up docker, the image, USB access and the default serial port
+    Setup,
+
+    /// Update affogato to the latest GitHub release
+    SelfUpdate {
+        /// Only report whether an update is available
+        #[arg(long)]
+        check: bool,
+    },
+
     /// Watch for changes and rebuild automatically
     Watch {
         /// Only rebuild FPGA (skip firmware)
         #[arg(long)]
         fpga_only: bool,
+
+        /// Flash the device and restart the monitor after each successful build
+        #[arg(long, conflicts_with = "fpga_only")]
+        flash: bool,
+
+        /// Rerun the testbenches affected by each change instead of building
+        #[arg(long, conflicts_with_all = ["fpga_only", "flash"])]
+        test: bool,
+
+        /// Only elaborate the design on changes; type `b` + Enter for a full build
+        #[arg(long, conflicts_with_all = ["flash", "test"])]
+        check: bool,
+
+        /// Don't show desktop notifications when builds finish
+        #[arg(long)]
+        no_notify: bool,
+
+        /// Serial port for --flash (default: the configured port, or /dev/ttyACM0)
+        #[arg(short, long)]
+        port: Option<String>,
+    },
+
+    /// Save and restore build caches between CI runs
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+
+    /// Set up continuous integration for the project
+    Ci {
+        #[command(subcommand)]
+        command: CiCommands,
+    },
+
+    /// Set up an editor for the project
+    Ide {
+        #[command(subcommand)]
+        command: IdeCommands,
+    },
+
+    /// Install git hooks running the [hooks] commands
+    Hooks {
+        #[command(subcommand)]
+        command: HooksCommands,
     },
 
     /// Run a demo project
+    #[command(args_conflicts_with_subcommands = true)]
     Demo {
-        /// Demo name (colorwheel, web-led). Omit to list available demos.
+        #[command(subcommand)]
+        command: Option<DemoCommands>,
+
+        /// Demo name, from the examples' demo.toml. Omit to list available demos.
         name: Option<String>,
 
-        /// Serial port
-        #[arg(short, long, default_value = "/dev/ttyACM0")]
-        port: String,
+        /// Serial port (default: the configured port, or /dev/ttyACM0)
+        #[arg(short, long)]
+        port: Option<String>,
 
         /// Build only, don't flash
         #[arg(long)]
         build_only: bool,
 
+        /// Run the demo's testbenches and open the waveform instead of
+        /// building for a board
+        #[arg(long, conflicts_with = "build_only")]
+        sim: bool,
+
         /// List available demos
         #[arg(short, long)]
         list: bool,
     },
+
+    /// Run an `affogato-<name>` plugin from PATH
+    #[command(external_subcommand)]
+    External(Vec<String>),
+}
+
+#[derive(Subcommand)]
+enum CacheCommands {
+    /// Print the cache key, paths and docker volume a CI system should cache
+    Manifest,
+
+    /// Write the caches to tar archives in a directory
+    Export {
+        /// Directory to write the archives into
+        dir: PathBuf,
+
+        /// Also save the toolchain image (several GB)
+        #[arg(long)]
+        include_image: bool,
+    },
+
+    /// Restore caches written by `cache export`
+    Import {
+        /// Directory holding the archives
+        dir: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum CiCommands {
+    /// Generate a pipeline that builds, tests and keeps the artifacts
+    Init {
+        /// CI system to generate for
+        #[arg(long, value_enum, default_value = "github")]
+        provider: ci::Provider,
+
+        /// Overwrite an existing pipeline file
+        #[arg(short, long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum IdeCommands {
+    /// Generate a devcontainer, build/test/flash tasks and recommended extensions
+    Init {
+        /// Editor to generate for
+        #[arg(long, value_enum, default_value = "vscode")]
+        editor: ide::Editor,
+
+        /// Overwrite existing files
+        #[arg(short, long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum HooksCommands {
+    /// Write pre-commit and pre-push hooks from [hooks] in affogato.toml
+    Install {
+        /// Replace hooks that affogato didn't write
+        #[arg(short, long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum DemoCommands {
+    /// Download a demo from the GitHub release matching this affogato
+    Fetch {
+        /// Demo name. Omit to list the release's demos.
+        name: Option<String>,
+
+        /// Release to fetch from, e.g. r512 or latest (default: this affogato's)
+        #[arg(long)]
+        tag: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum GenCommands {
+    /// Generate a testbench skeleton for an RTL module
+    Tb {
+        /// Module name
+        module: String,
+
+        /// FPGA directory (default: fpga)
+        #[arg(long, default_value = "fpga")]
+        dir: String,
+
+        /// Overwrite an existing testbench
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Generate a configured core (Verilog in fpga/rtl, C header in firmware/main)
+    Core {
+        #[command(subcommand)]
+        core: CoreCommands,
+    },
+
+    /// Generate the bus fabric described by [fpga.bus] in affogato.toml
+    Bus,
+}
+
+#[derive(Subcommand)]
+enum CoreCommands {
+    /// Multi-channel PWM with per-channel duty registers
+    Pwm {
+        /// Number of outputs (1-16)
+        #[arg(long, default_value = "4")]
+        channels: u32,
+
+        /// Counter width in bits (2-16)
+        #[arg(long, default_value = "8")]
+        resolution: u32,
+
+        /// Module name (default: pwm_<channels>ch)
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Overwrite existing files
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// GPIO with output, direction and input registers
+    Gpio {
+        /// Number of pins (1-16)
+        #[arg(long, default_value = "8")]
+        width: u32,
+
+        /// Module name (default: gpio_<width>)
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Overwrite existing files
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum AddCommands {
+    /// Install an IP core into fpga/rtl/ip, with its testbench
+    Ip {
+        /// Core name (see --list)
+        #[arg(required_unless_present = "list")]
+        name: Option<String>,
+
+        /// List the available cores
+        #[arg(long)]
+        list: bool,
+
+        /// Overwrite existing files
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum DepsCommands {
+    /// Vendor dependencies into fpga/vendor at the commits in affogato.lock
+    Fetch,
+
+    /// Re-resolve dependencies from their rev and update affogato.lock
+    Update {
+        /// Dependencies to update (default: all)
+        names: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum RegmapCommands {
+    /// Generate the Verilog register block, C header and register reference
+    Build {
+        /// Register map description
+        #[arg(default_value = regmap::DEFAULT_SPEC)]
+        spec: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum RegCommands {
+    /// Read a register
+    Read {
+        /// Address (e.g. 0x04) or register name from regmap.toml
+        register: String,
+
+        /// Serial port (default: the configured port, or /dev/ttyACM0)
+        #[arg(short, long)]
+        port: Option<String>,
+    },
+
+    /// Write a register
+    Write {
+        /// Address (e.g. 0x04) or register name from regmap.toml
+        register: String,
+
+        /// 16-bit value (e.g. 0xff)
+        value: String,
+
+        /// Serial port (default: the configured port, or /dev/ttyACM0)
+        #[arg(short, long)]
+        port: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum BenchCommands {
+    /// Stream data through an spi_loopback core at each SPI clock, reporting
+    /// throughput and bit errors
+    Spi {
+        /// SPI clocks to try, in Hz or with a k/M suffix (default: 1M to 80M)
+        freqs: Vec<String>,
+
+        /// Serial port (default: the configured port, or /dev/ttyACM0)
+        #[arg(short, long)]
+        port: Option<String>,
+    },
+
+    /// Time a clean build and record its durations, utilization and Fmax
+    /// against the commit, showing the change since the last record
+    Build {
+        /// Only build and record the FPGA
+        #[arg(long)]
+        fpga_only: bool,
+
+        /// Show the last recorded builds instead of building
+        #[arg(long, value_name = "COUNT", num_args = 0..=1, default_missing_value = "10", conflicts_with_all = ["fpga_only", "compare"])]
+        history: Option<usize>,
+
+        /// Compare the builds recorded for two commits (the second defaults to
+        /// the latest build) instead of building
+        #[arg(long, value_name = "COMMIT", num_args = 1..=2, conflicts_with = "fpga_only")]
+        compare: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum LaCommands {
+    /// Record signals through sigrok-cli, decoding SPI traffic when the spi
+    /// group is captured
+    Capture {
+        /// Signals from [la.probes], or spi for the ESP32-FPGA SPI bus
+        #[arg(short, long, value_delimiter = ',', default_value = "spi")]
+        channels: Vec<String>,
+
+        /// How long to record (e.g. "100ms", "2s")
+        #[arg(short, long, default_value = "100ms")]
+        duration: String,
+
+        /// Start on a sigrok trigger, e.g. FSPI_CS=f for a falling chip select
+        #[arg(short, long)]
+        trigger: Option<String>,
+
+        /// Capture file (default: .affogato/captures/capture-<time>.sr)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Open the capture in PulseView afterwards
+        #[arg(long)]
+        open: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum BitstreamCommands {
+    /// Decode a bitstream's header: device, boot images, BRAM use and size
+    Info {
+        /// Bitstream (default: fpga/top.bin)
+        file: Option<PathBuf>,
+    },
+
+    /// Compare two bitstreams tile by tile: routing changes vs new LUT or BRAM contents
+    Diff {
+        /// The original bitstream
+        a: PathBuf,
+
+        /// The changed bitstream
+        b: PathBuf,
+    },
 }
 
 #[derive(Subcommand)]
@@ -177,12 +998,117 @@ enum DockerCommands {
     Info,
 }
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+fn main() -> ExitCode {
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    output::set_json(cli.json);
+    let color = if cli.no_color {
+        output::ColorChoice::Never
+    } else {
+        cli.color
+    };
+    output::set_color(color);
+    dry_run::set_enabled(cli.dry_run);
+    cache::set_enabled(!cli.no_cache);
+    constraints::set_profiles(cli.profiles.clone());
+    let verbosity = output::Verbosity::from_flags(cli.quiet, cli.verbose);
+    output::set_verbosity(verbosity);
+    if let Err(e) = logging::init(cli.log_level.as_deref(), cli.log_format, verbosity, color) {
+        eprintln!("{} {}", "Error:".red().bold(), error::message(&e));
+        return ExitCode::FAILURE;
+    }
+
+    // Full subcommand path, e.g. "gen bus"
+    let mut command = Vec::new();
+    let mut sub = matches.subcommand();
+    while let Some((name, sub_matches)) = sub {
+        command.push(name);
+        sub = sub_matches.subcommand();
+    }
+
+    tool_log::set_command(&command.join(" "));
+    let result = run(cli);
+    tool_log::finish(result.as_ref().err());
+    output::finish(&command.join(" "), result.as_ref().err());
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{} {}", "Error:".red().bold(), error::message(&e));
+            ExitCode::from(error::exit_code(&e))
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    if let Some(dir) = &cli.project_dir {
+        std::env::set_current_dir(dir)
+            .with_context(|| format!("Cannot change to {}", dir.display()))?;
+    }
+    let mut config = Config::load()?;
+    let image = cli
+        .image
+        .clone()
+        .unwrap_or_else(|| config.docker.image.clone());
+
+    // Host setup commands run without docker or a project; doctor reports a
+    // broken affogato.toml or docker setup rather than failing on it
+    let host_command = matches!(
+        cli.command,
+        Commands::Doctor { .. }
+            | Commands::Setup
+            | Commands::SetupUdev { .. }
+            | Commands::SelfUpdate { .. }
+            | Commands::Demo {
+                command: Some(_),
+                ..
+            }
+            | Commands::External(_)
+    );
+    if !host_command && !Config::exists() && setup::interactive() {
+        setup::first_run(&image)?;
+        config = Config::load()?;
+    }
+    if let Commands::Doctor { port } = cli.command {
+        return doctor::run(&image, &config.serial_port(port), Project::detect());
+    }
+    if let Commands::Setup = cli.command {
+        return setup::run(&image);
+    }
+    if let Commands::SetupUdev { print } = cli.command {
+        return udev::setup(print);
+    }
+    if let Commands::SelfUpdate { check } = cli.command {
+        return update::self_update(check);
+    }
+    if let Commands::Demo {
+        command: Some(DemoCommands::Fetch { name, tag }),
+        ..
+    } = &cli.command
+    {
+        return demo::fetch(name.as_deref(), tag.as_deref());
+    }
 
-    let docker = Docker::new(cli.image, cli.verbose)?;
     let project = Project::detect()?;
 
+    // Plugins run on the host and may not need docker at all
+    if let Commands::External(args) = &cli.command {
+        return plugin::run(args, &project, &image);
+    }
+
+    // Reading logs needs neither docker nor a log of its own
+    if let Commands::Logs { which, list, path } = &cli.command {
+        project.require_project()?;
+        return logs::run(&project, which.as_deref(), *list, *path);
+    }
+
+    let jobs = cli
+        .jobs
+        .map(|jobs| jobs as usize)
+        .or(config.docker.jobs)
+        .filter(|&jobs| jobs > 0);
+    let docker = Docker::new(Some(image.clone()))?.with_jobs(jobs);
+    interrupt::install()?;
+
     match cli.command {
         Commands::New { name, template } => {
             project::create_new(&name, &template)?;
@@ -192,94 +1118,452 @@ fn main() -> Result<()> {
             project::init_current(&template)?;
         }
 
-        Commands::Fpga { args } => {
+        Commands::Fpga {
+            format,
+            iceprog,
+            ftdi,
+            args,
+        } => {
             project.require_project()?;
             docker.ensure_image()?;
 
-            println!("{}", "==> Building FPGA bitstream".blue().bold());
-            build_fpga(&docker, &project, &args)?;
+            outln!("{}", "==> Building FPGA bitstream".blue().bold());
+            build_fpga(&docker, &project, &args, format)?;
+            if iceprog {
+                iceprog::program(&docker, &project, ftdi.as_deref())?;
+            }
         }
 
-        Commands::Build { args } => {
+        Commands::Build {
+            format,
+            verify_repro,
+            remote,
+            args,
+        } => {
             project.require_project()?;
+            if let Some(host) = remote {
+                let mut flags = vec![
+                    "--color".to_string(),
+                    if output::color_enabled() {
+                        "always"
+                    } else {
+                        "never"
+                    }
+                    .to_string(),
+                ];
+                if cli.verbose > 0 {
+                    flags.push(format!("-{}", "v".repeat(cli.verbose as usize)));
+                }
+                if cli.quiet {
+                    flags.push("--quiet".to_string());
+                }
+                if cli.no_cache {
+                    flags.push("--no-cache".to_string());
+                }
+                if let Some(jobs) = cli.jobs {
+                    flags.push(format!("--jobs={}", jobs));
+                }
+                if !cli.profiles.is_empty() {
+                    flags.push(format!("--profile={}", cli.profiles.join(",")));
+                }
+                if let Some(image) = &cli.image {
+                    flags.extend(["--image".to_string(), image.clone()]);
+                }
+                return remote::build(&project, &host, &flags, &args, format);
+            }
             docker.ensure_image()?;
+            if verify_repro {
+                return repro::verify(&docker, &project, format);
+            }
 
             // Build FPGA first
-            println!("{}", "==> Building FPGA bitstream".blue().bold());
-            build_fpga(&docker, &project, &[])?;
+            outln!("{}", "==> Building FPGA bitstream".blue().bold());
+            build_fpga(&docker, &project, &[], format)?;
 
             // Then build firmware
-            println!("{}", "==> Building ESP32 firmware".blue().bold());
-            let idf_cmd = if args.is_empty() {
-                "cd firmware && idf.py build".to_string()
+            outln!("{}", "==> Building ESP32 firmware".blue().bold());
+            build::build_firmware(&docker, &project, &args, format)?;
+        }
+
+        Commands::Flash { port, dfu } => {
+            project.require_project()?;
+            let cmd = if dfu {
+                docker.ensure_image()?;
+                outln!("{}", "==> Flashing over USB DFU".blue().bold());
+                "cd firmware && idf.py dfu-flash".to_string()
             } else {
-                format!("cd firmware && idf.py build {}", args.join(" "))
+                let port = config.serial_port(port);
+                require_device(&port)?;
+                if is_com_port(&port) {
+                    outln!("{}", format!("==> Flashing to {}", port).blue().bold());
+                    return native::flash(&project, &port);
+                }
+                docker.ensure_image()?;
+                outln!("{}", format!("==> Flashing to {}", port).blue().bold());
+                format!("cd firmware && idf.py -p {} flash", port)
             };
-            docker.run_in_project(&project, &["bash", "-c", &idf_cmd], &[], false)?;
+            let (success, _) = docker.run_in_project_tee(&project, &["bash", "-c", &cmd], true)?;
+            if !success {
+                bail!("Flashing failed");
+            }
         }
 
-        Commands::Flash { port } => {
+        Commands::Monitor { port, elf } => {
+            let port = config.serial_port(port);
             project.require_project()?;
+            require_device(&port)?;
+            if is_com_port(&port) {
+                native::monitor(&project, &port, elf.as_deref())?;
+                return Ok(());
+            }
             docker.ensure_image()?;
 
-            println!("{}", format!("==> Flashing to {}", port).blue().bold());
-            let cmd = format!("cd firmware && idf.py -p {} flash", port);
-            docker.run_in_project(&project, &["bash", "-c", &cmd], &[], true)?;
+            monitor::run(&docker, &project, &port, false, elf.as_deref())?;
         }
 
-        Commands::Monitor { port } => {
+        Commands::Console { port } => {
             project.require_project()?;
-            docker.ensure_image()?;
-
-            println!("{}", "Ctrl+] to exit".yellow());
-            let cmd = format!("cd firmware && idf.py -p {} monitor", port);
-            docker.run_in_project(&project, &["bash", "-c", &cmd], &[], true)?;
+            let port = config.serial_port(port);
+            console::run(&docker, &project, &port)?;
         }
 
-        Commands::Run { port } => {
+        Commands::Run { port, elf } => {
+            let port = config.serial_port(port);
             project.require_project()?;
+            require_device(&port)?;
+            if is_com_port(&port) {
+                outln!(
+                    "{}",
+                    format!("==> Flash and monitor on {}", port).blue().bold()
+                );
+                native::flash(&project, &port)?;
+                native::monitor(&project, &port, elf.as_deref())?;
+                return Ok(());
+            }
             docker.ensure_image()?;
 
-            println!(
+            outln!(
                 "{}",
                 format!("==> Flash and monitor on {}", port).blue().bold()
             );
-            println!("{}", "Ctrl+] to exit".yellow());
-            let cmd = format!("cd firmware && idf.py -p {} flash monitor", port);
-            docker.run_in_project(&project, &["bash", "-c", &cmd], &[], true)?;
+            monitor::run(&docker, &project, &port, true, elf.as_deref())?;
+        }
+
+        Commands::Version { device, port } => {
+            project.require_project()?;
+            let port = device.then(|| config.serial_port(port));
+            version::run(&docker, &project, port.as_deref())?;
+        }
+
+        Commands::Verify {
+            bundle,
+            port,
+            key,
+            offline,
+        } => {
+            let port = (!offline).then(|| config.serial_port(port));
+            verify::run(&docker, &project, &bundle, key.as_deref(), port.as_deref())?;
+        }
+
+        Commands::Package {
+            output,
+            merged,
+            uf2,
+            dfu,
+            sign,
+        } => {
+            project.require_project()?;
+            let formats = package::Formats { merged, uf2, dfu };
+            package::run(
+                &docker,
+                &project,
+                &output,
+                &image,
+                &formats,
+                sign.as_deref(),
+            )?;
         }
 
         Commands::Test {
             name,
             view,
             dir,
-            verbose,
             parallel,
+            tags,
+            skip_tags,
+            list,
+            failed,
+            watch,
+            coverage,
+            min_coverage,
+            bless,
+            update_snapshots,
+            seed,
+            shard,
+            balance,
+            waves,
+            format,
+            report,
         } => {
             project.require_project()?;
-            docker.ensure_image()?;
+            if !list {
+                docker.ensure_image()?;
+            }
 
-            test::run_tests(
-                &docker,
-                &project,
-                name.as_deref(),
+            let opts = test::TestOptions {
+                name,
                 view,
-                &dir,
-                verbose,
+                fpga_dir: dir,
+                verbose: cli.verbose > 0,
                 parallel,
-            )?;
+                tags,
+                skip_tags,
+                list,
+                failed,
+                coverage,
+                min_coverage,
+                bless,
+                update_snapshots,
+                seed,
+                shard,
+                balance,
+                waves,
+                format,
+                report,
+            };
+            if watch {
+                watch::run_test_watch(&docker, &project, &opts, true)?;
+            } else {
+                test::run_tests(&docker, &project, &opts)?;
+            }
         }
 
-        Commands::Lint { dir } => {
+        Commands::Cdc { top } => {
             project.require_project()?;
             docker.ensure_image()?;
 
-            println!("{}", "==> Linting Verilog".blue().bold());
-            let cmd = format!(
-                "find {}/rtl -name '*.v' | xargs verilator --lint-only -Wall 2>&1 || true",
-                dir
-            );
-            docker.run_in_project(&project, &["bash", "-c", &cmd], &[], false)?;
+            cdc::run_cdc(&docker, &project, top.as_deref())?;
+        }
+
+        Commands::Equiv { top, seq } => {
+            project.require_project()?;
+            docker.ensure_image()?;
+
+            equiv::run_equiv(&docker, &project, top.as_deref(), seq)?;
+        }
+
+        Commands::Cosim { tests, trace } => {
+            cosim::run(&docker, &project, &tests, trace)?;
+        }
+
+        Commands::Formal { module, dir } => {
+            project.require_project()?;
+            docker.ensure_image()?;
+
+            formal::run_formal(&docker, &project, module.as_deref(), &dir, cli.verbose > 0)?;
+        }
+
+        Commands::Gen { command } => match command {
+            GenCommands::Tb { module, dir, force } => {
+                project.require_project()?;
+                gen::generate_testbench(&project, &module, &dir, force)?;
+            }
+            GenCommands::Core { core } => {
+                project.require_project()?;
+                match core {
+                    CoreCommands::Pwm {
+                        channels,
+                        resolution,
+                        name,
+                        force,
+                    } => coregen::generate_pwm(
+                        &project,
+                        channels,
+                        resolution,
+                        name.as_deref(),
+                        force,
+                    )?,
+                    CoreCommands::Gpio { width, name, force } => {
+                        coregen::generate_gpio(&project, width, name.as_deref(), force)?
+                    }
+                }
+            }
+            GenCommands::Bus => {
+                project.require_project()?;
+                fabric::generate(&project)?;
+            }
+        },
+
+        Commands::Add { command } => match command {
+            AddCommands::Ip { name, list, force } => match name {
+                Some(name) if !list => {
+                    project.require_project()?;
+                    ip::add(&project, &name, force)?;
+                }
+                _ => ip::list(),
+            },
+        },
+
+        Commands::Deps { command } => {
+            project.require_project()?;
+            match command {
+                DepsCommands::Fetch => deps::fetch(&project, &[], false)?,
+                DepsCommands::Update { names } => {
+                    deps::fetch(&project, &names, names.is_empty())?;
+                }
+            }
+        }
+
+        Commands::Softcore { flash, port } => {
+            project.require_project()?;
+            docker.ensure_image()?;
+            let port = config.serial_port(port);
+            softcore::rebuild(&docker, &project, flash.then_some(port.as_str()))?;
+        }
+
+        Commands::Bitstream { command } => match command {
+            BitstreamCommands::Info { file } => {
+                let file = match file {
+                    Some(file) => file,
+                    None => {
+                        project.require_project()?;
+                        project.root.as_ref().unwrap().join("fpga/top.bin")
+                    }
+                };
+                bitstream::info(&docker, &project, &file)?;
+            }
+            BitstreamCommands::Diff { a, b } => {
+                project.require_project()?;
+                bitstream::diff(&docker, &project, &a, &b)?;
+            }
+        },
+
+        Commands::Reg { command } => match command {
+            RegCommands::Read { register, port } => {
+                project.require_project()?;
+                let port = config.serial_port(port);
+                reg::read(&docker, &project, &register, &port)?;
+            }
+            RegCommands::Write {
+                register,
+                value,
+                port,
+            } => {
+                project.require_project()?;
+                let port = config.serial_port(port);
+                reg::write(&docker, &project, &register, &value, &port)?;
+            }
+        },
+
+        Commands::Bench { command } => match command {
+            BenchCommands::Spi { freqs, port } => {
+                project.require_project()?;
+                let port = config.serial_port(port);
+                bench::spi(&docker, &project, &freqs, &port)?;
+            }
+            BenchCommands::Build {
+                fpga_only,
+                history,
+                compare,
+            } => {
+                project.require_project()?;
+                if let Some(limit) = history {
+                    metrics::history(&project, limit)?;
+                } else if let [from, rest @ ..] = compare.as_slice() {
+                    metrics::compare(&project, from, rest.first().map(String::as_str))?;
+                } else {
+                    docker.ensure_image()?;
+                    metrics::run(&docker, &project, fpga_only)?;
+                }
+            }
+        },
+
+        Commands::Timing {
+            compare,
+            fail_on_regression,
+        } => {
+            project.require_project()?;
+            match compare {
+                Some(reference) => {
+                    timing::compare(&docker, &project, &reference, fail_on_regression)?
+                }
+                None => timing::show(&project)?,
+            }
+        }
+
+        Commands::Hwtest { port } => {
+            project.require_project()?;
+            let port = config.serial_port(port);
+            hwtest::run(&docker, &project, &port)?;
+        }
+
+        Commands::La { command } => match command {
+            LaCommands::Capture {
+                channels,
+                duration,
+                trigger,
+                output,
+                open,
+            } => {
+                project.require_project()?;
+                la::capture(
+                    &project,
+                    &channels,
+                    &duration,
+                    trigger.as_deref(),
+                    output,
+                    open,
+                )?;
+            }
+        },
+
+        Commands::Regmap { command } => match command {
+            RegmapCommands::Build { spec } => {
+                project.require_project()?;
+                regmap::build(&project, &spec)?;
+            }
+        },
+
+        Commands::Check => {
+            project.require_project()?;
+            docker.ensure_image()?;
+            watch::run_check(&docker, &project)?;
+        }
+
+        Commands::Fmt { check } => {
+            project.require_project()?;
+            docker.ensure_image()?;
+            fmt::run(&docker, &project, check)?;
+        }
+
+        Commands::Lint {
+            dir,
+            tool,
+            update_baseline,
+            format,
+            diff,
+            strict,
+            no_fail,
+        } => {
+            project.require_project()?;
+            docker.ensure_image()?;
+
+            let opts = lint::LintOptions {
+                fpga_dir: dir,
+                tool,
+                verbose: cli.verbose > 0,
+                update_baseline,
+                format,
+                diff,
+                strict,
+                no_fail,
+            };
+            lint::run_lint(&docker, &project, &opts)?;
+        }
+
+        Commands::Lsp { server, args } => {
+            project.require_project()?;
+            lsp::run(&docker, &project, server, &args)?;
         }
 
         Commands::Menuconfig => {
@@ -294,22 +1578,30 @@ fn main() -> Result<()> {
             )?;
         }
 
-        Commands::Clean { full } => {
-            project.require_project()?;
-            docker.ensure_image()?;
-
-            println!("{}", "==> Cleaning build artifacts".blue().bold());
-            docker.run_in_project(&project, &["make", "-C", "fpga", "clean"], &[], false)?;
-
-            let idf_cmd = if full { "fullclean" } else { "clean" };
-            let cmd = format!("cd firmware && idf.py {}", idf_cmd);
-            docker.run_in_project(&project, &["bash", "-c", &cmd], &[], false)?;
+        Commands::Clean {
+            fpga,
+            firmware,
+            cache,
+            all,
+            full,
+        } => {
+            let builds = !(fpga || firmware || cache);
+            let opts = clean::CleanOptions {
+                fpga: fpga || builds || all,
+                firmware: firmware || builds || all,
+                cache: cache || all,
+                full: full || all,
+            };
+            if opts.fpga || opts.firmware {
+                project.require_project()?;
+            }
+            clean::run(&docker, &project, &opts)?;
         }
 
         Commands::Shell { usb } => {
             docker.ensure_image()?;
 
-            println!("{}", "==> Opening shell in container".blue().bold());
+            outln!("{}", "==> Opening shell in container".blue().bold());
             if project.root.is_some() {
                 docker.run_in_project(&project, &["/bin/bash"], &[], usb)?;
             } else {
@@ -329,25 +1621,101 @@ fn main() -> Result<()> {
             }
         },
 
-        Commands::Watch { fpga_only } => {
+        Commands::Watch {
+            fpga_only,
+            flash,
+            test,
+            check,
+            no_notify,
+            port,
+        } => {
             project.require_project()?;
             docker.ensure_image()?;
 
-            watch::run_watch(&docker, &project, fpga_only)?;
+            if test {
+                let opts = test::TestOptions {
+                    fpga_dir: "fpga".to_string(),
+                    ..Default::default()
+                };
+                watch::run_test_watch(&docker, &project, &opts, !no_notify)?;
+            } else {
+                let opts = watch::WatchOptions {
+                    fpga_only,
+                    flash: flash.then(|| config.serial_port(port)),
+                    check,
+                    notify: !no_notify,
+                };
+                watch::run_watch(&docker, &project, &opts)?;
+            }
         }
 
+        Commands::Cache { command } => match command {
+            CacheCommands::Manifest => {
+                project.require_project()?;
+                ci_cache::manifest(&docker, &project, &image)?;
+            }
+            CacheCommands::Export { dir, include_image } => {
+                ci_cache::export(&docker, &project, &dir, include_image)?;
+            }
+            CacheCommands::Import { dir } => ci_cache::import(&docker, &project, &dir)?,
+        },
+
+        Commands::Ci { command } => match command {
+            CiCommands::Init { provider, force } => {
+                project.require_project()?;
+                ci::init(&project, &image, provider, force)?;
+            }
+        },
+
+        Commands::Ide { command } => match command {
+            IdeCommands::Init { editor, force } => {
+                project.require_project()?;
+                ide::init(&project, &image, editor, force)?;
+            }
+        },
+
+        Commands::Hooks { command } => match command {
+            HooksCommands::Install { force } => {
+                project.require_project()?;
+                hooks::install(&project, force)?;
+            }
+        },
+
         Commands::Demo {
+            command: Some(DemoCommands::Fetch { .. }),
+            ..
+        } => unreachable!("demo fetch is dispatched above"),
+        Commands::Demo {
+            command: None,
             name,
             port,
             build_only,
+            sim,
             list,
         } => {
             if list || name.is_none() {
-                demo::list_demos();
+                demo::list_demos()?;
             } else {
-                demo::run_demo(&docker, name.as_deref().unwrap(), &port, build_only, false)?;
+                let port = config.serial_port(port);
+                demo::run_demo(
+                    &docker,
+                    name.as_deref().unwrap(),
+                    &port,
+                    build_only,
+                    sim,
+                    false,
+                )?;
             }
         }
+
+        Commands::Doctor { .. }
+        | Commands::Setup
+        | Commands::SetupUdev { .. }
+        | Commands::SelfUpdate { .. } => {
+            unreachable!("host setup commands are dispatched above")
+        }
+        Commands::External(_) => unreachable!("plugins are dispatched above"),
+        Commands::Logs { .. } => unreachable!("logs is dispatched above"),
     }
 
     Ok(())
@@ -0,0 +1,342 @@
+use anyhow::{Context, Result};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use affogato_core::annotate::ReportFormat;
+use affogato_core::build::{self, build_fpga};
+use affogato_core::build_info::BuildInfo;
+use affogato_core::docker::Docker;
+use affogato_core::nextpnr::{self, Usage};
+use affogato_core::project::Project;
+use affogato_core::{cache, dry_run, outln, output};
+
+use crate::repro;
+
+/// One line per benchmarked build, relative to the project root
+const METRICS_FILE: &str = ".affogato/build-metrics.jsonl";
+
+/// The cells shown in tables; the rest are kept in the metrics file
+const SHOWN_CELLS: &[&str] = &["ICESTORM_LC", "ICESTORM_RAM"];
+
+/// What one benchmarked build took and produced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Record {
+    /// `git describe --always --dirty` of the tree that was built
+    commit: String,
+    /// When the build ran, in seconds since the epoch
+    recorded: u64,
+    fpga_secs: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    firmware_secs: Option<f64>,
+    #[serde(default)]
+    utilization: BTreeMap<String, Usage>,
+    #[serde(default)]
+    fmax_mhz: BTreeMap<String, f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bitstream_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    firmware_bytes: Option<u64>,
+}
+
+impl Record {
+    /// The slowest clock sets what the design can run at
+    fn fmax(&self) -> Option<f64> {
+        self.fmax_mhz.values().copied().reduce(f64::min)
+    }
+}
+
+/// Build everything from clean outputs with the build cache off, timing each
+/// half, then record the times, utilization and Fmax against the commit and
+/// compare them with the previous record
+pub fn run(docker: &Docker, project: &Project, fpga_only: bool) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    cache::set_enabled(false);
+
+    outln!("{}", "==> Cleaning build outputs".blue().bold());
+    docker.run_in_project(
+        project,
+        &["bash", "-c", &format!("rm -rf {}", repro::OUTPUTS)],
+        &[],
+        false,
+    )?;
+
+    outln!("{}", "==> Building FPGA bitstream".blue().bold());
+    let start = Instant::now();
+    build_fpga(docker, project, &[], ReportFormat::Text)?;
+    let fpga = start.elapsed();
+    let firmware = if fpga_only {
+        None
+    } else {
+        outln!("{}", "==> Building ESP32 firmware".blue().bold());
+        let start = Instant::now();
+        build::build_firmware(docker, project, &[], ReportFormat::Text)?;
+        Some(start.elapsed())
+    };
+    if dry_run::is_enabled() {
+        return Ok(());
+    }
+
+    let report = nextpnr::Report::load(project_root).unwrap_or_default();
+    let size = |path: &str| fs::metadata(project_root.join(path)).ok().map(|m| m.len());
+    let record = Record {
+        commit: BuildInfo::current(project_root).describe,
+        recorded: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs()),
+        fpga_secs: seconds(fpga),
+        firmware_secs: firmware.map(seconds),
+        utilization: report.utilization,
+        fmax_mhz: report.fmax_mhz,
+        bitstream_bytes: size("fpga/top.bin"),
+        firmware_bytes: firmware
+            .and_then(|_| build::firmware_image(project_root))
+            .and_then(|image| size(&image)),
+    };
+    let previous = load(project_root)?.pop();
+    append(project_root, &record)?;
+
+    outln!();
+    outln!(
+        "{}",
+        format!("==> Build metrics for {}", record.commit)
+            .blue()
+            .bold()
+    );
+    print_comparison(previous.as_ref(), &record);
+    outln!("Recorded in {}", METRICS_FILE.bold());
+    output::report("build_metrics", &record);
+    Ok(())
+}
+
+/// Print the last `limit` records, oldest first
+pub fn history(project: &Project, limit: usize) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    let records = load(project_root)?;
+    if records.is_empty() {
+        outln!(
+            "{}",
+            "No builds recorded yet; run affogato bench build".yellow()
+        );
+        return Ok(());
+    }
+    let shown = &records[records.len().saturating_sub(limit)..];
+
+    outln!(
+        "{}",
+        format!(
+            "{:<24} {:>9} {:>9} {:>14} {:>14} {:>10}",
+            "Commit", "FPGA", "Firmware", "LCs", "RAM", "Fmax"
+        )
+        .bold()
+    );
+    let mut previous: Option<&Record> = None;
+    for record in shown {
+        let usage = |cell: &str| {
+            record
+                .utilization
+                .get(cell)
+                .map_or("-".to_string(), |usage| {
+                    format!("{} ({:.0}%)", usage.used, usage.percent())
+                })
+        };
+        let trend = match (previous.and_then(Record::fmax), record.fmax()) {
+            (Some(before), Some(now)) if now > before => " ↑".green().to_string(),
+            (Some(before), Some(now)) if now < before => " ↓".red().to_string(),
+            _ => "  ".to_string(),
+        };
+        outln!(
+            "{:<24} {:>9} {:>9} {:>14} {:>14} {:>10}{}",
+            record.commit,
+            format!("{:.1}s", record.fpga_secs),
+            record
+                .firmware_secs
+                .map_or("-".to_string(), |secs| format!("{:.1}s", secs)),
+            usage(SHOWN_CELLS[0]),
+            usage(SHOWN_CELLS[1]),
+            record
+                .fmax()
+                .map_or("-".to_string(), |mhz| format!("{:.2} MHz", mhz)),
+            trend
+        );
+        previous = Some(record);
+    }
+    output::report("build_metrics", shown);
+    Ok(())
+}
+
+/// Compare the latest records of two commits; `to` defaults to the latest
+/// record of all
+pub fn compare(project: &Project, from: &str, to: Option<&str>) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    let records = load(project_root)?;
+    let find = |commit: &str| {
+        records
+            .iter()
+            .rev()
+            .find(|record| record.commit == commit || record.commit.contains(commit))
+            .with_context(|| {
+                format!(
+                    "No build recorded for {}; run affogato bench build on it first",
+                    commit
+                )
+            })
+    };
+    let before = find(from)?;
+    let after = match to {
+        Some(to) => find(to)?,
+        None => records.last().context("No builds recorded yet")?,
+    };
+    outln!(
+        "{}",
+        format!("==> {} compared with {}", after.commit, before.commit)
+            .blue()
+            .bold()
+    );
+    print_comparison(Some(before), after);
+    output::report(
+        "build_metrics",
+        serde_json::json!({ "from": before, "to": after }),
+    );
+    Ok(())
+}
+
+/// Print a record's figures, with the change from `before` when given
+fn print_comparison(before: Option<&Record>, record: &Record) {
+    let row = |label: &str, value: String, change: Option<String>| {
+        outln!(
+            "  {:<22} {:>16}  {}",
+            label,
+            value,
+            change.unwrap_or_default()
+        );
+    };
+    let secs = |now: f64, then: Option<f64>| {
+        then.map(|then| delta(now - then, |d| format!("{:+.1}s", d), false))
+    };
+
+    row(
+        "FPGA build",
+        format!("{:.1}s", record.fpga_secs),
+        secs(record.fpga_secs, before.map(|b| b.fpga_secs)),
+    );
+    if let Some(firmware) = record.firmware_secs {
+        row(
+            "Firmware build",
+            format!("{:.1}s", firmware),
+            secs(firmware, before.and_then(|b| b.firmware_secs)),
+        );
+    }
+    for (cell, usage) in &record.utilization {
+        if usage.used == 0 && !SHOWN_CELLS.contains(&cell.as_str()) {
+            continue;
+        }
+        row(
+            cell,
+            format!("{}/{} ({:.0}%)", usage.used, usage.total, usage.percent()),
+            before.and_then(|b| b.utilization.get(cell)).map(|then| {
+                delta(
+                    usage.used as f64 - then.used as f64,
+                    |d| format!("{:+}", d),
+                    false,
+                )
+            }),
+        );
+    }
+    for (clock, mhz) in &record.fmax_mhz {
+        row(
+            &format!("Fmax {}", clock),
+            format!("{:.2} MHz", mhz),
+            before
+                .and_then(|b| b.fmax_mhz.get(clock))
+                .map(|then| delta(mhz - then, |d| format!("{:+.2} MHz", d), true)),
+        );
+    }
+    for (label, now, then) in [
+        (
+            "Bitstream",
+            record.bitstream_bytes,
+            before.and_then(|b| b.bitstream_bytes),
+        ),
+        (
+            "Firmware image",
+            record.firmware_bytes,
+            before.and_then(|b| b.firmware_bytes),
+        ),
+    ] {
+        if let Some(now) = now {
+            row(
+                label,
+                format!("{} bytes", now),
+                then.map(|then| delta(now as f64 - then as f64, |d| format!("{:+}", d), false)),
+            );
+        }
+    }
+    if let Some(before) = before {
+        outln!(
+            "{}",
+            format!("  (changes since {})", before.commit).dimmed()
+        );
+    }
+}
+
+/// A change, green when it's an improvement and red when it's a regression
+pub fn delta(change: f64, format: impl Fn(f64) -> String, higher_is_better: bool) -> String {
+    let text = format(change);
+    if change.abs() < 1e-9 {
+        text.dimmed().to_string()
+    } else if (change > 0.0) == higher_is_better {
+        text.green().to_string()
+    } else {
+        text.red().to_string()
+    }
+}
+
+fn seconds(duration: Duration) -> f64 {
+    (duration.as_secs_f64() * 10.0).round() / 10.0
+}
+
+/// Every record, oldest first
+fn load(project_root: &Path) -> Result<Vec<Record>> {
+    let path = project_root.join(METRICS_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let text =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("Invalid record on line {} of {}", i + 1, METRICS_FILE))
+        })
+        .collect()
+}
+
+fn append(project_root: &Path, record: &Record) -> Result<()> {
+    let path = project_root.join(METRICS_FILE);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(record)?)?;
+    Ok(())
+}
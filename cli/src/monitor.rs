@@ -0,0 +1,67 @@
+use anyhow::{Context, Result};
+use colored::Colorize;
+use std::path::Path;
+
+use affogato_core::artifacts;
+use affogato_core::docker::Docker;
+use affogato_core::outln;
+use affogato_core::project::Project;
+
+/// Where an ELF from outside the project is mounted in the container
+const ELF_MOUNT: &str = "/elf";
+
+/// Open the serial monitor, after flashing with `flash`. Backtraces are
+/// decoded against `elf` if given, e.g. the ELF of a release the board
+/// runs; otherwise against the last firmware build's.
+pub fn run(
+    docker: &Docker,
+    project: &Project,
+    port: &str,
+    flash: bool,
+    elf: Option<&Path>,
+) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    let Some(elf) = elf else {
+        outln!("{}", "Ctrl+] to exit".yellow());
+        let flash = if flash { "flash " } else { "" };
+        let cmd = format!("cd firmware && idf.py -p {} {}monitor", port, flash);
+        return docker.run_in_project(project, &["bash", "-c", &cmd], &[], true);
+    };
+    let flash = if flash {
+        format!("idf.py -p {} flash && ", port)
+    } else {
+        String::new()
+    };
+
+    let (elf, artifacts) = artifacts::resolve_elf(project_root, Some(elf))?;
+    let elf = elf.canonicalize()?;
+    let mut mounts = Vec::new();
+    let container_elf = match elf.strip_prefix(project_root.canonicalize()?) {
+        Ok(relative) => format!(
+            "/workspace/{}",
+            relative.to_string_lossy().replace('\\', "/")
+        ),
+        Err(_) => {
+            let dir = elf.parent().context("ELF has no parent directory")?;
+            mounts.push("-v".to_string());
+            mounts.push(format!("{}:{}:ro", dir.display(), ELF_MOUNT));
+            format!(
+                "{}/{}",
+                ELF_MOUNT,
+                elf.file_name().unwrap_or_default().to_string_lossy()
+            )
+        }
+    };
+
+    outln!("Decoding backtraces with {}", elf.display());
+    outln!("{}", "Ctrl+] to exit".yellow());
+    let cmd = format!(
+        "cd firmware && {}python -m esp_idf_monitor -p {} --target {} --toolchain-prefix {} {}",
+        flash, port, artifacts.target, artifacts.toolchain_prefix, container_elf
+    );
+    let mounts: Vec<&str> = mounts.iter().map(String::as_str).collect();
+    docker.run_in_project_with_extra_mounts(project, &["bash", "-c", &cmd], &mounts, true)
+}
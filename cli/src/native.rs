@@ -0,0 +1,88 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use affogato_core::artifacts::{self, Artifacts};
+use affogato_core::dry_run;
+use affogato_core::outln;
+use affogato_core::project::Project;
+
+/// esptool's list of what to write where, written by every firmware build
+const FLASH_ARGS: &str = "firmware/build/flash_args";
+
+/// Flash the last firmware build over a COM port from the host. Docker on
+/// Windows can't hand serial ports to containers, so this uses esptool from
+/// the host's Python instead of idf.py in the image.
+pub fn flash(project: &Project, port: &str) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    if !dry_run::is_enabled() && !project_root.join(FLASH_ARGS).is_file() {
+        bail!("{} not found; run `affogato build` first", FLASH_ARGS);
+    }
+    // esptool can probe the chip when no build has been recorded
+    let target = Artifacts::load(project_root)
+        .ok()
+        .flatten()
+        .map_or_else(|| "auto".to_string(), |artifacts| artifacts.target);
+
+    let mut command = python()?;
+    command
+        .args([
+            "-m", "esptool", "--chip", &target, "-p", port, "-b", "460800",
+        ])
+        .args(["--before", "default_reset", "--after", "hard_reset"])
+        .args(["write_flash", "@flash_args"])
+        .current_dir(project_root.join("firmware/build"));
+    run(command, "esptool")
+}
+
+/// Open the serial monitor on a COM port from the host, decoding backtraces
+/// against `elf` or the last build's ELF
+pub fn monitor(project: &Project, port: &str, elf: Option<&Path>) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    let (elf, artifacts) = artifacts::resolve_elf(project_root, elf)?;
+
+    outln!("{}", "Ctrl+] to exit".yellow());
+    let mut command = python()?;
+    command
+        .args(["-m", "esp_idf_monitor", "-p", port])
+        .args(["--target", &artifacts.target])
+        .args(["--toolchain-prefix", &artifacts.toolchain_prefix])
+        .arg(&elf);
+    run(command, "esp-idf-monitor")
+}
+
+/// The host's Python, which needs esptool and esp-idf-monitor installed
+fn python() -> Result<Command> {
+    let python: PathBuf = ["python", "python3", "py"]
+        .iter()
+        .find_map(|name| which::which(name).ok())
+        .context(
+            "Python not found on PATH. COM ports are used from the host: install Python, \
+             then `pip install esptool esp-idf-monitor`",
+        )?;
+    Ok(Command::new(python))
+}
+
+fn run(mut command: Command, tool: &str) -> Result<()> {
+    if dry_run::is_enabled() {
+        dry_run::note(&format!("{:?}", command));
+        return Ok(());
+    }
+    let status = command
+        .status()
+        .with_context(|| format!("Failed to run {}", tool))?;
+    if !status.success() {
+        bail!(
+            "{} failed; is it installed? (`pip install esptool esp-idf-monitor`)",
+            tool
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,367 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::SystemTime;
+
+use affogato_core::docker::Docker;
+use affogato_core::dry_run;
+use affogato_core::outln;
+use affogato_core::output;
+use affogato_core::project::Project;
+
+use crate::signing::{self, Signer};
+
+/// Name of the manifest written into every bundle
+pub const MANIFEST: &str = "manifest.json";
+
+/// Extra images to build into the bundle
+pub struct Formats {
+    /// Every flashed partition merged into one image written at offset 0
+    pub merged: bool,
+    /// The merged image as UF2, for boards with a UF2 bootloader
+    pub uf2: bool,
+    /// The image as DFU, for flashing over the S2's native USB
+    pub dfu: bool,
+}
+
+/// A file copied into the bundle
+struct Artifact {
+    /// Where it was built, relative to the project root
+    source: PathBuf,
+    /// Its name inside the bundle
+    name: String,
+}
+
+/// Collect the bitstream, firmware binaries, ELF, map file and sdkconfig with
+/// a manifest of versions and checksums into `<output>/<project>-<version>.tar.gz`,
+/// adding the extra `formats` asked for. With a `sign` key, the manifest and
+/// the app image (what OTA updates deliver) are signed.
+pub fn run(
+    docker: &Docker,
+    project: &Project,
+    output_dir: &Path,
+    image: &str,
+    formats: &Formats,
+    sign: Option<&Path>,
+) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    let build = Build::load(project_root)?;
+    let bundle = format!("{}-{}", build.name, build.version.replace('/', "-"));
+    let signer = sign.map(Signer::load).transpose()?;
+    let app_image = bundle_name(Path::new(&build.app_bin));
+
+    outln!("{}", format!("==> Packaging {}", bundle).blue().bold());
+    let mut artifacts = build.artifacts(project_root)?;
+    let mut exports = serde_json::Map::new();
+    if formats.merged {
+        let artifact = merge(docker, project, &build, &bundle)?;
+        exports.insert(
+            "merged".into(),
+            json!({ "file": artifact.name, "offset": "0x0" }),
+        );
+        artifacts.push(artifact);
+    }
+    for (format, wanted) in [("uf2", formats.uf2), ("dfu", formats.dfu)] {
+        if wanted {
+            let artifact = export(docker, project, format, &bundle)?;
+            exports.insert(format.into(), json!({ "file": artifact.name }));
+            artifacts.push(artifact);
+        }
+    }
+
+    let mut files = Vec::new();
+    for artifact in &artifacts {
+        let data = match fs::read(project_root.join(&artifact.source)) {
+            // Extra images are only written by the docker commands a dry run prints
+            Err(_) if dry_run::is_enabled() => continue,
+            data => {
+                data.with_context(|| format!("Failed to read {}", artifact.source.display()))?
+            }
+        };
+        outln!(
+            "  {:<24} {:>9} bytes  {}",
+            artifact.name,
+            data.len(),
+            artifact.source.display().to_string().dimmed()
+        );
+        files.push(json!({
+            "file": artifact.name,
+            "size_bytes": data.len(),
+            "sha256": sha256(&data),
+        }));
+    }
+
+    let manifest = json!({
+        "name": build.name,
+        "version": build.version,
+        "created": humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        "git": git_info(project_root),
+        "target": build.target,
+        "tools": {
+            "affogato": env!("CARGO_PKG_VERSION"),
+            "esp_idf": build.idf_version,
+            "image": image,
+        },
+        "flash": build.flash_files(),
+        "flash_settings": build.flasher_args.get("flash_settings").cloned().unwrap_or(Value::Null),
+        "exports": exports,
+        "files": files,
+        "signing": signer.as_ref().map(|signer| json!({
+            "algorithm": signer.algorithm.name(),
+            "key_sha256": signer.key_id(),
+            "signed": [MANIFEST, app_image],
+        })),
+    });
+
+    let output_dir = project_root.join(output_dir);
+    let staging = output_dir.join(&bundle);
+    let archive = output_dir.join(format!("{}.tar.gz", bundle));
+    if staging.exists() {
+        dry_run::remove_dir_all(&staging)?;
+    }
+    dry_run::create_dir_all(&staging)?;
+    for artifact in &artifacts {
+        dry_run::copy(
+            project_root.join(&artifact.source),
+            staging.join(&artifact.name),
+        )?;
+    }
+    dry_run::write(
+        staging.join(MANIFEST),
+        serde_json::to_string_pretty(&manifest)? + "\n",
+    )?;
+    if let Some(signer) = &signer {
+        dry_run::write(staging.join(signing::PUBLIC_KEY_FILE), &signer.public_key)?;
+        for file in [MANIFEST, app_image.as_str()] {
+            if dry_run::is_enabled() {
+                dry_run::note(&format!("sign {} with {}", file, signer.algorithm.name()));
+            } else {
+                signer.sign(&staging.join(file))?;
+            }
+        }
+        outln!(
+            "  Signed {} and {} ({})",
+            MANIFEST,
+            app_image,
+            signer.algorithm.name()
+        );
+    }
+    create_archive(&output_dir, &bundle, &archive)?;
+    dry_run::remove_dir_all(&staging)?;
+
+    output::report("package", json!({ "path": archive, "manifest": manifest }));
+    outln!("{}", format!("Wrote {}", archive.display()).green().bold());
+    Ok(())
+}
+
+/// Merge everything `idf.py flash` writes (bootloader, partition table, app and
+/// any NVS, filesystem or bitstream partition images) into one factory image
+fn merge(docker: &Docker, project: &Project, build: &Build, bundle: &str) -> Result<Artifact> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    // Written by ESP-IDF next to flasher_args.json, in esptool's argument-file form
+    if !project_root.join("firmware/build/flash_args").exists() {
+        bail!("firmware/build/flash_args not found; run affogato build first");
+    }
+    let file = format!("{}-merged.bin", bundle);
+    let chip = build.target.as_deref().unwrap_or("esp32s2");
+
+    outln!("{}", "==> Merging flash images".blue().bold());
+    docker.ensure_image()?;
+    let cmd = format!(
+        "cd firmware/build && esptool.py --chip {} merge_bin -o {} @flash_args",
+        chip, file
+    );
+    docker.run_in_project(project, &["bash", "-c", &cmd], &[], false)?;
+    Ok(Artifact::new(Path::new("firmware/build").join(file)))
+}
+
+/// Convert the firmware to UF2 or DFU with idf.py, which writes
+/// `firmware/build/<format>.bin`; it is bundled as `<bundle>.<format>`
+fn export(docker: &Docker, project: &Project, format: &str, bundle: &str) -> Result<Artifact> {
+    outln!(
+        "{}",
+        format!("==> Exporting {}", format.to_uppercase())
+            .blue()
+            .bold()
+    );
+    docker.ensure_image()?;
+    let cmd = format!("cd firmware && idf.py {}", format);
+    docker.run_in_project(project, &["bash", "-c", &cmd], &[], false)?;
+    Ok(Artifact {
+        source: Path::new("firmware/build").join(format!("{}.bin", format)),
+        name: format!("{}.{}", bundle, format),
+    })
+}
+
+/// What ESP-IDF recorded about the last firmware build
+struct Build {
+    name: String,
+    version: String,
+    target: Option<String>,
+    idf_version: Option<String>,
+    app_bin: String,
+    app_elf: String,
+    flasher_args: Value,
+}
+
+impl Build {
+    fn load(project_root: &Path) -> Result<Self> {
+        let build_dir = project_root.join("firmware/build");
+        let description = read_json(&build_dir.join("project_description.json"))?;
+        let flasher_args = read_json(&build_dir.join("flasher_args.json"))?;
+        let field = |key: &str| description[key].as_str().map(str::to_string);
+
+        let name = field("project_name").context("project_description.json has no project_name")?;
+        Ok(Self {
+            version: field("project_version").unwrap_or_else(|| "0".to_string()),
+            target: field("target"),
+            idf_version: field("git_revision"),
+            app_bin: field("app_bin").unwrap_or_else(|| format!("{}.bin", name)),
+            app_elf: field("app_elf").unwrap_or_else(|| format!("{}.elf", name)),
+            name,
+            flasher_args,
+        })
+    }
+
+    /// The files to bundle; the bitstream and application are required
+    fn artifacts(&self, project_root: &Path) -> Result<Vec<Artifact>> {
+        let build = Path::new("firmware/build");
+        let mut artifacts = vec![
+            Artifact::new("fpga/top.bin"),
+            Artifact::new(build.join(&self.app_bin)),
+            Artifact::new(build.join(&self.app_elf)),
+        ];
+        for artifact in &artifacts {
+            if !project_root.join(&artifact.source).exists() {
+                bail!(
+                    "{} not found; run affogato build first",
+                    artifact.source.display()
+                );
+            }
+        }
+
+        let mut optional: Vec<PathBuf> = self
+            .flash_entries()
+            .into_iter()
+            .map(|(_, file)| build.join(file))
+            .collect();
+        optional.push(build.join(format!("{}.map", self.name)));
+        optional.push(build.join("flasher_args.json"));
+        optional.push("firmware/sdkconfig".into());
+        for source in optional {
+            if artifacts.iter().any(|a| a.source == source) {
+                continue;
+            }
+            if project_root.join(&source).exists() {
+                artifacts.push(Artifact::new(source));
+            } else {
+                outln!(
+                    "{}",
+                    format!("  {} not found; leaving it out", source.display()).yellow()
+                );
+            }
+        }
+        Ok(artifacts)
+    }
+
+    /// `(offset, file)` pairs from flasher_args.json, relative to the build directory
+    fn flash_entries(&self) -> Vec<(String, String)> {
+        self.flasher_args["flash_files"]
+            .as_object()
+            .map(|files| {
+                files
+                    .iter()
+                    .filter_map(|(offset, file)| Some((offset.clone(), file.as_str()?.to_string())))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Flash offsets of the bundled images, by their names in the bundle
+    fn flash_files(&self) -> Value {
+        self.flash_entries()
+            .into_iter()
+            .map(|(offset, file)| (offset, Value::from(bundle_name(Path::new(&file)))))
+            .collect::<serde_json::Map<_, _>>()
+            .into()
+    }
+}
+
+impl Artifact {
+    fn new(source: impl Into<PathBuf>) -> Self {
+        let source = source.into();
+        Self {
+            name: bundle_name(&source),
+            source,
+        }
+    }
+}
+
+/// Files are flattened into the bundle under their file names
+fn bundle_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+fn read_json(path: &Path) -> Result<Value> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("{} not found; run affogato build first", path.display()))?;
+    serde_json::from_str(&text).with_context(|| format!("Invalid {}", path.display()))
+}
+
+pub fn sha256(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// The commit the bundle was built from, and whether the tree had changes
+fn git_info(project_root: &Path) -> Value {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .current_dir(project_root)
+            .args(args)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+    match git(&["rev-parse", "HEAD"]) {
+        Some(commit) => json!({
+            "commit": commit,
+            "dirty": git(&["status", "--porcelain"]).is_some_and(|status| !status.is_empty()),
+        }),
+        None => Value::Null,
+    }
+}
+
+fn create_archive(output_dir: &Path, bundle: &str, archive: &Path) -> Result<()> {
+    if dry_run::is_enabled() {
+        dry_run::note(&format!("tar czf {} {}", archive.display(), bundle));
+        return Ok(());
+    }
+    let status = Command::new("tar")
+        .arg("czf")
+        .arg(archive)
+        .arg("-C")
+        .arg(output_dir)
+        .arg(bundle)
+        .status()
+        .context("Failed to run tar")?;
+    if !status.success() {
+        bail!("Failed to create {}", archive.display());
+    }
+    Ok(())
+}
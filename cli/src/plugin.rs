@@ -0,0 +1,48 @@
+use anyhow::{bail, Context, Result};
+use std::process::Command;
+
+use affogato_core::output;
+use affogato_core::project::Project;
+
+/// Run `affogato-<name>` from PATH for an unknown subcommand, like cargo does.
+///
+/// The plugin gets the remaining arguments and, in its environment:
+/// - `AFFOGATO`: path of this executable, to call back into affogato
+/// - `AFFOGATO_IMAGE`: the docker image affogato would use
+/// - `AFFOGATO_PROJECT_ROOT`: the project root, if in a project
+/// - `AFFOGATO_CONFIG`: the resolved affogato.toml as JSON, if in a project
+pub fn run(args: &[String], project: &Project, image: &str) -> Result<()> {
+    let (name, rest) = args.split_first().context("No subcommand given")?;
+    let program = format!("affogato-{}", name);
+    let Ok(path) = which::which(&program) else {
+        bail!(
+            "Unknown command: {} (no {} found on PATH; see affogato --help)",
+            name,
+            program
+        );
+    };
+
+    let mut command = Command::new(&path);
+    command.args(rest).env("AFFOGATO_IMAGE", image);
+    if let Ok(exe) = std::env::current_exe() {
+        command.env("AFFOGATO", exe);
+    }
+    if let Some(root) = &project.root {
+        command.env("AFFOGATO_PROJECT_ROOT", root);
+    }
+    if let Some(config) = &project.config {
+        command.env("AFFOGATO_CONFIG", serde_json::to_string(config)?);
+    }
+    if !output::color_enabled() {
+        command.env("NO_COLOR", "1");
+    }
+
+    let status = command
+        .status()
+        .with_context(|| format!("Failed to run {}", path.display()))?;
+    if !status.success() {
+        // Pass the plugin's own exit code through to the caller
+        std::process::exit(status.code().unwrap_or(1));
+    }
+    Ok(())
+}
@@ -0,0 +1,144 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use serde_json::json;
+
+use affogato_core::debug_console::{MAX_ADDRESS, REG_PREFIX};
+use affogato_core::docker::Docker;
+use affogato_core::project::Project;
+use affogato_core::{device, dry_run, outln, output};
+
+use crate::regmap;
+
+/// Read an FPGA register through the firmware's debug console
+pub fn read(docker: &Docker, project: &Project, register: &str, port: &str) -> Result<()> {
+    let (address, name) = resolve(project, register, false)?;
+    let Some(value) = access(
+        docker,
+        project,
+        port,
+        &format!("reg read 0x{:04x}", address),
+    )?
+    else {
+        return Ok(());
+    };
+    outln!(
+        "{}  0x{:04x}  ({})",
+        name.unwrap_or_else(|| format!("0x{:04x}", address)).bold(),
+        value,
+        value
+    );
+    output::report("reg", json!({ "address": address, "value": value }));
+    Ok(())
+}
+
+/// Write an FPGA register through the firmware's debug console
+pub fn write(
+    docker: &Docker,
+    project: &Project,
+    register: &str,
+    value: &str,
+    port: &str,
+) -> Result<()> {
+    let (address, name) = resolve(project, register, true)?;
+    let value = parse_number(value)
+        .filter(|value| *value <= 0xFFFF)
+        .with_context(|| format!("Invalid 16-bit value: {}", value))?;
+    let command = format!("reg write 0x{:04x} 0x{:04x}", address, value);
+    if access(docker, project, port, &command)?.is_none() {
+        return Ok(());
+    }
+    outln!(
+        "Wrote 0x{:04x} to {}",
+        value,
+        name.unwrap_or_else(|| format!("0x{:04x}", address)).bold()
+    );
+    output::report("reg", json!({ "address": address, "value": value }));
+    Ok(())
+}
+
+/// The address of `register`, a number or a name from regmap.toml, and the
+/// register's name if it has one
+fn resolve(project: &Project, register: &str, write: bool) -> Result<(u32, Option<String>)> {
+    if let Some(address) = parse_number(register) {
+        if address > MAX_ADDRESS {
+            bail!(
+                "Address 0x{:X} is past 0x{:X}, the last spi_bridge register",
+                address,
+                MAX_ADDRESS
+            );
+        }
+        return Ok((address, None));
+    }
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    let (address, access) = regmap::lookup(project_root, register)?.with_context(|| {
+        format!(
+            "{} is neither an address nor a register in {}",
+            register,
+            regmap::DEFAULT_SPEC
+        )
+    })?;
+    if write && access == "RO" {
+        bail!("{} is read-only", register.to_uppercase());
+    }
+    Ok((address, Some(register.to_uppercase())))
+}
+
+/// Run a `reg` command on the board, returning the value it reports
+fn access(docker: &Docker, project: &Project, port: &str, command: &str) -> Result<Option<u32>> {
+    warn_if_console_off(project);
+    docker.ensure_image()?;
+    let reply = device::query(
+        docker,
+        project,
+        port,
+        command,
+        REG_PREFIX,
+        device::REPLY_TIMEOUT,
+    )?;
+    if dry_run::is_enabled() {
+        return Ok(None);
+    }
+    let field = |key: &str| {
+        reply
+            .split_whitespace()
+            .find_map(|field| field.strip_prefix(key)?.strip_prefix('='))
+    };
+    if let Some(error) = field("error") {
+        match error {
+            "ESP_ERR_INVALID_STATE" => bail!(
+                "The firmware has no register bus; pass its SPI device to affogato_console_start()"
+            ),
+            _ => bail!("The firmware failed to access the register: {}", error),
+        }
+    }
+    field("value")
+        .and_then(parse_number)
+        .map(Some)
+        .with_context(|| format!("Unexpected reply from the board: {}", reply))
+}
+
+/// Point out that the board has no console to answer when the project turns it off
+pub fn warn_if_console_off(project: &Project) {
+    if project
+        .config
+        .as_ref()
+        .is_some_and(|config| !config.firmware.debug_console)
+    {
+        outln!(
+            "{}",
+            "The debug console is off: set debug_console = true under [firmware], then build and flash"
+                .yellow()
+        );
+    }
+}
+
+/// A decimal or 0x-prefixed hex number
+fn parse_number(text: &str) -> Option<u32> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
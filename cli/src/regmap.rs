@@ -0,0 +1,780 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::coregen::{addr_bits, check_name, BUS_PORTS};
+use affogato_core::dry_run;
+use affogato_core::ip;
+use affogato_core::outln;
+use affogato_core::project::Project;
+
+/// Registers are 16-bit words, the data width of `spi_bridge`
+const DATA_WIDTH: u32 = 16;
+
+/// Largest offset addressable by the 15-bit address of `spi_bridge`
+const MAX_OFFSET: u32 = 0x7FFF;
+
+/// A register map description (regmap.toml)
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegMapSpec {
+    /// Module, header and document name
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    bus: Bus,
+    #[serde(default, rename = "register")]
+    registers: Vec<RegisterSpec>,
+}
+
+/// How the register block is accessed
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Bus {
+    /// SPI pins, through an `spi_bridge` instance
+    #[default]
+    Spi,
+    /// The register interface of generated cores, for use behind a bus fabric
+    Simple,
+}
+
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Access {
+    #[default]
+    Rw,
+    Ro,
+    Wo,
+}
+
+impl Access {
+    fn label(self) -> &'static str {
+        match self {
+            Access::Rw => "RW",
+            Access::Ro => "RO",
+            Access::Wo => "WO",
+        }
+    }
+
+    fn writable(self) -> bool {
+        self != Access::Ro
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegisterSpec {
+    name: String,
+    /// Word offset (default: the word after the previous register)
+    offset: Option<u32>,
+    #[serde(default)]
+    access: Access,
+    #[serde(default)]
+    reset: u32,
+    /// Pulse o_<name>_wr for one clock when the register is written
+    #[serde(default)]
+    strobe: bool,
+    #[serde(default)]
+    description: String,
+    #[serde(default, rename = "field")]
+    fields: Vec<FieldSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FieldSpec {
+    name: String,
+    /// Bit range, "7:4" or a single bit "3"
+    bits: String,
+    #[serde(default)]
+    description: String,
+}
+
+/// A validated register with its offset resolved
+struct Register {
+    name: String,
+    offset: u32,
+    access: Access,
+    reset: u32,
+    strobe: bool,
+    description: String,
+    fields: Vec<Field>,
+}
+
+struct Field {
+    name: String,
+    msb: u32,
+    lsb: u32,
+    description: String,
+}
+
+impl Field {
+    fn width(&self) -> u32 {
+        self.msb - self.lsb + 1
+    }
+
+    fn mask(&self) -> u32 {
+        ((1u32 << self.width()) - 1) << self.lsb
+    }
+
+    fn bits(&self) -> String {
+        if self.msb == self.lsb {
+            self.lsb.to_string()
+        } else {
+            format!("{}:{}", self.msb, self.lsb)
+        }
+    }
+}
+
+impl Register {
+    /// Bits that hold data: the fields, or the whole word if there are none
+    fn mask(&self) -> u32 {
+        if self.fields.is_empty() {
+            (1 << DATA_WIDTH) - 1
+        } else {
+            self.fields.iter().map(Field::mask).fold(0, |a, b| a | b)
+        }
+    }
+}
+
+/// Generate the Verilog register block, C header and register reference from a spec
+pub fn build(project: &Project, spec: &str) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+
+    let spec_path = if Path::new(spec).exists() {
+        PathBuf::from(spec)
+    } else {
+        project_root.join(spec)
+    };
+    let content = fs::read_to_string(&spec_path)
+        .with_context(|| format!("Failed to read {}", spec_path.display()))?;
+    let map: RegMapSpec = toml::from_str(&content)
+        .with_context(|| format!("Failed to parse {}", spec_path.display()))?;
+
+    check_name(&map.name)?;
+    let registers = resolve(&map.registers)?;
+    let source = spec_path
+        .strip_prefix(project_root)
+        .unwrap_or(&spec_path)
+        .display()
+        .to_string();
+
+    outln!(
+        "{}",
+        format!("==> Building register map {}", map.name)
+            .blue()
+            .bold()
+    );
+
+    let files = [
+        (
+            format!("fpga/rtl/{}.v", map.name),
+            verilog(&map, &registers, &source),
+        ),
+        (
+            format!("firmware/main/{}.h", map.name),
+            header(&map, &registers, &source),
+        ),
+        (
+            format!("docs/{}.md", map.name),
+            reference(&map, &registers, &source),
+        ),
+    ];
+    for (path, content) in &files {
+        let full = project_root.join(path);
+        if let Some(parent) = full.parent() {
+            dry_run::create_dir_all(parent)?;
+        }
+        dry_run::write(&full, content)?;
+        outln!("  {} {}", "Generated".green(), path);
+    }
+
+    if map.bus == Bus::Spi {
+        ip::ensure(project_root, "spi_bridge")?;
+    }
+
+    Ok(())
+}
+
+/// Default register map description, relative to the project root
+pub const DEFAULT_SPEC: &str = "regmap.toml";
+
+/// The offset and access ("RW", "RO" or "WO") of the register called `name`
+/// in the project's regmap.toml, matched case-insensitively
+pub fn lookup(project_root: &Path, name: &str) -> Result<Option<(u32, &'static str)>> {
+    Ok(project_registers(project_root)?
+        .iter()
+        .find(|reg| reg.name.eq_ignore_ascii_case(name))
+        .map(|reg| (reg.offset, reg.access.label())))
+}
+
+/// Names of the registers in the project's regmap.toml, if it has one
+pub fn names(project_root: &Path) -> Result<Vec<String>> {
+    Ok(project_registers(project_root)?
+        .into_iter()
+        .map(|reg| reg.name)
+        .collect())
+}
+
+fn project_registers(project_root: &Path) -> Result<Vec<Register>> {
+    let spec_path = project_root.join(DEFAULT_SPEC);
+    if !spec_path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(&spec_path)?;
+    let map: RegMapSpec = toml::from_str(&content)
+        .with_context(|| format!("Failed to parse {}", spec_path.display()))?;
+    resolve(&map.registers)
+}
+
+/// Validate the registers and assign offsets
+fn resolve(specs: &[RegisterSpec]) -> Result<Vec<Register>> {
+    if specs.is_empty() {
+        bail!("Register map has no [[register]] entries");
+    }
+
+    let mut registers = Vec::new();
+    let mut names = HashSet::new();
+    let mut offsets = HashSet::new();
+    let mut next_offset = 0;
+
+    for spec in specs {
+        check_name(&spec.name)?;
+        if !names.insert(spec.name.to_lowercase()) {
+            bail!("Duplicate register: {}", spec.name);
+        }
+
+        let offset = spec.offset.unwrap_or(next_offset);
+        if offset > MAX_OFFSET {
+            bail!(
+                "{}: offset 0x{:X} is past 0x{:X}",
+                spec.name,
+                offset,
+                MAX_OFFSET
+            );
+        }
+        if !offsets.insert(offset) {
+            bail!("{}: offset 0x{:X} is already used", spec.name, offset);
+        }
+        next_offset = offset + 1;
+
+        if spec.strobe && !spec.access.writable() {
+            bail!("{}: strobe needs a writable register", spec.name);
+        }
+
+        let mut fields: Vec<Field> = Vec::new();
+        for field in &spec.fields {
+            check_name(&field.name)?;
+            let (msb, lsb) =
+                parse_bits(&field.bits).with_context(|| format!("{}.{}", spec.name, field.name))?;
+            let field = Field {
+                name: field.name.clone(),
+                msb,
+                lsb,
+                description: field.description.clone(),
+            };
+            if let Some(other) = fields.iter().find(|f| f.mask() & field.mask() != 0) {
+                bail!(
+                    "{}: fields {} and {} overlap",
+                    spec.name,
+                    other.name,
+                    field.name
+                );
+            }
+            if fields
+                .iter()
+                .any(|f| f.name.eq_ignore_ascii_case(&field.name))
+            {
+                bail!("{}: duplicate field {}", spec.name, field.name);
+            }
+            fields.push(field);
+        }
+        fields.sort_by_key(|f| f.lsb);
+
+        let register = Register {
+            name: spec.name.clone(),
+            offset,
+            access: spec.access,
+            reset: spec.reset,
+            strobe: spec.strobe,
+            description: spec.description.clone(),
+            fields,
+        };
+        if register.access == Access::Ro && register.reset != 0 {
+            bail!("{}: read-only registers have no reset value", spec.name);
+        }
+        if register.reset & !register.mask() != 0 {
+            bail!(
+                "{}: reset 0x{:X} sets bits outside the register's fields",
+                spec.name,
+                register.reset
+            );
+        }
+        registers.push(register);
+    }
+
+    registers.sort_by_key(|r| r.offset);
+    Ok(registers)
+}
+
+/// Parse "7:4" or "3" into (msb, lsb)
+fn parse_bits(bits: &str) -> Result<(u32, u32)> {
+    let parse = |s: &str| {
+        s.trim()
+            .parse::<u32>()
+            .with_context(|| format!("Invalid bit range: {}", bits))
+    };
+    let (msb, lsb) = match bits.split_once(':') {
+        Some((msb, lsb)) => (parse(msb)?, parse(lsb)?),
+        None => {
+            let bit = parse(bits)?;
+            (bit, bit)
+        }
+    };
+    if msb < lsb || msb >= DATA_WIDTH {
+        bail!(
+            "Invalid bit range: {} (registers are {} bits)",
+            bits,
+            DATA_WIDTH
+        );
+    }
+    Ok((msb, lsb))
+}
+
+/// Declaration range for a signal of `width` bits ("" for a single bit)
+fn range(width: u32) -> String {
+    if width == 1 {
+        String::new()
+    } else {
+        format!("[{}:0] ", width - 1)
+    }
+}
+
+/// Concatenate field signals into a 16-bit word, zero-filling the gaps
+fn compose(fields: &[Field], signal: impl Fn(&Field) -> String) -> String {
+    let mut parts = Vec::new();
+    let mut next = DATA_WIDTH;
+    for field in fields.iter().rev() {
+        if field.msb + 1 < next {
+            parts.push(format!("{}'d0", next - field.msb - 1));
+        }
+        parts.push(signal(field));
+        next = field.lsb;
+    }
+    if next > 0 {
+        parts.push(format!("{}'d0", next));
+    }
+    format!("{{{}}}", parts.join(", "))
+}
+
+fn verilog(map: &RegMapSpec, registers: &[Register], source: &str) -> String {
+    let (addr_width, bus, addr, wdata, we, rdata) = match map.bus {
+        Bus::Spi => (
+            15,
+            "    // SPI interface (asynchronous to i_clk)
+    input wire i_cs_n,
+    input wire i_sck,
+    input wire i_mosi,
+    output wire o_miso,"
+                .to_string(),
+            "addr",
+            "wdata",
+            "we",
+            "rdata",
+        ),
+        Bus::Simple => {
+            let last = registers.iter().map(|r| r.offset).max().unwrap_or(0);
+            let width = addr_bits(last + 1);
+            (
+                width,
+                BUS_PORTS.replace("{addr_msb}", &(width - 1).to_string()),
+                "i_addr",
+                "i_wdata",
+                "i_we",
+                "o_rdata",
+            )
+        }
+    };
+
+    let mut ports = Vec::new();
+    let mut regs = String::new();
+    let mut assigns = String::new();
+    let mut resets = String::new();
+    let mut clears = String::new();
+    let mut writes = String::new();
+    let mut reads = String::new();
+    let mut table = String::new();
+    let name_width = registers.iter().map(|r| r.name.len()).max().unwrap_or(0);
+
+    for reg in registers {
+        let name = &reg.name;
+        let label = format!("{}'h{:02X}", addr_width, reg.offset);
+        let row = format!(
+            " *   0x{:02X}  {:name_width$}  {}  {}",
+            reg.offset,
+            name.to_uppercase(),
+            reg.access.label(),
+            reg.description
+        );
+        table.push_str(row.trim_end());
+        table.push('\n');
+
+        let direction = if reg.access.writable() {
+            "output"
+        } else {
+            "input"
+        };
+        let prefix = if reg.access.writable() { "o" } else { "i" };
+        if reg.fields.is_empty() {
+            ports.push(format!(
+                "    {direction} wire {}{prefix}_{name}",
+                range(DATA_WIDTH)
+            ));
+        } else {
+            for field in &reg.fields {
+                ports.push(format!(
+                    "    {direction} wire {}{prefix}_{name}_{}",
+                    range(field.width()),
+                    field.name
+                ));
+            }
+        }
+        if reg.strobe {
+            ports.push(format!("    output reg o_{name}_wr"));
+        }
+
+        if reg.access.writable() {
+            regs.push_str(&format!("    reg [15:0] {name}_q;\n"));
+            if reg.fields.is_empty() {
+                assigns.push_str(&format!("    assign o_{name} = {name}_q;\n"));
+            } else {
+                for field in &reg.fields {
+                    assigns.push_str(&format!(
+                        "    assign o_{name}_{} = {name}_q[{}];\n",
+                        field.name,
+                        field.bits()
+                    ));
+                }
+            }
+            resets.push_str(&format!("            {name}_q <= 16'h{:04X};\n", reg.reset));
+            let value = if reg.fields.is_empty() {
+                wdata.to_string()
+            } else {
+                format!("{} & 16'h{:04X}", wdata, reg.mask())
+            };
+            if reg.strobe {
+                resets.push_str(&format!("            o_{name}_wr <= 1'b0;\n"));
+                clears.push_str(&format!("            o_{name}_wr <= 1'b0;\n"));
+                writes.push_str(&format!(
+                    "                    {label}: begin\n                        {name}_q <= {value};\n                        o_{name}_wr <= 1'b1;\n                    end\n"
+                ));
+            } else {
+                writes.push_str(&format!(
+                    "                    {label}: {name}_q <= {value};\n"
+                ));
+            }
+        }
+
+        match reg.access {
+            Access::Rw => reads.push_str(&format!("            {label}: {rdata} = {name}_q;\n")),
+            Access::Ro if reg.fields.is_empty() => {
+                reads.push_str(&format!("            {label}: {rdata} = i_{name};\n"))
+            }
+            Access::Ro => reads.push_str(&format!(
+                "            {label}: {rdata} = {};\n",
+                compose(&reg.fields, |f| format!("i_{name}_{}", f.name))
+            )),
+            Access::Wo => {}
+        }
+    }
+
+    let bridge = match map.bus {
+        Bus::Spi => format!(
+            "    wire [14:0] {addr};
+    wire [15:0] {wdata};
+    wire {we};
+    reg [15:0] {rdata};
+
+    spi_bridge bridge (
+        .i_clk(i_clk),
+        .i_rst(i_rst),
+        .i_cs_n(i_cs_n),
+        .i_sck(i_sck),
+        .i_mosi(i_mosi),
+        .o_miso(o_miso),
+        .o_addr({addr}),
+        .o_wdata({wdata}),
+        .o_we({we}),
+        .i_rdata({rdata})
+    );
+
+"
+        ),
+        Bus::Simple => String::new(),
+    };
+
+    let sequential = if regs.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "{regs}
+{assigns}
+    always @(posedge i_clk or posedge i_rst) begin
+        if (i_rst) begin
+{resets}        end else begin
+{clears}            if ({we}) begin
+                case ({addr})
+{writes}                    default: ;
+                endcase
+            end
+        end
+    end
+
+"
+        )
+    };
+
+    let access = match map.bus {
+        Bus::Spi => " * Accessed over SPI mode 0 through spi_bridge (i_clk at least 16x SCK).\n",
+        Bus::Simple => " * Accessed through the simple register interface of generated cores.\n",
+    };
+    let description = if map.description.is_empty() {
+        String::new()
+    } else {
+        format!(" * {}\n *\n", map.description)
+    };
+
+    format!(
+        r#"/**
+ * {name} - register block
+ *
+{description} * Generated by `affogato regmap build` from {source}; edit the spec, not this file.
+ * Register offsets for the firmware are in {name}.h, the reference in docs/{name}.md.
+{access} * Read-only inputs are sampled combinationally and should be synchronous to i_clk.
+ *
+ * Registers (16-bit, word addressed):
+{table} */
+module {name} (
+    input wire i_clk,
+    input wire i_rst,
+
+{bus}
+
+{ports}
+);
+
+{bridge}{sequential}    always @(*) begin
+        case ({addr})
+{reads}            default: {rdata} = 16'd0;
+        endcase
+    end
+
+endmodule
+"#,
+        name = map.name,
+        ports = ports.join(",\n"),
+    )
+}
+
+fn header(map: &RegMapSpec, registers: &[Register], source: &str) -> String {
+    let upper = map.name.to_uppercase();
+
+    let mut defines = Vec::new();
+    defines.push("/* Register offsets (16-bit words) */".to_string());
+    for reg in registers {
+        defines.push(format!(
+            "#define {}_{} 0x{:04X}",
+            upper,
+            reg.name.to_uppercase(),
+            reg.offset
+        ));
+    }
+    for reg in registers {
+        let prefix = format!("{}_{}", upper, reg.name.to_uppercase());
+        let mut lines = Vec::new();
+        if reg.reset != 0 {
+            lines.push(format!("#define {}_RESET 0x{:04X}", prefix, reg.reset));
+        }
+        for field in &reg.fields {
+            let field_prefix = format!("{}_{}", prefix, field.name.to_uppercase());
+            lines.push(format!("#define {}_SHIFT {}", field_prefix, field.lsb));
+            lines.push(format!(
+                "#define {}_MASK 0x{:04X}",
+                field_prefix,
+                field.mask()
+            ));
+        }
+        if !lines.is_empty() {
+            defines.push(String::new());
+            defines.push(format!("/* {} */", reg.name.to_uppercase()));
+            defines.extend(lines);
+        }
+    }
+
+    let defines = align_defines(defines);
+    let name = &map.name;
+    let mut out = format!(
+        "/**
+ * {name} register map - generated by `affogato regmap build` from {source}
+ */
+#pragma once
+"
+    );
+
+    if map.bus == Bus::Spi {
+        out.push_str(&format!(
+            "{SPI_INCLUDES}\n{}\n{}",
+            defines.join("\n"),
+            spi_helpers(name)
+        ));
+    } else {
+        out.push('\n');
+        out.push_str(&defines.join("\n"));
+        out.push('\n');
+    }
+    out
+}
+
+/// Includes needed by [`spi_helpers`]
+pub const SPI_INCLUDES: &str = r#"
+#include <stdint.h>
+#include <esp_err.h>
+#include "driver/spi_master.h"
+#include "ice40/master_spi.h"
+"#;
+
+/// `<prefix>_write`/`<prefix>_read` functions for registers behind `spi_bridge`
+pub fn spi_helpers(prefix: &str) -> String {
+    format!(
+        r#"
+/*
+ * Access over SPI mode 0 at up to 3 MHz (the FPGA samples SCK at 16x).
+ * Reads clock data back in the same transaction, so add the device without
+ * SPI_DEVICE_HALFDUPLEX.
+ */
+static inline esp_err_t {prefix}_write(spi_device_handle_t device, uint16_t reg, uint16_t value)
+{{
+    uint8_t tx[4] = {{0x80 | ((reg >> 8) & 0x7F), reg & 0xFF, value >> 8, value & 0xFF}};
+    spi_transaction_t trans = {{
+        .length = 32,
+        .tx_buffer = tx,
+    }};
+
+    xSemaphoreTake(master_spi_semaphore, portMAX_DELAY);
+    esp_err_t ret = spi_device_transmit(device, &trans);
+    xSemaphoreGive(master_spi_semaphore);
+    return ret;
+}}
+
+static inline esp_err_t {prefix}_read(spi_device_handle_t device, uint16_t reg, uint16_t *value)
+{{
+    uint8_t tx[4] = {{(reg >> 8) & 0x7F, reg & 0xFF, 0, 0}};
+    uint8_t rx[4] = {{0}};
+    spi_transaction_t trans = {{
+        .length = 32,
+        .tx_buffer = tx,
+        .rx_buffer = rx,
+    }};
+
+    xSemaphoreTake(master_spi_semaphore, portMAX_DELAY);
+    esp_err_t ret = spi_device_transmit(device, &trans);
+    xSemaphoreGive(master_spi_semaphore);
+    if (ret == ESP_OK) {{
+        *value = ((uint16_t)rx[2] << 8) | rx[3];
+    }}
+    return ret;
+}}
+"#
+    )
+}
+
+/// Align the values of `#define NAME VALUE` lines, leaving other lines alone
+pub fn align_defines(lines: Vec<String>) -> Vec<String> {
+    let width = lines
+        .iter()
+        .filter_map(|l| l.strip_prefix("#define "))
+        .filter_map(|l| l.rsplit_once(' '))
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    lines
+        .into_iter()
+        .map(|line| {
+            match line
+                .strip_prefix("#define ")
+                .and_then(|l| l.rsplit_once(' '))
+            {
+                Some((name, value)) => format!("#define {:width$}  {}", name, value),
+                None => line,
+            }
+        })
+        .collect()
+}
+
+fn reference(map: &RegMapSpec, registers: &[Register], source: &str) -> String {
+    let mut out = format!("# {} Register Map\n\n", map.name);
+    if !map.description.is_empty() {
+        out.push_str(&format!("{}\n\n", map.description));
+    }
+    out.push_str(&format!(
+        "Generated by `affogato regmap build` from `{}`. Registers are 16 bits wide and word addressed.\n\n",
+        source
+    ));
+    if map.bus == Bus::Spi {
+        out.push_str(
+            "Transactions are SPI mode 0, MSB first: a 16-bit header `[write][address:15]` \
+             followed by 16-bit data words. Longer transactions auto-increment the address.\n\n",
+        );
+    }
+
+    out.push_str("| Offset | Name | Access | Reset | Description |\n");
+    out.push_str("|--------|------|--------|-------|-------------|\n");
+    for reg in registers {
+        out.push_str(&format!(
+            "| 0x{:04X} | [{}](#{}) | {} | 0x{:04X} | {} |\n",
+            reg.offset,
+            reg.name.to_uppercase(),
+            reg.name.to_lowercase(),
+            reg.access.label(),
+            reg.reset,
+            reg.description
+        ));
+    }
+
+    for reg in registers {
+        out.push_str(&format!(
+            "\n## {}\n\nOffset 0x{:04X}, {}",
+            reg.name.to_uppercase(),
+            reg.offset,
+            reg.access.label()
+        ));
+        if reg.strobe {
+            out.push_str(", write strobe");
+        }
+        out.push_str(".\n");
+        if !reg.description.is_empty() {
+            out.push_str(&format!("\n{}\n", reg.description));
+        }
+        if !reg.fields.is_empty() {
+            out.push_str("\n| Bits | Field | Reset | Description |\n");
+            out.push_str("|------|-------|-------|-------------|\n");
+            for field in reg.fields.iter().rev() {
+                out.push_str(&format!(
+                    "| {} | {} | 0x{:X} | {} |\n",
+                    field.bits(),
+                    field.name.to_uppercase(),
+                    (reg.reset & field.mask()) >> field.lsb,
+                    field.description
+                ));
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,187 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use affogato_core::annotate::ReportFormat;
+use affogato_core::build::{FIRMWARE_OUTPUTS, FPGA_OUTPUTS};
+use affogato_core::error::Failure;
+use affogato_core::project::Project;
+use affogato_core::{dry_run, outln, output};
+
+/// Where projects are copied on the build server, relative to its home
+const REMOTE_DIR: &str = ".cache/affogato/remote";
+
+/// Exit status of the remote shell when affogato isn't installed there
+const NOT_INSTALLED: i32 = 127;
+
+/// Build on `host` over ssh: copy the project there with rsync, run
+/// `affogato build` with the server's docker, then copy the bitstream and
+/// firmware back. `flags` are global options to pass on.
+pub fn build(
+    project: &Project,
+    host: &str,
+    flags: &[String],
+    args: &[String],
+    format: ReportFormat,
+) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    for tool in ["rsync", "ssh"] {
+        which::which(tool).with_context(|| {
+            format!(
+                "{} not found on PATH; remote builds need rsync and ssh",
+                tool
+            )
+        })?;
+    }
+    let dir = remote_dir(project_root);
+
+    outln!(
+        "{}",
+        format!("==> Copying the project to {}:{}", host, dir)
+            .blue()
+            .bold()
+    );
+    run(
+        Command::new("ssh").args([host, &format!("mkdir -p {}", quote(&dir))]),
+        host,
+    )?;
+    // Build outputs are excluded, so the server keeps its own between builds
+    // and only rebuilds what changed
+    let mut upload = Command::new("rsync");
+    upload.args(["-az", "--delete"]);
+    for exclude in [
+        "/fpga/build",
+        "/fpga/top.json",
+        "/fpga/top.asc",
+        "/fpga/top.bin",
+        "/firmware/build",
+        "/.affogato",
+    ] {
+        upload.arg(format!("--exclude={}", exclude));
+    }
+    upload
+        .arg(format!("{}/", project_root.display()))
+        .arg(format!("{}:{}/", host, dir));
+    run(&mut upload, host)?;
+
+    outln!("{}", format!("==> Building on {}", host).blue().bold());
+    let mut remote_args: Vec<String> = flags.to_vec();
+    remote_args.extend(["build".to_string(), "--format".to_string()]);
+    remote_args.push(
+        match format {
+            ReportFormat::Text => "text",
+            ReportFormat::Gha => "gha",
+        }
+        .to_string(),
+    );
+    if !args.is_empty() {
+        remote_args.push("--".to_string());
+        remote_args.extend(args.iter().cloned());
+    }
+    let script = format!(
+        "command -v affogato >/dev/null || exit {}; cd {} && affogato {}",
+        NOT_INSTALLED,
+        quote(&dir),
+        remote_args
+            .iter()
+            .map(|arg| quote(arg))
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+    let mut command = Command::new("ssh");
+    command.args([host, &script]);
+    if dry_run::is_enabled() {
+        dry_run::note(&format!("{:?}", command));
+    } else {
+        let status = command
+            .stdin(Stdio::null())
+            .stdout(output::child_stdout())
+            .status()
+            .context("Failed to run ssh")?;
+        match status.code() {
+            Some(0) => {}
+            Some(NOT_INSTALLED) => bail!(
+                "affogato is not installed on {}; install it there (and docker) to build remotely",
+                host
+            ),
+            _ => return Err(Failure::BuildFailed.error(format!("The build on {} failed", host))),
+        }
+    }
+
+    outln!(
+        "{}",
+        format!("==> Copying the build back from {}", host)
+            .blue()
+            .bold()
+    );
+    let mut download = Command::new("rsync");
+    download.args(["-az", "--relative", "--ignore-missing-args"]);
+    for output in FPGA_OUTPUTS.iter().chain(FIRMWARE_OUTPUTS) {
+        download.arg(format!("{}:{}/./{}", host, dir, output));
+    }
+    download.arg(format!("{}/", project_root.display()));
+    run(&mut download, host)?;
+
+    if !dry_run::is_enabled() {
+        outln!(
+            "{}",
+            format!("Built on {}; artifacts are in place locally", host).green()
+        );
+    }
+    Ok(())
+}
+
+/// The project's directory on the server, named after the project and
+/// distinct for each local checkout
+fn remote_dir(project_root: &Path) -> String {
+    let name = project_root
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "project".to_string());
+    let hash: String = Sha256::digest(project_root.to_string_lossy().as_bytes())
+        .iter()
+        .take(4)
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("{}/{}-{}", REMOTE_DIR, name, hash)
+}
+
+/// Run rsync or ssh, failing with a hint about the connection
+fn run(command: &mut Command, host: &str) -> Result<()> {
+    if dry_run::is_enabled() {
+        dry_run::note(&format!("{:?}", command));
+        return Ok(());
+    }
+    let program = command.get_program().to_string_lossy().to_string();
+    let status = command
+        .stdin(Stdio::null())
+        .status()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !status.success() {
+        bail!(
+            "{} to {} failed; check that `ssh {}` works without a password prompt",
+            program,
+            host,
+            host
+        );
+    }
+    Ok(())
+}
+
+/// Quote a word for the remote shell
+fn quote(word: &str) -> String {
+    if !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,@".contains(c))
+    {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
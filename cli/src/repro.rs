@@ -0,0 +1,234 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use affogato_core::annotate::{self, ReportFormat};
+use affogato_core::build::build_fpga;
+use affogato_core::docker::Docker;
+use affogato_core::error::Failure;
+use affogato_core::project::Project;
+use affogato_core::{cache, dry_run, outln, output};
+
+/// Copies of the first build's artifacts, relative to the project root
+const FIRST_BUILD_DIR: &str = ".affogato/repro";
+
+/// Build outputs removed before each build, so neither reuses the other's work
+pub const OUTPUTS: &str = "fpga/build fpga/top.json fpga/top.asc fpga/top.bin firmware/build";
+
+/// Build everything twice from clean outputs, each in a fresh container with
+/// the same seed and timestamp, and compare the artifacts' hashes
+pub fn verify(docker: &Docker, project: &Project, format: ReportFormat) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+
+    // Stamp both builds with the commit time instead of the wall clock
+    let epoch = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .or_else(|| commit_time(project_root))
+        .unwrap_or_else(|| "0".to_string());
+    std::env::set_var("SOURCE_DATE_EPOCH", &epoch);
+    // Both builds must run every tool for the comparison to mean anything
+    cache::set_enabled(false);
+
+    let mut first = Vec::new();
+    for run in 1..=2 {
+        outln!(
+            "{}",
+            format!("==> Reproducibility build {} of 2", run)
+                .blue()
+                .bold()
+        );
+        docker.run_in_project(
+            project,
+            &["bash", "-c", &format!("rm -rf {}", OUTPUTS)],
+            &[],
+            false,
+        )?;
+        build_fpga(docker, project, &[], format)?;
+        let idf_cmd = format!(
+            "export SOURCE_DATE_EPOCH={} && cd firmware && idf.py build",
+            epoch
+        );
+        annotate::run_script(docker, project, &idf_cmd, format).context(Failure::BuildFailed)?;
+        if dry_run::is_enabled() {
+            continue;
+        }
+
+        let hashes = hash_artifacts(project_root)?;
+        if run == 1 {
+            let dir = project_root.join(FIRST_BUILD_DIR);
+            if dir.exists() {
+                fs::remove_dir_all(&dir)?;
+            }
+            for (path, _) in &hashes {
+                let copy = dir.join(path);
+                fs::create_dir_all(copy.parent().unwrap_or(&dir))?;
+                fs::copy(project_root.join(path), copy)?;
+            }
+            first = hashes;
+        } else {
+            return compare(project_root, &first, &hashes);
+        }
+    }
+    Ok(())
+}
+
+/// Report which artifacts differ between the builds and what likely made them differ
+fn compare(
+    project_root: &Path,
+    first: &[(String, String)],
+    second: &[(String, String)],
+) -> Result<()> {
+    outln!("{}", "==> Comparing artifacts".blue().bold());
+    let mut entries = Vec::new();
+    let mut differing = Vec::new();
+    for (path, hash) in first {
+        let other = second.iter().find(|(p, _)| p == path).map(|(_, h)| h);
+        let same = other == Some(hash);
+        if same {
+            outln!("  {} {}  {}", "✓".green(), path, hash[..16].dimmed());
+        } else {
+            let at = first_difference(
+                &project_root.join(FIRST_BUILD_DIR).join(path),
+                &project_root.join(path),
+            );
+            outln!(
+                "  {} {}  {}",
+                "✗".red(),
+                path,
+                match at {
+                    Some(offset) => format!("first differs at byte 0x{:x}", offset),
+                    None => "missing from the second build".to_string(),
+                }
+                .yellow()
+            );
+            differing.push(path.as_str());
+        }
+        entries.push(json!({
+            "path": path,
+            "first": hash,
+            "second": other,
+            "reproducible": same,
+        }));
+    }
+
+    let sources = nondeterminism_sources(project_root, &differing);
+    for source in &sources {
+        outln!("  {} {}", "!".yellow(), source);
+    }
+    output::report("repro", json!({ "artifacts": entries, "sources": sources }));
+
+    if !differing.is_empty() {
+        bail!(
+            "Build is not reproducible: {} of {} artifacts differ (first build kept in {})",
+            differing.len(),
+            first.len(),
+            FIRST_BUILD_DIR
+        );
+    }
+    outln!(
+        "{}",
+        format!("Build is reproducible: all {} artifacts match", first.len())
+            .green()
+            .bold()
+    );
+    Ok(())
+}
+
+/// Likely causes of the differences, going by which stage first diverged and
+/// the sdkconfig options that keep ESP-IDF builds deterministic
+fn nondeterminism_sources(project_root: &Path, differing: &[&str]) -> Vec<String> {
+    let mut sources = Vec::new();
+    if differing.contains(&"fpga/top.json") {
+        sources.push(
+            "Yosys produced a different netlist: look for $random, initial values from files that change, or generated RTL".to_string(),
+        );
+    } else if differing.contains(&"fpga/top.asc") {
+        sources.push(
+            "nextpnr placed or routed the same netlist differently despite the pinned seed"
+                .to_string(),
+        );
+    }
+
+    if differing.iter().any(|path| path.starts_with("firmware/")) {
+        let before = sources.len();
+        let sdkconfig =
+            fs::read_to_string(project_root.join("firmware/sdkconfig")).unwrap_or_default();
+        if !sdkconfig.contains("CONFIG_APP_REPRODUCIBLE_BUILD=y") {
+            sources.push(
+                "Firmware embeds build paths: set CONFIG_APP_REPRODUCIBLE_BUILD=y in sdkconfig.defaults".to_string(),
+            );
+        }
+        if !sdkconfig.contains("# CONFIG_APP_COMPILE_TIME_DATE is not set") {
+            sources.push(
+                "The app descriptor records the compile time: set CONFIG_APP_COMPILE_TIME_DATE=n"
+                    .to_string(),
+            );
+        }
+        if sources.len() == before {
+            sources.push(
+                "Firmware differs with reproducible settings: look for __DATE__, __TIME__ or generated sources".to_string(),
+            );
+        }
+    }
+    sources
+}
+
+/// SHA-256 of each artifact the build produced, by path relative to the project root
+fn hash_artifacts(project_root: &Path) -> Result<Vec<(String, String)>> {
+    let mut paths: Vec<String> = ["fpga/top.json", "fpga/top.asc", "fpga/top.bin"]
+        .iter()
+        .map(|path| path.to_string())
+        .collect();
+    let build = "firmware/build";
+    let description: serde_json::Value =
+        fs::read_to_string(project_root.join(build).join("project_description.json"))
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+    for key in ["app_bin", "app_elf"] {
+        if let Some(file) = description[key].as_str() {
+            paths.push(format!("{}/{}", build, file));
+        }
+    }
+    paths.push(format!("{}/bootloader/bootloader.bin", build));
+    paths.push(format!("{}/partition_table/partition-table.bin", build));
+
+    let mut hashes = Vec::new();
+    for path in paths {
+        let Ok(data) = fs::read(project_root.join(&path)) else {
+            continue;
+        };
+        let hash = Sha256::digest(&data)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        hashes.push((path, hash));
+    }
+    Ok(hashes)
+}
+
+fn first_difference(a: &Path, b: &Path) -> Option<usize> {
+    let (a, b) = (fs::read(a).ok()?, fs::read(b).ok()?);
+    a.iter()
+        .zip(&b)
+        .position(|(x, y)| x != y)
+        .or(Some(a.len().min(b.len())))
+}
+
+fn commit_time(project_root: &Path) -> Option<String> {
+    Command::new("git")
+        .current_dir(project_root)
+        .args(["log", "-1", "--format=%ct"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|time| !time.is_empty())
+}
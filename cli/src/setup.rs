@@ -0,0 +1,101 @@
+use anyhow::Result;
+use colored::Colorize;
+use std::io::{self, BufRead, IsTerminal};
+
+use affogato_core::config::{self, Config, DEFAULT_PORT};
+use affogato_core::docker::Docker;
+use affogato_core::{dry_run, out, outln, output};
+
+use crate::{doctor, udev};
+
+/// Whether setup can ask questions: on a terminal, and not in JSON or dry-run mode
+pub fn interactive() -> bool {
+    io::stdin().is_terminal()
+        && io::stdout().is_terminal()
+        && !output::is_json()
+        && !dry_run::is_enabled()
+}
+
+/// Offered once, before the user config exists. Declining still writes the
+/// default config so the question isn't asked again.
+pub fn first_run(image: &str) -> Result<()> {
+    outln!("{}", "Welcome to affogato!".blue().bold());
+    if confirm("No settings found. Set up docker, the toolchain image and the board now?")? {
+        run(image)?;
+    } else {
+        let path = Config::default().save()?;
+        outln!(
+            "Skipped; wrote defaults to {}. Run affogato setup any time.",
+            path.display()
+        );
+    }
+    outln!();
+    Ok(())
+}
+
+/// Interactive setup: check docker, pull the image, install the udev rule,
+/// pick how to find the serial port, then write the user config
+pub fn run(image: &str) -> Result<()> {
+    let mut config = Config::load().unwrap_or_default();
+
+    outln!("{}", "==> Docker".blue().bold());
+    if doctor::docker_ready() {
+        let docker = Docker::new(Some(image.to_string()))?;
+        if docker.image_exists()? {
+            outln!("  {} Image {} present", "✓".green(), image);
+        } else if confirm(&format!(
+            "Pull the toolchain image {} now (a few GB)?",
+            image
+        ))? {
+            docker.pull()?;
+        }
+    } else {
+        outln!(
+            "{}",
+            "Fix docker as above; the image is pulled on first use".yellow()
+        );
+    }
+
+    if cfg!(target_os = "linux") {
+        outln!("{}", "==> USB access".blue().bold());
+        if confirm("Install the udev rule so the board works without root (uses sudo)?")? {
+            if let Err(e) = udev::setup(false) {
+                outln!("{}", format!("udev rule not installed: {:#}", e).yellow());
+            }
+        }
+    }
+
+    outln!("{}", "==> Serial port".blue().bold());
+    let found = config::available_ports();
+    if !found.is_empty() {
+        outln!("  Ports present now: {}", found.join(", "));
+    }
+    outln!("  1) Find the board when needed (auto)");
+    outln!("  2) Always use {}", DEFAULT_PORT);
+    outln!("  3) Another port");
+    config.serial.port = match prompt("Choose [1]:")?.as_str() {
+        "2" => Some(DEFAULT_PORT.to_string()),
+        "3" => Some(prompt("Port:")?).filter(|port| !port.is_empty()),
+        _ => Some("auto".to_string()),
+    };
+
+    let path = config.save()?;
+    outln!(
+        "{}",
+        format!("Saved settings to {}", path.display()).green()
+    );
+    Ok(())
+}
+
+/// Ask a yes/no question, defaulting to yes
+fn confirm(question: &str) -> Result<bool> {
+    let answer = prompt(&format!("{} [Y/n]", question))?.to_lowercase();
+    Ok(answer.is_empty() || answer.starts_with('y'))
+}
+
+pub fn prompt(question: &str) -> Result<String> {
+    out!("{} ", question.bold());
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
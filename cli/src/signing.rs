@@ -0,0 +1,160 @@
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Signature file written next to each signed file
+pub const SIGNATURE_EXTENSION: &str = "sig";
+
+/// Public half of the signing key, shipped in the bundle for reference
+pub const PUBLIC_KEY_FILE: &str = "signing-key.pub.pem";
+
+/// How a bundle is signed
+#[derive(Clone, Copy, PartialEq)]
+pub enum Algorithm {
+    Ed25519,
+    RsaSha256,
+}
+
+impl Algorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Ed25519 => "ed25519",
+            Algorithm::RsaSha256 => "rsa-sha256",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "ed25519" => Ok(Algorithm::Ed25519),
+            "rsa-sha256" => Ok(Algorithm::RsaSha256),
+            other => bail!("Unknown signature algorithm: {}", other),
+        }
+    }
+}
+
+/// A private key, used through the openssl command-line tool
+pub struct Signer {
+    key: PathBuf,
+    pub algorithm: Algorithm,
+    /// The public key, PEM encoded
+    pub public_key: String,
+}
+
+impl Signer {
+    /// Load an ed25519 or RSA private key in PEM form
+    pub fn load(key: &Path) -> Result<Self> {
+        let text = openssl(
+            Command::new("openssl")
+                .args(["pkey", "-in"])
+                .arg(key)
+                .args(["-noout", "-text"]),
+        )
+        .with_context(|| format!("Failed to read the private key {}", key.display()))?;
+        let algorithm = if text.starts_with("ED25519") {
+            Algorithm::Ed25519
+        } else if text.contains("modulus:") {
+            Algorithm::RsaSha256
+        } else {
+            bail!(
+                "Unsupported key type in {}; use an ed25519 or RSA key",
+                key.display()
+            );
+        };
+        let public_key = openssl(
+            Command::new("openssl")
+                .args(["pkey", "-in"])
+                .arg(key)
+                .arg("-pubout"),
+        )?;
+        Ok(Self {
+            key: key.to_path_buf(),
+            algorithm,
+            public_key,
+        })
+    }
+
+    /// SHA-256 of the public key's PEM, identifying the key in the manifest
+    pub fn key_id(&self) -> String {
+        Sha256::digest(self.public_key.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// Sign `file`, writing the signature to `file.sig`
+    pub fn sign(&self, file: &Path) -> Result<PathBuf> {
+        let signature = signature_path(file);
+        let mut command = Command::new("openssl");
+        match self.algorithm {
+            Algorithm::Ed25519 => command
+                .args(["pkeyutl", "-sign", "-rawin", "-inkey"])
+                .arg(&self.key)
+                .arg("-in")
+                .arg(file)
+                .arg("-out")
+                .arg(&signature),
+            Algorithm::RsaSha256 => command
+                .args(["dgst", "-sha256", "-sign"])
+                .arg(&self.key)
+                .arg("-out")
+                .arg(&signature)
+                .arg(file),
+        };
+        openssl(&mut command).with_context(|| format!("Failed to sign {}", file.display()))?;
+        Ok(signature)
+    }
+}
+
+/// Check `file.sig` is a valid signature of `file` by `public_key`
+pub fn verify(algorithm: Algorithm, public_key: &Path, file: &Path) -> Result<()> {
+    let signature = signature_path(file);
+    if !signature.exists() {
+        bail!("{} is not signed", file.display());
+    }
+    let mut command = Command::new("openssl");
+    match algorithm {
+        Algorithm::Ed25519 => command
+            .args(["pkeyutl", "-verify", "-pubin", "-rawin", "-inkey"])
+            .arg(public_key)
+            .arg("-in")
+            .arg(file)
+            .arg("-sigfile")
+            .arg(&signature),
+        Algorithm::RsaSha256 => command
+            .args(["dgst", "-sha256", "-verify"])
+            .arg(public_key)
+            .arg("-signature")
+            .arg(&signature)
+            .arg(file),
+    };
+    if openssl(&mut command).is_err() {
+        bail!(
+            "Bad signature on {} for the key {}",
+            file.file_name().unwrap_or_default().to_string_lossy(),
+            public_key.display()
+        );
+    }
+    Ok(())
+}
+
+fn signature_path(file: &Path) -> PathBuf {
+    let mut name = file.as_os_str().to_owned();
+    name.push(".");
+    name.push(SIGNATURE_EXTENSION);
+    PathBuf::from(name)
+}
+
+/// Run openssl, returning its output; signing needs OpenSSL 3 for ed25519
+fn openssl(command: &mut Command) -> Result<String> {
+    let output = command
+        .output()
+        .context("Failed to run openssl; is it installed?")?;
+    if !output.status.success() {
+        bail!(
+            "openssl failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
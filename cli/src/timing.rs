@@ -0,0 +1,255 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use affogato_core::annotate::ReportFormat;
+use affogato_core::build::build_fpga;
+use affogato_core::build_info::BuildInfo;
+use affogato_core::docker::Docker;
+use affogato_core::nextpnr::CriticalPath;
+use affogato_core::project::Project;
+use affogato_core::timing::{self, Record};
+use affogato_core::{dry_run, outln, output};
+
+use crate::git::git;
+use crate::metrics::delta;
+
+/// Compare with the build before the latest instead of a git revision
+const PREVIOUS: &str = "previous";
+
+/// Show the last FPGA build's timing: each clock's Fmax against its target
+/// and its critical path
+pub fn show(project: &Project) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    let records = timing::load(project_root)?;
+    let latest = records
+        .iter()
+        .rfind(|record| !record.worktree)
+        .context("No timing recorded yet; run affogato build")?;
+
+    outln!(
+        "{}",
+        format!("==> Timing of {}", latest.describe).blue().bold()
+    );
+    for (clock, fmax) in &latest.fmax_mhz {
+        let target = latest.target_mhz.get(clock);
+        let verdict = match target {
+            Some(target) if fmax >= target => format!("(target {:.2} MHz)", target).green(),
+            Some(target) => format!("(FAIL at {:.2} MHz)", target).red(),
+            None => "".normal(),
+        };
+        outln!(
+            "  {:<32} {:>12}  {}",
+            clock,
+            format!("{:.2} MHz", fmax),
+            verdict
+        );
+        if let Some(path) = latest.critical_paths.get(clock) {
+            print_path(path);
+        }
+    }
+    output::report("timing", latest);
+    Ok(())
+}
+
+/// Compare the last build's timing with that of `reference`: a git revision,
+/// built in a temporary worktree if its timing isn't archived, or "previous"
+/// for the build before. With `fail_on_regression`, fail if any clock's Fmax
+/// dropped.
+pub fn compare(
+    docker: &Docker,
+    project: &Project,
+    reference: &str,
+    fail_on_regression: bool,
+) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    let records = timing::load(project_root)?;
+    let mut local = records.iter().rev().filter(|record| !record.worktree);
+    let after = local
+        .next()
+        .cloned()
+        .context("No timing recorded yet; run affogato build")?;
+    let before = if reference == PREVIOUS {
+        local
+            .next()
+            .cloned()
+            .context("Only one build recorded; nothing to compare with")?
+    } else {
+        let commit = timing::rev_parse(project_root, reference)
+            .with_context(|| format!("{} is not a git revision", reference))?;
+        match records
+            .iter()
+            .rev()
+            .find(|record| record.commit.as_ref() == Some(&commit) && !record.dirty)
+        {
+            Some(record) => record.clone(),
+            None => {
+                let Some(record) = build_revision(docker, project_root, reference, &commit)? else {
+                    return Ok(());
+                };
+                record
+            }
+        }
+    };
+
+    outln!(
+        "{}",
+        format!(
+            "==> Timing of {} compared with {}",
+            after.describe, before.describe
+        )
+        .blue()
+        .bold()
+    );
+    let clocks: BTreeSet<&String> = before
+        .fmax_mhz
+        .keys()
+        .chain(after.fmax_mhz.keys())
+        .collect();
+    let mut regressions = Vec::new();
+    for clock in clocks {
+        let (then, now) = (before.fmax_mhz.get(clock), after.fmax_mhz.get(clock));
+        let change = match (then, now) {
+            (Some(then), Some(now)) => {
+                if now < then {
+                    regressions.push(clock.as_str());
+                }
+                delta(now - then, |d| format!("{:+.2} MHz", d), true)
+            }
+            (None, Some(_)) => "new".dimmed().to_string(),
+            _ => "gone".dimmed().to_string(),
+        };
+        let fmax = |mhz: Option<&f64>| mhz.map_or("-".to_string(), |mhz| format!("{:.2}", mhz));
+        outln!(
+            "  {:<32} {:>8} -> {:>8} MHz  {}",
+            clock,
+            fmax(then),
+            fmax(now),
+            change
+        );
+        match (
+            before.critical_paths.get(clock),
+            after.critical_paths.get(clock),
+        ) {
+            (Some(then), Some(now)) => {
+                outln!(
+                    "    critical path {:.2} -> {:.2} ns  {}",
+                    then.delay_ns(),
+                    now.delay_ns(),
+                    delta(
+                        now.delay_ns() - then.delay_ns(),
+                        |d| format!("{:+.2} ns", d),
+                        false
+                    )
+                );
+                if (&then.from, &then.to) != (&now.from, &now.to) {
+                    outln!("    {} {} -> {}", "was".dimmed(), then.from, then.to);
+                    outln!("    {} {} -> {}", "now".dimmed(), now.from, now.to);
+                }
+            }
+            (_, Some(now)) => print_path(now),
+            _ => {}
+        }
+    }
+    output::report(
+        "timing",
+        json!({ "from": before, "to": after, "regressions": regressions }),
+    );
+
+    if regressions.is_empty() {
+        outln!("{}", "No Fmax regressions".green());
+    } else if fail_on_regression {
+        bail!("Fmax dropped for {}", regressions.join(", "));
+    }
+    Ok(())
+}
+
+fn print_path(path: &CriticalPath) {
+    outln!(
+        "    critical path {:.2} ns ({:.2} logic, {:.2} routing): {} -> {}",
+        path.delay_ns(),
+        path.logic_ns,
+        path.routing_ns,
+        path.from,
+        path.to
+    );
+}
+
+/// Build the FPGA design at `commit` in a temporary git worktree and archive
+/// its timing; None in a dry run
+fn build_revision(
+    docker: &Docker,
+    project_root: &Path,
+    reference: &str,
+    commit: &str,
+) -> Result<Option<Record>> {
+    outln!(
+        "{}",
+        format!(
+            "==> No timing archived for {}; building it in a temporary worktree",
+            reference
+        )
+        .blue()
+        .bold()
+    );
+    let top_level = PathBuf::from(git(project_root, &["rev-parse", "--show-toplevel"])?);
+    let subdir = project_root
+        .canonicalize()?
+        .strip_prefix(top_level.canonicalize()?)
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let worktree = std::env::temp_dir().join(format!("affogato-timing-{}", std::process::id()));
+    if dry_run::is_enabled() {
+        dry_run::note(&format!(
+            "git worktree add --detach {} {}, then build the FPGA design there",
+            worktree.display(),
+            commit
+        ));
+        return Ok(None);
+    }
+    docker.ensure_image()?;
+    git(
+        project_root,
+        &[
+            "worktree",
+            "add",
+            "--detach",
+            &worktree.to_string_lossy(),
+            commit,
+        ],
+    )?;
+    let result = (|| {
+        let project = Project::detect_at(worktree.join(&subdir))
+            .with_context(|| format!("{} has no Affogato project here", reference))?;
+        let worktree_root = project
+            .root
+            .as_ref()
+            .context("Not in an Affogato project")?;
+        // Taken before the build, whose generated files may dirty the tree
+        let describe = BuildInfo::current(worktree_root).describe;
+        build_fpga(docker, &project, &[], ReportFormat::Text)?;
+        let record = Record::current(worktree_root)
+            .with_context(|| format!("The build of {} left no timing report", reference))?;
+        Ok::<_, anyhow::Error>(Record {
+            describe,
+            dirty: false,
+            worktree: true,
+            ..record
+        })
+    })();
+    let _ = git(
+        project_root,
+        &["worktree", "remove", "--force", &worktree.to_string_lossy()],
+    );
+    let record = result?;
+    timing::append(project_root, &record)?;
+    Ok(Some(record))
+}
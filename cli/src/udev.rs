@@ -0,0 +1,107 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use affogato_core::dry_run;
+use affogato_core::outln;
+
+/// Where the rule is installed; numbered before systemd's 73-seat-late.rules
+const RULES_PATH: &str = "/etc/udev/rules.d/70-affogato.rules";
+
+/// Gives every user access to Espressif USB devices (VID 303a): the ESP32-S2's
+/// USB CDC console, its ROM download mode and DFU. ModemManager is told to
+/// leave the CDC port alone, as its probing garbles flashing. The FTDI
+/// FT2232H/FT232H (VID 0403) cover `affogato fpga --iceprog`.
+const RULES: &str = r#"# Installed by affogato setup-udev: non-root access to ESP32-S2 boards
+SUBSYSTEMS=="usb", ATTRS{idVendor}=="303a", ATTRS{idProduct}=="00??", MODE="0666", TAG+="uaccess", ENV{ID_MM_DEVICE_IGNORE}="1"
+# FTDI programmers for iceprog
+SUBSYSTEMS=="usb", ATTRS{idVendor}=="0403", ATTRS{idProduct}=="6010", MODE="0666", TAG+="uaccess"
+SUBSYSTEMS=="usb", ATTRS{idVendor}=="0403", ATTRS{idProduct}=="6014", MODE="0666", TAG+="uaccess"
+"#;
+
+/// Install the udev rule for the board (with sudo if not root), or print it
+pub fn setup(print: bool) -> Result<()> {
+    if print {
+        outln!("# {}", RULES_PATH);
+        outln!("{}", RULES.trim_end());
+        return Ok(());
+    }
+    if !cfg!(target_os = "linux") {
+        bail!("udev rules are only needed on Linux; the board is accessible without them here");
+    }
+
+    if fs::read_to_string(RULES_PATH).is_ok_and(|installed| installed == RULES) {
+        outln!(
+            "{}",
+            format!("Rule already installed at {}", RULES_PATH).green()
+        );
+        return Ok(());
+    }
+
+    outln!(
+        "{}",
+        format!("==> Installing udev rule to {}", RULES_PATH)
+            .blue()
+            .bold()
+    );
+    if !is_root() {
+        outln!(
+            "{}",
+            "This needs root; sudo may ask for your password".yellow()
+        );
+    }
+    privileged(&["tee", RULES_PATH], Some(RULES))?;
+    privileged(&["udevadm", "control", "--reload-rules"], None)?;
+    privileged(&["udevadm", "trigger", "--subsystem-match=usb"], None)?;
+
+    outln!("{}", "udev rule installed".green());
+    outln!("Unplug and reconnect the board to apply it.");
+    Ok(())
+}
+
+/// Run a command as root, through sudo unless already root, feeding it `input`
+fn privileged(args: &[&str], input: Option<&str>) -> Result<()> {
+    let mut command_line: Vec<&str> = args.to_vec();
+    if !is_root() {
+        command_line.insert(0, "sudo");
+    }
+    if dry_run::is_enabled() {
+        dry_run::note(&command_line.join(" "));
+        return Ok(());
+    }
+
+    let mut child = Command::new(command_line[0])
+        .args(&command_line[1..])
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::inherit()
+        })
+        .stdout(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run {}", command_line[0]))?;
+    if let Some(input) = input {
+        child
+            .stdin
+            .take()
+            .context("Failed to open stdin")?
+            .write_all(input.as_bytes())?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        bail!(
+            "{} failed; run affogato setup-udev --print to install the rule by hand",
+            command_line.join(" ")
+        );
+    }
+    Ok(())
+}
+
+fn is_root() -> bool {
+    Command::new("id")
+        .arg("-u")
+        .output()
+        .is_ok_and(|o| String::from_utf8_lossy(&o.stdout).trim() == "0")
+}
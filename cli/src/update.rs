@@ -0,0 +1,232 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use affogato_core::dry_run;
+use affogato_core::outln;
+use affogato_core::output;
+
+/// Checksums of every release asset, published alongside them
+pub const CHECKSUMS: &str = "SHA256SUMS";
+
+/// The parts of GitHub's release API response we use
+#[derive(Deserialize)]
+struct Release {
+    /// `r<commit count>`; the release version is `0.<commit count>.0`
+    tag_name: String,
+}
+
+/// Check GitHub releases for a newer affogato and, unless `check_only`,
+/// download it for this platform, verify its checksum and replace this executable
+pub fn self_update(check_only: bool) -> Result<()> {
+    let current = env!("CARGO_PKG_VERSION");
+    let tag = latest_tag()?;
+    let latest = release_version(&tag)?;
+    let newer = version_key(&latest) > version_key(current);
+    output::report(
+        "update",
+        json!({ "current": current, "latest": latest, "available": newer }),
+    );
+
+    if !newer {
+        outln!("{}", format!("affogato {} is up to date", current).green());
+        return Ok(());
+    }
+    if check_only {
+        outln!(
+            "{}",
+            format!(
+                "affogato {} is available (you have {}); run affogato self-update",
+                latest, current
+            )
+            .yellow()
+        );
+        return Ok(());
+    }
+
+    let exe = std::env::current_exe().context("Failed to find the affogato executable")?;
+    check_self_managed(&exe)?;
+    let target = target()?;
+    outln!(
+        "{}",
+        format!("==> Updating affogato {} to {}", current, latest)
+            .blue()
+            .bold()
+    );
+
+    let base = format!(
+        "https://github.com/{}/releases/download/{}",
+        repository(),
+        tag
+    );
+    let archive_name = format!("affogato-{}.tar.gz", target);
+    let archive = download(&format!("{}/{}", base, archive_name))?;
+    let checksums = String::from_utf8(download(&format!("{}/{}", base, CHECKSUMS))?)
+        .context("Invalid checksum file")?;
+    verify(&archive, &archive_name, &checksums).context("Not updating")?;
+    outln!("Checksum verified");
+
+    if dry_run::is_enabled() {
+        dry_run::note(&format!("replace {} with {}", exe.display(), archive_name));
+        return Ok(());
+    }
+    let dir = std::env::temp_dir().join(format!("affogato-update-{}", std::process::id()));
+    fs::create_dir_all(&dir)?;
+    let result = install(&archive, &dir, &exe);
+    let _ = fs::remove_dir_all(&dir);
+    result?;
+
+    outln!(
+        "{}",
+        format!("Updated to affogato {}", latest).green().bold()
+    );
+    Ok(())
+}
+
+/// The tag of the newest release on GitHub
+pub fn latest_tag() -> Result<String> {
+    let release: Release = serde_json::from_slice(&download(&format!(
+        "https://api.github.com/repos/{}/releases/latest",
+        repository()
+    ))?)
+    .context("Unexpected response from GitHub releases")?;
+    Ok(release.tag_name)
+}
+
+/// `owner/repo` on GitHub, from the package's repository URL
+pub fn repository() -> &'static str {
+    env!("CARGO_PKG_REPOSITORY")
+        .trim_start_matches("https://github.com/")
+        .trim_end_matches('/')
+}
+
+/// Fetch a URL with curl, which is on every platform releases are built for
+pub fn download(url: &str) -> Result<Vec<u8>> {
+    let output = Command::new("curl")
+        .args(["-fsSL", url])
+        .output()
+        .context("Failed to run curl; is it installed?")?;
+    if !output.status.success() {
+        bail!(
+            "Failed to download {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout)
+}
+
+/// The release tag this affogato was built for: `r<commit count>` from its
+/// `0.<commit count>.0` version
+pub fn release_tag() -> String {
+    let count = env!("CARGO_PKG_VERSION")
+        .split('.')
+        .nth(1)
+        .unwrap_or_default();
+    format!("r{}", count)
+}
+
+fn release_version(tag: &str) -> Result<String> {
+    let count = tag
+        .strip_prefix('r')
+        .filter(|count| count.parse::<u64>().is_ok())
+        .with_context(|| format!("Unrecognized release tag: {}", tag))?;
+    Ok(format!("0.{}.0", count))
+}
+
+/// A version's numeric parts, for comparison; build metadata is ignored
+fn version_key(version: &str) -> Vec<u64> {
+    version
+        .split('+')
+        .next()
+        .unwrap_or_default()
+        .split('.')
+        .map(|part| part.parse().unwrap_or(0))
+        .collect()
+}
+
+/// The release archive's target triple for this platform
+fn target() -> Result<String> {
+    let os = match std::env::consts::OS {
+        "linux" => "unknown-linux-gnu",
+        "macos" => "apple-darwin",
+        other => bail!("No release builds for {}; build from source instead", other),
+    };
+    let arch = match std::env::consts::ARCH {
+        arch @ ("x86_64" | "aarch64") => arch,
+        other => bail!("No release builds for {}; build from source instead", other),
+    };
+    Ok(format!("{}-{}", arch, os))
+}
+
+/// Refuse to replace a binary a package manager owns
+fn check_self_managed(exe: &Path) -> Result<()> {
+    let path = exe.display().to_string();
+    if path.contains("/Cellar/") || path.contains("/homebrew/") {
+        bail!("affogato was installed with Homebrew; run brew upgrade affogato instead");
+    }
+    if path.starts_with("/usr/bin/") {
+        bail!("affogato was installed from the .deb package; install the latest release's .deb instead");
+    }
+    Ok(())
+}
+
+/// Check a download against its line in the release's checksum file
+pub fn verify(archive: &[u8], name: &str, checksums: &str) -> Result<()> {
+    let expected = checksums
+        .lines()
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        .find(|(_, file)| file.trim().trim_start_matches('*') == name)
+        .map(|(sum, _)| sum.to_lowercase())
+        .with_context(|| format!("{} has no checksum for {}", CHECKSUMS, name))?;
+    let actual: String = Sha256::digest(archive)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    if actual != expected {
+        bail!(
+            "Checksum mismatch for {} (expected {}, got {})",
+            name,
+            expected,
+            actual
+        );
+    }
+    Ok(())
+}
+
+/// Unpack the archive and move its binary over `exe`
+fn install(archive: &[u8], dir: &Path, exe: &Path) -> Result<()> {
+    let archive_path = dir.join("affogato.tar.gz");
+    fs::write(&archive_path, archive)?;
+    let status = Command::new("tar")
+        .arg("xzf")
+        .arg(&archive_path)
+        .arg("-C")
+        .arg(dir)
+        .status()
+        .context("Failed to run tar")?;
+    if !status.success() {
+        bail!("Failed to unpack the release archive");
+    }
+
+    // Stage next to the executable so the final rename stays on one filesystem
+    let staged = exe.with_extension("new");
+    fs::copy(dir.join("affogato"), &staged).with_context(|| {
+        format!(
+            "Failed to write to {}; rerun with sudo if it is a system directory",
+            exe.parent().unwrap_or(exe).display()
+        )
+    })?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))?;
+    }
+    fs::rename(&staged, exe).with_context(|| format!("Failed to replace {}", exe.display()))?;
+    Ok(())
+}
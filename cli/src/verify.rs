@@ -0,0 +1,241 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use affogato_core::build_info::{self, BuildInfo};
+use affogato_core::docker::Docker;
+use affogato_core::project::Project;
+use affogato_core::{device, dry_run, outln, output};
+
+use crate::package::{self, MANIFEST};
+use crate::signing::{self, Algorithm};
+
+/// Check that the board on `port` runs exactly the release in `bundle`: an
+/// `affogato package` archive, its unpacked directory or its manifest.json.
+/// With a public `key`, first check the bundle is signed by it and unaltered;
+/// without a `port`, stop there.
+pub fn run(
+    docker: &Docker,
+    project: &Project,
+    bundle: &Path,
+    key: Option<&Path>,
+    port: Option<&str>,
+) -> Result<()> {
+    let manifest = match key {
+        Some(key) => check_signatures(bundle, key)?,
+        None => load_manifest(bundle)?,
+    };
+    let Some(port) = port else {
+        return Ok(());
+    };
+
+    // Field service may run this outside any project; the query then runs in
+    // a scratch directory, so nothing (such as its log) is left behind
+    if project.root.is_none() {
+        let scratch = std::env::temp_dir().join(format!("affogato-verify-{}", std::process::id()));
+        fs::create_dir_all(&scratch)?;
+        let scratch_project = Project {
+            root: Some(scratch.clone()),
+            name: None,
+            config: None,
+        };
+        let result = check(docker, &scratch_project, &manifest, port);
+        let _ = fs::remove_dir_all(&scratch);
+        return result;
+    }
+    check(docker, project, &manifest, port)
+}
+
+fn check(docker: &Docker, project: &Project, manifest: &Value, port: &str) -> Result<()> {
+    let release = format!(
+        "{} {}",
+        manifest["name"].as_str().unwrap_or("unknown"),
+        manifest["version"].as_str().unwrap_or("unknown")
+    );
+    let sha256_of = |matches: &dyn Fn(&str) -> bool| {
+        manifest["files"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|file| file["file"].as_str().is_some_and(matches))
+            .and_then(|file| file["sha256"].as_str())
+            .map(str::to_string)
+    };
+    let elf = sha256_of(&|name| name.ends_with(".elf"))
+        .context("The manifest lists no ELF to check the firmware against")?;
+    let bitstream = sha256_of(&|name| name == "top.bin")
+        .context("The manifest lists no top.bin to check the bitstream against")?;
+
+    outln!(
+        "{}",
+        format!("==> Checking {} against {}", port, release)
+            .blue()
+            .bold()
+    );
+    docker.ensure_image()?;
+    let reply = device::query(
+        docker,
+        project,
+        port,
+        build_info::QUERY,
+        build_info::REPLY_PREFIX,
+        device::REPLY_TIMEOUT,
+    )?;
+    if dry_run::is_enabled() {
+        return Ok(());
+    }
+    let fields = BuildInfo::parse_reply(&reply);
+    let field = |key: &str| {
+        fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.clone())
+    };
+
+    // The loader hashes what it actually loaded; older firmware only reports
+    // the bitstream it embeds
+    let (bitstream_label, running_bitstream) = match field("loaded") {
+        Some(loaded) => ("Bitstream", Some(loaded)),
+        None => ("Bitstream (embedded)", field("bitstream")),
+    };
+    let checks = [
+        ("Firmware ELF", field("elf"), elf),
+        (bitstream_label, running_bitstream, bitstream),
+    ];
+
+    let mut matches = true;
+    let mut entries = Vec::new();
+    for (label, actual, expected) in &checks {
+        let same = actual.as_deref() == Some(expected.as_str());
+        matches &= same;
+        let shown = actual.as_deref().unwrap_or("not reported");
+        if same {
+            outln!("  {} {:<21}{}", "✓".green(), label, shown);
+        } else {
+            outln!("  {} {:<21}{}", "✗".red(), label, shown);
+            outln!("    {:<21}{}", "expected", expected.dimmed());
+        }
+        entries.push(json!({
+            "check": label,
+            "device": actual,
+            "expected": expected,
+            "matches": same,
+        }));
+    }
+    output::report(
+        "verify",
+        json!({ "release": release, "matches": matches, "checks": entries }),
+    );
+
+    if !matches {
+        bail!("The board on {} is not running {}", port, release);
+    }
+    outln!("{}", format!("The board runs {}", release).green().bold());
+    Ok(())
+}
+
+/// Check the signed files of a bundle against `key` and every file against
+/// the manifest's hashes, returning the manifest once it can be trusted
+fn check_signatures(bundle: &Path, key: &Path) -> Result<Value> {
+    if !bundle.to_string_lossy().ends_with(".tar.gz") {
+        let dir = if bundle.is_dir() {
+            bundle
+        } else {
+            bundle.parent().unwrap_or(Path::new("."))
+        };
+        return check_signed_dir(dir, key);
+    }
+
+    let scratch =
+        std::env::temp_dir().join(format!("affogato-verify-bundle-{}", std::process::id()));
+    fs::create_dir_all(&scratch)?;
+    let result = tar(bundle, "xzf", &["-C", &scratch.to_string_lossy()]).and_then(|_| {
+        // The archive holds a single <name>-<version>/ directory
+        let dir = fs::read_dir(&scratch)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .find(|dir| dir.join(MANIFEST).exists())
+            .unwrap_or_else(|| scratch.clone());
+        check_signed_dir(&dir, key)
+    });
+    let _ = fs::remove_dir_all(&scratch);
+    result
+}
+
+fn check_signed_dir(dir: &Path, key: &Path) -> Result<Value> {
+    outln!(
+        "{}",
+        format!("==> Checking signatures with {}", key.display())
+            .blue()
+            .bold()
+    );
+    let manifest = load_manifest(dir)?;
+    let signing = &manifest["signing"];
+    let algorithm = match signing["algorithm"].as_str() {
+        Some(name) => Algorithm::from_name(name)?,
+        None => bail!("{} is not signed; package it with --sign", dir.display()),
+    };
+
+    // The manifest's signature vouches for the hashes of everything else
+    signing::verify(algorithm, key, &dir.join(MANIFEST))?;
+    for file in signing["signed"].as_array().into_iter().flatten() {
+        let Some(file) = file.as_str().filter(|file| *file != MANIFEST) else {
+            continue;
+        };
+        signing::verify(algorithm, key, &dir.join(file))?;
+    }
+    outln!("  {} {:<21}{}", "✓".green(), "Signature", algorithm.name());
+
+    let mut checked = 0;
+    for file in manifest["files"].as_array().into_iter().flatten() {
+        let (Some(name), Some(expected)) = (file["file"].as_str(), file["sha256"].as_str()) else {
+            continue;
+        };
+        let data = fs::read(dir.join(name))
+            .with_context(|| format!("{} is missing from the bundle", name))?;
+        if package::sha256(&data) != expected {
+            bail!("{} does not match the signed manifest", name);
+        }
+        checked += 1;
+    }
+    outln!("  {} {:<21}{} files", "✓".green(), "Contents", checked);
+    Ok(manifest)
+}
+
+fn load_manifest(bundle: &Path) -> Result<Value> {
+    let text = if bundle.is_dir() {
+        fs::read_to_string(bundle.join(MANIFEST))
+            .with_context(|| format!("No manifest.json in {}", bundle.display()))?
+    } else if bundle.to_string_lossy().ends_with(".tar.gz") {
+        manifest_from_archive(bundle)?
+    } else {
+        fs::read_to_string(bundle)
+            .with_context(|| format!("Failed to read {}", bundle.display()))?
+    };
+    serde_json::from_str(&text).with_context(|| format!("Invalid manifest in {}", bundle.display()))
+}
+
+fn manifest_from_archive(archive: &Path) -> Result<String> {
+    let listing = tar(archive, "tzf", &[])?;
+    let entry = listing
+        .lines()
+        .find(|entry| entry.ends_with("/manifest.json") || *entry == "manifest.json")
+        .with_context(|| format!("No manifest.json in {}", archive.display()))?;
+    tar(archive, "xzOf", &[entry])
+}
+
+/// Run tar in `mode` on the archive, returning its output
+fn tar(archive: &Path, mode: &str, args: &[&str]) -> Result<String> {
+    let output = Command::new("tar")
+        .arg(mode)
+        .arg(archive)
+        .args(args)
+        .output()
+        .context("Failed to run tar")?;
+    if !output.status.success() {
+        bail!("Failed to read {}", archive.display());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -0,0 +1,133 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use serde_json::json;
+use std::time::{Duration, UNIX_EPOCH};
+
+use affogato_core::build_info::{self, BuildInfo};
+use affogato_core::docker::Docker;
+use affogato_core::project::Project;
+use affogato_core::{device, dry_run, outln, output};
+
+/// Show affogato's version and the last build's metadata; with `port`, also
+/// ask the board what it runs and check it against that build
+pub fn run(docker: &Docker, project: &Project, port: Option<&str>) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+    let local = BuildInfo::load(project_root)?;
+
+    outln!("{:<13}{}", "affogato", env!("CARGO_PKG_VERSION"));
+    match &local {
+        Some(info) => {
+            outln!(
+                "{:<13}{} ({})",
+                "Build",
+                info.describe,
+                time(info.timestamp)
+            );
+            outln!("{:<13}{:08x}", "  commit", info.commit);
+            outln!(
+                "{:<13}{}",
+                "  bitstream",
+                info.bitstream_sha256.as_deref().unwrap_or("unknown")
+            );
+        }
+        None => outln!("{:<13}{}", "Build", "none yet; run affogato build".yellow()),
+    }
+
+    let Some(port) = port else {
+        output::report(
+            "version",
+            json!({ "affogato": env!("CARGO_PKG_VERSION"), "build": local }),
+        );
+        return Ok(());
+    };
+
+    outln!(
+        "{}",
+        format!("==> Reading the build from {}", port).blue().bold()
+    );
+    docker.ensure_image()?;
+    let reply = device::query(
+        docker,
+        project,
+        port,
+        build_info::QUERY,
+        build_info::REPLY_PREFIX,
+        device::REPLY_TIMEOUT,
+    )?;
+    if dry_run::is_enabled() {
+        return Ok(());
+    }
+    let fields = BuildInfo::parse_reply(&reply);
+    let field = |key: &str| {
+        fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    };
+
+    let expected = local.as_ref();
+    // (label, reply field, what this build would report)
+    let checks = [
+        (
+            "Firmware",
+            "firmware",
+            expected.map(|info| info.describe.clone()),
+        ),
+        (
+            "  bitstream",
+            "bitstream",
+            expected.and_then(|info| info.bitstream_sha256.clone()),
+        ),
+        (
+            "FPGA commit",
+            "fpga_commit",
+            expected.map(|info| format!("{:08x}", info.commit)),
+        ),
+        (
+            "  time",
+            "fpga_time",
+            expected.map(|info| (info.timestamp as u32).to_string()),
+        ),
+    ];
+
+    let mut mismatched = false;
+    let mut device = serde_json::Map::new();
+    for (label, key, expected) in &checks {
+        let Some(actual) = field(key) else {
+            outln!("{:<13}{}", label, "not reported".dimmed());
+            continue;
+        };
+        device.insert(key.to_string(), json!(actual));
+        let mark = match expected {
+            Some(expected) if expected == actual => "✓ matches this build".green(),
+            Some(_) => {
+                mismatched = true;
+                "✗ differs from this build".red()
+            }
+            None => "".normal(),
+        };
+        outln!("{:<13}{}  {}", label, actual, mark);
+    }
+    if !device.contains_key("fpga_commit") {
+        outln!(
+            "{}",
+            "Define affogato_fpga_build_id() in the firmware to read the FPGA's build id".dimmed()
+        );
+    }
+    output::report(
+        "version",
+        json!({ "affogato": env!("CARGO_PKG_VERSION"), "build": local, "device": device }),
+    );
+
+    if mismatched {
+        bail!("The board is not running this build; run affogato flash");
+    }
+    Ok(())
+}
+
+fn time(timestamp: u64) -> String {
+    humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(timestamp)).to_string()
+}
@@ -1,40 +1,131 @@
 use anyhow::{Context, Result};
 use colored::Colorize;
-use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher};
-use std::sync::mpsc::channel;
+use notify::{Config, Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
 use std::time::{Duration, Instant};
+use tracing::warn;
 
-use crate::docker::Docker;
-use crate::project::Project;
+use affogato_core::annotate::ReportFormat;
+use affogato_core::build::{build_firmware, build_fpga, check_fpga};
+use affogato_core::delivery;
+use affogato_core::docker::{require_device, BackgroundContainer, Docker};
+use affogato_core::interrupt;
+use affogato_core::outln;
+use affogato_core::project::{Project, WatchConfig};
+use affogato_core::test::{self, TestOptions};
+
+/// Build outputs that would otherwise retrigger the build that wrote them
+const BUILD_ARTIFACTS: &[&str] = &[
+    ".git/",
+    ".affogato/",
+    "firmware/build/",
+    "firmware/managed_components/",
+    "fpga/build/",
+    "firmware/main/affogato_build.h",
+    "firmware/main/affogato_console.h",
+    "**/__pycache__/",
+    "**/CMakeFiles/",
+    "*.json",
+    "*.asc",
+    "*.bin",
+    "*.blif",
+    "*.vcd",
+    "*.fst",
+    "*.vvp",
+    "sdkconfig.old",
+    "*.swp",
+    "*~",
+];
+
+/// Events arriving within this window of each other are handled as one change
+const COALESCE_WINDOW: Duration = Duration::from_millis(300);
+
+pub struct WatchOptions {
+    /// Only rebuild the FPGA bitstream
+    pub fpga_only: bool,
+    /// Serial port to flash and monitor after each successful build
+    pub flash: Option<String>,
+    /// Only run the elaboration check on changes; full builds on request
+    pub check: bool,
+    /// Show desktop notifications (unless disabled in `[watch]`)
+    pub notify: bool,
+}
+
+/// What the watcher channel carries
+enum Message {
+    File(notify::Result<Event>),
+    /// A command typed into the watch session
+    Command(String),
+}
+
+/// What woke the watch loop
+enum Trigger {
+    Changed(Vec<PathBuf>),
+    Command(String),
+}
 
 /// Run watch mode - rebuild on file changes
-pub fn run_watch(docker: &Docker, project: &Project, fpga_only: bool) -> Result<()> {
+pub fn run_watch(docker: &Docker, project: &Project, opts: &WatchOptions) -> Result<()> {
+    let fpga_only = opts.fpga_only;
     let project_root = project
         .root
         .as_ref()
         .context("Not in an Affogato project")?;
+    if let Some(port) = &opts.flash {
+        require_device(port)?;
+    }
 
     let fpga_dir = project_root.join("fpga");
     let firmware_dir = project_root.join("firmware");
+    let config = watch_config(project);
+    let notifier = Notifier {
+        enabled: opts.notify && config.notify,
+    };
 
-    println!("{}", "==> Starting watch mode".blue().bold());
-    println!("Watching for changes in:");
+    outln!("{}", "==> Starting watch mode".blue().bold());
+    outln!("Watching for changes in:");
     if fpga_dir.exists() {
-        println!("  - fpga/");
+        outln!("  - fpga/");
     }
     if !fpga_only && firmware_dir.exists() {
-        println!("  - firmware/");
+        outln!("  - firmware/");
+    }
+    for path in &config.extra_paths {
+        outln!("  - {}", path);
+    }
+    outln!();
+    if opts.check {
+        outln!("{}", "Type b + Enter for a full build".yellow());
+    }
+    outln!("{}", "Press Ctrl+C to stop".yellow());
+    outln!();
+
+    interrupt::handle_in_loop();
+    let (tx, rx) = channel();
+    if opts.check {
+        read_commands(tx.clone());
     }
-    println!();
-    println!("{}", "Press Ctrl+C to stop".yellow());
-    println!();
 
-    // Initial build
-    run_build(docker, project, fpga_only)?;
+    // Serial monitor between flashes, stopped to free the port
+    let mut monitor = None;
+
+    // Initial build; a broken tree is what watch mode is for, so keep going
+    let started = Instant::now();
+    if opts.check {
+        notifier.finish("Check", run_check(docker, project), started);
+    } else if let Some(port) = &opts.flash {
+        // The device may hold anything, so the first flash is a full one
+        let result = run_build(docker, project, false)
+            .and_then(|()| flash_and_monitor(docker, project, port, true, &mut monitor));
+        notifier.finish("Build and flash", result, started);
+    } else {
+        notifier.finish("Build", run_build(docker, project, fpga_only), started);
+    }
 
     // Set up file watcher
-    let (tx, rx) = channel();
-    let mut watcher = RecommendedWatcher::new(tx, Config::default())?;
+    let mut watcher = file_watcher(tx)?;
 
     // Watch fpga directory
     if fpga_dir.exists() {
@@ -46,62 +137,373 @@ pub fn run_watch(docker: &Docker, project: &Project, fpga_only: bool) -> Result<
         watcher.watch(&firmware_dir, RecursiveMode::Recursive)?;
     }
 
-    // Debounce settings
-    let debounce_duration = Duration::from_millis(500);
-    let mut last_build = Instant::now() - debounce_duration;
+    watch_extra_paths(&mut watcher, project_root, &config)?;
 
-    loop {
-        match rx.recv() {
-            Ok(event) => {
-                if let Ok(event) = event {
-                    // Skip non-modify events and build artifacts
-                    if !should_trigger_rebuild(&event) {
-                        continue;
-                    }
-
-                    // Debounce rapid changes
-                    let now = Instant::now();
-                    if now.duration_since(last_build) < debounce_duration {
-                        continue;
-                    }
-                    last_build = now;
-
-                    // Determine what changed
-                    let changed_path = event.paths.first();
-                    let is_fpga_change = changed_path
-                        .map(|p| p.starts_with(&fpga_dir))
-                        .unwrap_or(false);
-
-                    println!();
-                    if let Some(path) = changed_path {
-                        let relative = path.strip_prefix(project_root).unwrap_or(path);
-                        println!(
-                            "{}",
-                            format!("Change detected: {}", relative.display())
-                                .yellow()
-                                .bold()
-                        );
-                    }
-
-                    // Run appropriate build
-                    if is_fpga_change {
-                        if let Err(e) = run_fpga_build(docker, project) {
-                            println!("{}", format!("FPGA build failed: {}", e).red());
-                        }
-                    } else if !fpga_only {
-                        if let Err(e) = run_build(docker, project, fpga_only) {
-                            println!("{}", format!("Build failed: {}", e).red());
-                        }
-                    }
+    let accept =
+        |event: &Event| should_trigger_rebuild(event) && !all_ignored(project_root, &config, event);
+
+    while let Some(trigger) = next_change(&rx, accept) {
+        let started = Instant::now();
+        let changed = match trigger {
+            Trigger::Changed(changed) => changed,
+            Trigger::Command(command) => {
+                if command == "b" {
+                    outln!();
+                    notifier.finish("Build", run_build(docker, project, fpga_only), started);
+                } else {
+                    outln!("{}", format!("Unknown command: {}", command).yellow());
                 }
+                continue;
+            }
+        };
+        print_changes(project_root, &changed);
+
+        if opts.check {
+            if changed.iter().any(|p| p.starts_with(&fpga_dir)) {
+                notifier.finish("Check", run_check(docker, project), started);
+            } else {
+                outln!("{}", "Type b + Enter to build the firmware".dimmed());
             }
-            Err(e) => {
-                println!("{}", format!("Watch error: {}", e).red());
+            continue;
+        }
+
+        // The firmware loads the bitstream, so flashing needs both
+        if let Some(port) = &opts.flash {
+            let full = changed.iter().any(|p| needs_full_flash(p));
+            let result = run_build(docker, project, false)
+                .and_then(|()| flash_and_monitor(docker, project, port, full, &mut monitor));
+            notifier.finish("Build and flash", result, started);
+        } else if fpga_only || changed.iter().all(|p| p.starts_with(&fpga_dir)) {
+            notifier.finish("FPGA build", run_fpga_build(docker, project), started);
+        } else {
+            notifier.finish("Build", run_build(docker, project, fpga_only), started);
+        }
+    }
+
+    outln!();
+    outln!("{}", "Watch mode stopped".blue().bold());
+    Ok(())
+}
+
+/// Run test watch mode - rerun affected testbenches on file changes
+pub fn run_test_watch(
+    docker: &Docker,
+    project: &Project,
+    opts: &TestOptions,
+    notify: bool,
+) -> Result<()> {
+    let project_root = project
+        .root
+        .as_ref()
+        .context("Not in an Affogato project")?;
+
+    let fpga_dir = project_root.join(&opts.fpga_dir);
+    let config = watch_config(project);
+    let notifier = Notifier {
+        enabled: notify && config.notify,
+    };
+
+    outln!("{}", "==> Starting test watch mode".blue().bold());
+    outln!("Watching for changes in:");
+    outln!("  - {}/", opts.fpga_dir);
+    for path in &config.extra_paths {
+        outln!("  - {}", path);
+    }
+    outln!();
+    outln!("{}", "Press Ctrl+C to stop".yellow());
+    outln!();
+
+    interrupt::handle_in_loop();
+
+    // Initial run
+    let started = Instant::now();
+    notifier.finish("Tests", test::run_tests(docker, project, opts), started);
+    print_rolling_status(project, opts);
+
+    let (tx, rx) = channel();
+    let mut watcher = file_watcher(tx)?;
+    watcher.watch(&fpga_dir, RecursiveMode::Recursive)?;
+    watch_extra_paths(&mut watcher, project_root, &config)?;
+
+    let accept =
+        |event: &Event| should_trigger_test(event) && !all_ignored(project_root, &config, event);
+
+    while let Some(trigger) = next_change(&rx, accept) {
+        let Trigger::Changed(changed) = trigger else {
+            continue;
+        };
+        print_changes(project_root, &changed);
+
+        let started = Instant::now();
+        let result = test::run_affected(docker, project, opts, &changed);
+        notifier.finish("Tests", result, started);
+        print_rolling_status(project, opts);
+    }
+
+    outln!();
+    outln!("{}", "Watch mode stopped".blue().bold());
+    Ok(())
+}
+
+/// One-line pass/fail status across all tests, including those not rerun
+fn print_rolling_status(project: &Project, opts: &TestOptions) {
+    let status = match test::rolling_status(project, opts) {
+        Ok(status) => status,
+        Err(e) => {
+            warn!("Could not read test status: {:#}", e);
+            return;
+        }
+    };
+    let passing = status.iter().filter(|(_, s)| *s == Some(true)).count();
+    let failing: Vec<&str> = status
+        .iter()
+        .filter(|(_, s)| *s == Some(false))
+        .map(|(name, _)| name.as_str())
+        .collect();
+    let not_run = status.len() - passing - failing.len();
+
+    outln!();
+    let mut line = format!(
+        "{} {} passing, {} failing",
+        "Status:".bold(),
+        passing.to_string().green(),
+        failing.len().to_string().red()
+    );
+    if not_run > 0 {
+        line.push_str(&format!(", {} not run", not_run));
+    }
+    if !failing.is_empty() {
+        line.push_str(&format!(" ({})", failing.join(", ")));
+    }
+    outln!("{}", line);
+}
+
+/// Flash the firmware and (re)start the serial monitor in the background
+fn flash_and_monitor(
+    docker: &Docker,
+    project: &Project,
+    port: &str,
+    full: bool,
+    monitor: &mut Option<BackgroundContainer>,
+) -> Result<()> {
+    // Release the serial port before flashing
+    *monitor = None;
+
+    // app-flash skips the bootloader and partition table, which rarely change
+    let target = if full {
+        "flash".to_string()
+    } else {
+        let delivery = project
+            .config
+            .as_ref()
+            .map(|c| c.firmware.bitstream)
+            .unwrap_or_default();
+        delivery::app_flash_targets(delivery)
+    };
+    outln!(
+        "{}",
+        format!("==> Flashing to {} ({})", port, target)
+            .blue()
+            .bold()
+    );
+    let cmd = format!("cd firmware && idf.py -p {} {}", port, target);
+    docker.run_in_project(project, &["bash", "-c", &cmd], &[], true)?;
+
+    outln!("{}", format!("==> Monitoring {}", port).blue().bold());
+    let cmd = format!("cd firmware && idf.py -p {} monitor", port);
+    *monitor = Some(docker.spawn_in_project(project, &["bash", "-c", &cmd], true)?);
+    Ok(())
+}
+
+/// Changes to the bootloader, partition table or sdkconfig need a full flash
+fn needs_full_flash(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    name.starts_with("sdkconfig")
+        || name == "Kconfig"
+        || (name.starts_with("partitions") && name.ends_with(".csv"))
+}
+
+/// A file watcher feeding the watch loop's channel
+fn file_watcher(tx: Sender<Message>) -> Result<RecommendedWatcher> {
+    let watcher = RecommendedWatcher::new(
+        move |event| {
+            let _ = tx.send(Message::File(event));
+        },
+        Config::default(),
+    )?;
+    Ok(watcher)
+}
+
+/// Forward lines typed on stdin to the watch loop
+fn read_commands(tx: Sender<Message>) {
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            let command = line.trim().to_lowercase();
+            if !command.is_empty() && tx.send(Message::Command(command)).is_err() {
+                break;
             }
         }
+    });
+}
+
+/// Reports the outcome of each watch-triggered step
+struct Notifier {
+    enabled: bool,
+}
+
+impl Notifier {
+    /// Print a failed step, notify the desktop, and carry on watching
+    fn finish(&self, what: &str, result: Result<()>, started: Instant) {
+        if let Err(e) = &result {
+            outln!("{}", format!("{} failed: {}", what, e).red());
+        }
+        if !self.enabled {
+            return;
+        }
+
+        let elapsed = format!("{:.1}s", started.elapsed().as_secs_f64());
+        let (summary, body) = match &result {
+            Ok(()) => (
+                format!("{} succeeded", what),
+                format!("Finished in {}", elapsed),
+            ),
+            Err(e) => (
+                format!("{} failed", what),
+                format!("{} after {}", e, elapsed),
+            ),
+        };
+        // Headless machines and containers have no notification daemon; that's fine
+        let _ = notify_rust::Notification::new()
+            .appname("affogato")
+            .summary(&summary)
+            .body(&body)
+            .show();
     }
 }
 
+fn watch_config(project: &Project) -> WatchConfig {
+    project
+        .config
+        .as_ref()
+        .map(|c| c.watch.clone())
+        .unwrap_or_default()
+}
+
+/// Watch the `[watch] extra_paths` entries that exist
+fn watch_extra_paths(
+    watcher: &mut RecommendedWatcher,
+    project_root: &Path,
+    config: &WatchConfig,
+) -> Result<()> {
+    for path in &config.extra_paths {
+        let full = project_root.join(path);
+        if !full.exists() {
+            warn!("Watch path not found: {}", path);
+            continue;
+        }
+        let mode = if full.is_dir() {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        watcher
+            .watch(&full, mode)
+            .with_context(|| format!("Failed to watch {}", path))?;
+    }
+    Ok(())
+}
+
+/// Whether every path in the event is a build artifact or matches `[watch] ignore`
+fn all_ignored(project_root: &Path, config: &WatchConfig, event: &Event) -> bool {
+    event.paths.iter().all(|path| {
+        let relative = path.strip_prefix(project_root).unwrap_or(path);
+        let relative = relative.to_string_lossy();
+        BUILD_ARTIFACTS
+            .iter()
+            .copied()
+            .chain(config.ignore.iter().map(String::as_str))
+            .any(|pattern| matches_pattern(&relative, pattern))
+    })
+}
+
+/// Match a path against a directory ("build/"), file or glob pattern
+fn matches_pattern(path: &str, pattern: &str) -> bool {
+    let dir = pattern.trim_end_matches('/');
+    path == dir
+        || path.starts_with(&format!("{}/", dir))
+        || path.contains(&format!("/{}/", dir.trim_start_matches("**/")))
+        || glob::Pattern::new(pattern).is_ok_and(|p| p.matches(path))
+}
+
+/// Wait for a relevant change, then collect the burst of events that follows
+/// (editors and `git checkout` touch many files at once) into one set of paths.
+/// Returns `None` once Ctrl+C has been pressed.
+fn next_change(rx: &Receiver<Message>, accept: impl Fn(&Event) -> bool) -> Option<Trigger> {
+    let mut changed = BTreeSet::new();
+    while changed.is_empty() {
+        match next_message(rx)? {
+            Message::File(Ok(event)) if accept(&event) => changed.extend(event.paths),
+            Message::File(Ok(_)) => {}
+            Message::File(Err(e)) => outln!("{}", format!("Watch error: {}", e).red()),
+            Message::Command(command) => return Some(Trigger::Command(command)),
+        }
+    }
+
+    loop {
+        match rx.recv_timeout(COALESCE_WINDOW) {
+            Ok(Message::File(Ok(event))) if accept(&event) => changed.extend(event.paths),
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+    Some(Trigger::Changed(changed.into_iter().collect()))
+}
+
+fn print_changes(project_root: &Path, changed: &[PathBuf]) {
+    outln!();
+    for path in changed {
+        let relative = path.strip_prefix(project_root).unwrap_or(path);
+        outln!(
+            "{}",
+            format!("Change detected: {}", relative.display())
+                .yellow()
+                .bold()
+        );
+    }
+}
+
+/// Wait for the next watcher event or command, or `None` once Ctrl+C has been pressed
+fn next_message(rx: &Receiver<Message>) -> Option<Message> {
+    while !interrupt::requested() {
+        match rx.recv_timeout(Duration::from_millis(200)) {
+            Ok(event) => return Some(event),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return None,
+        }
+    }
+    None
+}
+
+/// Check if this event should trigger a test rerun
+fn should_trigger_test(event: &notify::Event) -> bool {
+    use notify::EventKind;
+
+    if !matches!(event.kind, EventKind::Modify(_) | EventKind::Create(_)) {
+        return false;
+    }
+
+    event.paths.iter().any(|path| {
+        path.extension()
+            .is_some_and(|ext| matches!(ext.to_string_lossy().as_ref(), "v" | "sv" | "vh"))
+    })
+}
+
 /// Check if this event should trigger a rebuild
 fn should_trigger_rebuild(event: &notify::Event) -> bool {
     use notify::EventKind;
@@ -135,11 +537,19 @@ fn should_trigger_rebuild(event: &notify::Event) -> bool {
     false
 }
 
+/// Parse and elaborate the FPGA design without synthesis
+pub fn run_check(docker: &Docker, project: &Project) -> Result<()> {
+    outln!("{}", "==> Checking FPGA design".blue().bold());
+    check_fpga(docker, project)?;
+    outln!("{}", "Check passed".green());
+    Ok(())
+}
+
 /// Run FPGA build only
 fn run_fpga_build(docker: &Docker, project: &Project) -> Result<()> {
-    println!("{}", "==> Building FPGA bitstream".blue().bold());
-    docker.run_in_project(project, &["make", "-C", "fpga"], &[], false)?;
-    println!("{}", "FPGA build complete".green());
+    outln!("{}", "==> Building FPGA bitstream".blue().bold());
+    build_fpga(docker, project, &[], ReportFormat::Text)?;
+    outln!("{}", "FPGA build complete".green());
     Ok(())
 }
 
@@ -150,14 +560,9 @@ fn run_build(docker: &Docker, project: &Project, fpga_only: bool) -> Result<()>
 
     if !fpga_only {
         // Build firmware
-        println!("{}", "==> Building ESP32 firmware".blue().bold());
-        docker.run_in_project(
-            project,
-            &["bash", "-c", "cd firmware && idf.py build"],
-            &[],
-            false,
-        )?;
-        println!("{}", "Firmware build complete".green());
+        outln!("{}", "==> Building ESP32 firmware".blue().bold());
+        build_firmware(docker, project, &[], ReportFormat::Text)?;
+        outln!("{}", "Firmware build complete".green());
     }
 
     Ok(())
@@ -4,26 +4,38 @@
 #include <driver/gpio.h>
 #include <driver/spi_master.h>
 #include <esp_log.h>
+#include <esp_partition.h>
 #include <freertos/FreeRTOS.h>
 #include <freertos/semphr.h>
 #include <freertos/task.h>
 #include <soc/gpio_sig_map.h>
 #include <soc/soc.h>
+#include <soc/spi_periph.h>
 #include <rom/gpio.h>
+#include <mbedtls/sha256.h>
 
 #include <string.h>
 #include <sys/stat.h>
 
 #define LOADER_BUFFER_SIZE (CONFIG_FPGA_SPI_BUFFER_SIZE * 4)
 
+// The SPI peripheral's hardware chip select, routed back to the CS pin after
+// the loader drives it by hand
+#define FPGA_SPI_CS_SIGNAL (spi_periph_signal[FPGA_SPI_HOST].spics_out[0])
+
 static const char *TAG = "ice40_loader";
 
 static spi_device_handle_t fpga_update_device = NULL;
 
+// SHA-256 of the last bitstream loaded successfully
+static uint8_t loaded_sha256[32];
+static bool loaded = false;
+
 typedef struct {
     size_t size;
     void *ctx;
     size_t (*read)(void *buffer, size_t size, void *ctx);
+    void (*rewind)(void *ctx);
 } firmware_source_t;
 
 static esp_err_t update_spi_device_add(void)
@@ -39,7 +51,7 @@ static esp_err_t update_spi_device_add(void)
         .flags = SPI_DEVICE_HALFDUPLEX,
     };
 
-    return spi_bus_add_device(FSPI_HOST, &devcfg, &fpga_update_device);
+    return spi_bus_add_device(FPGA_SPI_HOST, &devcfg, &fpga_update_device);
 }
 
 static esp_err_t update_spi_device_remove(void)
@@ -88,7 +100,7 @@ static esp_err_t cdone_pin_wait(bool value, uint32_t timeout_ms)
     return ESP_OK;
 }
 
-static esp_err_t fpga_loader_load(firmware_source_t *source)
+static esp_err_t cram_load(firmware_source_t *source)
 {
     esp_err_t ret;
 
@@ -145,6 +157,11 @@ static esp_err_t fpga_loader_load(firmware_source_t *source)
     size_t remaining = source->size;
     ESP_LOGI(TAG, "Loading %d bytes", remaining);
 
+    mbedtls_sha256_context sha;
+    mbedtls_sha256_init(&sha);
+    mbedtls_sha256_starts(&sha, 0);
+    loaded = false;
+
     while (remaining > 0) {
         size_t chunk = (remaining > LOADER_BUFFER_SIZE) ? LOADER_BUFFER_SIZE : remaining;
 
@@ -155,6 +172,7 @@ static esp_err_t fpga_loader_load(firmware_source_t *source)
             break;
         }
 
+        mbedtls_sha256_update(&sha, buffer, chunk);
         ret = write_update_block(buffer, chunk);
         if (ret != ESP_OK) {
             ESP_LOGE(TAG, "Write error");
@@ -179,13 +197,16 @@ static esp_err_t fpga_loader_load(firmware_source_t *source)
 
     // Step 10: Restore CS to hardware control
     gpio_set_level(CONFIG_FPGA_CS_GPIO, 1);
-    gpio_matrix_out(CONFIG_FPGA_CS_GPIO, FSPICS0_OUT_IDX, false, false);
+    gpio_matrix_out(CONFIG_FPGA_CS_GPIO, FPGA_SPI_CS_SIGNAL, false, false);
 
     heap_caps_free(buffer);
 
     if (ret == ESP_OK) {
+        mbedtls_sha256_finish(&sha, loaded_sha256);
+        loaded = true;
         ESP_LOGI(TAG, "FPGA configuration complete");
     }
+    mbedtls_sha256_free(&sha);
 
 cleanup_bus:
     spi_device_release_bus(fpga_update_device);
@@ -196,6 +217,281 @@ cleanup_device:
     return ret;
 }
 
+#if CONFIG_FPGA_BOOT_FLASH
+
+// SPI NOR commands shared by the configuration flashes iCE40 boards carry
+#define FLASH_CMD_PAGE_PROGRAM       0x02
+#define FLASH_CMD_READ               0x03
+#define FLASH_CMD_READ_STATUS        0x05
+#define FLASH_CMD_WRITE_ENABLE       0x06
+#define FLASH_CMD_RELEASE_POWER_DOWN 0xAB
+#define FLASH_CMD_BLOCK_ERASE        0xD8
+
+#define FLASH_STATUS_BUSY 0x01
+#define FLASH_PAGE_SIZE   256
+#define FLASH_BLOCK_SIZE  65536
+
+// The FPGA reads its configuration out of the flash in well under a second
+#define FLASH_BOOT_TIMEOUT_MS  1000
+#define FLASH_ERASE_TIMEOUT_MS 3000
+#define FLASH_PAGE_TIMEOUT_MS  100
+
+// Run one flash command with the FPGA held in reset: the command byte, a
+// 24-bit address unless address < 0, then data out and/or data in
+static esp_err_t flash_command(uint8_t command, int32_t address,
+                               const uint8_t *data, size_t data_len,
+                               uint8_t *rx, size_t rx_len)
+{
+    uint8_t header[4] = {command, address >> 16, address >> 8, address};
+
+    gpio_set_level(CONFIG_FPGA_CS_GPIO, 0);
+    esp_err_t ret = write_update_block(header, address < 0 ? 1 : sizeof(header));
+    if (ret == ESP_OK && data_len > 0) {
+        ret = write_update_block(data, data_len);
+    }
+    if (ret == ESP_OK && rx_len > 0) {
+        spi_transaction_t trans = {
+            .length = 0,
+            .rxlength = rx_len * 8,
+            .rx_buffer = rx,
+        };
+        xSemaphoreTake(master_spi_semaphore, portMAX_DELAY);
+        ret = spi_device_transmit(fpga_update_device, &trans);
+        xSemaphoreGive(master_spi_semaphore);
+    }
+    gpio_set_level(CONFIG_FPGA_CS_GPIO, 1);
+
+    return ret;
+}
+
+// Poll the status register until an erase or program finishes; page programs
+// take about a millisecond, so only erases give up the CPU between polls
+static esp_err_t flash_wait_idle(uint32_t timeout_ms, bool sleep)
+{
+    TickType_t timeout = xTaskGetTickCount() + pdMS_TO_TICKS(timeout_ms);
+    uint8_t status;
+
+    do {
+        esp_err_t ret = flash_command(FLASH_CMD_READ_STATUS, -1, NULL, 0, &status, 1);
+        if (ret != ESP_OK) {
+            return ret;
+        }
+        if (!(status & FLASH_STATUS_BUSY)) {
+            return ESP_OK;
+        }
+        if (sleep) {
+            vTaskDelay(1);
+        }
+    } while (xTaskGetTickCount() <= timeout);
+
+    return ESP_ERR_TIMEOUT;
+}
+
+// Compare the flash with the bitstream, hashing the bitstream on a full match
+static esp_err_t flash_matches(firmware_source_t *source, uint8_t *buffer,
+                               uint8_t *readback, bool *matches)
+{
+    mbedtls_sha256_context sha;
+    mbedtls_sha256_init(&sha);
+    mbedtls_sha256_starts(&sha, 0);
+    source->rewind(source->ctx);
+    *matches = true;
+
+    esp_err_t ret = ESP_OK;
+    for (size_t offset = 0; offset < source->size; offset += FLASH_PAGE_SIZE) {
+        size_t chunk = source->size - offset;
+        if (chunk > FLASH_PAGE_SIZE) {
+            chunk = FLASH_PAGE_SIZE;
+        }
+
+        if (source->read(buffer, chunk, source->ctx) != chunk) {
+            ESP_LOGE(TAG, "Read error at offset %d", offset);
+            ret = ESP_FAIL;
+            break;
+        }
+        ret = flash_command(FLASH_CMD_READ, offset, NULL, 0, readback, chunk);
+        if (ret != ESP_OK) {
+            break;
+        }
+        if (memcmp(buffer, readback, chunk) != 0) {
+            *matches = false;
+            break;
+        }
+        mbedtls_sha256_update(&sha, buffer, chunk);
+    }
+
+    if (ret == ESP_OK && *matches) {
+        mbedtls_sha256_finish(&sha, loaded_sha256);
+    }
+    mbedtls_sha256_free(&sha);
+    return ret;
+}
+
+// Erase the blocks the bitstream covers and program it page by page
+static esp_err_t flash_program(firmware_source_t *source, uint8_t *buffer)
+{
+    esp_err_t ret;
+
+    ESP_LOGI(TAG, "Writing %d bytes to the configuration flash", source->size);
+    for (size_t offset = 0; offset < source->size; offset += FLASH_BLOCK_SIZE) {
+        ret = flash_command(FLASH_CMD_WRITE_ENABLE, -1, NULL, 0, NULL, 0);
+        if (ret == ESP_OK) {
+            ret = flash_command(FLASH_CMD_BLOCK_ERASE, offset, NULL, 0, NULL, 0);
+        }
+        if (ret == ESP_OK) {
+            ret = flash_wait_idle(FLASH_ERASE_TIMEOUT_MS, true);
+        }
+        if (ret != ESP_OK) {
+            ESP_LOGE(TAG, "Erase failed at 0x%06x: %s", offset, esp_err_to_name(ret));
+            return ret;
+        }
+    }
+
+    source->rewind(source->ctx);
+    for (size_t offset = 0; offset < source->size; offset += FLASH_PAGE_SIZE) {
+        size_t chunk = source->size - offset;
+        if (chunk > FLASH_PAGE_SIZE) {
+            chunk = FLASH_PAGE_SIZE;
+        }
+
+        if (source->read(buffer, chunk, source->ctx) != chunk) {
+            ESP_LOGE(TAG, "Read error at offset %d", offset);
+            return ESP_FAIL;
+        }
+        ret = flash_command(FLASH_CMD_WRITE_ENABLE, -1, NULL, 0, NULL, 0);
+        if (ret == ESP_OK) {
+            ret = flash_command(FLASH_CMD_PAGE_PROGRAM, offset, buffer, chunk, NULL, 0);
+        }
+        if (ret == ESP_OK) {
+            ret = flash_wait_idle(FLASH_PAGE_TIMEOUT_MS, false);
+        }
+        if (ret != ESP_OK) {
+            ESP_LOGE(TAG, "Program failed at 0x%06x: %s", offset, esp_err_to_name(ret));
+            return ret;
+        }
+    }
+
+    return ESP_OK;
+}
+
+// Release CS, SCLK and MOSI and pulse CRESET_B so the FPGA configures itself
+// from the flash as SPI master (SPI_SS_B pulled high selects master mode),
+// then take the pins back for the SPI bus
+static esp_err_t flash_boot(void)
+{
+    const spi_signal_conn_t *spi = &spi_periph_signal[FPGA_SPI_HOST];
+
+    gpio_set_direction(CONFIG_FPGA_SCLK_GPIO, GPIO_MODE_INPUT);
+    gpio_set_direction(CONFIG_FPGA_MOSI_GPIO, GPIO_MODE_INPUT);
+    gpio_set_direction(CONFIG_FPGA_CS_GPIO, GPIO_MODE_INPUT);
+    gpio_pullup_en(CONFIG_FPGA_CS_GPIO);
+
+    reset_pin_set(0);
+    vTaskDelay(1);
+    reset_pin_set(1);
+    esp_err_t ret = cdone_pin_wait(true, FLASH_BOOT_TIMEOUT_MS);
+    if (ret != ESP_OK) {
+        ESP_LOGE(TAG, "CDONE timeout - the FPGA did not boot from flash");
+    }
+
+    gpio_pullup_dis(CONFIG_FPGA_CS_GPIO);
+    gpio_set_direction(CONFIG_FPGA_SCLK_GPIO, GPIO_MODE_OUTPUT);
+    gpio_matrix_out(CONFIG_FPGA_SCLK_GPIO, spi->spiclk_out, false, false);
+    gpio_set_direction(CONFIG_FPGA_MOSI_GPIO, GPIO_MODE_OUTPUT);
+    gpio_matrix_out(CONFIG_FPGA_MOSI_GPIO, spi->spid_out, false, false);
+    gpio_set_direction(CONFIG_FPGA_CS_GPIO, GPIO_MODE_OUTPUT);
+    gpio_set_level(CONFIG_FPGA_CS_GPIO, 1);
+    gpio_matrix_out(CONFIG_FPGA_CS_GPIO, FPGA_SPI_CS_SIGNAL, false, false);
+
+    return ret;
+}
+
+// Bring the configuration flash up to date with the bitstream, rewriting it
+// only when it differs, then boot the FPGA from it
+static esp_err_t flash_load(firmware_source_t *source)
+{
+    esp_err_t ret;
+
+    ret = update_spi_device_add();
+    if (ret != ESP_OK) {
+        ESP_LOGE(TAG, "Failed to add SPI device: %s", esp_err_to_name(ret));
+        return ret;
+    }
+
+    ret = spi_device_acquire_bus(fpga_update_device, portMAX_DELAY);
+    if (ret != ESP_OK) {
+        ESP_LOGE(TAG, "Failed to acquire SPI bus: %s", esp_err_to_name(ret));
+        goto cleanup_device;
+    }
+
+    // Hold the FPGA in reset so the flash is ours, CS under GPIO control
+    reset_pin_set(0);
+    gpio_set_level(CONFIG_FPGA_CS_GPIO, 1);
+    gpio_matrix_out(CONFIG_FPGA_CS_GPIO, SIG_GPIO_OUT_IDX, false, false);
+    vTaskDelay(1);
+    loaded = false;
+
+    uint8_t *buffer = heap_caps_malloc(FLASH_PAGE_SIZE * 2, MALLOC_CAP_DMA);
+    if (buffer == NULL) {
+        ESP_LOGE(TAG, "Failed to allocate DMA buffer");
+        gpio_matrix_out(CONFIG_FPGA_CS_GPIO, FPGA_SPI_CS_SIGNAL, false, false);
+        ret = ESP_ERR_NO_MEM;
+        goto cleanup_bus;
+    }
+    uint8_t *readback = buffer + FLASH_PAGE_SIZE;
+
+    // Some flashes power down after configuring the FPGA
+    ret = flash_command(FLASH_CMD_RELEASE_POWER_DOWN, -1, NULL, 0, NULL, 0);
+    vTaskDelay(1);
+
+    bool matches = false;
+    if (ret == ESP_OK) {
+        ret = flash_matches(source, buffer, readback, &matches);
+    }
+    if (ret == ESP_OK && matches) {
+        ESP_LOGI(TAG, "Configuration flash is up to date");
+    } else if (ret == ESP_OK) {
+        ret = flash_program(source, buffer);
+        if (ret == ESP_OK) {
+            ret = flash_matches(source, buffer, readback, &matches);
+        }
+        if (ret == ESP_OK && !matches) {
+            ESP_LOGE(TAG, "Configuration flash verify failed");
+            ret = ESP_ERR_INVALID_CRC;
+        }
+    }
+    heap_caps_free(buffer);
+
+    if (ret == ESP_OK) {
+        ret = flash_boot();
+    } else {
+        gpio_matrix_out(CONFIG_FPGA_CS_GPIO, FPGA_SPI_CS_SIGNAL, false, false);
+    }
+    if (ret == ESP_OK) {
+        loaded = true;
+        ESP_LOGI(TAG, "FPGA booted from the configuration flash");
+    }
+
+cleanup_bus:
+    spi_device_release_bus(fpga_update_device);
+
+cleanup_device:
+    update_spi_device_remove();
+
+    return ret;
+}
+
+#endif  // CONFIG_FPGA_BOOT_FLASH
+
+static esp_err_t fpga_loader_load(firmware_source_t *source)
+{
+#if CONFIG_FPGA_BOOT_FLASH
+    return flash_load(source);
+#else
+    return cram_load(source);
+#endif
+}
+
 // ROM source implementation
 typedef struct {
     const uint8_t *data;
@@ -216,6 +512,11 @@ static size_t rom_read(void *buffer, size_t size, void *ctx)
     return size;
 }
 
+static void rom_rewind(void *ctx)
+{
+    ((rom_ctx_t *)ctx)->pos = 0;
+}
+
 esp_err_t fpga_loader_load_from_rom(const fpga_bin_t *fpga_bin)
 {
     if (fpga_bin == NULL || fpga_bin->end <= fpga_bin->start) {
@@ -235,6 +536,7 @@ esp_err_t fpga_loader_load_from_rom(const fpga_bin_t *fpga_bin)
         .size = ctx.size,
         .ctx = &ctx,
         .read = rom_read,
+        .rewind = rom_rewind,
     };
 
     return fpga_loader_load(&source);
@@ -246,6 +548,11 @@ static size_t file_read(void *buffer, size_t size, void *ctx)
     return fread(buffer, 1, size, (FILE *)ctx);
 }
 
+static void file_rewind(void *ctx)
+{
+    rewind((FILE *)ctx);
+}
+
 esp_err_t fpga_loader_load_from_file(const char *filename)
 {
     struct stat st;
@@ -266,6 +573,7 @@ esp_err_t fpga_loader_load_from_file(const char *filename)
         .size = st.st_size,
         .ctx = fp,
         .read = file_read,
+        .rewind = file_rewind,
     };
 
     esp_err_t ret = fpga_loader_load(&source);
@@ -274,6 +582,75 @@ esp_err_t fpga_loader_load_from_file(const char *filename)
     return ret;
 }
 
+// Partition source implementation
+typedef struct {
+    const esp_partition_t *partition;
+    size_t pos;
+} partition_ctx_t;
+
+static size_t partition_read(void *buffer, size_t size, void *ctx)
+{
+    partition_ctx_t *part = (partition_ctx_t *)ctx;
+
+    if (esp_partition_read(part->partition, FPGA_PARTITION_HEADER_LEN + part->pos,
+                           buffer, size) != ESP_OK) {
+        return 0;
+    }
+    part->pos += size;
+    return size;
+}
+
+static void partition_rewind(void *ctx)
+{
+    ((partition_ctx_t *)ctx)->pos = 0;
+}
+
+esp_err_t fpga_loader_load_from_partition(const char *label)
+{
+    const esp_partition_t *partition = esp_partition_find_first(
+        ESP_PARTITION_TYPE_DATA, ESP_PARTITION_SUBTYPE_ANY, label);
+    if (partition == NULL) {
+        ESP_LOGE(TAG, "Partition not found: %s", label);
+        return ESP_ERR_NOT_FOUND;
+    }
+
+    uint32_t header[2];
+    esp_err_t ret = esp_partition_read(partition, 0, header, sizeof(header));
+    if (ret != ESP_OK) {
+        return ret;
+    }
+    if (header[0] != FPGA_PARTITION_MAGIC ||
+        header[1] > partition->size - FPGA_PARTITION_HEADER_LEN) {
+        ESP_LOGE(TAG, "No bitstream in partition %s", label);
+        return ESP_ERR_INVALID_STATE;
+    }
+
+    ESP_LOGI(TAG, "Loading FPGA from partition %s, size=%lu", label,
+             (unsigned long)header[1]);
+
+    partition_ctx_t ctx = {
+        .partition = partition,
+        .pos = 0,
+    };
+    firmware_source_t source = {
+        .size = header[1],
+        .ctx = &ctx,
+        .read = partition_read,
+        .rewind = partition_rewind,
+    };
+
+    return fpga_loader_load(&source);
+}
+
+esp_err_t fpga_loader_get_sha256(uint8_t sha256[32])
+{
+    if (!loaded) {
+        return ESP_ERR_INVALID_STATE;
+    }
+    memcpy(sha256, loaded_sha256, sizeof(loaded_sha256));
+    return ESP_OK;
+}
+
 esp_err_t fpga_loader_init(void)
 {
     // Configure CRESET as output (active low)
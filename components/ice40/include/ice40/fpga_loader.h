@@ -1,5 +1,6 @@
 #pragma once
 
+#include <stdint.h>
 #include <esp_err.h>
 #include "fpga_bin.h"
 
@@ -14,12 +15,17 @@
  * The loader supports both:
  * - Loading from embedded ROM (bitstream linked into firmware)
  * - Loading from filesystem (bitstream stored in VFS)
+ * - Loading from a data partition (bitstream flashed on its own)
  *
  * Hardware requirements:
  * - SPI connection (MOSI, SCLK, CS directly wired)
  * - CRESET_B GPIO output (active low reset)
  * - CDONE GPIO input (configuration done indicator)
  *
+ * With CONFIG_FPGA_BOOT_FLASH the loads go through the FPGA's SPI
+ * configuration flash instead: the flash is rewritten if it differs from
+ * the bitstream, then the FPGA is reset to boot from it as SPI master.
+ *
  * @{
  */
 
@@ -44,6 +50,9 @@ esp_err_t fpga_loader_init(void);
  * 5. Wait for CDONE high
  * 6. Send additional clocks to activate I/O
  *
+ * With CONFIG_FPGA_BOOT_FLASH, writes the bitstream to the configuration
+ * flash if it differs and boots the FPGA from the flash instead.
+ *
  * @param fpga_bin Pointer to fpga_bin_t describing the embedded bitstream
  * @return ESP_OK on success, error code otherwise
  */
@@ -60,4 +69,37 @@ esp_err_t fpga_loader_load_from_rom(const fpga_bin_t *fpga_bin);
  */
 esp_err_t fpga_loader_load_from_file(const char *filename);
 
+/**
+ * @brief Magic word starting a bitstream partition: "AFBS" little-endian
+ *
+ * A partition image is this word, the bitstream's length as a little-endian
+ * 32-bit word, then the bitstream. `affogato build` writes it for
+ * `[firmware] bitstream = "partition"`.
+ */
+#define FPGA_PARTITION_MAGIC 0x53424641u
+#define FPGA_PARTITION_HEADER_LEN 8
+
+/**
+ * @brief Load FPGA configuration from a data partition
+ *
+ * Same as fpga_loader_load_from_rom() but reads the bitstream from a
+ * partition holding a bitstream partition image (see FPGA_PARTITION_MAGIC).
+ *
+ * @param label Label of the partition in the partition table (e.g., "fpga")
+ * @return ESP_OK on success, ESP_ERR_NOT_FOUND if there is no such partition,
+ *         ESP_ERR_INVALID_STATE if it holds no bitstream, other errors otherwise
+ */
+esp_err_t fpga_loader_load_from_partition(const char *label);
+
+/**
+ * @brief Get the SHA-256 of the bitstream last loaded into the FPGA
+ *
+ * The hash is taken over the bytes actually streamed to the FPGA, so it
+ * identifies the running configuration whichever source it came from.
+ *
+ * @param sha256 Receives the 32-byte digest
+ * @return ESP_OK on success, ESP_ERR_INVALID_STATE if no load has succeeded
+ */
+esp_err_t fpga_loader_get_sha256(uint8_t sha256[32]);
+
 /** @} */
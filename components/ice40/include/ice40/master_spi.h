@@ -1,5 +1,6 @@
 #pragma once
 
+#include <driver/spi_common.h>
 #include <esp_err.h>
 #include <freertos/FreeRTOS.h>
 #include <freertos/semphr.h>
@@ -16,6 +17,14 @@
  * @{
  */
 
+/**
+ * @brief SPI peripheral the FPGA is on, from CONFIG_FPGA_SPI_HOST
+ *
+ * Kconfig numbers the peripherals as the datasheet does (SPI2, SPI3);
+ * spi_host_device_t counts from SPI1.
+ */
+#define FPGA_SPI_HOST ((spi_host_device_t)(CONFIG_FPGA_SPI_HOST - 1))
+
 /**
  * @brief Semaphore for SPI bus arbitration
  *
@@ -34,7 +43,7 @@ extern SemaphoreHandle_t master_spi_semaphore;
 /**
  * @brief Initialize the SPI master bus
  *
- * Configures the SPI peripheral with pins from Kconfig:
+ * Configures FPGA_SPI_HOST with pins from Kconfig:
  * - CONFIG_FPGA_SCLK_GPIO
  * - CONFIG_FPGA_MOSI_GPIO
  * - CONFIG_FPGA_MISO_GPIO
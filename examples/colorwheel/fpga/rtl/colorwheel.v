@@ -0,0 +1,72 @@
+// Colorwheel core - sweeps the hue and drives one PWM output per color
+// Kept apart from the SB_HFOSC and SB_RGBA_DRV primitives in top.v so the
+// testbench in fpga/rtl_test can simulate it
+
+module colorwheel #(
+    // ~1.4 sec per full rotation at 48MHz; smaller sweeps faster
+    parameter PHASE_BITS = 26
+) (
+    input wire clk,
+    output wire r_pwm,
+    output wire g_pwm,
+    output wire b_pwm,
+    output wire [7:0] hue
+);
+    // Slow counter for color cycling
+    reg [PHASE_BITS-1:0] phase_counter = 0;
+    always @(posedge clk)
+        phase_counter <= phase_counter + 1;
+
+    // Use top 8 bits as hue (0-255)
+    assign hue = phase_counter[PHASE_BITS-1 -: 8];
+
+    // PWM counter for brightness levels
+    reg [7:0] pwm_counter = 0;
+    always @(posedge clk)
+        pwm_counter <= pwm_counter + 1;
+
+    // HSV to RGB conversion (simplified, saturation=1, value=1)
+    // Divides hue into 6 sectors of ~42 values each
+    reg [7:0] r_level, g_level, b_level;
+
+    always @(posedge clk) begin
+        case (hue[7:5])  // 3 bits = 8 sectors, but we use 6
+            3'd0: begin  // Red to Yellow (hue 0-42)
+                r_level <= 8'd255;
+                g_level <= {hue[4:0], 3'b0};  // Rising
+                b_level <= 8'd0;
+            end
+            3'd1: begin  // Yellow to Green (hue 43-85)
+                r_level <= 8'd255 - {hue[4:0], 3'b0};  // Falling
+                g_level <= 8'd255;
+                b_level <= 8'd0;
+            end
+            3'd2: begin  // Green to Cyan (hue 86-128)
+                r_level <= 8'd0;
+                g_level <= 8'd255;
+                b_level <= {hue[4:0], 3'b0};  // Rising
+            end
+            3'd3: begin  // Cyan to Blue (hue 129-170)
+                r_level <= 8'd0;
+                g_level <= 8'd255 - {hue[4:0], 3'b0};  // Falling
+                b_level <= 8'd255;
+            end
+            3'd4: begin  // Blue to Magenta (hue 171-213)
+                r_level <= {hue[4:0], 3'b0};  // Rising
+                g_level <= 8'd0;
+                b_level <= 8'd255;
+            end
+            default: begin  // Magenta to Red (hue 214-255)
+                r_level <= 8'd255;
+                g_level <= 8'd0;
+                b_level <= 8'd255 - {hue[4:0], 3'b0};  // Falling
+            end
+        endcase
+    end
+
+    // PWM comparison for each channel
+    assign r_pwm = (pwm_counter < r_level);
+    assign g_pwm = (pwm_counter < g_level);
+    assign b_pwm = (pwm_counter < b_level);
+
+endmodule
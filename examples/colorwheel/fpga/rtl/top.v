@@ -15,62 +15,15 @@ module top (
         .CLKHF(clk)
     );
 
-    // Slow counter for color cycling (~1.4 sec per full rotation at 48MHz)
-    reg [25:0] phase_counter;
-    always @(posedge clk)
-        phase_counter <= phase_counter + 1;
-
-    // Use top 8 bits as hue (0-255)
-    wire [7:0] hue = phase_counter[25:18];
-
-    // PWM counter for brightness levels
-    reg [7:0] pwm_counter;
-    always @(posedge clk)
-        pwm_counter <= pwm_counter + 1;
-
-    // HSV to RGB conversion (simplified, saturation=1, value=1)
-    // Divides hue into 6 sectors of ~42 values each
-    reg [7:0] r_level, g_level, b_level;
-
-    always @(posedge clk) begin
-        case (hue[7:5])  // 3 bits = 8 sectors, but we use 6
-            3'd0: begin  // Red to Yellow (hue 0-42)
-                r_level <= 8'd255;
-                g_level <= {hue[4:0], 3'b0};  // Rising
-                b_level <= 8'd0;
-            end
-            3'd1: begin  // Yellow to Green (hue 43-85)
-                r_level <= 8'd255 - {hue[4:0], 3'b0};  // Falling
-                g_level <= 8'd255;
-                b_level <= 8'd0;
-            end
-            3'd2: begin  // Green to Cyan (hue 86-128)
-                r_level <= 8'd0;
-                g_level <= 8'd255;
-                b_level <= {hue[4:0], 3'b0};  // Rising
-            end
-            3'd3: begin  // Cyan to Blue (hue 129-170)
-                r_level <= 8'd0;
-                g_level <= 8'd255 - {hue[4:0], 3'b0};  // Falling
-                b_level <= 8'd255;
-            end
-            3'd4: begin  // Blue to Magenta (hue 171-213)
-                r_level <= {hue[4:0], 3'b0};  // Rising
-                g_level <= 8'd0;
-                b_level <= 8'd255;
-            end
-            default: begin  // Magenta to Red (hue 214-255)
-                r_level <= 8'd255;
-                g_level <= 8'd0;
-                b_level <= 8'd255 - {hue[4:0], 3'b0};  // Falling
-            end
-        endcase
-    end
-
-    // PWM comparison for each channel
-    wire r_pwm = (pwm_counter < r_level);
-    wire g_pwm = (pwm_counter < g_level);
-    wire b_pwm = (pwm_counter < b_level);
+    // Hue sweep and PWM
+    wire r_pwm, g_pwm, b_pwm;
+    colorwheel wheel (
+        .clk(clk),
+        .r_pwm(r_pwm),
+        .g_pwm(g_pwm),
+        .b_pwm(b_pwm),
+        .hue()
+    );
 
     // RGB LED driver (directly drives internal LED, no external pins)
     wire rgb0, rgb1, rgb2;
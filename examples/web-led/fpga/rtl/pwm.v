@@ -10,7 +10,7 @@ module pwm (
     output wire o_pwm
 );
 
-    reg [7:0] counter;
+    reg [7:0] counter = 0;
 
     always @(posedge i_clk)
         counter <= counter + 1;
@@ -28,29 +28,27 @@ module spi_rgb_slave (
     reg [23:0] shift_reg;
     reg [4:0] bit_count;
 
-    // Synchronize CS to system clock for edge detection
-    reg cs_sync1, cs_sync2, cs_prev;
-    wire cs_rising = cs_sync2 && !cs_prev;
+    // Synchronize the SPI signals to the system clock. At 48MHz this
+    // oversamples the ESP32's 1MHz SPI clock many times over.
+    reg [2:0] sck_sync, cs_sync;
+    reg [1:0] mosi_sync;
 
     always @(posedge i_clk) begin
-        cs_sync1 <= i_cs;
-        cs_sync2 <= cs_sync1;
-        cs_prev <= cs_sync2;
+        sck_sync <= {sck_sync[1:0], i_sck};
+        cs_sync <= {cs_sync[1:0], i_cs};
+        mosi_sync <= {mosi_sync[0], i_mosi};
     end
 
-    // Shift in data on SPI clock rising edge
-    always @(posedge i_sck or posedge i_cs) begin
-        if (i_cs) begin
-            bit_count <= 0;
-        end else begin
-            shift_reg <= {shift_reg[22:0], i_mosi};
-            bit_count <= bit_count + 1;
-        end
-    end
+    wire sck_rising = sck_sync[2:1] == 2'b01;
+    wire cs_active = !cs_sync[1];
+    wire cs_rising = cs_sync[2:1] == 2'b01;
 
-    // Latch RGB values on CS rising edge (transaction complete)
+    // Shift in data on SPI clock rising edges, and latch RGB values on CS
+    // rising edge (transaction complete). The bit count clears only once
+    // the latch has seen it.
     always @(posedge i_clk or posedge i_rst) begin
         if (i_rst) begin
+            bit_count <= 0;
             o_red <= 8'd0;
             o_green <= 8'd0;
             o_blue <= 8'd0;
@@ -58,11 +56,20 @@ module spi_rgb_slave (
         end else begin
             o_valid <= 1'b0;
 
-            if (cs_rising && bit_count >= 24) begin
-                o_red <= shift_reg[23:16];
-                o_green <= shift_reg[15:8];
-                o_blue <= shift_reg[7:0];
-                o_valid <= 1'b1;
+            if (cs_active && sck_rising) begin
+                shift_reg <= {shift_reg[22:0], mosi_sync[1]};
+                if (bit_count != 5'd31)
+                    bit_count <= bit_count + 1;
+            end
+
+            if (cs_rising) begin
+                bit_count <= 0;
+                if (bit_count >= 24) begin
+                    o_red <= shift_reg[23:16];
+                    o_green <= shift_reg[15:8];
+                    o_blue <= shift_reg[7:0];
+                    o_valid <= 1'b1;
+                end
             end
         end
     end
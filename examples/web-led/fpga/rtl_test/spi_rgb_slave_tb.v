@@ -0,0 +1,68 @@
+`timescale 1ns / 1ps
+`include "affogato_tb.vh"
+// SPI RGB slave testbench - a color is latched when CS rises after 24 bits
+// and a shorter transaction is dropped. The latch acts a few system clocks
+// after CS rises, once the edge is through the synchronizer, so the bit
+// count must still be there then.
+
+module spi_rgb_slave_tb;
+    `TB_DECLARE
+
+    wire clk;
+    affogato_clock clock (.clk(clk));
+
+    wire sck, mosi, cs;
+    affogato_spi_master #(.FREQ_HZ(1_000_000)) esp32 (
+        .sck(sck),
+        .mosi(mosi),
+        .miso(1'b0),
+        .cs_n(cs)
+    );
+
+    reg rst = 1'b1;
+    wire [7:0] red, green, blue;
+    wire valid;
+
+    spi_rgb_slave dut (
+        .i_clk(clk),
+        .i_rst(rst),
+        .i_cs(cs),
+        .i_sck(sck),
+        .i_mosi(mosi),
+        .o_red(red),
+        .o_green(green),
+        .o_blue(blue),
+        .o_valid(valid)
+    );
+
+    // Colors latched, counted on each o_valid pulse
+    integer updates = 0;
+    always @(posedge clk)
+        if (valid)
+            updates = updates + 1;
+
+    initial begin
+        $dumpfile("spi_rgb_slave_tb.vcd");
+        $dumpvars(0, spi_rgb_slave_tb);
+
+        clock.wait_cycles(4);
+        rst = 1'b0;
+
+        esp32.write_bytes(24'h123456, 3);
+        clock.wait_cycles(8);
+        `ASSERT_EQ(updates, 1, "color latched when CS rises")
+        `ASSERT_EQ({red, green, blue}, 24'h123456, "latched color")
+
+        esp32.write_bytes(16'hABCD, 2);
+        clock.wait_cycles(8);
+        `ASSERT_EQ(updates, 1, "16-bit transaction ignored")
+        `ASSERT_EQ({red, green, blue}, 24'h123456, "color kept after a short transaction")
+
+        esp32.write_bytes(24'hFF0080, 3);
+        clock.wait_cycles(8);
+        `ASSERT_EQ(updates, 2, "next color latched")
+        `ASSERT_EQ({red, green, blue}, 24'hFF0080, "next latched color")
+
+        `TB_FINISH
+    end
+endmodule
@@ -0,0 +1,99 @@
+`timescale 1ns / 1ps
+`include "affogato_tb.vh"
+// Web LED testbench - sends colors over SPI the way the ESP32 does and
+// checks the SPI slave latches them and the PWM duty of each channel
+// follows. The SB_HFOSC and SB_RGBA_DRV primitives in top.v are left out;
+// the testbench supplies the clock and watches the PWM outputs directly.
+
+module web_led_tb;
+    `TB_DECLARE
+
+    wire clk;
+    affogato_clock clock (.clk(clk));
+
+    // The ESP32 side, at the firmware's 1MHz SPI clock
+    wire sck, mosi, cs;
+    affogato_spi_master #(.FREQ_HZ(1_000_000)) esp32 (
+        .sck(sck),
+        .mosi(mosi),
+        .miso(1'b0),
+        .cs_n(cs)
+    );
+
+    reg rst = 1'b1;
+    wire [7:0] red, green, blue;
+    wire color_valid;
+
+    spi_rgb_slave spi (
+        .i_clk(clk),
+        .i_rst(rst),
+        .i_cs(cs),
+        .i_sck(sck),
+        .i_mosi(mosi),
+        .o_red(red),
+        .o_green(green),
+        .o_blue(blue),
+        .o_valid(color_valid)
+    );
+
+    wire r_pwm, g_pwm, b_pwm;
+    pwm pwm_r (.i_clk(clk), .i_duty(red),   .o_pwm(r_pwm));
+    pwm pwm_g (.i_clk(clk), .i_duty(green), .o_pwm(g_pwm));
+    pwm pwm_b (.i_clk(clk), .i_duty(blue),  .o_pwm(b_pwm));
+
+    // Colors latched, counted on each o_valid pulse
+    integer updates = 0;
+    always @(posedge clk)
+        if (color_valid)
+            updates = updates + 1;
+
+    // On-cycles of each channel over one PWM period
+    integer r_on, g_on, b_on;
+
+    task measure_duty;
+        begin
+            r_on = 0;
+            g_on = 0;
+            b_on = 0;
+            repeat (256) begin
+                @(negedge clk);
+                r_on = r_on + r_pwm;
+                g_on = g_on + g_pwm;
+                b_on = b_on + b_pwm;
+            end
+        end
+    endtask
+
+    // Send R, G, B in one transaction, then check the latch and the LED
+    task send_color(input [7:0] r, input [7:0] g, input [7:0] b);
+        integer before;
+        begin
+            before = updates;
+            esp32.write_bytes({r, g, b}, 3);
+            // CS rising passes through the synchronizer before the latch
+            clock.wait_cycles(8);
+            $display("  sent #%02h%02h%02h, latched #%02h%02h%02h", r, g, b, red, green, blue);
+            `ASSERT_EQ(updates, before + 1, "one color latched per transaction")
+            `ASSERT_EQ({red, green, blue}, {r, g, b}, "latched color")
+
+            measure_duty;
+            `ASSERT_EQ(r_on, r, "red duty")
+            `ASSERT_EQ(g_on, g, "green duty")
+            `ASSERT_EQ(b_on, b, "blue duty")
+        end
+    endtask
+
+    initial begin
+        $dumpfile("web_led_tb.vcd");
+        $dumpvars(0, web_led_tb);
+
+        clock.wait_cycles(4);
+        rst = 1'b0;
+        `ASSERT_EQ({red, green, blue}, 24'h000000, "LED off after reset")
+
+        send_color(8'hFF, 8'h80, 8'h00);  // Orange
+        send_color(8'h10, 8'h20, 8'hC0);  // Blue
+
+        `TB_FINISH
+    end
+endmodule